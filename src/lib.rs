@@ -1,18 +1,51 @@
 pub mod lib {
     pub mod data;
     pub mod api {
+        pub mod dataverse;
         pub mod dryad;
         pub mod figshare;
+        pub mod filesystem;
+        pub mod gdrive;
+        pub mod osf;
+        pub mod rclone;
+        pub mod s3;
+        pub mod sftp;
+        pub mod webdav;
         pub mod zenodo;
     }
     pub mod assets;
+    pub mod attributes;
+    pub mod catalog;
+    pub mod checksum;
+    pub mod compression;
+    pub mod dedup;
+    pub mod delta;
+    pub mod diff;
     pub mod download;
+    pub mod encryption;
+    pub mod export;
+    pub mod filter;
+    pub mod history;
+    pub mod hooks;
+    pub mod ignore;
+    pub mod lock;
     pub mod macros;
+    pub mod md5_cache;
+    pub mod path_style;
     pub mod progress;
     pub mod project;
+    pub mod project_config;
     pub mod remote;
+    pub mod remote_cache;
+    pub mod report;
+    pub mod staging;
+    pub mod storage;
     pub mod test_utilities;
+    pub mod throttle;
+    pub mod timestamp;
+    pub mod usage;
     pub mod utils;
+    pub mod walk;
 }
 
 pub mod logging_setup;
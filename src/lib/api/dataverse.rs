@@ -0,0 +1,745 @@
+// Dataverse API
+//
+// Dataverse is run by many institutions, each at its own server, so a
+// remote records its server along with the collection (a "dataverse", by
+// its alias) holding its dataset, e.g. with
+//
+//   sdf link data/ dataverse <TOKEN> --endpoint https://dataverse.example.edu \
+//       --name my-lab/<title>
+//
+// The dataset is found in (or created in) the collection by its title,
+// which defaults to the project's name. Files are uploaded with the
+// native API, in the dataset's directories (with --path-style
+// hierarchical), and without tabular ingest, which would replace e.g. CSV
+// files with converted copies (and MD5s) of their own. Dataverse reports
+// each file's MD5, unless the server is set up to use another checksum, in
+// which case files only show as existing on the remote.
+//
+// Dataverse may fail additions of files to a dataset made at once, so
+// uploads to a dataset are made one at a time.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+#[allow(unused_imports)]
+use log::{debug, info, trace};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Method, Response};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use url::Url;
+
+use crate::lib::data::DataFile;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::LocalMetadata;
+use crate::lib::remote::{retry_policy, send_with_retry, FileForm, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
+use crate::lib::utils::shorten;
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+const TOKEN_HEADER: &str = "X-Dataverse-key";
+
+// The response to Dataverse's native API, e.g. {"status": "OK", "data": ...}.
+#[derive(Debug, Deserialize)]
+struct DataverseResponse<T> {
+    data: T,
+}
+
+// An item of a collection's contents.
+#[derive(Debug, Deserialize)]
+struct DataverseItem {
+    #[serde(rename = "type")]
+    kind: String,
+    id: u64,
+}
+
+// The response to creating a dataset.
+#[derive(Debug, Deserialize)]
+struct DataverseDataset {
+    id: u64,
+    #[serde(rename = "persistentId")]
+    persistent_id: String,
+}
+
+// The latest version of a dataset (with only what is used).
+#[derive(Debug, Deserialize)]
+struct DataverseVersion {
+    #[serde(rename = "datasetPersistentId")]
+    persistent_id: String,
+    #[serde(rename = "metadataBlocks", default)]
+    metadata_blocks: Value,
+}
+
+impl DataverseVersion {
+    fn title(&self) -> Option<&str> {
+        let fields = self.metadata_blocks["citation"]["fields"].as_array()?;
+        fields
+            .iter()
+            .find(|field| field["typeName"] == "title")
+            .and_then(|field| field["value"].as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DataverseUploaded {
+    files: Vec<DataverseFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DataverseFile {
+    label: String,
+    #[serde(rename = "directoryLabel", default)]
+    directory_label: Option<String>,
+    #[serde(rename = "dataFile")]
+    data_file: DataverseDataFile,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DataverseDataFile {
+    id: u64,
+    #[serde(default)]
+    filesize: Option<u64>,
+    // older servers only report the MD5, newer ones the checksum
+    #[serde(default)]
+    md5: Option<String>,
+    #[serde(default)]
+    checksum: Option<DataverseChecksum>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DataverseChecksum {
+    #[serde(rename = "type")]
+    kind: String,
+    value: String,
+}
+
+impl DataverseFile {
+    // The file's path in the dataset.
+    pub fn name(&self) -> String {
+        match self.directory_label.as_deref() {
+            Some(dir) if !dir.is_empty() => format!("{}/{}", dir, self.label),
+            _ => self.label.clone(),
+        }
+    }
+
+    fn md5(&self) -> Option<String> {
+        let data_file = &self.data_file;
+        match &data_file.checksum {
+            Some(checksum) if checksum.kind.eq_ignore_ascii_case("md5") => {
+                Some(checksum.value.to_lowercase())
+            }
+            Some(_) => None,
+            None => data_file.md5.as_ref().map(|md5| md5.to_lowercase()),
+        }
+    }
+}
+
+// Additions of files to the dataset, one at a time. Not part of a
+// remote's identity.
+#[derive(Debug, Clone, Default)]
+struct AddLock(Arc<tokio::sync::Mutex<()>>);
+
+impl PartialEq for AddLock {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct DataverseAPI {
+    // the server, e.g. https://dataverse.example.edu
+    server: String,
+    // the alias of the collection with the dataset
+    collection: String,
+    // the dataset's title
+    name: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    token: String,
+    dataset_id: Option<u64>,
+    persistent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+    #[serde(skip)]
+    adding: AddLock,
+}
+
+impl DataverseAPI {
+    // A remote for location, '<collection>[/<title>]' (where the title
+    // defaults to default_title), on server.
+    pub fn new(location: &str, default_title: &str, server: &str) -> Result<Self> {
+        let (collection, title) = match location.split_once('/') {
+            Some((collection, title)) => (collection, title),
+            None => (location, default_title),
+        };
+        if collection.is_empty() || title.is_empty() {
+            return Err(anyhow!(
+                "Linking to Dataverse requires --name <collection>[/<title>], \
+                 not '{}'.",
+                location
+            ));
+        }
+        let url = Url::parse(server)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or(anyhow!(
+                "Invalid Dataverse server '{}' (expected e.g. https://dataverse.example.edu).",
+                server
+            ))?;
+        let server = url.as_str().trim_end_matches('/');
+        let server = server.strip_suffix("/api").unwrap_or(server);
+        Ok(DataverseAPI {
+            server: server.to_string(),
+            collection: collection.to_string(),
+            name: title.to_string(),
+            token: String::new(),
+            dataset_id: None,
+            persistent_id: None,
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+            adding: AddLock::default(),
+        })
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = token;
+    }
+
+    pub fn has_token(&self) -> bool {
+        !self.token.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        self.persistent_id
+            .as_ref()
+            .map(|id| format!("{}/dataset.xhtml?persistentId={}", self.server, id))
+    }
+
+    fn get_dataset_id(&self) -> Result<u64> {
+        self.dataset_id
+            .ok_or(anyhow!("Internal Error: Dataverse dataset_id not set."))
+    }
+
+    async fn issue_request<T: serde::Serialize>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        data: Option<RequestData<T>>,
+    ) -> Result<Response> {
+        let url = format!("{}/api/{}", self.server, endpoint.trim_start_matches('/'));
+        trace!("request URL: {:?}", url);
+
+        let operation = Operation::of_api_request(&method);
+        let bytes_sent = request_body_size(&data).await?;
+        let client = Client::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(TOKEN_HEADER, HeaderValue::from_str(&self.token)?);
+
+        // each attempt is sent (and accounted) as its own request
+        let (method, url, headers, data) = (&method, &url, &headers, &data);
+        let response = send_with_retry(&retry_policy(), method, || async {
+            let mut request = client.request(method.clone(), url).headers(headers.clone());
+            if let Some(data) = data {
+                request = data.attach(request).await?;
+            }
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await?;
+        let response_status = response.status();
+        if response_status.is_success() {
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "HTTP Error: {}\nurl: {:?}\n{:?}",
+                response_status,
+                url,
+                response.text().await?
+            ))
+        }
+    }
+
+    // The data of a GET of endpoint.
+    async fn get_data<D: DeserializeOwned>(&self, endpoint: &str) -> Result<D> {
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, endpoint, None)
+            .await?;
+        let response: DataverseResponse<D> = response.json().await?;
+        Ok(response.data)
+    }
+
+    // Find the collection's dataset with this remote's title.
+    async fn find_dataset(&self) -> Result<Option<DataverseDataset>> {
+        let endpoint = format!("dataverses/{}/contents", self.collection);
+        let items: Vec<DataverseItem> = self.get_data(&endpoint).await?;
+        let mut matches_found = Vec::new();
+        for item in items.iter().filter(|item| item.kind == "dataset") {
+            let endpoint = format!("datasets/{}/versions/:latest", item.id);
+            let version: DataverseVersion = self.get_data(&endpoint).await?;
+            if version.title() == Some(self.name.as_str()) {
+                matches_found.push(DataverseDataset {
+                    id: item.id,
+                    persistent_id: version.persistent_id,
+                });
+            }
+        }
+        if matches_found.len() > 1 {
+            return Err(anyhow!(
+                "Found multiple Dataverse datasets with the title '{}' in '{}'",
+                self.name,
+                self.collection
+            ));
+        }
+        Ok(matches_found.pop())
+    }
+
+    // Create a dataset in the collection, with the citation metadata
+    // Dataverse requires.
+    async fn create_dataset(&self, local_metadata: LocalMetadata) -> Result<DataverseDataset> {
+        let author = local_metadata.author_name.ok_or(anyhow!(
+            "Dataverse requires an author; set it with 'sdf config --name <NAME>'."
+        ))?;
+        let email = local_metadata.email.ok_or(anyhow!(
+            "Dataverse requires a contact email; set it with 'sdf config --email <EMAIL>'."
+        ))?;
+        let description = local_metadata
+            .description
+            .unwrap_or("Upload by SciDataFlow.".to_string());
+        let primitive = |name: &str, value: &str| json!({"typeName": name, "multiple": false, "typeClass": "primitive", "value": value});
+        let mut author_fields = json!({ "authorName": primitive("authorName", &author) });
+        if let Some(affiliation) = &local_metadata.affiliation {
+            author_fields["authorAffiliation"] = primitive("authorAffiliation", affiliation);
+        }
+        let fields = json!([
+            primitive("title", &self.name),
+            {"typeName": "author", "multiple": true, "typeClass": "compound",
+             "value": [author_fields]},
+            {"typeName": "datasetContact", "multiple": true, "typeClass": "compound",
+             "value": [{
+                 "datasetContactName": primitive("datasetContactName", &author),
+                 "datasetContactEmail": primitive("datasetContactEmail", &email),
+             }]},
+            {"typeName": "dsDescription", "multiple": true, "typeClass": "compound",
+             "value": [{"dsDescriptionValue": primitive("dsDescriptionValue", &description)}]},
+            {"typeName": "subject", "multiple": true, "typeClass": "controlledVocabulary",
+             "value": ["Other"]},
+        ]);
+        let data = json!({
+            "datasetVersion": {"metadataBlocks": {"citation": {"fields": fields}}}
+        });
+        let endpoint = format!("dataverses/{}/datasets", self.collection);
+        let response = self
+            .issue_request(Method::POST, &endpoint, Some(RequestData::Json(data)))
+            .await?;
+        let response: DataverseResponse<DataverseDataset> = response.json().await?;
+        Ok(response.data)
+    }
+
+    // Dataverse Remote initialization
+    //
+    // This creates a dataset in the collection for the tracked
+    // directory (or with --link-only, finds an existing one).
+    pub async fn remote_init(
+        &mut self,
+        local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        let dataset = match self.find_dataset().await? {
+            Some(_) if !link_only => {
+                return Err(anyhow!(
+                    "An existing Dataverse dataset with the title '{}' was found in '{}'. \
+                     Use --link-only to link.",
+                    self.name,
+                    self.collection
+                ))
+            }
+            Some(dataset) => dataset,
+            None => self.create_dataset(local_metadata).await?,
+        };
+        self.dataset_id = Some(dataset.id);
+        self.persistent_id = Some(dataset.persistent_id);
+        Ok(())
+    }
+
+    pub async fn get_files(&self) -> Result<Vec<DataverseFile>> {
+        let endpoint = format!("datasets/{}/versions/:latest/files", self.get_dataset_id()?);
+        self.get_data(&endpoint).await
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let files = self.get_files().await?;
+        let remote_files = files
+            .into_iter()
+            .map(|file| RemoteFile {
+                name: file.name(),
+                md5: file.md5(),
+                size: file.data_file.filesize,
+                remote_service: "Dataverse".to_string(),
+                url: Some(format!(
+                    "{}/api/access/datafile/{}",
+                    self.server, file.data_file.id
+                )),
+            })
+            .collect();
+        Ok(remote_files)
+    }
+
+    // Check if file exists, returning None if not,
+    // and the DataverseFile if so
+    pub async fn file_exists(&self, name: &str) -> Result<Option<DataverseFile>> {
+        let files = self.get_files().await?;
+        Ok(files.into_iter().find(|file| file.name() == name))
+    }
+
+    async fn delete_file(&self, file: &DataverseFile) -> Result<()> {
+        let endpoint = format!("files/{}", file.data_file.id);
+        self.issue_request::<HashMap<String, String>>(Method::DELETE, &endpoint, None)
+            .await?;
+        info!("deleted Dataverse file '{}'", file.name());
+        Ok(())
+    }
+
+    // Upload the file, replacing any existing file if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let id = self.get_dataset_id()?;
+        let _adding = self.adding.0.lock().await;
+        if let Some(file) = self.file_exists(name).await? {
+            if !overwrite {
+                print_info!(
+                    "Dataverse::upload() found file '{}' in dataset ID={}. Since \
+                     overwrite=false, this file will not be deleted and re-uploaded.",
+                    name,
+                    id
+                );
+                return Ok(false);
+            }
+            info!(
+                "Dataverse::upload() is deleting file '{}' since overwrite=true.",
+                name
+            );
+            self.delete_file(&file).await?;
+        }
+
+        // files keep the remote name's directories, and are not ingested
+        let (directory, filename) = match name.rsplit_once('/') {
+            Some((directory, filename)) => (Some(directory), filename),
+            None => (None, name),
+        };
+        let mut json_data = json!({ "tabIngest": false });
+        if let Some(directory) = directory {
+            json_data["directoryLabel"] = json!(directory);
+        }
+        let file = tokio::fs::File::open(data_file.full_path(path_context)?).await?;
+        let fields = vec![("jsonData".to_string(), json_data.to_string())];
+        let form = FileForm::new(fields, filename, file, progress.clone());
+        let endpoint = format!("datasets/{}/add", id);
+        let response = self
+            .issue_request::<HashMap<String, String>>(
+                Method::POST,
+                &endpoint,
+                Some(RequestData::Form(form)),
+            )
+            .await?;
+        let uploaded: DataverseResponse<DataverseUploaded> = response.json().await?;
+
+        // a file with a different MD5 than local is not kept
+        let local_md5 = data_file.recorded_md5();
+        for file in &uploaded.data.files {
+            match file.md5() {
+                Some(remote_md5) if remote_md5 != local_md5 => {
+                    self.delete_file(file).await?;
+                    return Err(anyhow!(
+                        "After upload, the local ({}) and remote ({}) MD5s of '{}' differed, \
+                         so the remote file was deleted.",
+                        shorten(local_md5, Some(8)),
+                        shorten(&remote_md5, Some(8)),
+                        name
+                    ));
+                }
+                _ => (),
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn authenticate_url(&self, url: &str) -> Result<String> {
+        Ok(format!("{}?key={}", url, self.token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use httpmock::prelude::*;
+    use tempfile::TempDir;
+
+    const TEST_TOKEN: &str = "test-token";
+
+    fn test_api(server: &MockServer) -> DataverseAPI {
+        let mut api = DataverseAPI::new("lab", "Project", &server.url("/")).unwrap();
+        api.set_token(TEST_TOKEN.to_string());
+        api
+    }
+
+    fn metadata(email: Option<&str>) -> LocalMetadata {
+        LocalMetadata {
+            author_name: Some("Joan B. Scientist".to_string()),
+            email: email.map(|email| email.to_string()),
+            affiliation: None,
+            title: None,
+            description: None,
+            deposit: Default::default(),
+        }
+    }
+
+    fn version(title: &str, pid: &str) -> Value {
+        json!({"status": "OK", "data": {
+            "datasetPersistentId": pid,
+            "metadataBlocks": {"citation": {"fields": [
+                {"typeName": "title", "value": title}
+            ]}}
+        }})
+    }
+
+    #[test]
+    fn test_new_parses_location_and_server() {
+        let api =
+            DataverseAPI::new("lab/My Data", "Project", "https://dv.example.edu/api/").unwrap();
+        assert_eq!(api.server, "https://dv.example.edu");
+        assert_eq!(
+            (api.collection.as_str(), api.name.as_str()),
+            ("lab", "My Data")
+        );
+        let api = DataverseAPI::new("lab", "Project", "https://dv.example.edu").unwrap();
+        assert_eq!(api.name, "Project");
+        assert_eq!(api.web_url(), None);
+
+        assert!(DataverseAPI::new("/title", "Project", "https://dv.example.edu").is_err());
+        let err = DataverseAPI::new("lab", "Project", "dv.example.edu").unwrap_err();
+        assert!(err.to_string().contains("Invalid Dataverse server"));
+    }
+
+    #[tokio::test]
+    async fn test_remote_init_finds_or_creates_dataset() {
+        setup();
+        let server = MockServer::start();
+        let contents = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/dataverses/lab/contents")
+                .header(TOKEN_HEADER, TEST_TOKEN);
+            then.status(200).json_body(json!({"status": "OK", "data": [
+                {"type": "dataverse", "id": 1},
+                {"type": "dataset", "id": 7},
+                {"type": "dataset", "id": 8},
+            ]}));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/api/datasets/7/versions/:latest");
+            then.status(200)
+                .json_body(version("Other", "doi:10.5072/FK2/OTHER"));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/api/datasets/8/versions/:latest");
+            then.status(200)
+                .json_body(version("Project", "doi:10.5072/FK2/PROJ"));
+        });
+
+        // an existing dataset is only linked with --link-only
+        let mut api = test_api(&server);
+        let err = api
+            .remote_init(metadata(Some("joan@example.edu")), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Use --link-only"), "{}", err);
+        api.remote_init(metadata(Some("joan@example.edu")), true)
+            .await
+            .unwrap();
+        assert_eq!(api.dataset_id, Some(8));
+        assert_eq!(
+            api.web_url().unwrap(),
+            format!(
+                "{}/dataset.xhtml?persistentId=doi:10.5072/FK2/PROJ",
+                server.url("")
+            )
+        );
+        contents.assert_hits(2);
+
+        // otherwise, the dataset is made, with a contact
+        let mut api = DataverseAPI::new("lab/New", "Project", &server.url("")).unwrap();
+        api.set_token(TEST_TOKEN.to_string());
+        let create = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/dataverses/lab/datasets")
+                .header(TOKEN_HEADER, TEST_TOKEN)
+                .body_contains("\"value\":\"New\"")
+                .body_contains("joan@example.edu");
+            then.status(201).json_body(json!({"status": "OK", "data": {
+                "id": 9, "persistentId": "doi:10.5072/FK2/NEW"
+            }}));
+        });
+        let err = api.remote_init(metadata(None), false).await.unwrap_err();
+        assert!(err.to_string().contains("sdf config --email"), "{}", err);
+        api.remote_init(metadata(Some("joan@example.edu")), false)
+            .await
+            .unwrap();
+        create.assert_hits(1);
+        assert_eq!(api.dataset_id, Some(9));
+        assert_eq!(api.persistent_id.as_deref(), Some("doi:10.5072/FK2/NEW"));
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    #[tokio::test]
+    async fn test_push_status_and_pull() {
+        setup();
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let (reads, notes) = ("ACGT\n", "a,b\n1,2\n");
+        std::fs::write(path_context.join("data/raw/reads.txt"), reads).unwrap();
+        std::fs::write(path_context.join("data/notes.csv"), notes).unwrap();
+        let reads_md5 = format!("{:x}", md5::compute(reads));
+        let notes_md5 = format!("{:x}", md5::compute(notes));
+
+        let mut api = test_api(&server);
+        api.dataset_id = Some(8);
+        api.path_style = PathStyle::Hierarchical;
+        let remote = Remote::DataverseAPI(api.clone());
+        let mut data = DataCollection::new();
+        for path in ["data/raw/reads.txt", "data/notes.csv"] {
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = path.ends_with("reads.txt");
+            data.register(data_file).unwrap();
+        }
+        data.register_remote(&"data".to_string(), remote).unwrap();
+
+        // the file is added in its directory, without ingest
+        let mut listing = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/datasets/8/versions/:latest/files");
+            then.status(200)
+                .json_body(json!({"status": "OK", "data": []}));
+        });
+        let add = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/datasets/8/add")
+                .header(TOKEN_HEADER, TEST_TOKEN)
+                .header_exists("content-length")
+                .body_contains("name=\"jsonData\"")
+                .body_contains("\"directoryLabel\":\"raw\"")
+                .body_contains("\"tabIngest\":false")
+                .body_contains("filename=\"reads.txt\"")
+                .body_contains(reads);
+            then.status(200)
+                .json_body(json!({"status": "OK", "data": {"files": [{
+                    "label": "reads.txt", "directoryLabel": "raw",
+                    "dataFile": {"id": 101, "filesize": 5,
+                                 "checksum": {"type": "MD5", "value": reads_md5}}
+                }]}}));
+        });
+        let reads_file = data.files.get("data/raw/reads.txt").unwrap().clone();
+        let uploaded = api
+            .upload(
+                &reads_file,
+                "raw/reads.txt",
+                path_context,
+                false,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert!(uploaded);
+        add.assert_hits(1);
+
+        // listed with their directories (notes.csv, uploaded elsewhere,
+        // with an older server's MD5)
+        listing.delete();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/datasets/8/versions/:latest/files");
+            then.status(200).json_body(json!({"status": "OK", "data": [
+                {"label": "reads.txt", "directoryLabel": "raw",
+                 "dataFile": {"id": 101, "filesize": 5,
+                              "checksum": {"type": "MD5", "value": reads_md5}}},
+                {"label": "notes.csv",
+                 "dataFile": {"id": 102, "filesize": 8, "md5": notes_md5}},
+            ]}));
+        });
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            vec![
+                ("data/notes.csv".to_string(), "current".to_string()),
+                ("data/raw/reads.txt".to_string(), "current".to_string()),
+            ]
+        );
+
+        // and pulled through the access API
+        let download = server.mock(|when, then| {
+            when.method(GET)
+                .path("/api/access/datafile/101")
+                .query_param("key", TEST_TOKEN);
+            then.status(200).body(reads);
+        });
+        std::fs::remove_file(path_context.join("data/raw/reads.txt")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        download.assert_hits(1);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/raw/reads.txt")).unwrap(),
+            reads
+        );
+    }
+}
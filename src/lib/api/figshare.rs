@@ -13,25 +13,34 @@ use reqwest::{
     header::{HeaderMap, HeaderValue},
     Method,
 };
-use reqwest::{Body, Client, Response};
+use reqwest::{Client, Response};
 use serde_derive::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::time::Instant;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use url::Url;
 
-use crate::lib::data::DataFile;
+use crate::lib::data::{DataFile, DepositMetadata};
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
 use crate::lib::project::LocalMetadata;
-use crate::lib::remote::{AuthKeys, RemoteFile, RequestData};
+use crate::lib::remote::{retry_policy, send_with_retry, AuthKeys, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
 #[allow(unused_imports)]
 use crate::{print_info, print_warn};
 
 pub const FIGSHARE_BASE_URL: &str = "https://api.figshare.com/v2/";
 
+// The status of a file whose upload was started but not completed, and
+// that of an uploaded part.
+const UPLOAD_CREATED: &str = "created";
+const PART_COMPLETE: &str = "COMPLETE";
+
 // for testing:
 const TEST_TOKEN: &str = "test-token";
 
@@ -47,8 +56,21 @@ pub struct FigShareAPI {
     // one remote corresponds to a FigShare article
     article_id: Option<u64>,
     name: String,
+    // a published article (e.g. someone else's), linked only for
+    // downloading (see published()), its version (default: the latest),
+    // and its DOI
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    read_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doi: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     token: String,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
 }
 
 pub struct FigShareUpload<'a> {
@@ -73,6 +95,36 @@ pub struct FigShareFile {
     computed_md5: String,
 }
 
+/// A published article, from GETs to /articles/{article_id}, with its
+/// public files.
+#[derive(Debug, Deserialize)]
+pub struct FigSharePublicArticle {
+    title: String,
+    doi: Option<String>,
+    #[serde(default)]
+    files: Vec<FigSharePublicFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FigSharePublicFile {
+    name: String,
+    size: u64,
+    download_url: String,
+    computed_md5: String,
+}
+
+impl From<FigSharePublicFile> for RemoteFile {
+    fn from(fgsh: FigSharePublicFile) -> Self {
+        RemoteFile {
+            name: fgsh.name,
+            md5: Some(fgsh.computed_md5).filter(|md5| !md5.is_empty()),
+            size: Some(fgsh.size),
+            remote_service: "FigShare".to_string(),
+            url: Some(fgsh.download_url),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FigShareNewUpload {
     md5: String,
@@ -120,6 +172,7 @@ impl<'a> FigShareUpload<'a> {
     async fn init_upload(
         &self,
         data_file: &DataFile,
+        name: &str,
     ) -> Result<(FigShareFile, FigSharePendingUploadInfo)> {
         debug!("initializing upload of '{:?}'", data_file);
         // Requires: article ID, in FigShareArticle struct
@@ -127,7 +180,7 @@ impl<'a> FigShareUpload<'a> {
         let article_id = self.api_instance.get_article_id()?;
         let url = format!("account/articles/{}/files", article_id);
         let data = FigShareNewUpload {
-            name: data_file.basename()?,
+            name: name.to_string(),
             md5: data_file.md5.clone(),
             size: data_file.size,
         };
@@ -160,13 +213,22 @@ impl<'a> FigShareUpload<'a> {
         // (4) Now, we need to issue another GET to initiate upload.
         // This returns the file parts info, which tells us how to split
         // the file.
+        let pending_upload_info = self.pending_upload_info(&upload_info).await?;
+        Ok((upload_info, pending_upload_info))
+    }
+
+    // The parts of an upload, and which have been uploaded.
+    async fn pending_upload_info(
+        &self,
+        upload_info: &FigShareFile,
+    ) -> Result<FigSharePendingUploadInfo> {
         let response = self
             .api_instance
             .issue_request::<HashMap<String, String>>(Method::GET, &upload_info.upload_url, None)
             .await?;
         let pending_upload_info: FigSharePendingUploadInfo = response.json().await?;
         debug!("pending upload info: {:?}", pending_upload_info);
-        Ok((upload_info, pending_upload_info))
+        Ok(pending_upload_info)
     }
 
     async fn upload_parts(
@@ -175,6 +237,7 @@ impl<'a> FigShareUpload<'a> {
         upload_info: &FigShareFile,
         pending_upload_info: &FigSharePendingUploadInfo,
         path_context: &Path,
+        progress: &FileProgress,
     ) -> Result<()> {
         let full_path = path_context.join(&data_file.path);
         let url = &upload_info.upload_url;
@@ -183,6 +246,12 @@ impl<'a> FigShareUpload<'a> {
         for part in &pending_upload_info.parts {
             let start_offset = part.start_offset;
             let end_offset = part.end_offset;
+            // parts uploaded before an upload was interrupted are skipped
+            if part.status == PART_COMPLETE {
+                progress.inc(end_offset - start_offset + 1);
+                debug!("part {} was already uploaded", part.part_no);
+                continue;
+            }
 
             // get the binary data between these offsets
             file.seek(SeekFrom::Start(start_offset))?;
@@ -190,6 +259,7 @@ impl<'a> FigShareUpload<'a> {
             file.read_exact(&mut data)?;
 
             let part_url = format!("{}/{}", &url, part.part_no);
+            let part_size = data.len() as u64;
             let _response = self
                 .api_instance
                 .issue_request::<HashMap<String, String>>(
@@ -198,6 +268,7 @@ impl<'a> FigShareUpload<'a> {
                     Some(RequestData::Binary(data)),
                 )
                 .await?;
+            progress.inc(part_size);
             debug!(
                 "uploaded part {} (offsets {}:{})",
                 part.part_no, start_offset, end_offset
@@ -224,8 +295,10 @@ impl<'a> FigShareUpload<'a> {
     pub async fn upload(
         &self,
         data_file: &DataFile,
+        name: &str,
         path_context: &Path,
         overwrite: bool,
+        progress: &FileProgress,
     ) -> Result<()> {
         if !data_file.is_alive(path_context) {
             return Err(anyhow!(
@@ -235,10 +308,28 @@ impl<'a> FigShareUpload<'a> {
         }
         // check if any files are associated with this article
         let article_id = self.api_instance.get_article_id()?;
-        let name = data_file.basename()?;
-        let existing_file = self.api_instance.file_exists(&name).await?;
+        let existing_file = self.api_instance.file_exists(name).await?;
         if let Some(file) = existing_file {
-            if !overwrite {
+            if file.status == UPLOAD_CREATED {
+                // An upload left unfinished (e.g. by an interrupted push)
+                // is resumed if it is of this version of the file, and
+                // otherwise replaced, since it is not a copy of the file.
+                if file.supplied_md5 == data_file.md5 && file.size == data_file.size {
+                    info!("resuming the unfinished upload of '{}'", name);
+                    let pending_upload_info = self.pending_upload_info(&file).await?;
+                    self.upload_parts(
+                        data_file,
+                        &file,
+                        &pending_upload_info,
+                        path_context,
+                        progress,
+                    )
+                    .await?;
+                    self.complete_upload(&file).await?;
+                    return Ok(());
+                }
+                self.api_instance.delete_article_file(&file).await?;
+            } else if !overwrite {
                 print_info!(
                     "FigShare::upload() found file '{}' in FigShare \
                             Article ID={}. Since overwrite=false, 
@@ -255,9 +346,15 @@ impl<'a> FigShareUpload<'a> {
                 self.api_instance.delete_article_file(&file).await?;
             }
         }
-        let (upload_info, pending_upload_info) = self.init_upload(data_file).await?;
-        self.upload_parts(data_file, &upload_info, &pending_upload_info, path_context)
-            .await?;
+        let (upload_info, pending_upload_info) = self.init_upload(data_file, name).await?;
+        self.upload_parts(
+            data_file,
+            &upload_info,
+            &pending_upload_info,
+            path_context,
+            progress,
+        )
+        .await?;
         self.complete_upload(&upload_info).await?;
         Ok(())
     }
@@ -275,6 +372,66 @@ impl From<FigShareFile> for RemoteFile {
     }
 }
 
+// The ID and version (if given) of a published article, from its DOI
+// (e.g. 10.6084/m9.figshare.1234.v2, or as a https://doi.org/ URL), its
+// page (e.g. https://figshare.com/articles/dataset/title/1234/2), or the
+// ID itself.
+pub fn article_of(reference: &str) -> Result<(u64, Option<u64>)> {
+    let reference = reference
+        .trim()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let (id, version) = if let Some((_, path)) = reference.split_once("/articles/") {
+        // the page is at articles/<type>/<title>/<id>[/<version>]
+        let segments: Vec<&str> = path.split('/').collect();
+        let segments = segments
+            .get(2..)
+            .filter(|s| !s.is_empty())
+            .unwrap_or(&segments);
+        (segments[0], segments.get(1).copied())
+    } else {
+        // the DOI ends in <id>[.v<version>]
+        let suffix = reference.rsplit('/').next().unwrap_or_default();
+        match suffix.rsplit_once('.') {
+            Some((rest, version)) if version.starts_with('v') => (
+                rest.rsplit('.').next().unwrap_or_default(),
+                Some(&version[1..]),
+            ),
+            Some((_, id)) => (id, None),
+            None => (suffix, None),
+        }
+    };
+    let parse = |number: &str| {
+        number.parse::<u64>().map_err(|_| {
+            anyhow!(
+                "'{}' is not the DOI (e.g. 10.6084/m9.figshare.1234), URL or ID of a \
+                 FigShare article.",
+                reference
+            )
+        })
+    };
+    Ok((parse(id)?, version.map(parse).transpose()?))
+}
+
+// A license FigShare offers, by its number (see account/licenses).
+#[derive(Debug, Deserialize)]
+struct FigShareLicense {
+    value: u64,
+    name: String,
+    url: String,
+}
+
+// License names and IDs, compared loosely (e.g. 'CC BY 4.0' is 'cc-by-4.0').
+fn license_key(license: &str) -> String {
+    license
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct FigShareArticle {
     title: String,
@@ -303,14 +460,57 @@ impl FigShareAPI {
             base_url,
             article_id: None,
             name: name.to_string(),
+            read_only: false,
+            version: None,
+            doi: None,
             token,
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        })
+    }
+
+    // A published article, from its DOI, URL or ID (see article_of()). Its
+    // files can only be downloaded, which needs no token.
+    pub fn published(reference: &str, base_url: Option<String>) -> Result<Self> {
+        let (article_id, version) = article_of(reference)?;
+        Ok(FigShareAPI {
+            base_url: base_url.unwrap_or(FIGSHARE_BASE_URL.to_string()),
+            article_id: Some(article_id),
+            name: reference.to_string(),
+            read_only: true,
+            version,
+            doi: None,
+            token: String::new(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
         })
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn set_token(&mut self, token: String) {
         self.token = token;
     }
 
+    pub fn has_token(&self) -> bool {
+        self.read_only || !self.token.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        if let Some(doi) = &self.doi {
+            return Some(format!("https://doi.org/{}", doi));
+        }
+        let page = if self.read_only {
+            "articles"
+        } else {
+            "account/articles"
+        };
+        self.article_id
+            .map(|id| format!("https://figshare.com/{}/{}", page, id))
+    }
+
     pub fn get_base_url(&self) -> String {
         self.base_url.clone()
     }
@@ -333,32 +533,34 @@ impl FigShareAPI {
 
         trace!("request URL: {:?}", url);
 
+        let operation = Operation::of_api_request(&method);
+        let bytes_sent = request_body_size(&data).await?;
         let client = Client::new();
-        let mut request = client.request(method, &url);
 
-        headers.insert(
-            "Authorization",
-            HeaderValue::from_str(&format!("token {}", self.token)).unwrap(),
-        );
+        // published articles are read without a token
+        if !self.token.is_empty() {
+            headers.insert(
+                "Authorization",
+                HeaderValue::from_str(&format!("token {}", self.token)).unwrap(),
+            );
+        }
         trace!("headers: {:?}", headers);
-        request = request.headers(headers);
-
-        let request = match data {
-            Some(RequestData::Json(json_data)) => request.json(&json_data),
-            Some(RequestData::Binary(bin_data)) => request.body(bin_data),
-            Some(RequestData::File(file)) => request.body(file),
-            Some(RequestData::Stream(file)) => {
-                let stream = tokio_util::io::ReaderStream::new(file);
-                let body = Body::wrap_stream(stream);
-                request.body(body)
-            }
-            Some(RequestData::Empty) => {
-                request.json(&serde_json::Value::Object(serde_json::Map::new()))
-            }
-            None => request,
-        };
 
-        let response = request.send().await?;
+        // each attempt is sent (and accounted) as its own request
+        let (method, url, headers, data) = (&method, &url, &headers, &data);
+        let response = send_with_retry(&retry_policy(), method, || async {
+            let mut request = client.request(method.clone(), url).headers(headers.clone());
+            if let Some(data) = data {
+                request = data.attach(request).await?;
+            }
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await?;
         let response_status = response.status();
         if response_status.is_success() {
             Ok(response)
@@ -402,7 +604,7 @@ impl FigShareAPI {
             .await?;
         let data = response.json::<Value>().await?;
         let article_id_result = match data.get("location").and_then(|loc| loc.as_str()) {
-            Some(loc) => Ok(loc.split('/').last().unwrap_or_default().to_string()),
+            Some(loc) => Ok(loc.split('/').next_back().unwrap_or_default().to_string()),
             None => Err(anyhow!("Response does not have 'location' set!")),
         };
         let article_id: u64 = article_id_result?
@@ -420,17 +622,22 @@ impl FigShareAPI {
     pub async fn upload(
         &self,
         data_file: &DataFile,
+        name: &str,
         path_context: &Path,
         overwrite: bool,
+        progress: &FileProgress,
     ) -> Result<bool> {
         let this_upload = FigShareUpload::new(self);
         this_upload
-            .upload(data_file, path_context, overwrite)
+            .upload(data_file, name, path_context, overwrite, progress)
             .await?;
         Ok(true)
     }
 
     pub fn authenticate_url(&self, url: &str) -> Result<String> {
+        if self.token.is_empty() {
+            return Ok(url.to_string());
+        }
         Ok(format!("{}?token={}", url, self.token))
     }
 
@@ -463,6 +670,14 @@ impl FigShareAPI {
         local_metadata: LocalMetadata,
         link_only: bool,
     ) -> Result<()> {
+        // A published article only needs to exist
+        if self.read_only {
+            let article = self.get_public_article().await?;
+            self.name = article.title;
+            self.doi = article.doi;
+            return Ok(());
+        }
+
         // (1) Let's make sure there is no Article that exists
         // with this same name
         let found_match = self.find_article().await?;
@@ -483,9 +698,138 @@ impl FigShareAPI {
         // (3) Set the Article ID, which is the only state needed
         // for later queries
         self.article_id = Some(article.id);
+
+        // (4) Set the rest of the manifest's metadata for the article (its
+        // title is the remote's name)
+        let deposit = DepositMetadata {
+            title: None,
+            ..local_metadata.deposit
+        };
+        if !deposit.is_empty() {
+            self.update_metadata(&deposit).await?;
+        }
         Ok(())
     }
 
+    // Update the article's metadata to the manifest's (see
+    // DepositMetadata), if it differs. Keywords are FigShare's tags, and
+    // the license is looked up by its name or URL.
+    pub async fn update_metadata(&mut self, deposit: &DepositMetadata) -> Result<bool> {
+        let article_id = self.get_article_id()?;
+        let url = format!("account/articles/{}", article_id);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None)
+            .await?;
+        let article = response.json::<Value>().await?;
+
+        let mut changes = serde_json::Map::new();
+        if let Some(title) = &deposit.title {
+            if article["title"] != json!(title) {
+                changes.insert("title".to_string(), json!(title));
+            }
+        }
+        if let Some(description) = &deposit.description {
+            if article["description"] != json!(description) {
+                changes.insert("description".to_string(), json!(description));
+            }
+        }
+        if !deposit.keywords.is_empty() && article["tags"] != json!(deposit.keywords) {
+            changes.insert("tags".to_string(), json!(deposit.keywords));
+        }
+        if !deposit.creators.is_empty() {
+            let names: Vec<&str> = article["authors"]
+                .as_array()
+                .map(|authors| {
+                    authors
+                        .iter()
+                        .filter_map(|author| author["full_name"].as_str())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if !names.iter().eq(deposit.creators.iter().map(|c| &c.name)) {
+                let authors: Vec<Value> = deposit
+                    .creators
+                    .iter()
+                    .map(|creator| {
+                        let mut author = json!({ "name": creator.name });
+                        if let Some(orcid) = &creator.orcid {
+                            author["orcid_id"] = json!(orcid);
+                        }
+                        author
+                    })
+                    .collect();
+                changes.insert("authors".to_string(), Value::Array(authors));
+            }
+        }
+        if let Some(license) = &deposit.license {
+            let current = &article["license"];
+            let same = current["name"].as_str().map(license_key) == Some(license_key(license))
+                || current["url"].as_str() == Some(license.as_str());
+            if !same {
+                changes.insert(
+                    "license".to_string(),
+                    json!(self.license_of(license).await?),
+                );
+            }
+        }
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        self.issue_request(Method::PUT, &url, Some(RequestData::Json(changes)))
+            .await?;
+        if let Some(title) = &deposit.title {
+            self.name = title.clone();
+        }
+        Ok(true)
+    }
+
+    // The number of a license, by its name or URL.
+    async fn license_of(&self, license: &str) -> Result<u64> {
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, "account/licenses", None)
+            .await?;
+        let licenses: Vec<FigShareLicense> = response.json().await?;
+        licenses
+            .iter()
+            .find(|offered| {
+                license_key(&offered.name) == license_key(license) || offered.url == license
+            })
+            .map(|offered| offered.value)
+            .ok_or_else(|| {
+                let names: Vec<&str> = licenses.iter().map(|l| l.name.as_str()).collect();
+                anyhow!(
+                    "FigShare has no license '{}'; it has: {}.",
+                    license,
+                    names.join(", ")
+                )
+            })
+    }
+
+    // Publish the article, which mints its DOI (kept, and returned): FigShare
+    // gives it in the article's details, not the publish response.
+    pub async fn publish(&mut self) -> Result<String> {
+        let article_id = self.get_article_id()?;
+        let url = format!("account/articles/{}/publish", article_id);
+        self.issue_request::<HashMap<String, String>>(Method::POST, &url, None)
+            .await?;
+        let url = format!("account/articles/{}", article_id);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None)
+            .await?;
+        let article = response.json::<Value>().await?;
+        let doi = match article.get("doi").and_then(|doi| doi.as_str()) {
+            Some(doi) if !doi.is_empty() => doi.to_string(),
+            _ => {
+                return Err(anyhow!(
+                    "FigShare published article {}, but did not give its DOI.",
+                    article_id
+                ))
+            }
+        };
+        self.doi = Some(doi.clone());
+        Ok(doi)
+    }
+
     // Get FigShare Articles as FigShareArticle
     // TODO? does this get published data sets?
     async fn get_articles(&self) -> Result<Vec<FigShareArticle>> {
@@ -497,7 +841,24 @@ impl FigShareAPI {
         Ok(articles)
     }
 
+    // Get a published article (at its version, if set), with its files.
+    pub async fn get_public_article(&self) -> Result<FigSharePublicArticle> {
+        let article_id = self.get_article_id()?;
+        let url = match self.version {
+            Some(version) => format!("/articles/{}/versions/{}", article_id, version),
+            None => format!("/articles/{}", article_id),
+        };
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None)
+            .await?;
+        Ok(response.json().await?)
+    }
+
     pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        if self.read_only {
+            let article = self.get_public_article().await?;
+            return Ok(article.files.into_iter().map(RemoteFile::from).collect());
+        }
         let articles = self.get_files().await?;
         let remote_files = articles.into_iter().map(RemoteFile::from).collect();
         Ok(remote_files)
@@ -584,10 +945,7 @@ mod tests {
         let create_article_mock = server.mock(|when, then| {
             when.method(POST)
                 .path("/account/articles")
-                .header(
-                    "Authorization",
-                    &format!("token {}", TEST_TOKEN.to_string()),
-                )
+                .header("Authorization", format!("token {}", TEST_TOKEN))
                 .json_body(json!({
                     "title": title.to_string(),
                     "defined_type": "dataset"
@@ -605,7 +963,7 @@ mod tests {
         let result = api.create_article(title).await;
 
         // Check the result
-        assert_eq!(result.is_ok(), true);
+        assert!(result.is_ok());
         let article = result.unwrap();
         assert_eq!(article.title, title);
         assert_eq!(article.id, expected_id);
@@ -613,4 +971,251 @@ mod tests {
         // Verify that the mock was called exactly once
         create_article_mock.assert();
     }
+
+    #[test]
+    fn test_article_of_doi_or_url() {
+        for (reference, expected) in [
+            ("10.6084/m9.figshare.1234", (1234, None)),
+            (
+                "https://doi.org/10.6084/m9.figshare.1234.v2",
+                (1234, Some(2)),
+            ),
+            ("10.25405/data.ncl.1234.v3", (1234, Some(3))),
+            (
+                "https://figshare.com/articles/dataset/Counts/1234",
+                (1234, None),
+            ),
+            (
+                "https://figshare.com/articles/dataset/Counts/1234/2",
+                (1234, Some(2)),
+            ),
+            ("1234", (1234, None)),
+        ] {
+            assert_eq!(article_of(reference).unwrap(), expected, "{}", reference);
+        }
+        assert!(article_of("10.5281/zenodo.abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_metadata_tags_authors_and_license() {
+        setup();
+        let server = MockServer::start();
+        let article_id = 12347;
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/account/articles/{}", article_id));
+            then.status(200).json_body(json!({
+                "id": article_id,
+                "title": "Test Article",
+                "description": "",
+                "tags": [],
+                "authors": [{"id": 1, "full_name": "Joan B. Scientist"}],
+                "license": {"value": 2, "name": "CC0", "url": "https://creativecommons.org/publicdomain/zero/1.0/"}
+            }));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/account/licenses");
+            then.status(200).json_body(json!([
+                {"value": 1, "name": "CC BY 4.0", "url": "https://creativecommons.org/licenses/by/4.0/"},
+                {"value": 2, "name": "CC0", "url": "https://creativecommons.org/publicdomain/zero/1.0/"}
+            ]));
+        });
+        // the title and authors are already set
+        let put_mock = server.mock(|when, then| {
+            when.method(PUT)
+                .path(format!("/account/articles/{}", article_id))
+                .json_body(json!({"tags": ["aDNA"], "license": 1}));
+            then.status(205);
+        });
+
+        let mut api = FigShareAPI::new("Test Article", Some(server.url(""))).unwrap();
+        api.article_id = Some(article_id);
+        let deposit = DepositMetadata {
+            title: Some("Test Article".to_string()),
+            creators: vec![crate::lib::data::Creator {
+                name: "Joan B. Scientist".to_string(),
+                affiliation: None,
+                orcid: None,
+            }],
+            license: Some("cc-by-4.0".to_string()),
+            keywords: vec!["aDNA".to_string()],
+            ..Default::default()
+        };
+        assert!(api.update_metadata(&deposit).await.unwrap());
+        put_mock.assert();
+
+        let err = api
+            .update_metadata(&DepositMetadata {
+                license: Some("GPL-3.0".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("it has: CC BY 4.0, CC0."),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_keeps_doi() {
+        setup();
+        let server = MockServer::start();
+        let article_id = 12346;
+        let doi = "10.6084/m9.figshare.12346.v1";
+        let publish_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/account/articles/{}/publish", article_id))
+                .header("Authorization", format!("token {}", TEST_TOKEN));
+            then.status(201).json_body(json!({
+                "location": format!("{}articles/{}", server.url(""), article_id)
+            }));
+        });
+        // the DOI is only in the article's details
+        let article_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/account/articles/{}", article_id));
+            then.status(200).json_body(json!({
+                "id": article_id,
+                "title": "Test Article",
+                "doi": doi
+            }));
+        });
+
+        let mut api = FigShareAPI::new("Test Article", Some(server.url(""))).unwrap();
+        api.article_id = Some(article_id);
+        assert_eq!(api.publish().await.unwrap(), doi);
+        publish_mock.assert();
+        article_mock.assert();
+        assert_eq!(api.doi.as_deref(), Some(doi));
+        assert_eq!(api.web_url(), Some(format!("https://doi.org/{}", doi)));
+    }
+
+    #[tokio::test]
+    async fn test_published_article_version() {
+        setup();
+        let server = MockServer::start();
+        let article_mock = server.mock(|when, then| {
+            when.method(GET).path("/articles/1234/versions/2");
+            then.status(200).json_body(json!({
+                "id": 1234,
+                "title": "Published Data",
+                "doi": "10.6084/m9.figshare.1234.v2",
+                "files": [{
+                    "id": 9,
+                    "name": "counts.tsv",
+                    "size": 12,
+                    "is_link_only": false,
+                    "download_url": "https://ndownloader.figshare.com/files/9",
+                    "supplied_md5": "",
+                    "computed_md5": "0123456789abcdef0123456789abcdef"
+                }]
+            }));
+        });
+
+        let mut api =
+            FigShareAPI::published("10.6084/m9.figshare.1234.v2", Some(server.url(""))).unwrap();
+        assert!(api.has_token());
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(api.name, "Published Data");
+        assert_eq!(
+            api.web_url().as_deref(),
+            Some("https://doi.org/10.6084/m9.figshare.1234.v2")
+        );
+
+        // files are downloaded from their public URLs, without a token
+        let files = api.get_remote_files().await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, Some(12));
+        let url = files[0].url.as_deref().unwrap();
+        assert_eq!(
+            api.authenticate_url(url).unwrap(),
+            "https://ndownloader.figshare.com/files/9"
+        );
+        article_mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_upload_is_resumed() {
+        setup();
+        let server = MockServer::start();
+        let article_id = 12348;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let contents = "0123456789abcdefghij";
+        std::fs::write(path_context.join("reads.fq"), contents).unwrap();
+        let data_file = DataFile::new("reads.fq".to_string(), None, path_context)
+            .await
+            .unwrap();
+
+        // an earlier push uploaded the first of two parts
+        let upload_url = server.url("/upload/token-1");
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/account/articles/{}/files", article_id));
+            then.status(200).json_body(json!([{
+                "upload_token": "token-1",
+                "upload_url": upload_url,
+                "status": "created",
+                "preview_state": "preview_not_available",
+                "viewer_type": "",
+                "is_attached_to_public_version": false,
+                "id": 7,
+                "name": "reads.fq",
+                "size": 20,
+                "is_link_only": false,
+                "download_url": "https://ndownloader.figshare.com/files/7",
+                "supplied_md5": data_file.md5,
+                "computed_md5": ""
+            }]));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/upload/token-1");
+            then.status(200).json_body(json!({
+                "token": "token-1",
+                "md5": data_file.md5,
+                "size": 20,
+                "name": "7/reads.fq",
+                "status": "PENDING",
+                "parts": [
+                    {"partNo": 1, "startOffset": 0, "endOffset": 9, "status": "COMPLETE", "locked": false},
+                    {"partNo": 2, "startOffset": 10, "endOffset": 19, "status": "PENDING", "locked": false}
+                ]
+            }));
+        });
+        let put_first = server.mock(|when, then| {
+            when.method(PUT).path("/upload/token-1/1");
+            then.status(200);
+        });
+        let put_second = server.mock(|when, then| {
+            when.method(PUT)
+                .path("/upload/token-1/2")
+                .body("abcdefghij");
+            then.status(200);
+        });
+        let complete = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/account/articles/{}/files/7", article_id));
+            then.status(202);
+        });
+        let new_upload = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!("/account/articles/{}/files", article_id));
+            then.status(201);
+        });
+
+        let mut api = FigShareAPI::new("Test Article", Some(server.url(""))).unwrap();
+        api.article_id = Some(article_id);
+        let progress = FileProgress::hidden();
+        api.upload(&data_file, "reads.fq", path_context, false, &progress)
+            .await
+            .unwrap();
+        put_first.assert_hits(0);
+        put_second.assert();
+        complete.assert();
+        new_upload.assert_hits(0);
+    }
 }
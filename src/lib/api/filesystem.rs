@@ -0,0 +1,566 @@
+// Directories on a filesystem that sdf can read and write itself, e.g. a
+// cluster's shared project space, mounted over NFS: files are stored under
+// the directory's path, as under the tracked directory.
+//
+// Pushes copy each file to FILE.partial beside its destination, flush it
+// to disk, re-read it to check its MD5, and only then rename it into
+// place. With 'sdf push --delta', existing copies are instead updated in
+// place, and replaced in full if they then fail the check. Pulls copy
+// files back with 'cp', checked against the manifest's MD5s as other
+// downloads are. A filesystem keeps no checksums, so
+// listings hash each file under the path: unchanged files come from the
+// MD5 cache (see md5_cache.rs), and files are hashed with as many jobs as
+// suit the destination's storage (see storage.rs), e.g. few for NFS.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use futures::StreamExt;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+use crate::lib::delta::{delta_copy_blocking, ChunkParams};
+use crate::lib::download::{CommandDownload, PARTIAL_SUFFIX};
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::remote::RemoteFile;
+use crate::lib::storage::{detect_storage, io_settings};
+use crate::lib::usage::{Operation, UsageCounter};
+use crate::lib::utils::{compute_md5, rehash_md5};
+use crate::lib::{data::DataFile, project::LocalMetadata};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct FilesystemAPI {
+    // the absolute path of the directory files are stored in
+    path: String,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+    // update existing copies in place, writing only their changed chunks
+    // (see 'sdf push --delta')
+    #[serde(skip)]
+    pub delta: bool,
+}
+
+// The files under dir (recursively), as (name, path, size), where names
+// are relative to dir. Symbolic links to files are followed, but not those
+// to directories, so links cannot cause cycles. Partial copies (from an
+// interrupted push) are skipped.
+fn walk(dir: &Path) -> Result<Vec<(String, PathBuf, u64)>> {
+    let mut files = Vec::new();
+    let mut directories = vec![dir.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = fs::read_dir(&directory)
+            .map_err(|err| anyhow!("Could not read directory {:?}: {}", directory, err))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            let name = path
+                .strip_prefix(dir)?
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/");
+            if !name.ends_with(PARTIAL_SUFFIX) {
+                files.push((name, path, metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl FilesystemAPI {
+    // The remote for a directory, made absolute (relative paths are taken
+    // from the working directory).
+    pub fn new(path: &str) -> Result<Self> {
+        if path.is_empty() {
+            return Err(anyhow!(
+                "Linking to a filesystem requires --name <PATH>, the directory to store files in."
+            ));
+        }
+        let absolute =
+            std::path::absolute(path).map_err(|err| anyhow!("Invalid path '{}': {}", path, err))?;
+        let absolute = absolute.to_string_lossy();
+        let trimmed = absolute.trim_end_matches('/');
+        Ok(FilesystemAPI {
+            path: if trimmed.is_empty() { "/" } else { trimmed }.to_string(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+            delta: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        Path::new(&self.path)
+    }
+
+    // The filesystem's own permissions apply, so there is no token.
+    pub fn has_token(&self) -> bool {
+        true
+    }
+
+    // The path of a remote file.
+    fn target(&self, name: &str) -> PathBuf {
+        self.path().join(name)
+    }
+
+    // List the files under the directory (none if it does not exist), with
+    // their MD5s.
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        if !self.path().is_dir() {
+            return Ok(Vec::new());
+        }
+        let files = walk(self.path())?;
+        let jobs = detect_storage(self.path()).defaults().jobs.max(1);
+        let hashed: Vec<_> = futures::stream::iter(files)
+            .map(|(name, path, size)| async move {
+                let md5 = compute_md5(&path).await;
+                (name, path, size, md5)
+            })
+            .buffered(jobs)
+            .collect()
+            .await;
+        self.usage.record(Operation::List, 0, 0);
+        let mut remote_files = Vec::new();
+        for (name, path, size, md5) in hashed {
+            let md5 = md5.map_err(|err| anyhow!("Could not hash {:?}: {}", path, err))?;
+            remote_files.push(RemoteFile {
+                name,
+                md5,
+                size: Some(size),
+                remote_service: "Filesystem".to_string(),
+                url: Some(path.to_string_lossy().to_string()),
+            });
+        }
+        Ok(remote_files)
+    }
+
+    // Check that, unless only linking, no files are stored under the path
+    // yet, then make the directory. With link_only, it must exist.
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        let exists = self.path().is_dir();
+        if link_only {
+            if !exists {
+                return Err(anyhow!("Directory '{}' does not exist.", self.path));
+            }
+            return Ok(());
+        }
+        if exists && !walk(self.path())?.is_empty() {
+            return Err(anyhow!(
+                "'{}' already has files. Use --link-only to link.",
+                self.path
+            ));
+        }
+        fs::create_dir_all(self.path())
+            .map_err(|err| anyhow!("Could not make directory '{}': {}", self.path, err))
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        self.list().await
+    }
+
+    // Copy the file to the directory, replacing any existing copy if
+    // overwrite is true. The copy is re-read to check its MD5 before it
+    // replaces the destination.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let target = self.target(name);
+        if !overwrite && target.exists() {
+            print_info!(
+                "FilesystemAPI::upload() found '{}'. Since overwrite=false, \
+                 this file will not be copied again.",
+                target.display()
+            );
+            return Ok(false);
+        }
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|err| anyhow!("Could not make directory {:?}: {}", parent, err))?;
+        }
+        if self.delta && target.is_file() {
+            match self.delta_update(data_file, path_context, &target).await {
+                Ok(bytes_sent) => {
+                    progress.inc(data_file.size);
+                    self.usage.record(Operation::Put, bytes_sent, 0);
+                    return Ok(true);
+                }
+                Err(err) => {
+                    print_warn!(
+                        "Could not update {:?} in place ({}); copying it in full.",
+                        target,
+                        err
+                    );
+                }
+            }
+        }
+        let partial = PathBuf::from(format!("{}{}", target.display(), PARTIAL_SUFFIX));
+        let result = self
+            .copy_verified(data_file, path_context, &partial, progress)
+            .await;
+        if let Err(err) = result {
+            let _ = tokio::fs::remove_file(&partial).await;
+            self.usage.record(Operation::Put, 0, 0);
+            return Err(err);
+        }
+        tokio::fs::rename(&partial, &target)
+            .await
+            .map_err(|err| anyhow!("Could not move the copy into {:?}: {}", target, err))?;
+        self.usage.record(Operation::Put, data_file.size, 0);
+        Ok(true)
+    }
+
+    // Update the existing copy at target in place, writing only the chunks
+    // that changed, then check it has the file's MD5. Returns the number of
+    // bytes written. If the check fails, the copy must be replaced in full.
+    async fn delta_update(
+        &self,
+        data_file: &DataFile,
+        path_context: &Path,
+        target: &Path,
+    ) -> Result<u64> {
+        let source = path_context.join(&data_file.path);
+        let summary = delta_copy_blocking(&source, target, ChunkParams::default()).await?;
+        let md5 = rehash_md5(target).await?.unwrap_or_default();
+        if md5 != data_file.recorded_md5() {
+            return Err(anyhow!(
+                "the updated copy has MD5 {}, not the manifest's {}",
+                md5,
+                data_file.recorded_md5()
+            ));
+        }
+        Ok(summary.bytes_sent)
+    }
+
+    // Copy the file to partial, flushed to disk, checking that partial
+    // then has the file's MD5.
+    async fn copy_verified(
+        &self,
+        data_file: &DataFile,
+        path_context: &Path,
+        partial: &Path,
+        progress: &FileProgress,
+    ) -> Result<()> {
+        let source = path_context.join(&data_file.path);
+        let mut reader = tokio::fs::File::open(&source)
+            .await
+            .map_err(|err| anyhow!("Could not open {:?}: {}", source, err))?;
+        let mut writer = tokio::fs::File::create(partial)
+            .await
+            .map_err(|err| anyhow!("Could not write {:?}: {}", partial, err))?;
+        let mut buffer = vec![0; io_settings().buffer_size];
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read]).await?;
+            progress.inc(read as u64);
+        }
+        writer.sync_all().await?;
+        drop(writer);
+
+        let md5 = rehash_md5(partial).await?.unwrap_or_default();
+        if md5 != data_file.recorded_md5() {
+            return Err(anyhow!(
+                "After copying '{}', the copy has MD5 {}, not the manifest's {}; it was \
+                 removed. Was the file changed (see 'sdf status')?",
+                data_file.path,
+                md5,
+                data_file.recorded_md5()
+            ));
+        }
+        Ok(())
+    }
+
+    // The copy of a remote file, by its path, to filename.
+    pub fn download(&self, path: &str, filename: &str) -> CommandDownload {
+        CommandDownload {
+            program: "cp".into(),
+            args: vec!["--".to_string(), path.to_string()],
+            filename: filename.to_string(),
+        }
+    }
+
+    // Stream a remote file, by its path, to writer, verifying it against
+    // expected_md5.
+    pub async fn stream_to<W: Write>(
+        &self,
+        path: &str,
+        expected_md5: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let mut file =
+            fs::File::open(path).map_err(|err| anyhow!("Could not open '{}': {}", path, err))?;
+        let mut md5 = md5::Context::new();
+        let mut written: u64 = 0;
+        let mut buffer = vec![0; io_settings().buffer_size];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read])?;
+            md5.consume(&buffer[..read]);
+            written += read as u64;
+        }
+        writer.flush()?;
+
+        let md5 = format!("{:x}", md5.compute());
+        if md5 != expected_md5 {
+            return Err(anyhow!(
+                "MD5 mismatch: the manifest has {}, but the streamed data has {}.",
+                expected_md5,
+                md5
+            ));
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+
+    fn md5(contents: &str) -> String {
+        format!("{:x}", md5::compute(contents))
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    fn expected(statuses: &[(&str, &str)]) -> Vec<(String, String)> {
+        statuses
+            .iter()
+            .map(|(name, status)| (format!("data/{}", name), status.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_makes_path_absolute() {
+        let api = FilesystemAPI::new("shared/atlas/").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(api.path(), cwd.join("shared/atlas"));
+        assert_eq!(api.target("raw/a.tsv"), cwd.join("shared/atlas/raw/a.tsv"));
+        assert_eq!(FilesystemAPI::new("/").unwrap().path(), Path::new("/"));
+        assert!(FilesystemAPI::new("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_push_status_and_pull() {
+        setup();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = &temp_dir.path().join("project");
+        fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let mut data = DataCollection::new();
+        for (name, contents) in [
+            ("a.tsv", "same"),
+            ("b.tsv", "mine"),
+            ("c.tsv", "new"),
+            ("raw/d.tsv", "nested"),
+        ] {
+            let path = format!("data/{}", name);
+            fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        let root = temp_dir.path().join("shared/atlas");
+        let mut api = FilesystemAPI::new(&root.to_string_lossy()).unwrap();
+        api.path_style = PathStyle::Hierarchical;
+
+        // linking makes the directory, unless it already has files (and
+        // only links an existing one)
+        let err = api
+            .remote_init(LocalMetadata::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        assert!(root.is_dir());
+        fs::write(root.join("a.tsv"), "same").unwrap();
+        fs::write(root.join("b.tsv"), "main").unwrap();
+        // an interrupted copy is not a remote file
+        fs::write(root.join("c.tsv.partial"), "ne").unwrap();
+        let err = api
+            .remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already has files"), "{}", err);
+        data.register_remote(&"data".to_string(), Remote::FilesystemAPI(api.clone()))
+            .unwrap();
+
+        // the copies are hashed, so files of the same size are compared
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            expected(&[
+                ("a.tsv", "current"),
+                ("b.tsv", "different"),
+                ("c.tsv", "not_exists"),
+                ("raw/d.tsv", "not_exists"),
+            ])
+        );
+
+        // only the new files are pushed, making their directories; with
+        // overwrite, the different one is replaced
+        let report = data
+            .push(path_context, false, &Default::default(), 2, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 2);
+        assert_eq!(fs::read_to_string(root.join("c.tsv")).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(root.join("raw/d.tsv")).unwrap(),
+            "nested"
+        );
+        let report = data
+            .push(path_context, true, &Default::default(), 2, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(fs::read_to_string(root.join("b.tsv")).unwrap(), "mine");
+        assert!(!root.join("b.tsv.partial").exists());
+        assert!(remote_statuses(&mut data, path_context)
+            .await
+            .iter()
+            .all(|(_, status)| status == "current"));
+
+        // a copy changed on the filesystem is no longer current
+        fs::write(root.join("a.tsv"), "sane").unwrap();
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await[0],
+            ("data/a.tsv".to_string(), "different".to_string())
+        );
+
+        // pulled back with cp, and checked against the manifest's MD5
+        fs::remove_file(path_context.join("data/raw/d.tsv")).unwrap();
+        fs::remove_file(path_context.join("data/a.tsv")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(report.count(Outcome::Failed), 1);
+        assert!(!path_context.join("data/a.tsv").exists());
+        assert_eq!(
+            fs::read_to_string(path_context.join("data/raw/d.tsv")).unwrap(),
+            "nested"
+        );
+
+        // streamed, as by 'sdf cat'
+        let mut streamed = Vec::new();
+        let path = root.join("raw/d.tsv").to_string_lossy().to_string();
+        api.stream_to(&path, &md5("nested"), &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"nested");
+        let path = root.join("a.tsv").to_string_lossy().to_string();
+        let err = api
+            .stream_to(&path, &md5("same"), &mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("MD5 mismatch"));
+    }
+
+    #[tokio::test]
+    async fn test_track_and_push_in_subdirectory() {
+        setup();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = &temp_dir.path().join("project");
+        fs::create_dir_all(path_context.join("data/sub")).unwrap();
+        let root = temp_dir.path().join("shared/atlas");
+        let mut api = FilesystemAPI::new(&root.to_string_lossy()).unwrap();
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::FilesystemAPI(api))
+            .unwrap();
+
+        // files under the linked directory are tracked with its remote
+        let path = "data/sub/y.txt".to_string();
+        fs::write(path_context.join(&path), "nested").unwrap();
+        let data_file = DataFile::new(path.clone(), None, path_context)
+            .await
+            .unwrap();
+        data.register(data_file).unwrap();
+        data.track_file(&path, path_context).unwrap();
+        assert!(data.files[&path].tracked);
+
+        // and pushed under their flat name
+        let report = data
+            .push(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(
+            fs::read_to_string(root.join("sub__y.txt")).unwrap(),
+            "nested"
+        );
+
+        // files outside of any linked directory cannot be tracked
+        fs::create_dir_all(path_context.join("results")).unwrap();
+        let path = "results/z.txt".to_string();
+        fs::write(path_context.join(&path), "other").unwrap();
+        let data_file = DataFile::new(path.clone(), None, path_context)
+            .await
+            .unwrap();
+        data.register(data_file).unwrap();
+        let err = data.track_file(&path, path_context).unwrap_err();
+        assert!(err.to_string().contains("not registered"), "{}", err);
+    }
+}
@@ -0,0 +1,1080 @@
+// Google Drive API
+//
+// A remote is a Drive folder, by its ID (the last part of its URL, e.g.
+// https://drive.google.com/drive/folders/<ID>), or one made when linking,
+// named after the project, in My Drive. Files are listed with the
+// Drive API (folder by folder, as Drive has no paths), uploaded with
+// resumable uploads, and downloaded with 'alt=media' requests. Drive
+// reports each file's MD5 (md5Checksum), except for Google Docs, Sheets,
+// etc., which are not data files and so are not listed. With --path-style
+// hierarchical, folders are made as needed. Drive allows several files of
+// the same name in a folder; only the first listed is used.
+//
+// sdf is authorized with the OAuth device flow, with the user's own OAuth
+// client (of the "TVs and Limited Input devices" type), given to 'sdf link'
+// as CLIENT_ID:CLIENT_SECRET: the user visits a Google page and enters a
+// code shown by sdf, once, after which the refresh token is saved with the
+// other keys. Access tokens are fetched with it as needed. The device flow
+// only grants the drive.file scope, so sdf (with that OAuth client) only
+// sees the files and folders it made: a folder made elsewhere cannot be
+// linked, so sdf makes the folder, which can then be shared.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+#[allow(unused_imports)]
+use log::{debug, info, trace};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, LOCATION};
+use reqwest::{Client, Method, Response};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::lib::data::DataFile;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::LocalMetadata;
+use crate::lib::remote::{retry_policy, send_with_retry, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
+use crate::lib::utils::shorten;
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+const OAUTH_URL: &str = "https://oauth2.googleapis.com";
+const API_URL: &str = "https://www.googleapis.com";
+const WEB_URL: &str = "https://drive.google.com/drive/folders";
+const SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+// the fields of files in listings and responses
+const FILE_FIELDS: &str = "id, name, mimeType, md5Checksum, size";
+
+// The key of the saved refresh token (the OAuth client is saved as
+// 'gdrive').
+pub const REFRESH_TOKEN_KEY: &str = "gdrive_refresh_token";
+
+// State shared by the clones of a remote (e.g. those made for concurrent
+// uploads), which is not part of its identity.
+#[derive(Debug, Clone, Default)]
+struct Shared<T>(Arc<tokio::sync::Mutex<T>>);
+
+impl<T> PartialEq for Shared<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    expires_in: u64,
+    #[serde(default)]
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileList {
+    #[serde(default)]
+    next_page_token: Option<String>,
+    files: Vec<DriveFile>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveFile {
+    id: String,
+    name: String,
+    #[serde(default)]
+    mime_type: String,
+    #[serde(default)]
+    md5_checksum: Option<String>,
+    // (Drive gives sizes as strings)
+    #[serde(default)]
+    size: Option<String>,
+}
+
+impl DriveFile {
+    fn is_folder(&self) -> bool {
+        self.mime_type == FOLDER_MIME_TYPE
+    }
+
+    // Google Docs, Sheets, etc. (which have no contents to download)
+    fn is_google_file(&self) -> bool {
+        self.mime_type.starts_with("application/vnd.google-apps.")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct GoogleDriveAPI {
+    // set for mock HTTP servers in tests (serving both OAuth and Drive)
+    #[serde(skip_serializing, skip_deserializing)]
+    base_url: Option<String>,
+    folder_id: String,
+    // the folder's name, once linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    folder_name: Option<String>,
+    // the OAuth client, as CLIENT_ID:CLIENT_SECRET
+    #[serde(skip_serializing, skip_deserializing)]
+    client: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    refresh_token: String,
+    #[serde(skip)]
+    access_token: Shared<Option<String>>,
+    // the IDs of folders made by this run, by path (so that concurrent
+    // uploads do not each make them)
+    #[serde(skip)]
+    made_folders: Shared<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+}
+
+impl GoogleDriveAPI {
+    // A remote for a folder, by its ID (or its URL).
+    pub fn new(folder: &str, base_url: Option<String>) -> Result<Self> {
+        let folder_id = folder.trim();
+        let folder_id = match folder_id.split_once("/folders/") {
+            Some((_, rest)) => rest,
+            None => folder_id,
+        };
+        let folder_id = folder_id
+            .split(['/', '?', '#'])
+            .next()
+            .filter(|id| !id.is_empty())
+            .filter(|id| {
+                id.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+            .ok_or(anyhow!(
+                "Linking to Google Drive requires --name <FOLDER_ID>, the folder's ID in its \
+                 URL (https://drive.google.com/drive/folders/<FOLDER_ID>), not '{}'.",
+                folder
+            ))?;
+        Ok(GoogleDriveAPI {
+            base_url,
+            folder_id: folder_id.to_string(),
+            folder_name: None,
+            client: String::new(),
+            refresh_token: String::new(),
+            access_token: Shared::default(),
+            made_folders: Shared::default(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        })
+    }
+
+    // A remote for a folder to be made by remote_init(), named name.
+    pub fn with_new_folder(name: &str, base_url: Option<String>) -> Self {
+        GoogleDriveAPI {
+            base_url,
+            folder_id: String::new(),
+            folder_name: Some(name.to_string()),
+            client: String::new(),
+            refresh_token: String::new(),
+            access_token: Shared::default(),
+            made_folders: Shared::default(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        }
+    }
+
+    // Set the OAuth client, and the refresh token if authorized.
+    pub fn set_credentials(&mut self, client: String, refresh_token: Option<String>) {
+        self.client = client;
+        self.refresh_token = refresh_token.unwrap_or_default();
+    }
+
+    pub fn has_token(&self) -> bool {
+        !self.client.is_empty()
+    }
+
+    pub fn is_authorized(&self) -> bool {
+        !self.refresh_token.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        Some(format!("{}/{}", WEB_URL, self.folder_id))
+    }
+
+    fn oauth_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(OAUTH_URL)
+    }
+
+    fn api_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(API_URL)
+    }
+
+    fn files_url(&self, id: &str) -> String {
+        format!("{}/drive/v3/files/{}", self.api_url(), id)
+    }
+
+    fn client_parts(&self) -> Result<(&str, &str)> {
+        self.client.split_once(':').ok_or(anyhow!(
+            "The Google Drive key must be the OAuth client, as CLIENT_ID:CLIENT_SECRET."
+        ))
+    }
+
+    // POST a form to the OAuth server, returning its response whatever its
+    // status.
+    async fn oauth_request(&self, path: &str, form: &[(&str, &str)]) -> Result<Response> {
+        let url = format!("{}/{}", self.oauth_url(), path);
+        let client = Client::new();
+        send_with_retry(&retry_policy(), &Method::POST, || async {
+            Ok(client.post(&url).form(form).send().await?)
+        })
+        .await
+    }
+
+    // Authorize sdf with the device flow, returning the refresh token (to
+    // be saved).
+    pub async fn authorize(&mut self) -> Result<String> {
+        let (client_id, client_secret) = self.client_parts()?;
+        let response = self
+            .oauth_request("device/code", &[("client_id", client_id), ("scope", SCOPE)])
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Could not start the Google Drive authorization: {}",
+                response.text().await?
+            ));
+        }
+        let device: DeviceCode = response.json().await?;
+        print_info!(
+            "To let sdf use Google Drive, visit {} and enter the code {}",
+            device.verification_url,
+            device.user_code
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = device.interval;
+        let form = [
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("device_code", device.device_code.as_str()),
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+        ];
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if Instant::now() > deadline {
+                return Err(anyhow!(
+                    "The Google Drive code expired before it was entered."
+                ));
+            }
+            let response = self.oauth_request("token", &form).await?;
+            if response.status().is_success() {
+                let token: TokenResponse = response.json().await?;
+                let refresh_token = token
+                    .refresh_token
+                    .ok_or(anyhow!("Google did not grant a refresh token."))?;
+                *self.access_token.0.lock().await = Some(token.access_token);
+                self.refresh_token = refresh_token.clone();
+                return Ok(refresh_token);
+            }
+            let error: OAuthError = response.json().await?;
+            match error.error.as_str() {
+                "authorization_pending" => (),
+                "slow_down" => interval += 5,
+                _ => {
+                    return Err(anyhow!(
+                        "Google Drive authorization failed: {}",
+                        error.error_description.unwrap_or(error.error)
+                    ))
+                }
+            }
+        }
+    }
+
+    // The access token, fetched with the refresh token if needed.
+    async fn access_token(&self) -> Result<String> {
+        let mut access_token = self.access_token.0.lock().await;
+        if let Some(token) = access_token.as_ref() {
+            return Ok(token.clone());
+        }
+        if !self.is_authorized() {
+            return Err(anyhow!(
+                "sdf is not authorized to use Google Drive: run 'sdf link' again, with \
+                 the OAuth client."
+            ));
+        }
+        let (client_id, client_secret) = self.client_parts()?;
+        let form = [
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("refresh_token", self.refresh_token.as_str()),
+            ("grant_type", "refresh_token"),
+        ];
+        let response = self.oauth_request("token", &form).await?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Could not refresh the Google Drive authorization (if it was revoked, \
+                 remove {} from ~/.scidataflow_authkeys.yml and run 'sdf link' again): {}",
+                REFRESH_TOKEN_KEY,
+                response.text().await?
+            ));
+        }
+        let token: TokenResponse = response.json().await?;
+        *access_token = Some(token.access_token.clone());
+        Ok(token.access_token)
+    }
+
+    // The headers of downloads (made once the remote's files are listed,
+    // so the access token is known).
+    pub fn headers(&self) -> Result<HeaderMap> {
+        let token = self
+            .access_token
+            .0
+            .try_lock()
+            .ok()
+            .and_then(|token| token.clone())
+            .ok_or(anyhow!(
+                "Internal Error: Google Drive download before authorization. Please report."
+            ))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+        Ok(headers)
+    }
+
+    async fn issue_request<T: serde::Serialize>(
+        &self,
+        method: Method,
+        url: &str,
+        query: &[(&str, &str)],
+        data: Option<RequestData<T>>,
+    ) -> Result<Response> {
+        let mut url = Url::parse(url)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        trace!("request URL: {:?}", url);
+
+        let operation = Operation::of_api_request(&method);
+        let bytes_sent = request_body_size(&data).await?;
+        let client = Client::new();
+        let bearer = format!("Bearer {}", self.access_token().await?);
+
+        // each attempt is sent (and accounted) as its own request
+        let (method, url, bearer, data) = (&method, &url, &bearer, &data);
+        let response = send_with_retry(&retry_policy(), method, || async {
+            let mut request = client
+                .request(method.clone(), url.clone())
+                .header(AUTHORIZATION, bearer);
+            if let Some(data) = data {
+                request = data.attach(request).await?;
+            }
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await?;
+        let response_status = response.status();
+        if response_status.is_success() {
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "HTTP Error: {}\nurl: {:?}\n{:?}",
+                response_status,
+                url.as_str(),
+                response.text().await?
+            ))
+        }
+    }
+
+    // Google Drive Remote initialization
+    //
+    // This makes the folder if it has no ID yet. Otherwise, it checks that
+    // the folder exists (and sdf can access it), and records its name.
+    // Unless only linking, the folder must not have files yet.
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        if self.folder_id.is_empty() {
+            if link_only {
+                return Err(anyhow!(
+                    "Linking to Google Drive with --link-only requires --name <FOLDER_ID>."
+                ));
+            }
+            let url = format!("{}/drive/v3/files", self.api_url());
+            let metadata = json!({"name": self.folder_name, "mimeType": FOLDER_MIME_TYPE});
+            let query = [("fields", FILE_FIELDS), ("supportsAllDrives", "true")];
+            let folder: DriveFile = self
+                .issue_request(
+                    Method::POST,
+                    &url,
+                    &query,
+                    Some(RequestData::Json(metadata)),
+                )
+                .await?
+                .json()
+                .await?;
+            print_info!(
+                "Made Google Drive folder '{}': {}/{}",
+                folder.name,
+                WEB_URL,
+                folder.id
+            );
+            self.folder_id = folder.id;
+            return Ok(());
+        }
+        let url = self.files_url(&self.folder_id);
+        let query = [("fields", FILE_FIELDS), ("supportsAllDrives", "true")];
+        let folder: DriveFile = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, &query, None)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Could not find Google Drive folder '{}' (check the ID, and that sdf can \
+                     access it): {}",
+                    self.folder_id,
+                    err
+                )
+            })?
+            .json()
+            .await?;
+        if !folder.is_folder() {
+            return Err(anyhow!(
+                "Google Drive file '{}' ({}) is not a folder.",
+                folder.name,
+                self.folder_id
+            ));
+        }
+        if !link_only && !self.get_files().await?.is_empty() {
+            return Err(anyhow!(
+                "Google Drive folder '{}' already has files. Use --link-only to link.",
+                folder.name
+            ));
+        }
+        self.folder_name = Some(folder.name);
+        Ok(())
+    }
+
+    // The files and folders in the folder, and in its folders, by their
+    // paths in it (folders end in '/').
+    async fn list(&self) -> Result<HashMap<String, DriveFile>> {
+        let mut entries: HashMap<String, DriveFile> = HashMap::new();
+        let mut folders = vec![(self.folder_id.clone(), String::new())];
+        let url = format!("{}/drive/v3/files", self.api_url());
+        let fields = format!("nextPageToken, files({})", FILE_FIELDS);
+        while let Some((folder_id, prefix)) = folders.pop() {
+            let q = format!("'{}' in parents and trashed = false", folder_id);
+            let mut page_token: Option<String> = None;
+            loop {
+                let mut query = vec![
+                    ("q", q.as_str()),
+                    ("fields", fields.as_str()),
+                    ("pageSize", "1000"),
+                    ("supportsAllDrives", "true"),
+                    ("includeItemsFromAllDrives", "true"),
+                ];
+                if let Some(page_token) = &page_token {
+                    query.push(("pageToken", page_token));
+                }
+                let response = self
+                    .issue_request::<HashMap<String, String>>(Method::GET, &url, &query, None)
+                    .await?;
+                let listing: FileList = response.json().await?;
+                for file in listing.files {
+                    let mut path = format!("{}{}", prefix, file.name);
+                    if file.is_folder() {
+                        path.push('/');
+                    }
+                    if entries.contains_key(&path) {
+                        print_warn!(
+                            "Google Drive has more than one '{}'; only the first is used.",
+                            path
+                        );
+                        continue;
+                    }
+                    if file.is_folder() {
+                        folders.push((file.id.clone(), path.clone()));
+                    }
+                    entries.insert(path, file);
+                }
+                page_token = listing.next_page_token;
+                if page_token.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    // The folder's files (by their paths), without Google Docs, etc.
+    pub async fn get_files(&self) -> Result<Vec<(String, DriveFile)>> {
+        let entries = self.list().await?;
+        Ok(entries
+            .into_iter()
+            .filter(|(_, file)| !file.is_google_file())
+            .collect())
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let files = self.get_files().await?;
+        let remote_files = files
+            .into_iter()
+            .map(|(name, file)| RemoteFile {
+                name,
+                md5: file.md5_checksum.map(|md5| md5.to_lowercase()),
+                size: file.size.and_then(|size| size.parse().ok()),
+                remote_service: "Google Drive".to_string(),
+                url: Some(format!(
+                    "{}?alt=media&supportsAllDrives=true",
+                    self.files_url(&file.id)
+                )),
+            })
+            .collect();
+        Ok(remote_files)
+    }
+
+    // The ID of directory's folder (e.g. 'raw/fastq'), making its folders
+    // as needed.
+    async fn folder(
+        &self,
+        directory: &str,
+        entries: &HashMap<String, DriveFile>,
+    ) -> Result<String> {
+        let mut made_folders = self.made_folders.0.lock().await;
+        let mut folder_id = self.folder_id.clone();
+        let mut path = String::new();
+        for name in directory.split('/').filter(|name| !name.is_empty()) {
+            path = format!("{}{}/", path, name);
+            let existing = entries
+                .get(&path)
+                .filter(|file| file.is_folder())
+                .map(|file| file.id.clone())
+                .or_else(|| made_folders.get(&path).cloned());
+            folder_id = match existing {
+                Some(id) => id,
+                None => {
+                    let url = format!("{}/drive/v3/files", self.api_url());
+                    let metadata = json!({
+                        "name": name,
+                        "mimeType": FOLDER_MIME_TYPE,
+                        "parents": [folder_id],
+                    });
+                    let query = [("fields", FILE_FIELDS), ("supportsAllDrives", "true")];
+                    let made: DriveFile = self
+                        .issue_request(
+                            Method::POST,
+                            &url,
+                            &query,
+                            Some(RequestData::Json(metadata)),
+                        )
+                        .await?
+                        .json()
+                        .await?;
+                    made_folders.insert(path.clone(), made.id.clone());
+                    made.id
+                }
+            };
+        }
+        Ok(folder_id)
+    }
+
+    // Upload the file, updating any existing file if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let entries = self.list().await?;
+        let existing = entries.get(name).filter(|file| !file.is_folder());
+        if existing.is_some() && !overwrite {
+            print_info!(
+                "GoogleDriveAPI::upload() found file '{}' in Google Drive folder '{}'. Since \
+                 overwrite=false, this file will not be re-uploaded.",
+                name,
+                self.folder_id
+            );
+            return Ok(false);
+        }
+
+        // a resumable upload: its session is started (with the file's
+        // metadata), then the file is sent to the session's URL
+        let upload_url = format!("{}/upload/drive/v3/files", self.api_url());
+        let query = [
+            ("uploadType", "resumable"),
+            ("supportsAllDrives", "true"),
+            ("fields", FILE_FIELDS),
+        ];
+        let session = match existing {
+            // a new version of the file
+            Some(existing) => {
+                let url = format!("{}/{}", upload_url, existing.id);
+                let metadata = RequestData::Json(json!({}));
+                self.issue_request(Method::PATCH, &url, &query, Some(metadata))
+                    .await?
+            }
+            None => {
+                let (directory, filename) = name.rsplit_once('/').unwrap_or(("", name));
+                let parent = self.folder(directory, &entries).await?;
+                let metadata = RequestData::Json(json!({"name": filename, "parents": [parent]}));
+                self.issue_request(Method::POST, &upload_url, &query, Some(metadata))
+                    .await?
+            }
+        };
+        let session_url = session
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or(anyhow!(
+                "Google Drive did not start the upload of '{}'.",
+                name
+            ))?
+            .to_string();
+        let file = tokio::fs::File::open(data_file.full_path(path_context)?).await?;
+        let data = Some(RequestData::<HashMap<String, String>>::Stream(
+            file,
+            progress.clone(),
+        ));
+        let uploaded: DriveFile = self
+            .issue_request(Method::PUT, &session_url, &[], data)
+            .await?
+            .json()
+            .await?;
+
+        // a new file with a different MD5 than local is not kept
+        let local_md5 = data_file.recorded_md5();
+        match uploaded.md5_checksum.map(|md5| md5.to_lowercase()) {
+            Some(remote_md5) if remote_md5 != local_md5 => {
+                if existing.is_none() {
+                    let url = self.files_url(&uploaded.id);
+                    let query = [("supportsAllDrives", "true")];
+                    self.issue_request::<HashMap<String, String>>(
+                        Method::DELETE,
+                        &url,
+                        &query,
+                        None,
+                    )
+                    .await?;
+                }
+                Err(anyhow!(
+                    "After upload, the local ({}) and remote ({}) MD5s of '{}' differed.",
+                    shorten(local_md5, Some(8)),
+                    shorten(&remote_md5, Some(8)),
+                    name
+                ))
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use httpmock::prelude::*;
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    const TEST_CLIENT: &str = "client-id:client-secret";
+    const TEST_BEARER: &str = "Bearer access-token";
+
+    fn test_api(server: &MockServer) -> GoogleDriveAPI {
+        let mut api = GoogleDriveAPI::new("root1", Some(server.url(""))).unwrap();
+        api.set_credentials(TEST_CLIENT.to_string(), Some("refresh-token".to_string()));
+        api
+    }
+
+    fn md5(contents: &str) -> String {
+        format!("{:x}", md5::compute(contents))
+    }
+
+    fn file(id: &str, name: &str, contents: &str) -> Value {
+        json!({"id": id, "name": name, "mimeType": "text/tab-separated-values",
+               "md5Checksum": md5(contents), "size": contents.len().to_string()})
+    }
+
+    fn folder(id: &str, name: &str) -> Value {
+        json!({"id": id, "name": name, "mimeType": FOLDER_MIME_TYPE})
+    }
+
+    fn in_parents(id: &str) -> String {
+        format!("'{}' in parents and trashed = false", id)
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    #[test]
+    fn test_new_parses_folder() {
+        for folder in [
+            "1AbC-d_9",
+            "https://drive.google.com/drive/folders/1AbC-d_9",
+            "https://drive.google.com/drive/u/0/folders/1AbC-d_9/?usp=sharing",
+        ] {
+            assert_eq!(
+                GoogleDriveAPI::new(folder, None).unwrap().folder_id,
+                "1AbC-d_9"
+            );
+        }
+        let api = GoogleDriveAPI::new("1AbC-d_9", None).unwrap();
+        assert_eq!(
+            api.web_url().unwrap(),
+            "https://drive.google.com/drive/folders/1AbC-d_9"
+        );
+        for folder in ["", "my folder", "https://drive.google.com/drive/folders/"] {
+            let err = GoogleDriveAPI::new(folder, None).unwrap_err();
+            assert!(err.to_string().contains("--name <FOLDER_ID>"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_init_makes_or_checks_folder() {
+        setup();
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/token");
+            then.status(200)
+                .json_body(json!({"access_token": "access-token"}));
+        });
+        let make = server.mock(|when, then| {
+            when.method(POST)
+                .path("/drive/v3/files")
+                .header("Authorization", TEST_BEARER)
+                .json_body(json!({"name": "Atlas", "mimeType": FOLDER_MIME_TYPE}));
+            then.status(200).json_body(folder("atlas1", "Atlas"));
+        });
+        let mut api = GoogleDriveAPI::with_new_folder("Atlas", Some(server.url("")));
+        api.set_credentials(TEST_CLIENT.to_string(), Some("refresh-token".to_string()));
+        let err = api
+            .remote_init(LocalMetadata::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("requires --name"), "{}", err);
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        make.assert();
+        assert_eq!(api.folder_id, "atlas1");
+
+        // an existing folder must be a folder, without files unless only linking
+        server.mock(|when, then| {
+            when.method(GET).path("/drive/v3/files/atlas1");
+            then.status(200).json_body(folder("atlas1", "Atlas"));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/drive/v3/files/a1");
+            then.status(200).json_body(file("a1", "a.tsv", "same"));
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/drive/v3/files")
+                .query_param("q", in_parents("atlas1"));
+            then.status(200)
+                .json_body(json!({"files": [file("a1", "a.tsv", "same")]}));
+        });
+        let err = api
+            .remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already has files"), "{}", err);
+        api.remote_init(LocalMetadata::default(), true)
+            .await
+            .unwrap();
+        let mut api = test_api(&server);
+        api.folder_id = "a1".to_string();
+        let err = api
+            .remote_init(LocalMetadata::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a folder"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_authorize_with_device_flow() {
+        setup();
+        let server = MockServer::start();
+        let device_code = server.mock(|when, then| {
+            when.method(POST)
+                .path("/device/code")
+                .x_www_form_urlencoded_tuple("client_id", "client-id")
+                .x_www_form_urlencoded_tuple("scope", SCOPE);
+            then.status(200).json_body(json!({
+                "device_code": "device", "user_code": "ABCD-EFGH",
+                "verification_url": "https://www.google.com/device",
+                "expires_in": 1800, "interval": 0
+            }));
+        });
+        let token = server.mock(|when, then| {
+            when.method(POST)
+                .path("/token")
+                .x_www_form_urlencoded_tuple("client_secret", "client-secret")
+                .x_www_form_urlencoded_tuple("device_code", "device");
+            then.status(200).json_body(json!({
+                "access_token": "access-token", "refresh_token": "refresh-token",
+                "expires_in": 3599
+            }));
+        });
+        let mut api = GoogleDriveAPI::new("root1", Some(server.url(""))).unwrap();
+        api.set_credentials(TEST_CLIENT.to_string(), None);
+        assert!(!api.is_authorized());
+        assert_eq!(api.authorize().await.unwrap(), "refresh-token");
+        device_code.assert();
+        token.assert();
+        assert!(api.is_authorized());
+        assert_eq!(api.headers().unwrap()[AUTHORIZATION], TEST_BEARER);
+
+        // a denied authorization is an error
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.path("/device/code");
+            then.status(200).json_body(json!({
+                "device_code": "device", "user_code": "ABCD-EFGH",
+                "verification_url": "https://www.google.com/device", "expires_in": 1800
+            }));
+        });
+        server.mock(|when, then| {
+            when.path("/token");
+            then.status(403)
+                .json_body(json!({"error": "access_denied"}));
+        });
+        let mut api = GoogleDriveAPI::new("root1", Some(server.url(""))).unwrap();
+        api.set_credentials(TEST_CLIENT.to_string(), None);
+        let err = api.authorize().await.unwrap_err().to_string();
+        assert!(
+            err.contains("authorization failed: access_denied"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_upload_and_pull() {
+        setup();
+        let server = MockServer::start();
+        let refresh = server.mock(|when, then| {
+            when.method(POST)
+                .path("/token")
+                .x_www_form_urlencoded_tuple("grant_type", "refresh_token")
+                .x_www_form_urlencoded_tuple("refresh_token", "refresh-token");
+            then.status(200)
+                .json_body(json!({"access_token": "access-token", "expires_in": 3599}));
+        });
+        let root = server.mock(|when, then| {
+            when.method(GET)
+                .path("/drive/v3/files")
+                .header("Authorization", TEST_BEARER)
+                .query_param("q", in_parents("root1"));
+            then.status(200).json_body(json!({"files": [
+                file("a1", "a.tsv", "same"),
+                folder("raw1", "raw"),
+                {"id": "doc1", "name": "notes", "mimeType": "application/vnd.google-apps.document"},
+            ]}));
+        });
+        let raw = server.mock(|when, then| {
+            when.method(GET)
+                .path("/drive/v3/files")
+                .query_param("q", in_parents("raw1"));
+            then.status(200)
+                .json_body(json!({"files": [file("b1", "b.tsv", "theirs")]}));
+        });
+        let mut api = test_api(&server);
+        api.path_style = PathStyle::Hierarchical;
+        let mut files: Vec<_> = api
+            .get_remote_files()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.md5, file.size))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                ("a.tsv".to_string(), Some(md5("same")), Some(4)),
+                ("raw/b.tsv".to_string(), Some(md5("theirs")), Some(6)),
+            ]
+        );
+        root.assert();
+        raw.assert();
+
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let mut data = DataCollection::new();
+        for (name, contents) in [
+            ("a.tsv", "same"),
+            ("raw/b.tsv", "mine"),
+            ("new/c.tsv", "new"),
+        ] {
+            let path = format!("data/{}", name);
+            let full_path = path_context.join(&path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(full_path, contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        data.register_remote(&"data".to_string(), Remote::GoogleDriveAPI(api.clone()))
+            .unwrap();
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            vec![
+                ("data/a.tsv".to_string(), "current".to_string()),
+                ("data/new/c.tsv".to_string(), "not_exists".to_string()),
+                ("data/raw/b.tsv".to_string(), "different".to_string()),
+            ]
+        );
+
+        // a new file's folder is made, then it is uploaded to a session
+        let make_folder = server.mock(|when, then| {
+            when.method(POST).path("/drive/v3/files").json_body(
+                json!({"name": "new", "mimeType": FOLDER_MIME_TYPE, "parents": ["root1"]}),
+            );
+            then.status(200).json_body(folder("new1", "new"));
+        });
+        let session = server.mock(|when, then| {
+            when.method(POST)
+                .path("/upload/drive/v3/files")
+                .query_param("uploadType", "resumable")
+                .json_body(json!({"name": "c.tsv", "parents": ["new1"]}));
+            then.status(200)
+                .header("Location", server.url("/upload/session/c"));
+        });
+        let put = server.mock(|when, then| {
+            when.method(PUT)
+                .path("/upload/session/c")
+                .header("Authorization", TEST_BEARER)
+                .body("new");
+            then.status(200).json_body(file("c1", "c.tsv", "new"));
+        });
+        let c_file = data.files.get("data/new/c.tsv").unwrap().clone();
+        let uploaded = api
+            .upload(
+                &c_file,
+                "new/c.tsv",
+                path_context,
+                false,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert!(uploaded);
+        make_folder.assert();
+        session.assert();
+        put.assert();
+
+        // an existing file is only updated with overwrite
+        let b_file = data.files.get("data/raw/b.tsv").unwrap().clone();
+        let uploaded = api
+            .upload(
+                &b_file,
+                "raw/b.tsv",
+                path_context,
+                false,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert!(!uploaded);
+        let update = server.mock(|when, then| {
+            when.method(httpmock::Method::PATCH)
+                .path("/upload/drive/v3/files/b1")
+                .query_param("uploadType", "resumable");
+            then.status(200)
+                .header("Location", server.url("/upload/session/b"));
+        });
+        let put_b = server.mock(|when, then| {
+            when.method(PUT).path("/upload/session/b").body("mine");
+            then.status(200).json_body(file("b1", "b.tsv", "corrupted"));
+        });
+        let err = api
+            .upload(
+                &b_file,
+                "raw/b.tsv",
+                path_context,
+                true,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("MD5s of 'raw/b.tsv' differed"),
+            "{}",
+            err
+        );
+        update.assert();
+        put_b.assert();
+
+        // pulled with the access token in a header
+        let get = server.mock(|when, then| {
+            when.method(GET)
+                .path("/drive/v3/files/a1")
+                .query_param("alt", "media")
+                .header("Authorization", TEST_BEARER);
+            then.status(200).body("same");
+        });
+        std::fs::remove_file(path_context.join("data/a.tsv")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/a.tsv")).unwrap(),
+            "same"
+        );
+        get.assert();
+        // (the access token is fetched once)
+        refresh.assert_hits(1);
+    }
+}
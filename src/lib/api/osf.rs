@@ -0,0 +1,648 @@
+// Open Science Framework (OSF) API
+//
+// A remote is an existing OSF project (or component), by its GUID, e.g.
+// 'abc12' for https://osf.io/abc12/, whose files are kept in its OSF
+// Storage. The project is looked up with OSF's API, and files are listed,
+// uploaded and downloaded with Waterbutler, OSF's file API. Waterbutler
+// refers to files and folders by their IDs (e.g. '/5a1b2c3d'), and gives
+// their paths in the project as their "materialized" paths, which are the
+// remote file names (so with --path-style hierarchical, folders are made
+// as needed). OSF Storage reports each file's MD5.
+//
+// Requests (including downloads, which cannot take the token in their URL)
+// are made with a personal access token, as a Bearer token.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+#[allow(unused_imports)]
+use log::{debug, info, trace};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, Method, Response};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+use url::Url;
+
+use crate::lib::data::DataFile;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::LocalMetadata;
+use crate::lib::remote::{retry_policy, send_with_retry, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
+use crate::lib::utils::shorten;
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+const API_URL: &str = "https://api.osf.io/v2";
+const FILES_URL: &str = "https://files.osf.io/v1";
+const WEB_URL: &str = "https://osf.io";
+
+// The response to GETs of /nodes/{guid}/.
+#[derive(Debug, Deserialize)]
+struct OsfNode {
+    data: OsfNodeData,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsfNodeData {
+    attributes: OsfNodeAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+struct OsfNodeAttributes {
+    title: String,
+}
+
+// Waterbutler's listing of a folder.
+#[derive(Debug, Deserialize)]
+struct WaterbutlerListing {
+    data: Vec<WaterbutlerEntry>,
+}
+
+// Waterbutler's response to uploads, and to making folders.
+#[derive(Debug, Deserialize)]
+struct WaterbutlerResponse {
+    data: WaterbutlerEntry,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct WaterbutlerEntry {
+    attributes: WaterbutlerFile,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WaterbutlerFile {
+    // 'file' or 'folder'
+    kind: String,
+    // the ID path, e.g. '/5a1b2c3d' (folders end in '/')
+    path: String,
+    // the path in the project, e.g. '/raw/reads.fq'
+    materialized: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    extra: Value,
+}
+
+impl WaterbutlerFile {
+    fn is_folder(&self) -> bool {
+        self.kind == "folder"
+    }
+
+    // The file's name as a remote file (its path in the project).
+    pub fn name(&self) -> &str {
+        self.materialized.trim_matches('/')
+    }
+
+    fn md5(&self) -> Option<String> {
+        self.extra["hashes"]["md5"]
+            .as_str()
+            .filter(|md5| !md5.is_empty())
+            .map(|md5| md5.to_lowercase())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct OsfAPI {
+    // set for mock HTTP servers in tests (serving both APIs)
+    #[serde(skip_serializing, skip_deserializing)]
+    base_url: Option<String>,
+    // the GUID of the project or component
+    guid: String,
+    // the project's title, once linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing, skip_deserializing)]
+    token: String,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+}
+
+impl OsfAPI {
+    // A remote for a project, by its GUID (or its URL, e.g.
+    // https://osf.io/abc12/).
+    pub fn new(project: &str, base_url: Option<String>) -> Result<Self> {
+        let guid = project.trim().trim_end_matches('/');
+        let guid = guid.strip_prefix(WEB_URL).unwrap_or(guid).trim_matches('/');
+        if guid.is_empty() || !guid.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!(
+                "Linking to OSF requires --name <GUID>, the project's ID in its URL \
+                 (e.g. 'abc12' for https://osf.io/abc12/), not '{}'.",
+                project
+            ));
+        }
+        Ok(OsfAPI {
+            base_url,
+            guid: guid.to_lowercase(),
+            title: None,
+            token: String::new(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        })
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = token;
+    }
+
+    pub fn has_token(&self) -> bool {
+        !self.token.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        Some(format!("{}/{}/", WEB_URL, self.guid))
+    }
+
+    fn api_url(&self) -> String {
+        match &self.base_url {
+            Some(base_url) => format!("{}/v2", base_url.trim_end_matches('/')),
+            None => API_URL.to_string(),
+        }
+    }
+
+    // The Waterbutler URL of an ID path in the project's OSF Storage (''
+    // for its root).
+    fn storage_url(&self, path: &str) -> String {
+        let files_url = match &self.base_url {
+            Some(base_url) => format!("{}/v1", base_url.trim_end_matches('/')),
+            None => FILES_URL.to_string(),
+        };
+        format!(
+            "{}/resources/{}/providers/osfstorage/{}",
+            files_url,
+            self.guid,
+            path.trim_start_matches('/')
+        )
+    }
+
+    // The headers of requests, and downloads.
+    pub fn headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let bearer = HeaderValue::from_str(&format!("Bearer {}", self.token))?;
+        headers.insert(AUTHORIZATION, bearer);
+        Ok(headers)
+    }
+
+    async fn issue_request<T: serde::Serialize>(
+        &self,
+        method: Method,
+        url: &str,
+        query: &[(&str, &str)],
+        data: Option<RequestData<T>>,
+    ) -> Result<Response> {
+        let mut url = Url::parse(url)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        trace!("request URL: {:?}", url);
+
+        let operation = Operation::of_api_request(&method);
+        let bytes_sent = request_body_size(&data).await?;
+        let client = Client::new();
+        let headers = self.headers()?;
+
+        // each attempt is sent (and accounted) as its own request
+        let (method, url, headers, data) = (&method, &url, &headers, &data);
+        let response = send_with_retry(&retry_policy(), method, || async {
+            let mut request = client
+                .request(method.clone(), url.clone())
+                .headers(headers.clone());
+            if let Some(data) = data {
+                request = data.attach(request).await?;
+            }
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await?;
+        let response_status = response.status();
+        if response_status.is_success() {
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "HTTP Error: {}\nurl: {:?}\n{:?}",
+                response_status,
+                url.as_str(),
+                response.text().await?
+            ))
+        }
+    }
+
+    // OSF Remote initialization
+    //
+    // OSF projects are made on OSF, so this checks that the project
+    // exists (and the token can access it), and records its title.
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        _link_only: bool,
+    ) -> Result<()> {
+        let url = format!("{}/nodes/{}/", self.api_url(), self.guid);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, &[], None)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Could not find OSF project '{}' (check the GUID, and that the \
+                     token can access it): {}",
+                    self.guid,
+                    err
+                )
+            })?;
+        let node: OsfNode = response.json().await?;
+        self.title = Some(node.data.attributes.title);
+        Ok(())
+    }
+
+    // The files and folders in a folder (by its ID path), and in its
+    // folders.
+    async fn list(&self, folder: &str) -> Result<Vec<WaterbutlerFile>> {
+        let mut entries = Vec::new();
+        let mut folders = vec![folder.to_string()];
+        while let Some(folder) = folders.pop() {
+            let url = self.storage_url(&folder);
+            let response = self
+                .issue_request::<HashMap<String, String>>(Method::GET, &url, &[("meta", "")], None)
+                .await?;
+            let listing: WaterbutlerListing = response.json().await?;
+            for entry in listing.data {
+                let file = entry.attributes;
+                if file.is_folder() {
+                    folders.push(file.path.clone());
+                }
+                entries.push(file);
+            }
+        }
+        Ok(entries)
+    }
+
+    pub async fn get_files(&self) -> Result<Vec<WaterbutlerFile>> {
+        let entries = self.list("").await?;
+        Ok(entries
+            .into_iter()
+            .filter(|file| !file.is_folder())
+            .collect())
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let files = self.get_files().await?;
+        let remote_files = files
+            .into_iter()
+            .map(|file| RemoteFile {
+                name: file.name().to_string(),
+                md5: file.md5(),
+                size: file.size,
+                remote_service: "OSF".to_string(),
+                url: Some(self.storage_url(&file.path)),
+            })
+            .collect();
+        Ok(remote_files)
+    }
+
+    // The ID path of directory (e.g. 'raw/fastq'), making its folders as
+    // needed.
+    async fn folder(&self, directory: &str, entries: &[WaterbutlerFile]) -> Result<String> {
+        let find = |entries: &[WaterbutlerFile], materialized: &str| {
+            entries
+                .iter()
+                .find(|file| file.is_folder() && file.materialized == materialized)
+                .map(|file| file.path.clone())
+        };
+        let mut path = "/".to_string();
+        let mut materialized = "/".to_string();
+        for name in directory.split('/').filter(|name| !name.is_empty()) {
+            materialized = format!("{}{}/", materialized, name);
+            if let Some(existing) = find(entries, &materialized) {
+                path = existing;
+                continue;
+            }
+            let url = self.storage_url(&path);
+            let query = [("kind", "folder"), ("name", name)];
+            let created = self
+                .issue_request::<HashMap<String, String>>(Method::PUT, &url, &query, None)
+                .await;
+            path = match created {
+                Ok(response) => {
+                    response
+                        .json::<WaterbutlerResponse>()
+                        .await?
+                        .data
+                        .attributes
+                        .path
+                }
+                // e.g. made by a concurrent upload
+                Err(err) => find(&self.list("").await?, &materialized).ok_or(err)?,
+            };
+        }
+        Ok(path)
+    }
+
+    // Upload the file, updating any existing file if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let entries = self.list("").await?;
+        let existing = entries
+            .iter()
+            .find(|file| !file.is_folder() && file.name() == name);
+        let file = tokio::fs::File::open(data_file.full_path(path_context)?).await?;
+        let data = Some(RequestData::<HashMap<String, String>>::Stream(
+            file,
+            progress.clone(),
+        ));
+        let response = match existing {
+            Some(_) if !overwrite => {
+                print_info!(
+                    "OSF::upload() found file '{}' in OSF project '{}'. Since overwrite=false, \
+                     this file will not be re-uploaded.",
+                    name,
+                    self.guid
+                );
+                return Ok(false);
+            }
+            // a new version of the file
+            Some(existing) => {
+                let url = self.storage_url(&existing.path);
+                self.issue_request(Method::PUT, &url, &[("kind", "file")], data)
+                    .await?
+            }
+            None => {
+                let (directory, filename) = name.rsplit_once('/').unwrap_or(("", name));
+                let url = self.storage_url(&self.folder(directory, &entries).await?);
+                let query = [("kind", "file"), ("name", filename)];
+                self.issue_request(Method::PUT, &url, &query, data).await?
+            }
+        };
+        let uploaded = response
+            .json::<WaterbutlerResponse>()
+            .await?
+            .data
+            .attributes;
+
+        // a file with a different MD5 than local is not kept
+        let local_md5 = data_file.recorded_md5();
+        match uploaded.md5() {
+            Some(remote_md5) if remote_md5 != local_md5 => {
+                let url = self.storage_url(&uploaded.path);
+                self.issue_request::<HashMap<String, String>>(Method::DELETE, &url, &[], None)
+                    .await?;
+                Err(anyhow!(
+                    "After upload, the local ({}) and remote ({}) MD5s of '{}' differed, \
+                     so the remote file was deleted.",
+                    shorten(local_md5, Some(8)),
+                    shorten(&remote_md5, Some(8)),
+                    name
+                ))
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use httpmock::prelude::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const TEST_TOKEN: &str = "test-token";
+    const STORAGE: &str = "/v1/resources/abc12/providers/osfstorage/";
+
+    fn test_api(server: &MockServer) -> OsfAPI {
+        let mut api = OsfAPI::new("abc12", Some(server.url(""))).unwrap();
+        api.set_token(TEST_TOKEN.to_string());
+        api
+    }
+
+    fn entry(kind: &str, path: &str, materialized: &str, contents: &str) -> Value {
+        json!({"attributes": {
+            "kind": kind, "path": path, "materialized": materialized,
+            "size": contents.len(),
+            "extra": {"hashes": {"md5": format!("{:x}", md5::compute(contents))}}
+        }})
+    }
+
+    #[test]
+    fn test_new_parses_guid() {
+        for project in ["abc12", "ABC12", "https://osf.io/abc12/", " abc12/ "] {
+            assert_eq!(OsfAPI::new(project, None).unwrap().guid, "abc12");
+        }
+        let api = OsfAPI::new("abc12", None).unwrap();
+        assert_eq!(api.web_url().unwrap(), "https://osf.io/abc12/");
+        assert_eq!(
+            api.storage_url("/5a1b/"),
+            "https://files.osf.io/v1/resources/abc12/providers/osfstorage/5a1b/"
+        );
+        for project in ["", "my project", "https://osf.io/"] {
+            let err = OsfAPI::new(project, None).unwrap_err();
+            assert!(err.to_string().contains("--name <GUID>"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_init_checks_project() {
+        setup();
+        let server = MockServer::start();
+        let node = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v2/nodes/abc12/")
+                .header("Authorization", format!("Bearer {}", TEST_TOKEN));
+            then.status(200)
+                .json_body(json!({"data": {"id": "abc12", "attributes": {"title": "Atlas"}}}));
+        });
+        let mut api = test_api(&server);
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        node.assert();
+        assert_eq!(api.title.as_deref(), Some("Atlas"));
+
+        let mut api = OsfAPI::new("zzz99", Some(server.url(""))).unwrap();
+        api.set_token(TEST_TOKEN.to_string());
+        server.mock(|when, then| {
+            when.method(GET).path("/v2/nodes/zzz99/");
+            then.status(404);
+        });
+        let err = api
+            .remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Could not find OSF project 'zzz99'"));
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    #[tokio::test]
+    async fn test_upload_status_and_pull() {
+        setup();
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let (reads, counts) = ("ACGT\n", "gene\tcount\n");
+        std::fs::write(path_context.join("data/raw/reads.fq"), reads).unwrap();
+        std::fs::write(path_context.join("data/counts.tsv"), counts).unwrap();
+
+        let mut api = test_api(&server);
+        api.path_style = PathStyle::Hierarchical;
+        let mut data = DataCollection::new();
+        for path in ["data/raw/reads.fq", "data/counts.tsv"] {
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        data.register_remote(&"data".to_string(), Remote::OsfAPI(api.clone()))
+            .unwrap();
+
+        // counts.tsv is on the root, but an old version
+        let mut root = server.mock(|when, then| {
+            when.method(GET).path(STORAGE);
+            then.status(200).json_body(json!({"data": [
+                entry("file", "/5c0", "/counts.tsv", "gene\n"),
+            ]}));
+        });
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            vec![
+                ("data/counts.tsv".to_string(), "different".to_string()),
+                ("data/raw/reads.fq".to_string(), "not_exists".to_string()),
+            ]
+        );
+
+        // a new file is uploaded to its folder, which is made first
+        let make_folder = server.mock(|when, then| {
+            when.method(PUT)
+                .path(STORAGE)
+                .query_param("kind", "folder")
+                .query_param("name", "raw");
+            then.status(201)
+                .json_body(json!({"data": entry("folder", "/f01/", "/raw/", "")}));
+        });
+        let new_file = server.mock(|when, then| {
+            when.method(PUT)
+                .path(format!("{}f01/", STORAGE))
+                .query_param("kind", "file")
+                .query_param("name", "reads.fq")
+                .header("Authorization", format!("Bearer {}", TEST_TOKEN))
+                .body(reads);
+            then.status(201)
+                .json_body(json!({"data": entry("file", "/5r0", "/raw/reads.fq", reads)}));
+        });
+        let reads_file = data.files.get("data/raw/reads.fq").unwrap().clone();
+        let progress = FileProgress::hidden();
+        assert!(api
+            .upload(&reads_file, "raw/reads.fq", path_context, false, &progress)
+            .await
+            .unwrap());
+        make_folder.assert();
+        new_file.assert();
+
+        // an existing one is only updated with overwrite
+        let update = server.mock(|when, then| {
+            when.method(PUT)
+                .path(format!("{}5c0", STORAGE))
+                .query_param("kind", "file")
+                .body(counts);
+            then.status(200)
+                .json_body(json!({"data": entry("file", "/5c0", "/counts.tsv", counts)}));
+        });
+        let counts_file = data.files.get("data/counts.tsv").unwrap().clone();
+        assert!(!api
+            .upload(&counts_file, "counts.tsv", path_context, false, &progress)
+            .await
+            .unwrap());
+        assert!(api
+            .upload(&counts_file, "counts.tsv", path_context, true, &progress)
+            .await
+            .unwrap());
+        update.assert_hits(1);
+
+        // the project's folders are listed too
+        root.delete();
+        server.mock(|when, then| {
+            when.method(GET).path(STORAGE);
+            then.status(200).json_body(json!({"data": [
+                entry("file", "/5c0", "/counts.tsv", counts),
+                entry("folder", "/f01/", "/raw/", ""),
+            ]}));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path(format!("{}f01/", STORAGE));
+            then.status(200).json_body(json!({"data": [
+                entry("file", "/5r0", "/raw/reads.fq", reads),
+            ]}));
+        });
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            vec![
+                ("data/counts.tsv".to_string(), "current".to_string()),
+                ("data/raw/reads.fq".to_string(), "current".to_string()),
+            ]
+        );
+
+        // and files are downloaded with the token
+        let download = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("{}5r0", STORAGE))
+                .header("Authorization", format!("Bearer {}", TEST_TOKEN));
+            then.status(200).body(reads);
+        });
+        std::fs::remove_file(path_context.join("data/raw/reads.fq")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        download.assert_hits(1);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/raw/reads.fq")).unwrap(),
+            reads
+        );
+    }
+}
@@ -0,0 +1,510 @@
+// Remotes reached through rclone (https://rclone.org), e.g. Google Drive,
+// Backblaze B2 or WebDAV: files are stored under a path of a remote set up
+// with 'rclone config', and sdf runs rclone for each operation.
+//
+// Files are listed with 'rclone lsjson', uploaded and downloaded with
+// 'rclone copyto', and streamed with 'rclone cat'. Listings ask for MD5s,
+// but not all providers have them; files without one are compared by
+// size (see RemoteFile::get_md5()).
+//
+// The rclone remote and path are saved in the manifest, while the rclone
+// binary is set per machine, with 'sdf config --rclone <PATH>' (by default,
+// the rclone on PATH).
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+use crate::lib::download::CommandDownload;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::Project;
+use crate::lib::remote::RemoteFile;
+use crate::lib::usage::{Operation, UsageCounter};
+use crate::lib::{data::DataFile, project::LocalMetadata};
+
+pub const DEFAULT_BINARY: &str = "rclone";
+
+// rclone's exit statuses for a missing directory or file.
+const DIRECTORY_NOT_FOUND: i32 = 3;
+const FILE_NOT_FOUND: i32 = 4;
+
+// The rclone binary set with 'sdf config --rclone', or else the one on PATH.
+pub fn configured_binary() -> PathBuf {
+    Project::load_config()
+        .ok()
+        .and_then(|config| config.rclone)
+        .unwrap_or(DEFAULT_BINARY.to_string())
+        .into()
+}
+
+// An entry of 'rclone lsjson' output.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListItem {
+    path: String,
+    // -1 if the provider does not know
+    size: i64,
+    #[serde(default)]
+    is_dir: bool,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct RcloneAPI {
+    // the rclone remote (as listed by 'rclone listremotes', without ':')
+    remote: String,
+    // the project's directory on the remote (without a trailing '/')
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    path: String,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    binary: Option<PathBuf>,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+}
+
+impl RcloneAPI {
+    // The remote for location, '<remote>:' or '<remote>:<path>'.
+    pub fn new(location: &str) -> Result<Self> {
+        let (remote, path) = location
+            .split_once(':')
+            .filter(|(remote, _)| !remote.is_empty())
+            .ok_or(anyhow!(
+                "Linking to rclone requires --name <remote>:<path> (see 'rclone listremotes')."
+            ))?;
+        Ok(RcloneAPI {
+            remote: remote.to_string(),
+            path: path.trim_matches('/').to_string(),
+            path_style: PathStyle::default(),
+            binary: None,
+            usage: UsageCounter::default(),
+        })
+    }
+
+    pub fn set_binary(&mut self, binary: PathBuf) {
+        self.binary = Some(binary);
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.binary.is_some()
+    }
+
+    fn binary(&self) -> PathBuf {
+        self.binary.clone().unwrap_or(DEFAULT_BINARY.into())
+    }
+
+    // The rclone path of the project's directory, e.g. 'gdrive:projects/atlas'.
+    fn root(&self) -> String {
+        format!("{}:{}", self.remote, self.path)
+    }
+
+    // The rclone path of a remote file.
+    fn target(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            format!("{}:{}", self.remote, name)
+        } else {
+            format!("{}:{}/{}", self.remote, self.path, name)
+        }
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(self.binary());
+        command.args(args).stdin(Stdio::null());
+        command
+    }
+
+    fn spawn_error(&self, err: std::io::Error) -> anyhow::Error {
+        anyhow!(
+            "Could not run rclone ('{}'): {}. Install rclone, or set its path with \
+             'sdf config --rclone <PATH>'.",
+            self.binary().display(),
+            err
+        )
+    }
+
+    // Run rclone, returning its output whatever its exit status, and
+    // recording how long it took (as the latency of a request).
+    async fn output(&self, args: &[&str]) -> Result<Output> {
+        let started = Instant::now();
+        let output = self
+            .command(args)
+            .output()
+            .await
+            .map_err(|err| self.spawn_error(err))?;
+        self.usage.record_latency(started.elapsed());
+        Ok(output)
+    }
+
+    fn failed(&self, args: &[&str], output: &Output) -> anyhow::Error {
+        anyhow!(
+            "'rclone {}' failed ({}): {}",
+            args.first().unwrap_or(&""),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+
+    // List the files under an rclone path (none if it does not exist).
+    async fn list(&self, target: &str) -> Result<Vec<ListItem>> {
+        let args = [
+            "lsjson",
+            "--recursive",
+            "--files-only",
+            "--hash",
+            "--hash-type",
+            "md5",
+            target,
+        ];
+        let output = self.output(&args).await?;
+        self.usage
+            .record(Operation::List, 0, output.stdout.len() as u64);
+        match output.status.code() {
+            Some(0) => (),
+            Some(DIRECTORY_NOT_FOUND | FILE_NOT_FOUND) => return Ok(Vec::new()),
+            _ => return Err(self.failed(&args, &output)),
+        }
+        let items: Vec<ListItem> = serde_json::from_slice(&output.stdout)
+            .map_err(|err| anyhow!("Could not parse the output of 'rclone lsjson': {}", err))?;
+        Ok(items.into_iter().filter(|item| !item.is_dir).collect())
+    }
+
+    // Check that rclone can list the remote, and, unless only linking,
+    // that no files are stored under the path yet.
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        let items = self.list(&self.root()).await?;
+        if !items.is_empty() && !link_only {
+            return Err(anyhow!(
+                "rclone remote '{}' already has files. Use --link-only to link.",
+                self.root()
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let remote_files = self
+            .list(&self.root())
+            .await?
+            .into_iter()
+            .map(|item| RemoteFile {
+                md5: item
+                    .hashes
+                    .get("md5")
+                    .filter(|md5| !md5.is_empty())
+                    .map(|md5| md5.to_lowercase()),
+                size: u64::try_from(item.size).ok(),
+                name: item.path,
+                remote_service: "Rclone".to_string(),
+                url: None,
+            })
+            .collect();
+        Ok(remote_files)
+    }
+
+    // Upload the file, replacing any existing copy if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let target = self.target(name);
+        if !overwrite && !self.list(&target).await?.is_empty() {
+            print_info!(
+                "RcloneAPI::upload() found '{}'. Since overwrite=false, \
+                 this file will not be re-uploaded.",
+                target
+            );
+            return Ok(false);
+        }
+        let source = path_context.join(&data_file.path);
+        let source = source.to_string_lossy();
+        let args = ["copyto", source.as_ref(), &target];
+        let output = self.output(&args).await?;
+        if !output.status.success() {
+            self.usage.record(Operation::Put, 0, 0);
+            return Err(self.failed(&args, &output));
+        }
+        self.usage.record(Operation::Put, data_file.size, 0);
+        progress.inc(data_file.size);
+        Ok(true)
+    }
+
+    // The download of a remote file to filename.
+    pub fn download(&self, name: &str, filename: &str) -> CommandDownload {
+        CommandDownload {
+            program: self.binary(),
+            args: vec!["copyto".to_string(), self.target(name)],
+            filename: filename.to_string(),
+        }
+    }
+
+    // Stream a remote file to writer, verifying it against expected_md5.
+    pub async fn stream_to<W: Write>(
+        &self,
+        name: &str,
+        expected_md5: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let target = self.target(name);
+        let mut child = self
+            .command(&["cat", &target])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.spawn_error(err))?;
+        let mut stdout = child.stdout.take().unwrap();
+        let mut md5 = md5::Context::new();
+        let mut written: u64 = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = stdout.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read])?;
+            md5.consume(&buffer[..read]);
+            written += read as u64;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(self.failed(&["cat"], &output));
+        }
+        writer.flush()?;
+
+        let md5 = format!("{:x}", md5.compute());
+        if md5 != expected_md5 {
+            return Err(anyhow!(
+                "MD5 mismatch: the manifest has {}, but the streamed data has {}.",
+                expected_md5,
+                md5
+            ));
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // A fake rclone, which stores the files of the remote 'fake:' under
+    // STORE, and logs its arguments to LOG. Listings have no MD5s if
+    // STORE/no-md5 exists.
+    const FAKE_RCLONE: &str = r#"#!/bin/sh
+echo "$*" >> "LOG"
+local_path() {
+    case "$1" in
+        fake:*) printf '%s/%s' "STORE" "${1#fake:}" ;;
+        *) printf '%s' "$1" ;;
+    esac
+}
+for last in "$@"; do :; done
+case "$1" in
+    lsjson)
+        target=$(local_path "$last")
+        [ -e "$target" ] || { echo "directory not found" >&2; exit 3; }
+        if [ -f "$target" ]; then
+            dir=$(dirname "$target"); files=$(basename "$target")
+        else
+            dir=$target; files=$(cd "$dir" && find . -type f | sed 's|^\./||' | sort)
+        fi
+        printf '['
+        sep=
+        for file in $files; do
+            size=$(wc -c < "$dir/$file" | tr -d ' ')
+            hashes=
+            if [ ! -e "STORE/no-md5" ]; then
+                md5=$(md5sum "$dir/$file" | cut -d' ' -f1)
+                hashes=$(printf ',"Hashes":{"md5":"%s"}' "$md5")
+            fi
+            printf '%s{"Path":"%s","Size":%s,"IsDir":false%s}' "$sep" "$file" "$size" "$hashes"
+            sep=,
+        done
+        echo ']'
+        ;;
+    copyto)
+        dest=$(local_path "$3")
+        mkdir -p "$(dirname "$dest")"
+        cp "$(local_path "$2")" "$dest"
+        ;;
+    cat) cat "$(local_path "$2")" ;;
+    *) echo "unknown command $1" >&2; exit 1 ;;
+esac
+"#;
+
+    fn md5(contents: &str) -> String {
+        format!("{:x}", md5::compute(contents))
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    fn expected(statuses: &[(&str, &str)]) -> Vec<(String, String)> {
+        statuses
+            .iter()
+            .map(|(name, status)| (format!("data/{}", name), status.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fake_rclone() {
+        setup();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (bin, store) = (temp_dir.path().join("bin"), temp_dir.path().join("store"));
+        let path_context = &temp_dir.path().join("project");
+        for dir in [&bin, &store.join("atlas"), &path_context.join("data")] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let log = temp_dir.path().join("rclone.log");
+        let script = FAKE_RCLONE
+            .replace("LOG", &log.to_string_lossy())
+            .replace("STORE", &store.to_string_lossy());
+        fs::write(bin.join("rclone"), script).unwrap();
+        fs::set_permissions(bin.join("rclone"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut data = DataCollection::new();
+        for (name, contents) in [("a.tsv", "same"), ("b.tsv", "mine"), ("c.tsv", "new")] {
+            let path = format!("data/{}", name);
+            fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        fs::write(store.join("atlas/a.tsv"), "same").unwrap();
+        fs::write(store.join("atlas/b.tsv"), "theirs").unwrap();
+        let mut api = RcloneAPI::new("fake:atlas/").unwrap();
+        api.set_binary(bin.join("rclone"));
+        data.register_remote(&"data".to_string(), Remote::RcloneAPI(api.clone()))
+            .unwrap();
+
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            expected(&[
+                ("a.tsv", "current"),
+                ("b.tsv", "different"),
+                ("c.tsv", "not_exists"),
+            ])
+        );
+        let listing = "lsjson --recursive --files-only --hash --hash-type md5 fake:atlas";
+        assert!(fs::read_to_string(&log).unwrap().contains(listing));
+        // the listing's rclone call is timed, for --remote-latency
+        let latency = data.latency();
+        assert_eq!(latency.len(), 1);
+        assert_eq!(latency[0].latency.calls, 1);
+
+        // only the new file is pushed (the different one needs --overwrite)
+        let report = data
+            .push(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        let copy = format!(
+            "copyto {} fake:atlas/c.tsv",
+            path_context.join("data/c.tsv").display()
+        );
+        assert!(fs::read_to_string(&log).unwrap().contains(&copy));
+        assert_eq!(
+            fs::read_to_string(store.join("atlas/c.tsv")).unwrap(),
+            "new"
+        );
+
+        // without MD5s, files are compared by size
+        fs::write(store.join("no-md5"), "").unwrap();
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            expected(&[
+                ("a.tsv", "exists"),
+                ("b.tsv", "different"),
+                ("c.tsv", "exists"),
+            ])
+        );
+
+        // pulled back, and checked against the manifest's MD5
+        fs::remove_file(path_context.join("data/a.tsv")).unwrap();
+        fs::write(store.join("atlas/c.tsv"), "old").unwrap();
+        fs::remove_file(path_context.join("data/c.tsv")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        let copy = format!(
+            "copyto fake:atlas/a.tsv {}.partial",
+            path_context.join("data/a.tsv").display()
+        );
+        assert!(fs::read_to_string(&log).unwrap().contains(&copy));
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(report.count(Outcome::Failed), 1);
+        assert_eq!(
+            fs::read_to_string(path_context.join("data/a.tsv")).unwrap(),
+            "same"
+        );
+        assert!(!path_context.join("data/c.tsv").exists());
+        assert!(!path_context.join("data/c.tsv.partial").exists());
+
+        // streamed, as by 'sdf cat'
+        let mut streamed = Vec::new();
+        api.stream_to("a.tsv", &md5("same"), &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"same");
+        let err = api
+            .stream_to("c.tsv", &md5("new"), &mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("MD5 mismatch"));
+
+        api.set_binary(bin.join("missing"));
+        let err = api.get_remote_files().await.unwrap_err();
+        assert!(err.to_string().contains("sdf config --rclone"));
+    }
+}
@@ -0,0 +1,615 @@
+// Directories on servers reached over SSH (e.g. a lab's file server), as
+// 'user@host:/path': files are stored under the path, and sdf runs the
+// OpenSSH tools for each operation, so they authenticate as 'ssh' does
+// (with SSH keys or an agent, and settings like ports or jump hosts taken
+// from ~/.ssh/config). Nothing prompts for a password (BatchMode).
+//
+// Files are listed by running 'find' over ssh (with GNU find's -printf),
+// uploaded with sftp (making directories as needed), downloaded with scp
+// (which uses SFTP since OpenSSH 9.0), and streamed with 'cat' over ssh.
+// Listings have only sizes, unless the remote was linked with --md5sum, in
+// which case the server also runs 'md5sum' on each file, so that files can
+// be compared by MD5 (see RemoteFile::get_md5()).
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+use crate::lib::download::CommandDownload;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::remote::RemoteFile;
+use crate::lib::usage::{Operation, UsageCounter};
+use crate::lib::{data::DataFile, project::LocalMetadata};
+
+// ssh's exit status when it fails itself (rather than the remote command).
+const SSH_FAILED: i32 = 255;
+
+// Quote text for the remote shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// Quote a path for an sftp batch file.
+fn batch_quote(path: &str) -> Result<String> {
+    if path.contains('\n') {
+        return Err(anyhow!(
+            "Cannot transfer '{}' with sftp: it has a newline.",
+            path
+        ));
+    }
+    Ok(format!(
+        "\"{}\"",
+        path.replace('\\', r"\\").replace('"', "\\\"")
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SftpAPI {
+    // the SSH destination, 'host' or 'user@host' (or a Host of ~/.ssh/config)
+    host: String,
+    // the project's directory on the server (relative to the login's home
+    // directory, unless absolute)
+    path: String,
+    // compute MD5s by running md5sum on the server
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub md5sum: bool,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    // the directory of ssh, sftp and scp (by default, they are found on PATH)
+    #[serde(skip)]
+    bin_dir: Option<PathBuf>,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+}
+
+impl SftpAPI {
+    // The remote for location, '[user@]host:path'.
+    pub fn new(location: &str, md5sum: bool) -> Result<Self> {
+        let (host, path) = location
+            .split_once(':')
+            .filter(|(host, _)| !host.is_empty() && !host.ends_with('@'))
+            .ok_or(anyhow!(
+                "Linking to SFTP requires --name [<user>@]<host>:<path>."
+            ))?;
+        let path = match path.trim_end_matches('/') {
+            "" if path.starts_with('/') => "/",
+            "" => ".",
+            trimmed => trimmed,
+        };
+        Ok(SftpAPI {
+            host: host.to_string(),
+            path: path.to_string(),
+            md5sum,
+            path_style: PathStyle::default(),
+            bin_dir: None,
+            usage: UsageCounter::default(),
+        })
+    }
+
+    pub fn set_bin_dir(&mut self, bin_dir: PathBuf) {
+        self.bin_dir = Some(bin_dir);
+    }
+
+    // SSH authenticates with the user's own keys, so there is no token.
+    pub fn has_token(&self) -> bool {
+        true
+    }
+
+    fn program(&self, name: &str) -> PathBuf {
+        match &self.bin_dir {
+            Some(bin_dir) => bin_dir.join(name),
+            None => name.into(),
+        }
+    }
+
+    // The path of a remote file on the server.
+    fn target(&self, name: &str) -> String {
+        match self.path.as_str() {
+            "." => name.to_string(),
+            "/" => format!("/{}", name),
+            path => format!("{}/{}", path, name),
+        }
+    }
+
+    // The scp-style location of a remote file, 'host:path', which stands
+    // in for its URL (as merged files only keep the file name).
+    fn location(&self, name: &str) -> String {
+        format!("{}:{}", self.host, self.target(name))
+    }
+
+    // Run a shell command on the server.
+    fn ssh(&self, script: &str) -> Command {
+        let mut command = Command::new(self.program("ssh"));
+        command
+            .args(["-o", "BatchMode=yes", "--", &self.host, script])
+            .stdin(Stdio::null());
+        command
+    }
+
+    fn spawn_error(&self, program: &str, err: std::io::Error) -> anyhow::Error {
+        anyhow!(
+            "Could not run {} ('{}'): {}. SFTP remotes need OpenSSH's ssh, sftp and scp.",
+            program,
+            self.program(program).display(),
+            err
+        )
+    }
+
+    fn failed(&self, action: &str, output: &Output) -> anyhow::Error {
+        anyhow!(
+            "Could not {} on '{}' ({}): {}",
+            action,
+            self.host,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+
+    // Run a shell command on the server to completion, recording how long
+    // it took (as the latency of a request).
+    async fn ssh_output(&self, script: &str) -> Result<Output> {
+        let started = Instant::now();
+        let output = self
+            .ssh(script)
+            .output()
+            .await
+            .map_err(|err| self.spawn_error("ssh", err))?;
+        self.usage.record_latency(started.elapsed());
+        Ok(output)
+    }
+
+    // Run a shell command on the server, returning its output if it
+    // succeeds.
+    async fn run(&self, script: &str, action: &str) -> Result<Output> {
+        let output = self.ssh_output(script).await?;
+        if !output.status.success() {
+            return Err(self.failed(action, &output));
+        }
+        Ok(output)
+    }
+
+    // List the files under the project's directory (none if it does not
+    // exist), with their MD5s if md5sum is set.
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        let mut script = format!(
+            "cd -- {} 2>/dev/null || exit 0; find . -type f -printf '%s\\t%P\\n'",
+            shell_quote(&self.path)
+        );
+        if self.md5sum {
+            script.push_str("; echo; find . -type f -exec md5sum -- {} +");
+        }
+        let output = self.run(&script, "list files").await?;
+        self.usage
+            .record(Operation::List, 0, output.stdout.len() as u64);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (sizes, md5s) = stdout.split_once("\n\n").unwrap_or((&stdout, ""));
+
+        // (md5sum escapes unusual names, with a leading '\', so these have
+        // no MD5)
+        let md5s: HashMap<&str, &str> = md5s
+            .lines()
+            .filter(|line| !line.starts_with('\\'))
+            .filter_map(|line| line.split_once("  ./"))
+            .map(|(md5, name)| (name, md5))
+            .collect();
+        let mut files = Vec::new();
+        for line in sizes.lines().filter(|line| !line.is_empty()) {
+            let (size, name) = line.split_once('\t').ok_or(anyhow!(
+                "Unexpected line in the listing of '{}': {}",
+                self.host,
+                line
+            ))?;
+            files.push(RemoteFile {
+                name: name.to_string(),
+                md5: md5s.get(name).map(|md5| md5.to_lowercase()),
+                size: size.parse().ok(),
+                remote_service: "SFTP".to_string(),
+                url: Some(self.location(name)),
+            });
+        }
+        Ok(files)
+    }
+
+    // Check that the server can be reached, and, unless only linking, that
+    // no files are stored under the path yet, then make the directory.
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        let files = self.list().await?;
+        if link_only {
+            return Ok(());
+        }
+        if !files.is_empty() {
+            return Err(anyhow!(
+                "'{}:{}' already has files. Use --link-only to link.",
+                self.host,
+                self.path
+            ));
+        }
+        let script = format!("mkdir -p -- {}", shell_quote(&self.path));
+        self.run(&script, "make the project's directory").await?;
+        Ok(())
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        self.list().await
+    }
+
+    // Whether a remote file exists.
+    async fn exists(&self, name: &str) -> Result<bool> {
+        let script = format!("test -e {}", shell_quote(&self.target(name)));
+        let output = self.ssh_output(&script).await?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(SSH_FAILED) | None => Err(self.failed("check for a file", &output)),
+            Some(_) => Ok(false),
+        }
+    }
+
+    // Upload the file, replacing any existing copy if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let target = self.target(name);
+        if !overwrite && self.exists(name).await? {
+            print_info!(
+                "SftpAPI::upload() found '{}'. Since overwrite=false, \
+                 this file will not be re-uploaded.",
+                target
+            );
+            return Ok(false);
+        }
+
+        // the batch makes the file's directories (ignoring errors, as they
+        // may exist), then uploads it
+        let mut batch = String::new();
+        let mut directory = self.path.clone();
+        let components: Vec<&str> = name.split('/').collect();
+        for component in &components[..components.len() - 1] {
+            directory = format!("{}/{}", directory.trim_end_matches('/'), component);
+            batch.push_str(&format!("-mkdir {}\n", batch_quote(&directory)?));
+        }
+        let source = path_context.join(&data_file.path);
+        batch.push_str(&format!(
+            "put {} {}\n",
+            batch_quote(&source.to_string_lossy())?,
+            batch_quote(&target)?
+        ));
+
+        let started = Instant::now();
+        let mut child = Command::new(self.program("sftp"))
+            .args(["-q", "-o", "BatchMode=yes", "-b", "-", "--", &self.host])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.spawn_error("sftp", err))?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(batch.as_bytes()).await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+        self.usage.record_latency(started.elapsed());
+        if !output.status.success() {
+            self.usage.record(Operation::Put, 0, 0);
+            return Err(self.failed(&format!("upload '{}'", name), &output));
+        }
+        self.usage.record(Operation::Put, data_file.size, 0);
+        progress.inc(data_file.size);
+        Ok(true)
+    }
+
+    // The download of a remote file, by its location, to filename.
+    pub fn download(&self, location: &str, filename: &str) -> CommandDownload {
+        CommandDownload {
+            program: self.program("scp"),
+            args: vec!["-q".to_string(), "-B".to_string(), location.to_string()],
+            filename: filename.to_string(),
+        }
+    }
+
+    // Stream a remote file, by its location, to writer, verifying it
+    // against expected_md5.
+    pub async fn stream_to<W: Write>(
+        &self,
+        location: &str,
+        expected_md5: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let host = format!("{}:", self.host);
+        let path = location.strip_prefix(&host).unwrap_or(location);
+        let script = format!("cat -- {}", shell_quote(path));
+        let mut child = self
+            .ssh(&script)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.spawn_error("ssh", err))?;
+        let mut stdout = child.stdout.take().unwrap();
+        let mut md5 = md5::Context::new();
+        let mut written: u64 = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = stdout.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read])?;
+            md5.consume(&buffer[..read]);
+            written += read as u64;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(self.failed(&format!("read '{}'", path), &output));
+        }
+        writer.flush()?;
+
+        let md5 = format!("{:x}", md5.compute());
+        if md5 != expected_md5 {
+            return Err(anyhow!(
+                "MD5 mismatch: the manifest has {}, but the streamed data has {}.",
+                expected_md5,
+                md5
+            ));
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Fake OpenSSH tools, for a "server" that is the local machine: ssh
+    // runs its command here, sftp runs the batch's mkdir and put commands,
+    // and scp copies the file. Each logs its arguments to LOG.
+    const FAKE_SSH: &str = r#"#!/bin/sh
+echo "ssh $*" >> "LOG"
+for last in "$@"; do :; done
+exec sh -c "$last"
+"#;
+    const FAKE_SFTP: &str = r#"#!/bin/sh
+echo "sftp $*" >> "LOG"
+while read -r line; do
+    echo "$line" >> "LOG"
+    eval "set -- $line"
+    case "$1" in
+        -mkdir) mkdir "$2" 2>/dev/null ;;
+        put) cp "$2" "$3" || exit 1 ;;
+        *) echo "unknown command $1" >&2; exit 1 ;;
+    esac
+done
+"#;
+    const FAKE_SCP: &str = r#"#!/bin/sh
+echo "scp $*" >> "LOG"
+cp "${3#*:}" "$4"
+"#;
+
+    fn md5(contents: &str) -> String {
+        format!("{:x}", md5::compute(contents))
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    fn expected(statuses: &[(&str, &str)]) -> Vec<(String, String)> {
+        statuses
+            .iter()
+            .map(|(name, status)| (format!("data/{}", name), status.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_parses_location() {
+        let api = SftpAPI::new("me@lab.example.org:/srv/data/atlas/", false).unwrap();
+        assert_eq!(
+            (api.host.as_str(), api.path.as_str()),
+            ("me@lab.example.org", "/srv/data/atlas")
+        );
+        assert_eq!(api.target("raw/a.tsv"), "/srv/data/atlas/raw/a.tsv");
+        let api = SftpAPI::new("lab:", true).unwrap();
+        assert_eq!(
+            (api.path.as_str(), api.target("a.tsv")),
+            (".", "a.tsv".to_string())
+        );
+        assert_eq!(
+            SftpAPI::new("lab:/", false).unwrap().target("a.tsv"),
+            "/a.tsv"
+        );
+        for location in ["lab", ":/srv/data", "me@:/srv/data"] {
+            let err = SftpAPI::new(location, false).unwrap_err().to_string();
+            assert!(
+                err.contains("--name [<user>@]<host>:<path>"),
+                "{}",
+                location
+            );
+        }
+
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(batch_quote(r#"a "b"\c"#).unwrap(), r#""a \"b\"\\c""#);
+        assert!(batch_quote("a\nb").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fake_server() {
+        setup();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let (bin, server) = (temp_dir.path().join("bin"), temp_dir.path().join("server"));
+        let path_context = &temp_dir.path().join("project");
+        for dir in [&bin, &server, &path_context.join("data/raw")] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let log = temp_dir.path().join("ssh.log");
+        for (name, script) in [("ssh", FAKE_SSH), ("sftp", FAKE_SFTP), ("scp", FAKE_SCP)] {
+            let script = script.replace("LOG", &log.to_string_lossy());
+            fs::write(bin.join(name), script).unwrap();
+            fs::set_permissions(bin.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut data = DataCollection::new();
+        for (name, contents) in [
+            ("a.tsv", "same"),
+            ("b.tsv", "mine"),
+            ("c.tsv", "new"),
+            ("raw/d.tsv", "nested"),
+        ] {
+            let path = format!("data/{}", name);
+            fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        let root = server.join("atlas");
+        let location = format!("me@lab:{}/", root.display());
+        let mut api = SftpAPI::new(&location, false).unwrap();
+        api.set_bin_dir(bin.clone());
+        api.path_style = PathStyle::Hierarchical;
+
+        // linking makes the directory, unless it already has files
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        assert!(root.is_dir());
+        fs::write(root.join("a.tsv"), "same").unwrap();
+        fs::write(root.join("b.tsv"), "different").unwrap();
+        let err = api
+            .remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already has files"), "{}", err);
+        data.register_remote(&"data".to_string(), Remote::SftpAPI(api.clone()))
+            .unwrap();
+
+        // without --md5sum, files are compared by size
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            expected(&[
+                ("a.tsv", "exists"),
+                ("b.tsv", "different"),
+                ("c.tsv", "not_exists"),
+                ("raw/d.tsv", "not_exists"),
+            ])
+        );
+        // each ssh call so far (the two listings and mkdir of linking, and
+        // the status's listing) is timed, for --remote-latency
+        let latency = data.latency();
+        assert_eq!(latency.len(), 1);
+        assert_eq!(latency[0].remote, "SFTP");
+        assert_eq!(latency[0].latency.calls, 4);
+
+        // only the new files are pushed, making their directories
+        let report = data
+            .push(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 2);
+        let logged = fs::read_to_string(&log).unwrap();
+        assert!(logged.contains(&format!("-mkdir \"{}/raw\"", root.display())));
+        assert_eq!(
+            fs::read_to_string(root.join("raw/d.tsv")).unwrap(),
+            "nested"
+        );
+        assert_eq!(fs::read_to_string(root.join("c.tsv")).unwrap(), "new");
+
+        // with --md5sum, by MD5 (b.tsv now has the local size)
+        let mut api = SftpAPI::new(&location, true).unwrap();
+        api.set_bin_dir(bin.clone());
+        api.path_style = PathStyle::Hierarchical;
+        fs::write(root.join("b.tsv"), "main").unwrap();
+        data.remotes
+            .insert("data".to_string(), Remote::SftpAPI(api.clone()));
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            expected(&[
+                ("a.tsv", "current"),
+                ("b.tsv", "different"),
+                ("c.tsv", "current"),
+                ("raw/d.tsv", "current"),
+            ])
+        );
+
+        // pulled back with scp, and checked against the manifest's MD5
+        fs::remove_file(path_context.join("data/raw/d.tsv")).unwrap();
+        fs::remove_file(path_context.join("data/b.tsv")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(report.count(Outcome::Failed), 1);
+        assert!(!path_context.join("data/b.tsv").exists());
+        let copy = format!(
+            "scp -q -B me@lab:{}/raw/d.tsv {}.partial",
+            root.display(),
+            path_context.join("data/raw/d.tsv").display()
+        );
+        assert!(fs::read_to_string(&log).unwrap().contains(&copy));
+        assert_eq!(
+            fs::read_to_string(path_context.join("data/raw/d.tsv")).unwrap(),
+            "nested"
+        );
+
+        // streamed, as by 'sdf cat'
+        let mut streamed = Vec::new();
+        api.stream_to(&api.location("raw/d.tsv"), &md5("nested"), &mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"nested");
+        let err = api
+            .stream_to(&api.location("b.tsv"), &md5("mine"), &mut Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("MD5 mismatch"));
+
+        api.set_bin_dir(bin.join("missing"));
+        let err = api.get_remote_files().await.unwrap_err().to_string();
+        assert!(err.starts_with("Could not run ssh"), "{}", err);
+    }
+}
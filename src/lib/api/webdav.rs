@@ -0,0 +1,738 @@
+// WebDAV API, e.g. for Nextcloud, ownCloud, or institutional stores
+//
+// A remote is a collection (a WebDAV directory) by its URL, e.g.
+// https://cloud.example.org/remote.php/dav/files/me/atlas/, which is made
+// when linking if it does not exist (its parent must). Files are listed
+// with PROPFIND requests (one per collection, as many servers refuse
+// 'Depth: infinity'), uploaded with PUT, and downloaded with GET. With
+// --path-style hierarchical, collections are made (with MKCOL) as needed.
+//
+// Plain WebDAV has no checksums, so files are compared by size. ownCloud
+// and Nextcloud keep the checksum given with an upload (its OC-Checksum
+// header) and list it, so the files sdf uploads there have MD5s.
+//
+// The credentials are 'user:password' (e.g. a Nextcloud app password),
+// sent with basic authentication, or else a token, sent as a Bearer token.
+// Downloads take them in a header too.
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use colored::Colorize;
+#[allow(unused_imports)]
+use log::{debug, info, trace};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Client, Method, Response, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+use url::Url;
+
+use crate::lib::data::DataFile;
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::LocalMetadata;
+use crate::lib::remote::{retry_policy, send_with_retry, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
+#[allow(unused_imports)]
+use crate::{print_info, print_warn};
+
+// The properties asked for in listings (oc:checksums is ownCloud's and
+// Nextcloud's).
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+  <d:prop><d:resourcetype/><d:getcontentlength/><oc:checksums/></d:prop>
+</d:propfind>"#;
+
+// The contents of each element named tag (ignoring its namespace prefix,
+// which servers choose, e.g. <d:href> or <D:href>) in xml; empty for
+// empty elements, e.g. <d:collection/>. Elements of the same name must not
+// nest, which PROPFIND responses' do not.
+fn dav_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('>') else {
+            break;
+        };
+        let opening = &after[..end];
+        rest = &after[end + 1..];
+        let name = opening
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        if name.rsplit(':').next() != Some(tag) {
+            continue;
+        }
+        if opening.ends_with('/') {
+            elements.push("");
+            continue;
+        }
+        let close = format!("</{}>", name);
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        elements.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    elements
+}
+
+fn dav_text(xml: &str, tag: &str) -> Option<String> {
+    let text = dav_elements(xml, tag).into_iter().next()?;
+    Some(
+        text.trim()
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&"),
+    )
+}
+
+// Decode the %XX escapes of a URL path.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            let hex = std::str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16).ok()
+        });
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// A file or collection in a PROPFIND response.
+#[derive(Debug, Clone, PartialEq)]
+struct DavEntry {
+    url: Url,
+    is_collection: bool,
+    size: Option<u64>,
+    md5: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct WebdavAPI {
+    // the URL of the project's collection (ending in '/')
+    url: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    credentials: String,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
+}
+
+impl WebdavAPI {
+    pub fn new(url: &str) -> Result<Self> {
+        let mut parsed = Url::parse(url)
+            .ok()
+            .filter(|url| ["http", "https"].contains(&url.scheme()))
+            .ok_or(anyhow!(
+                "Linking to WebDAV requires --name <URL>, the http(s) URL of a \
+                 collection, not '{}'.",
+                url
+            ))?;
+        if !parsed.path().ends_with('/') {
+            parsed.set_path(&format!("{}/", parsed.path()));
+        }
+        Ok(WebdavAPI {
+            url: parsed.to_string(),
+            credentials: String::new(),
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        })
+    }
+
+    pub fn set_token(&mut self, credentials: String) {
+        self.credentials = credentials;
+    }
+
+    pub fn has_token(&self) -> bool {
+        !self.credentials.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    // The URL of a remote file (or with a trailing '/', a collection).
+    fn file_url(&self, name: &str) -> Result<Url> {
+        let mut url = Url::parse(&self.url)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid WebDAV URL '{}'.", self.url))?
+            .pop_if_empty()
+            .extend(name.split('/'));
+        Ok(url)
+    }
+
+    // The headers of requests, and downloads.
+    pub fn headers(&self) -> Result<HeaderMap> {
+        let authorization = match self.credentials.split_once(':') {
+            Some(_) => format!("Basic {}", STANDARD.encode(&self.credentials)),
+            None => format!("Bearer {}", self.credentials),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+        Ok(headers)
+    }
+
+    // Send a request, returning its response whatever its status.
+    async fn send<T: serde::Serialize>(
+        &self,
+        method: Method,
+        url: &Url,
+        extra_headers: &[(HeaderName, String)],
+        data: Option<RequestData<T>>,
+    ) -> Result<Response> {
+        trace!("request URL: {:?}", url);
+        let operation = match method.as_str() {
+            "PROPFIND" => Operation::List,
+            _ => Operation::of_api_request(&method),
+        };
+        let bytes_sent = request_body_size(&data).await?;
+        let client = Client::new();
+        let mut headers = self.headers()?;
+        for (name, value) in extra_headers {
+            headers.insert(name.clone(), HeaderValue::from_str(value)?);
+        }
+
+        // each attempt is sent (and accounted) as its own request
+        let (method, headers, data) = (&method, &headers, &data);
+        send_with_retry(&retry_policy(), method, || async {
+            let mut request = client
+                .request(method.clone(), url.clone())
+                .headers(headers.clone());
+            if let Some(data) = data {
+                request = data.attach(request).await?;
+            }
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await
+    }
+
+    async fn issue_request<T: serde::Serialize>(
+        &self,
+        method: Method,
+        url: &Url,
+        extra_headers: &[(HeaderName, String)],
+        data: Option<RequestData<T>>,
+    ) -> Result<Response> {
+        let response = self.send(method, url, extra_headers, data).await?;
+        let response_status = response.status();
+        if response_status.is_success() {
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "HTTP Error: {}\nurl: {:?}\n{:?}",
+                response_status,
+                url.as_str(),
+                response.text().await?
+            ))
+        }
+    }
+
+    // The entries of a PROPFIND of url, to depth ("0" for it alone, "1"
+    // for it and its members), or None if it does not exist.
+    async fn propfind(&self, url: &Url, depth: &str) -> Result<Option<Vec<DavEntry>>> {
+        let headers = [
+            (HeaderName::from_static("depth"), depth.to_string()),
+            (CONTENT_TYPE, "application/xml".to_string()),
+        ];
+        let method = Method::from_bytes(b"PROPFIND")?;
+        let data = Some(RequestData::<HashMap<String, String>>::Binary(
+            PROPFIND_BODY.as_bytes().to_vec(),
+        ));
+        let response = self.send(method, url, &headers, data).await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(anyhow!(
+                "HTTP Error: {}\nurl: {:?}\n{:?}",
+                status,
+                url.as_str(),
+                response.text().await?
+            ));
+        }
+        let text = response.text().await?;
+        let mut entries = Vec::new();
+        for element in dav_elements(&text, "response") {
+            let href = dav_text(element, "href")
+                .ok_or(anyhow!("WebDAV response without an href: {}", element))?;
+            // (properties a server lacks are in a propstat with a 404
+            // status, as empty elements)
+            let md5 = dav_text(element, "checksum").and_then(|checksums| {
+                checksums
+                    .split_whitespace()
+                    .find_map(|checksum| checksum.strip_prefix("MD5:"))
+                    .map(|md5| md5.to_lowercase())
+            });
+            entries.push(DavEntry {
+                url: url.join(&href)?,
+                is_collection: dav_elements(element, "resourcetype")
+                    .iter()
+                    .any(|types| !dav_elements(types, "collection").is_empty()),
+                size: dav_text(element, "getcontentlength").and_then(|size| size.parse().ok()),
+                md5,
+            });
+        }
+        Ok(Some(entries))
+    }
+
+    // The files under the project's collection, by their names (their
+    // paths under it).
+    async fn list(&self) -> Result<Vec<(String, DavEntry)>> {
+        let root = Url::parse(&self.url)?;
+        let root_path = percent_decode(root.path());
+        let mut files = Vec::new();
+        let mut collections = vec![root];
+        while let Some(collection) = collections.pop() {
+            let entries = self
+                .propfind(&collection, "1")
+                .await?
+                .ok_or(anyhow!("WebDAV collection '{}' not found.", collection))?;
+            let collection_path = percent_decode(collection.path());
+            for entry in entries {
+                let path = percent_decode(entry.url.path());
+                // the collection itself
+                if path.trim_end_matches('/') == collection_path.trim_end_matches('/') {
+                    continue;
+                }
+                if entry.is_collection {
+                    collections.push(entry.url);
+                    continue;
+                }
+                let Some(name) = path.strip_prefix(&root_path) else {
+                    continue;
+                };
+                files.push((name.to_string(), entry));
+            }
+        }
+        Ok(files)
+    }
+
+    // WebDAV Remote initialization
+    //
+    // This makes the project's collection if it does not exist (unless
+    // only linking), and otherwise checks it has no files yet (unless only
+    // linking).
+    pub async fn remote_init(
+        &mut self,
+        _local_metadata: LocalMetadata,
+        link_only: bool,
+    ) -> Result<()> {
+        let url = Url::parse(&self.url)?;
+        match self.propfind(&url, "0").await? {
+            Some(_) if link_only => Ok(()),
+            Some(_) if !self.list().await?.is_empty() => Err(anyhow!(
+                "WebDAV collection '{}' already has files. Use --link-only to link.",
+                self.url
+            )),
+            Some(_) => Ok(()),
+            None if link_only => Err(anyhow!("WebDAV collection '{}' not found.", self.url)),
+            None => {
+                let method = Method::from_bytes(b"MKCOL")?;
+                self.issue_request::<HashMap<String, String>>(method, &url, &[], None)
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let remote_files = self
+            .list()
+            .await?
+            .into_iter()
+            .map(|(name, entry)| RemoteFile {
+                name,
+                md5: entry.md5,
+                size: entry.size,
+                remote_service: "WebDAV".to_string(),
+                url: Some(entry.url.to_string()),
+            })
+            .collect();
+        Ok(remote_files)
+    }
+
+    // Make the collections of directory (e.g. 'raw/fastq') under the
+    // project's, as needed.
+    async fn make_collections(&self, directory: &str) -> Result<()> {
+        let mut path = String::new();
+        for name in directory.split('/').filter(|name| !name.is_empty()) {
+            path = format!("{}{}/", path, name);
+            let url = self.file_url(&path)?;
+            let method = Method::from_bytes(b"MKCOL")?;
+            let response = self
+                .send::<HashMap<String, String>>(method, &url, &[], None)
+                .await?;
+            // (405 Method Not Allowed is for a collection that exists)
+            let status = response.status();
+            if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(anyhow!(
+                    "HTTP Error: {}\nurl: {:?}\n{:?}",
+                    status,
+                    url.as_str(),
+                    response.text().await?
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Upload the file, replacing any existing file if overwrite is true.
+    //
+    // Returns true/false if upload was completed or not. Will Error in other cases.
+    pub async fn upload(
+        &self,
+        data_file: &DataFile,
+        name: &str,
+        path_context: &Path,
+        overwrite: bool,
+        progress: &FileProgress,
+    ) -> Result<bool> {
+        let url = self.file_url(name)?;
+        if !overwrite && self.propfind(&url, "0").await?.is_some() {
+            print_info!(
+                "WebdavAPI::upload() found file '{}' in '{}'. Since overwrite=false, \
+                 this file will not be re-uploaded.",
+                name,
+                self.url
+            );
+            return Ok(false);
+        }
+        if let Some((directory, _)) = name.rsplit_once('/') {
+            self.make_collections(directory).await?;
+        }
+        let file = tokio::fs::File::open(data_file.full_path(path_context)?).await?;
+        let data = Some(RequestData::<HashMap<String, String>>::Stream(
+            file,
+            progress.clone(),
+        ));
+        let checksum = format!("MD5:{}", data_file.recorded_md5());
+        let headers = [(HeaderName::from_static("oc-checksum"), checksum)];
+        self.issue_request(Method::PUT, &url, &headers, data)
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, StatusOptions};
+    use crate::lib::remote::Remote;
+    use crate::lib::report::Outcome;
+    use crate::lib::utils::status_records;
+    use crate::logging_setup::setup;
+    use httpmock::prelude::*;
+    use tempfile::TempDir;
+
+    // me:secret, for basic authentication
+    const TEST_CREDENTIALS: &str = "me:secret";
+    const TEST_AUTHORIZATION: &str = "Basic bWU6c2VjcmV0";
+
+    fn test_api(server: &MockServer) -> WebdavAPI {
+        let mut api = WebdavAPI::new(&server.url("/dav/atlas")).unwrap();
+        api.set_token(TEST_CREDENTIALS.to_string());
+        api
+    }
+
+    // (httpmock's methods do not include WebDAV's)
+    fn is_mkcol(request: &HttpMockRequest) -> bool {
+        request.method == "MKCOL"
+    }
+
+    fn md5(contents: &str) -> String {
+        format!("{:x}", md5::compute(contents))
+    }
+
+    // A PROPFIND response element, for a collection if contents is None,
+    // and with an MD5 if checksum is set (as ownCloud's).
+    fn response(href: &str, contents: Option<&str>, checksum: bool) -> String {
+        let props = match contents {
+            None => "<d:resourcetype><d:collection/></d:resourcetype>".to_string(),
+            Some(contents) if checksum => format!(
+                "<d:resourcetype/><d:getcontentlength>{}</d:getcontentlength>\
+                 <oc:checksums><oc:checksum>SHA1:abc MD5:{}</oc:checksum></oc:checksums>",
+                contents.len(),
+                md5(contents).to_uppercase()
+            ),
+            Some(contents) => format!(
+                "<d:resourcetype/><d:getcontentlength>{}</d:getcontentlength>",
+                contents.len()
+            ),
+        };
+        format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>{}</d:prop>\
+             <d:status>HTTP/1.1 200 OK</d:status></d:propstat>\
+             <d:propstat><d:prop><oc:checksums/></d:prop>\
+             <d:status>HTTP/1.1 404 Not Found</d:status></d:propstat></d:response>",
+            href, props
+        )
+    }
+
+    fn multistatus(responses: &[String]) -> String {
+        format!(
+            "<?xml version=\"1.0\"?><d:multistatus xmlns:d=\"DAV:\" \
+             xmlns:oc=\"http://owncloud.org/ns\">{}</d:multistatus>",
+            responses.concat()
+        )
+    }
+
+    // The remote status of each file, by path.
+    async fn remote_statuses(
+        data: &mut DataCollection,
+        path_context: &Path,
+    ) -> Vec<(String, String)> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        let mut statuses: Vec<(String, String)> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| {
+                let path = record["path"].as_str().unwrap().to_string();
+                (path, record["remote_status"].as_str().unwrap().to_string())
+            })
+            .collect();
+        statuses.sort();
+        statuses
+    }
+
+    #[test]
+    fn test_urls_headers_and_xml() {
+        let api = WebdavAPI::new("https://cloud.example.org/dav/files/me/atlas").unwrap();
+        assert_eq!(api.url, "https://cloud.example.org/dav/files/me/atlas/");
+        assert_eq!(
+            api.file_url("raw/my reads#1.fq").unwrap().as_str(),
+            "https://cloud.example.org/dav/files/me/atlas/raw/my%20reads%231.fq"
+        );
+        assert_eq!(
+            percent_decode("/atlas/my%20reads%231.fq"),
+            "/atlas/my reads#1.fq"
+        );
+        for url in ["cloud.example.org/atlas", "ftp://example.org/atlas"] {
+            let err = WebdavAPI::new(url).unwrap_err().to_string();
+            assert!(err.contains("--name <URL>"), "{}", err);
+        }
+
+        let mut api = api;
+        api.set_token("token".to_string());
+        assert_eq!(api.headers().unwrap()[AUTHORIZATION], "Bearer token");
+        api.set_token(TEST_CREDENTIALS.to_string());
+        assert_eq!(api.headers().unwrap()[AUTHORIZATION], TEST_AUTHORIZATION);
+
+        // namespace prefixes vary by server
+        let xml = "<D:multistatus xmlns:D=\"DAV:\"><D:response><D:href>/a&amp;b</D:href>\
+                   <D:resourcetype /></D:response></D:multistatus>";
+        assert_eq!(dav_text(xml, "href").as_deref(), Some("/a&b"));
+        assert_eq!(dav_elements(xml, "resourcetype"), vec![""]);
+        assert!(dav_elements(xml, "collection").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remote_init_makes_collection() {
+        setup();
+        let server = MockServer::start();
+        let missing = server.mock(|when, then| {
+            when.path("/dav/atlas/").header("Depth", "0");
+            then.status(404);
+        });
+        let mkcol = server.mock(|when, then| {
+            when.path("/dav/atlas/")
+                .header("Authorization", TEST_AUTHORIZATION)
+                .matches(is_mkcol);
+            then.status(201);
+        });
+        let mut api = test_api(&server);
+        let err = api
+            .remote_init(LocalMetadata::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not found"), "{}", err);
+        api.remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        missing.assert_hits(2);
+        mkcol.assert();
+    }
+
+    #[tokio::test]
+    async fn test_list_push_and_pull() {
+        setup();
+        let server = MockServer::start();
+        let root = server.mock(|when, then| {
+            when.path("/dav/atlas/")
+                .header("Depth", "1")
+                .header("Authorization", TEST_AUTHORIZATION)
+                .body_contains("getcontentlength");
+            then.status(207).body(multistatus(&[
+                response("/dav/atlas/", None, false),
+                response("/dav/atlas/a.tsv", Some("same"), true),
+                response(&server.url("/dav/atlas/raw/"), None, false),
+            ]));
+        });
+        let raw = server.mock(|when, then| {
+            when.path("/dav/atlas/raw/").header("Depth", "1");
+            then.status(207).body(multistatus(&[
+                response("/dav/atlas/raw", None, false),
+                response("/dav/atlas/raw/b.tsv", Some("theirs"), false),
+            ]));
+        });
+        let api = test_api(&server);
+        let mut files = api.get_remote_files().await.unwrap();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        let files: Vec<_> = files
+            .iter()
+            .map(|file| (file.name.as_str(), file.md5.clone(), file.size))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("a.tsv", Some(md5("same")), Some(4)),
+                ("raw/b.tsv", None, Some(6)),
+            ]
+        );
+        root.assert();
+        raw.assert();
+
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let mut data = DataCollection::new();
+        for (name, contents) in [("a.tsv", "same"), ("raw/b.tsv", "mine"), ("c.tsv", "new")] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        let mut api = api;
+        api.path_style = PathStyle::Hierarchical;
+        data.register_remote(&"data".to_string(), Remote::WebdavAPI(api.clone()))
+            .unwrap();
+        assert_eq!(
+            remote_statuses(&mut data, path_context).await,
+            vec![
+                ("data/a.tsv".to_string(), "current".to_string()),
+                ("data/c.tsv".to_string(), "not_exists".to_string()),
+                ("data/raw/b.tsv".to_string(), "different".to_string()),
+            ]
+        );
+
+        // uploaded (with its MD5, for ownCloud), unless it exists
+        let c_missing = server.mock(|when, then| {
+            when.path("/dav/atlas/c.tsv").header("Depth", "0");
+            then.status(404);
+        });
+        let put = server.mock(|when, then| {
+            when.method(PUT)
+                .path("/dav/atlas/c.tsv")
+                .header("Authorization", TEST_AUTHORIZATION)
+                .header("OC-Checksum", format!("MD5:{}", md5("new")))
+                .body("new");
+            then.status(201);
+        });
+        let c_file = data.files.get("data/c.tsv").unwrap().clone();
+        let uploaded = api
+            .upload(
+                &c_file,
+                "c.tsv",
+                path_context,
+                false,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert!(uploaded);
+        c_missing.assert();
+        put.assert();
+
+        let b_exists = server.mock(|when, then| {
+            when.path("/dav/atlas/raw/b.tsv").header("Depth", "0");
+            then.status(207).body(multistatus(&[response(
+                "/dav/atlas/raw/b.tsv",
+                Some("theirs"),
+                false,
+            )]));
+        });
+        let b_file = data.files.get("data/raw/b.tsv").unwrap().clone();
+        let uploaded = api
+            .upload(
+                &b_file,
+                "raw/b.tsv",
+                path_context,
+                false,
+                &FileProgress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert!(!uploaded);
+        b_exists.assert();
+
+        // collections are made for nested names (405 is for one that exists)
+        let mkcol = server.mock(|when, then| {
+            when.path("/dav/atlas/raw/").matches(is_mkcol);
+            then.status(405);
+        });
+        let put_b = server.mock(|when, then| {
+            when.method(PUT).path("/dav/atlas/raw/b.tsv").body("mine");
+            then.status(204);
+        });
+        api.upload(
+            &b_file,
+            "raw/b.tsv",
+            path_context,
+            true,
+            &FileProgress::hidden(),
+        )
+        .await
+        .unwrap();
+        mkcol.assert();
+        put_b.assert();
+
+        // downloads send the credentials in a header
+        let get = server.mock(|when, then| {
+            when.method(GET)
+                .path("/dav/atlas/a.tsv")
+                .header("Authorization", TEST_AUTHORIZATION);
+            then.status(200).body("same");
+        });
+        std::fs::remove_file(path_context.join("data/a.tsv")).unwrap();
+        let report = data
+            .pull(path_context, false, &Default::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/a.tsv")).unwrap(),
+            "same"
+        );
+        get.assert();
+    }
+}
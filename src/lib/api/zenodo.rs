@@ -6,21 +6,29 @@ use reqwest::{
     header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
     Method,
 };
-use reqwest::{Body, Client, Response};
+use reqwest::{Client, Response};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::path::Path;
-use tokio_util::io::ReaderStream;
+use std::time::Instant;
 
 #[allow(unused_imports)]
 use crate::{print_info, print_warn};
 
-use crate::lib::remote::{AuthKeys, RemoteFile, RequestData};
+use crate::lib::checksum::EMPTY_MD5;
+use crate::lib::data::{Creator, DataFile, DepositMetadata};
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
+use crate::lib::project::LocalMetadata;
+use crate::lib::remote::{retry_policy, send_with_retry, AuthKeys, RemoteFile, RequestData};
+use crate::lib::usage::{request_body_size, Operation, UsageCounter};
 use crate::lib::utils::{shorten, ISSUE_URL};
-use crate::lib::{data::DataFile, project::LocalMetadata};
 
 const BASE_URL: &str = "https://zenodo.org/api";
+// Zenodo's test instance, for trying out deposits: its records are not
+// archived, and it needs its own account and token.
+const SANDBOX_URL: &str = "https://sandbox.zenodo.org/api";
 
 // for testing:
 const TEST_TOKEN: &str = "test-token";
@@ -29,6 +37,9 @@ const TEST_TOKEN: &str = "test-token";
 pub struct ZenodoDeposition {
     conceptrecid: String,
     created: String,
+    // minted once published
+    #[serde(default)]
+    doi: String,
     #[serde(skip_deserializing)]
     files: Vec<String>,
     id: u32,
@@ -89,6 +100,45 @@ impl From<ZenodoFile> for RemoteFile {
     }
 }
 
+// A published record, from /records/{id}; its files are public, so it
+// can be read without a token.
+#[derive(Debug, Deserialize)]
+pub struct ZenodoRecord {
+    doi: Option<String>,
+    metadata: ZenodoRecordMetadata,
+    #[serde(default)]
+    files: Vec<ZenodoRecordFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ZenodoRecordMetadata {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ZenodoRecordFile {
+    key: String,
+    size: u64,
+    // e.g. 'md5:...'
+    checksum: String,
+    links: HashMap<String, String>,
+}
+
+impl From<ZenodoRecordFile> for RemoteFile {
+    fn from(file: ZenodoRecordFile) -> Self {
+        RemoteFile {
+            md5: file
+                .checksum
+                .strip_prefix("md5:")
+                .map(|md5| md5.to_string()),
+            size: Some(file.size),
+            remote_service: "Zenodo".to_string(),
+            url: file.links.get("self").cloned(),
+            name: file.key,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct ZenodoLinks {
     download: Option<String>,
@@ -105,9 +155,21 @@ pub struct ZenodoLinks {
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-struct Creator {
+struct ZenodoCreator {
     name: String,
     affiliation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orcid: Option<String>,
+}
+
+impl From<&Creator> for ZenodoCreator {
+    fn from(creator: &Creator) -> Self {
+        ZenodoCreator {
+            name: creator.name.clone(),
+            affiliation: creator.affiliation.clone(),
+            orcid: creator.orcid.clone(),
+        }
+    }
 }
 
 // We need this wrapper to provide the metadata
@@ -124,38 +186,79 @@ struct ZenodoMetadata {
     title: String,
     upload_type: Option<String>,
     description: Option<String>,
-    creators: Option<Vec<Creator>>,
+    creators: Option<Vec<ZenodoCreator>>,
+    // (an ID, e.g. 'cc-by-4.0')
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keywords: Option<Vec<String>>,
+    // the rest (e.g. access_right), kept as is on updates
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ZenodoMetadata {
+    // Set the fields given in the manifest.
+    fn apply(&mut self, deposit: &DepositMetadata) {
+        if let Some(title) = &deposit.title {
+            self.title = title.clone();
+        }
+        if let Some(description) = &deposit.description {
+            self.description = Some(description.clone());
+        }
+        if !deposit.creators.is_empty() {
+            self.creators = Some(deposit.creators.iter().map(ZenodoCreator::from).collect());
+        }
+        if let Some(license) = &deposit.license {
+            self.license = Some(serde_json::Value::String(license.clone()));
+        }
+        if !deposit.keywords.is_empty() {
+            self.keywords = Some(deposit.keywords.clone());
+        }
+    }
 }
 
 impl TryInto<ZenodoDepositionData> for LocalMetadata {
     type Error = anyhow::Error;
 
     fn try_into(self) -> Result<ZenodoDepositionData> {
-        let name = self
-            .author_name
-            .ok_or_else(|| anyhow!("Author name is required"))?;
+        // the creators set in the manifest, else the user
+        let name = match self.author_name {
+            Some(name) => name,
+            None if !self.deposit.creators.is_empty() => String::new(),
+            None => return Err(anyhow!("Author name is required")),
+        };
         // TODO? Warn user of default description?
         let description = self
             .description
             .unwrap_or("Upload by SciDataFlow.".to_string());
 
-        Ok(ZenodoDepositionData {
-            metadata: ZenodoMetadata {
-                prereserve_doi: None,
-                title: self.title.ok_or(anyhow!(
-                    "Zenodo requires a title be set.\n\
+        let mut metadata = ZenodoMetadata {
+            prereserve_doi: None,
+            title: self.title.ok_or(anyhow!(
+                "Zenodo requires a title be set.\n\
                                                 Either: \n\
                                                   - set this manually in data_manifest.yml\n\
                                                   - specify with 'sdf link --name <NAME>'\n"
-                ))?,
-                upload_type: Some("dataset".to_string()),
-                description: Some(description),
-                creators: Some(vec![Creator {
-                    name,
-                    affiliation: self.affiliation,
-                }]),
-            },
-        })
+            ))?,
+            upload_type: Some("dataset".to_string()),
+            description: Some(description),
+            creators: Some(vec![ZenodoCreator {
+                name,
+                affiliation: self.affiliation,
+                orcid: None,
+            }]),
+            license: None,
+            keywords: None,
+            other: Default::default(),
+        };
+        // the rest of the manifest's metadata for the deposit (its title
+        // is the remote's name)
+        metadata.apply(&DepositMetadata {
+            title: None,
+            ..self.deposit
+        });
+        Ok(ZenodoDepositionData { metadata })
     }
 }
 
@@ -165,30 +268,64 @@ struct PrereserveDoi {
     recid: usize,
 }
 
-// Remove the BASE_URL from full URLs, e.g. for
+// Remove the base URL from full URLs, e.g. for
 // bucket_urls provided by Zenodo so they can go through the common
 // issue_request() method
-fn remove_base_url(full_url: &str) -> Result<String> {
+fn remove_base_url(full_url: &str, base_url: &str) -> Result<String> {
     full_url
-        .strip_prefix(BASE_URL)
+        .strip_prefix(base_url)
         .map(|s| s.to_string())
         .ok_or(anyhow!(
-            "Internal error: Zenodo BASE_URL not found in full URL: full_url={:?}, BASE_URL={:?}",
+            "Internal error: Zenodo base URL not found in full URL: full_url={:?}, base URL={:?}",
             full_url,
-            BASE_URL
+            base_url
         ))
 }
 
-// for serde deserialize default
-fn zenodo_api_url() -> String {
-    BASE_URL.to_string()
+// The key of a Zenodo token in ~/.scidataflow_authkeys.yml.
+pub fn token_key(sandbox: bool) -> &'static str {
+    if sandbox {
+        "zenodo-sandbox"
+    } else {
+        "zenodo"
+    }
+}
+
+// The ID of a published record, from its DOI (e.g. 10.5281/zenodo.1234,
+// or as a https://doi.org/ URL), its page (e.g.
+// https://zenodo.org/records/1234), or the ID itself.
+pub fn record_id(reference: &str) -> Result<u64> {
+    let reference = reference
+        .trim()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let id = match reference
+        .rsplit_once("/records/")
+        .or(reference.rsplit_once("/record/"))
+        .or(reference.rsplit_once("zenodo."))
+    {
+        Some((_, id)) => id,
+        None => reference,
+    };
+    id.parse().map_err(|_| {
+        anyhow!(
+            "'{}' is not the DOI (e.g. 10.5281/zenodo.1234), URL or ID of a Zenodo record.",
+            reference
+        )
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ZenodoAPI {
-    #[serde(skip_serializing, skip_deserializing, default = "zenodo_api_url")]
-    base_url: String,
+    // set for mock HTTP servers in tests
+    #[serde(skip_serializing, skip_deserializing)]
+    base_url: Option<String>,
     name: String,
+    // whether the deposition is on the sandbox, rather than the archive
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sandbox: bool,
     #[serde(skip_serializing, skip_deserializing)]
     token: String,
     // Minimal info for other API operations:
@@ -196,10 +333,20 @@ pub struct ZenodoAPI {
     // this is rather lengthy.
     deposition_id: Option<u64>,
     bucket_url: Option<String>,
+    // a published record (e.g. someone else's), linked only for
+    // downloading (see published()), and its DOI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    record_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doi: Option<String>,
+    #[serde(default, skip_serializing_if = "PathStyle::is_flat")]
+    pub path_style: PathStyle,
+    #[serde(skip)]
+    pub usage: UsageCounter,
 }
 
 impl ZenodoAPI {
-    pub fn new(name: &str, base_url: Option<String>) -> Result<Self> {
+    pub fn new(name: &str, base_url: Option<String>, sandbox: bool) -> Result<Self> {
         // Note: this constructor is not called often, except through
         // Project::link(), since serde is usually deserializing the
         // new ZenodoAPI Remote variant from the manifest.
@@ -211,24 +358,92 @@ impl ZenodoAPI {
             // If base_url is set, we're using mock HTTP servers,
             // so we use the test-token
             let mut auth_keys = AuthKeys::default();
-            auth_keys.temporary_add("zenodo", TEST_TOKEN);
+            auth_keys.temporary_add(token_key(sandbox), TEST_TOKEN);
             auth_keys
         };
-        let token = auth_keys.get("zenodo".to_string())?;
-        let base_url = base_url.unwrap_or(BASE_URL.to_string());
+        let token = auth_keys.get(token_key(sandbox).to_string())?;
         Ok(ZenodoAPI {
             base_url,
             name: name.to_string(),
+            sandbox,
             token,
             deposition_id: None,
             bucket_url: None,
+            record_id: None,
+            doi: None,
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
         })
     }
 
+    // A published record, from its DOI, URL or ID (see record_id()). Its
+    // files can only be downloaded, which needs no token.
+    pub fn published(reference: &str, base_url: Option<String>, sandbox: bool) -> Result<Self> {
+        Ok(ZenodoAPI {
+            base_url,
+            name: reference.to_string(),
+            sandbox,
+            token: String::new(),
+            deposition_id: None,
+            bucket_url: None,
+            record_id: Some(record_id(reference)?),
+            doi: None,
+            path_style: PathStyle::default(),
+            usage: UsageCounter::default(),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.record_id.is_some()
+    }
+
     pub fn set_token(&mut self, token: String) {
         self.token = token;
     }
 
+    pub fn has_token(&self) -> bool {
+        self.is_read_only() || !self.token.is_empty()
+    }
+
+    pub fn web_url(&self) -> Option<String> {
+        let host = self.api_url().trim_end_matches("/api");
+        if let Some(doi) = &self.doi {
+            return Some(format!("https://doi.org/{}", doi));
+        }
+        match self.record_id {
+            Some(id) => Some(format!("{}/records/{}", host, id)),
+            None => self
+                .deposition_id
+                .map(|id| format!("{}/deposit/{}", host, id)),
+        }
+    }
+
+    // The API of the archive or the sandbox.
+    fn api_url(&self) -> &'static str {
+        if self.sandbox {
+            SANDBOX_URL
+        } else {
+            BASE_URL
+        }
+    }
+
+    fn request_url(&self, endpoint: &str) -> String {
+        let url = format!(
+            "{}/{}",
+            self.base_url
+                .as_deref()
+                .unwrap_or(self.api_url())
+                .trim_end_matches('/'),
+            endpoint.trim_start_matches('/')
+        );
+        // published records are read without a token
+        if self.token.is_empty() {
+            url
+        } else {
+            format!("{}?access_token={}", url, self.token)
+        }
+    }
+
     // issue request
     // TODO: this is the same as FigShareAPI's issue_request().
     // Since APIs can have different authentication routines, we
@@ -240,37 +455,32 @@ impl ZenodoAPI {
         headers: Option<HeaderMap>,
         data: Option<RequestData<T>>,
     ) -> Result<Response> {
-        let url = format!(
-            "{}/{}?access_token={}",
-            self.base_url.trim_end_matches('/'),
-            endpoint.trim_start_matches('/'),
-            self.token
-        );
+        let url = self.request_url(endpoint);
         trace!("request URL: {:?}", &url);
 
+        let operation = Operation::of_api_request(&method);
+        let bytes_sent = request_body_size(&data).await?;
         let client = Client::new();
-        let mut request = client.request(method, &url);
-        if let Some(h) = headers {
-            request = request.headers(h);
-        }
-
-        let request = match data {
-            Some(RequestData::Json(json_data)) => request.json(&json_data),
-            Some(RequestData::Binary(bin_data)) => request.body(bin_data),
-            Some(RequestData::File(file)) => request.body(file),
-            Some(RequestData::Stream(file)) => {
-                let stream = ReaderStream::new(file);
-                let body = Body::wrap_stream(stream);
-                request.body(body)
+
+        // each attempt is sent (and accounted) as its own request
+        let (method, url, headers, data) = (&method, &url, &headers, &data);
+        let response = send_with_retry(&retry_policy(), method, || async {
+            let mut request = client.request(method.clone(), url);
+            if let Some(h) = headers {
+                request = request.headers(h.clone());
             }
-            Some(RequestData::Empty) => {
-                request.json(&serde_json::Value::Object(serde_json::Map::new()))
+            if let Some(data) = data {
+                request = data.attach(request).await?;
             }
-            None => request,
-        };
-
-        trace!("request (before send): {:?}", request);
-        let response = request.send().await?;
+            trace!("request (before send): {:?}", request);
+            let started = Instant::now();
+            let response = request.send().await?;
+            self.usage.record_latency(started.elapsed());
+            let bytes_received = response.content_length().unwrap_or(0);
+            self.usage.record(operation, bytes_sent, bytes_received);
+            Ok(response)
+        })
+        .await?;
 
         let response_status = response.status();
         if response_status.is_success() {
@@ -369,6 +579,14 @@ impl ZenodoAPI {
         local_metadata: LocalMetadata,
         link_only: bool,
     ) -> Result<()> {
+        // A published record only needs to exist
+        if self.is_read_only() {
+            let record = self.get_record().await?;
+            self.name = record.metadata.title;
+            self.doi = record.doi;
+            return Ok(());
+        }
+
         // Step 1: Check if a deposition already exists
         let found_match = self.find_deposition().await?;
 
@@ -396,6 +614,122 @@ impl ZenodoAPI {
         Ok(())
     }
 
+    // Get the linked deposition, e.g. to see whether it was published.
+    pub async fn get_deposition(&self) -> Result<ZenodoDeposition> {
+        let url = format!("deposit/depositions/{}", self.get_deposition_id()?);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None, None)
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    // Get the deposition at one of the (full) links Zenodo gives.
+    async fn get_linked_deposition(&self, link: &str) -> Result<ZenodoDeposition> {
+        let url = remove_base_url(link, self.api_url())?;
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None, None)
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    // The files of a published deposition are locked, so pushing to one
+    // goes to a new version of it (its draft, which starts with copies of
+    // the published files). Only new files are then uploaded, and changed
+    // ones replace their copies (with --overwrite). The new version's
+    // deposition ID is kept, so later pushes go to the draft until it too
+    // is published.
+    pub async fn prepare_push(&mut self) -> Result<()> {
+        if self.is_read_only() {
+            return Ok(());
+        }
+        let deposition = self.get_deposition().await?;
+        if !deposition.submitted {
+            return Ok(());
+        }
+        // a new version may already have been started, e.g. on the website
+        let links = &deposition.links;
+        let draft_link = match &links.latest_draft {
+            Some(draft) if links.self_link.as_ref() != Some(draft) => draft.clone(),
+            _ => {
+                let url = format!("deposit/depositions/{}/actions/newversion", deposition.id);
+                let response = self
+                    .issue_request::<HashMap<String, String>>(Method::POST, &url, None, None)
+                    .await?;
+                let published: ZenodoDeposition = response.json().await?;
+                published.links.latest_draft.ok_or(anyhow!(
+                    "Zenodo did not link the new version of deposition {}.",
+                    deposition.id
+                ))?
+            }
+        };
+        let draft = self.get_linked_deposition(&draft_link).await?;
+        let bucket_url = draft.links.bucket.ok_or(anyhow!(
+            "Internal Error: the new version of Zenodo deposition {} has no bucket_url.",
+            deposition.id
+        ))?;
+        print_info!(
+            "Zenodo deposition {} is published, so files are pushed to a new version of it \
+             (deposition {}).",
+            deposition.id,
+            draft.id
+        );
+        self.deposition_id = Some(draft.id as u64);
+        self.bucket_url = Some(bucket_url);
+        // the new version gets its own DOI once published
+        self.doi = None;
+        Ok(())
+    }
+
+    // Update the deposition's metadata to the manifest's (see
+    // DepositMetadata), if it differs. Published metadata is left alone:
+    // it is updated in the next version (see prepare_push()).
+    pub async fn update_metadata(&mut self, deposit: &DepositMetadata) -> Result<bool> {
+        let deposition = self.get_deposition().await?;
+        let mut metadata = deposition.metadata.clone();
+        metadata.apply(deposit);
+        if metadata == deposition.metadata {
+            return Ok(false);
+        }
+        if deposition.submitted {
+            print_info!(
+                "Zenodo deposition {} is published, so its metadata is updated \
+                 in its next version.",
+                deposition.id
+            );
+            return Ok(false);
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        metadata.prereserve_doi = None;
+        let url = format!("deposit/depositions/{}", deposition.id);
+        let data = Some(RequestData::Json(ZenodoDepositionData { metadata }));
+        self.issue_request(Method::PUT, &url, Some(headers), data)
+            .await?;
+        if let Some(title) = &deposit.title {
+            self.name = title.clone();
+        }
+        Ok(true)
+    }
+
+    // Publish the deposition, which mints its DOI (kept, and returned).
+    // Zenodo records can't be deleted once published.
+    pub async fn publish(&mut self) -> Result<String> {
+        let id = self.get_deposition_id()?;
+        let url = format!("deposit/depositions/{}/actions/publish", id);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::POST, &url, None, None)
+            .await?;
+        let deposition: ZenodoDeposition = response.json().await?;
+        if deposition.doi.is_empty() {
+            return Err(anyhow!(
+                "Zenodo published deposition {}, but did not give its DOI.",
+                id
+            ));
+        }
+        self.doi = Some(deposition.doi.clone());
+        Ok(deposition.doi)
+    }
+
     // Check if file exists, returning None if not,
     // and the ZenodoFile if so
     // TODO: could be part of higher Remote API, e.g. through generics?
@@ -428,8 +762,10 @@ impl ZenodoAPI {
     pub async fn upload(
         &self,
         data_file: &DataFile,
+        name: &str,
         path_context: &Path,
         overwrite: bool,
+        progress: &FileProgress,
     ) -> Result<bool> {
         // (1) First, let's make sure that data_file isn't empty
         if data_file.size == 0 {
@@ -441,7 +777,6 @@ impl ZenodoAPI {
 
         // (2) Get local file info
         let full_path = path_context.join(&data_file.path);
-        let name = data_file.basename()?;
         let file_size = data_file.size;
 
         // (3) Find the bucket url.
@@ -450,7 +785,7 @@ impl ZenodoAPI {
         ))?;
 
         // (4) Let's check if the file exists on the remote
-        let existing_file = self.file_exists(&name).await?;
+        let existing_file = self.file_exists(name).await?;
         let id = self.get_deposition_id()?;
 
         // (5) handle deleting files first if a file exists and overwrite is true
@@ -489,7 +824,7 @@ impl ZenodoAPI {
 
         // (7) we need to take the Zenodo bucket_url, remove the base since
         // issue_request adds it
-        let bucket_endpoint = remove_base_url(bucket_url)?;
+        let bucket_endpoint = remove_base_url(bucket_url, self.api_url())?;
         let bucket_endpoint = format!("{}/{}", bucket_endpoint, name);
 
         // (8) Prepare the file upload
@@ -499,7 +834,7 @@ impl ZenodoAPI {
                 Method::PUT,
                 &bucket_endpoint,
                 Some(headers),
-                Some(RequestData::Stream(file)),
+                Some(RequestData::Stream(file, progress.clone())),
             )
             .await?;
         let info: ZenodoFileUpload = response.json().await?;
@@ -510,12 +845,13 @@ impl ZenodoAPI {
                               Please file an issue at: {}",
             ISSUE_URL
         );
-        let remote_md5 = info
-            .checksum
-            .strip_prefix("md5:")
-            .expect(&err_msg)
-            .to_owned();
-        let local_md5 = data_file.md5.clone();
+        let remote_md5 = match info.checksum.strip_prefix("md5:") {
+            Some(md5) if !md5.is_empty() => md5.to_owned(),
+            // an empty file may come back without a checksum
+            _ if data_file.size == 0 => EMPTY_MD5.to_string(),
+            _ => return Err(anyhow!(err_msg)),
+        };
+        let local_md5 = data_file.recorded_md5().to_string();
 
         let msg = format!(
             "After upload, the local ({}) and remote ({}) MD5s differed.\n\
@@ -563,7 +899,23 @@ impl ZenodoAPI {
         Ok(files)
     }
 
+    // Get a published record, with its files.
+    pub async fn get_record(&self) -> Result<ZenodoRecord> {
+        let id = self
+            .record_id
+            .ok_or(anyhow!("Internal Error: Zenodo record_id not set."))?;
+        let url = format!("/records/{}", id);
+        let response = self
+            .issue_request::<HashMap<String, String>>(Method::GET, &url, None, None)
+            .await?;
+        Ok(response.json().await?)
+    }
+
     pub async fn get_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        if self.is_read_only() {
+            let record = self.get_record().await?;
+            return Ok(record.files.into_iter().map(RemoteFile::from).collect());
+        }
         let articles = self.get_files().await?;
         let remote_files: Vec<RemoteFile> = articles.into_iter().map(RemoteFile::from).collect();
         Ok(remote_files)
@@ -581,6 +933,9 @@ impl ZenodoAPI {
     }
 
     pub fn authenticate_url(&self, url: &str) -> Result<String> {
+        if self.token.is_empty() {
+            return Ok(url.to_string());
+        }
         Ok(format!("{}?access_token={}", url, self.token))
     }
 }
@@ -588,10 +943,19 @@ impl ZenodoAPI {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lib::api::s3::{S3Credentials, S3API};
+    use crate::lib::data::{CatSource, DataCollection, StatusOptions, SyncSummary};
+    use crate::lib::diff::ContentDiff;
+    use crate::lib::filter::FileFilter;
+    use crate::lib::remote::{Remote, Upload};
+    use crate::lib::report::Outcome;
+    use crate::lib::usage::Usage;
+    use crate::lib::utils::{format_status_lines, status_records, StatusDisplay};
     use crate::logging_setup::setup;
     use httpmock::prelude::*;
     use serde_json::json;
     use std::io::Write;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_remote_init_success() {
@@ -611,6 +975,7 @@ mod tests {
             description: Some(
                 "Let's build infrastructure so science can build off itself.".to_string(),
             ),
+            deposit: Default::default(),
         };
 
         // Create a mock deposition endpoint with a simulated success response
@@ -670,7 +1035,7 @@ mod tests {
         });
 
         // Create an instance of ZenodoAPI
-        let mut api = ZenodoAPI::new("test", Some(server.url("/"))).unwrap();
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
 
         // Main call to test
         let _result = api.remote_init(local_metadata, false).await;
@@ -685,6 +1050,144 @@ mod tests {
         assert_eq!(api.bucket_url, Some(expected_bucket_url.to_string()));
     }
 
+    #[test]
+    fn test_sandbox_remote_uses_sandbox_host() {
+        let mut api = ZenodoAPI::new("test", Some("http://localhost".to_string()), true).unwrap();
+        api.deposition_id = Some(1234573);
+
+        // the sandbox is recorded in the manifest, so a loaded remote
+        // keeps using it
+        let yaml = serde_yaml::to_string(&Remote::ZenodoAPI(api)).unwrap();
+        let remote: Remote = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(remote.name(), "Zenodo Sandbox");
+        assert_eq!(remote.auth_key(), "zenodo-sandbox");
+        assert_eq!(
+            remote.web_url().as_deref(),
+            Some("https://sandbox.zenodo.org/deposit/1234573")
+        );
+        let Remote::ZenodoAPI(mut api) = remote else {
+            panic!("expected a Zenodo remote");
+        };
+        api.set_token(TEST_TOKEN.to_string());
+        assert_eq!(
+            api.request_url("/deposit/depositions"),
+            "https://sandbox.zenodo.org/api/deposit/depositions?access_token=test-token"
+        );
+        let bucket_url = "https://sandbox.zenodo.org/api/files/568377dd";
+        assert_eq!(
+            remove_base_url(bucket_url, api.api_url()).unwrap(),
+            "/files/568377dd"
+        );
+
+        // archive remotes are saved as before
+        let api = ZenodoAPI::new("test", Some("http://localhost".to_string()), false).unwrap();
+        let yaml = serde_yaml::to_string(&Remote::ZenodoAPI(api)).unwrap();
+        assert!(!yaml.contains("sandbox"));
+    }
+
+    #[test]
+    fn test_record_id_from_doi_or_url() {
+        for reference in [
+            "10.5281/zenodo.1234",
+            "https://doi.org/10.5281/zenodo.1234",
+            "doi:10.5281/zenodo.1234",
+            "https://zenodo.org/records/1234",
+            "https://zenodo.org/record/1234/",
+            "https://sandbox.zenodo.org/records/1234?preview=1",
+            "1234",
+        ] {
+            assert_eq!(record_id(reference).unwrap(), 1234, "{}", reference);
+        }
+        assert!(record_id("10.6084/m9.figshare.1234").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_published_record_is_pulled_not_pushed() {
+        setup();
+        let server = MockServer::start();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("inputs")).unwrap();
+        let contents = "Someone else's data\n";
+        let md5 = format!("{:x}", md5::compute(contents));
+
+        let record_mock = server.mock(|when, then| {
+            when.method(GET).path("/records/1234");
+            then.status(200).json_body(json!({
+                "id": 1234,
+                "doi": "10.5281/zenodo.1234",
+                "metadata": { "title": "Published Data" },
+                "files": [{
+                    "id": "f1",
+                    "key": "counts.tsv",
+                    "size": contents.len(),
+                    "checksum": format!("md5:{}", md5),
+                    "links": { "self": server.url("/records/1234/files/counts.tsv/content") }
+                }]
+            }));
+        });
+        let download_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/records/1234/files/counts.tsv/content");
+            then.status(200).body(contents);
+        });
+
+        // the record needs no token, and is named by its title once found
+        let api = ZenodoAPI::published(
+            "https://doi.org/10.5281/zenodo.1234",
+            Some(server.url("/")),
+            false,
+        )
+        .unwrap();
+        let mut remote = Remote::ZenodoAPI(api);
+        assert!(remote.has_token());
+        assert!(remote.is_read_only());
+        remote
+            .remote_init(LocalMetadata::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            remote.web_url().as_deref(),
+            Some("https://doi.org/10.5281/zenodo.1234")
+        );
+        let yaml = serde_yaml::to_string(&remote).unwrap();
+        assert!(yaml.contains("record_id: 1234"));
+        assert!(yaml.contains("name: Published Data"));
+
+        // its files are added to the manifest, and pulled
+        let mut data = DataCollection::new();
+        let dir = "inputs".to_string();
+        data.register_remote(&dir, remote).unwrap();
+        assert_eq!(data.register_remote_files(&dir).await.unwrap(), 1);
+        assert_eq!(data.files["inputs/counts.tsv"].md5, md5);
+        let report = data
+            .pull(path_context, false, &FileFilter::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        download_mock.assert();
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("inputs/counts.tsv")).unwrap(),
+            contents
+        );
+
+        // but nothing is pushed, even once the file is changed
+        let err = data
+            .push(path_context, false, &FileFilter::default(), 1, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("read-only"), "{}", err);
+        std::fs::write(path_context.join("inputs/counts.tsv"), "changed\n").unwrap();
+        let data_file = data.files.get_mut("inputs/counts.tsv").unwrap();
+        data_file.update(path_context).await.unwrap();
+        let err = data
+            .push_plan(path_context, true, &FileFilter::default(), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("'inputs'"), "{}", err);
+        record_mock.assert_hits(5);
+    }
+
     #[tokio::test]
     async fn test_delete_article_file() {
         setup();
@@ -713,7 +1216,7 @@ mod tests {
         });
 
         // Create an instance of your API class and set the deposition_id
-        let mut api = ZenodoAPI::new("test", Some(server.url("/"))).unwrap();
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
         trace!("auth_keys: {:?}", api.token);
         api.deposition_id = Some(expected_deposition_id);
 
@@ -755,6 +1258,17 @@ mod tests {
         bucket_endpoint: &'a str,
         md5: &'a str,
         size: usize,
+    ) -> httpmock::Mock<'a> {
+        setup_slow_upload_file_mock(server, bucket_endpoint, md5, size, Duration::ZERO)
+    }
+
+    // Like setup_upload_file_mock(), taking delay to answer each upload.
+    fn setup_slow_upload_file_mock<'a>(
+        server: &'a MockServer,
+        bucket_endpoint: &'a str,
+        md5: &'a str,
+        size: usize,
+        delay: Duration,
     ) -> httpmock::Mock<'a> {
         debug!("Setting up upload_file mock");
         //let md5 = if !wrong_md5 { md5.to_owned() } else { md5.clone().chars().rev().collect::<String>() };
@@ -764,6 +1278,7 @@ mod tests {
                 .header("Content-Type", "application/octet-stream")
                 .path_matches(Regex::new(&format!(r"{}/([^/]+)", bucket_endpoint)).unwrap());
             then.status(201)
+                .delay(delay)
                 .json_body(json!({
                     "key": "example_data_file.tsv",
                     "mimetype": "application/zip",
@@ -829,6 +1344,16 @@ mod tests {
             md5: md5.to_string(),
             size,
             url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            algo: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
         };
 
         let path_context = Path::new("path/to/datafile");
@@ -856,8 +1381,7 @@ mod tests {
 
         // Mock for the upload method
         // NOTE: this mock does not test for binary files
-        let upload_file_mock =
-            setup_upload_file_mock(&server, &bucket_endpoint, &md5, size as usize);
+        let upload_file_mock = setup_upload_file_mock(&server, bucket_endpoint, md5, size as usize);
 
         // Mock for the delete_article_file method
         let delete_file_mock = if file_exists && overwrite {
@@ -871,12 +1395,21 @@ mod tests {
         };
 
         // Create an instance of your API class and set the deposition_id
-        let mut api = ZenodoAPI::new("test", Some(server.url("/"))).unwrap();
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
         api.deposition_id = Some(expected_deposition_id);
         api.bucket_url = Some(bucket_url.to_string());
 
         // Main call to test
-        let result = api.upload(&data_file, &path_context, overwrite).await;
+        let name = data_file.basename().unwrap();
+        let result = api
+            .upload(
+                &data_file,
+                &name,
+                path_context,
+                overwrite,
+                &FileProgress::hidden(),
+            )
+            .await;
 
         //println!("get_files_mock={:}?, upload_file_mock={:?}, delete_file_mock={:?}",
         //         get_files_mock.hits(), upload_file_mock.hits(), delete_file_mock.unwrap().hits());
@@ -891,7 +1424,7 @@ mod tests {
         if file_exists && overwrite {
             delete_file_mock.unwrap().assert();
         }
-        return result;
+        result
     }
 
     #[tokio::test]
@@ -927,4 +1460,1308 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_lost_upload_response_is_not_reuploaded() {
+        setup();
+        let server = MockServer::start();
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(temp_file, "Some test data for the file").unwrap();
+        let path = temp_file.path().to_string_lossy().to_string();
+        let md5 = "2942bfabb3d05332b66eb128e0842cff";
+        let data_file = DataFile {
+            path,
+            tracked: true,
+            md5: md5.to_string(),
+            size: 28,
+            url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            algo: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+        };
+        let name = data_file.basename().unwrap();
+        let deposition_id = 1234570;
+        let bucket_endpoint = "/files/568377dd-daf8-4235-85e1-a56011ad454c";
+
+        // the upload goes through, but its response is lost (a gateway
+        // timeout); the listing shows the uploaded file
+        let remote_files = vec![ZenodoFile {
+            checksum: md5.to_string(),
+            filename: name.clone(),
+            filesize: 28,
+            id: "4242".to_string(),
+            links: ZenodoLinks::default(),
+        }];
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+        let delete_file_mock = setup_delete_file_mock(&server, &remote_files[0], deposition_id);
+        let upload_mock = server.mock(|when, then| {
+            when.method("PUT").path_contains(bucket_endpoint);
+            then.status(504);
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        api.bucket_url = Some(format!("{}/{}", BASE_URL, bucket_endpoint));
+        let remote = Remote::ZenodoAPI(api);
+        let result = remote
+            .upload(
+                &data_file,
+                &name,
+                Path::new(""),
+                true,
+                &FileProgress::hidden(),
+            )
+            .await;
+        assert!(result.is_err());
+        let upload = Upload {
+            data_file,
+            name,
+            result,
+        };
+        let uploaded = remote.confirm_uploads(vec![upload]).await;
+        assert!(uploaded[0].as_ref().unwrap());
+        // the PUT is retried, but once every attempt fails, the upload
+        // is confirmed from the listing rather than sent again
+        upload_mock.assert_hits(retry_policy().max_attempts as usize);
+        delete_file_mock.assert_hits(1);
+        // once before the upload, and once to confirm it
+        get_files_mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_push_reports_each_file() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234571;
+        let bucket_endpoint = "/files/568377dd-daf8-4235-85e1-a56011ad454d";
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+
+        // the remote has every file, without MD5s, so all are pushed with
+        // --overwrite; one upload keeps failing
+        let names = ["a.tsv", "b.tsv", "bad.tsv", "c.tsv", "d.tsv", "e.tsv"];
+        let contents = "Some test data for the file\n";
+        let md5 = format!("{:x}", md5::compute(contents));
+        let mut data = DataCollection::new();
+        let mut remote_files = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            assert_eq!(data_file.md5, md5);
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+            remote_files.push(ZenodoFile {
+                checksum: String::new(),
+                filename: name.to_string(),
+                filesize: 28,
+                id: format!("42{}", i),
+                links: ZenodoLinks::default(),
+            });
+        }
+        setup_get_files_mock(&server, deposition_id, &remote_files);
+        setup_get_deposition_mock(&server, deposition_id, false, deposition_id);
+        let delete_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path_contains(format!("/deposit/depositions/{}/files/", deposition_id));
+            then.status(204);
+        });
+        let failing_mock = server.mock(|when, then| {
+            when.method("PUT")
+                .path(format!("{}/bad.tsv", bucket_endpoint));
+            then.status(500);
+        });
+        let upload_mock = setup_upload_file_mock(&server, bucket_endpoint, &md5, 28);
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        api.bucket_url = Some(format!("{}/{}", BASE_URL, bucket_endpoint));
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        let report = data
+            .push(path_context, true, &Default::default(), 3, None)
+            .await
+            .unwrap();
+        upload_mock.assert_hits(5);
+        failing_mock.assert_hits(retry_policy().max_attempts as usize);
+        delete_mock.assert_hits(names.len());
+
+        // outcomes are reported in path order, whatever order they finished in
+        let outcomes: Vec<(String, Outcome)> = report
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file.status.clone()))
+            .collect();
+        let expected: Vec<(String, Outcome)> = names
+            .iter()
+            .map(|name| {
+                let status = if *name == "bad.tsv" {
+                    Outcome::Failed
+                } else {
+                    Outcome::Succeeded
+                };
+                (format!("data/{}", name), status)
+            })
+            .collect();
+        assert_eq!(outcomes, expected);
+        assert_eq!(data.files["data/a.tsv"].remote_md5, Some(md5));
+        assert_eq!(data.files["data/bad.tsv"].remote_md5, None);
+    }
+
+    #[tokio::test]
+    async fn test_push_limits_concurrent_uploads() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234572;
+        let bucket_endpoint = "/files/bucket-1234572";
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let names = ["a.tsv", "b.tsv", "c.tsv", "d.tsv"];
+        let contents = "Some test data for the file\n";
+        let md5 = format!("{:x}", md5::compute(contents));
+        let mut data = DataCollection::new();
+        let mut remote_files = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+            remote_files.push(ZenodoFile {
+                checksum: String::new(),
+                filename: name.to_string(),
+                filesize: 28,
+                id: format!("43{}", i),
+                links: ZenodoLinks::default(),
+            });
+        }
+        setup_get_files_mock(&server, deposition_id, &remote_files);
+        setup_get_deposition_mock(&server, deposition_id, false, deposition_id);
+        server.mock(|when, then| {
+            when.method(DELETE)
+                .path_contains(format!("/deposit/depositions/{}/files/", deposition_id));
+            then.status(204);
+        });
+        let delay = Duration::from_millis(300);
+        let upload_mock = setup_slow_upload_file_mock(&server, bucket_endpoint, &md5, 28, delay);
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        api.bucket_url = Some(format!("{}/{}", BASE_URL, bucket_endpoint));
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        // --max-concurrent 4, but only 2 uploads to Zenodo at once
+        let start = std::time::Instant::now();
+        let report = data
+            .push(path_context, true, &Default::default(), 4, None)
+            .await
+            .unwrap();
+        upload_mock.assert_hits(names.len());
+        assert_eq!(report.count(Outcome::Succeeded), names.len());
+        assert!(start.elapsed() >= delay * 2, "{:?}", start.elapsed());
+    }
+
+    // A deposition, as Zenodo lists it, with its latest draft (itself,
+    // unless a new version was started).
+    fn deposition_json(id: u64, submitted: bool, latest_draft: u64) -> serde_json::Value {
+        let link = |id: u64| format!("{}/deposit/depositions/{}", BASE_URL, id);
+        json!({
+            "conceptrecid": "8266447",
+            "created": "2023-08-20T01:31:12.406094+00:00",
+            "id": id,
+            "links": {
+                "bucket": format!("{}/files/bucket-{}", BASE_URL, id),
+                "latest_draft": link(latest_draft),
+                "self": link(id)
+            },
+            "metadata": {
+                "title": "test",
+                "upload_type": "dataset",
+                "description": null,
+                "creators": null
+            },
+            "modified": "2023-08-20T01:31:12.406103+00:00",
+            "owner": 110965,
+            "record_id": id,
+            "state": if submitted { "done" } else { "unsubmitted" },
+            "submitted": submitted,
+            "title": "test"
+        })
+    }
+
+    fn setup_get_deposition_mock(
+        server: &MockServer,
+        id: u64,
+        submitted: bool,
+        latest_draft: u64,
+    ) -> httpmock::Mock<'_> {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/deposit/depositions/{}", id))
+                .query_param("access_token", TEST_TOKEN);
+            then.status(200)
+                .json_body(deposition_json(id, submitted, latest_draft));
+        })
+    }
+
+    #[tokio::test]
+    async fn test_push_to_published_deposition_makes_new_version() {
+        setup();
+        let server = MockServer::start();
+        let (published_id, draft_id) = (1234575, 1234576);
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+
+        // the new version starts with copies of the published files: a.tsv
+        // is unchanged, and b.tsv has changed since
+        let (old, new) = ("published contents\n", "new contents\n");
+        let new_md5 = format!("{:x}", md5::compute(new));
+        let mut data = DataCollection::new();
+        for (name, contents) in [("a.tsv", old), ("b.tsv", new)] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        let file = |name: &str, id: &str, contents: &str| ZenodoFile {
+            checksum: format!("{:x}", md5::compute(contents)),
+            filename: name.to_string(),
+            filesize: contents.len(),
+            id: id.to_string(),
+            links: ZenodoLinks::default(),
+        };
+        let published_files = vec![file("a.tsv", "520", old), file("b.tsv", "521", old)];
+        // (the listing mock is static, so it lists the new version as
+        // the push leaves it)
+        let copies = vec![file("a.tsv", "420", old), file("b.tsv", "421", new)];
+
+        let published_mock = setup_get_deposition_mock(&server, published_id, true, published_id);
+        let new_version_mock = server.mock(|when, then| {
+            when.method(POST).path(format!(
+                "/deposit/depositions/{}/actions/newversion",
+                published_id
+            ));
+            then.status(201)
+                .json_body(deposition_json(published_id, true, draft_id));
+        });
+        let draft_mock = setup_get_deposition_mock(&server, draft_id, false, draft_id);
+        // the status is from the published files (which the copies match),
+        // but files are replaced in the new version
+        setup_get_files_mock(&server, published_id, &published_files);
+        setup_get_files_mock(&server, draft_id, &copies);
+        let delete_mock = setup_delete_file_mock(&server, &copies[1], draft_id);
+        let bucket_endpoint = format!("/files/bucket-{}", draft_id);
+        let upload_mock = setup_upload_file_mock(&server, &bucket_endpoint, &new_md5, new.len());
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(published_id);
+        api.bucket_url = Some(format!("{}/files/bucket-{}", BASE_URL, published_id));
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        let report = data
+            .push(path_context, true, &FileFilter::default(), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        new_version_mock.assert_hits(1);
+        // only the changed file's copy is replaced
+        delete_mock.assert_hits(1);
+        upload_mock.assert_hits(1);
+
+        // the manifest now has the new version, which later pushes go to
+        // (until it is published, too)
+        let Remote::ZenodoAPI(api) = &data.remotes["data"] else {
+            panic!("not a Zenodo remote");
+        };
+        assert_eq!(api.deposition_id, Some(draft_id));
+        assert_eq!(
+            api.bucket_url,
+            Some(format!("{}{}", BASE_URL, bucket_endpoint))
+        );
+        assert_eq!(data.files["data/b.tsv"].remote_md5, Some(new_md5.clone()));
+        data.push(path_context, true, &FileFilter::default(), 1, None)
+            .await
+            .unwrap();
+        // the next push lists the new version, where b.tsv is current, so
+        // there is nothing to upload (nor a version to make)
+        published_mock.assert_hits(1);
+        draft_mock.assert_hits(1);
+        upload_mock.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn test_push_updates_deposition_metadata() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234578;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut deposition = deposition_json(deposition_id, false, deposition_id);
+        deposition["metadata"]["access_right"] = json!("open");
+        let mut get_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/deposit/depositions/{}", deposition_id));
+            then.status(200).json_body(deposition.clone());
+        });
+        let no_files = Vec::new();
+        setup_get_files_mock(&server, deposition_id, &no_files);
+        // the manifest's fields are set, and the rest kept
+        let put_mock = server.mock(|when, then| {
+            when.method(PUT)
+                .path(format!("/deposit/depositions/{}", deposition_id))
+                .json_body_partial(
+                    json!({"metadata": {
+                        "title": "test",
+                        "access_right": "open",
+                        "license": "cc-by-4.0",
+                        "keywords": ["aDNA"],
+                        "creators": [{
+                            "name": "Joan B. Scientist",
+                            "affiliation": null,
+                            "orcid": "0000-0002-1825-0097"
+                        }]
+                    }})
+                    .to_string(),
+                );
+            then.status(200).json_body(deposition.clone());
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+        data.metadata.creators = vec![Creator {
+            name: "Joan B. Scientist".to_string(),
+            affiliation: None,
+            orcid: Some("0000-0002-1825-0097".to_string()),
+        }];
+        data.metadata.license = Some("cc-by-4.0".to_string());
+        data.metadata.keywords = vec!["aDNA".to_string()];
+        data.push(temp_dir.path(), false, &FileFilter::default(), 1, None)
+            .await
+            .unwrap();
+        put_mock.assert_hits(1);
+
+        // once the deposition has it, its metadata is left alone
+        let mut updated: ZenodoDeposition = serde_json::from_value(deposition).unwrap();
+        updated.metadata.apply(&data.metadata.deposit("data"));
+        get_mock.delete();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/deposit/depositions/{}", deposition_id));
+            then.status(200).json_body(json!(updated));
+        });
+        data.push(temp_dir.path(), false, &FileFilter::default(), 1, None)
+            .await
+            .unwrap();
+        put_mock.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_keeps_doi() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234577;
+        let doi = "10.5281/zenodo.1234577";
+        let mut published = deposition_json(deposition_id, true, deposition_id);
+        published["doi"] = json!(doi);
+        let publish_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path(format!(
+                    "/deposit/depositions/{}/actions/publish",
+                    deposition_id
+                ))
+                .query_param("access_token", TEST_TOKEN);
+            then.status(202).json_body(published);
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut remote = Remote::ZenodoAPI(api);
+        assert_eq!(remote.publish().await.unwrap(), doi);
+        publish_mock.assert();
+        assert_eq!(remote.web_url(), Some(format!("https://doi.org/{}", doi)));
+
+        // the DOI is kept in the manifest, though the deposition is still
+        // pushed to (see prepare_push())
+        let Remote::ZenodoAPI(api) = &remote else {
+            panic!("not a Zenodo remote");
+        };
+        assert!(!api.is_read_only());
+        let saved: ZenodoAPI = serde_yaml::from_str(&serde_yaml::to_string(api).unwrap()).unwrap();
+        assert_eq!(saved.doi.as_deref(), Some(doi));
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pull_scoped_to_paths() {
+        setup();
+        let server = MockServer::start();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let contents = "Some test data for the file\n";
+        let md5 = format!("{:x}", md5::compute(contents));
+
+        // two linked directories, each with a file on the remote and one
+        // not yet pushed
+        let mut data = DataCollection::new();
+        let mut remote_files = HashMap::new();
+        for (dir, deposition_id, pushed, unpushed) in [
+            ("alignments", 1234580, "a.bam", "b.bam"),
+            ("reads", 1234581, "r.fq", "s.fq"),
+        ] {
+            std::fs::create_dir(path_context.join(dir)).unwrap();
+            for name in [pushed, unpushed] {
+                let path = format!("{}/{}", dir, name);
+                std::fs::write(path_context.join(&path), contents).unwrap();
+                let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+                data_file.tracked = true;
+                data.register(data_file).unwrap();
+            }
+            let links = ZenodoLinks {
+                download: Some(server.url(format!("/files/{}/{}", dir, pushed))),
+                ..Default::default()
+            };
+            let files = vec![ZenodoFile {
+                checksum: md5.clone(),
+                filename: pushed.to_string(),
+                filesize: contents.len(),
+                id: format!("{}", deposition_id),
+                links,
+            }];
+            remote_files.insert(dir, (deposition_id, files));
+        }
+        let mut mocks = HashMap::new();
+        for (dir, (deposition_id, files)) in &remote_files {
+            let listing = setup_get_files_mock(&server, *deposition_id, files);
+            let download = server.mock(|when, then| {
+                when.method(GET)
+                    .path(format!("/files/{}/{}", dir, files[0].filename));
+                then.status(200).body(contents);
+            });
+            mocks.insert(*dir, (listing, download));
+
+            let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+            api.deposition_id = Some(*deposition_id);
+            data.register_remote(&dir.to_string(), Remote::ZenodoAPI(api))
+                .unwrap();
+        }
+
+        // only the unpushed file under alignments/ would be uploaded; the
+        // other remote is not even listed
+        let filter = FileFilter {
+            paths: vec!["alignments".to_string()],
+            ..Default::default()
+        };
+        let plan = data
+            .push_plan(path_context, false, &filter, None)
+            .await
+            .unwrap();
+        let planned: Vec<_> = plan.transfers.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(planned, ["alignments/b.bam"]);
+        mocks["alignments"].0.assert_hits(1);
+        mocks["reads"].0.assert_hits(0);
+
+        // with a pattern, only the matching deleted file is restored
+        for path in ["alignments/a.bam", "reads/r.fq"] {
+            std::fs::remove_file(path_context.join(path)).unwrap();
+        }
+        let filter = FileFilter {
+            paths: vec!["alignments/*.bam".to_string()],
+            ..Default::default()
+        };
+        let report = data
+            .pull(path_context, false, &filter, 2, None)
+            .await
+            .unwrap();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        mocks["alignments"].1.assert_hits(1);
+        mocks["reads"].0.assert_hits(0);
+        mocks["reads"].1.assert_hits(0);
+        assert!(path_context.join("alignments/a.bam").exists());
+        assert!(!path_context.join("reads/r.fq").exists());
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_confirmed_with_one_listing() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234571;
+        let files = ["a.tsv", "b.tsv", "c.tsv"];
+        let remote_files: Vec<ZenodoFile> = files
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ZenodoFile {
+                checksum: format!("{:032x}", i),
+                filename: name.to_string(),
+                filesize: 10,
+                id: i.to_string(),
+                links: ZenodoLinks::default(),
+            })
+            .collect();
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let remote = Remote::ZenodoAPI(api);
+        let uploads = files
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Upload {
+                data_file: DataFile {
+                    path: format!("data/{}", name),
+                    tracked: true,
+                    // (c.tsv's upload went wrong)
+                    md5: format!("{:032x}", if i == 2 { 9 } else { i }),
+                    size: 10,
+                    url: None,
+                    modified: None,
+                    remote_md5: None,
+                    remote_size: None,
+                    remote: None,
+                    checksums: Default::default(),
+                    algo: Default::default(),
+                    block_hashes: None,
+                    compression: None,
+                    encryption: None,
+                    stored: None,
+                },
+                name: name.to_string(),
+                result: Ok(true),
+            })
+            .collect();
+        let results = remote.confirm_uploads(uploads).await;
+        get_files_mock.assert_hits(1);
+        assert!(results[0].as_ref().unwrap());
+        assert!(results[1].as_ref().unwrap());
+        let err = results[2].as_ref().unwrap_err();
+        assert!(err.to_string().contains("After upload"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_checksum_from_remote() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234564;
+        let md5 = "2942bfabb3d05332b66eb128e0842cff";
+
+        // a hand-written manifest entry, with no checksum
+        let data_file = DataFile {
+            path: "data/results.tsv".to_string(),
+            tracked: true,
+            md5: "".to_string(),
+            size: 0,
+            url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            algo: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+        };
+        let remote_files = vec![ZenodoFile {
+            checksum: md5.to_string(),
+            filename: "results.tsv".to_string(),
+            filesize: 28,
+            id: "4242".to_string(),
+            links: ZenodoLinks::default(),
+        }];
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+        data.register(data_file).unwrap();
+
+        let report = data.backfill_checksums().await.unwrap();
+        get_files_mock.assert();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        let backfilled = &data.files["data/results.tsv"];
+        assert_eq!(backfilled.md5, md5);
+        assert_eq!(backfilled.size, 28);
+    }
+
+    #[tokio::test]
+    async fn test_repair_from_remote() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234565;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let good = "Mock data.";
+        std::fs::write(path_context.join("data/results.tsv"), good).unwrap();
+        let data_file = DataFile::new("data/results.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+
+        // bit rot: the local copy no longer matches the manifest
+        std::fs::write(path_context.join("data/results.tsv"), "Mock dat4.").unwrap();
+
+        let links = ZenodoLinks {
+            download: Some(server.url("/files/results.tsv")),
+            ..Default::default()
+        };
+        let remote_files = vec![ZenodoFile {
+            checksum: data_file.md5.clone(),
+            filename: "results.tsv".to_string(),
+            filesize: good.len(),
+            id: "4242".to_string(),
+            links,
+        }];
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+        let download_mock = server.mock(|when, then| {
+            when.method(GET).path("/files/results.tsv");
+            then.status(200).body(good);
+        });
+        server.mock(|when, then| {
+            when.method("HEAD").path("/files/results.tsv");
+            then.status(200);
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+        data.register(data_file).unwrap();
+
+        let verified = data.verify(path_context).await.unwrap();
+        assert_eq!(verified.report.count(Outcome::Failed), 1);
+        assert_eq!(verified.exit_code(), 1);
+
+        let corrupted = ["data/results.tsv".to_string()].into_iter().collect();
+        let report = data
+            .repair_from_remote(path_context, &corrupted)
+            .await
+            .unwrap();
+        get_files_mock.assert();
+        download_mock.assert();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        let repaired = std::fs::read_to_string(path_context.join("data/results.tsv")).unwrap();
+        assert_eq!(repaired, good);
+        let verified = data.verify(path_context).await.unwrap();
+        assert!(!verified.report.has_failures());
+    }
+
+    #[tokio::test]
+    async fn test_verify_remote_copies() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234566;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut data = DataCollection::new();
+        let mut remote_files = Vec::new();
+        for (name, remote_contents) in [
+            ("good.tsv", Some("Mock data.")),
+            ("rotten.tsv", Some("Mock dat4.")),
+            ("unpushed.tsv", None),
+        ] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), "Mock data.").unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+            if let Some(contents) = remote_contents {
+                remote_files.push(ZenodoFile {
+                    checksum: format!("{:x}", md5::compute(contents)),
+                    filename: name.to_string(),
+                    filesize: contents.len(),
+                    id: name.to_string(),
+                    links: ZenodoLinks::default(),
+                });
+            }
+        }
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        // every local copy is fine, but the remote's are not
+        let mut verified = data.verify(path_context).await.unwrap();
+        assert_eq!(verified.exit_code(), 0);
+        data.verify_remotes(path_context, &mut verified)
+            .await
+            .unwrap();
+        get_files_mock.assert();
+        assert_eq!(verified.remote_missing, 1);
+        assert_eq!(verified.remote_mismatched, 1);
+        assert_eq!(verified.exit_code(), 1);
+        let failed: Vec<_> = verified
+            .report
+            .sorted()
+            .into_iter()
+            .filter(|file| file.status == Outcome::Failed)
+            .map(|file| (file.path.as_str(), file.remote.as_deref()))
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                ("data/rotten.tsv", Some("Zenodo")),
+                ("data/unpushed.tsv", Some("Zenodo"))
+            ]
+        );
+        assert_eq!(verified.report.count(Outcome::Succeeded), 4);
+    }
+
+    async fn cat_from_mock_remote(deposition_id: u64, served: &str) -> Result<Vec<u8>> {
+        let server = MockServer::start();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(path_context.join("data/results.tsv"), "Mock data.").unwrap();
+        let data_file = DataFile::new("data/results.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        // not available locally, so it has to be streamed
+        std::fs::remove_file(path_context.join("data/results.tsv")).unwrap();
+
+        let links = ZenodoLinks {
+            download: Some(server.url("/files/results.tsv")),
+            ..Default::default()
+        };
+        let remote_files = vec![ZenodoFile {
+            checksum: data_file.md5.clone(),
+            filename: "results.tsv".to_string(),
+            filesize: served.len(),
+            id: "4242".to_string(),
+            links,
+        }];
+        setup_get_files_mock(&server, deposition_id, &remote_files);
+        let served = served.to_string();
+        server.mock(move |when, then| {
+            when.method(GET).path("/files/results.tsv");
+            then.status(200).body(served);
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+        data.register(data_file).unwrap();
+
+        let mut streamed = Vec::new();
+        let source = data
+            .cat(path_context, "data/results.tsv", &mut streamed)
+            .await?;
+        assert_eq!(source, CatSource::Remote("Zenodo".to_string()));
+        Ok(streamed)
+    }
+
+    #[tokio::test]
+    async fn test_cat_streams_and_verifies() {
+        setup();
+        let streamed = cat_from_mock_remote(1234567, "Mock data.").await.unwrap();
+        assert_eq!(streamed, b"Mock data.");
+
+        // corrupted in transit: the MD5 does not match the manifest,
+        // which makes 'sdf cat' exit non-zero
+        let result = cat_from_mock_remote(1234568, "Mock dat4.").await;
+        assert!(result.unwrap_err().to_string().contains("MD5 mismatch"));
+    }
+
+    #[tokio::test]
+    async fn test_usage_counts_requests_and_bytes() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234566;
+        let remote_files = vec![ZenodoFile {
+            checksum: "2942bfabb3d05332b66eb128e0842cff".to_string(),
+            filename: "results.tsv".to_string(),
+            filesize: 28,
+            id: "4242".to_string(),
+            links: ZenodoLinks::default(),
+        }];
+        let get_files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        api.get_files().await.unwrap();
+        api.get_files().await.unwrap();
+        get_files_mock.assert_hits(2);
+
+        let body_size = serde_json::to_vec(&json!(remote_files)).unwrap().len() as u64;
+        let usage = api.usage.snapshot();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(
+            usage[&Operation::List],
+            Usage {
+                requests: 2,
+                bytes_sent: 0,
+                bytes_received: 2 * body_size,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_remote_latency() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234569;
+        setup_get_files_mock(&server, deposition_id, &Vec::new());
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+        assert!(data.latency().is_empty());
+
+        let options = StatusOptions {
+            include_remotes: true,
+            remote_latency: true,
+            ..Default::default()
+        };
+        data.status(temp_dir.path(), &options).await.unwrap();
+        let latency = data.latency();
+        assert_eq!(latency.len(), 1);
+        assert_eq!(
+            (latency[0].directory.as_str(), latency[0].remote.as_str()),
+            ("data", "Zenodo")
+        );
+        let stats = latency[0].latency;
+        assert_eq!(stats.calls, 1);
+        assert!(stats.min <= stats.median && stats.median <= stats.max);
+    }
+
+    #[tokio::test]
+    async fn test_ahead_behind_counts() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234571;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let md5 = |contents: &str| format!("{:x}", md5::compute(contents));
+
+        let mut data = DataCollection::new();
+        let mut remote_files = Vec::new();
+        // (file, local contents, remote contents, contents at the last sync)
+        let fixture = [
+            ("ahead.tsv", "new", Some("old"), Some("old")),
+            ("behind.tsv", "old", Some("new"), Some("old")),
+            ("conflict.tsv", "mine", Some("theirs"), Some("old")),
+            ("synced.tsv", "same", Some("same"), Some("same")),
+            ("unpushed.tsv", "new", None, None),
+        ];
+        for (i, (name, local, remote, synced)) in fixture.into_iter().enumerate() {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), local).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data_file.remote_md5 = synced.map(md5);
+            data.register(data_file).unwrap();
+            if let Some(remote) = remote {
+                remote_files.push(ZenodoFile {
+                    checksum: md5(remote),
+                    filename: name.to_string(),
+                    filesize: remote.len(),
+                    id: i.to_string(),
+                    links: ZenodoLinks::default(),
+                });
+            }
+        }
+        setup_get_files_mock(&server, deposition_id, &remote_files);
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        let summaries = data.ahead_behind(path_context).await.unwrap();
+        assert_eq!(
+            summaries,
+            vec![SyncSummary {
+                directory: "data".to_string(),
+                remote: "Zenodo".to_string(),
+                ahead: 2,
+                behind: 1,
+                conflict: 1,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_reuses_cached_remote_listing() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234570;
+        let remote_files = Vec::new();
+        let files_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+        let temp_dir = tempfile::TempDir::new().unwrap();
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut data = DataCollection::new();
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        let mut options = StatusOptions {
+            include_remotes: true,
+            remote_cache_ttl: Some(3600),
+            ..Default::default()
+        };
+        data.status(temp_dir.path(), &options).await.unwrap();
+        assert_eq!(files_mock.hits(), 1);
+
+        // within the TTL, the cached listing is used
+        data.status(temp_dir.path(), &options).await.unwrap();
+        assert_eq!(files_mock.hits(), 1);
+
+        // i.e. --refresh
+        options.refresh = true;
+        data.status(temp_dir.path(), &options).await.unwrap();
+        assert_eq!(files_mock.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_status_json_records() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234572;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let md5 = |contents: &str| format!("{:x}", md5::compute(contents));
+
+        let mut data = DataCollection::new();
+        for (name, contents) in [("current.tsv", "same"), ("modified.tsv", "old")] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        std::fs::write(path_context.join("data/modified.tsv"), "new").unwrap();
+        let remote_files: Vec<ZenodoFile> = [("current.tsv", "same"), ("extra.tsv", "more")]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, contents))| ZenodoFile {
+                checksum: md5(contents),
+                filename: name.to_string(),
+                filesize: contents.len(),
+                id: i.to_string(),
+                links: ZenodoLinks::default(),
+            })
+            .collect();
+        setup_get_files_mock(&server, deposition_id, &remote_files);
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        data.register_remote(&"data".to_string(), Remote::ZenodoAPI(api))
+            .unwrap();
+
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        let mut records = serde_json::to_value(status_records(&rows, true)).unwrap();
+        // local files have a modification time (in UTC), remote-only ones none
+        let mtimes: Vec<_> = records
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .map(|record| record.as_object_mut().unwrap().remove("mtime").unwrap())
+            .collect();
+        assert!(mtimes[0].as_str().unwrap().ends_with('Z'));
+        assert!(mtimes[1].is_null());
+        assert!(mtimes[2].is_string());
+        assert_eq!(
+            records,
+            json!([
+                {
+                    "path": "data/current.tsv",
+                    "tracked": true,
+                    "local_status": "current",
+                    "remote_status": "current",
+                    "size": 4,
+                    "md5": md5("same"),
+                    "remote": "Zenodo"
+                },
+                {
+                    "path": "data/extra.tsv",
+                    "tracked": null,
+                    "local_status": null,
+                    "remote_status": "no_local",
+                    "size": 4,
+                    "md5": md5("more"),
+                    "remote": "Zenodo"
+                },
+                {
+                    "path": "data/modified.tsv",
+                    "tracked": true,
+                    "local_status": "modified",
+                    "remote_status": "not_exists",
+                    "size": 3,
+                    "md5": md5("new"),
+                    "remote": "Zenodo"
+                }
+            ])
+        );
+        // without --all, files not in the manifest are left out
+        assert_eq!(status_records(&rows, false).len(), 2);
+
+        // TSV rows have the same fields, with nulls empty
+        let row = status_records(&rows, true)[1].tsv_row();
+        assert_eq!(
+            row,
+            [
+                "data/extra.tsv",
+                "",
+                "",
+                "no_local",
+                &md5("more"),
+                "4",
+                "",
+                "Zenodo"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_and_transfers_with_s3_mirror() {
+        setup();
+        let server = MockServer::start();
+        let deposition_id = 1234574;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let md5 = |contents: &str| format!("{:x}", md5::compute(contents));
+
+        let files = [
+            ("both.tsv", "same"),
+            ("published.tsv", "on zenodo"),
+            ("working.tsv", "on s3"),
+        ];
+        let mut data = DataCollection::new();
+        for (name, contents) in files {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
+        }
+        let remote_files: Vec<ZenodoFile> = files[..2]
+            .iter()
+            .enumerate()
+            .map(|(i, (name, contents))| ZenodoFile {
+                checksum: md5(contents),
+                filename: name.to_string(),
+                filesize: contents.len(),
+                id: i.to_string(),
+                links: ZenodoLinks::default(),
+            })
+            .collect();
+        let zenodo_mock = setup_get_files_mock(&server, deposition_id, &remote_files);
+        // the S3 mirror has both.tsv and working.tsv, and a file that is
+        // not in the manifest
+        let objects: String = [files[0], files[2], ("scratch.tsv", "tmp")]
+            .iter()
+            .map(|(name, contents)| {
+                format!(
+                    "<Contents><Key>project/{}</Key><ETag>&quot;{}&quot;</ETag>\
+                     <Size>{}</Size></Contents>",
+                    name,
+                    md5(contents),
+                    contents.len()
+                )
+            })
+            .collect();
+        let s3_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/working")
+                .query_param("list-type", "2");
+            then.status(200).body(format!(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                objects
+            ));
+        });
+
+        let mut api = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        api.deposition_id = Some(deposition_id);
+        let mut s3 = S3API::new("working/project", Some(server.base_url()), None).unwrap();
+        s3.set_credentials(S3Credentials::new("test-id", "test-secret"));
+        let dir = "data".to_string();
+        data.register_remote(&dir, Remote::ZenodoAPI(api)).unwrap();
+        data.register_mirror(&dir, Remote::S3API(s3.clone()))
+            .unwrap();
+        assert!(data.register_mirror(&dir, Remote::S3API(s3)).is_err());
+
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let rows = data.status(path_context, &options).await.unwrap();
+        zenodo_mock.assert_hits(1);
+        s3_mock.assert_hits(1);
+        let statuses: Vec<(String, String, String)> =
+            serde_json::to_value(status_records(&rows, true))
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|record| {
+                    let path = record["path"].as_str().unwrap().to_string();
+                    let zenodo = record["remote_status"].as_str().unwrap().to_string();
+                    let s3 = record["mirrors"][0]["remote_status"]
+                        .as_str()
+                        .unwrap()
+                        .to_string();
+                    (path, zenodo, s3)
+                })
+                .collect();
+        let expected = [
+            ("data/both.tsv", "current", "current"),
+            ("data/published.tsv", "current", "not_exists"),
+            ("data/scratch.tsv", "not_exists", "no_local"),
+            ("data/working.tsv", "not_exists", "current"),
+        ];
+        let expected: Vec<(String, String, String)> = expected
+            .iter()
+            .map(|(path, zenodo, s3)| (path.to_string(), zenodo.to_string(), s3.to_string()))
+            .collect();
+        assert_eq!(statuses, expected);
+        // the manifest keeps the last-known state of Zenodo's copies
+        assert_eq!(data.files["data/working.tsv"].remote_md5, None);
+        assert_eq!(
+            data.files["data/both.tsv"].remote,
+            Some("Zenodo".to_string())
+        );
+
+        // each remote gets a column, and files missing from one are
+        // flagged like files missing from the only remote
+        let display = StatusDisplay {
+            color: false,
+            ..Default::default()
+        };
+        let entry = |name: &str| rows["data"].iter().find(|e| e.name == name).unwrap();
+        let remote_columns = |name: &str| entry(name).columns(None)[4..].to_vec();
+        assert_eq!(
+            remote_columns("both.tsv"),
+            ["Zenodo: identical remote", "S3: identical remote"]
+        );
+        assert_eq!(
+            remote_columns("published.tsv"),
+            ["Zenodo: identical remote", "S3: not on remote"]
+        );
+        assert_eq!(
+            remote_columns("working.tsv"),
+            ["Zenodo: not on remote", "S3: identical remote"]
+        );
+        assert_eq!(entry("both.tsv").severity(), 0);
+        assert_eq!(entry("published.tsv").severity(), 1);
+        let lines = format_status_lines(&rows, None, None, &display, None);
+        assert!(lines[2].contains("published.tsv"));
+        assert!(lines[2].split_whitespace().collect::<Vec<_>>().ends_with(&[
+            "Zenodo:",
+            "identical",
+            "remote",
+            "S3:",
+            "not",
+            "on",
+            "remote"
+        ]));
+        // files not in the manifest are left out, as usual
+        assert!(!lines.iter().any(|line| line.contains("scratch.tsv")));
+
+        // --remote s3 only plans uploads to the mirror
+        let plan = data
+            .push_plan(path_context, false, &Default::default(), Some("s3"))
+            .await
+            .unwrap();
+        let planned: Vec<(&str, Option<&str>)> = plan
+            .transfers
+            .iter()
+            .map(|t| (t.path.as_str(), t.remote.as_deref()))
+            .collect();
+        assert_eq!(planned, [("data/published.tsv", Some("S3"))]);
+        zenodo_mock.assert_hits(1);
+        let plan = data
+            .push_plan(path_context, false, &Default::default(), None)
+            .await
+            .unwrap();
+        let planned: Vec<(&str, Option<&str>)> = plan
+            .transfers
+            .iter()
+            .map(|t| (t.path.as_str(), t.remote.as_deref()))
+            .collect();
+        assert_eq!(
+            planned,
+            [
+                ("data/working.tsv", Some("Zenodo")),
+                ("data/published.tsv", Some("S3"))
+            ]
+        );
+        assert!(data
+            .push_plan(path_context, false, &Default::default(), Some("figshare"))
+            .await
+            .is_err());
+
+        // a file deleted locally, and missing from Zenodo, is pulled from
+        // the mirror
+        std::fs::remove_file(path_context.join("data/working.tsv")).unwrap();
+        let download_mock = server.mock(|when, then| {
+            when.method(GET).path("/working/project/working.tsv");
+            then.status(200).body("on s3");
+        });
+        let report = data
+            .pull(path_context, false, &FileFilter::default(), 2, None)
+            .await
+            .unwrap();
+        download_mock.assert();
+        let succeeded: Vec<_> = report
+            .files
+            .iter()
+            .filter(|f| f.status == Outcome::Succeeded)
+            .map(|f| (f.path.as_str(), f.remote.as_deref()))
+            .collect();
+        assert_eq!(succeeded, [("data/working.tsv", Some("S3"))]);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/working.tsv")).unwrap(),
+            "on s3"
+        );
+
+        // --remote s3 compares a file with its copy on the mirror
+        std::fs::write(path_context.join("data/both.tsv"), "edited").unwrap();
+        server.mock(|when, then| {
+            when.method(GET).path("/working/project/both.tsv");
+            then.status(200).body("same");
+        });
+        let diff = data
+            .diff_remote(path_context, "data/both.tsv", 1024, Some("s3"))
+            .await
+            .unwrap();
+        let ContentDiff::Text(diff) = diff else {
+            panic!("expected a text diff, not {:?}", diff);
+        };
+        assert!(diff.contains("--- S3/data/both.tsv"), "{}", diff);
+        assert_eq!(data.remotes[&dir].name(), "Zenodo");
+        let err = data
+            .diff_remote(path_context, "data/both.tsv", 1024, Some("figshare"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("named 'figshare'"), "{}", err);
+    }
 }
@@ -0,0 +1,263 @@
+// Per-path options, set in a project's .sdfattributes file.
+//
+// Like .gitattributes, each line is a path pattern followed by the
+// attributes of files matching it:
+//
+//   # extra checksums for alignments
+//   *.bam           algo=sha256
+//   data/raw/**     algo=sha256 block-hashes=1048576
+//
+// Blank lines and lines starting with '#' are ignored. Attributes:
+//  - algo=ALGO[,ALGO...]: checksums recorded in addition to the MD5 (as
+//    with --algos).
+//  - block-hashes=SIZE: record the MD5s of SIZE-byte blocks (as with
+//    --block-hashes).
+//  - compress=gzip|zstd: store the remote copy compressed (see
+//    compression.rs).
+//  - encrypt=aes-256-gcm: store the remote copy encrypted (see
+//    encryption.rs).
+//
+// Patterns: '*' matches anything except '/', '**' matches anything
+// (including '/'), and '?' matches one character except '/'. A pattern
+// without a '/' matches a file name in any directory; otherwise it
+// matches the path from the project root (a leading '/' is optional).
+//
+// Precedence: if several lines set an attribute for a file, the last one
+// wins, and explicit flags to 'sdf add' and 'sdf track' override the
+// attributes entirely.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+
+use crate::lib::checksum::ChecksumAlgo;
+use crate::lib::compression::Compression;
+use crate::lib::encryption::Encryption;
+
+pub const ATTRIBUTES_FILE: &str = ".sdfattributes";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attributes {
+    pub algos: Option<Vec<ChecksumAlgo>>,
+    pub block_size: Option<u64>,
+    pub compression: Option<Compression>,
+    pub encryption: Option<Encryption>,
+}
+
+impl Attributes {
+    // Later attributes override these.
+    fn extend(&mut self, other: &Attributes) {
+        if other.algos.is_some() {
+            self.algos = other.algos.clone();
+        }
+        if other.block_size.is_some() {
+            self.block_size = other.block_size;
+        }
+        if other.compression.is_some() {
+            self.compression = other.compression;
+        }
+        if other.encryption.is_some() {
+            self.encryption = other.encryption;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AttributeRule {
+    pattern: String,
+    attributes: Attributes,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AttributeFile {
+    rules: Vec<AttributeRule>,
+}
+
+fn parse_attribute(attribute: &str, attributes: &mut Attributes) -> Result<()> {
+    let (name, value) = attribute
+        .split_once('=')
+        .ok_or(anyhow!("expected NAME=VALUE, found '{}'", attribute))?;
+    match name {
+        "algo" => {
+            let algos = value
+                .split(',')
+                .map(|algo| {
+                    ChecksumAlgo::from_str(algo, true)
+                        .map_err(|_| anyhow!("unsupported checksum algorithm '{}'", algo))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            attributes.algos = Some(algos);
+        }
+        "block-hashes" => {
+            let block_size = value
+                .parse::<u64>()
+                .ok()
+                .filter(|size| *size > 0)
+                .ok_or(anyhow!("invalid block size '{}'", value))?;
+            attributes.block_size = Some(block_size);
+        }
+        "compress" => {
+            let compression = Compression::from_str(value, true)
+                .map_err(|_| anyhow!("unsupported compression '{}'", value))?;
+            attributes.compression = Some(compression);
+        }
+        "encrypt" => {
+            let encryption = Encryption::from_str(value, true)
+                .map_err(|_| anyhow!("unsupported encryption '{}'", value))?;
+            attributes.encryption = Some(encryption);
+        }
+        _ => {
+            return Err(anyhow!(
+                "unknown attribute '{}' (supported: algo, block-hashes, compress, encrypt)",
+                name
+            ))
+        }
+    }
+    Ok(())
+}
+
+// Match text against a pattern (see the top of this file).
+pub fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // zero or more leading directories
+            glob_match(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            let limit = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=limit).any(|i| glob_match(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => !text.is_empty() && text[0] != '/' && glob_match(rest, &text[1..]),
+        [c, rest @ ..] => !text.is_empty() && text[0] == *c && glob_match(rest, &text[1..]),
+    }
+}
+
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.trim_start_matches('/');
+    let text = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match(&pattern, &text)
+}
+
+impl AttributeFile {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let pattern = fields.next().unwrap_or_default().to_string();
+            let mut attributes = Attributes::default();
+            for attribute in fields {
+                parse_attribute(attribute, &mut attributes).map_err(|err| {
+                    anyhow!("Invalid {} (line {}): {}.", ATTRIBUTES_FILE, i + 1, err)
+                })?;
+            }
+            rules.push(AttributeRule {
+                pattern,
+                attributes,
+            });
+        }
+        Ok(AttributeFile { rules })
+    }
+
+    // Load the project's .sdfattributes (which is optional).
+    pub fn load(path_context: &Path) -> Result<Self> {
+        match fs::read_to_string(path_context.join(ATTRIBUTES_FILE)) {
+            Ok(contents) => Self::parse(&contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(anyhow!("Could not read {}: {}", ATTRIBUTES_FILE, err)),
+        }
+    }
+
+    // The attributes of a manifest path.
+    pub fn for_path(&self, path: &str) -> Attributes {
+        let mut attributes = Attributes::default();
+        for rule in &self.rules {
+            if pattern_matches(&rule.pattern, path) {
+                attributes.extend(&rule.attributes);
+            }
+        }
+        attributes
+    }
+
+    // The checksum algorithms and block size for a file, where explicit
+    // flags (non-empty algos, or a block size) override its attributes.
+    pub fn apply(
+        &self,
+        path: &str,
+        algos: &[ChecksumAlgo],
+        block_size: Option<u64>,
+    ) -> (Vec<ChecksumAlgo>, Option<u64>) {
+        let attributes = self.for_path(path);
+        let algos = if algos.is_empty() {
+            attributes.algos.unwrap_or_default()
+        } else {
+            algos.to_vec()
+        };
+        (algos, block_size.or(attributes.block_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes_for_path() {
+        let attributes = AttributeFile::parse(
+            "# alignments\n\
+             *.bam           algo=sha256\n\
+             data/raw/**     block-hashes=1024\n\
+             *.txt           algo=sha256\n\
+             /data/raw/*.txt algo=md5\n",
+        )
+        .unwrap();
+        let bam = attributes.for_path("data/aligned/sample.bam");
+        assert_eq!(bam.algos, Some(vec![ChecksumAlgo::Sha256]));
+        assert_eq!(bam.block_size, None);
+
+        let raw = attributes.for_path("data/raw/2024/sample.bam");
+        assert_eq!(raw.algos, Some(vec![ChecksumAlgo::Sha256]));
+        assert_eq!(raw.block_size, Some(1024));
+
+        // later lines override earlier ones
+        let notes = attributes.for_path("data/raw/notes.txt");
+        assert_eq!(notes.algos, Some(vec![ChecksumAlgo::Md5]));
+        // '*' does not cross directories
+        let nested = attributes.for_path("data/raw/a/notes.txt");
+        assert_eq!(nested.algos, Some(vec![ChecksumAlgo::Sha256]));
+        assert_eq!(attributes.for_path("results.tsv"), Attributes::default());
+
+        assert!(AttributeFile::parse("*.bam remote=zenodo").is_err());
+        assert!(AttributeFile::parse("*.bam algo=crc32").is_err());
+
+        let compressed =
+            AttributeFile::parse("*.bed compress=gzip\ndata/vcf/** compress=zstd\n").unwrap();
+        let bed = compressed.for_path("data/vcf/peaks.bed");
+        assert_eq!(bed.compression, Some(Compression::Zstd));
+        assert_eq!(
+            compressed.for_path("peaks.bed").compression,
+            Some(Compression::Gzip)
+        );
+        assert!(AttributeFile::parse("*.bed compress=bzip2").is_err());
+
+        let encrypted =
+            AttributeFile::parse("patients/** encrypt=aes-256-gcm compress=gzip\n").unwrap();
+        let tsv = encrypted.for_path("patients/visits.tsv");
+        assert_eq!(tsv.encryption, Some(Encryption::Aes256Gcm));
+        assert_eq!(tsv.compression, Some(Compression::Gzip));
+        assert!(AttributeFile::parse("* encrypt=rot13").is_err());
+    }
+}
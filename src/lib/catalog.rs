@@ -0,0 +1,352 @@
+// Importing file lists from data catalogs.
+//
+// Supported formats:
+//  - Frictionless Data Packages (datapackage.json): each resource with a
+//    local path becomes a manifest entry. Resource paths are relative to
+//    the datapackage.json's directory.
+//  - CSV or TSV file lists ('sdf import'): the header names a 'path'
+//    column and, optionally, 'md5' and 'sha256' columns (other columns are
+//    ignored). Unlike catalog checksums, these are checked against the
+//    files, which must exist. Paths are relative to the list's directory.
+
+use anyhow::{anyhow, Result};
+use csv::ReaderBuilder;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::metadata;
+use std::path::{Component, Path};
+
+use crate::lib::checksum::ChecksumAlgo;
+use crate::lib::data::DataFile;
+use crate::lib::utils::normalize_path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    // the path, relative to the catalog's directory
+    pub path: String,
+    pub checksums: BTreeMap<ChecksumAlgo, String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub entries: Vec<CatalogEntry>,
+    // resources that cannot be imported, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataPackage {
+    title: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    resources: Vec<Resource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resource {
+    name: Option<String>,
+    path: Option<serde_json::Value>,
+    hash: Option<String>,
+    bytes: Option<u64>,
+}
+
+// Parse a Frictionless hash, "ALGORITHM:HEX" (MD5 if there is no prefix).
+fn parse_hash(hash: &str) -> Result<(ChecksumAlgo, String)> {
+    let (algo, hex) = match hash.split_once(':') {
+        Some((algo, hex)) => (algo.to_lowercase(), hex),
+        None => ("md5".to_string(), hash),
+    };
+    let algo = match algo.as_str() {
+        "md5" => ChecksumAlgo::Md5,
+        "sha256" => ChecksumAlgo::Sha256,
+        "blake3" => ChecksumAlgo::Blake3,
+        _ => return Err(anyhow!("unsupported hash algorithm '{}'", algo)),
+    };
+    Ok((algo, hex.to_lowercase()))
+}
+
+// Resource paths must stay within the package (per the specification).
+fn is_safe_path(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+pub fn read_datapackage(json: &str) -> Result<Catalog> {
+    let package: DataPackage = serde_json::from_str(json)
+        .map_err(|err| anyhow!("Could not parse datapackage.json: {}", err))?;
+    let mut catalog = Catalog {
+        title: package.title,
+        description: package.description,
+        ..Default::default()
+    };
+    for (i, resource) in package.resources.into_iter().enumerate() {
+        let label = resource
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("resource {}", i));
+        let path = match resource.path {
+            Some(serde_json::Value::String(path)) => path,
+            Some(serde_json::Value::Array(_)) => {
+                catalog
+                    .skipped
+                    .push((label, "multi-part resources are not supported".to_string()));
+                continue;
+            }
+            _ => {
+                catalog
+                    .skipped
+                    .push((label, "no path (inline data)".to_string()));
+                continue;
+            }
+        };
+        if path.starts_with("http://") || path.starts_with("https://") {
+            catalog.skipped.push((
+                path,
+                "remote resource (use 'sdf get' or 'sdf bulk')".to_string(),
+            ));
+            continue;
+        }
+        if !is_safe_path(&path) {
+            catalog
+                .skipped
+                .push((path, "path is outside the package".to_string()));
+            continue;
+        }
+        let mut checksums = BTreeMap::new();
+        if let Some(hash) = &resource.hash {
+            match parse_hash(hash) {
+                Ok((algo, hex)) => {
+                    checksums.insert(algo, hex);
+                }
+                Err(err) => {
+                    catalog.skipped.push((path, err.to_string()));
+                    continue;
+                }
+            }
+        }
+        catalog.entries.push(CatalogEntry {
+            path: normalize_path(path.trim_start_matches("./")),
+            checksums,
+            size: resource.bytes,
+        });
+    }
+    Ok(catalog)
+}
+
+// Read a file list; rows with an empty path are skipped, as are empty
+// checksum cells.
+pub fn read_file_list(contents: &str, delimiter: u8) -> Result<Catalog> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+    let parse_error = |err: csv::Error| anyhow!("Could not parse the file list: {}", err);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(parse_error)?
+        .iter()
+        .map(|header| header.to_lowercase())
+        .collect();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let path_column = column("path").ok_or(anyhow!("The file list has no 'path' column."))?;
+    let checksum_columns: Vec<(ChecksumAlgo, usize)> = [
+        (ChecksumAlgo::Md5, "md5"),
+        (ChecksumAlgo::Sha256, "sha256"),
+        (ChecksumAlgo::Blake3, "blake3"),
+    ]
+    .into_iter()
+    .filter_map(|(algo, name)| Some((algo, column(name)?)))
+    .collect();
+
+    let mut catalog = Catalog::default();
+    for record in reader.records() {
+        let record = record.map_err(parse_error)?;
+        let path = record.get(path_column).unwrap_or_default();
+        if path.is_empty() {
+            continue;
+        }
+        if !is_safe_path(path) {
+            catalog.skipped.push((
+                path.to_string(),
+                "path is outside the list's directory".to_string(),
+            ));
+            continue;
+        }
+        let checksums = checksum_columns
+            .iter()
+            .filter_map(|(algo, i)| {
+                let checksum = record.get(*i).filter(|checksum| !checksum.is_empty())?;
+                Some((*algo, checksum.to_lowercase()))
+            })
+            .collect();
+        catalog.entries.push(CatalogEntry {
+            path: normalize_path(path.trim_start_matches("./")),
+            checksums,
+            size: None,
+        });
+    }
+    Ok(catalog)
+}
+
+impl CatalogEntry {
+    // The manifest entry for this file, where prefix is the catalog's
+    // directory (relative to the project). Files with an MD5 in the
+    // catalog are not rehashed.
+    pub async fn to_data_file(&self, prefix: &Path, path_context: &Path) -> Result<DataFile> {
+        let path = normalize_path(&prefix.join(&self.path).to_string_lossy());
+        let mut checksums = self.checksums.clone();
+        let Some(md5) = checksums.remove(&ChecksumAlgo::Md5) else {
+            // the MD5 is always needed, so the file has to be hashed
+            let mut data_file = DataFile::new(path, None, path_context).await?;
+            data_file.checksums.extend(checksums);
+            return Ok(data_file);
+        };
+        let size = match metadata(path_context.join(&path)) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => self.size.ok_or(anyhow!(
+                "File '{}' does not exist locally, and the catalog has no size for it.",
+                path
+            ))?,
+        };
+        Ok(DataFile {
+            path,
+            tracked: false,
+            md5,
+            size,
+            url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums,
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+            algo: ChecksumAlgo::Md5,
+        })
+    }
+
+    // Like to_data_file(), but the file is always hashed, and it is an
+    // error if any checksum in the entry does not match.
+    pub async fn verify(&self, prefix: &Path, path_context: &Path) -> Result<DataFile> {
+        let path = normalize_path(&prefix.join(&self.path).to_string_lossy());
+        if !path_context.join(&path).is_file() {
+            return Err(anyhow!("file does not exist"));
+        }
+        let algos: Vec<ChecksumAlgo> = self.checksums.keys().cloned().collect();
+        let data_file = DataFile::new_with_checksums(path, None, path_context, &algos).await?;
+        for (algo, expected) in &self.checksums {
+            let computed = if algo.is_md5() {
+                &data_file.md5
+            } else {
+                &data_file.checksums[algo]
+            };
+            if computed != expected {
+                return Err(anyhow!(
+                    "{} mismatch (the list has {}, but the file's is {})",
+                    format!("{:?}", algo).to_uppercase(),
+                    expected,
+                    computed
+                ));
+            }
+        }
+        Ok(data_file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DATAPACKAGE: &str = r#"{
+        "name": "example",
+        "title": "Example package",
+        "resources": [
+            {"name": "counts", "path": "data/counts.csv", "hash": "md5:0123456789ABCDEF0123456789abcdef", "bytes": 11},
+            {"name": "samples", "path": "./data/samples.csv", "hash": "sha256:ab12"},
+            {"name": "remote", "path": "https://example.com/remote.csv"},
+            {"name": "escape", "path": "../outside.csv"},
+            {"name": "inline", "data": [[1, 2]]}
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn test_import_datapackage() {
+        let catalog = read_datapackage(DATAPACKAGE).unwrap();
+        assert_eq!(catalog.title.as_deref(), Some("Example package"));
+        let paths: Vec<_> = catalog.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["data/counts.csv", "data/samples.csv"]);
+        let skipped: Vec<_> = catalog.skipped.iter().map(|s| s.0.as_str()).collect();
+        assert_eq!(
+            skipped,
+            ["https://example.com/remote.csv", "../outside.csv", "inline"]
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("pkg/data")).unwrap();
+        std::fs::write(path_context.join("pkg/data/samples.csv"), "a,b\n").unwrap();
+        let prefix = Path::new("pkg");
+
+        // the catalog MD5 is used as is: counts.csv is not even local
+        let counts = catalog.entries[0]
+            .to_data_file(prefix, path_context)
+            .await
+            .unwrap();
+        assert_eq!(counts.path, "pkg/data/counts.csv");
+        assert_eq!(counts.md5, "0123456789abcdef0123456789abcdef");
+        assert_eq!(counts.size, 11);
+
+        // without an MD5, the file is hashed (keeping the catalog's SHA256)
+        let samples = catalog.entries[1]
+            .to_data_file(prefix, path_context)
+            .await
+            .unwrap();
+        assert_eq!(samples.md5, format!("{:x}", md5::compute("a,b\n")));
+        assert_eq!(samples.checksums[&ChecksumAlgo::Sha256], "ab12");
+    }
+
+    #[tokio::test]
+    async fn test_read_file_list() {
+        let list = "Path\tMD5\tsha256\tnotes\n\
+            ./data/counts.csv\t0123456789ABCDEF0123456789abcdef\t\tfirst\n\
+            \t\t\tno path\n\
+            data/samples.csv\t\tab12\t\n\
+            /etc/passwd\t\t\t\n";
+        let catalog = read_file_list(list, b'\t').unwrap();
+        let paths: Vec<_> = catalog.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["data/counts.csv", "data/samples.csv"]);
+        assert_eq!(
+            catalog.entries[0].checksums[&ChecksumAlgo::Md5],
+            "0123456789abcdef0123456789abcdef"
+        );
+        assert!(!catalog.entries[1]
+            .checksums
+            .contains_key(&ChecksumAlgo::Md5));
+        assert_eq!(catalog.skipped[0].0, "/etc/passwd");
+
+        let err = read_file_list("file,md5\na.csv,abc\n", b',').unwrap_err();
+        assert!(err.to_string().contains("no 'path' column"));
+
+        // checksums are verified against the file
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("data")).unwrap();
+        std::fs::write(temp_dir.path().join("data/samples.csv"), "a,b\n").unwrap();
+        let err = catalog.entries[1]
+            .verify(Path::new(""), temp_dir.path())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("SHA256 mismatch"));
+        let err = catalog.entries[0]
+            .verify(Path::new(""), temp_dir.path())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "file does not exist");
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use md5::Context;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::lib::storage::io_settings;
+
+// Checksum algorithms a manifest entry can record. MD5 is always
+// computed, since it is what the remotes report.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgo {
+    #[default]
+    Md5,
+    Sha256,
+    Blake3,
+}
+
+impl ChecksumAlgo {
+    pub fn is_md5(&self) -> bool {
+        *self == ChecksumAlgo::Md5
+    }
+}
+
+enum Hasher {
+    Md5(Context),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algo: ChecksumAlgo) -> Self {
+        match algo {
+            ChecksumAlgo::Md5 => Hasher::Md5(Context::new()),
+            ChecksumAlgo::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgo::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn consume(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(context) => context.consume(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Md5(context) => format!("{:x}", context.compute()),
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+// The MD5 of an empty file. Empty files are hashed like any other (None
+// always means a file does not exist), but remotes may report no MD5 for
+// them, and manifests may have recorded none; either way, an empty file
+// is taken to have this one, so it is current wherever it is empty.
+pub const EMPTY_MD5: &str = "d41d8cd98f00b204e9800998ecf8427e";
+
+// Compute the checksums of a file in a single pass, feeding each buffer
+// to every hasher. Returns None if the file does not exist (and an empty
+// file has the checksums of no bytes; see EMPTY_MD5).
+//
+// The result always includes the MD5, whether or not it is in algos.
+// Hashing is blocking I/O, so it runs off the async worker threads, which
+// lets several files be hashed at once (see storage.rs).
+pub async fn compute_checksums(
+    file_path: &Path,
+    algos: &[ChecksumAlgo],
+) -> Result<Option<BTreeMap<ChecksumAlgo, String>>> {
+    let file_path = file_path.to_path_buf();
+    let algos = algos.to_vec();
+    let buffer_size = io_settings().buffer_size;
+    tokio::task::spawn_blocking(move || checksums_of(&file_path, &algos, buffer_size)).await?
+}
+
+// Reads go straight into one buffer of buffer_size (at least 64 KiB,
+// unless set with --buffer-size; see storage.rs), so a BufReader would
+// only add a copy.
+fn checksums_of(
+    file_path: &Path,
+    algos: &[ChecksumAlgo],
+    buffer_size: usize,
+) -> Result<Option<BTreeMap<ChecksumAlgo, String>>> {
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    let mut hashers: BTreeMap<ChecksumAlgo, Hasher> = BTreeMap::new();
+    hashers.insert(ChecksumAlgo::Md5, Hasher::new(ChecksumAlgo::Md5));
+    for algo in algos {
+        hashers.entry(*algo).or_insert_with(|| Hasher::new(*algo));
+    }
+
+    let mut buffer = vec![0; buffer_size.max(1)];
+    loop {
+        let bytes_read = match file.read(&mut buffer) {
+            Ok(0) => break, // EOF
+            Ok(bytes_read) => bytes_read,
+            Err(e) => return Err(anyhow!("I/O reading file: {:?}", e)),
+        };
+        for hasher in hashers.values_mut() {
+            hasher.consume(&buffer[..bytes_read]);
+        }
+    }
+
+    Ok(Some(
+        hashers
+            .into_iter()
+            .map(|(algo, hasher)| (algo, hasher.finish()))
+            .collect(),
+    ))
+}
+
+// MD5s of consecutive fixed-size blocks of a file (the last block may be
+// shorter), for tools that store chunked hashes and for verifying parts
+// of a file. Unlike the content-defined chunks of delta uploads, block
+// boundaries only depend on the block size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockHashes {
+    pub block_size: u64,
+    pub md5s: Vec<String>,
+}
+
+// Compute the block hashes of a file, along with the MD5 of the whole file
+// (in the same pass). Returns None if the file does not exist.
+pub async fn compute_block_hashes(
+    file_path: &Path,
+    block_size: u64,
+) -> Result<Option<(String, BlockHashes)>> {
+    if block_size == 0 {
+        return Err(anyhow!("The block size must be positive."));
+    }
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    let mut whole = Context::new();
+    let mut block = Context::new();
+    let mut block_filled: u64 = 0;
+    let mut md5s = Vec::new();
+    let mut buffer = vec![0; io_settings().buffer_size];
+    loop {
+        let bytes_read = match file.read(&mut buffer) {
+            Ok(0) => break, // EOF
+            Ok(bytes_read) => bytes_read,
+            Err(e) => return Err(anyhow!("I/O reading file: {:?}", e)),
+        };
+        whole.consume(&buffer[..bytes_read]);
+        // a buffer can span several blocks
+        let mut data = &buffer[..bytes_read];
+        while !data.is_empty() {
+            let take = data.len().min((block_size - block_filled) as usize);
+            block.consume(&data[..take]);
+            block_filled += take as u64;
+            data = &data[take..];
+            if block_filled == block_size {
+                let full = std::mem::replace(&mut block, Context::new());
+                md5s.push(format!("{:x}", full.compute()));
+                block_filled = 0;
+            }
+        }
+    }
+    if block_filled > 0 {
+        md5s.push(format!("{:x}", block.compute()));
+    }
+
+    Ok(Some((
+        format!("{:x}", whole.compute()),
+        BlockHashes { block_size, md5s },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::storage::StorageKind;
+    use crate::lib::utils::compute_checksum;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_blake3() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "Mock data.").unwrap();
+        let checksums = compute_checksums(file.path(), &[ChecksumAlgo::Blake3])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            checksums[&ChecksumAlgo::Blake3],
+            blake3::hash(b"Mock data.").to_hex().to_string()
+        );
+        // (the hash of no bytes, from the BLAKE3 test vectors)
+        let empty = NamedTempFile::new().unwrap();
+        assert_eq!(
+            compute_checksum(empty.path(), ChecksumAlgo::Blake3)
+                .await
+                .unwrap()
+                .unwrap(),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_pass_md5_and_sha256() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "Mock data.").unwrap();
+        let checksums = compute_checksums(file.path(), &[ChecksumAlgo::Sha256])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            checksums[&ChecksumAlgo::Md5],
+            format!("{:x}", md5::compute("Mock data."))
+        );
+        assert_eq!(
+            checksums[&ChecksumAlgo::Sha256],
+            format!("{:x}", Sha256::digest("Mock data."))
+        );
+    }
+
+    #[test]
+    fn test_checksums_do_not_depend_on_buffer_size() {
+        let mut file = NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        file.write_all(&data).unwrap();
+        let expected = format!("{:x}", md5::compute(&data));
+        for buffer_size in [1, 1000, 64 * 1024, 1024 * 1024] {
+            let checksums = checksums_of(file.path(), &[], buffer_size)
+                .unwrap()
+                .unwrap();
+            assert_eq!(checksums[&ChecksumAlgo::Md5], expected);
+        }
+    }
+
+    // A benchmark of hashing a 1 GiB file with the default buffer against
+    // 1 KiB reads; run it with 'cargo test --release -- --ignored bench'.
+    #[test]
+    #[ignore]
+    fn bench_buffer_sizes() {
+        let mut file = NamedTempFile::new().unwrap();
+        let block: Vec<u8> = (0..1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        for _ in 0..1024 {
+            file.write_all(&block).unwrap();
+        }
+        file.flush().unwrap();
+        let time = |buffer_size| {
+            let started = std::time::Instant::now();
+            let checksums = checksums_of(file.path(), &[], buffer_size).unwrap();
+            (started.elapsed(), checksums)
+        };
+        // a first pass fills the page cache, so that neither pass waits on
+        // the disk
+        time(1024 * 1024);
+        let default_size = StorageKind::Unknown.defaults().buffer_size;
+        let (small, small_checksums) = time(1024);
+        let (default, default_checksums) = time(default_size);
+        eprintln!(
+            "1 KiB reads: {:.2?}; {} KiB reads: {:.2?}",
+            small,
+            default_size / 1024,
+            default
+        );
+        assert_eq!(small_checksums, default_checksums);
+        assert!(default < small);
+    }
+
+    #[tokio::test]
+    async fn test_sha256_known_vector() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "abc").unwrap();
+        let checksums = compute_checksums(file.path(), &[ChecksumAlgo::Sha256])
+            .await
+            .unwrap()
+            .unwrap();
+        // FIPS 180-2, appendix B.1
+        assert_eq!(
+            checksums[&ChecksumAlgo::Sha256],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_hashes_are_stable() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "Mock data.").unwrap();
+        let (md5, blocks) = compute_block_hashes(file.path(), 4).await.unwrap().unwrap();
+        assert_eq!(md5, format!("{:x}", md5::compute("Mock data.")));
+        // "Mock", " dat", "a."
+        assert_eq!(
+            blocks.md5s,
+            [
+                "2bb15221819b63cb5ac5653baa0ad3d3",
+                "fc65fbe2ecfa78f8e9d6f5ac54f3a515",
+                "9fbcccf456ef61f9ea007c417297911d",
+            ]
+        );
+        for (md5, block) in blocks.md5s.iter().zip(["Mock", " dat", "a."]) {
+            assert_eq!(*md5, format!("{:x}", md5::compute(block)));
+        }
+    }
+}
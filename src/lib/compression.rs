@@ -0,0 +1,152 @@
+// Transparent compression of remote copies, e.g. so that uncompressed
+// text files (BED, VCF, TSV) take less space and bandwidth on their
+// remote. It is opted into per path in .sdfattributes (compress=gzip or
+// compress=zstd), and recorded in a file's manifest entry when it is added
+// or tracked.
+//
+// The local file stays uncompressed, and its manifest entry keeps the MD5
+// and size of the uncompressed file, so local status is unaffected. On
+// push, the file is compressed into a staged file, which is uploaded with
+// the compression's suffix (e.g. 'peaks.bed.gz'), and the MD5 and size of
+// the compressed copy are recorded (see data::StoredCopy). Remote listings
+// report the compressed copy, so while merging, a copy with the recorded
+// MD5 is taken to be the uncompressed file it was made from, and remote
+// status compares uncompressed MD5s as for any other file.
+//
+// On pull, the compressed copy is downloaded like any other file (to a
+// partial file that can be resumed, and that is checked against the
+// compressed MD5 once complete), and only then decompressed, and checked
+// against the uncompressed MD5, before being moved into place.
+//
+// Compression is deterministic (gzip headers have no name or time), so
+// the same file compresses to the same copy, e.g. on each mirror.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    // The suffix of compressed copies' remote names.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).map_err(|err| anyhow!("Could not open '{}': {}", path.display(), err))
+}
+
+fn create(path: &Path) -> Result<File> {
+    File::create(path).map_err(|err| anyhow!("Could not create '{}': {}", path.display(), err))
+}
+
+fn compress_file(source: &Path, destination: &Path, compression: Compression) -> Result<()> {
+    let mut reader = BufReader::new(open(source)?);
+    let writer = BufWriter::new(create(destination)?);
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)?;
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn decompress_file(source: &Path, destination: &Path, compression: Compression) -> Result<()> {
+    let reader = BufReader::new(open(source)?);
+    let mut writer = BufWriter::new(create(destination)?);
+    let copied = match compression {
+        Compression::Gzip => std::io::copy(&mut MultiGzDecoder::new(reader), &mut writer),
+        Compression::Zstd => zstd::Decoder::new(reader)
+            .and_then(|mut decoder| std::io::copy(&mut decoder, &mut writer)),
+    };
+    copied.map_err(|err| {
+        anyhow!(
+            "Could not decompress '{}' ({:?}): {}",
+            source.display(),
+            compression,
+            err
+        )
+    })?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Compress source into destination (off the async runtime's threads).
+pub async fn compress(source: &Path, destination: &Path, compression: Compression) -> Result<()> {
+    let (source, destination): (PathBuf, PathBuf) = (source.into(), destination.into());
+    tokio::task::spawn_blocking(move || compress_file(&source, &destination, compression)).await?
+}
+
+// Decompress source into destination (off the async runtime's threads).
+pub async fn decompress(source: &Path, destination: &Path, compression: Compression) -> Result<()> {
+    let (source, destination): (PathBuf, PathBuf) = (source.into(), destination.into());
+    tokio::task::spawn_blocking(move || decompress_file(&source, &destination, compression)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_round_trip_is_deterministic() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let contents = "chr1\t100\t200\tpeak_1\n".repeat(1000);
+        std::fs::write(dir.join("peaks.bed"), &contents).unwrap();
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = dir.join(format!("peaks.bed{}", compression.suffix()));
+            compress(&dir.join("peaks.bed"), &compressed, compression)
+                .await
+                .unwrap();
+            let bytes = std::fs::read(&compressed).unwrap();
+            assert!(bytes.len() < contents.len() / 10);
+
+            // the same file compresses to the same copy
+            let again = dir.join("again");
+            compress(&dir.join("peaks.bed"), &again, compression)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(&again).unwrap(), bytes);
+
+            let restored = dir.join("restored.bed");
+            decompress(&compressed, &restored, compression)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&restored).unwrap(), contents);
+        }
+        // a gzip file is not zstd
+        let err = decompress(
+            &dir.join("peaks.bed.gz"),
+            &dir.join("bad"),
+            Compression::Zstd,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Could not decompress"));
+    }
+}
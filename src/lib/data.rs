@@ -1,27 +1,262 @@
 use crate::lib::data::serde::{Deserializer, Serializer};
-use crate::lib::download::Downloads;
+use crate::lib::download::{Downloads, Restore};
 use anyhow::{anyhow, Result};
 use chrono::prelude::*;
+use chrono::Duration;
+use clap::ValueEnum;
 use colored::*;
 use futures::future::join_all;
-use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 #[allow(unused_imports)]
 use log::{debug, info, trace};
 use serde;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::fs::metadata;
 use std::path::{Path, PathBuf};
-
-use crate::lib::progress::Progress;
-use crate::lib::remote::{authenticate_remote, Remote, RemoteFile, RemoteStatusCode};
-use crate::lib::utils::{compute_md5, format_mod_time, md5_status, pluralize};
+use tokio::sync::Semaphore;
+
+use crate::lib::checksum::{
+    compute_block_hashes, compute_checksums, BlockHashes, ChecksumAlgo, EMPTY_MD5,
+};
+use crate::lib::compression::{self, Compression};
+use crate::lib::diff::{diff_files, ContentDiff};
+use crate::lib::encryption::{self, Encryption, EncryptionKey};
+use crate::lib::filter::{path_matches, FileFilter, SizeRange};
+use crate::lib::ignore::IgnoreRules;
+use crate::lib::progress::{progress_enabled, FileProgress, Progress, TransferProgress};
+use crate::lib::remote::{authenticate_remote, Remote, RemoteFile, RemoteStatusCode, Upload};
+use crate::lib::remote_cache::RemoteCache;
+use crate::lib::report::{Direction, Outcome, PlannedTransfer, TransferPlan, TransferReport};
+use crate::lib::staging::StagedFile;
+use crate::lib::storage::io_settings;
+use crate::lib::usage::{Operation, RemoteLatency, RemoteUsage};
+use crate::lib::utils::{
+    compute_checksum, compute_md5, format_bytes, format_mod_time, is_project_path, md5_status,
+    normalize_path, pluralize, rehash_checksum, rehash_md5, resolve_normalized,
+};
 use crate::{print_info, print_warn};
 
+// Reasons a push or pull skips a file, shown in the end-of-run report.
+const SKIP_UNTRACKED: &str = "untracked";
+const SKIP_CURRENT: &str = "remote file is identical to local file";
+const SKIP_OVERWRITE: &str = "would overwrite (use --overwrite)";
+const SKIP_MESSY: &str = "manifest and local file disagree (use 'sdf update')";
+const SKIP_EXISTS: &str = "local file exists (use --overwrite)";
+
+// Reasons a push or pull transfers a file, shown by --dry-run.
+const TRANSFER_NOT_ON_REMOTE: &str = "not on remote";
+const TRANSFER_MISSING_LOCALLY: &str = "missing locally";
+const TRANSFER_DIFFERENT: &str = "MD5 differs from remote (--overwrite)";
+const TRANSFER_SIZE_MISMATCH: &str = "size differs from remote (--overwrite)";
+const TRANSFER_NO_REMOTE_MD5: &str = "remote has no MD5 to compare (--overwrite)";
+const TRANSFER_URL_OVERWRITE: &str = "local file exists (--overwrite)";
+
+// What push or pull does with a file, given its remote status. Both
+// the transfers and their dry runs decide this way.
+#[derive(Debug, PartialEq)]
+pub enum TransferAction {
+    Transfer(&'static str),
+    Skip(&'static str),
+    // e.g. remote-only files: nothing to push, and nothing to report
+    Nothing,
+    Invalid,
+}
+
+// Remote files that exist but differ (or cannot be compared) are only
+// replaced with overwrite, in either direction.
+// TODO if remote supports modification times, could do extra
+// comparison here
+fn overwrite_action(status: &RemoteStatusCode, overwrite: bool) -> TransferAction {
+    if !overwrite {
+        return TransferAction::Skip(SKIP_OVERWRITE);
+    }
+    TransferAction::Transfer(match status {
+        RemoteStatusCode::SizeMismatch => TRANSFER_SIZE_MISMATCH,
+        RemoteStatusCode::Exists => TRANSFER_NO_REMOTE_MD5,
+        _ => TRANSFER_DIFFERENT,
+    })
+}
+
+pub fn push_action(status: &RemoteStatusCode, overwrite: bool) -> TransferAction {
+    match status {
+        // A file exists on the remote, but not locally: there
+        // is nothing to push in this case (or count!)
+        RemoteStatusCode::NoLocal => TransferAction::Nothing,
+        RemoteStatusCode::Current => TransferAction::Skip(SKIP_CURRENT),
+        RemoteStatusCode::Exists | RemoteStatusCode::Different | RemoteStatusCode::SizeMismatch => {
+            overwrite_action(status, overwrite)
+        }
+        RemoteStatusCode::MessyLocal => TransferAction::Skip(SKIP_MESSY),
+        RemoteStatusCode::Invalid => TransferAction::Invalid,
+        // there is nothing to upload
+        RemoteStatusCode::DeletedLocal => TransferAction::Skip("deleted locally"),
+        RemoteStatusCode::NotExists => TransferAction::Transfer(TRANSFER_NOT_ON_REMOTE),
+    }
+}
+
+// Like push_action(), for files that are both in the manifest and on the
+// remote (see MergedFile::can_download()).
+pub fn pull_action(status: &RemoteStatusCode, overwrite: bool) -> TransferAction {
+    match status {
+        RemoteStatusCode::NoLocal => TransferAction::Invalid,
+        RemoteStatusCode::Current => TransferAction::Skip(SKIP_CURRENT),
+        RemoteStatusCode::Exists | RemoteStatusCode::Different | RemoteStatusCode::SizeMismatch => {
+            overwrite_action(status, overwrite)
+        }
+        RemoteStatusCode::MessyLocal => TransferAction::Skip(SKIP_MESSY),
+        RemoteStatusCode::Invalid => TransferAction::Invalid,
+        RemoteStatusCode::DeletedLocal | RemoteStatusCode::NotExists => {
+            TransferAction::Transfer(TRANSFER_MISSING_LOCALLY)
+        }
+    }
+}
+
+// A file that push or pull decided to transfer, with what the transfer
+// needs.
+struct QueuedTransfer {
+    planned: PlannedTransfer,
+    // the tracked directory whose remote the file is transferred to or
+    // from (None for URL downloads)
+    tracked_dir: Option<String>,
+    merged_file: MergedFile,
+    // what a download must hash to, if known
+    expected_md5: Option<String>,
+}
+
+fn to_plan(queue: Vec<QueuedTransfer>, report: TransferReport) -> TransferPlan {
+    TransferPlan {
+        transfers: queue.into_iter().map(|queued| queued.planned).collect(),
+        report,
+    }
+}
+
+// Upload a file (see Remote::upload()), or if its remote copy is
+// compressed or encrypted, that copy of it. Returns the upload, for
+// Remote::confirm_uploads(), and the stored copy (if any), which is the
+// remote's once the upload is confirmed.
+async fn upload_file(
+    remote: &Remote,
+    data_file: &DataFile,
+    remote_name: String,
+    path_context: &Path,
+    overwrite: bool,
+    encryption_key: Option<&EncryptionKey>,
+    progress: &FileProgress,
+) -> Result<(Upload, Option<StoredCopy>)> {
+    if data_file.compression.is_none() && data_file.encryption.is_none() {
+        let result = remote
+            .upload(data_file, &remote_name, path_context, overwrite, progress)
+            .await;
+        let upload = Upload {
+            data_file: data_file.clone(),
+            name: remote_name,
+            result,
+        };
+        return Ok((upload, None));
+    }
+    // compressed, then encrypted (each staged until uploaded)
+    let mut stored = data_file.full_path(path_context)?;
+    let mut staged_files = Vec::new();
+    if let Some(compression) = data_file.compression {
+        let staged = StagedFile::new(path_context, "compressed")?;
+        compression::compress(&stored, staged.path(), compression).await?;
+        stored = staged.path().to_path_buf();
+        staged_files.push(staged);
+    }
+    let mut nonce = None;
+    if data_file.encryption.is_some() {
+        let key = EncryptionKey::given_or_env(encryption_key)?;
+        let staged = StagedFile::new(path_context, "encrypted")?;
+        nonce = Some(encryption::encrypt(&stored, staged.path(), &key).await?);
+        stored = staged.path().to_path_buf();
+        staged_files.push(staged);
+    }
+    let md5 = rehash_md5(&stored).await?.ok_or(anyhow!(
+        "The stored copy of '{}' is missing.",
+        data_file.path
+    ))?;
+    let size = fs::metadata(&stored)?.len();
+    // the stored copy, standing in for the file
+    let copy = DataFile {
+        path: stored
+            .strip_prefix(path_context)?
+            .to_string_lossy()
+            .to_string(),
+        md5: md5.clone(),
+        size,
+        checksums: BTreeMap::new(),
+        block_hashes: None,
+        compression: None,
+        encryption: None,
+        stored: None,
+        ..data_file.clone()
+    };
+    let result = remote
+        .upload(&copy, &remote_name, path_context, overwrite, progress)
+        .await;
+    let upload = Upload {
+        data_file: copy,
+        name: remote_name,
+        result,
+    };
+    let stored = StoredCopy {
+        md5,
+        size,
+        source_md5: data_file.md5.clone(),
+        nonce,
+    };
+    Ok((upload, Some(stored)))
+}
+
+// Reported for local files that do not match their manifest MD5.
+const VERIFY_MISMATCH: &str = "MD5 does not match the manifest";
+const VERIFY_MISSING: &str = "missing locally";
+// and for remote copies that do not match the manifest (sdf verify --remotes)
+const VERIFY_REMOTE_MISSING: &str = "missing on the remote";
+const VERIFY_REMOTE_MISMATCH: &str = "remote MD5 does not match the manifest";
+const VERIFY_REMOTE_SIZE: &str = "remote size does not match the manifest";
+const VERIFY_NO_REMOTE_MD5: &str = "the remote reports no MD5 to compare";
+
+// The result of 'sdf verify': a report of each tracked file, and which
+// failed and why.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub report: TransferReport,
+    // files that do not match the manifest
+    pub corrupted: HashSet<String>,
+    pub missing: usize,
+    // files that could not be read (e.g. I/O or permission errors)
+    pub unreadable: usize,
+    // remote copies (of tracked files, on their remotes or mirrors) that
+    // are missing, or do not match the manifest
+    pub remote_missing: usize,
+    pub remote_mismatched: usize,
+}
+
+impl VerifyReport {
+    // The exit code of 'sdf verify': 0 if every file is current, 1 if any
+    // are corrupted or missing (locally or on a remote), and 2 if any
+    // could not be read.
+    pub fn exit_code(&self) -> i32 {
+        if self.unreadable > 0 {
+            2
+        } else if !self.corrupted.is_empty()
+            || self.missing > 0
+            || self.remote_missing > 0
+            || self.remote_mismatched > 0
+        {
+            1
+        } else {
+            0
+        }
+    }
+}
+
 // The status of a local data file, *conditioned* on it being in the manifest.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum LocalStatusCode {
     Current,  // The MD5s between the file and manifest agree
     Modified, // The MD5s disagree
@@ -29,6 +264,28 @@ pub enum LocalStatusCode {
     Invalid,  // Invalid state
 }
 
+impl LocalStatusCode {
+    // How the status is shown by 'sdf status'.
+    pub fn label(&self) -> &'static str {
+        match self {
+            LocalStatusCode::Current => "current",
+            LocalStatusCode::Modified => "changed",
+            LocalStatusCode::Deleted => "deleted",
+            LocalStatusCode::Invalid => "invalid",
+        }
+    }
+}
+
+// The status of a file on one of its directory's mirrors (see
+// DataCollection.mirrors).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MirrorStatus {
+    pub remote: String,
+    pub remote_status: Option<RemoteStatusCode>,
+    pub remote_md5: Option<String>,
+    pub remote_size: Option<u64>,
+}
+
 #[derive(Debug, Clone)]
 pub struct StatusEntry {
     pub name: String,
@@ -39,7 +296,33 @@ pub struct StatusEntry {
     pub local_md5: Option<String>,
     pub remote_md5: Option<String>,
     pub manifest_md5: Option<String>,
+    pub manifest_size: Option<u64>,
+    pub remote_size: Option<u64>,
     pub local_mod_time: Option<DateTime<Utc>>,
+    // the status on each mirror, in the order they were linked
+    pub mirrors: Vec<MirrorStatus>,
+}
+
+// How a remote status is shown by 'sdf status'.
+fn remote_status_message(
+    status: Option<&RemoteStatusCode>,
+    remote_md5: Option<&String>,
+    remote_size: Option<u64>,
+    manifest_md5: Option<&String>,
+    abbrev: Option<i32>,
+) -> String {
+    match status {
+        Some(RemoteStatusCode::Different) => {
+            let remote_md5 = md5_status(remote_md5, manifest_md5, abbrev);
+            format!("different remote version ({:})", remote_md5)
+        }
+        Some(RemoteStatusCode::SizeMismatch) => match remote_size {
+            Some(size) => format!("remote size differs ({} bytes)", size),
+            None => "remote size differs".to_string(),
+        },
+        Some(status) => status.label().to_string(),
+        None => "invalid".to_string(),
+    }
 }
 
 impl StatusEntry {
@@ -50,19 +333,25 @@ impl StatusEntry {
             abbrev,
         ))
     }
-    fn remote_md5_column(&self, abbrev: Option<i32>) -> Result<String> {
-        Ok(md5_status(
-            self.remote_md5.as_ref(),
-            self.manifest_md5.as_ref(),
-            abbrev,
-        ))
-    }
     // StatusEntry.remote_status can be set to None; if so the remote status
     // columns will no be displayed.
     fn include_remotes(&self) -> bool {
         self.remote_service.is_some()
     }
+    // A tracked, current file that some mirror does not have (or has a
+    // different version of).
+    fn mirrors_behind(&self) -> bool {
+        self.tracked == Some(true)
+            && self.local_status == Some(LocalStatusCode::Current)
+            && self
+                .mirrors
+                .iter()
+                .any(|mirror| mirror.remote_status != Some(RemoteStatusCode::Current))
+    }
     pub fn color(&self, line: String) -> String {
+        if self.mirrors_behind() && self.remote_status == Some(RemoteStatusCode::Current) {
+            return line.yellow().to_string();
+        }
         // color is polymorphic on whether remote_status is None.
         let tracked = self.tracked;
         let local_status = &self.local_status;
@@ -92,6 +381,9 @@ impl StatusEntry {
             (Some(true), Some(LocalStatusCode::Current), Some(RemoteStatusCode::Different)) => {
                 line.yellow().to_string()
             }
+            (_, Some(LocalStatusCode::Current), Some(RemoteStatusCode::SizeMismatch)) => {
+                line.magenta().to_string()
+            }
             // untracked, but exists on remote -- invalid
             (Some(false), Some(LocalStatusCode::Current), Some(RemoteStatusCode::Different)) => {
                 line.cyan().to_string()
@@ -105,6 +397,33 @@ impl StatusEntry {
             }
         }
     }
+    // The size of the file, as recorded in the manifest (or reported by
+    // the remote, for files not in the manifest).
+    pub fn size(&self) -> Option<u64> {
+        self.manifest_size.or(self.remote_size)
+    }
+    // How much attention an entry needs, from 0 (all current) up; used
+    // to order directories by their worst entry.
+    pub fn severity(&self) -> u8 {
+        if self.mirrors_behind() && self.remote_status == Some(RemoteStatusCode::Current) {
+            return 1;
+        }
+        match (&self.local_status, &self.remote_status) {
+            (Some(LocalStatusCode::Modified), _) => 3,
+            (Some(LocalStatusCode::Deleted), _) | (Some(LocalStatusCode::Invalid), _) => 2,
+            (_, None) | (_, Some(RemoteStatusCode::Current)) => 0,
+            (_, Some(RemoteStatusCode::NotExists)) if self.tracked != Some(true) => 0,
+            _ => 1,
+        }
+    }
+    // The entry on one line, with its path (for 'sdf status --stream').
+    pub fn line(&self, directory: &str) -> String {
+        let mut columns = self.columns(Some(8));
+        if !directory.is_empty() {
+            columns[0] = format!("{}/{}", directory, self.name);
+        }
+        self.color(columns.join("  "))
+    }
     pub fn columns(&self, abbrev: Option<i32>) -> Vec<String> {
         let local_status = &self.local_status;
 
@@ -115,13 +434,9 @@ impl StatusEntry {
         let mod_time_pretty = self.local_mod_time.map(format_mod_time).unwrap_or_default();
 
         // append a local status message column
-        let local_status_msg = match local_status {
-            Some(LocalStatusCode::Current) => "current",
-            Some(LocalStatusCode::Modified) => "changed",
-            Some(LocalStatusCode::Deleted) => "deleted",
-            Some(LocalStatusCode::Invalid) => "invalid",
-            _ => "no file",
-        };
+        let local_status_msg = local_status
+            .as_ref()
+            .map_or("no file", LocalStatusCode::label);
 
         let tracked = match (self.include_remotes(), self.tracked) {
             (false, _) => "".to_string(),
@@ -137,34 +452,332 @@ impl StatusEntry {
         ];
 
         if self.include_remotes() {
-            let remote_status_msg = match &self.remote_status {
-                Some(RemoteStatusCode::Current) => "identical remote".to_string(),
-                Some(RemoteStatusCode::MessyLocal) => "messy local".to_string(),
-                Some(RemoteStatusCode::Different) => {
-                    let remote_md5 = self
-                        .remote_md5_column(abbrev)
-                        .expect("Internal Error: StatusEntry::remote_md5_column().");
-                    format!("different remote version ({:})", remote_md5)
-                }
-                Some(RemoteStatusCode::NotExists) => "not on remote".to_string(),
-                Some(RemoteStatusCode::NoLocal) => "unknown (messy remote)".to_string(),
-                Some(RemoteStatusCode::Exists) => "exists, no remote MD5".to_string(),
-                Some(RemoteStatusCode::DeletedLocal) => "exists on remote".to_string(),
-                _ => "invalid".to_string(),
-            };
-            columns.push(remote_status_msg.to_string());
+            let remote_status_msg = remote_status_message(
+                self.remote_status.as_ref(),
+                self.remote_md5.as_ref(),
+                self.remote_size,
+                self.manifest_md5.as_ref(),
+                abbrev,
+            );
+            // with mirrors, each remote gets a column, named by the remote
+            match (&self.remote_service, self.mirrors.is_empty()) {
+                (Some(remote), false) => columns.push(format!("{}: {}", remote, remote_status_msg)),
+                _ => columns.push(remote_status_msg),
+            }
+            for mirror in &self.mirrors {
+                let message = remote_status_message(
+                    mirror.remote_status.as_ref(),
+                    mirror.remote_md5.as_ref(),
+                    mirror.remote_size,
+                    self.manifest_md5.as_ref(),
+                    abbrev,
+                );
+                columns.push(format!("{}: {}", mirror.remote, message));
+            }
         }
         columns
     }
 }
 
+// The compressed and/or encrypted copy of a file last pushed to its
+// remote (see compression.rs and encryption.rs).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredCopy {
+    pub md5: String,
+    pub size: u64,
+    // the MD5 of the file it was made from
+    pub source_md5: String,
+    // the nonce prefix it was encrypted with (in hex), if encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DataFile {
     pub path: String,
     pub tracked: bool,
     pub md5: String,
     pub size: u64,
-    pub url: Option<String>, //modified: Option<DateTime<Utc>>,
+    pub url: Option<String>,
+    // The file's modification time when it was last hashed (added or
+    // updated), in UTC (see timestamp.rs).
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::lib::timestamp::option"
+    )]
+    pub modified: Option<DateTime<Utc>>,
+    // The last-known remote state, recorded whenever the remote is
+    // fetched or pushed to; used for offline status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_md5: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_size: Option<u64>,
+    // The name of the remote the last-known state is from, so entries
+    // can be flagged if that remote is removed from the manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    // Checksums from other algorithms (the MD5 is always in md5).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<ChecksumAlgo, String>,
+    // The checksum used to detect local changes. Manifests from before
+    // this was recorded use the MD5; other checksums are in checksums.
+    #[serde(default, skip_serializing_if = "ChecksumAlgo::is_md5")]
+    pub algo: ChecksumAlgo,
+    // MD5s of fixed-size blocks, if requested (e.g. sdf add --block-hashes).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hashes: Option<BlockHashes>,
+    // How the remote copy is compressed, if it is (see compression.rs).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    // How the remote copy is encrypted, if it is (see encryption.rs).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<Encryption>,
+    // The compressed and/or encrypted copy last pushed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stored: Option<StoredCopy>,
+}
+
+// Options for computing the project status.
+#[derive(Debug, Clone, Default)]
+pub struct StatusOptions {
+    pub include_remotes: bool,
+    // use the last-known remote state in the manifest, rather than fetching
+    pub cached_remotes: bool,
+    // the checksum used to detect local changes (if the manifest has it),
+    // rather than each file's own
+    pub algo: Option<ChecksumAlgo>,
+    // only include files whose remote copy is not current
+    pub only_changed_remotes: bool,
+    // report the latency of remote requests (see DataCollection::latency())
+    pub remote_latency: bool,
+    // only report the total size pending upload
+    pub pending_upload_size: bool,
+    // color directory headers by their share of the total size
+    pub tree_size: bool,
+    // the widest a status column is shown (see utils::StatusDisplay)
+    pub max_column_width: Option<usize>,
+    // only include files in this size range
+    pub size: SizeRange,
+    // only report the ahead/behind/conflict counts of each remote
+    pub ahead_behind: bool,
+    // the number of files hashed at once (by default, from --jobs; see
+    // storage.rs)
+    pub jobs: Option<usize>,
+    // reuse remote listings fetched within this many seconds (with
+    // include_remotes), unless refresh is set
+    pub remote_cache_ttl: Option<u64>,
+    pub refresh: bool,
+    // only include files matching any of these (all, if empty)
+    pub filters: Vec<StatusFilter>,
+    // only include these manifest paths (files or directories) or files
+    // matching these glob patterns (all, if empty)
+    pub paths: Vec<String>,
+    // the order of files within each directory
+    pub sort: FileSort,
+    pub reverse: bool,
+    // print each file's status as soon as it is known, rather than a
+    // table of them all at the end (see Project::status())
+    pub stream: bool,
+}
+
+impl StatusOptions {
+    // Whether a status entry is shown, given the --size, --filter and
+    // --only-changed-remotes options.
+    pub fn keeps(&self, entry: &StatusEntry) -> bool {
+        let changed = |status: &Option<RemoteStatusCode>| {
+            status
+                .as_ref()
+                .is_some_and(|status| *status != RemoteStatusCode::Current)
+        };
+        if !self.size.is_empty() && !entry.size().is_some_and(|size| self.size.contains(size)) {
+            return false;
+        }
+        if self.only_changed_remotes
+            && !changed(&entry.remote_status)
+            && !entry
+                .mirrors
+                .iter()
+                .any(|mirror| changed(&mirror.remote_status))
+        {
+            return false;
+        }
+        self.filters.is_empty() || self.filters.iter().any(|filter| filter.matches(entry))
+    }
+
+    // Whether a file at path may be kept (see keeps()), judging only by
+    // its path and manifest entry, so other files are skipped before they
+    // are hashed. Remote-only files are left to keeps().
+    fn selects(&self, path: &str, merged_file: &MergedFile) -> bool {
+        if !self.paths.is_empty()
+            && !self
+                .paths
+                .iter()
+                .any(|filter_path| path_matches(filter_path, path))
+        {
+            return false;
+        }
+        let Some(local) = merged_file.local.as_ref() else {
+            return true;
+        };
+        if !self.size.is_empty() && !self.size.contains(local.size) {
+            return false;
+        }
+        self.filters.is_empty() || self.filters.iter().any(|filter| filter.may_match(local))
+    }
+}
+
+// The order of files within each directory of 'sdf status' (--sort).
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum FileSort {
+    #[default]
+    Name,
+    Size,
+    // the local modification time
+    Mtime,
+}
+
+impl FileSort {
+    // Order entries by this (smallest or oldest first, unless reversed;
+    // unknown sizes and times first); ties are broken by name.
+    pub fn sort(&self, entries: &mut [StatusEntry], reverse: bool) {
+        entries.sort_by(|a, b| {
+            let order = match self {
+                FileSort::Name => a.name.cmp(&b.name),
+                FileSort::Size => a.size().cmp(&b.size()),
+                FileSort::Mtime => a.local_mod_time.cmp(&b.local_mod_time),
+            };
+            let order = if reverse { order.reverse() } else { order };
+            order.then_with(|| a.name.cmp(&b.name))
+        });
+    }
+}
+
+// Which files 'sdf status --filter' shows.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum StatusFilter {
+    Modified,
+    Current,
+    Untracked,
+    // not on the directory's remote (or one of its mirrors)
+    RemoteMissing,
+    // on the remote (or a mirror), with a different MD5
+    Md5Mismatch,
+    // in the manifest, but missing locally
+    Missing,
+}
+
+impl StatusFilter {
+    // Whether the filter needs the remote status.
+    pub fn needs_remotes(&self) -> bool {
+        matches!(
+            self,
+            StatusFilter::RemoteMissing | StatusFilter::Md5Mismatch
+        )
+    }
+
+    // Whether a file in the manifest as local may match, before it is
+    // hashed; remote statuses are not known yet, so always may.
+    fn may_match(&self, local: &DataFile) -> bool {
+        match self {
+            StatusFilter::Untracked => !local.tracked,
+            _ => true,
+        }
+    }
+
+    pub fn matches(&self, entry: &StatusEntry) -> bool {
+        let on_any_remote = |status: RemoteStatusCode| {
+            entry.remote_status.as_ref() == Some(&status)
+                || entry
+                    .mirrors
+                    .iter()
+                    .any(|mirror| mirror.remote_status.as_ref() == Some(&status))
+        };
+        match self {
+            StatusFilter::Modified => entry.local_status == Some(LocalStatusCode::Modified),
+            StatusFilter::Current => entry.local_status == Some(LocalStatusCode::Current),
+            StatusFilter::Untracked => entry.tracked == Some(false),
+            // files outside linked directories cannot be pushed anyway
+            StatusFilter::RemoteMissing => {
+                entry.remote_service.is_some() && on_any_remote(RemoteStatusCode::NotExists)
+            }
+            StatusFilter::Md5Mismatch => on_any_remote(RemoteStatusCode::Different),
+            StatusFilter::Missing => entry.local_status == Some(LocalStatusCode::Deleted),
+        }
+    }
+}
+
+// Everything known about one manifest file, for 'sdf info'.
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub path: String,
+    pub status: StatusEntry,
+    // the remote of its directory, then its mirrors
+    pub remotes: Vec<String>,
+    // whether the remote status was fetched
+    pub include_remotes: bool,
+}
+
+impl FileInfo {
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let status = &self.status;
+        let missing = || "(missing)".to_string();
+        let mut rows = vec![
+            vec!["path".to_string(), self.path.clone()],
+            vec![
+                "tracked".to_string(),
+                status.tracked.unwrap_or(false).to_string(),
+            ],
+            vec![
+                "size".to_string(),
+                status.manifest_size.map(format_bytes).unwrap_or_default(),
+            ],
+            vec![
+                "manifest md5".to_string(),
+                status.manifest_md5.clone().unwrap_or_default(),
+            ],
+            vec![
+                "current md5".to_string(),
+                status.local_md5.clone().unwrap_or_else(missing),
+            ],
+            vec![
+                "modified".to_string(),
+                status
+                    .local_mod_time
+                    .map(format_mod_time)
+                    .unwrap_or_else(missing),
+            ],
+            vec![
+                "local status".to_string(),
+                status
+                    .local_status
+                    .as_ref()
+                    .map_or("unknown", |status| status.label())
+                    .to_string(),
+            ],
+        ];
+        let remotes = if self.remotes.is_empty() {
+            "(none)".to_string()
+        } else {
+            self.remotes.join(", ")
+        };
+        rows.push(vec!["remotes".to_string(), remotes]);
+        let remote_status = match &status.remote_status {
+            _ if self.remotes.is_empty() => "(no remote)".to_string(),
+            Some(remote_status) => remote_status.label().to_string(),
+            None => "(not fetched; use --remotes)".to_string(),
+        };
+        rows.push(vec!["remote status".to_string(), remote_status]);
+        if self.include_remotes {
+            if let Some(remote_md5) = &status.remote_md5 {
+                rows.push(vec!["remote md5".to_string(), remote_md5.clone()]);
+            }
+            if let Some(remote_size) = status.remote_size {
+                rows.push(vec!["remote size".to_string(), format_bytes(remote_size)]);
+            }
+        }
+        rows
+    }
 }
 
 // A merged DataFile and RemoteFile
@@ -236,6 +849,21 @@ impl MergedFile {
         self.remote.as_ref().and_then(|remote| remote.get_md5())
     }
 
+    pub fn remote_size(&self) -> Option<u64> {
+        self.remote.as_ref().and_then(|remote| remote.size)
+    }
+
+    // Some remotes could report a stale MD5 that agrees with the local
+    // file, while the stored object itself differs (e.g. a truncated
+    // re-upload). Returns Some(true) only if both sizes are known and differ.
+    pub fn local_remote_size_mismatch(&self) -> Option<bool> {
+        let local_size = self.local.as_ref().map(|local| local.size);
+        match (local_size, self.remote_size()) {
+            (Some(local), Some(remote)) => Some(local != remote),
+            _ => None,
+        }
+    }
+
     //pub fn local_md5_mismatch(&self, path_context: &PathBuf) -> Option<bool> {
     //}
 
@@ -283,7 +911,15 @@ impl MergedFile {
                 // no local file (so can't get MD5)
                 RemoteStatusCode::NoLocal
             }
-            (Some(LocalStatusCode::Current), Some(false)) => RemoteStatusCode::Current,
+            (Some(LocalStatusCode::Current), Some(false)) => {
+                // The MD5s agree, but we also require the sizes to agree
+                // before trusting the remote as current.
+                if self.local_remote_size_mismatch() == Some(true) {
+                    RemoteStatusCode::SizeMismatch
+                } else {
+                    RemoteStatusCode::Current
+                }
+            }
             (Some(LocalStatusCode::Current), Some(true)) => {
                 // Will pull with --overwrite.
                 // Will push with --overwrite.
@@ -291,8 +927,18 @@ impl MergedFile {
             }
             (Some(LocalStatusCode::Current), None) => {
                 // We can't compare the MD5s, i.e. because remote
-                // does not support them
-                RemoteStatusCode::Exists
+                // does not support them, but can tell a file of another
+                // size is different (unless the remote's is a stored
+                // copy, whose size is its own)
+                let stored = self
+                    .local
+                    .as_ref()
+                    .is_some_and(|local| local.is_stored_as_copy());
+                if !stored && self.local_remote_size_mismatch() == Some(true) {
+                    RemoteStatusCode::Different
+                } else {
+                    RemoteStatusCode::Exists
+                }
             }
             (Some(LocalStatusCode::Modified), _) => {
                 // Messy local -- this will prevent syncing!
@@ -318,10 +964,12 @@ impl MergedFile {
         &self,
         path_context: &Path,
         include_remotes: bool,
+        algo: Option<ChecksumAlgo>,
     ) -> Result<StatusEntry> {
         let tracked = self.local.as_ref().map(|df| df.tracked);
         let local_status = if let Some(local) = self.local.as_ref() {
-            local.status(path_context).await.ok()
+            let algo = algo.unwrap_or(local.algo);
+            Some(local.status_by(path_context, algo).await?)
         } else {
             None
         };
@@ -354,36 +1002,126 @@ impl MergedFile {
             local_md5: self.local_md5(path_context).await,
             remote_md5: self.remote_md5(),
             manifest_md5: self.manifest_md5(),
+            manifest_size: self.local.as_ref().map(|local| local.size),
+            remote_size: self.remote_size(),
             local_mod_time: self.local_mod_time(path_context),
+            mirrors: Vec::new(),
         })
     }
 }
 
 impl DataFile {
     pub async fn new(path: String, url: Option<&str>, path_context: &Path) -> Result<DataFile> {
-        let full_path = path_context.join(&path);
+        DataFile::new_with_checksums(path, url, path_context, &[]).await
+    }
+
+    // Create a DataFile, also recording the checksums of algos (computed
+    // in the same pass over the file as the MD5).
+    pub async fn new_with_checksums(
+        path: String,
+        url: Option<&str>,
+        path_context: &Path,
+        algos: &[ChecksumAlgo],
+    ) -> Result<DataFile> {
+        let full_path = resolve_normalized(path_context, &path);
+        let path = normalize_path(&path);
         if !full_path.exists() {
             return Err(anyhow!("File '{}' does not exist.", path));
         }
-        let md5 = match compute_md5(&full_path).await? {
-            Some(md5) => md5,
+        let mut checksums = match compute_checksums(&full_path, algos).await? {
+            Some(checksums) => checksums,
             None => return Err(anyhow!("Could not compute MD5 as file does not exist")),
         };
-        let size = metadata(full_path)
-            .map_err(|err| anyhow!("Failed to get metadata for file {:?}: {}", path, err))?
-            .len();
+        let md5 = checksums
+            .remove(&ChecksumAlgo::Md5)
+            .ok_or(anyhow!("Internal error: MD5 was not computed."))?;
+        let metadata = metadata(full_path)
+            .map_err(|err| anyhow!("Failed to get metadata for file {:?}: {}", path, err))?;
         let maybe_url: Option<String> = url.map(|s| s.to_string());
         Ok(DataFile {
             path,
             tracked: false,
             md5,
-            size,
+            size: metadata.len(),
             url: maybe_url,
+            modified: metadata.modified().ok().map(DateTime::from),
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums,
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+            algo: ChecksumAlgo::Md5,
         })
     }
 
+    pub fn cache_remote_state(&mut self, remote_file: &RemoteFile) {
+        self.remote_md5 = remote_file.get_md5();
+        self.remote_size = remote_file.size;
+        self.remote = Some(remote_file.remote_service.clone());
+    }
+
+    // Whether the remote copy is compressed or encrypted.
+    pub fn is_stored_as_copy(&self) -> bool {
+        self.compression.is_some() || self.encryption.is_some()
+    }
+
+    // The suffix of the file's remote name, if its remote copy is
+    // compressed or encrypted (e.g. '.gz.enc').
+    pub fn remote_suffix(&self) -> String {
+        let compression = self
+            .compression
+            .map_or("", |compression| compression.suffix());
+        let encryption = self.encryption.map_or("", |encryption| encryption.suffix());
+        format!("{}{}", compression, encryption)
+    }
+
+    // A remote copy of a compressed or encrypted file, as the file it was
+    // made from: if it is the copy last pushed (matched by MD5, or by size
+    // if the remote reports no MD5), it has the MD5 and size of that file.
+    pub fn restored_remote(&self, remote_file: &mut RemoteFile) {
+        let Some(copy) = self.stored.as_ref().filter(|_| self.is_stored_as_copy()) else {
+            return;
+        };
+        let remote_md5 = remote_file.get_md5();
+        let is_copy = match &remote_md5 {
+            Some(md5) => *md5 == copy.md5,
+            None => remote_file.size == Some(copy.size),
+        };
+        if !is_copy {
+            return;
+        }
+        if remote_md5.is_some() {
+            remote_file.md5 = Some(copy.source_md5.clone());
+        }
+        remote_file.size = (copy.source_md5 == self.md5).then_some(self.size);
+    }
+
+    // Forget the last-known remote state.
+    pub fn clear_remote_state(&mut self) {
+        self.remote_md5 = None;
+        self.remote_size = None;
+        self.remote = None;
+    }
+
+    // A RemoteFile from the last-known remote state, if there is one.
+    pub fn cached_remote_file(&self, remote_service: &str) -> Result<Option<RemoteFile>> {
+        if self.remote_md5.is_none() && self.remote_size.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(RemoteFile {
+            name: self.basename()?,
+            md5: self.remote_md5.clone(),
+            size: self.remote_size,
+            remote_service: remote_service.to_string(),
+            url: None,
+        }))
+    }
+
     pub fn full_path(&self, path_context: &Path) -> Result<PathBuf> {
-        Ok(path_context.join(self.path.clone()))
+        Ok(resolve_normalized(path_context, &self.path))
     }
 
     pub fn basename(&self) -> Result<String> {
@@ -423,20 +1161,82 @@ impl DataFile {
     }
 
     pub fn is_alive(&self, path_context: &Path) -> bool {
-        path_context.join(&self.path).exists()
+        resolve_normalized(path_context, &self.path).exists()
+    }
+
+    // The recorded MD5, which for an empty file recorded without one (e.g.
+    // in a hand-written manifest) is EMPTY_MD5, so that the file is
+    // current while it stays empty.
+    pub fn recorded_md5(&self) -> &str {
+        if self.md5.is_empty() && self.size == 0 {
+            EMPTY_MD5
+        } else {
+            &self.md5
+        }
     }
 
     // Returns true if the file does not exist.
     pub async fn is_changed(&self, path_context: &Path) -> Result<bool> {
         match self.get_md5(path_context).await? {
-            Some(new_md5) => Ok(new_md5 != self.md5),
+            Some(new_md5) => Ok(new_md5 != self.recorded_md5()),
             None => Ok(true),
         }
     }
 
+    // The stored checksum of algo, if the manifest has it.
+    pub fn checksum(&self, algo: ChecksumAlgo) -> Option<&str> {
+        if algo.is_md5() {
+            Some(self.recorded_md5())
+        } else {
+            self.checksums.get(&algo).map(|checksum| checksum.as_str())
+        }
+    }
+
+    // Like is_changed(), but compares with the stored checksum of algo, if
+    // the manifest has it (otherwise, the MD5 is used).
+    pub async fn is_changed_by(&self, path_context: &Path, algo: ChecksumAlgo) -> Result<bool> {
+        let (algo, stored) = match self.checksum(algo) {
+            Some(stored) => (algo, stored),
+            None => (ChecksumAlgo::Md5, self.recorded_md5()),
+        };
+        match compute_checksum(&self.full_path(path_context)?, algo).await? {
+            Some(checksum) => Ok(checksum != stored),
+            None => Ok(true),
+        }
+    }
+
+    // The local status, by the file's own checksum.
     pub async fn status(&self, path_context: &Path) -> Result<LocalStatusCode> {
+        self.status_by(path_context, self.algo).await
+    }
+
+    // Like status(), but always rehashes the file: corruption (e.g. bit
+    // rot) need not change its size or modification time, so the MD5
+    // cache cannot be trusted. Each file is checked by its own checksum, so
+    // a manifest can mix algorithms.
+    pub async fn verify(&self, path_context: &Path) -> Result<LocalStatusCode> {
+        let full_path = self.full_path(path_context)?;
+        let (checksum, stored) = match self.checksum(self.algo) {
+            Some(stored) if !self.algo.is_md5() => {
+                (rehash_checksum(&full_path, self.algo).await?, stored)
+            }
+            _ => (rehash_md5(&full_path).await?, self.recorded_md5()),
+        };
+        let status = match checksum {
+            Some(checksum) if checksum == stored => LocalStatusCode::Current,
+            Some(_) => LocalStatusCode::Modified,
+            None => LocalStatusCode::Deleted,
+        };
+        Ok(status)
+    }
+
+    pub async fn status_by(
+        &self,
+        path_context: &Path,
+        algo: ChecksumAlgo,
+    ) -> Result<LocalStatusCode> {
         let is_alive = self.is_alive(path_context);
-        let is_changed = self.is_changed(path_context).await?;
+        let is_changed = self.is_changed_by(path_context, algo).await?;
         let local_status = match (is_changed, is_alive) {
             (false, true) => LocalStatusCode::Current,
             (true, true) => LocalStatusCode::Modified,
@@ -449,9 +1249,75 @@ impl DataFile {
         Ok(local_status)
     }
 
+    // Rehash the file (the MD5 and any other stored checksums, in one
+    // pass), and update its size.
     pub async fn update(&mut self, path_context: &Path) -> Result<()> {
-        self.update_md5(path_context).await?;
+        let algos: Vec<ChecksumAlgo> = self.checksums.keys().cloned().collect();
+        self.update_checksums(path_context, &algos).await?;
         self.update_size(path_context)?;
+        self.modified = self.get_mod_time(path_context).ok();
+        if let Some(block_hashes) = &self.block_hashes {
+            let block_size = block_hashes.block_size;
+            self.block_hashes = None;
+            self.add_block_hashes(path_context, block_size).await?;
+        }
+        Ok(())
+    }
+
+    // Record the MD5s of block_size blocks of an unmodified file.
+    pub async fn add_block_hashes(&mut self, path_context: &Path, block_size: u64) -> Result<()> {
+        let (md5, block_hashes) =
+            match compute_block_hashes(&self.full_path(path_context)?, block_size).await? {
+                Some(hashes) => hashes,
+                None => return Err(anyhow!("File '{}' does not exist.", self.path)),
+            };
+        if md5 != self.md5 {
+            return Err(anyhow!(
+                "File '{}' has changed since it was added; use 'sdf update {}' first.",
+                self.path,
+                self.path
+            ));
+        }
+        self.block_hashes = Some(block_hashes);
+        Ok(())
+    }
+
+    // Record the checksums of algos for an unmodified file; a modified
+    // file must be updated first, so the checksums agree with the MD5.
+    pub async fn add_checksums(
+        &mut self,
+        path_context: &Path,
+        algos: &[ChecksumAlgo],
+    ) -> Result<()> {
+        let mut checksums = match compute_checksums(&self.full_path(path_context)?, algos).await? {
+            Some(checksums) => checksums,
+            None => return Err(anyhow!("File '{}' does not exist.", self.path)),
+        };
+        if checksums.remove(&ChecksumAlgo::Md5).as_ref() != Some(&self.md5) {
+            return Err(anyhow!(
+                "File '{}' has changed since it was added; use 'sdf update {}' first.",
+                self.path,
+                self.path
+            ));
+        }
+        self.checksums.extend(checksums);
+        Ok(())
+    }
+
+    // Compute and store the checksums of algos (and the MD5).
+    pub async fn update_checksums(
+        &mut self,
+        path_context: &Path,
+        algos: &[ChecksumAlgo],
+    ) -> Result<()> {
+        let mut checksums = match compute_checksums(&self.full_path(path_context)?, algos).await? {
+            Some(checksums) => checksums,
+            None => return Err(anyhow!("Cannot update checksums: file does not exist")),
+        };
+        if let Some(md5) = checksums.remove(&ChecksumAlgo::Md5) {
+            self.md5 = md5;
+        }
+        self.checksums.extend(checksums);
         Ok(())
     }
 
@@ -497,6 +1363,121 @@ impl DataFile {
 pub struct DataCollectionMetadata {
     pub title: Option<String>,
     pub description: Option<String>,
+    // record-level metadata of the project's deposits (Zenodo depositions
+    // and FigShare articles), and of particular directories' deposits
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub creators: Vec<Creator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub directories: BTreeMap<String, DepositMetadata>,
+}
+
+impl DataCollectionMetadata {
+    // The metadata of a directory's deposit: its own, else the project's.
+    // The title is only its own, since the project's is already the
+    // default remote name (see Project::name()).
+    pub fn deposit(&self, dir: &str) -> DepositMetadata {
+        fn or_project<T: Clone>(own: Vec<T>, project: &[T]) -> Vec<T> {
+            if own.is_empty() {
+                project.to_vec()
+            } else {
+                own
+            }
+        }
+        let own = self.directories.get(dir).cloned().unwrap_or_default();
+        DepositMetadata {
+            title: own.title,
+            description: own.description.or(self.description.clone()),
+            creators: or_project(own.creators, &self.creators),
+            license: own.license.or(self.license.clone()),
+            keywords: or_project(own.keywords, &self.keywords),
+        }
+    }
+}
+
+// Record-level metadata of a deposit, as set in the manifest. Unset
+// fields are left as they are on the remote.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
+pub struct DepositMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub creators: Vec<Creator>,
+    // e.g. 'cc-by-4.0'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+impl DepositMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == DepositMetadata::default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Creator {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affiliation: Option<String>,
+    // e.g. '0000-0002-1825-0097'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orcid: Option<String>,
+}
+
+// How a tracked file compares with its remote copy, relative to the last
+// sync (its last-known remote MD5, recorded whenever the remote state is
+// fetched and saved, e.g. by push, pull, and 'status --remotes').
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    InSync,
+    // local changes not on the remote (to push)
+    Ahead,
+    // remote changes since the last sync (to pull)
+    Behind,
+    // both changed since the last sync (or there is no last sync)
+    Conflict,
+}
+
+pub fn sync_state(
+    local: Option<&str>,
+    remote: Option<&str>,
+    last_synced: Option<&str>,
+) -> SyncState {
+    match (local, remote) {
+        (None, None) => SyncState::InSync,
+        (Some(_), None) => SyncState::Ahead,
+        (None, Some(_)) => SyncState::Behind,
+        (Some(local), Some(remote)) if local == remote => SyncState::InSync,
+        (Some(local), Some(remote)) => match last_synced {
+            Some(synced) if synced == remote => SyncState::Ahead,
+            Some(synced) if synced == local => SyncState::Behind,
+            _ => SyncState::Conflict,
+        },
+    }
+}
+
+// The sync states of a tracked directory's files, like git's ahead/behind.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SyncSummary {
+    pub directory: String,
+    pub remote: String,
+    pub ahead: usize,
+    pub behind: usize,
+    pub conflict: usize,
+}
+
+// Where the contents written by DataCollection::cat() came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatSource {
+    Local,
+    Remote(String),
 }
 
 /// DataCollection structure for managing the data manifest
@@ -505,16 +1486,33 @@ pub struct DataCollectionMetadata {
 pub struct DataCollection {
     pub files: HashMap<String, DataFile>,
     pub remotes: HashMap<String, Remote>, // key is tracked directory
+    // further remotes a tracked directory is mirrored to, pushed to and
+    // pulled from after its remote (the last-known remote state in the
+    // manifest is always its remote's)
+    pub mirrors: HashMap<String, Vec<Remote>>,
     pub metadata: DataCollectionMetadata,
+    // the key stored copies are encrypted and decrypted with (if not set,
+    // it is read from the environment; see EncryptionKey::from_env())
+    pub encryption_key: Option<EncryptionKey>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct MinimalDataCollection {
     pub files: Vec<DataFile>,
     pub remotes: HashMap<String, Remote>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub mirrors: HashMap<String, Vec<Remote>>,
     pub metadata: DataCollectionMetadata,
 }
 
+// The remotes swapped out for a pass over mirrors (see
+// DataCollection::begin_pass()), and the files whose last-known remote
+// state the pass may overwrite.
+struct SavedRemotes {
+    remotes: HashMap<String, Remote>,
+    files: Vec<DataFile>,
+}
+
 impl serde::Serialize for DataCollection {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -527,6 +1525,7 @@ impl serde::Serialize for DataCollection {
         let to_serialize = MinimalDataCollection {
             files: sorted_files,
             remotes: self.remotes.clone(),
+            mirrors: self.mirrors.clone(),
             metadata: self.metadata.clone(),
         };
 
@@ -542,17 +1541,22 @@ impl<'de> serde::Deserialize<'de> for DataCollection {
         // Deserialize into a temporary struct
         let temp = MinimalDataCollection::deserialize(deserializer)?;
 
-        // Build the HashMap for files based on the path
+        // Build the HashMap for files based on the (normalized) path
         let files = temp
             .files
             .into_iter()
-            .map(|df| (df.path.clone(), df))
+            .map(|mut df| {
+                df.path = normalize_path(&df.path);
+                (df.path.clone(), df)
+            })
             .collect();
 
         Ok(DataCollection {
             files,
             remotes: temp.remotes,
+            mirrors: temp.mirrors,
             metadata: temp.metadata,
+            encryption_key: None,
         })
     }
 }
@@ -564,7 +1568,9 @@ impl DataCollection {
         Self {
             files: HashMap::new(),
             remotes: HashMap::new(),
+            mirrors: HashMap::new(),
             metadata: DataCollectionMetadata::default(),
+            encryption_key: None,
         }
     }
 
@@ -580,6 +1586,15 @@ impl DataCollection {
     }
 
     pub fn register(&mut self, data_file: DataFile) -> Result<()> {
+        // manifest paths are relative to the project, with no '.' or '..'
+        // segments (see utils::project_path())
+        if !is_project_path(&data_file.path) {
+            return Err(anyhow!(
+                "Path '{}' is not relative to the project directory, so cannot be \
+                 registered in the data manifest.",
+                data_file.path
+            ));
+        }
         let path = data_file.path.clone();
         if let std::collections::hash_map::Entry::Vacant(e) = self.files.entry(path) {
             e.insert(data_file);
@@ -594,12 +1609,11 @@ impl DataCollection {
     }
 
     pub async fn contains(&self, filename: &str) -> Result<bool> {
-        Ok(self.files.contains_key(filename))
+        Ok(self.files.contains_key(&normalize_path(filename)))
     }
 
     pub async fn remove(&mut self, filename: &str) -> bool {
-        if self.files.contains_key(filename) {
-            self.files.remove(filename);
+        if self.files.remove(&normalize_path(filename)).is_some() {
             true
         } else {
             println!(
@@ -613,7 +1627,7 @@ impl DataCollection {
     pub async fn update(&mut self, filename: Option<&String>, path_context: &Path) -> Result<()> {
         match filename {
             Some(file) => {
-                if let Some(data_file) = self.files.get_mut(file) {
+                if let Some(data_file) = self.files.get_mut(&normalize_path(file)) {
                     data_file.update(path_context).await?;
                     debug!("rehashed file {:?}", data_file.path);
                 } else {
@@ -681,34 +1695,360 @@ impl DataCollection {
         }
     }
 
-    // Register the remote
+    // Manifest entries whose last-known remote state is from a remote that
+    // no longer covers them (e.g. it was removed from the manifest).
     //
-    // This can overwrite existing entries.
-    pub fn register_remote(&mut self, dir: &String, remote: Remote) -> Result<()> {
-        self.validate_remote_directory(dir)?;
-        self.remotes.insert(dir.to_string(), remote);
-        Ok(())
-    }
-
-    pub fn get_remote(&mut self, dir: &String) -> Result<&Remote> {
-        match self.remotes.get(dir) {
-            Some(remote) => Ok(remote),
-            None => Err(anyhow!("No such remote")),
+    // Returns: remote name -> [paths, ...]
+    pub fn dangling_remotes(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut dangling: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for data_file in self.files.values() {
+            let Some(remote) = &data_file.remote else {
+                continue;
+            };
+            if self.get_this_files_remote(data_file)?.as_ref() != Some(remote) {
+                dangling
+                    .entry(remote.clone())
+                    .or_default()
+                    .push(data_file.path.clone());
+            }
         }
+        for paths in dangling.values_mut() {
+            paths.sort();
+        }
+        Ok(dangling)
     }
-    pub fn track_file(&mut self, filepath: &String, path_context: &Path) -> Result<()> {
+
+    pub fn unknown_remote_warnings(&self) -> Result<Vec<String>> {
+        Ok(self
+            .dangling_remotes()?
+            .iter()
+            .map(|(remote, paths)| {
+                format!(
+                    "unknown remote: {} (referenced by {}; use 'sdf remote prune' to clear).",
+                    remote,
+                    pluralize(paths.len() as u64, "file")
+                )
+            })
+            .collect())
+    }
+
+    // Clear the remote state of entries referencing unknown remotes.
+    // Returns the number of entries cleared.
+    pub fn prune_dangling_remotes(&mut self) -> Result<usize> {
+        let dangling: Vec<String> = self.dangling_remotes()?.into_values().flatten().collect();
+        for path in &dangling {
+            if let Some(data_file) = self.files.get_mut(path) {
+                data_file.clear_remote_state();
+            }
+        }
+        Ok(dangling.len())
+    }
+
+    // Map manifest paths under tracked_dir to the names of their copies on
+    // its remote (remote name -> manifest path): their names under the
+    // remote's path style, with the suffix of their compression (if any).
+    fn remote_names<'a>(
+        &self,
+        tracked_dir: &str,
+        paths: impl Iterator<Item = &'a String>,
+    ) -> Result<HashMap<String, String>> {
+        let path_style = self.remotes[tracked_dir].path_style();
+        let mut names = HashMap::new();
+        for (name, path) in path_style.remote_names(tracked_dir, paths)? {
+            let suffix = self
+                .files
+                .get(&path)
+                .map(|file| file.remote_suffix())
+                .unwrap_or_default();
+            let name = format!("{}{}", name, suffix);
+            if let Some(other) = names.insert(name.clone(), path.clone()) {
+                return Err(anyhow!(
+                    "Files '{}' and '{}' both map to the remote file '{}'; rename one of them.",
+                    other,
+                    path,
+                    name
+                ));
+            }
+        }
+        Ok(names)
+    }
+
+    // The tracked directory (i.e. with a linked remote) that contains dir.
+    pub fn tracked_dir_of(&self, dir: &str) -> Option<&String> {
+        self.remotes
+            .keys()
+            .find(|tracked_dir| Path::new(dir).starts_with(tracked_dir))
+    }
+
+    // Register the remote
+    //
+    // This can overwrite existing entries.
+    pub fn register_remote(&mut self, dir: &String, remote: Remote) -> Result<()> {
+        self.validate_remote_directory(dir)?;
+        self.remotes.insert(dir.to_string(), remote);
+        Ok(())
+    }
+
+    // Add the files of a directory's remote that are not in the manifest,
+    // as tracked files with the remote's MD5s and sizes, so they can be
+    // pulled (e.g. those of a published record; see 'sdf link --read-only').
+    //
+    // Returns the number of files added.
+    pub async fn register_remote_files(&mut self, dir: &String) -> Result<usize> {
+        let remote = self.get_remote(dir)?;
+        let (path_style, remote_name) = (remote.path_style(), remote.name().to_string());
+        let mut added = 0;
+        for remote_file in remote.get_files().await? {
+            let path = normalize_path(
+                &PathBuf::from(dir)
+                    .join(path_style.decode(&remote_file.name))
+                    .to_string_lossy(),
+            );
+            if self.files.contains_key(&path) {
+                continue;
+            }
+            let (Some(md5), Some(size)) = (remote_file.get_md5(), remote_file.size) else {
+                print_warn!(
+                    "{} does not report the MD5 and size of '{}', so it is not added.",
+                    remote_name,
+                    remote_file.name
+                );
+                continue;
+            };
+            self.register(DataFile {
+                path,
+                tracked: true,
+                md5: md5.clone(),
+                size,
+                url: None,
+                modified: None,
+                remote_md5: Some(md5),
+                remote_size: Some(size),
+                remote: Some(remote_name.clone()),
+                checksums: BTreeMap::new(),
+                algo: ChecksumAlgo::Md5,
+                block_hashes: None,
+                compression: None,
+                encryption: None,
+                stored: None,
+            })?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    pub fn get_remote(&mut self, dir: &String) -> Result<&Remote> {
+        match self.remotes.get(dir) {
+            Some(remote) => Ok(remote),
+            None => Err(anyhow!("No such remote")),
+        }
+    }
+
+    // Validate the directory as one that can be mirrored, i.e. it is
+    // already linked to a remote.
+    pub fn validate_mirror_directory(&self, dir: &String) -> Result<()> {
+        if !self.remotes.contains_key(dir) {
+            return Err(anyhow!(
+                "Directory '{}' is not linked to a remote, so it cannot be mirrored \
+                 (link it without --mirror first).",
+                dir
+            ));
+        }
+        Ok(())
+    }
+
+    // Register a mirror of a directory already linked to a remote.
+    pub fn register_mirror(&mut self, dir: &String, remote: Remote) -> Result<()> {
+        self.validate_mirror_directory(dir)?;
+        if self.remotes_of(dir).contains(&&remote) {
+            return Err(anyhow!(
+                "Directory '{}' is already linked to this {} remote.",
+                dir,
+                remote.name()
+            ));
+        }
+        self.mirrors.entry(dir.clone()).or_default().push(remote);
+        Ok(())
+    }
+
+    // The remotes of a tracked directory: its remote, then its mirrors.
+    pub fn remotes_of(&self, dir: &str) -> Vec<&Remote> {
+        let mirrors = self.mirrors.get(dir).into_iter().flatten();
+        self.remotes.get(dir).into_iter().chain(mirrors).collect()
+    }
+
+    // What is known about the manifest file path, including its status
+    // (as in status()), computed for it alone: with include_remotes, only
+    // its directory's remote is fetched (not its mirrors).
+    pub async fn file_info(
+        &mut self,
+        path_context: &Path,
+        path: &str,
+        include_remotes: bool,
+    ) -> Result<FileInfo> {
+        let data_file = self
+            .files
+            .get(path)
+            .ok_or(anyhow!("File '{}' is not in the manifest.", path))?;
+        let remotes = match self.tracked_dir_of(&data_file.directory()?) {
+            Some(dir) => self
+                .remotes_of(dir)
+                .iter()
+                .map(|remote| remote.name().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let include_remotes = include_remotes && !remotes.is_empty();
+        let candidates = HashSet::from([path.to_string()]);
+        let merged = self.merge(include_remotes, Some(&candidates)).await?;
+        let merged_file = merged
+            .values()
+            .find_map(|files| files.get(path))
+            .ok_or(anyhow!("File '{}' is not in the manifest.", path))?;
+        Ok(FileInfo {
+            path: path.to_string(),
+            status: merged_file
+                .status_entry(path_context, include_remotes, None)
+                .await?,
+            remotes,
+            include_remotes,
+        })
+    }
+
+    // The remotes of each tracked directory (e.g. for 'sdf status').
+    pub fn linked_remotes(&self) -> HashMap<String, Vec<&Remote>> {
+        self.remotes
+            .keys()
+            .map(|dir| (dir.clone(), self.remotes_of(dir)))
+            .collect()
+    }
+
+    // The remotes of each pass that push, pull, and status make over the
+    // tracked directories: the first pass is to each directory's remote,
+    // and the i-th to its i-th mirror. With only, each directory's remotes
+    // are first narrowed to those named only (e.g. 'zenodo' or 's3'), so
+    // 'sdf push --remote s3' makes one pass, to the directories on S3.
+    fn remote_passes(&self, only: Option<&str>) -> Result<Vec<HashMap<String, Remote>>> {
+        let mut passes: Vec<HashMap<String, Remote>> = Vec::new();
+        for dir in self.remotes.keys() {
+            let remotes = self
+                .remotes_of(dir)
+                .into_iter()
+                .filter(|remote| only.is_none_or(|name| remote.matches(name)));
+            for (i, remote) in remotes.enumerate() {
+                if passes.len() == i {
+                    passes.push(HashMap::new());
+                }
+                passes[i].insert(dir.clone(), remote.clone());
+            }
+        }
+        match only {
+            Some(name) if passes.is_empty() => Err(anyhow!(
+                "No directory is linked to a remote named '{}'.",
+                name
+            )),
+            // without remotes, one pass still covers the local files
+            None if passes.is_empty() => Ok(vec![HashMap::new()]),
+            _ => Ok(passes),
+        }
+    }
+
+    // Swap in the remotes of a pass (see remote_passes()), until
+    // end_pass(). Fetches in the pass record the last-known remote state
+    // as usual; for the files of directories on a mirror, it is put back
+    // by end_pass(), so the manifest keeps that of their own remote.
+    fn begin_pass(&mut self, remotes: HashMap<String, Remote>) -> SavedRemotes {
+        let mirrored: HashSet<String> = remotes
+            .iter()
+            .filter(|(dir, remote)| self.remotes.get(*dir) != Some(remote))
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        let files = self
+            .files_in(&mirrored)
+            .into_iter()
+            .map(|path| self.files[&path].clone())
+            .collect();
+        SavedRemotes {
+            remotes: std::mem::replace(&mut self.remotes, remotes),
+            files,
+        }
+    }
+
+    fn end_pass(&mut self, saved: SavedRemotes) {
+        self.remotes = saved.remotes;
+        for saved_file in saved.files {
+            if let Some(data_file) = self.files.get_mut(&saved_file.path) {
+                data_file.remote_md5 = saved_file.remote_md5;
+                data_file.remote_size = saved_file.remote_size;
+                data_file.remote = saved_file.remote;
+            }
+        }
+    }
+
+    // Keep the changes a pass made to its remotes (e.g. a new Zenodo
+    // deposition ID, see Remote::prepare_push()), in the remote or mirror
+    // each was a copy of.
+    fn keep_pass_changes(
+        &mut self,
+        before: &HashMap<String, Remote>,
+        after: HashMap<String, Remote>,
+    ) {
+        for (dir, remote) in after {
+            let old = match before.get(&dir) {
+                Some(old) if *old != remote => old,
+                _ => continue,
+            };
+            if self.remotes.get(&dir) == Some(old) {
+                self.remotes.insert(dir, remote);
+            } else if let Some(mirror) = self
+                .mirrors
+                .get_mut(&dir)
+                .and_then(|mirrors| mirrors.iter_mut().find(|mirror| *mirror == old))
+            {
+                *mirror = remote;
+            }
+        }
+    }
+
+    // The name of the (current) remote of a file, by its path.
+    fn remote_name_of(&self, path: &str) -> Option<String> {
+        let dir = Path::new(path).parent()?.to_string_lossy().to_string();
+        let tracked_dir = self.tracked_dir_of(&dir)?;
+        Some(self.remotes[tracked_dir].name().to_string())
+    }
+
+    // Name the remote of each file in a report, if there are mirrors (so
+    // that a file can be in the report once per remote).
+    fn name_remotes(&self, report: &mut TransferReport) {
+        if self.mirrors.is_empty() {
+            return;
+        }
+        for file in report.files.iter_mut() {
+            file.remote = self.remote_name_of(&file.path);
+        }
+    }
+
+    fn name_plan_remotes(&self, plan: &mut TransferPlan) {
+        if self.mirrors.is_empty() {
+            return;
+        }
+        for transfer in plan.transfers.iter_mut() {
+            transfer.remote = self.remote_name_of(&transfer.path);
+        }
+        self.name_remotes(&mut plan.report);
+    }
+    pub fn track_file(&mut self, filepath: &String, path_context: &Path) -> Result<()> {
         trace!("complete files: {:?}", self.files);
-        let data_file = self.files.get_mut(filepath);
 
         // extract the directory from the filepath
         let dir_path = Path::new(filepath)
             .parent()
             .ok_or_else(|| anyhow!("Failed to get directory for file '{}'", filepath))?;
 
-        // check if the directory exists in self.remotes
-        if !self
-            .remotes
-            .contains_key(dir_path.to_str().unwrap_or_default())
+        // check if the directory (or one enclosing it) exists in self.remotes
+        if self
+            .tracked_dir_of(dir_path.to_str().unwrap_or_default())
+            .is_none()
         {
             return Err(anyhow!(
                 "Directory '{}' is not registered in remotes.",
@@ -716,7 +2056,7 @@ impl DataCollection {
             ));
         }
 
-        match data_file {
+        match self.files.get_mut(&normalize_path(filepath)) {
             None => Err(anyhow!(
                 "Data file '{}' is not in the data manifest. Add it first using:\n \
                                 $ sdf track {}\n",
@@ -738,7 +2078,7 @@ impl DataCollection {
         }
     }
     pub fn untrack_file(&mut self, filepath: &String) -> Result<()> {
-        let data_file = self.files.get_mut(filepath);
+        let data_file = self.files.get_mut(&normalize_path(filepath));
         match data_file {
             None => Err(anyhow!(
                 "Cannot untrack data file '{}' since it was never added to\
@@ -765,19 +2105,27 @@ impl DataCollection {
     // Fetch all remote files.
     //
     // (remote service, path) -> { filename -> RemoteFile, ... }
+    //
+    // If dirs is set, only the remotes of those tracked directories are fetched.
     pub async fn fetch(
         &mut self,
+        dirs: Option<&HashSet<String>>,
     ) -> Result<HashMap<(String, String), HashMap<String, RemoteFile>>> {
         self.authenticate_remotes()?;
 
+        let remotes: Vec<(&String, &Remote)> = self
+            .remotes
+            .iter()
+            .filter(|(path, _)| dirs.is_none_or(|dirs| dirs.contains(*path)))
+            .collect();
+
         let mut all_remote_files = HashMap::new();
-        let pb = Progress::new(self.remotes.len() as u64)?;
+        let pb = Progress::new(remotes.len() as u64)?;
         pb.bar.set_message("Fetching remote files...");
 
         // Convert remotes into Futures, so that they can be awaited in parallel
-        let fetch_futures: Vec<_> = self
-            .remotes
-            .iter()
+        let fetch_futures: Vec<_> = remotes
+            .into_iter()
             .map(|(path, remote)| {
                 let remote_name = remote.name().to_string();
                 let path_clone = path.clone();
@@ -806,16 +2154,62 @@ impl DataCollection {
         Ok(all_remote_files)
     }
 
+    // The manifest entries whose files are missing locally, in path order.
+    pub fn missing_files(&self, path_context: &Path) -> Vec<String> {
+        let mut missing: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| !path_context.join(path).exists())
+            .cloned()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    // Of the tracked files among paths, those on their directory's remote,
+    // with its name (in path order). Only the remotes of paths are fetched.
+    pub async fn on_remotes(&mut self, paths: &[String]) -> Result<Vec<(String, String)>> {
+        let tracked: Vec<&String> = paths
+            .iter()
+            .filter(|path| self.files.get(*path).is_some_and(|file| file.tracked))
+            .collect();
+        let dirs: HashSet<String> = tracked
+            .iter()
+            .filter_map(|path| {
+                let dir = Path::new(path).parent()?.to_string_lossy().to_string();
+                self.tracked_dir_of(&dir).cloned()
+            })
+            .collect();
+        if dirs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut found = Vec::new();
+        for ((remote_name, dir), remote_files) in self.fetch(Some(&dirs)).await? {
+            let names = self.remote_names(&dir, tracked.iter().copied())?;
+            for (name, path) in names {
+                if remote_files.contains_key(&name) {
+                    found.push((path, remote_name.clone()));
+                }
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+
     // Merge all local and remote files.
     //
     // Use a fetch to get all remote files (as RemoteFile), and merge these
     // in with the local data files (DataFile) into a MergedFile struct.
     // Missing remote/local files are None.
     //
+    // If candidates is set, only those local files are merged, and only
+    // the remotes of their directories are fetched.
+    //
     // Returns: Result with HashMap of directory -> { File -> MergedFile, ... }
     pub async fn merge(
         &mut self,
         include_remotes: bool,
+        candidates: Option<&HashSet<String>>,
     ) -> Result<HashMap<String, HashMap<String, MergedFile>>> {
         // directory -> {(filename -> MergedFile), ...}
         let mut result: HashMap<String, HashMap<String, MergedFile>> = HashMap::new();
@@ -825,6 +2219,9 @@ impl DataCollection {
         // case where we have a local file in a tracked directory
         // but it won't merge with a remote file later on.
         for (name, local_file) in &self.files {
+            if candidates.is_some_and(|c| !c.contains(name)) {
+                continue;
+            }
             let remote_service = self.get_this_files_remote(local_file)?;
             //info!("local_file: {:?}", local_file);
             let dir = local_file.directory()?;
@@ -843,32 +2240,132 @@ impl DataCollection {
         }
 
         // iterate through each remote and retrieve remote files
-        let all_remote_files = self.fetch().await?;
+        let dirs: Option<HashSet<String>> = candidates.map(|_| {
+            result
+                .keys()
+                .filter_map(|dir| self.tracked_dir_of(dir))
+                .cloned()
+                .collect()
+        });
+        let all_remote_files = self.fetch(dirs.as_ref()).await?;
+        self.merge_remote_files(result, &all_remote_files)
+    }
+
+    // Merge all local files with remote listings that are fetched unless
+    // a listing within the TTL is in the remote cache (see remote_cache.rs).
+    // With refresh, every listing is fetched (and cached).
+    pub async fn merge_with_remote_cache(
+        &mut self,
+        path_context: &Path,
+        ttl: Duration,
+        refresh: bool,
+    ) -> Result<HashMap<String, HashMap<String, MergedFile>>> {
+        let result = self.merge(false, None).await?;
+        let mut cache = RemoteCache::load(path_context);
+        let now = Utc::now();
+        let mut all_remote_files = HashMap::new();
+        let mut stale = HashSet::new();
+        for (dir, remote) in self.remotes.iter() {
+            match cache.get(dir, remote.name(), ttl, now).filter(|_| !refresh) {
+                Some(listing) => {
+                    let age = (now - listing.fetched).num_seconds();
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "Remote files of '{}' ({}): cached ({}s ago).",
+                            dir,
+                            remote.name(),
+                            age
+                        )
+                        .yellow()
+                    );
+                    let key = (remote.name().to_string(), dir.clone());
+                    all_remote_files.insert(key, listing.files.clone());
+                }
+                None => {
+                    stale.insert(dir.clone());
+                }
+            }
+        }
+        if !stale.is_empty() {
+            let fetched = self.fetch(Some(&stale)).await?;
+            for ((remote_name, dir), files) in fetched.iter() {
+                cache.insert(dir, remote_name, files.clone(), now);
+            }
+            cache.save(path_context)?;
+            all_remote_files.extend(fetched);
+        }
+        self.merge_remote_files(result, &all_remote_files)
+    }
+
+    // Merge remote listings, keyed by (remote name, tracked directory), into
+    // merged local files (from merge() without remotes).
+    fn merge_remote_files(
+        &mut self,
+        mut result: HashMap<String, HashMap<String, MergedFile>>,
+        all_remote_files: &HashMap<(String, String), HashMap<String, RemoteFile>>,
+    ) -> Result<HashMap<String, HashMap<String, MergedFile>>> {
         for ((remote_service, tracked_dir), remote_files) in all_remote_files.iter() {
+            // remote files are matched to manifest entries through the
+            // remote's path style (e.g. flattened names)
+            let path_style = self.remotes[tracked_dir].path_style();
+            let names = self.remote_names(tracked_dir, self.files.keys())?;
+
             // merge remote files with local files
             for (name, remote_file) in remote_files {
-                // try to get the tracked directory; it doesn't exist make it
-                let path_key = PathBuf::from(tracked_dir)
-                    .join(name)
-                    .to_str()
-                    .unwrap()
-                    .to_string();
-                if let Some(merged_file) = result
-                    .entry(tracked_dir.clone())
-                    .or_default()
-                    .get_mut(&path_key)
+                let path_key = match names.get(name) {
+                    Some(path) => path.clone(),
+                    None => PathBuf::from(tracked_dir)
+                        .join(path_style.decode(name))
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                // e.g. an uncompressed copy of a file that is now pushed
+                // compressed (or encrypted), which is not its remote copy
+                if !names.contains_key(name) && self.files.contains_key(&path_key) {
+                    continue;
+                }
+                // e.g. a remote file named '../notes.txt'
+                if !is_project_path(&path_key) {
+                    print_warn!(
+                        "Skipping the file '{}' on {}, as it would be outside the project.",
+                        name,
+                        remote_service
+                    );
+                    continue;
+                }
+                let path = Path::new(&path_key);
+                let dir = path
+                    .parent()
+                    .map(|dir| dir.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                // the merged remote file carries the local name (and for
+                // stored copies, the MD5 and size of the file)
+                let mut remote_file = remote_file.clone();
+                remote_file.name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if let Some(data_file) = self.files.get(&path_key) {
+                    data_file.restored_remote(&mut remote_file);
+                }
+
+                if let Some(merged_file) = result.entry(dir.clone()).or_default().get_mut(&path_key)
                 {
                     // we have a local and a remote file
                     // set the joined remote file and the service
-                    merged_file.remote = Some(remote_file.clone());
+                    if let Some(data_file) = self.files.get_mut(&path_key) {
+                        data_file.cache_remote_state(&remote_file);
+                    }
+                    merged_file.remote = Some(remote_file);
                     merged_file.remote_service = Some(remote_service.to_string());
                 } else {
                     // no local file, but we have a remote
-                    result.entry(tracked_dir.clone()).or_default().insert(
-                        path_key.to_string(),
+                    result.entry(dir).or_default().insert(
+                        path_key,
                         MergedFile {
                             local: None,
-                            remote: Some(remote_file.clone()),
+                            remote: Some(remote_file),
                             remote_service: Some(remote_service.to_string()),
                         },
                     );
@@ -878,342 +2375,1489 @@ impl DataCollection {
         Ok(result)
     }
 
+    // Merge local files with their last-known remote state, as recorded in
+    // the manifest. This makes no network calls, so remote-only files are
+    // not included, and the remote status is only an approximation.
+    pub fn merge_cached(&self) -> Result<HashMap<String, HashMap<String, MergedFile>>> {
+        let mut result: HashMap<String, HashMap<String, MergedFile>> = HashMap::new();
+        for (name, local_file) in &self.files {
+            let remote_service = self.get_this_files_remote(local_file)?;
+            let remote = match &remote_service {
+                Some(service) => local_file.cached_remote_file(service)?,
+                None => None,
+            };
+            result.entry(local_file.directory()?).or_default().insert(
+                name.clone(),
+                MergedFile {
+                    local: Some(local_file.clone()),
+                    remote,
+                    remote_service,
+                },
+            );
+        }
+        Ok(result)
+    }
+
     // Get the status of the DataCollection, optionally with remotes.
+    // If options.cached_remotes is set, the remote status is computed from
+    // the last-known remote state in the manifest, rather than fetched.
+    // Fetched statuses include each file's status on its directory's
+    // mirrors (see remote_passes()).
     //
     // Returns Result of BTreeMap of directory -> [ StatusEntry, ...]
     pub async fn status(
         &mut self,
         path_context: &Path,
-        include_remotes: bool,
+        options: &StatusOptions,
+    ) -> Result<BTreeMap<String, Vec<StatusEntry>>> {
+        self.status_streamed(path_context, options, &mut |_, _| {})
+            .await
+    }
+
+    // Like status(), but each file's entry on the directory's remote is
+    // also given to on_entry (with its directory) as soon as it is hashed,
+    // so they come in the order files finish, without mirror statuses.
+    // Only entries that options.keeps() are given.
+    pub async fn status_streamed(
+        &mut self,
+        path_context: &Path,
+        options: &StatusOptions,
+        on_entry: &mut dyn FnMut(&str, &StatusEntry),
+    ) -> Result<BTreeMap<String, Vec<StatusEntry>>> {
+        let mut statuses = self
+            .status_pass(path_context, options, None, on_entry)
+            .await?;
+        if options.include_remotes && !options.cached_remotes && !self.mirrors.is_empty() {
+            // mirror listings are not cached, as they would be under the
+            // same directory (and possibly remote name) as the remote's
+            let mirror_options = StatusOptions {
+                remote_cache_ttl: None,
+                ..options.clone()
+            };
+            for (pass, remotes) in self.remote_passes(None)?.into_iter().enumerate().skip(1) {
+                let names: HashMap<String, String> = remotes
+                    .iter()
+                    .map(|(dir, remote)| (dir.clone(), remote.name().to_string()))
+                    .collect();
+                let candidates = self.files_in(&names.keys().cloned().collect());
+                let saved = self.begin_pass(remotes);
+                let mirrored = self
+                    .status_pass(
+                        path_context,
+                        &mirror_options,
+                        Some(&candidates),
+                        &mut |_, _| {},
+                    )
+                    .await;
+                self.end_pass(saved);
+                self.add_mirror_statuses(&mut statuses, mirrored?, &names, pass);
+            }
+        }
+
+        for entries in statuses.values_mut() {
+            entries.retain(|entry| options.keeps(entry));
+        }
+        statuses.retain(|_, entries| !entries.is_empty());
+        for entries in statuses.values_mut() {
+            options.sort.sort(entries, options.reverse);
+        }
+        Ok(statuses)
+    }
+
+    // The manifest entries in the given tracked directories.
+    fn files_in(&self, dirs: &HashSet<String>) -> HashSet<String> {
+        self.files
+            .values()
+            .filter(|data_file| {
+                let dir = data_file.directory().unwrap_or_default();
+                self.tracked_dir_of(&dir)
+                    .is_some_and(|tracked_dir| dirs.contains(tracked_dir))
+            })
+            .map(|data_file| data_file.path.clone())
+            .collect()
+    }
+
+    // Add the statuses from a pass over mirrors (the pass-th; names has the
+    // mirror of each directory in the pass) to the statuses on the
+    // directories' remotes. Each file in these directories gets a status
+    // on the mirror, so files it does not have are "not on remote"; files
+    // only on the mirror get an entry of their own (not on the remote).
+    fn add_mirror_statuses(
+        &self,
+        statuses: &mut BTreeMap<String, Vec<StatusEntry>>,
+        mirrored: BTreeMap<String, Vec<StatusEntry>>,
+        names: &HashMap<String, String>,
+        pass: usize,
+    ) {
+        let not_on = |remote: &str| MirrorStatus {
+            remote: remote.to_string(),
+            remote_status: Some(RemoteStatusCode::NotExists),
+            remote_md5: None,
+            remote_size: None,
+        };
+        for (dir, mirror_entries) in mirrored {
+            let Some(tracked_dir) = self.tracked_dir_of(&dir) else {
+                continue;
+            };
+            let Some(remote) = names.get(tracked_dir) else {
+                continue;
+            };
+            let entries = statuses.entry(dir).or_default();
+            for mirror_entry in mirror_entries {
+                let mirror = MirrorStatus {
+                    remote: remote.clone(),
+                    remote_status: mirror_entry.remote_status.clone(),
+                    remote_md5: mirror_entry.remote_md5.clone(),
+                    remote_size: mirror_entry.remote_size,
+                };
+                if let Some(entry) = entries.iter_mut().find(|e| e.name == mirror_entry.name) {
+                    entry.mirrors.push(mirror);
+                    continue;
+                }
+                // not on the remote, nor on the earlier mirrors
+                let mut mirrors: Vec<MirrorStatus> = self.mirrors[tracked_dir][..pass - 1]
+                    .iter()
+                    .map(|earlier| not_on(earlier.name()))
+                    .collect();
+                mirrors.push(mirror);
+                entries.push(StatusEntry {
+                    remote_status: Some(RemoteStatusCode::NotExists),
+                    remote_service: Some(self.remotes[tracked_dir].name().to_string()),
+                    remote_md5: None,
+                    remote_size: None,
+                    mirrors,
+                    ..mirror_entry
+                });
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        // files in the pass's directories the mirror does not have
+        for (dir, entries) in statuses.iter_mut() {
+            let Some(remote) = self.tracked_dir_of(dir).and_then(|dir| names.get(dir)) else {
+                continue;
+            };
+            for entry in entries
+                .iter_mut()
+                .filter(|entry| entry.mirrors.len() < pass)
+            {
+                entry.mirrors.push(not_on(remote));
+            }
+        }
+    }
+
+    // The status of each file on the current remotes (see status()).
+    async fn status_pass(
+        &mut self,
+        path_context: &Path,
+        options: &StatusOptions,
+        candidates: Option<&HashSet<String>>,
+        on_entry: &mut dyn FnMut(&str, &StatusEntry),
     ) -> Result<BTreeMap<String, Vec<StatusEntry>>> {
-        let merged_files = self.merge(include_remotes).await?;
+        let cache_ttl = options.remote_cache_ttl.filter(|_| options.include_remotes);
+        let (merged_files, include_remotes) = if options.cached_remotes {
+            (self.merge_cached()?, true)
+        } else if let Some(ttl) = cache_ttl {
+            let ttl = Duration::seconds(ttl as i64);
+            (
+                self.merge_with_remote_cache(path_context, ttl, options.refresh)
+                    .await?,
+                true,
+            )
+        } else {
+            (
+                self.merge(options.include_remotes, candidates).await?,
+                options.include_remotes,
+            )
+        };
+        let algo = options.algo;
 
-        let mut statuses_futures = FuturesUnordered::new();
+        let mut statuses_futures = Vec::new();
 
+        let mut ignore = IgnoreRules::new(path_context);
         for (directory, inner_map) in merged_files.into_iter() {
             // this clone is to prevent a borrow issue due to async move below
             let files: Vec<_> = inner_map.values().cloned().collect();
             for mf in files {
+                let path = match (&mf.local, &mf.remote) {
+                    (Some(local), _) => local.path.clone(),
+                    (None, Some(remote)) => {
+                        let path = Path::new(&directory).join(&remote.name);
+                        let path = normalize_path(&path.to_string_lossy());
+                        // files only on the remote can be ignored (see ignore.rs)
+                        if ignore.is_ignored(&path, false)? {
+                            continue;
+                        }
+                        path
+                    }
+                    (None, None) => continue,
+                };
+                if !options.selects(&path, &mf) {
+                    continue;
+                }
                 let directory_clone = directory.clone();
                 statuses_futures.push(async move {
-                    let status_entry = mf
-                        .status_entry(path_context, include_remotes)
-                        .await
-                        .map_err(anyhow::Error::from)?;
-                    Ok::<(String, StatusEntry), anyhow::Error>((directory_clone, status_entry))
+                    let status_entry = mf.status_entry(path_context, include_remotes, algo).await;
+                    (directory_clone, mf, status_entry)
                 });
             }
         }
 
         let mut statuses = BTreeMap::new();
 
-        let pb = Progress::new(statuses_futures.len() as u64)?;
-
-        // process the futures as they become ready
-        while let Some(result) = statuses_futures.next().await {
-            if let Ok((key, value)) = result {
-                pb.bar
-                    .set_message(format!("Calculating MD5s... {} done.", &value.name));
-                statuses.entry(key).or_insert_with(Vec::new).push(value);
-                pb.bar.inc(1);
-            } else {
-                result?;
+        let total = statuses_futures.len();
+        let pb = Progress::new(total as u64)?;
+        // hash up to --jobs files at once
+        let jobs = options.jobs.unwrap_or(io_settings().jobs).max(1);
+        let mut statuses_futures = futures::stream::iter(statuses_futures).buffer_unordered(jobs);
+
+        // process the futures as they become ready; a file that cannot be
+        // read is reported, but does not stop the others
+        let mut failed = Vec::new();
+        while let Some((key, merged_file, result)) = statuses_futures.next().await {
+            pb.inc("files hashed");
+            match result {
+                Ok(value) => {
+                    if options.keeps(&value) {
+                        pb.bar.suspend(|| on_entry(&key, &value));
+                    }
+                    statuses.entry(key).or_insert_with(Vec::new).push(value)
+                }
+                Err(err) => failed.push((merged_file.name().unwrap_or(key), err)),
             }
         }
 
         pb.bar.finish_with_message("Complete.");
+        for (name, err) in failed {
+            print_warn!("Could not get the status of '{}': {:#}", name, err);
+        }
+        // files finish in any order
+        for entries in statuses.values_mut() {
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
         Ok(statuses)
     }
 
-    pub async fn push(&mut self, path_context: &Path, overwrite: bool) -> Result<()> {
-        // TODO before any push, we need to make sure that the project
-        // status is "clean" e.g. nothing out of data.
-
-        // Fetch all files as MergedFiles
-        // note: this authenticates
-        let all_files = self.merge(true).await?;
-
-        let mut num_uploaded = 0;
-        let mut current_skipped = Vec::new();
-        let mut messy_skipped = Vec::new();
-        let mut overwrite_skipped = Vec::new();
-        let mut untracked_skipped = Vec::new();
-
-        for (tracked_dir, files) in all_files.iter() {
-            if let Some(remote) = self.remotes.get(tracked_dir) {
-                for merged_file in files.values() {
-                    let name = merged_file.name()?;
-                    let path = PathBuf::from(tracked_dir)
-                        .join(name)
-                        .to_str()
-                        .unwrap()
-                        .to_string();
-                    let local = merged_file.local.clone();
-
-                    // if the file is not tracked or is remote-only,
-                    // we do not do anything
-                    if local.as_ref().map_or(false, |mf| !mf.tracked) {
-                        untracked_skipped.push(path);
-                        continue;
-                    }
-
-                    // now we need to figure out whether to push the file,
-                    // which depends on the RemoteStatusCode and whether
-                    // we should overwrite (TODO)
-                    let do_upload = match merged_file.status(path_context).await? {
-                        RemoteStatusCode::NoLocal => {
-                            // A file exists on the remote, but not locally: there
-                            // is nothing to push in this case (or count!)
-                            false
-                        }
-                        RemoteStatusCode::Current => {
-                            current_skipped.push(path);
-                            false
-                        }
-                        RemoteStatusCode::Exists => {
-                            // it exists on the remote, but we cannot
-                            // compare MD5s. Push only if overwrite is true.
-                            if !overwrite {
-                                overwrite_skipped.push(path);
-                            }
-                            overwrite
-                        }
-                        RemoteStatusCode::MessyLocal => {
-                            messy_skipped.push(path);
-                            false
-                        }
-                        RemoteStatusCode::Invalid => {
-                            return Err(anyhow!("A file ({:}) with RemoteStatusCode::Invalid was encountered. Please report.", path));
-                        }
-                        RemoteStatusCode::Different => {
-                            // TODO if remote supports modification times,
-                            // could do extra comparison here
-                            info!("skipping {:} {:}", path, overwrite);
-                            if !overwrite {
-                                overwrite_skipped.push(path);
-                            }
-                            overwrite
-                        }
-                        RemoteStatusCode::DeletedLocal => {
-                            // there is nothing to upload
-                            print_warn!("A file ({:}) was skipped because it was deleted.", path);
-                            false
-                        }
-                        RemoteStatusCode::NotExists => true,
-                    };
-
-                    if do_upload {
-                        let data_file = local.ok_or(anyhow!("Internal error (do_upload() with MergedFile.local = None): please report."))?;
-                        print_info!("uploading file {:?} to {}", data_file.path, remote.name());
-                        remote.upload(&data_file, path_context, overwrite).await?;
-                        num_uploaded += 1;
-                    }
+    // Compare each tracked file's local and remote MD5s (see sync_state()).
+    // Remote files without an MD5 cannot be compared, and are not counted.
+    pub async fn ahead_behind(&mut self, path_context: &Path) -> Result<Vec<SyncSummary>> {
+        // merging overwrites the last-known remote state
+        let last_synced: HashMap<String, Option<String>> = self
+            .files
+            .iter()
+            .map(|(path, data_file)| (path.clone(), data_file.remote_md5.clone()))
+            .collect();
+        let merged_files = self.merge(true, None).await?;
+        let mut summaries: BTreeMap<String, SyncSummary> = self
+            .remotes
+            .iter()
+            .map(|(dir, remote)| {
+                let summary = SyncSummary {
+                    directory: dir.clone(),
+                    remote: remote.name().to_string(),
+                    ..Default::default()
+                };
+                (dir.clone(), summary)
+            })
+            .collect();
+        for (dir, files) in merged_files.iter() {
+            let Some(tracked_dir) = self.tracked_dir_of(dir) else {
+                continue;
+            };
+            for (path, merged_file) in files {
+                let Some(local) = merged_file.local.as_ref().filter(|local| local.tracked) else {
+                    continue;
+                };
+                let remote_md5 = match &merged_file.remote {
+                    None => None,
+                    Some(remote) => match remote.get_md5() {
+                        Some(md5) => Some(md5),
+                        None => continue,
+                    },
+                };
+                let local_md5 = local.get_md5(path_context).await?;
+                let last_synced = last_synced.get(path).cloned().flatten();
+                let summary = summaries
+                    .get_mut(tracked_dir)
+                    .expect("summaries has every tracked directory");
+                match sync_state(
+                    local_md5.as_deref(),
+                    remote_md5.as_deref(),
+                    last_synced.as_deref(),
+                ) {
+                    SyncState::InSync => {}
+                    SyncState::Ahead => summary.ahead += 1,
+                    SyncState::Behind => summary.behind += 1,
+                    SyncState::Conflict => summary.conflict += 1,
                 }
             }
         }
-        println!("Uploaded {}.", pluralize(num_uploaded as u64, "file"));
-        let num_skipped = overwrite_skipped.len()
-            + current_skipped.len()
-            + messy_skipped.len()
-            + untracked_skipped.len();
-        let punc = if num_skipped > 0 { "." } else { ":" };
-        println!("Skipped {}{}", pluralize(num_skipped as u64, "file"), punc);
-        if !untracked_skipped.is_empty() {
-            println!(
-                "  Untracked: {}",
-                pluralize(untracked_skipped.len() as u64, "file")
-            );
-            for path in untracked_skipped {
-                println!("   - {:}", path);
-            }
-        }
-        if !current_skipped.is_empty() {
-            println!(
-                "  Remote file is indentical to local file: {}",
-                pluralize(current_skipped.len() as u64, "file")
-            );
-            for path in current_skipped {
-                println!("   - {:}", path);
+        Ok(summaries.into_values().collect())
+    }
+
+    // The total local size of the files a push (with --overwrite) would
+    // upload: tracked, present locally, and not current on the remote.
+    pub async fn pending_upload_size(&mut self, path_context: &Path, cached: bool) -> Result<u64> {
+        let merged_files = if cached {
+            self.merge_cached()?
+        } else {
+            self.merge(true, None).await?
+        };
+        let mut total = 0;
+        for (dir, files) in merged_files.iter() {
+            if self.tracked_dir_of(dir).is_none() {
+                continue;
+            }
+            for merged_file in files.values() {
+                let Some(local) = merged_file.local.as_ref().filter(|local| local.tracked) else {
+                    continue;
+                };
+                let status = merged_file.status(path_context).await?;
+                if matches!(push_action(&status, true), TransferAction::Transfer(_)) {
+                    total += local.get_size(path_context)?;
+                }
             }
         }
-        if !overwrite_skipped.is_empty() {
-            println!(
-                "  Would overwrite (use --overwrite to push): {}",
-                pluralize(overwrite_skipped.len() as u64, "file")
-            );
-            for path in overwrite_skipped {
-                println!("   - {:}", path);
+        Ok(total)
+    }
+
+    // Get the manifest paths of files matching a filter, or None if the
+    // filter is empty (i.e. all files are candidates).
+    pub fn candidates(&self, filter: &FileFilter, path_context: &Path) -> Option<HashSet<String>> {
+        if filter.is_empty() {
+            return None;
+        }
+        Some(
+            self.files
+                .iter()
+                .filter(|(_, data_file)| filter.matches(data_file, path_context))
+                .map(|(path, _)| path.clone())
+                .collect(),
+        )
+    }
+
+    // Decide which of the merged files to upload, in path order (so a
+    // failed push can be resumed from the file it failed on, with
+    // --resume-from). This makes no requests.
+    async fn queue_uploads(
+        &self,
+        path_context: &Path,
+        all_files: &HashMap<String, HashMap<String, MergedFile>>,
+        overwrite: bool,
+        candidates: Option<&HashSet<String>>,
+    ) -> Result<(Vec<QueuedTransfer>, TransferReport)> {
+        let mut report = TransferReport::new();
+        let mut queue = Vec::new();
+        for (dir, files) in all_files.iter() {
+            for merged_file in files.values() {
+                let name = merged_file.name()?;
+                let path = PathBuf::from(dir).join(name).to_str().unwrap().to_string();
+                queue.push((path, dir, merged_file));
             }
         }
-        if !messy_skipped.is_empty() {
-            println!(
-                "  Local is \"messy\" (manifest and file disagree): {}",
-                pluralize(messy_skipped.len() as u64, "file")
-            );
-            println!("  Use 'sdf update <FILE>' to add the current version to the manifest.");
-            for path in messy_skipped {
-                println!("   - {:}", path);
+        queue.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut uploads = Vec::new();
+        for (path, dir, merged_file) in queue {
+            // files in subdirectories are pushed to the enclosing
+            // tracked directory's remote
+            if let Some(tracked_dir) = self.tracked_dir_of(dir) {
+                if candidates.is_some() && merged_file.local.is_none() {
+                    // remote-only files are outside of any filter
+                    continue;
+                }
+                let local = merged_file.local.clone();
+
+                // if the file is not tracked or is remote-only,
+                // we do not do anything
+                if local.as_ref().is_some_and(|mf| !mf.tracked) {
+                    report.skipped(&path, SKIP_UNTRACKED);
+                    continue;
+                }
+
+                // now we need to figure out whether to push the file,
+                // which depends on the RemoteStatusCode and whether
+                // we should overwrite
+                let reason = match push_action(&merged_file.status(path_context).await?, overwrite)
+                {
+                    TransferAction::Transfer(reason) => reason,
+                    TransferAction::Skip(reason) => {
+                        report.skipped(&path, reason);
+                        continue;
+                    }
+                    TransferAction::Nothing => continue,
+                    TransferAction::Invalid => {
+                        return Err(anyhow!("A file ({:}) with RemoteStatusCode::Invalid was encountered. Please report.", path));
+                    }
+                };
+
+                let data_file = local.ok_or(anyhow!(
+                    "Internal error (do_upload() with MergedFile.local = None): please report."
+                ))?;
+                uploads.push(QueuedTransfer {
+                    planned: PlannedTransfer {
+                        path,
+                        direction: Direction::Upload,
+                        size: Some(data_file.size),
+                        reason: reason.to_string(),
+                        remote: None,
+                    },
+                    tracked_dir: Some(tracked_dir.clone()),
+                    merged_file: merged_file.clone(),
+                    expected_md5: None,
+                });
+            }
+        }
+        Ok((uploads, report))
+    }
+
+    // What push would upload and skip, without uploading anything.
+    pub async fn push_plan(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        remote: Option<&str>,
+    ) -> Result<TransferPlan> {
+        let mut plan = TransferPlan::default();
+        for remotes in self.remote_passes(remote)? {
+            let saved = self.begin_pass(remotes);
+            let mut result = self.push_plan_pass(path_context, overwrite, filter).await;
+            if let Ok(pass_plan) = result.as_mut() {
+                self.name_plan_remotes(pass_plan);
             }
+            self.end_pass(saved);
+            plan.extend(result?);
         }
+        Ok(plan)
+    }
+
+    async fn push_plan_pass(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+    ) -> Result<TransferPlan> {
+        let candidates = self.candidates(filter, path_context);
+        let all_files = self.merge(true, candidates.as_ref()).await?;
+        let (uploads, report) = self
+            .queue_uploads(path_context, &all_files, overwrite, candidates.as_ref())
+            .await?;
+        self.check_writable(candidates.as_ref(), &uploads)?;
+        Ok(to_plan(uploads, report))
+    }
 
+    // Published records (see 'sdf link --read-only') are only pulled from,
+    // so a push is an error if it would upload to one, or if every
+    // directory it covers is linked to one.
+    fn check_writable(
+        &self,
+        candidates: Option<&HashSet<String>>,
+        uploads: &[QueuedTransfer],
+    ) -> Result<()> {
+        let error = |dir: &str, remote: &Remote| {
+            anyhow!(
+                "Directory '{}' is linked to a published {} record ({}), which is read-only: \
+                 its files can be pulled, but not pushed.",
+                dir,
+                remote.name(),
+                remote.web_url().unwrap_or_default()
+            )
+        };
+        for queued in uploads {
+            let Some(dir) = &queued.tracked_dir else {
+                continue;
+            };
+            if self.remotes[dir].is_read_only() {
+                return Err(error(dir, &self.remotes[dir]));
+            }
+        }
+        let mut covered = self.remotes.iter().filter(|(dir, _)| {
+            candidates.is_none_or(|paths| paths.iter().any(|path| Path::new(path).starts_with(dir)))
+        });
+        if let Some((dir, remote)) = covered.clone().next() {
+            if covered.all(|(_, remote)| remote.is_read_only()) {
+                return Err(error(dir, remote));
+            }
+        }
         Ok(())
     }
 
-    pub async fn pull_urls(&mut self, path_context: &Path, overwrite: bool) -> Result<()> {
-        let mut downloads = Downloads::new();
-        let mut filepaths = Vec::new();
-        let mut skipped = Vec::new();
-        let mut num_downloaded = 0;
+    // Push to each directory's remote, then to its mirrors (or with
+    // remote, only to the remotes of that name; see remote_passes()).
+    pub async fn push(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        max_concurrent: usize,
+        remote: Option<&str>,
+    ) -> Result<TransferReport> {
+        let mut report = TransferReport::new();
+        for remotes in self.remote_passes(remote)? {
+            let before = remotes.clone();
+            let saved = self.begin_pass(remotes);
+            let mut result = self
+                .push_pass(path_context, overwrite, filter, max_concurrent)
+                .await;
+            if let Ok(pass_report) = result.as_mut() {
+                self.name_remotes(pass_report);
+            }
+            let after = self.remotes.clone();
+            self.end_pass(saved);
+            self.keep_pass_changes(&before, after);
+            report.extend(result?);
+        }
+        Ok(report)
+    }
+
+    async fn push_pass(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        max_concurrent: usize,
+    ) -> Result<TransferReport> {
+        // TODO before any push, we need to make sure that the project
+        // status is "clean" e.g. nothing out of data.
+
+        // Pre-filter the candidate files (e.g. by modification time), so
+        // that other files are neither hashed nor fetched from the remotes.
+        let candidates = self.candidates(filter, path_context);
+
+        // Fetch all files as MergedFiles
+        // note: this authenticates
+        let all_files = self.merge(true, candidates.as_ref()).await?;
+
+        // first decide which files to upload, so the progress bar knows
+        // the total size up front
+        let (uploads, mut report) = self
+            .queue_uploads(path_context, &all_files, overwrite, candidates.as_ref())
+            .await?;
+        self.check_writable(candidates.as_ref(), &uploads)?;
+        let mut uploaded = Vec::new();
+        // per remote, the files uploaded (with their paths and stored
+        // copies), and the uploads to confirm
+        let mut to_confirm: HashMap<String, (Vec<_>, Vec<Upload>)> = HashMap::new();
+
+        // ready the remotes that are uploaded to (e.g. a published Zenodo
+        // deposition gets a new version); push() keeps their changes
+        let upload_dirs: BTreeSet<String> = uploads
+            .iter()
+            .filter_map(|queued| queued.tracked_dir.clone())
+            .collect();
+        for dir in &upload_dirs {
+            if let Some(remote) = self.remotes.get_mut(dir) {
+                remote.prepare_push().await?;
+            }
+        }
+        // the deposits covered get the manifest's metadata (see
+        // DataCollectionMetadata::deposit())
+        let mut covered: Vec<String> = self
+            .remotes
+            .keys()
+            .filter(|dir| {
+                candidates
+                    .as_ref()
+                    .is_none_or(|paths| paths.iter().any(|path| Path::new(path).starts_with(dir)))
+            })
+            .cloned()
+            .collect();
+        covered.sort();
+        for dir in covered {
+            let metadata = self.metadata.deposit(&dir);
+            let remote = self
+                .remotes
+                .get_mut(&dir)
+                .expect("covered directory has a remote");
+            if metadata.is_empty() || remote.check_publishable().is_err() {
+                continue;
+            }
+            if remote.update_metadata(&metadata).await? {
+                print_info!(
+                    "Updated the metadata of the {} deposit of '{}'.",
+                    remote.name(),
+                    dir
+                );
+            }
+        }
+
+        let total_size = uploads
+            .iter()
+            .filter_map(|queued| queued.planned.size)
+            .sum();
+        let progress = TransferProgress::new(total_size, uploads.len() as u64)?;
+        // remotes with a limit of their own share it across directories
+        let slots: HashMap<&str, Semaphore> = self
+            .remotes
+            .values()
+            .filter_map(|remote| {
+                let limit = remote.max_concurrent_transfers()?;
+                Some((remote.name(), Semaphore::new(limit)))
+            })
+            .collect();
+        let (remotes, progress, slots) = (&self.remotes, &progress, &slots);
+        let encryption_key = self.encryption_key.as_ref();
+        // up to max_concurrent files are uploaded at once (fewer to some
+        // remotes); a failed upload does not stop the others, and results
+        // are kept in queue order
+        // (uploads are confirmed below, once per remote)
+        let results: Vec<_> = futures::stream::iter(uploads)
+            .filter_map(|queued| async move {
+                let tracked_dir = queued.tracked_dir?;
+                let data_file = queued.merged_file.local?;
+                Some((queued.planned.path, tracked_dir, data_file))
+            })
+            .map(|(path, tracked_dir, data_file)| async move {
+                let remote = &remotes[&tracked_dir];
+                let _slot = match slots.get(remote.name()) {
+                    Some(slots) => slots.acquire().await.ok(),
+                    None => None,
+                };
+                if !progress_enabled() {
+                    print_info!("uploading file {:?} to {}", data_file.path, remote.name());
+                }
+                let pushed = async {
+                    let remote_name = format!(
+                        "{}{}",
+                        remote
+                            .path_style()
+                            .remote_name(&tracked_dir, &data_file.path)?,
+                        data_file.remote_suffix()
+                    );
+                    let file_progress = progress.file(&path, data_file.size)?;
+                    let pushed = upload_file(
+                        remote,
+                        &data_file,
+                        remote_name,
+                        path_context,
+                        overwrite,
+                        encryption_key,
+                        &file_progress,
+                    )
+                    .await;
+                    file_progress.finish();
+                    pushed
+                }
+                .await;
+                (path, tracked_dir, data_file.path, pushed)
+            })
+            .buffered(max_concurrent.max(1))
+            .collect()
+            .await;
+        progress.finish();
+        for (path, tracked_dir, manifest_path, pushed) in results {
+            match pushed {
+                Ok((upload, copy)) => {
+                    let (files, uploads) = to_confirm.entry(tracked_dir).or_default();
+                    files.push((path, manifest_path, copy));
+                    uploads.push(upload);
+                }
+                Err(err) => report.failed(&path, &format!("{:#}", err)),
+            }
+        }
+
+        // confirm the remotes have the uploaded files
+        for (tracked_dir, (files, uploads)) in to_confirm {
+            let results = self.remotes[&tracked_dir].confirm_uploads(uploads).await;
+            for ((path, manifest_path, copy), result) in files.into_iter().zip(results) {
+                match result {
+                    Ok(sent) => {
+                        // (a copy that was not sent is not the remote's)
+                        uploaded.push((manifest_path, copy.filter(|_| sent)));
+                        report.succeeded(&path);
+                    }
+                    Err(err) => report.failed(&path, &format!("{:#}", err)),
+                }
+            }
+        }
+
+        // the remote now has the pushed version
+        for (path, copy) in uploaded {
+            let remote_service = self.get_this_files_remote(&self.files[&path])?;
+            if let Some(data_file) = self.files.get_mut(&path) {
+                data_file.remote_md5 = Some(data_file.md5.clone());
+                data_file.remote_size = Some(data_file.size);
+                data_file.remote = remote_service;
+                if copy.is_some() {
+                    data_file.stored = copy;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    // Record the remote-reported MD5s of manifest entries lacking one
+    // (e.g. hand-written manifests), without downloading any files.
+    pub async fn backfill_checksums(&mut self) -> Result<TransferReport> {
+        let all_files = self.merge(true, None).await?;
+        let mut report = TransferReport::new();
+        for merged_file in all_files.values().flat_map(|files| files.values()) {
+            let path = match &merged_file.local {
+                Some(local) if local.md5.is_empty() => local.path.clone(),
+                _ => continue,
+            };
+            let md5 = match merged_file.remote_md5() {
+                Some(md5) => md5,
+                None if merged_file.remote.is_none() => {
+                    report.skipped(&path, "not found on a remote");
+                    continue;
+                }
+                None => {
+                    report.skipped(&path, "remote does not report a checksum");
+                    continue;
+                }
+            };
+            if let Some(data_file) = self.files.get_mut(&path) {
+                data_file.md5 = md5;
+                if data_file.size == 0 {
+                    data_file.size = merged_file.remote_size().unwrap_or(0);
+                }
+                report.succeeded(&path);
+            }
+        }
+        Ok(report)
+    }
+
+    // Check local files against the MD5s in the manifest. Mismatches (e.g.
+    // from bit rot) are failures; files missing locally are skipped.
+    pub async fn verify(&self, path_context: &Path) -> Result<VerifyReport> {
+        let mut verified = VerifyReport::default();
+        let report = &mut verified.report;
         for data_file in self.files.values() {
-            if let Some(url) = &data_file.url {
-                let full_path = data_file.full_path(path_context)?;
-                let download =
-                    downloads.add(url.clone(), Some(&full_path.to_string_lossy()), overwrite)?;
-                if let Some(dl) = download {
-                    let filepath = dl.filename.clone();
-                    filepaths.push(filepath);
-                    num_downloaded += 1;
+            let path = &data_file.path;
+            match data_file.verify(path_context).await {
+                Ok(LocalStatusCode::Current) => report.succeeded(path),
+                Ok(LocalStatusCode::Modified) => {
+                    report.failed(path, VERIFY_MISMATCH);
+                    verified.corrupted.insert(path.clone());
+                }
+                Ok(LocalStatusCode::Deleted) => {
+                    report.failed(path, VERIFY_MISSING);
+                    verified.missing += 1;
+                }
+                Ok(LocalStatusCode::Invalid) => report.skipped(path, "invalid manifest entry"),
+                Err(err) => {
+                    report.failed(path, &format!("could not be read: {:#}", err));
+                    verified.unreadable += 1;
+                }
+            }
+        }
+        Ok(verified)
+    }
+
+    // Check the remote copies of tracked files against the manifest: each
+    // must be on its directory's remote (and mirrors), with the manifest's
+    // MD5 and size. Local files are compared by verify(); this compares
+    // what the remotes report, whatever the state of the local copy.
+    pub async fn verify_remotes(
+        &mut self,
+        path_context: &Path,
+        verified: &mut VerifyReport,
+    ) -> Result<()> {
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        let statuses = self.status(path_context, &options).await?;
+        for (directory, entries) in &statuses {
+            for entry in entries.iter().filter(|entry| entry.tracked == Some(true)) {
+                let path = if directory.is_empty() {
+                    entry.name.clone()
                 } else {
-                    skipped.push(url.clone());
+                    format!("{}/{}", directory, entry.name)
+                };
+                let copies = entry
+                    .remote_service
+                    .iter()
+                    .map(|remote| {
+                        (
+                            remote,
+                            &entry.remote_status,
+                            &entry.remote_md5,
+                            entry.remote_size,
+                        )
+                    })
+                    .chain(entry.mirrors.iter().map(|mirror| {
+                        (
+                            &mirror.remote,
+                            &mirror.remote_status,
+                            &mirror.remote_md5,
+                            mirror.remote_size,
+                        )
+                    }));
+                for (remote, status, remote_md5, remote_size) in copies {
+                    let size_differs = remote_size
+                        .zip(entry.manifest_size)
+                        .is_some_and(|(remote, manifest)| remote != manifest);
+                    let (outcome, reason) = if status == &Some(RemoteStatusCode::NotExists) {
+                        verified.remote_missing += 1;
+                        (Outcome::Failed, Some(VERIFY_REMOTE_MISSING))
+                    } else if remote_md5.is_some() && *remote_md5 != entry.manifest_md5 {
+                        verified.remote_mismatched += 1;
+                        (Outcome::Failed, Some(VERIFY_REMOTE_MISMATCH))
+                    } else if size_differs {
+                        verified.remote_mismatched += 1;
+                        (Outcome::Failed, Some(VERIFY_REMOTE_SIZE))
+                    } else if remote_md5.is_none() {
+                        (Outcome::Skipped, Some(VERIFY_NO_REMOTE_MD5))
+                    } else {
+                        (Outcome::Succeeded, None)
+                    };
+                    verified.report.record_on(&path, remote, outcome, reason);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Replace corrupted local copies of the given files with their remote
+    // versions. Downloads are staged, and only replace the local copy once
+    // their MD5 matches the manifest.
+    pub async fn repair_from_remote(
+        &mut self,
+        path_context: &Path,
+        paths: &HashSet<String>,
+    ) -> Result<TransferReport> {
+        let all_files = self.merge(true, Some(paths)).await?;
+
+        let mut downloads = Downloads::new(path_context);
+        let mut sources = HashMap::new();
+        // staged download -> (manifest path, guard)
+        let mut staged = HashMap::new();
+        let mut report = TransferReport::new();
+        for (dir, merged_files) in all_files.iter() {
+            for merged_file in merged_files.values() {
+                let local = match &merged_file.local {
+                    Some(local) if paths.contains(&local.path) => local,
+                    _ => continue,
+                };
+                let path = &local.path;
+                let Some(tracked_dir) = self.tracked_dir_of(dir) else {
+                    report.skipped(path, "no remote");
+                    continue;
+                };
+                let remote = &self.remotes[tracked_dir];
+                if merged_file.remote.is_none() {
+                    report.skipped(path, "not found on the remote");
+                    continue;
+                }
+                if merged_file
+                    .remote_md5()
+                    .is_some_and(|remote_md5| remote_md5 != local.md5)
+                {
+                    report.skipped(path, "remote version differs from the manifest");
+                    continue;
+                }
+                match remote.get_download_info(merged_file, path_context, true) {
+                    Ok(mut download) => {
+                        let staged_file = StagedFile::new(path_context, "repair")?;
+                        let filename = staged_file.path().to_string_lossy().to_string();
+                        download.set_filename(filename.clone());
+                        sources.insert(filename.clone(), tracked_dir.clone());
+                        staged.insert(filename.clone(), (path.clone(), staged_file));
+                        downloads.labels.insert(filename.clone(), path.clone());
+                        downloads.sizes.insert(filename, local.size);
+                        downloads.push(download);
+                    }
+                    Err(err) => report.failed(path, &format!("{:#}", err)),
                 }
             }
         }
 
-        if num_downloaded > 0 {
-            println!("Downloaded:");
+        let downloaded = downloads.retrieve(None, None, false).await?;
+        self.record_downloads(&sources, &downloaded);
+        for outcome in downloaded.files {
+            let (path, staged_file) = staged.remove(&outcome.path).ok_or(anyhow!(
+                "Internal Error: unexpected repair download '{}'.",
+                outcome.path
+            ))?;
+            if outcome.status != Outcome::Succeeded {
+                report.failed(&path, &outcome.reason.unwrap_or_default());
+                continue;
+            }
+            let data_file = self.files.get(&path).ok_or(anyhow!(
+                "Internal Error: repaired file '{}' not in manifest.",
+                path
+            ))?;
+            let md5 = compute_md5(staged_file.path()).await?;
+            if md5.as_ref() == Some(&data_file.md5) {
+                staged_file.persist(&data_file.full_path(path_context)?)?;
+                report.succeeded(&path);
+            } else {
+                report.failed(&path, "download does not match the manifest MD5");
+            }
         }
-        // grab all the files
-        downloads.retrieve(Some(" - {}"), None, false).await?;
+        Ok(report)
+    }
 
-        let num_skipped = skipped.len();
-        println!(
-            "{} files were downloaded.\n\
-                  {} files were skipped because they existed (and --overwrite was not specified).",
-            num_downloaded, num_skipped
-        );
-        Ok(())
+    // Write the contents of a tracked file to writer: the local copy if it
+    // is current, otherwise the remote version, streamed and checked
+    // against the manifest MD5.
+    pub async fn cat<W: std::io::Write>(
+        &mut self,
+        path_context: &Path,
+        path: &str,
+        writer: &mut W,
+    ) -> Result<CatSource> {
+        let data_file = self
+            .files
+            .get(path)
+            .ok_or(anyhow!("File '{}' is not in the manifest.", path))?;
+        if data_file.status(path_context).await? == LocalStatusCode::Current {
+            let mut file = fs::File::open(data_file.full_path(path_context)?)?;
+            std::io::copy(&mut file, writer)?;
+            writer.flush()?;
+            return Ok(CatSource::Local);
+        }
+        let expected_md5 = data_file.md5.clone();
+        let (tracked_dir, merged_file) = self.remote_copy(path, "is not current locally").await?;
+        let remote = &self.remotes[&tracked_dir];
+        remote
+            .stream_to(&merged_file, path_context, &expected_md5, writer)
+            .await?;
+        Ok(CatSource::Remote(remote.name().to_string()))
     }
 
-    // Download all files
-    //
-    // TODO: code redundancy with the push method's tracking of
-    // why stuff is skipped; split out info enum, etc.
-    pub async fn pull(&mut self, path_context: &Path, overwrite: bool) -> Result<()> {
-        let all_files = self.merge(true).await?;
+    // Merge a manifest file with its remote, returning its tracked
+    // directory and the merged file. It is an error if the file is not on
+    // its remote, where context explains why the remote copy is needed.
+    async fn remote_copy(&mut self, path: &str, context: &str) -> Result<(String, MergedFile)> {
+        let paths = HashSet::from([path.to_string()]);
+        let all_files = self.merge(true, Some(&paths)).await?;
+        let (dir, merged_file) = all_files
+            .into_iter()
+            .find_map(|(dir, mut files)| files.remove(path).map(|file| (dir, file)))
+            .ok_or(anyhow!("Internal Error: '{}' was not merged.", path))?;
+        let tracked_dir = self.tracked_dir_of(&dir).ok_or(anyhow!(
+            "File '{}' {}, and its directory has no remote.",
+            path,
+            context
+        ))?;
+        if merged_file.remote.is_none() {
+            return Err(anyhow!(
+                "File '{}' {}, and was not found on {}.",
+                path,
+                context,
+                self.remotes[tracked_dir].name()
+            ));
+        }
+        if merged_file
+            .local
+            .as_ref()
+            .is_some_and(|local| local.is_stored_as_copy())
+        {
+            return Err(anyhow!(
+                "File '{}' {}, and its remote copy is compressed or encrypted, so cannot \
+                 be read directly; use 'sdf pull' to restore it.",
+                path,
+                context
+            ));
+        }
+        Ok((tracked_dir.clone(), merged_file))
+    }
+
+    // Compare a local file with its copy on the remote (or with remote,
+    // on the first of its directory's remotes of that name, e.g. a
+    // mirror), which is downloaded to a staged file. Files larger than
+    // max_size (locally or on the remote) are not compared.
+    pub async fn diff_remote(
+        &mut self,
+        path_context: &Path,
+        path: &str,
+        max_size: u64,
+        remote: Option<&str>,
+    ) -> Result<ContentDiff> {
+        let Some(name) = remote else {
+            return self.diff_pass(path_context, path, max_size).await;
+        };
+        let remotes = self.remote_passes(Some(name))?.swap_remove(0);
+        let saved = self.begin_pass(remotes);
+        let result = self.diff_pass(path_context, path, max_size).await;
+        self.end_pass(saved);
+        result
+    }
+
+    async fn diff_pass(
+        &mut self,
+        path_context: &Path,
+        path: &str,
+        max_size: u64,
+    ) -> Result<ContentDiff> {
+        let data_file = self
+            .files
+            .get(path)
+            .ok_or(anyhow!("File '{}' is not in the manifest.", path))?;
+        let local_path = data_file.full_path(path_context)?;
+        let local_size = metadata(&local_path)
+            .map_err(|_| anyhow!("File '{}' does not exist locally.", path))?
+            .len();
+        let (tracked_dir, merged_file) = self.remote_copy(path, "is being compared").await?;
+        let remote = &self.remotes[&tracked_dir];
+        let remote_file = merged_file
+            .remote
+            .as_ref()
+            .expect("checked by remote_copy()");
+        let size = local_size.max(remote_file.size.unwrap_or(0));
+        if size > max_size {
+            return Err(anyhow!(
+                "File '{}' is too large to compare ({}, the limit is {}); use --max-size to raise the limit.",
+                path,
+                format_bytes(size),
+                format_bytes(max_size)
+            ));
+        }
+        let remote_md5 = remote_file.get_md5().ok_or(anyhow!(
+            "{} does not report an MD5 for '{}', so its copy cannot be verified.",
+            remote.name(),
+            path
+        ))?;
+
+        let staged = StagedFile::new(path_context, "diff")?;
+        let mut file = fs::File::create(staged.path())?;
+        remote
+            .stream_to(&merged_file, path_context, &remote_md5, &mut file)
+            .await?;
+        diff_files(
+            &local_path,
+            staged.path(),
+            &format!("local/{}", path),
+            &format!("{}/{}", remote.name(), path),
+        )
+    }
+
+    // Account downloads (which do not go through the remotes' APIs) to
+    // the remotes they came from.
+    fn record_downloads(&self, sources: &HashMap<String, String>, downloaded: &TransferReport) {
+        for file in &downloaded.files {
+            let counter = sources
+                .get(&file.path)
+                .and_then(|dir| self.remotes.get(dir))
+                .and_then(|remote| remote.usage());
+            let Some(counter) = counter else {
+                continue;
+            };
+            let size = match file.status {
+                Outcome::Succeeded => fs::metadata(&file.path).map(|m| m.len()).unwrap_or(0),
+                _ => 0,
+            };
+            counter.record(Operation::Get, 0, size);
+        }
+    }
+
+    // Request and byte counts of each remote during this command.
+    pub fn usage(&self) -> Vec<RemoteUsage> {
+        let mut dirs: Vec<&String> = self.remotes.keys().collect();
+        dirs.sort();
+        let mut usage = Vec::new();
+        for dir in dirs {
+            for remote in self.remotes_of(dir) {
+                let Some(counter) = remote.usage() else {
+                    continue;
+                };
+                for (operation, counts) in counter.snapshot() {
+                    usage.push(RemoteUsage {
+                        directory: dir.clone(),
+                        remote: remote.name().to_string(),
+                        operation,
+                        usage: counts,
+                    });
+                }
+            }
+        }
+        usage
+    }
 
-        let mut downloads = Downloads::new();
+    // Latency of each remote's API requests during this command.
+    pub fn latency(&self) -> Vec<RemoteLatency> {
+        let mut dirs: Vec<&String> = self.remotes.keys().collect();
+        dirs.sort();
+        dirs.into_iter()
+            .flat_map(|dir| {
+                self.remotes_of(dir)
+                    .into_iter()
+                    .map(move |remote| (dir, remote))
+            })
+            .filter_map(|(dir, remote)| {
+                let latency = remote.usage()?.latency()?;
+                Some(RemoteLatency {
+                    directory: dir.clone(),
+                    remote: remote.name().to_string(),
+                    latency,
+                })
+            })
+            .collect()
+    }
 
-        let mut current_skipped = Vec::new();
-        let mut messy_skipped = Vec::new();
-        let mut overwrite_skipped = Vec::new();
+    // Decide which files with URLs to download (in the size range). This
+    // makes no requests.
+    fn queue_url_downloads(
+        &self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+    ) -> Result<(Vec<QueuedTransfer>, TransferReport)> {
+        let mut report = TransferReport::new();
+        let mut queue = Vec::new();
+        for data_file in self.files.values().filter(|f| filter.matches_entry(f)) {
+            let Some(url) = &data_file.url else {
+                continue;
+            };
+            let exists = data_file.full_path(path_context)?.exists();
+            if exists && !overwrite {
+                report.skipped(&data_file.path, SKIP_EXISTS);
+                continue;
+            }
+            let reason = if exists {
+                TRANSFER_URL_OVERWRITE
+            } else {
+                TRANSFER_MISSING_LOCALLY
+            };
+            queue.push(QueuedTransfer {
+                planned: PlannedTransfer {
+                    path: data_file.path.clone(),
+                    direction: Direction::Download,
+                    size: Some(data_file.size),
+                    reason: format!("{} ({})", reason, url),
+                    remote: None,
+                },
+                tracked_dir: None,
+                merged_file: MergedFile {
+                    local: Some(data_file.clone()),
+                    remote: None,
+                    remote_service: None,
+                },
+                expected_md5: None,
+            });
+        }
+        queue.sort_by(|a, b| a.planned.path.cmp(&b.planned.path));
+        Ok((queue, report))
+    }
 
+    // What 'sdf pull --urls' would download and skip, without downloading
+    // anything.
+    pub fn pull_urls_plan(
+        &self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+    ) -> Result<TransferPlan> {
+        let (queue, report) = self.queue_url_downloads(path_context, overwrite, filter)?;
+        Ok(to_plan(queue, report))
+    }
+
+    pub async fn pull_urls(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        max_concurrent: usize,
+    ) -> Result<TransferReport> {
+        let mut downloads = Downloads::new(path_context);
+        downloads.max_concurrent = max_concurrent;
+        let (queue, mut report) = self.queue_url_downloads(path_context, overwrite, filter)?;
+        for queued in queue {
+            let Some(data_file) = queued.merged_file.local else {
+                continue;
+            };
+            let Some(url) = data_file.url.clone() else {
+                continue;
+            };
+            let full_path = data_file.full_path(path_context)?;
+            let filename = full_path.to_string_lossy().to_string();
+            if downloads.add(url, Some(&filename), overwrite)?.is_none() {
+                report.skipped(&data_file.path, SKIP_EXISTS);
+            } else {
+                downloads.sizes.insert(filename, data_file.size);
+            }
+        }
+
+        // grab all the files (which the report then lists)
+        let downloaded = downloads.retrieve(None, None, false).await?;
+        report.extend(downloaded.relative_to(path_context));
+        Ok(report)
+    }
+
+    // Decide which of the merged files (matching the filter's paths and
+    // size range) to download. This makes no requests.
+    async fn queue_downloads(
+        &self,
+        path_context: &Path,
+        all_files: &HashMap<String, HashMap<String, MergedFile>>,
+        overwrite: bool,
+        filter: &FileFilter,
+    ) -> Result<(Vec<QueuedTransfer>, TransferReport)> {
+        let mut report = TransferReport::new();
+        let mut queue = Vec::new();
         for (dir, merged_files) in all_files.iter() {
             // can_download() is true only if local and remote are not None.
             // (local file can be deleted, but will only be None if not in manifest also)
-            for merged_file in merged_files.values().filter(|f| f.can_download()) {
-                let path = merged_file.name()?;
+            let selected = |f: &&MergedFile| {
+                f.local
+                    .as_ref()
+                    .is_some_and(|local| filter.matches_entry(local))
+            };
+            for merged_file in merged_files
+                .values()
+                .filter(|f| f.can_download())
+                .filter(selected)
+            {
+                let path = PathBuf::from(dir)
+                    .join(merged_file.name()?)
+                    .to_string_lossy()
+                    .to_string();
 
-                let do_download = match merged_file.status(path_context).await? {
-                    RemoteStatusCode::NoLocal => {
-                        return Err(anyhow!("Internal error: execution should not have reached this point, please report.\n\
-                                           'sdf pull' filtered by MergedFile.can_download() but found a RemoteStatusCode::NoLocal status."));
-                    }
-                    RemoteStatusCode::Current => {
-                        current_skipped.push(path);
-                        false
-                    }
-                    RemoteStatusCode::Exists => {
-                        // it exists on the remote, but we cannot
-                        // compare MD5s. Push only if overwrite is true.
-                        if !overwrite {
-                            overwrite_skipped.push(path);
-                        }
-                        overwrite
-                    }
-                    RemoteStatusCode::MessyLocal => {
-                        messy_skipped.push(path);
-                        false
+                let status = merged_file.status(path_context).await?;
+                let reason = match pull_action(&status, overwrite) {
+                    TransferAction::Transfer(reason) => reason,
+                    TransferAction::Skip(reason) => {
+                        report.skipped(&path, reason);
+                        continue;
                     }
-                    RemoteStatusCode::Invalid => {
-                        return Err(anyhow!("A file ({:}) with RemoteStatusCode::Invalid was encountered. Please report.", path));
+                    TransferAction::Nothing => continue,
+                    TransferAction::Invalid => {
+                        return Err(anyhow!("A file ({:}) with RemoteStatusCode::{:?} was encountered. Please report.", path, status));
                     }
-                    RemoteStatusCode::Different => {
-                        // TODO if remote supports modification times,
-                        // could do extra comparison here
-                        info!("skipping {:} {:}", path, overwrite);
-                        if !overwrite {
-                            overwrite_skipped.push(path);
-                        }
-                        overwrite
+                };
+                let Some(tracked_dir) = self.tracked_dir_of(dir) else {
+                    continue;
+                };
+                // restored files must match the manifest; overwritten
+                // ones, the remote (if it has MD5s)
+                let expected_md5 = match status {
+                    RemoteStatusCode::DeletedLocal | RemoteStatusCode::NotExists => {
+                        merged_file.local.as_ref().map(|local| local.md5.clone())
                     }
-                    RemoteStatusCode::DeletedLocal => true,
-                    RemoteStatusCode::NotExists => true,
+                    _ => merged_file.remote_md5(),
                 };
+                let size = merged_file
+                    .remote_size()
+                    .or(merged_file.local.as_ref().map(|local| local.size));
+                queue.push(QueuedTransfer {
+                    planned: PlannedTransfer {
+                        path,
+                        direction: Direction::Download,
+                        size,
+                        reason: reason.to_string(),
+                        remote: None,
+                    },
+                    tracked_dir: Some(tracked_dir.clone()),
+                    merged_file: merged_file.clone(),
+                    expected_md5,
+                });
+            }
+        }
+        queue.sort_by(|a, b| a.planned.path.cmp(&b.planned.path));
+        Ok((queue, report))
+    }
 
-                if do_download {
-                    if let Some(remote) = self.remotes.get(dir) {
-                        let download =
-                            remote.get_download_info(merged_file, path_context, overwrite)?;
-                        downloads.queue.push(download);
-                    }
-                }
+    // What pull would download and skip, without downloading anything.
+    pub async fn pull_plan(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        remote: Option<&str>,
+    ) -> Result<TransferPlan> {
+        let mut plan = TransferPlan::default();
+        for remotes in self.remote_passes(remote)? {
+            let saved = self.begin_pass(remotes);
+            let mut result = self.pull_plan_pass(path_context, overwrite, filter).await;
+            if let Ok(pass_plan) = result.as_mut() {
+                self.name_plan_remotes(pass_plan);
             }
+            self.end_pass(saved);
+            plan.extend(result?);
         }
+        Ok(plan)
+    }
 
-        // now retrieve all the files in the queue.
-        downloads
-            .retrieve(Some(" - {}"), Some("No files downloaded."), true)
+    async fn pull_plan_pass(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+    ) -> Result<TransferPlan> {
+        let candidates = self.candidates(filter, path_context);
+        let all_files = self.merge(true, candidates.as_ref()).await?;
+        let (queue, report) = self
+            .queue_downloads(path_context, &all_files, overwrite, filter)
             .await?;
+        Ok(to_plan(queue, report))
+    }
 
-        let num_skipped = overwrite_skipped.len() + current_skipped.len() + messy_skipped.len();
-        println!("Skipped {} files. Reasons:", num_skipped);
-        if !current_skipped.is_empty() {
-            println!(
-                "  Remote file is indentical to local file: {}",
-                pluralize(current_skipped.len() as u64, "file")
-            );
-            for path in current_skipped {
-                println!("   - {:}", path);
-            }
-        }
-        if !overwrite_skipped.is_empty() {
-            println!(
-                "  Would overwrite (use --overwrite to push): {}",
-                pluralize(overwrite_skipped.len() as u64, "file")
-            );
-            for path in overwrite_skipped {
-                println!("   - {:}", path);
+    // Download all files (matching the filter), from each directory's
+    // remote, then from its mirrors (so files missing from the remote are
+    // restored from a mirror), or with remote, only from the remotes of
+    // that name.
+    pub async fn pull(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        max_concurrent: usize,
+        remote: Option<&str>,
+    ) -> Result<TransferReport> {
+        let mut report = TransferReport::new();
+        for remotes in self.remote_passes(remote)? {
+            let saved = self.begin_pass(remotes);
+            let mut result = self
+                .pull_pass(path_context, overwrite, filter, max_concurrent)
+                .await;
+            if let Ok(pass_report) = result.as_mut() {
+                self.name_remotes(pass_report);
             }
+            self.end_pass(saved);
+            report.extend(result?);
         }
-        if !messy_skipped.is_empty() {
-            println!(
-                "  Local is \"messy\" (manifest and file disagree): {}",
-                pluralize(messy_skipped.len() as u64, "file")
-            );
-            println!("  Use 'sdf update <FILE>' to add the current version to the manifest.");
-            for path in messy_skipped {
-                println!("   - {:}", path);
+        Ok(report)
+    }
+
+    async fn pull_pass(
+        &mut self,
+        path_context: &Path,
+        overwrite: bool,
+        filter: &FileFilter,
+        max_concurrent: usize,
+    ) -> Result<TransferReport> {
+        // as in push_pass(), files outside the filter are neither hashed
+        // nor fetched
+        let candidates = self.candidates(filter, path_context);
+        let all_files = self.merge(true, candidates.as_ref()).await?;
+        let (queue, mut report) = self
+            .queue_downloads(path_context, &all_files, overwrite, filter)
+            .await?;
+
+        let mut downloads = Downloads::new(path_context);
+        // downloads share one pool, so the strictest remote's limit (see
+        // Remote::max_concurrent_transfers()) applies to them all
+        let remote_limit = queue
+            .iter()
+            .filter_map(|queued| queued.tracked_dir.as_ref())
+            .filter_map(|dir| self.remotes[dir].max_concurrent_transfers())
+            .min();
+        downloads.max_concurrent =
+            remote_limit.map_or(max_concurrent, |limit| limit.min(max_concurrent));
+        // download destination -> tracked directory, for usage accounting
+        let mut sources = HashMap::new();
+        // read once, and only if a file is encrypted
+        let mut key = None;
+        for queued in queue {
+            let Some(tracked_dir) = queued.tracked_dir else {
+                continue;
+            };
+            let remote = &self.remotes[&tracked_dir];
+            let path = &queued.planned.path;
+            let stored = queued
+                .merged_file
+                .local
+                .as_ref()
+                .filter(|local| local.is_stored_as_copy());
+            // encrypted copies are not downloaded if they cannot be decrypted
+            let decryption = match stored.filter(|local| local.encryption.is_some()) {
+                Some(local) => {
+                    let nonce = local.stored.as_ref().and_then(|copy| copy.nonce.clone());
+                    let given = self.encryption_key.as_ref();
+                    match (
+                        key.get_or_insert_with(|| EncryptionKey::given_or_env(given)),
+                        nonce,
+                    ) {
+                        (Ok(key), Some(nonce)) => Some((key.clone(), nonce)),
+                        (Err(err), _) => {
+                            report.failed(path, &format!("{:#}", err));
+                            continue;
+                        }
+                        (Ok(_), None) => {
+                            report.failed(
+                                path,
+                                "its remote copy is encrypted, but the manifest has no \
+                                 record of how (it was not pushed from this manifest)",
+                            );
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+            match remote.get_download_info(&queued.merged_file, path_context, overwrite) {
+                Ok(download) => {
+                    let filename = download.filename().to_string();
+                    sources.insert(filename.clone(), tracked_dir.clone());
+                    if let Some(local) = stored {
+                        // the stored copy is checked against its own MD5, if
+                        // known, and once restored, against the file's
+                        // (unless that is not known either)
+                        let remote_md5 = queued.merged_file.remote_md5();
+                        let stored_md5 = match &local.stored {
+                            Some(copy) if remote_md5 == Some(copy.source_md5.clone()) => {
+                                downloads.sizes.insert(filename.clone(), copy.size);
+                                Some(copy.md5.clone())
+                            }
+                            _ => remote_md5,
+                        };
+                        let md5 = queued
+                            .expected_md5
+                            .filter(|md5| Some(md5) != stored_md5.as_ref());
+                        if let Some(stored_md5) = stored_md5 {
+                            downloads.expect_md5(&filename, &stored_md5);
+                        }
+                        downloads.restore(
+                            &filename,
+                            Restore {
+                                decryption,
+                                compression: local.compression,
+                                md5,
+                            },
+                        );
+                    } else if let Some(md5) = queued.expected_md5 {
+                        downloads.expect_md5(&filename, &md5);
+                    }
+                    if let Some(size) = queued.planned.size {
+                        downloads.sizes.entry(filename.clone()).or_insert(size);
+                    }
+                    downloads.push(download)
+                }
+                Err(err) => report.failed(path, &format!("{:#}", err)),
             }
         }
 
-        Ok(())
+        // now retrieve all the files in the queue.
+        let downloaded = downloads
+            .retrieve(Some(" - {}"), Some("No files downloaded."), true)
+            .await?;
+        self.record_downloads(&sources, &downloaded);
+        report.extend(downloaded.relative_to(path_context));
+        Ok(report)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::lib::api::figshare::{FigShareAPI, FIGSHARE_BASE_URL};
-    use crate::lib::remote::Remote;
+    use crate::lib::checksum::{ChecksumAlgo, EMPTY_MD5};
+    use crate::lib::filter::{parse_since, parse_size, FileFilter, SizeRange};
+    use crate::lib::md5_cache;
+    use crate::lib::remote::{Remote, RemoteFile, RemoteStatusCode};
+    use crate::lib::report::Outcome;
     use crate::lib::test_utilities::check_error;
-
-    use super::{DataCollection, DataFile};
+    use crate::lib::utils::{
+        compute_checksum, format_status_lines, group_by_extension, sort_directories, status_record,
+        DirSort, StatusDisplay, StatusLinks,
+    };
+
+    use super::{
+        DataCollection, DataCollectionMetadata, DataFile, LocalStatusCode, MergedFile,
+        QueuedTransfer, StatusEntry, StatusFilter, StatusOptions, TRANSFER_DIFFERENT,
+        TRANSFER_MISSING_LOCALLY, TRANSFER_NOT_ON_REMOTE,
+    };
+    use colored::{Color, Colorize};
+    use httpmock::prelude::*;
+    use std::collections::{BTreeMap, HashMap};
     use std::io::Write;
     use std::path::Path;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
     fn mock_data_file() -> NamedTempFile {
-        let temp_file = NamedTempFile::new().unwrap();
-        temp_file
+        NamedTempFile::new().unwrap()
     }
 
     #[tokio::test]
@@ -1221,9 +3865,9 @@ mod tests {
         let nonexistent_path = "some/nonexistent/path".to_string();
         let path_context = Path::new("");
 
-        let result = DataFile::new(nonexistent_path, None, &path_context).await;
+        let result = DataFile::new(nonexistent_path, None, path_context).await;
         match result {
-            Ok(_) => assert!(false, "Expected an error, but got Ok"),
+            Ok(_) => panic!("Expected an error, but got Ok"),
             Err(err) => {
                 assert!(
                     err.to_string().contains("does not exist"),
@@ -1244,14 +3888,71 @@ mod tests {
 
         // Make a DataFile
         let path = file.path().to_string_lossy().to_string();
-        let data_file = DataFile::new(path, None, &path_context).await.unwrap();
+        let data_file = DataFile::new(path, None, path_context).await.unwrap();
 
         // Compare MD5s
         let expected_md5 = "d3feb335769173b2db573413b0f6abf4".to_string();
-        let observed_md5 = data_file.get_md5(&path_context).await.unwrap().unwrap();
+        let observed_md5 = data_file.get_md5(path_context).await.unwrap().unwrap();
         assert!(observed_md5 == expected_md5, "MD5 mismatch!");
     }
 
+    #[tokio::test]
+    async fn test_status_by_recorded_checksum() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::write(path_context.join("reads.fa"), "Mock data.\n").unwrap();
+
+        // a legacy, MD5-only entry
+        let legacy: DataFile = serde_yaml::from_str(
+            "path: reads.fa\ntracked: true\nmd5: d3feb335769173b2db573413b0f6abf4\n\
+             size: 11\nurl: null\n",
+        )
+        .unwrap();
+        assert_eq!(legacy.algo, ChecksumAlgo::Md5);
+        assert_eq!(
+            legacy.status(path_context).await.unwrap(),
+            LocalStatusCode::Current
+        );
+        assert!(!serde_yaml::to_string(&legacy).unwrap().contains("algo"));
+
+        // a SHA-256 entry is compared by its SHA-256, even with a stale MD5
+        let sha256 = DataFile {
+            md5: "0".repeat(32),
+            checksums: BTreeMap::from([(
+                ChecksumAlgo::Sha256,
+                compute_checksum(&path_context.join("reads.fa"), ChecksumAlgo::Sha256)
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            )]),
+            algo: ChecksumAlgo::Sha256,
+            ..legacy.clone()
+        };
+        let yaml = serde_yaml::to_string(&sha256).unwrap();
+        let sha256: DataFile = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            sha256.status(path_context).await.unwrap(),
+            LocalStatusCode::Current
+        );
+        assert_eq!(
+            sha256
+                .status_by(path_context, ChecksumAlgo::Md5)
+                .await
+                .unwrap(),
+            LocalStatusCode::Modified
+        );
+
+        std::fs::write(path_context.join("reads.fa"), "Mock data?\n").unwrap();
+        assert_eq!(
+            sha256.status(path_context).await.unwrap(),
+            LocalStatusCode::Modified
+        );
+        assert_eq!(
+            legacy.status(path_context).await.unwrap(),
+            LocalStatusCode::Modified
+        );
+    }
+
     #[tokio::test]
     async fn test_size() {
         let path_context = Path::new("");
@@ -1262,7 +3963,7 @@ mod tests {
 
         // Make a DataFile
         let path = file.path().to_string_lossy().to_string();
-        let data_file = DataFile::new(path, None, &path_context).await.unwrap();
+        let data_file = DataFile::new(path, None, path_context).await.unwrap();
 
         // Let's also check size
         assert!(
@@ -1283,14 +3984,14 @@ mod tests {
 
         // Make a DataFile
         let path = file.path().to_string_lossy().to_string();
-        let mut data_file = DataFile::new(path, None, &path_context).await.unwrap();
+        let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
 
         // Now, we change the data.
         writeln!(file, "Modified mock data.").unwrap();
 
         // Make sure the file MD5 is right
         let expected_md5 = "c6526ab1de615b49e53398ae5588bd00".to_string();
-        let observed_md5 = data_file.get_md5(&path_context).await.unwrap().unwrap();
+        let observed_md5 = data_file.get_md5(path_context).await.unwrap().unwrap();
         assert!(observed_md5 == expected_md5);
 
         // Make sure the old MD5 is in the DataFile
@@ -1315,7 +4016,7 @@ mod tests {
 
         // Make a DataFile
         let path = file.path().to_string_lossy().to_string();
-        let mut data_file = DataFile::new(path, None, &path_context).await.unwrap();
+        let mut data_file = DataFile::new(path, None, path_context).await.unwrap();
 
         // Now, we change the data.
         writeln!(file, "Modified mock data.").unwrap();
@@ -1354,4 +4055,1053 @@ mod tests {
         let result = dc.register_remote(&dir, Remote::FigShareAPI(figshare));
         check_error(result, "already tracked");
     }
+
+    #[tokio::test]
+    async fn test_file_info_of_modified_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(path_context.join("data/counts.tsv"), "gene\tcount\n").unwrap();
+        let mut dc = DataCollection::new();
+        let figshare = |name| FigShareAPI::new(name, Some(FIGSHARE_BASE_URL.to_string())).unwrap();
+        dc.register_remote(
+            &"data".to_string(),
+            Remote::FigShareAPI(figshare("archive")),
+        )
+        .unwrap();
+        dc.register_mirror(&"data".to_string(), Remote::FigShareAPI(figshare("copy")))
+            .unwrap();
+        let mut data_file = DataFile::new("data/counts.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        data_file.tracked = true;
+        let manifest_md5 = data_file.md5.clone();
+        dc.register(data_file).unwrap();
+        std::fs::write(
+            path_context.join("data/counts.tsv"),
+            "gene\tcount\nBRCA1\t7\n",
+        )
+        .unwrap();
+
+        let info = dc
+            .file_info(path_context, "data/counts.tsv", false)
+            .await
+            .unwrap();
+        let rows: HashMap<String, String> = info
+            .rows()
+            .into_iter()
+            .map(|row| (row[0].clone(), row[1].clone()))
+            .collect();
+        assert_eq!(rows["path"], "data/counts.tsv");
+        assert_eq!(rows["tracked"], "true");
+        assert_eq!(rows["size"], "11 B");
+        assert_eq!(rows["manifest md5"], manifest_md5);
+        assert_eq!(
+            rows["current md5"],
+            format!("{:x}", md5::compute("gene\tcount\nBRCA1\t7\n"))
+        );
+        assert_ne!(rows["modified"], "(missing)");
+        assert_eq!(rows["local status"], "changed");
+        assert_eq!(rows["remotes"], "FigShare, FigShare");
+        assert_eq!(rows["remote status"], "(not fetched; use --remotes)");
+
+        let err = dc
+            .file_info(path_context, "data/other.tsv", false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not in the manifest"));
+    }
+
+    #[test]
+    fn test_mirrors_in_manifest() {
+        let mut dc = DataCollection::new();
+        let figshare = |name| FigShareAPI::new(name, Some(FIGSHARE_BASE_URL.to_string())).unwrap();
+        let dir = "data".to_string();
+        dc.register_remote(&dir, Remote::FigShareAPI(figshare("archive")))
+            .unwrap();
+        // manifests without mirrors are unchanged
+        assert!(!serde_yaml::to_string(&dc).unwrap().contains("mirrors"));
+
+        let result = dc.register_mirror(&"results".to_string(), Remote::FigShareAPI(figshare("x")));
+        check_error(result, "not linked to a remote");
+        dc.register_mirror(&dir, Remote::FigShareAPI(figshare("copy")))
+            .unwrap();
+        let yaml = serde_yaml::to_string(&dc).unwrap();
+        assert!(yaml.contains("mirrors:\n  data:\n  - !FigShareAPI"));
+        let read: DataCollection = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(read.remotes_of("data").len(), 2);
+        assert!(read.remotes_of("data")[1].matches("figshare"));
+    }
+
+    #[test]
+    fn test_deposit_metadata_in_manifest() {
+        let yaml = "\
+title: Ancient DNA analysis
+description: Data for the paper.
+creators:
+- name: Joan B. Scientist
+  orcid: 0000-0002-1825-0097
+license: cc-by-4.0
+keywords: [aDNA]
+directories:
+  data/supplement:
+    title: Supplementary tables
+    keywords: [aDNA, tables]
+";
+        let metadata: DataCollectionMetadata = serde_yaml::from_str(yaml).unwrap();
+        let supplement = metadata.deposit("data/supplement");
+        assert_eq!(supplement.title.as_deref(), Some("Supplementary tables"));
+        assert_eq!(supplement.keywords, vec!["aDNA", "tables"]);
+        assert_eq!(supplement.license.as_deref(), Some("cc-by-4.0"));
+        assert_eq!(
+            supplement.creators[0].orcid.as_deref(),
+            Some("0000-0002-1825-0097")
+        );
+        // other deposits are titled by their remote's name
+        let other = metadata.deposit("data/raw");
+        assert_eq!(other.title, None);
+        assert_eq!(other.description.as_deref(), Some("Data for the paper."));
+        assert_eq!(other.keywords, vec!["aDNA"]);
+
+        // manifests without deposit metadata are unchanged
+        let plain = DataCollectionMetadata {
+            title: metadata.title.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_yaml::to_string(&plain).unwrap(),
+            "title: Ancient DNA analysis\ndescription: null\n"
+        );
+        assert!(DataCollectionMetadata::default().deposit("data").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remote_size_mismatch_not_current() {
+        let path_context = Path::new("");
+        let mut file = mock_data_file();
+        writeln!(file, "Mock data.").unwrap();
+
+        let path = file.path().to_string_lossy().to_string();
+        let data_file = DataFile::new(path, None, path_context).await.unwrap();
+
+        // a mock remote file that reports the same MD5, but a truncated size
+        let mut remote_file = RemoteFile {
+            name: data_file.basename().unwrap(),
+            md5: Some(data_file.md5.clone()),
+            size: Some(data_file.size - 1),
+            remote_service: "Zenodo".to_string(),
+            url: None,
+        };
+        let merged_file =
+            MergedFile::new(&data_file, &remote_file, Some("Zenodo".to_string())).unwrap();
+        let status = merged_file.status(path_context).await.unwrap();
+        assert_eq!(status, RemoteStatusCode::SizeMismatch);
+
+        // with agreeing sizes, the remote is current
+        remote_file.set_size(data_file.size);
+        let merged_file =
+            MergedFile::new(&data_file, &remote_file, Some("Zenodo".to_string())).unwrap();
+        let status = merged_file.status(path_context).await.unwrap();
+        assert_eq!(status, RemoteStatusCode::Current);
+    }
+
+    #[tokio::test]
+    async fn test_empty_file_status() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::write(path_context.join("placeholder.txt"), "").unwrap();
+        let mut data_file = DataFile::new("placeholder.txt".to_string(), None, path_context)
+            .await
+            .unwrap();
+        data_file.tracked = true;
+        assert_eq!(data_file.md5, EMPTY_MD5);
+        assert_eq!(
+            data_file.status(path_context).await.unwrap(),
+            LocalStatusCode::Current
+        );
+        let remote_status = |remote: Option<RemoteFile>| {
+            let merged_file = MergedFile {
+                local: Some(data_file.clone()),
+                remote,
+                remote_service: Some("Zenodo".to_string()),
+            };
+            async move { merged_file.status(path_context).await.unwrap() }
+        };
+        let remote = |md5: Option<&str>, size| RemoteFile {
+            name: "placeholder.txt".to_string(),
+            md5: md5.map(|md5| md5.to_string()),
+            size: Some(size),
+            remote_service: "Zenodo".to_string(),
+            url: None,
+        };
+
+        // local only
+        assert_eq!(remote_status(None).await, RemoteStatusCode::NotExists);
+        // an empty remote matches, whether or not it reports an MD5
+        for md5 in [Some(EMPTY_MD5), Some(""), None] {
+            let status = remote_status(Some(remote(md5, 0))).await;
+            assert_eq!(status, RemoteStatusCode::Current);
+        }
+        // but a remote with content does not
+        let md5 = format!("{:x}", md5::compute("draft"));
+        let status = remote_status(Some(remote(Some(&md5), 5))).await;
+        assert_eq!(status, RemoteStatusCode::Different);
+
+        // an empty file recorded without an MD5 is current while empty
+        let unhashed = DataFile {
+            md5: String::new(),
+            ..data_file.clone()
+        };
+        assert_eq!(
+            unhashed.status(path_context).await.unwrap(),
+            LocalStatusCode::Current
+        );
+        std::fs::write(path_context.join("placeholder.txt"), "draft").unwrap();
+        assert_eq!(
+            unhashed.status(path_context).await.unwrap(),
+            LocalStatusCode::Modified
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pull_report_lists_failed_file() {
+        let server = MockServer::start();
+        let ok_mock = server.mock(|when, then| {
+            when.method(GET).path("/good.tsv");
+            then.status(200).body("a\tb\n");
+        });
+        let fail_mock = server.mock(|when, then| {
+            when.method(GET).path("/bad.tsv");
+            then.status(404);
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut dc = DataCollection::new();
+        for name in ["good.tsv", "bad.tsv"] {
+            let data_file = DataFile {
+                path: format!("data/{}", name),
+                tracked: false,
+                md5: "".to_string(),
+                size: 0,
+                url: Some(server.url(format!("/{}", name))),
+                modified: None,
+                remote_md5: None,
+                remote_size: None,
+                remote: None,
+                checksums: Default::default(),
+                algo: Default::default(),
+                block_hashes: None,
+                compression: None,
+                encryption: None,
+                stored: None,
+            };
+            dc.register(data_file).unwrap();
+        }
+
+        let report = dc
+            .pull_urls(path_context, false, &FileFilter::default(), 2)
+            .await
+            .unwrap();
+        ok_mock.assert();
+        fail_mock.assert();
+
+        let failed: Vec<_> = report
+            .files
+            .iter()
+            .filter(|f| f.status == Outcome::Failed)
+            .collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].path, "data/bad.tsv");
+        assert!(failed[0].reason.as_ref().unwrap().contains("404"));
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert!(path_context.join("data/good.tsv").exists());
+    }
+
+    #[tokio::test]
+    async fn test_nfd_file_matches_nfc_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        // "café.tsv" with a decomposed é (as written by macOS)
+        let nfd_name = "cafe\u{301}.tsv";
+        let nfc_name = "caf\u{e9}.tsv";
+        std::fs::write(path_context.join(nfd_name), "a\tb\n").unwrap();
+
+        let data_file = DataFile::new(nfd_name.to_string(), None, path_context)
+            .await
+            .unwrap();
+        assert_eq!(data_file.path, nfc_name);
+
+        let mut dc = DataCollection::new();
+        dc.register(data_file.clone()).unwrap();
+        assert!(dc.contains(nfd_name).await.unwrap());
+        assert!(data_file.is_alive(path_context));
+        let status = data_file.status(path_context).await.unwrap();
+        assert_eq!(status, LocalStatusCode::Current);
+    }
+
+    #[tokio::test]
+    async fn test_push_candidates_since() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        for name in ["data/old.tsv", "data/new.tsv"] {
+            std::fs::write(path_context.join(name), "a\tb\n").unwrap();
+            let data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            dc.register(data_file).unwrap();
+        }
+        let old_time = std::time::SystemTime::now() - std::time::Duration::from_secs(30 * 86400);
+        std::fs::File::options()
+            .write(true)
+            .open(path_context.join("data/old.tsv"))
+            .unwrap()
+            .set_modified(old_time)
+            .unwrap();
+
+        let filter = FileFilter {
+            paths: vec!["data".to_string()],
+            since: Some(parse_since("7d").unwrap()),
+            ..Default::default()
+        };
+        let candidates = dc.candidates(&filter, path_context).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates.contains("data/new.tsv"));
+
+        // without a filter, every file is a candidate
+        assert!(dc
+            .candidates(&FileFilter::default(), path_context)
+            .is_none());
+
+        // files outside the given paths are not candidates
+        let filter = FileFilter {
+            paths: vec!["other".to_string()],
+            ..Default::default()
+        };
+        assert!(dc.candidates(&filter, path_context).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_candidates_resume_from() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let mut dc = DataCollection::new();
+        for name in ["data/a.tsv", "data/c.tsv", "data/raw/b.tsv", "data/d.tsv"] {
+            std::fs::write(path_context.join(name), "a\tb\n").unwrap();
+            let data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            dc.register(data_file).unwrap();
+        }
+
+        let filter = FileFilter {
+            resume_from: Some("data/d.tsv".to_string()),
+            ..Default::default()
+        };
+        let candidates = dc.candidates(&filter, path_context).unwrap();
+        let mut candidates: Vec<_> = candidates.into_iter().collect();
+        candidates.sort();
+        // entries before the resume point (in path order) are skipped
+        assert_eq!(candidates, ["data/d.tsv", "data/raw/b.tsv"]);
+    }
+
+    #[tokio::test]
+    async fn test_push_candidates_min_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(
+            path_context.join("data/large.bin"),
+            vec![0; 11 * 1024 * 1024],
+        )
+        .unwrap();
+        std::fs::write(path_context.join("data/small.tsv"), "Mock data.").unwrap();
+        let mut dc = DataCollection::new();
+        for path in ["data/large.bin", "data/small.tsv"] {
+            let data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            dc.register(data_file).unwrap();
+        }
+
+        // i.e. sdf push --min-size 10MB
+        let filter = FileFilter {
+            size: SizeRange {
+                min: Some(parse_size("10MB").unwrap()),
+                max: None,
+            },
+            ..Default::default()
+        };
+        let candidates = dc.candidates(&filter, path_context).unwrap();
+        assert_eq!(
+            candidates.into_iter().collect::<Vec<_>>(),
+            ["data/large.bin"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_remote_status_offline() {
+        // any request to the remote would hit this
+        let server = MockServer::start();
+        let any_mock = server.mock(|_, then| {
+            then.status(500);
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        let figshare = FigShareAPI::new("Test remote", Some(server.url(""))).unwrap();
+        dc.register_remote(&"data".to_string(), Remote::FigShareAPI(figshare))
+            .unwrap();
+
+        for name in ["data/current.tsv", "data/changed.tsv"] {
+            std::fs::write(path_context.join(name), name).unwrap();
+            let mut data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = true;
+            data_file.remote_md5 = Some(data_file.md5.clone());
+            data_file.remote_size = Some(data_file.size);
+            dc.register(data_file).unwrap();
+        }
+        // the last-known remote version of this file differs
+        let changed = dc.files.get_mut("data/changed.tsv").unwrap();
+        changed.remote_md5 = Some("0".repeat(32));
+
+        let options = StatusOptions {
+            cached_remotes: true,
+            ..Default::default()
+        };
+        let statuses = dc.status(path_context, &options).await.unwrap();
+        any_mock.assert_hits(0);
+        let status_of = |name: &str| {
+            statuses["data"]
+                .iter()
+                .find(|entry| entry.name == name)
+                .unwrap()
+                .remote_status
+                .clone()
+        };
+        assert_eq!(status_of("current.tsv"), Some(RemoteStatusCode::Current));
+        assert_eq!(status_of("changed.tsv"), Some(RemoteStatusCode::Different));
+    }
+
+    #[tokio::test]
+    async fn test_pending_upload_size() {
+        let server = MockServer::start();
+        let any_mock = server.mock(|_, then| {
+            then.status(500);
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        let figshare = FigShareAPI::new("Test remote", Some(server.url(""))).unwrap();
+        dc.register_remote(&"data".to_string(), Remote::FigShareAPI(figshare))
+            .unwrap();
+
+        let names = [
+            "data/current.tsv",
+            "data/changed.tsv",
+            "data/new.tsv",
+            "data/untracked.tsv",
+        ];
+        for name in names {
+            std::fs::write(path_context.join(name), name).unwrap();
+            let mut data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = name != "data/untracked.tsv";
+            if name != "data/new.tsv" {
+                data_file.remote_md5 = Some(data_file.md5.clone());
+                data_file.remote_size = Some(data_file.size);
+            }
+            dc.register(data_file).unwrap();
+        }
+        let changed = dc.files.get_mut("data/changed.tsv").unwrap();
+        changed.remote_md5 = Some("0".repeat(32));
+
+        // only changed.tsv and new.tsv are out of sync
+        let pending = dc.pending_upload_size(path_context, true).await.unwrap();
+        any_mock.assert_hits(0);
+        assert_eq!(
+            pending,
+            ("data/changed.tsv".len() + "data/new.tsv".len()) as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_makes_no_requests() {
+        // any request to the remote (or a URL) would hit this
+        let server = MockServer::start();
+        let any_mock = server.mock(|_, then| {
+            then.status(500);
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        let figshare = FigShareAPI::new("Test remote", Some(server.url(""))).unwrap();
+        dc.register_remote(&"data".to_string(), Remote::FigShareAPI(figshare))
+            .unwrap();
+
+        let mut listing = HashMap::new();
+        for name in ["data/new.tsv", "data/changed.tsv", "data/deleted.tsv"] {
+            std::fs::write(path_context.join(name), name).unwrap();
+            let mut data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = true;
+            if name != "data/new.tsv" {
+                let md5 = if name == "data/changed.tsv" {
+                    "0".repeat(32)
+                } else {
+                    data_file.md5.clone()
+                };
+                let remote_file = RemoteFile {
+                    name: data_file.basename().unwrap(),
+                    md5: Some(md5),
+                    size: Some(data_file.size),
+                    remote_service: "FigShare".to_string(),
+                    url: Some(server.url(format!("/{}", name))),
+                };
+                listing.insert(remote_file.name.clone(), remote_file);
+            }
+            dc.register(data_file).unwrap();
+        }
+        std::fs::remove_file(path_context.join("data/deleted.tsv")).unwrap();
+        dc.register(DataFile {
+            path: "data/linked.tsv".to_string(),
+            tracked: false,
+            md5: "".to_string(),
+            size: 0,
+            url: Some(server.url("/linked.tsv")),
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            algo: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+        })
+        .unwrap();
+        let listings = HashMap::from([(("FigShare".to_string(), "data".to_string()), listing)]);
+        let local = dc.merge(false, None).await.unwrap();
+        let all_files = dc.merge_remote_files(local, &listings).unwrap();
+        let planned = |queue: &[QueuedTransfer]| -> Vec<(String, String)> {
+            queue
+                .iter()
+                .map(|queued| (queued.planned.path.clone(), queued.planned.reason.clone()))
+                .collect()
+        };
+
+        let (uploads, report) = dc
+            .queue_uploads(path_context, &all_files, false, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            planned(&uploads),
+            [(
+                "data/new.tsv".to_string(),
+                TRANSFER_NOT_ON_REMOTE.to_string()
+            )]
+        );
+        assert_eq!(report.count(Outcome::Skipped), 3);
+        let (uploads, _) = dc
+            .queue_uploads(path_context, &all_files, true, None)
+            .await
+            .unwrap();
+        assert_eq!(planned(&uploads)[0].1, TRANSFER_DIFFERENT);
+
+        let (downloads, _) = dc
+            .queue_downloads(path_context, &all_files, false, &FileFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            planned(&downloads),
+            [(
+                "data/deleted.tsv".to_string(),
+                TRANSFER_MISSING_LOCALLY.to_string()
+            )]
+        );
+        let plan = dc
+            .pull_urls_plan(path_context, false, &FileFilter::default())
+            .unwrap();
+        assert_eq!(plan.transfers.len(), 1);
+        assert_eq!(plan.transfers[0].path, "data/linked.tsv");
+        any_mock.assert_hits(0);
+    }
+
+    #[tokio::test]
+    async fn test_only_changed_remotes() {
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut dc = DataCollection::new();
+        for dir in ["data", "results"] {
+            std::fs::create_dir(path_context.join(dir)).unwrap();
+            let figshare = FigShareAPI::new(dir, Some(server.url(""))).unwrap();
+            dc.register_remote(&dir.to_string(), Remote::FigShareAPI(figshare))
+                .unwrap();
+        }
+        for name in ["data/synced.tsv", "results/synced.tsv", "results/stale.tsv"] {
+            std::fs::write(path_context.join(name), name).unwrap();
+            let mut data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = true;
+            data_file.remote_md5 = Some(data_file.md5.clone());
+            data_file.remote_size = Some(data_file.size);
+            dc.register(data_file).unwrap();
+        }
+        let stale = dc.files.get_mut("results/stale.tsv").unwrap();
+        stale.remote_md5 = Some("0".repeat(32));
+
+        let options = StatusOptions {
+            cached_remotes: true,
+            only_changed_remotes: true,
+            ..Default::default()
+        };
+        let statuses = dc.status(path_context, &options).await.unwrap();
+        assert!(!statuses.contains_key("data"));
+        let names: Vec<&str> = statuses["results"]
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["stale.tsv"]);
+    }
+
+    #[tokio::test]
+    async fn test_group_by_extension() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let fixture = [
+            ("data/a.bam", 10),
+            ("data/b.bam", 20),
+            ("data/calls.vcf.gz", 5),
+            ("data/archive.tar.gz", 7),
+            ("data/README", 3),
+            ("data/.hidden", 1),
+        ];
+        let mut dc = DataCollection::new();
+        for (name, size) in fixture {
+            std::fs::write(path_context.join(name), "x".repeat(size)).unwrap();
+            let data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            dc.register(data_file).unwrap();
+        }
+
+        let statuses = dc
+            .status(path_context, &StatusOptions::default())
+            .await
+            .unwrap();
+        let summaries = group_by_extension(&statuses, false);
+        let by_ext = |ext: &str| {
+            summaries
+                .iter()
+                .find(|summary| summary.extension == ext)
+                .map(|summary| (summary.files, summary.size))
+        };
+        assert_eq!(by_ext("bam"), Some((2, 30)));
+        assert_eq!(by_ext("vcf.gz"), Some((1, 5)));
+        assert_eq!(by_ext("tar.gz"), Some((1, 7)));
+        assert_eq!(by_ext(""), Some((2, 4)));
+        assert_eq!(by_ext("gz"), None);
+        // largest first
+        assert_eq!(summaries[0].extension, "bam");
+    }
+
+    #[tokio::test]
+    async fn test_status_hyperlinks_only_when_enabled() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(path_context.join("data/a.tsv"), "Mock data.").unwrap();
+        let mut dc = DataCollection::new();
+        let data_file = DataFile::new("data/a.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        dc.register(data_file).unwrap();
+        let statuses = dc
+            .status(path_context, &StatusOptions::default())
+            .await
+            .unwrap();
+
+        let display = StatusDisplay::default();
+        let plain = format_status_lines(&statuses, None, None, &display, None).join("\n");
+        assert!(!plain.contains("\x1b]8;;"));
+
+        let mut links = StatusLinks {
+            path_context: path_context.to_path_buf(),
+            ..Default::default()
+        };
+        links.remote_urls.insert(
+            "data".to_string(),
+            "https://zenodo.org/deposit/1".to_string(),
+        );
+        let linked = format_status_lines(&statuses, None, None, &display, Some(&links)).join("\n");
+        assert!(linked.contains("\x1b]8;;https://zenodo.org/deposit/1\x1b\\data\x1b]8;;"));
+        assert!(linked.contains("\x1b]8;;file://"));
+        assert!(linked.contains("/data/a.tsv\x1b\\a.tsv\x1b]8;;\x1b\\"));
+    }
+
+    #[tokio::test]
+    async fn test_dir_sort_status_lists_modified_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut dc = DataCollection::new();
+        // "a_current" sorts first by name
+        for dir in ["a_current", "b_modified"] {
+            std::fs::create_dir(path_context.join(dir)).unwrap();
+            let path = format!("{}/data.tsv", dir);
+            std::fs::write(path_context.join(&path), "Mock data.").unwrap();
+            let data_file = DataFile::new(path, None, path_context).await.unwrap();
+            dc.register(data_file).unwrap();
+        }
+        std::fs::write(path_context.join("b_modified/data.tsv"), "Changed data.").unwrap();
+        std::fs::write(path_context.join("a_current/big.tsv"), "Lots of mock data.").unwrap();
+        let data_file = DataFile::new("a_current/big.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        dc.register(data_file).unwrap();
+
+        let statuses = dc
+            .status(path_context, &StatusOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            sort_directories(&statuses, DirSort::Name),
+            ["a_current", "b_modified"]
+        );
+        assert_eq!(
+            sort_directories(&statuses, DirSort::Status),
+            ["b_modified", "a_current"]
+        );
+        assert_eq!(
+            sort_directories(&statuses, DirSort::Size),
+            ["a_current", "b_modified"]
+        );
+
+        let display = StatusDisplay {
+            dir_sort: DirSort::Status,
+            ..Default::default()
+        };
+        let lines = format_status_lines(&statuses, None, None, &display, None);
+        let position = |header: &str| lines.iter().position(|line| line == header).unwrap();
+        assert!(position("[b_modified]") < position("[a_current]"));
+    }
+
+    #[tokio::test]
+    async fn test_parallel_status_matches_serial() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        for i in 0..40 {
+            let path = format!("data/file_{:02}.tsv", i);
+            std::fs::write(path_context.join(&path), "x".repeat(i * 1000)).unwrap();
+            let data_file = DataFile::new(path, None, path_context).await.unwrap();
+            dc.register(data_file).unwrap();
+        }
+        // some files change after they are added
+        for i in [3, 17, 29] {
+            let path = path_context.join(format!("data/file_{:02}.tsv", i));
+            std::fs::write(path, "changed").unwrap();
+        }
+
+        let summarize = |statuses: BTreeMap<String, Vec<StatusEntry>>| -> Vec<_> {
+            statuses
+                .into_iter()
+                .flat_map(|(dir, entries)| {
+                    entries
+                        .into_iter()
+                        .map(move |e| (dir.clone(), e.name, e.local_md5, e.local_status))
+                })
+                .collect()
+        };
+        let mut options = StatusOptions {
+            jobs: Some(1),
+            ..Default::default()
+        };
+        let serial = summarize(dc.status(path_context, &options).await.unwrap());
+        options.jobs = Some(8);
+        let parallel = summarize(dc.status(path_context, &options).await.unwrap());
+        assert_eq!(serial.len(), 40);
+        assert_eq!(parallel, serial);
+        let modified: Vec<_> = serial
+            .iter()
+            .filter(|entry| entry.3 == Some(LocalStatusCode::Modified))
+            .map(|entry| entry.1.as_str())
+            .collect();
+        assert_eq!(modified, ["file_03.tsv", "file_17.tsv", "file_29.tsv"]);
+
+        // streamed entries are the kept ones, as they finish
+        let mut streamed = Vec::new();
+        options.filters = vec![StatusFilter::Modified];
+        let statuses = dc
+            .status_streamed(path_context, &options, &mut |dir, entry| {
+                streamed.push(format!("{}/{}", dir, entry.name))
+            })
+            .await
+            .unwrap();
+        streamed.sort();
+        assert_eq!(
+            streamed,
+            ["data/file_03.tsv", "data/file_17.tsv", "data/file_29.tsv"]
+        );
+        assert_eq!(statuses["data"].len(), 3);
+    }
+
+    // Files outside the given paths, or that cannot match the filters by
+    // their manifest entry, are skipped before they are hashed.
+    #[tokio::test]
+    async fn test_status_selects_paths_before_hashing() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir_all(path_context.join("data/raw")).unwrap();
+        let mut dc = DataCollection::new();
+        for path in ["data/raw/a.tsv", "data/raw/b.bam", "data/c.tsv"] {
+            std::fs::write(path_context.join(path), path).unwrap();
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = path.ends_with(".tsv");
+            dc.register(data_file).unwrap();
+        }
+        let names = |statuses: BTreeMap<String, Vec<StatusEntry>>| -> Vec<String> {
+            statuses
+                .into_iter()
+                .flat_map(|(dir, entries)| {
+                    entries
+                        .into_iter()
+                        .map(move |entry| format!("{}/{}", dir, entry.name))
+                })
+                .collect()
+        };
+
+        let mut options = StatusOptions {
+            paths: vec!["data/raw".to_string()],
+            ..Default::default()
+        };
+        let selected = names(dc.status(path_context, &options).await.unwrap());
+        assert_eq!(selected, ["data/raw/a.tsv", "data/raw/b.bam"]);
+
+        options.paths = vec!["data/**/*.tsv".to_string()];
+        let selected = names(dc.status(path_context, &options).await.unwrap());
+        assert_eq!(selected, ["data/c.tsv", "data/raw/a.tsv"]);
+
+        // tracked files cannot be untracked, so are not hashed
+        options.paths = Vec::new();
+        options.filters = vec![StatusFilter::Untracked];
+        let merged = dc.merge(false, None).await.unwrap();
+        assert!(!options.selects("data/c.tsv", &merged["data"]["data/c.tsv"]));
+        let selected = names(dc.status(path_context, &options).await.unwrap());
+        assert_eq!(selected, ["data/raw/b.bam"]);
+    }
+
+    // Statuses are streamed as each file is hashed: with one job, files
+    // are hashed in turn, and the callback for the first changes the other,
+    // so that is only seen as modified if it is hashed after the callback.
+    // The streamed records then match those of a plain status.
+    #[tokio::test]
+    async fn test_status_streams_entries_as_they_finish() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        for name in ["a.tsv", "b.tsv"] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), name).unwrap();
+            let data_file = DataFile::new(path, None, path_context).await.unwrap();
+            dc.register(data_file).unwrap();
+        }
+
+        let options = StatusOptions {
+            jobs: Some(1),
+            ..Default::default()
+        };
+        let mut streamed = Vec::new();
+        let mut on_entry = |dir: &str, entry: &StatusEntry| {
+            if streamed.is_empty() {
+                let other = if entry.name == "a.tsv" {
+                    "b.tsv"
+                } else {
+                    "a.tsv"
+                };
+                std::fs::write(path_context.join(dir).join(other), "changed").unwrap();
+            }
+            streamed.push(serde_json::to_value(status_record(dir, entry)).unwrap());
+        };
+        dc.status_streamed(path_context, &options, &mut on_entry)
+            .await
+            .unwrap();
+        let statuses: Vec<&serde_json::Value> = streamed
+            .iter()
+            .map(|record| &record["local_status"])
+            .collect();
+        assert_eq!(statuses, ["current", "modified"]);
+
+        let plain = dc.status(path_context, &options).await.unwrap();
+        let mut records: Vec<_> = plain["data"]
+            .iter()
+            .map(|entry| serde_json::to_value(status_record("data", entry)).unwrap())
+            .collect();
+        streamed.sort_by_key(|record| record["path"].to_string());
+        records.sort_by_key(|record| record["path"].to_string());
+        assert_eq!(records, streamed);
+    }
+
+    #[tokio::test]
+    async fn test_verify_corrupted_and_deleted_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        md5_cache::enable(path_context);
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut dc = DataCollection::new();
+        let set_mtime = |path: &Path, mtime| {
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(mtime).unwrap();
+        };
+        let mtime = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        for name in ["intact.tsv", "corrupted.tsv", "deleted.tsv"] {
+            let path = format!("data/{}", name);
+            std::fs::write(path_context.join(&path), "id\tvalue\n").unwrap();
+            set_mtime(&path_context.join(&path), mtime);
+            let data_file = DataFile::new(path, None, path_context).await.unwrap();
+            dc.register(data_file).unwrap();
+        }
+        // (this also caches the MD5s)
+        let verified = dc.verify(path_context).await.unwrap();
+        assert_eq!(verified.exit_code(), 0);
+
+        // bit rot: the same size and modification time, so the cached MD5
+        // still matches
+        let corrupted = path_context.join("data/corrupted.tsv");
+        std::fs::write(&corrupted, "id\tvalu3\n").unwrap();
+        set_mtime(&corrupted, mtime);
+        std::fs::remove_file(path_context.join("data/deleted.tsv")).unwrap();
+
+        let verified = dc.verify(path_context).await.unwrap();
+        assert_eq!(verified.corrupted.len(), 1);
+        assert!(verified.corrupted.contains("data/corrupted.tsv"));
+        assert_eq!(verified.missing, 1);
+        assert_eq!(verified.report.count(Outcome::Succeeded), 1);
+        assert_eq!(verified.exit_code(), 1);
+
+        // unreadable files take precedence
+        std::fs::create_dir(path_context.join("data/deleted.tsv")).unwrap();
+        let verified = dc.verify(path_context).await.unwrap();
+        assert_eq!(verified.unreadable, 1);
+        assert_eq!(verified.exit_code(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_verify_mixed_algorithms() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut dc = DataCollection::new();
+        for (name, algo) in [
+            ("a.tsv", ChecksumAlgo::Md5),
+            ("b.tsv", ChecksumAlgo::Sha256),
+            ("c.tsv", ChecksumAlgo::Blake3),
+        ] {
+            std::fs::write(path_context.join(name), "id\tvalue\n").unwrap();
+            let mut data_file = DataFile::new(name.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file
+                .update_checksums(path_context, &[algo])
+                .await
+                .unwrap();
+            data_file.algo = algo;
+            dc.register(data_file).unwrap();
+        }
+        let yaml = serde_yaml::to_string(&dc).unwrap();
+        assert!(yaml.contains("algo: blake3"));
+        let mut dc: DataCollection = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(dc.verify(path_context).await.unwrap().exit_code(), 0);
+
+        // each file is checked by its own checksum, not its MD5
+        dc.files.get_mut("c.tsv").unwrap().md5 = "0".repeat(32);
+        std::fs::write(path_context.join("b.tsv"), "id\tvalu3\n").unwrap();
+        let verified = dc.verify(path_context).await.unwrap();
+        assert_eq!(verified.corrupted.len(), 1);
+        assert!(verified.corrupted.contains("b.tsv"));
+        assert_eq!(verified.report.count(Outcome::Succeeded), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tree_size_colors_largest_directory_warm() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut dc = DataCollection::new();
+        for (dir, contents) in [("large", "x".repeat(1000)), ("small", "x".to_string())] {
+            std::fs::create_dir(path_context.join(dir)).unwrap();
+            let path = format!("{}/data.tsv", dir);
+            std::fs::write(path_context.join(&path), contents).unwrap();
+            let data_file = DataFile::new(path, None, path_context).await.unwrap();
+            dc.register(data_file).unwrap();
+        }
+        let statuses = dc
+            .status(path_context, &StatusOptions::default())
+            .await
+            .unwrap();
+
+        colored::control::set_override(true);
+        let display = StatusDisplay {
+            color: true,
+            tree_size: true,
+            ..Default::default()
+        };
+        let lines = format_status_lines(&statuses, None, None, &display, None);
+        let header = |name: &str| {
+            lines
+                .iter()
+                .find(|line| line.contains(name) && line.starts_with('['))
+                .unwrap()
+                .clone()
+        };
+        let large = "large".bold().color(Color::Red).to_string();
+        let small = "small".bold().color(Color::Blue).to_string();
+        colored::control::unset_override();
+        assert_eq!(header("large"), format!("[{}]", large));
+        assert!(large.contains("31m"));
+        assert_eq!(header("small"), format!("[{}]", small));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_remote_warning_and_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(path_context.join("data/a.tsv"), "Mock data.").unwrap();
+        let mut data_file = DataFile::new("data/a.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        // last fetched from a Zenodo remote that has since been removed
+        data_file.remote_md5 = Some(data_file.md5.clone());
+        data_file.remote = Some("Zenodo".to_string());
+        let mut dc = DataCollection::new();
+        dc.register(data_file).unwrap();
+
+        let warnings = dc.unknown_remote_warnings().unwrap();
+        assert_eq!(
+            warnings,
+            ["unknown remote: Zenodo (referenced by 1 file; use 'sdf remote prune' to clear)."]
+        );
+
+        assert_eq!(dc.prune_dangling_remotes().unwrap(), 1);
+        assert!(dc.unknown_remote_warnings().unwrap().is_empty());
+        let pruned = &dc.files["data/a.tsv"];
+        assert_eq!((&pruned.remote, &pruned.remote_md5), (&None, &None));
+    }
 }
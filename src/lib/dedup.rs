@@ -0,0 +1,177 @@
+// Finding manifest files with the same content ('sdf dedup'), e.g.
+// accidental copies of a large file under different names. Untracked
+// files are included, since their copies still take local space.
+//
+// Files are grouped by their manifest MD5, so finding duplicates hashes
+// nothing. Empty files (which are all alike) and files missing locally are
+// left out. 'sdf dedup --link' replaces each duplicate with a hard link to
+// the first file of its set (in path order), but only after re-reading
+// both to confirm they still have the set's MD5. Duplicates that already
+// are hard links to it take no extra space, so are not reclaimable.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::lib::data::DataCollection;
+use crate::lib::staging::StagedFile;
+use crate::lib::utils::rehash_md5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    pub path: String,
+    // already a hard link to the file that is kept
+    pub linked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateSet {
+    pub md5: String,
+    pub size: u64,
+    // the file that is kept
+    pub keep: String,
+    pub duplicates: Vec<Duplicate>,
+}
+
+impl DuplicateSet {
+    // The bytes freed by linking the duplicates.
+    pub fn reclaimable(&self) -> u64 {
+        let unlinked = self.duplicates.iter().filter(|dup| !dup.linked).count();
+        self.size * unlinked as u64
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+// The sets of two or more files with the same MD5, in MD5 order.
+pub fn find_duplicates(data: &DataCollection, path_context: &Path) -> Vec<DuplicateSet> {
+    let mut by_md5: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for (path, data_file) in &data.files {
+        if data_file.size == 0 || data_file.md5.is_empty() {
+            continue;
+        }
+        if path_context.join(path).is_file() {
+            by_md5.entry(&data_file.md5).or_default().push(path);
+        }
+    }
+    by_md5
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(md5, mut paths)| {
+            paths.sort();
+            let keep = paths[0].clone();
+            let duplicates = paths[1..]
+                .iter()
+                .map(|path| Duplicate {
+                    path: path.to_string(),
+                    linked: same_file(&path_context.join(&keep), &path_context.join(path)),
+                })
+                .collect();
+            DuplicateSet {
+                md5: md5.to_string(),
+                size: data.files[&keep].size,
+                keep,
+                duplicates,
+            }
+        })
+        .collect()
+}
+
+// Replace a duplicate with a hard link to the kept file, once both are
+// re-read and confirmed to have the set's MD5.
+pub async fn link_duplicate(set: &DuplicateSet, path: &str, path_context: &Path) -> Result<()> {
+    for file in [set.keep.as_str(), path] {
+        let md5 = rehash_md5(&path_context.join(file))
+            .await?
+            .ok_or(anyhow!("'{}' is missing", file))?;
+        if md5 != set.md5 {
+            return Err(anyhow!(
+                "'{}' has changed (its MD5 is now {}); run 'sdf update' first",
+                file,
+                md5
+            ));
+        }
+    }
+    // stage the link, then move it over the duplicate
+    let staged = StagedFile::new(path_context, "dedup")?;
+    fs::hard_link(path_context.join(&set.keep), staged.path())
+        .map_err(|err| anyhow!("Could not link to '{}': {}", set.keep, err))?;
+    staged.persist(&path_context.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::DataFile;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_find_and_link_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut data = DataCollection::new();
+        for (path, contents) in [
+            ("data/reads.fa", ">r1\nACGT\n"),
+            ("data/reads copy.fa", ">r1\nACGT\n"),
+            ("data/counts.tsv", "gene\tcount\n"),
+            ("data/empty.txt", ""),
+            ("data/empty copy.txt", ""),
+        ] {
+            fs::create_dir_all(path_context.join("data")).unwrap();
+            fs::write(path_context.join(path), contents).unwrap();
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = !path.contains("copy");
+            data.register(data_file).unwrap();
+        }
+
+        let sets = find_duplicates(&data, path_context);
+        assert_eq!(sets.len(), 1);
+        let set = &sets[0];
+        assert_eq!(set.md5, format!("{:x}", md5::compute(">r1\nACGT\n")));
+        assert_eq!(set.keep, "data/reads copy.fa");
+        let paths: Vec<&str> = set.duplicates.iter().map(|dup| dup.path.as_str()).collect();
+        assert_eq!(paths, ["data/reads.fa"]);
+        assert_eq!(set.reclaimable(), 9);
+
+        // a duplicate that no longer matches is not replaced
+        fs::write(path_context.join("data/reads.fa"), ">r2\nTTGA\n").unwrap();
+        let err = link_duplicate(set, "data/reads.fa", path_context)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has changed"));
+        assert_eq!(
+            fs::read_to_string(path_context.join("data/reads.fa")).unwrap(),
+            ">r2\nTTGA\n"
+        );
+
+        fs::write(path_context.join("data/reads.fa"), ">r1\nACGT\n").unwrap();
+        link_duplicate(set, "data/reads.fa", path_context)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(path_context.join("data/reads.fa")).unwrap(),
+            ">r1\nACGT\n"
+        );
+        #[cfg(unix)]
+        {
+            let sets = find_duplicates(&data, path_context);
+            assert!(sets[0].duplicates[0].linked);
+            assert_eq!(sets[0].reclaimable(), 0);
+        }
+    }
+}
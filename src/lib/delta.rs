@@ -0,0 +1,257 @@
+// Delta (rsync-style) uploads, for remotes that can write part of a stored
+// file in place.
+//
+// Files are split into content-defined chunks using a rolling (gear) hash,
+// so chunk boundaries depend only on nearby content: appending to a file
+// (or editing part of it) leaves the signatures of the other chunks
+// unchanged. Comparing the local chunk signatures against those of the
+// remote copy gives the chunks that need to be sent.
+//
+// A remote can only be delta-synced if it can write ranges of a stored
+// file, truncate it, and read it back to compute its chunk signatures and
+// verify it. Of the current remotes, only filesystems can (see
+// filesystem.rs); for the others, `sdf push --delta` falls back to full
+// uploads.
+
+use anyhow::{anyhow, Result};
+use md5::Context;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+// Chunk size limits; the average chunk size is set by the boundary mask.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkParams {
+    pub min_size: u64,
+    pub max_size: u64,
+    pub mask: u64,
+}
+
+impl Default for ChunkParams {
+    // ~64 KiB average chunks
+    fn default() -> Self {
+        ChunkParams {
+            min_size: 16 * 1024,
+            max_size: 256 * 1024,
+            mask: (1 << 16) - 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Chunk {
+    pub offset: u64,
+    pub len: u64,
+    pub md5: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DeltaSummary {
+    pub chunks_sent: usize,
+    pub bytes_sent: u64,
+}
+
+// The gear hash table, from a fixed seed (splitmix64) so that chunk
+// boundaries are the same across runs and machines.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9e3779b97f4a7c15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+// Compute the content-defined chunk signatures of a file.
+pub fn chunk_signatures(path: &Path, params: &ChunkParams) -> Result<Vec<Chunk>> {
+    let mut file = File::open(path)
+        .map_err(|err| anyhow!("Could not open '{}': {}", path.to_string_lossy(), err))?;
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut chunks = Vec::new();
+
+    let mut offset = 0;
+    let mut len = 0;
+    let mut hash: u64 = 0;
+    let mut md5 = Context::new();
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        let mut start = 0;
+        for (i, byte) in buffer[..bytes_read].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            len += 1;
+            let at_boundary = len >= params.min_size && hash & params.mask == 0;
+            if at_boundary || len >= params.max_size {
+                md5.consume(&buffer[start..=i]);
+                let digest = std::mem::replace(&mut md5, Context::new()).compute();
+                chunks.push(Chunk {
+                    offset,
+                    len,
+                    md5: format!("{:x}", digest),
+                });
+                offset += len;
+                len = 0;
+                hash = 0;
+                start = i + 1;
+            }
+        }
+        md5.consume(&buffer[start..bytes_read]);
+    }
+    if len > 0 {
+        chunks.push(Chunk {
+            offset,
+            len,
+            md5: format!("{:x}", md5.compute()),
+        });
+    }
+    Ok(chunks)
+}
+
+// The local chunks that differ from the remote copy. Since ranged writes
+// happen in place, a chunk is only unchanged if it is at the same offset.
+pub fn changed_chunks(remote: &[Chunk], local: &[Chunk]) -> Vec<Chunk> {
+    let remote: HashSet<&Chunk> = remote.iter().collect();
+    local
+        .iter()
+        .filter(|chunk| !remote.contains(chunk))
+        .cloned()
+        .collect()
+}
+
+fn total_size(chunks: &[Chunk]) -> u64 {
+    chunks.last().map(|c| c.offset + c.len).unwrap_or(0)
+}
+
+// Update target in place to match source, writing only the chunks of
+// source that differ from those of target, then truncating target to
+// source's size and flushing it to disk. Target is not verified here.
+pub fn delta_copy(source: &Path, target: &Path, params: &ChunkParams) -> Result<DeltaSummary> {
+    let target_chunks = chunk_signatures(target, params)?;
+    let source_chunks = chunk_signatures(source, params)?;
+    let mut summary = DeltaSummary::default();
+    let mut reader = File::open(source)
+        .map_err(|err| anyhow!("Could not open '{}': {}", source.to_string_lossy(), err))?;
+    let mut writer = OpenOptions::new()
+        .write(true)
+        .open(target)
+        .map_err(|err| anyhow!("Could not write '{}': {}", target.to_string_lossy(), err))?;
+    for chunk in changed_chunks(&target_chunks, &source_chunks) {
+        let mut data = vec![0; chunk.len as usize];
+        reader.seek(SeekFrom::Start(chunk.offset))?;
+        reader.read_exact(&mut data)?;
+        writer.seek(SeekFrom::Start(chunk.offset))?;
+        writer.write_all(&data)?;
+        summary.chunks_sent += 1;
+        summary.bytes_sent += chunk.len;
+    }
+    writer.set_len(total_size(&source_chunks))?;
+    writer.sync_all()?;
+    Ok(summary)
+}
+
+// delta_copy(), off the async runtime's threads.
+pub async fn delta_copy_blocking(
+    source: &Path,
+    target: &Path,
+    params: ChunkParams,
+) -> Result<DeltaSummary> {
+    let (source, target) = (source.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || delta_copy(&source, &target, &params)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn small_params() -> ChunkParams {
+        ChunkParams {
+            min_size: 64,
+            max_size: 1024,
+            mask: (1 << 8) - 1,
+        }
+    }
+
+    // pseudo-random bytes, so that chunk boundaries are content-defined
+    fn mock_data(n: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_sends_only_tail() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("log.dat");
+        let target = temp_dir.path().join("copy.dat");
+        let params = small_params();
+
+        let original = mock_data(20_000, 1);
+        std::fs::write(&target, &original).unwrap();
+        let remote_chunks = chunk_signatures(&target, &params).unwrap();
+        assert!(remote_chunks.len() > 4);
+
+        // append to the file
+        let mut appended = original.clone();
+        appended.extend(mock_data(3_000, 2));
+        std::fs::write(&path, &appended).unwrap();
+
+        // only the last original chunk (which ended at the old EOF) and
+        // the new tail should be sent
+        let tail_start = remote_chunks.last().unwrap().offset;
+        let local_chunks = chunk_signatures(&path, &params).unwrap();
+        let changed = changed_chunks(&remote_chunks, &local_chunks);
+        assert!(changed.iter().all(|chunk| chunk.offset >= tail_start));
+
+        let summary = delta_copy(&path, &target, &params).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), appended);
+        assert!(summary.chunks_sent >= 1);
+        assert_eq!(summary.bytes_sent, appended.len() as u64 - tail_start);
+    }
+
+    #[test]
+    fn test_shorter_file_truncates_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("log.dat");
+        let target = temp_dir.path().join("copy.dat");
+        let original = mock_data(20_000, 4);
+        std::fs::write(&target, &original).unwrap();
+        std::fs::write(&path, &original[..12_000]).unwrap();
+
+        let summary = delta_copy(&path, &target, &small_params()).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), &original[..12_000]);
+        assert!(summary.bytes_sent < 12_000);
+    }
+
+    #[test]
+    fn test_chunks_cover_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("data.dat");
+        std::fs::write(&path, mock_data(10_000, 3)).unwrap();
+        let chunks = chunk_signatures(&path, &small_params()).unwrap();
+        assert_eq!(total_size(&chunks), 10_000);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].offset + pair[0].len, pair[1].offset);
+        }
+    }
+}
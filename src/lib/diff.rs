@@ -0,0 +1,103 @@
+// Comparing a local file with another copy (e.g. downloaded from a remote).
+//
+// Text files (valid UTF-8 without NUL bytes) get a unified line diff;
+// other files only get the first offset where they differ.
+
+use anyhow::{anyhow, Result};
+use similar::TextDiff;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentDiff {
+    Identical,
+    // a unified diff, from the other copy to the local file
+    Text(String),
+    Binary {
+        // None if one file is a prefix of the other
+        offset: Option<u64>,
+        local_size: u64,
+        other_size: u64,
+    },
+}
+
+fn as_text(contents: &[u8]) -> Option<&str> {
+    if contents.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(contents).ok()
+}
+
+// Compare local with other, labeling them in the diff header.
+pub fn diff_files(
+    local: &Path,
+    other: &Path,
+    local_label: &str,
+    other_label: &str,
+) -> Result<ContentDiff> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|err| anyhow!("Could not read '{}': {}", path.display(), err))
+    };
+    let local_contents = read(local)?;
+    let other_contents = read(other)?;
+    if local_contents == other_contents {
+        return Ok(ContentDiff::Identical);
+    }
+    if let (Some(local_text), Some(other_text)) =
+        (as_text(&local_contents), as_text(&other_contents))
+    {
+        let diff = TextDiff::from_lines(other_text, local_text)
+            .unified_diff()
+            .header(other_label, local_label)
+            .to_string();
+        return Ok(ContentDiff::Text(diff));
+    }
+    let offset = local_contents
+        .iter()
+        .zip(other_contents.iter())
+        .position(|(a, b)| a != b)
+        .map(|offset| offset as u64);
+    Ok(ContentDiff::Binary {
+        offset,
+        local_size: local_contents.len() as u64,
+        other_size: other_contents.len() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diff_text_and_binary_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let local = temp_dir.path().join("local.tsv");
+        let other = temp_dir.path().join("remote.tsv");
+        fs::write(&local, "id\tvalue\n1\t0.5\n2\t0.7\n").unwrap();
+        fs::write(&other, "id\tvalue\n1\t0.5\n2\t0.9\n").unwrap();
+        let ContentDiff::Text(diff) = diff_files(&local, &other, "local", "remote").unwrap() else {
+            panic!("expected a text diff");
+        };
+        assert!(diff.starts_with("--- remote\n+++ local\n@@ -1,3 +1,3 @@\n"));
+        assert!(diff.contains("\n-2\t0.9\n+2\t0.7\n"));
+        assert!(!diff.contains("-1\t0.5"));
+
+        fs::write(&other, "id\tvalue\n1\t0.5\n2\t0.7\n").unwrap();
+        assert_eq!(
+            diff_files(&local, &other, "local", "remote").unwrap(),
+            ContentDiff::Identical
+        );
+
+        fs::write(&local, [0u8, 1, 2, 3]).unwrap();
+        fs::write(&other, [0u8, 1, 9]).unwrap();
+        assert_eq!(
+            diff_files(&local, &other, "local", "remote").unwrap(),
+            ContentDiff::Binary {
+                offset: Some(2),
+                local_size: 4,
+                other_size: 3
+            }
+        );
+    }
+}
@@ -1,16 +1,320 @@
 use anyhow::{anyhow, Context, Result};
-use reqwest::Url;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{Client, Method, StatusCode, Url};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
 
-use trauma::download::Download;
-use trauma::downloader::{DownloaderBuilder, ProgressBarOpts, StyleOptions};
+use trauma::download::{Download, Status, Summary};
+use trauma::downloader::DownloaderBuilder;
 
-use crate::lib::progress::{DEFAULT_PROGRESS_INC, DEFAULT_PROGRESS_STYLE};
-use crate::lib::utils::pluralize;
+use crate::lib::compression::{decompress, Compression};
+use crate::lib::encryption::{decrypt, EncryptionKey};
+use crate::lib::progress::TransferProgress;
+use crate::lib::remote::{retry_policy, send_with_retry, MAX_CONCURRENT_TRANSFERS};
+use crate::lib::report::{Outcome, TransferReport};
+use crate::lib::staging::StagedFile;
+use crate::lib::throttle::{self, paced, RateLimiter};
+use crate::lib::utils::{format_bytes, pluralize, rehash_md5};
+use crate::{print_info, print_warn};
+use colored::Colorize;
+
+// Downloads are written to FILE.partial, and only renamed to FILE once
+// complete (and if its MD5 is known, verified), so an interrupted download
+// can be resumed (if the server supports Range requests).
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+// Downloads of compressed or encrypted copies (see compression.rs and
+// encryption.rs) are, once complete and verified, decrypted and
+// decompressed to staged files (see staging.rs), as are the local files
+// downloads replace, until they are complete.
+
+// How many times an interrupted stream (see stream_verified()), or a
+// download that failed part way (see Downloads::resume()), is resumed.
+const STREAM_RETRIES: usize = 3;
+
+// Before resuming, this many bytes before the resume offset are re-fetched
+// and compared against the partial file.
+const PARTIAL_VERIFY_BYTES: u64 = 64 * 1024;
 
 pub struct Downloads {
     pub queue: Vec<Download>,
+    // downloads made by running a command (see CommandDownload)
+    pub commands: Vec<CommandDownload>,
+    // download destination -> the headers it is requested with, e.g. a
+    // token that cannot be given in its URL
+    pub headers: HashMap<String, HeaderMap>,
+    // download destination -> the MD5 it must have
+    pub expected_md5s: HashMap<String, String>,
+    // download destination -> its size, if known (for progress)
+    pub sizes: HashMap<String, u64>,
+    // download destination -> the manifest path it is shown as in progress,
+    // if not its file name (e.g. for downloads staged in .sdf/tmp)
+    pub labels: HashMap<String, String>,
+    // download destination -> how to restore it, if it is a stored copy
+    pub restored: HashMap<String, Restore>,
+    // the number of files downloaded at once
+    pub max_concurrent: usize,
+    // the project directory files are staged in (see staging.rs)
+    path_context: PathBuf,
+}
+
+// How to restore the file a download of a compressed and/or encrypted
+// copy was made from.
+#[derive(Debug, Clone, Default)]
+pub struct Restore {
+    // the key and nonce prefix to decrypt it with
+    pub decryption: Option<(EncryptionKey, String)>,
+    pub compression: Option<Compression>,
+    // the MD5 it must have once restored (if known)
+    pub md5: Option<String>,
+}
+
+// A download made by running a program (e.g. 'rclone copyto'), which is
+// given the partial file to write as its last argument. These cannot be
+// resumed, or paced by --limit-rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandDownload {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub filename: String,
+}
+
+impl CommandDownload {
+    async fn run(&self) -> Result<()> {
+        let partial = partial_path(&self.filename);
+        let output = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .arg(&partial)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await
+            .map_err(|err| anyhow!("Could not run '{}': {}", self.program.display(), err))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "'{}' failed ({}): {}",
+                self.program.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// How a remote file is downloaded: from a URL (requested with headers,
+// for Authorized), or by a command.
+#[derive(Debug, Clone)]
+pub enum RemoteDownload {
+    Url(Download),
+    Authorized(Download, HeaderMap),
+    Command(CommandDownload),
+}
+
+impl RemoteDownload {
+    pub fn filename(&self) -> &str {
+        match self {
+            RemoteDownload::Url(download) | RemoteDownload::Authorized(download, _) => {
+                &download.filename
+            }
+            RemoteDownload::Command(download) => &download.filename,
+        }
+    }
+    pub fn set_filename(&mut self, filename: String) {
+        match self {
+            RemoteDownload::Url(download) | RemoteDownload::Authorized(download, _) => {
+                download.filename = filename
+            }
+            RemoteDownload::Command(download) => download.filename = filename,
+        }
+    }
+}
+
+pub fn partial_path(filename: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}", filename, PARTIAL_SUFFIX))
+}
+
+// Check whether an existing partial download agrees with the remote file.
+//
+// Remotes do not provide range checksums, so we re-check from a known-good
+// offset: the last PARTIAL_VERIFY_BYTES of the partial file are fetched with
+// a Range request and compared byte-for-byte. Resuming from a corrupt prefix
+// is a guaranteed failure at the final MD5 check, so if the window disagrees
+// (or the server ignores the Range), the partial should be discarded. If the
+// window cannot be fetched (e.g. the server is down), it is an error, as
+// that says nothing about the partial.
+pub async fn verify_partial(url: &Url, headers: &HeaderMap, partial: &Path) -> Result<bool> {
+    let len = fs::metadata(partial)?.len();
+    if len == 0 {
+        return Ok(false);
+    }
+    let start = len.saturating_sub(PARTIAL_VERIFY_BYTES);
+    let mut local = vec![0u8; (len - start) as usize];
+    let mut file = fs::File::open(partial)?;
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut local)?;
+
+    let client = Client::new();
+    let response = send_with_retry(&retry_policy(), &Method::GET, || async {
+        Ok(client
+            .get(url.clone())
+            .headers(headers.clone())
+            .header(RANGE, format!("bytes={}-{}", start, len - 1))
+            .send()
+            .await?)
+    })
+    .await?;
+    let status = response.status();
+    // (a partial longer than the remote file is not satisfiable)
+    if !status.is_success() && status != StatusCode::RANGE_NOT_SATISFIABLE {
+        return Err(anyhow!("HTTP {}", status));
+    }
+    if status != StatusCode::PARTIAL_CONTENT {
+        return Ok(false);
+    }
+    let remote = response.bytes().await?;
+    Ok(remote.as_ref() == local.as_slice())
+}
+
+// Check whether a partial download can be resumed: its tail must agree
+// with the remote (see verify_partial()), the server must advertise Range
+// support (Accept-Ranges), and the partial cannot be longer than the
+// remote file (Content-Length, if given). Otherwise the partial has to be
+// discarded, since the downloader would write over the start of it
+// without truncating it.
+pub async fn can_resume(url: &Url, headers: &HeaderMap, partial: &Path) -> Result<bool> {
+    if !verify_partial(url, headers, partial).await? {
+        return Ok(false);
+    }
+    let client = Client::new();
+    let response = send_with_retry(&retry_policy(), &Method::HEAD, || async {
+        Ok(client
+            .head(url.clone())
+            .headers(headers.clone())
+            .send()
+            .await?)
+    })
+    .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP {}", response.status()));
+    }
+    let headers = response.headers();
+    let accepts_ranges = headers
+        .get(ACCEPT_RANGES)
+        .is_some_and(|value| value != "none");
+    let remote_len = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let len = fs::metadata(partial)?.len();
+    Ok(accepts_ranges && remote_len.is_none_or(|remote_len| len <= remote_len))
+}
+
+// trauma does not flush its (tokio) file handle before reporting success, so
+// the last chunk may still be in flight; wait (briefly) for it to land. If
+// it does not, the download is incomplete.
+async fn wait_for_size(path: &Path, size: u64) -> Result<()> {
+    for _ in 0..100 {
+        if fs::metadata(path).is_ok_and(|m| m.len() >= size) {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let written = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    Err(anyhow!(
+        "The download is incomplete ({} of {} written).",
+        format_bytes(written),
+        format_bytes(size)
+    ))
+}
+
+// Download (or resume) a partial download, requested with headers and
+// paced with limiter (if any). As with trauma's downloads, failures are
+// reported in the summary.
+async fn paced_download(
+    client: &Client,
+    download: &Download,
+    headers: &HeaderMap,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Summary {
+    let offset = fs::metadata(&download.filename)
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let sent = send_with_retry(&retry_policy(), &Method::GET, || async {
+        let mut request = client.get(download.url.clone()).headers(headers.clone());
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        Ok(request.send().await?)
+    })
+    .await;
+    let response = match sent {
+        Ok(response) => response,
+        Err(err) => {
+            return Summary::new(download.clone(), StatusCode::BAD_REQUEST, 0, false).fail(err)
+        }
+    };
+    let status = response.status();
+    let summary = Summary::new(download.clone(), status, 0, offset > 0);
+    if !status.is_success() {
+        return summary.fail(format!("HTTP {}", status));
+    }
+    // a server that ignores the Range sends the whole file
+    let resumed = status == StatusCode::PARTIAL_CONTENT;
+    let written: Result<u64> = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&download.filename)
+            .await?;
+        let mut stream = Box::pin(paced(response.bytes_stream(), limiter));
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        Ok(file.metadata().await?.len())
+    }
+    .await;
+    match written {
+        Ok(size) => Summary::new(download.clone(), status, size, true).with_status(Status::Success),
+        Err(err) => summary.fail(err),
+    }
+}
+
+// Download everything with the --limit-rate limiter, which trauma cannot
+// pace.
+async fn paced_downloads(
+    downloads: &[Download],
+    max_concurrent: usize,
+    limiter: Arc<RateLimiter>,
+) -> Vec<Summary> {
+    let client = Client::new();
+    let headers = HeaderMap::new();
+    futures_util::stream::iter(downloads)
+        .map(|download| paced_download(&client, download, &headers, Some(limiter.clone())))
+        .buffer_unordered(max_concurrent)
+        .collect()
+        .await
+}
+
+// Download everything that is requested with headers (which trauma cannot
+// send per download), paced by --limit-rate.
+async fn authorized_downloads(
+    downloads: &[(Download, HeaderMap)],
+    max_concurrent: usize,
+) -> Vec<Summary> {
+    let client = Client::new();
+    futures_util::stream::iter(downloads)
+        .map(|(download, headers)| paced_download(&client, download, headers, throttle::limiter()))
+        .buffer_unordered(max_concurrent)
+        .collect()
+        .await
 }
 
 pub trait Downloadable {
@@ -30,16 +334,125 @@ impl Downloadable for Url {
     }
 }
 
-impl Default for Downloads {
-    fn default() -> Self {
-        Self::new()
+// Stream a download to writer, verifying its MD5 once complete. If the
+// connection drops mid-stream, it is resumed where it left off with a
+// Range request. Returns the number of bytes written.
+//
+// Note: on an MD5 mismatch the bytes have already been written, so
+// consumers must treat the error as invalidating the output.
+pub async fn stream_verified<W: Write>(
+    url: &Url,
+    headers: &HeaderMap,
+    expected_md5: &str,
+    writer: &mut W,
+) -> Result<u64> {
+    let client = Client::new();
+    let mut md5 = md5::Context::new();
+    let mut written: u64 = 0;
+    let mut retries = 0;
+    loop {
+        let response = send_with_retry(&retry_policy(), &Method::GET, || async {
+            let mut request = client.get(url.clone()).headers(headers.clone());
+            if written > 0 {
+                request = request.header(RANGE, format!("bytes={}-", written));
+            }
+            Ok(request.send().await?)
+        })
+        .await?;
+        let status = response.status();
+        if written > 0 && status != StatusCode::PARTIAL_CONTENT {
+            return Err(anyhow!(
+                "Could not resume the stream (HTTP {}); the server may not support Range requests.",
+                status
+            ));
+        }
+        if !status.is_success() {
+            return Err(anyhow!("Download failed: HTTP {}", status));
+        }
+
+        let mut stream = Box::pin(paced(response.bytes_stream(), throttle::limiter()));
+        let mut interrupted = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(bytes) => {
+                    writer.write_all(&bytes)?;
+                    md5.consume(&bytes);
+                    written += bytes.len() as u64;
+                }
+                Err(err) => {
+                    interrupted = Some(err);
+                    break;
+                }
+            }
+        }
+        match interrupted {
+            None => break,
+            Some(err) if retries < STREAM_RETRIES => {
+                retries += 1;
+                // stdout is the stream, so warnings go to stderr
+                eprintln!(
+                    "Stream interrupted ({}); resuming at byte {}.",
+                    err, written
+                );
+            }
+            Some(err) => return Err(anyhow!("Stream interrupted: {}", err)),
+        }
+    }
+    writer.flush()?;
+
+    let md5 = format!("{:x}", md5.compute());
+    if md5 != expected_md5 {
+        return Err(anyhow!(
+            "MD5 mismatch: the manifest has {}, but the streamed data has {}.",
+            expected_md5,
+            md5
+        ));
     }
+    Ok(written)
 }
 
 impl Downloads {
-    pub fn new() -> Self {
+    pub fn new(path_context: &Path) -> Self {
         let queue = Vec::new();
-        Downloads { queue }
+        Downloads {
+            queue,
+            commands: Vec::new(),
+            headers: HashMap::new(),
+            expected_md5s: HashMap::new(),
+            sizes: HashMap::new(),
+            labels: HashMap::new(),
+            restored: HashMap::new(),
+            max_concurrent: MAX_CONCURRENT_TRANSFERS,
+            path_context: path_context.to_path_buf(),
+        }
+    }
+
+    // Verify the download to filename against md5 before moving it into
+    // place.
+    pub fn expect_md5(&mut self, filename: &str, md5: &str) {
+        self.expected_md5s
+            .insert(filename.to_string(), md5.to_string());
+    }
+
+    // Restore the download to filename (decrypting and decompressing it)
+    // before moving it into place.
+    pub fn restore(&mut self, filename: &str, restore: Restore) {
+        self.restored.insert(filename.to_string(), restore);
+    }
+
+    pub fn push(&mut self, download: RemoteDownload) {
+        match download {
+            RemoteDownload::Url(download) => self.queue.push(download),
+            RemoteDownload::Authorized(download, headers) => {
+                self.headers.insert(download.filename.clone(), headers);
+                self.queue.push(download);
+            }
+            RemoteDownload::Command(download) => self.commands.push(download),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.commands.is_empty()
     }
 
     pub fn add<T: Downloadable>(
@@ -55,7 +468,7 @@ impl Downloads {
             None => url
                 .path_segments()
                 .ok_or_else(|| anyhow::anyhow!("Error parsing URL."))?
-                .last()
+                .next_back()
                 .ok_or_else(|| anyhow::anyhow!("Error getting filename from download URL."))?
                 .to_string(),
         };
@@ -77,16 +490,197 @@ impl Downloads {
         ))
     }
 
-    pub fn default_style(&self) -> Result<StyleOptions> {
-        let style = ProgressBarOpts::new(
-            Some(DEFAULT_PROGRESS_STYLE.to_string()),
-            Some(DEFAULT_PROGRESS_INC.to_string()),
-            true,
-            true,
-        );
+    // The name a download is shown by in progress bars and logs.
+    fn label(&self, filename: &str) -> String {
+        match self.labels.get(filename) {
+            Some(label) => label.clone(),
+            None => Path::new(filename)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    // Download everything, showing progress by polling the sizes of the
+    // partial downloads (trauma's own bars count files, not bytes). The
+    // results of command downloads are returned with trauma's summaries.
+    async fn download_with_progress(
+        &self,
+        partial_downloads: &[Download],
+        authorized: &[(Download, HeaderMap)],
+    ) -> Result<(Vec<Summary>, Vec<Result<()>>)> {
+        let total_size = self.sizes.values().sum();
+        let total_files = (self.queue.len() + self.commands.len()) as u64;
+        let progress = TransferProgress::new(total_size, total_files)?;
+        let mut files = Vec::new();
+        let filenames = (self.queue.iter().map(|download| &download.filename))
+            .chain(self.commands.iter().map(|download| &download.filename));
+        for filename in filenames {
+            let size = self.sizes.get(filename).copied().unwrap_or(0);
+            let file_progress = progress.file(&self.label(filename), size)?;
+            files.push((partial_path(filename), file_progress));
+        }
+        let (stop, mut stopped) = tokio::sync::oneshot::channel::<()>();
+        let poller = tokio::spawn(async move {
+            loop {
+                for (partial, file_progress) in &files {
+                    let size = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+                    file_progress.set_position(size);
+                    // (a file of known size is done once it is all written)
+                    if file_progress.is_complete() {
+                        file_progress.finish();
+                    }
+                }
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = tokio::time::sleep(std::time::Duration::from_millis(100)) => {}
+                }
+            }
+            for (_, file_progress) in &files {
+                file_progress.finish();
+            }
+        });
+        let max_concurrent = self.max_concurrent.max(1);
+        let summaries = async {
+            let mut summaries = match throttle::limiter() {
+                Some(limiter) => paced_downloads(partial_downloads, max_concurrent, limiter).await,
+                None => {
+                    // (trauma retries with its own backoff, as many times)
+                    DownloaderBuilder::hidden()
+                        .concurrent_downloads(max_concurrent)
+                        .retries(retry_policy().max_attempts - 1)
+                        .build()
+                        .download(partial_downloads)
+                        .await
+                }
+            };
+            summaries.extend(authorized_downloads(authorized, max_concurrent).await);
+            summaries
+        };
+        let commands = futures_util::stream::iter(&self.commands)
+            .map(|download| download.run())
+            .buffered(max_concurrent)
+            .collect::<Vec<_>>();
+        let (summaries, command_results) = tokio::join!(summaries, commands);
+        let _ = stop.send(());
+        poller.await?;
+        progress.finish();
+        Ok((summaries, command_results))
+    }
 
-        let style_clone = style.clone();
-        Ok(StyleOptions::new(style, style_clone))
+    // Check a completed download against its expected MD5 (if any).
+    async fn check_md5(&self, filename: &str, partial: &Path) -> Result<()> {
+        let Some(expected) = self.expected_md5s.get(filename) else {
+            return Ok(());
+        };
+        let md5 = rehash_md5(partial)
+            .await?
+            .ok_or(anyhow!("Download '{}' is missing.", partial.display()))?;
+        if md5 != *expected {
+            return Err(anyhow!(
+                "MD5 mismatch: expected {}, but the download has {}.",
+                expected,
+                md5
+            ));
+        }
+        Ok(())
+    }
+
+    // Move a complete (and checked) download into place, restoring it
+    // first if it is a stored copy.
+    async fn finish(&self, filename: &str, partial: &Path) -> Result<()> {
+        let Some(restore) = self.restored.get(filename) else {
+            fs::rename(partial, filename)?;
+            return Ok(());
+        };
+        let decrypted = StagedFile::new(&self.path_context, "decrypted")?;
+        let decompressed = StagedFile::new(&self.path_context, "decompressed")?;
+        let result = async {
+            let mut restored = partial.to_path_buf();
+            if let Some((key, nonce)) = &restore.decryption {
+                decrypt(&restored, decrypted.path(), key, nonce).await?;
+                restored = decrypted.path().to_path_buf();
+            }
+            if let Some(compression) = restore.compression {
+                decompress(&restored, decompressed.path(), compression).await?;
+                restored = decompressed.path().to_path_buf();
+            }
+            if let Some(expected) = &restore.md5 {
+                let md5 = rehash_md5(&restored)
+                    .await?
+                    .ok_or(anyhow!("Download '{}' is missing.", restored.display()))?;
+                if md5 != *expected {
+                    return Err(anyhow!(
+                        "MD5 mismatch: expected {}, but the restored download has {}.",
+                        expected,
+                        md5
+                    ));
+                }
+            }
+            fs::rename(&restored, filename)?;
+            Ok(())
+        }
+        .await;
+        // resuming a copy that does not restore to the file cannot fix it
+        // (the staged files are removed as they are dropped)
+        if partial.exists() {
+            fs::remove_file(partial)?;
+        }
+        result
+    }
+
+    // Check a completed download, and move it into place.
+    async fn complete(
+        &self,
+        path: &str,
+        partial: &Path,
+        report: &mut TransferReport,
+    ) -> Result<()> {
+        match self.check_md5(path, partial).await {
+            Ok(()) => match self.finish(path, partial).await {
+                Ok(()) => report.succeeded(path),
+                Err(err) => report.failed(path, &err.to_string()),
+            },
+            Err(err) => {
+                // resuming a corrupt download cannot fix it
+                fs::remove_file(partial)?;
+                report.failed(path, &err.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Resume a download (to path) that failed part way, e.g. because the
+    // connection dropped, from what was written to its partial file, up to
+    // STREAM_RETRIES times. (trauma retries its own downloads, but not
+    // those with headers or paced by --limit-rate.) Returns whether it
+    // completed; if not, the partial is kept for the next pull.
+    async fn resume(&self, path: &str, download: &Download) -> bool {
+        let client = Client::new();
+        let headers = self.headers.get(path).cloned().unwrap_or_default();
+        let partial = Path::new(&download.filename);
+        for attempt in 1..=STREAM_RETRIES {
+            let written = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+            if written == 0
+                || !can_resume(&download.url, &headers, partial)
+                    .await
+                    .unwrap_or(false)
+            {
+                return false;
+            }
+            print_info!(
+                "resuming the download of '{}' after {} (attempt {} of {}).",
+                path,
+                format_bytes(written),
+                attempt,
+                STREAM_RETRIES
+            );
+            let summary = paced_download(&client, download, &headers, throttle::limiter()).await;
+            if let Status::Success = summary.status() {
+                return true;
+            }
+        }
+        false
     }
 
     // Retrieve all files in the download queue.
@@ -100,21 +694,75 @@ impl Downloads {
         success_status: Option<&str>,
         no_downloads_message: Option<&str>,
         show_total: bool,
-    ) -> Result<()> {
+    ) -> Result<TransferReport> {
+        let mut report = TransferReport::new();
         let downloads = &self.queue;
-        let total_files = downloads.len();
-        if !downloads.is_empty() {
+        if !self.is_empty() {
             // Let's handle the file operations:
             // 1) Move all the files to temporary destinations
             // 2) Create the directory structure if it does not exist.
-            let mut temp_files = Vec::new();
+            // 3) Check any partial downloads before they are resumed (if
+            //    one cannot be checked, that file fails, and nothing of it
+            //    is changed).
+            // (local file -> temporary destination)
+            let mut temp_files = HashMap::new();
+            let mut partial_downloads = Vec::new();
+            let mut authorized = Vec::new();
+            let mut unchecked = HashSet::new();
+            let no_headers = HeaderMap::new();
             for file in downloads {
-                let path = PathBuf::from(&file.filename);
+                let partial = partial_path(&file.filename);
+                let headers = self.headers.get(&file.filename);
+                let resumable = if partial.exists() {
+                    match can_resume(&file.url, headers.unwrap_or(&no_headers), &partial).await {
+                        Ok(resumable) => resumable,
+                        Err(err) => {
+                            let msg = format!(
+                                "Could not check the partial download '{}' ({}); it is kept, to resume later.",
+                                partial.display(),
+                                err
+                            );
+                            report.failed(&file.filename, &msg);
+                            unchecked.insert(file.filename.as_str());
+                            continue;
+                        }
+                    }
+                } else {
+                    true
+                };
+                if !resumable {
+                    print_warn!(
+                        "partial download '{}' does not match the remote; restarting download.",
+                        partial.to_string_lossy()
+                    );
+                    fs::remove_file(&partial)?;
+                }
+                let download = Download {
+                    url: file.url.clone(),
+                    filename: partial.to_string_lossy().to_string(),
+                };
+                match headers {
+                    Some(headers) => authorized.push((download, headers.clone())),
+                    None => partial_downloads.push(download),
+                }
+            }
+            // command downloads cannot be resumed
+            for file in &self.commands {
+                let partial = partial_path(&file.filename);
+                if partial.exists() {
+                    fs::remove_file(&partial)?;
+                }
+            }
+            let filenames = (downloads.iter().map(|download| &download.filename))
+                .chain(self.commands.iter().map(|download| &download.filename))
+                .filter(|filename| !unchecked.contains(filename.as_str()));
+            for filename in filenames {
+                let path = PathBuf::from(filename);
                 if path.exists() {
-                    // rather than delete, we move the file
-                    let temp_file_path = path.with_extension(".tmp");
-                    fs::rename(&path, &temp_file_path)?;
-                    temp_files.push(temp_file_path);
+                    // rather than delete, we move the file aside
+                    let backup = StagedFile::new(&self.path_context, "backup")?;
+                    fs::rename(&path, backup.path())?;
+                    temp_files.insert(path.clone(), backup);
                 }
 
                 // recreate the directory structure if not there
@@ -125,19 +773,58 @@ impl Downloads {
                 }
             }
 
-            let downloader = DownloaderBuilder::new()
-                .style_options(self.default_style()?)
-                .build();
-
-            // download everything
-            downloader.download(downloads).await;
-
-            // now remove the temp files
-            for temp_file_path in temp_files {
-                if temp_file_path.exists() {
-                    fs::remove_file(temp_file_path)?;
+            // download everything, moving completed downloads into place
+            // (failed downloads are left as partials, to be resumed later,
+            // and the local files they were replacing are restored)
+            let (summaries, command_results) = self
+                .download_with_progress(&partial_downloads, &authorized)
+                .await?;
+            for summary in summaries {
+                let partial = PathBuf::from(&summary.download().filename);
+                let filename = partial.to_string_lossy().to_string();
+                let path = filename
+                    .strip_suffix(PARTIAL_SUFFIX)
+                    .ok_or(anyhow!("Internal Error: download is not a partial file."))?;
+                match summary.status() {
+                    Status::Success | Status::Skipped(_) => {
+                        // (an incomplete download is kept, to be resumed)
+                        if let Err(err) = wait_for_size(&partial, summary.size()).await {
+                            report.failed(path, &err.to_string());
+                            continue;
+                        }
+                        self.complete(path, &partial, &mut report).await?;
+                    }
+                    Status::Fail(msg) => {
+                        if self.resume(path, summary.download()).await {
+                            self.complete(path, &partial, &mut report).await?;
+                        } else {
+                            report.failed(path, msg);
+                        }
+                    }
+                    Status::NotStarted => continue,
+                }
+            }
+            for (download, result) in self.commands.iter().zip(command_results) {
+                let (path, partial) = (&download.filename, partial_path(&download.filename));
+                match result {
+                    Ok(()) => self.complete(path, &partial, &mut report).await?,
+                    Err(err) => {
+                        if partial.exists() {
+                            fs::remove_file(&partial)?;
+                        }
+                        report.failed(path, &format!("{:#}", err));
+                    }
+                }
+            }
+            for file in report.files.iter().filter(|f| f.status == Outcome::Failed) {
+                if let Some(backup) = temp_files.remove(Path::new(&file.path)) {
+                    backup.persist(Path::new(&file.path))?;
                 }
             }
+
+            // now remove the other backups
+            drop(temp_files);
+            let total_files = report.count(Outcome::Succeeded);
             if show_total {
                 let punc = if total_files > 0 { "." } else { ":" };
                 println!(
@@ -146,9 +833,13 @@ impl Downloads {
                     punc
                 );
             }
-            for download in downloads {
+            for download in report
+                .files
+                .iter()
+                .filter(|f| f.status == Outcome::Succeeded)
+            {
                 if let Some(msg) = success_status {
-                    let filename = PathBuf::from(&download.filename);
+                    let filename = PathBuf::from(&download.path);
                     let name_str = filename.file_name().ok_or(anyhow!(
                         "Internal Error: could not extract filename from download"
                     ))?;
@@ -159,6 +850,460 @@ impl Downloads {
         } else if no_downloads_message.is_some() {
             println!("{}", no_downloads_message.unwrap_or(""));
         }
-        Ok(())
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::staging::staging_dir;
+    use httpmock::prelude::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_corrupt_partial_restarts() {
+        let server = MockServer::start();
+        let content: Vec<u8> = (0..200u32).map(|i| (i % 251) as u8).collect();
+
+        let temp_dir = TempDir::new().unwrap();
+        let filename = temp_dir
+            .path()
+            .join("data.bin")
+            .to_string_lossy()
+            .to_string();
+
+        // a partial download whose prefix is corrupt
+        let partial = partial_path(&filename);
+        fs::write(&partial, vec![255u8; 100]).unwrap();
+
+        let head_mock = server.mock(|when, then| {
+            when.method("HEAD").path("/data.bin");
+            then.status(200).header("Accept-Ranges", "bytes");
+        });
+        let verify_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=0-99");
+            then.status(206).body(&content[0..100]);
+        });
+        let resume_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=100-");
+            then.status(206).body(&content[100..]);
+        });
+        let restart_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=0-");
+            then.status(200).body(&content);
+        });
+
+        let mut downloads = Downloads::new(temp_dir.path());
+        downloads
+            .add(server.url("/data.bin"), Some(&filename), false)
+            .unwrap();
+        downloads.retrieve(None, None, false).await.unwrap();
+
+        verify_mock.assert();
+        head_mock.assert();
+        restart_mock.assert();
+        resume_mock.assert_hits(0);
+        assert!(!partial.exists());
+        let got = fs::read(&filename).unwrap();
+        assert_eq!(got, content);
+    }
+
+    #[tokio::test]
+    async fn test_paced_downloads_resume_partials() {
+        let server = MockServer::start();
+        let content: Vec<u8> = (0..300u32).map(|i| (i % 251) as u8).collect();
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("reads.fq.partial");
+        fs::write(&partial, &content[0..120]).unwrap();
+        let resume_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/reads.fq")
+                .header("Range", "bytes=120-");
+            then.status(206).body(&content[120..]);
+        });
+        // and a server that ignores the Range
+        let whole = temp_dir.path().join("counts.tsv.partial");
+        fs::write(&whole, "stale").unwrap();
+        server.mock(|when, then| {
+            when.method(GET).path("/counts.tsv");
+            then.status(200).body("gene\tcount\n");
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/missing.tsv");
+            then.status(404);
+        });
+
+        let downloads: Vec<Download> = [
+            ("/reads.fq", &partial),
+            ("/counts.tsv", &whole),
+            ("/missing.tsv", &temp_dir.path().join("missing.tsv.partial")),
+        ]
+        .iter()
+        .map(|(path, partial)| Download {
+            url: Url::parse(&server.url(*path)).unwrap(),
+            filename: partial.to_string_lossy().to_string(),
+        })
+        .collect();
+        let limiter = Arc::new(RateLimiter::new(1024 * 1024));
+        let mut summaries = paced_downloads(&downloads, 2, limiter).await;
+        summaries.sort_by_key(|summary| summary.download().filename.clone());
+
+        resume_mock.assert();
+        assert_eq!(fs::read(&partial).unwrap(), content);
+        assert_eq!(fs::read_to_string(&whole).unwrap(), "gene\tcount\n");
+        let statuses: Vec<&Status> = summaries.iter().map(|summary| summary.status()).collect();
+        assert_eq!(
+            statuses,
+            [
+                &Status::Success,
+                &Status::Fail("HTTP 404 Not Found".to_string()),
+                &Status::Success
+            ]
+        );
+        assert_eq!(summaries[2].size(), 300);
+    }
+
+    #[tokio::test]
+    async fn test_paced_download_retries_server_errors() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        // a server that is unavailable for the first request
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/reads.fq", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in [
+                "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let _ = socket.read(&mut request).await.unwrap();
+                let body = if response.contains("200") {
+                    "ACGTN"
+                } else {
+                    ""
+                };
+                let reply = format!("{}Connection: close\r\n\r\n{}", response, body);
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("reads.fq.partial");
+        let download = Download {
+            url: Url::parse(&url).unwrap(),
+            filename: partial.to_string_lossy().to_string(),
+        };
+        let summary = paced_download(&Client::new(), &download, &HeaderMap::new(), None).await;
+        assert_eq!(summary.status(), &Status::Success);
+        assert_eq!(fs::read_to_string(&partial).unwrap(), "ACGTN");
+    }
+
+    #[tokio::test]
+    async fn test_partial_resumes_with_range() {
+        let server = MockServer::start();
+        let content: Vec<u8> = (0..300u32).map(|i| (i % 251) as u8).collect();
+        let temp_dir = TempDir::new().unwrap();
+        let filename = temp_dir
+            .path()
+            .join("reads.fq")
+            .to_string_lossy()
+            .to_string();
+        let partial = partial_path(&filename);
+        fs::write(&partial, &content[0..120]).unwrap();
+
+        server.mock(|when, then| {
+            when.method("HEAD").path("/reads.fq");
+            then.status(200)
+                .header("Accept-Ranges", "bytes")
+                .header("Content-Length", "300");
+        });
+        let verify_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/reads.fq")
+                .header("Range", "bytes=0-119");
+            then.status(206).body(&content[0..120]);
+        });
+        let resume_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/reads.fq")
+                .header("Range", "bytes=120-");
+            then.status(206)
+                .header("Content-Range", "bytes 120-299/300")
+                .body(&content[120..]);
+        });
+
+        let mut downloads = Downloads::new(temp_dir.path());
+        downloads
+            .add(server.url("/reads.fq"), Some(&filename), false)
+            .unwrap();
+        downloads.expect_md5(&filename, &format!("{:x}", md5::compute(&content)));
+        let report = downloads.retrieve(None, None, false).await.unwrap();
+
+        verify_mock.assert();
+        resume_mock.assert();
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert!(!partial.exists());
+        assert_eq!(fs::read(&filename).unwrap(), content);
+    }
+
+    // Serve content over HTTP (with Range support), but drop the first
+    // full download after cut_at bytes, as a flaky connection would.
+    async fn serve_dropping_first_download(content: Vec<u8>, cut_at: usize) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/reads.fq", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut dropped = false;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 4096];
+                let len = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..len]).to_lowercase();
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| {
+                        let (start, end) = range.trim().split_once('-').unwrap();
+                        let end = end.parse().map_or(content.len(), |end: usize| end + 1);
+                        (start.parse::<usize>().unwrap(), end)
+                    });
+                let (status, body) = match range {
+                    Some((start, end)) => ("206 Partial Content", &content[start..end]),
+                    None => ("200 OK", &content[..]),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                if request.starts_with("head") {
+                    continue;
+                }
+                if range.is_none() && !dropped {
+                    dropped = true;
+                    socket.write_all(&body[..cut_at]).await.unwrap();
+                    continue;
+                }
+                socket.write_all(body).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_dropped_download_is_resumed() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let url = serve_dropping_first_download(content.clone(), 40_000).await;
+        let temp_dir = TempDir::new().unwrap();
+        let filename = temp_dir
+            .path()
+            .join("reads.fq")
+            .to_string_lossy()
+            .to_string();
+
+        // (trauma retries its own downloads; those with headers are ours)
+        let mut downloads = Downloads::new(temp_dir.path());
+        let download = Download {
+            url: Url::parse(&url).unwrap(),
+            filename: filename.clone(),
+        };
+        downloads.push(RemoteDownload::Authorized(download, HeaderMap::new()));
+        downloads.expect_md5(&filename, &format!("{:x}", md5::compute(&content)));
+        let report = downloads.retrieve(None, None, false).await.unwrap();
+
+        assert_eq!(report.count(Outcome::Succeeded), 1, "{:?}", report.files);
+        assert!(!partial_path(&filename).exists());
+        assert_eq!(fs::read(&filename).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn test_download_md5_mismatch_is_not_moved_into_place() {
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let filename = temp_dir
+            .path()
+            .join("reads.fq")
+            .to_string_lossy()
+            .to_string();
+        fs::write(&filename, "local copy").unwrap();
+        // a stale partial, from a server that does not support ranges
+        let partial = partial_path(&filename);
+        fs::write(&partial, "stale bytes from an earlier download").unwrap();
+
+        server.mock(|when, then| {
+            when.method("HEAD").path("/reads.fq");
+            then.status(200);
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/reads.fq")
+                .header("Range", "bytes=0-35");
+            then.status(200)
+                .body("stale bytes from an earlier download");
+        });
+        let get_mock = server.mock(|when, then| {
+            when.method(GET).path("/reads.fq");
+            then.status(200).body("corrupted");
+        });
+
+        let mut downloads = Downloads::new(temp_dir.path());
+        downloads
+            .add(server.url("/reads.fq"), Some(&filename), true)
+            .unwrap();
+        downloads.expect_md5(&filename, &format!("{:x}", md5::compute("expected")));
+        let report = downloads.retrieve(None, None, false).await.unwrap();
+
+        // the partial was discarded and the download restarted, but it did
+        // not match, so the local copy is kept
+        get_mock.assert();
+        assert_eq!(report.count(Outcome::Failed), 1);
+        assert!(report.files[0]
+            .reason
+            .as_ref()
+            .unwrap()
+            .contains("MD5 mismatch"));
+        assert!(!partial.exists());
+        assert_eq!(fs::read_to_string(&filename).unwrap(), "local copy");
+    }
+
+    #[tokio::test]
+    async fn test_good_partial_verifies() {
+        let server = MockServer::start();
+        let content: Vec<u8> = (0..200u32).map(|i| (i % 251) as u8).collect();
+
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("data.bin.partial");
+        fs::write(&partial, &content[0..100]).unwrap();
+
+        let verify_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=0-99");
+            then.status(206).body(&content[0..100]);
+        });
+        let url = Url::parse(&server.url("/data.bin")).unwrap();
+        assert!(verify_partial(&url, &HeaderMap::new(), &partial)
+            .await
+            .unwrap());
+        verify_mock.assert();
+    }
+
+    // A partial that cannot be checked (the server errors) is not
+    // discarded, and nothing is downloaded.
+    #[tokio::test]
+    async fn test_unchecked_partial_is_kept() {
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+        let filename = path("data.bin");
+        let partial = partial_path(&filename);
+        fs::write(&partial, vec![7u8; 100]).unwrap();
+
+        let verify_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=0-99");
+            then.status(503);
+        });
+        let get_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/data.bin")
+                .header("Range", "bytes=0-");
+            then.status(200).body("restarted");
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/other.bin");
+            then.status(200).body("other");
+        });
+
+        let mut downloads = Downloads::new(temp_dir.path());
+        downloads
+            .add(server.url("/data.bin"), Some(&filename), true)
+            .unwrap();
+        downloads
+            .add(server.url("/other.bin"), Some(&path("other.bin")), true)
+            .unwrap();
+        let report = downloads.retrieve(None, None, false).await.unwrap();
+
+        let failed = report
+            .files
+            .iter()
+            .find(|file| file.status == Outcome::Failed)
+            .unwrap();
+        assert_eq!(failed.path, filename);
+        // (the unavailable server is retried first)
+        verify_mock.assert_hits(retry_policy().max_attempts as usize);
+        get_mock.assert_hits(0);
+        assert_eq!(fs::read(&partial).unwrap(), vec![7u8; 100]);
+        // the rest of the batch is still downloaded
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(fs::read_to_string(path("other.bin")).unwrap(), "other");
+    }
+
+    #[tokio::test]
+    async fn test_failed_download_restores_its_own_file() {
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+        // files with the same stem, both replaced by the pull
+        fs::write(path("a.tsv"), "old tsv").unwrap();
+        fs::write(path("a.csv"), "old csv").unwrap();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/a.tsv");
+            then.status(200).body("new tsv");
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/a.csv");
+            then.status(404);
+        });
+
+        // (the failing download's file is moved aside first)
+        let mut downloads = Downloads::new(temp_dir.path());
+        for name in ["a.csv", "a.tsv"] {
+            downloads
+                .add(server.url(format!("/{}", name)), Some(&path(name)), true)
+                .unwrap();
+        }
+        let report = downloads.retrieve(None, None, false).await.unwrap();
+
+        assert_eq!(report.count(Outcome::Succeeded), 1);
+        assert_eq!(report.count(Outcome::Failed), 1);
+        assert_eq!(fs::read_to_string(path("a.tsv")).unwrap(), "new tsv");
+        assert_eq!(fs::read_to_string(path("a.csv")).unwrap(), "old csv");
+        // and no backups are left behind
+        let staged = fs::read_dir(staging_dir(temp_dir.path())).unwrap().count();
+        assert_eq!(staged, 0);
+    }
+
+    #[test]
+    fn test_progress_labels() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut downloads = Downloads::new(temp_dir.path());
+        assert_eq!(downloads.label("/project/data/raw/a.tsv"), "a.tsv");
+        // staged downloads are shown by the manifest path they are for
+        let staged = "/project/.sdf/tmp/repair-11961-0.tmp";
+        downloads
+            .labels
+            .insert(staged.to_string(), "data/raw/a.tsv".to_string());
+        assert_eq!(downloads.label(staged), "data/raw/a.tsv");
+    }
+
+    #[tokio::test]
+    async fn test_short_download_is_incomplete() {
+        let temp_dir = TempDir::new().unwrap();
+        let partial = temp_dir.path().join("data.bin.partial");
+        fs::write(&partial, vec![0u8; 10]).unwrap();
+
+        assert!(wait_for_size(&partial, 10).await.is_ok());
+        let err = wait_for_size(&partial, 20).await.unwrap_err();
+        assert!(err.to_string().contains("incomplete"), "{}", err);
     }
 }
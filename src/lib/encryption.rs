@@ -0,0 +1,342 @@
+// Client-side encryption of remote copies, for access-restricted data
+// that should not be readable on its remote. It is opted into per path in
+// .sdfattributes (encrypt=aes-256-gcm), and recorded in a file's manifest
+// entry when it is added or tracked, like compression (see
+// compression.rs), which it follows if both are set.
+//
+// The key is 32 bytes, given as 64 hex characters in SDF_ENCRYPTION_KEY,
+// or in a file named by SDF_ENCRYPTION_KEYFILE (e.g. one made with
+// 'openssl rand -hex 32 > sdf.key'). It is never written to the manifest.
+//
+// Files are encrypted with AES-256-GCM in chunks (the STREAM construction,
+// with 64 KiB chunks, each with its own tag), so that large files need not
+// fit in memory, and chunks cannot be dropped, reordered, or truncated
+// undetected. The encrypted copy is only the chunks; the nonce prefix is
+// recorded in the manifest (see data::StoredCopy). It is derived from the
+// key and the file's contents, so the same file encrypts to the same copy
+// (e.g. on each mirror, or when pushed again), and different files never
+// share a nonce; the cost is that a remote can tell when two copies are
+// of the same file.
+//
+// A copy that does not decrypt (with the wrong key, or after corruption)
+// is an error, and nothing is written in place of the file.
+
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::KeyInit;
+use aes_gcm::Aes256Gcm;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use serde_derive::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const KEY_VAR: &str = "SDF_ENCRYPTION_KEY";
+pub const KEYFILE_VAR: &str = "SDF_ENCRYPTION_KEYFILE";
+
+const KEY_SIZE: usize = 32;
+// the STREAM nonce prefix (AES-GCM's 12-byte nonce, less the 4-byte
+// counter and the last-chunk flag)
+const NONCE_SIZE: usize = 7;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Encryption {
+    #[serde(rename = "aes-256-gcm")]
+    #[value(name = "aes-256-gcm")]
+    Aes256Gcm,
+}
+
+impl Encryption {
+    // The suffix of encrypted copies' remote names.
+    pub fn suffix(&self) -> &'static str {
+        ".enc"
+    }
+}
+
+impl fmt::Display for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Encryption::Aes256Gcm => write!(f, "aes-256-gcm"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct EncryptionKey([u8; KEY_SIZE]);
+
+// keys are never printed
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl EncryptionKey {
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let bytes = from_hex(hex.trim())
+            .and_then(|bytes| <[u8; KEY_SIZE]>::try_from(bytes).ok())
+            .ok_or(anyhow!(
+                "Invalid encryption key: it must be {} hex characters.",
+                2 * KEY_SIZE
+            ))?;
+        Ok(EncryptionKey(bytes))
+    }
+
+    // The key from SDF_ENCRYPTION_KEY, or else the file named by
+    // SDF_ENCRYPTION_KEYFILE.
+    pub fn from_env() -> Result<Self> {
+        if let Some(hex) = env::var(KEY_VAR).ok().filter(|hex| !hex.is_empty()) {
+            return Self::from_hex(&hex).map_err(|err| anyhow!("{} ({})", err, KEY_VAR));
+        }
+        let Some(keyfile) = env::var(KEYFILE_VAR).ok().filter(|path| !path.is_empty()) else {
+            return Err(anyhow!(
+                "No encryption key: set {} (to {} hex characters), or {} (to a file \
+                 with them).",
+                KEY_VAR,
+                2 * KEY_SIZE,
+                KEYFILE_VAR
+            ));
+        };
+        let hex = fs::read_to_string(&keyfile)
+            .map_err(|err| anyhow!("Could not read the key file '{}': {}", keyfile, err))?;
+        Self::from_hex(&hex).map_err(|err| anyhow!("{} ('{}')", err, keyfile))
+    }
+
+    // The given key, or else the key from the environment (see from_env()).
+    pub fn given_or_env(key: Option<&EncryptionKey>) -> Result<Self> {
+        match key {
+            Some(key) => Ok(key.clone()),
+            None => Self::from_env(),
+        }
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+
+    // The nonce prefix of a file's encrypted copy, from an HMAC of its
+    // contents (with a key derived from this one, not this one itself).
+    fn nonce(&self, source: &Path) -> Result<[u8; NONCE_SIZE]> {
+        let mut nonce_key = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)?;
+        nonce_key.update(b"sdf nonce key");
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&nonce_key.finalize().into_bytes())?;
+        let mut reader = BufReader::new(open(source)?);
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            mac.update(&buffer[..n]);
+        }
+        let digest = mac.finalize().into_bytes();
+        let mut nonce = [0; NONCE_SIZE];
+        nonce.copy_from_slice(&digest[..NONCE_SIZE]);
+        Ok(nonce)
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).map_err(|err| anyhow!("Could not open '{}': {}", path.display(), err))
+}
+
+fn create(path: &Path) -> Result<File> {
+    File::create(path).map_err(|err| anyhow!("Could not create '{}': {}", path.display(), err))
+}
+
+// Fill buffer from reader, short only at the end of its input.
+fn read_chunk(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+// Call write on each chunk of reader, with whether it is the last one (an
+// empty input is one empty chunk).
+fn for_each_chunk(
+    reader: &mut impl Read,
+    chunk_size: usize,
+    mut write: impl FnMut(&[u8], bool) -> Result<()>,
+) -> Result<()> {
+    let mut chunk = vec![0; chunk_size];
+    let mut next = vec![0; chunk_size];
+    let mut n = read_chunk(reader, &mut chunk)?;
+    loop {
+        let m = if n == chunk_size {
+            read_chunk(reader, &mut next)?
+        } else {
+            0
+        };
+        write(&chunk[..n], m == 0)?;
+        if m == 0 {
+            return Ok(());
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        n = m;
+    }
+}
+
+fn encrypt_file(source: &Path, destination: &Path, key: &EncryptionKey) -> Result<String> {
+    let nonce = key.nonce(source)?;
+    let mut encryptor = Some(EncryptorBE32::from_aead(key.cipher(), &nonce.into()));
+    let mut reader = BufReader::new(open(source)?);
+    let mut writer = BufWriter::new(create(destination)?);
+    let failed = |_| anyhow!("Could not encrypt '{}'.", source.display());
+    for_each_chunk(&mut reader, CHUNK_SIZE, |chunk, last| {
+        let encrypted = if last {
+            encryptor
+                .take()
+                .unwrap()
+                .encrypt_last(chunk)
+                .map_err(failed)?
+        } else {
+            encryptor
+                .as_mut()
+                .unwrap()
+                .encrypt_next(chunk)
+                .map_err(failed)?
+        };
+        writer.write_all(&encrypted)?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(to_hex(&nonce))
+}
+
+fn decrypt_file(source: &Path, destination: &Path, key: &EncryptionKey, nonce: &str) -> Result<()> {
+    let nonce = from_hex(nonce)
+        .and_then(|bytes| <[u8; NONCE_SIZE]>::try_from(bytes).ok())
+        .ok_or(anyhow!("Invalid nonce '{}'.", nonce))?;
+    let mut decryptor = Some(DecryptorBE32::from_aead(key.cipher(), &nonce.into()));
+    let mut reader = BufReader::new(open(source)?);
+    let mut writer = BufWriter::new(create(destination)?);
+    let failed = |_| {
+        anyhow!(
+            "Could not decrypt '{}': the encryption key is wrong, or the copy is corrupted.",
+            source.display()
+        )
+    };
+    for_each_chunk(&mut reader, CHUNK_SIZE + TAG_SIZE, |chunk, last| {
+        let decrypted = if last {
+            decryptor
+                .take()
+                .unwrap()
+                .decrypt_last(chunk)
+                .map_err(failed)?
+        } else {
+            decryptor
+                .as_mut()
+                .unwrap()
+                .decrypt_next(chunk)
+                .map_err(failed)?
+        };
+        writer.write_all(&decrypted)?;
+        Ok(())
+    })?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Encrypt source into destination (off the async runtime's threads),
+// returning the nonce prefix (in hex).
+pub async fn encrypt(source: &Path, destination: &Path, key: &EncryptionKey) -> Result<String> {
+    let (source, destination): (PathBuf, PathBuf) = (source.into(), destination.into());
+    let key = key.clone();
+    tokio::task::spawn_blocking(move || encrypt_file(&source, &destination, &key)).await?
+}
+
+// Decrypt source into destination (off the async runtime's threads).
+pub async fn decrypt(
+    source: &Path,
+    destination: &Path,
+    key: &EncryptionKey,
+    nonce: &str,
+) -> Result<()> {
+    let (source, destination): (PathBuf, PathBuf) = (source.into(), destination.into());
+    let (key, nonce) = (key.clone(), nonce.to_string());
+    tokio::task::spawn_blocking(move || decrypt_file(&source, &destination, &key, &nonce)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_round_trip_and_wrong_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let key = EncryptionKey::from_hex(&"ab".repeat(32)).unwrap();
+        let other_key = EncryptionKey::from_hex(&"cd".repeat(32)).unwrap();
+        assert!(EncryptionKey::from_hex("abcd").is_err());
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+
+        // several chunks, the last one short; and an empty file
+        let contents: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        for contents in [contents, Vec::new()] {
+            std::fs::write(dir.join("patients.tsv"), &contents).unwrap();
+            let encrypted = dir.join("patients.tsv.enc");
+            let nonce = encrypt(&dir.join("patients.tsv"), &encrypted, &key)
+                .await
+                .unwrap();
+            let bytes = std::fs::read(&encrypted).unwrap();
+            let chunks = contents.len() / CHUNK_SIZE + 1;
+            assert_eq!(bytes.len(), contents.len() + chunks * TAG_SIZE);
+
+            // the same file encrypts to the same copy
+            let again = dir.join("again");
+            let same_nonce = encrypt(&dir.join("patients.tsv"), &again, &key)
+                .await
+                .unwrap();
+            assert_eq!(same_nonce, nonce);
+            assert_eq!(std::fs::read(&again).unwrap(), bytes);
+
+            let restored = dir.join("restored.tsv");
+            decrypt(&encrypted, &restored, &key, &nonce).await.unwrap();
+            assert_eq!(std::fs::read(&restored).unwrap(), contents);
+
+            let err = decrypt(&encrypted, &restored, &other_key, &nonce)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("the encryption key is wrong"));
+        }
+
+        // a truncated copy does not decrypt
+        let contents = vec![1u8; 2 * CHUNK_SIZE];
+        std::fs::write(dir.join("patients.tsv"), &contents).unwrap();
+        let encrypted = dir.join("patients.tsv.enc");
+        let nonce = encrypt(&dir.join("patients.tsv"), &encrypted, &key)
+            .await
+            .unwrap();
+        let bytes = std::fs::read(&encrypted).unwrap();
+        std::fs::write(&encrypted, &bytes[..CHUNK_SIZE + TAG_SIZE]).unwrap();
+        assert!(decrypt(&encrypted, &dir.join("restored.tsv"), &key, &nonce)
+            .await
+            .is_err());
+    }
+}
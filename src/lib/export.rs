@@ -0,0 +1,167 @@
+// Exporting the manifest as a table, e.g. for collaborators to review the
+// data inventory in a spreadsheet ('sdf export').
+//
+// There is one row per manifest entry, in path order. The local status
+// and modification time come from the file on disk (hashing it unless its
+// MD5 is cached; see md5_cache.rs), and the remote column names the
+// remotes its directory is linked to (the primary, then any mirrors), so
+// no remote is contacted.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use csv::WriterBuilder;
+use std::io::Write;
+use std::path::Path;
+
+use crate::lib::data::{DataCollection, LocalStatusCode};
+use crate::lib::utils::format_mod_time;
+
+pub const EXPORT_COLUMNS: [&str; 7] = [
+    "path",
+    "size",
+    "md5",
+    "tracked",
+    "remote",
+    "local_status",
+    "last_modified",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Tsv,
+}
+
+impl ExportFormat {
+    fn delimiter(&self) -> u8 {
+        match self {
+            ExportFormat::Csv => b',',
+            ExportFormat::Tsv => b'\t',
+        }
+    }
+}
+
+// The rows of the export, without the header.
+pub async fn export_rows(data: &DataCollection, path_context: &Path) -> Result<Vec<Vec<String>>> {
+    let mut paths: Vec<&String> = data.files.keys().collect();
+    paths.sort();
+    let mut rows = Vec::new();
+    for path in paths {
+        let data_file = &data.files[path];
+        let remotes: Vec<&str> = data_file
+            .directory()
+            .ok()
+            .and_then(|dir| data.tracked_dir_of(&dir).cloned())
+            .map(|dir| {
+                let remotes = data.remotes_of(&dir);
+                remotes.into_iter().map(|remote| remote.name()).collect()
+            })
+            .unwrap_or_default();
+        let local_status = data_file
+            .status(path_context)
+            .await
+            .unwrap_or(LocalStatusCode::Invalid);
+        // empty for deleted files
+        let modified = data_file
+            .get_mod_time(path_context)
+            .map(format_mod_time)
+            .unwrap_or_default();
+        rows.push(vec![
+            data_file.path.clone(),
+            data_file.size.to_string(),
+            data_file.md5.clone(),
+            data_file.tracked.to_string(),
+            remotes.join(", "),
+            local_status.label().to_string(),
+            modified,
+        ]);
+    }
+    Ok(rows)
+}
+
+// Write the header and rows; fields with delimiters (or quotes, or
+// newlines) are quoted.
+pub fn write_export<W: Write>(rows: &[Vec<String>], format: ExportFormat, writer: W) -> Result<()> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .from_writer(writer);
+    let write_error = |err: csv::Error| anyhow!("Could not write the export: {}", err);
+    writer.write_record(EXPORT_COLUMNS).map_err(write_error)?;
+    for row in rows {
+        writer.write_record(row).map_err(write_error)?;
+    }
+    writer
+        .flush()
+        .map_err(|err| anyhow!("Could not write the export: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::api::figshare::{FigShareAPI, FIGSHARE_BASE_URL};
+    use crate::lib::data::DataFile;
+    use crate::lib::remote::Remote;
+    use csv::ReaderBuilder;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_export_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let mut data = DataCollection::new();
+        for dir in ["data", "notes"] {
+            std::fs::create_dir(path_context.join(dir)).unwrap();
+        }
+        let figshare = FigShareAPI::new("Test remote", Some(FIGSHARE_BASE_URL.to_string()));
+        data.register_remote(&"data".to_string(), Remote::FigShareAPI(figshare.unwrap()))
+            .unwrap();
+        for (path, contents) in [
+            ("data/counts.tsv", "gene\tcount\n"),
+            ("notes/a, b.txt", "draft"),
+        ] {
+            std::fs::write(path_context.join(path), contents).unwrap();
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = path.starts_with("data/");
+            data.register(data_file).unwrap();
+        }
+        std::fs::write(path_context.join("notes/a, b.txt"), "edited").unwrap();
+
+        let rows = export_rows(&data, path_context).await.unwrap();
+        let mut csv = Vec::new();
+        write_export(&rows, ExportFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("path,size,md5,tracked,remote,local_status,last_modified\n"));
+        // the comma in the name is quoted
+        assert!(csv.contains("\n\"notes/a, b.txt\",5,"));
+
+        let mut reader = ReaderBuilder::new().from_reader(csv.as_bytes());
+        assert_eq!(reader.headers().unwrap(), EXPORT_COLUMNS.as_slice());
+        let records: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        let md5 = format!("{:x}", md5::compute("gene\tcount\n"));
+        assert_eq!(
+            &records[0].iter().take(6).collect::<Vec<_>>(),
+            &[
+                "data/counts.tsv",
+                "11",
+                md5.as_str(),
+                "true",
+                "FigShare",
+                "current"
+            ]
+        );
+        assert!(!records[0][6].is_empty());
+        assert_eq!(
+            &records[1].iter().skip(3).take(3).collect::<Vec<_>>(),
+            &["false", "", "changed"]
+        );
+
+        let mut tsv = Vec::new();
+        write_export(&rows, ExportFormat::Tsv, &mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("\nnotes/a, b.txt\t5\t"));
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use glob::Pattern;
+use std::path::Path;
+
+use crate::lib::data::DataFile;
+use crate::lib::walk::{is_pattern, MATCH_OPTIONS};
+
+// Parse a --since cutoff: either a duration back from now (e.g. "30m",
+// "12h", "7d", "2w"), or a date ("2024-01-31") or RFC 3339 timestamp.
+pub fn parse_since(since: &str) -> Result<DateTime<Utc>> {
+    let since = since.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(since) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let datetime = date
+            .and_hms_opt(0, 0, 0)
+            .ok_or(anyhow!("Invalid date '{}'.", since))?;
+        return Ok(datetime.and_utc());
+    }
+    let invalid = || {
+        anyhow!(
+            "Invalid --since value '{}': use a duration like '7d' or '12h', or a date like '2024-01-31'.",
+            since
+        )
+    };
+    let (split, _) = since.char_indices().next_back().ok_or_else(invalid)?;
+    let (count, unit) = since.split_at(split);
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "s" => Duration::seconds(count),
+        "m" => Duration::minutes(count),
+        "h" => Duration::hours(count),
+        "d" => Duration::days(count),
+        "w" => Duration::weeks(count),
+        _ => return Err(invalid()),
+    };
+    Ok(Utc::now() - duration)
+}
+
+// Parse a size like "100MB", "1.5G", or "512" (bytes). Units are binary
+// (1 KB = 1 KiB = 1024 bytes), as in utils::format_bytes(), and
+// case-insensitive.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let invalid = || {
+        anyhow!(
+            "Invalid size '{}': use a number of bytes, optionally with a unit (e.g. '100MB', '1.5GB').",
+            size
+        )
+    };
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.trim().to_uppercase();
+    let unit = unit.trim_end_matches("IB").trim_end_matches('B');
+    let exponent = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return Err(invalid()),
+    };
+    Ok((number * 1024_f64.powi(exponent)).round() as u64)
+}
+
+// An inclusive range of file sizes (in bytes); unset bounds are open.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SizeRange {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl SizeRange {
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    pub fn contains(&self, size: u64) -> bool {
+        self.min.is_none_or(|min| size >= min) && self.max.is_none_or(|max| size <= max)
+    }
+}
+
+// Whether the manifest path is selected by a filter path: a file, a
+// directory containing it, or a glob pattern (e.g. 'data/**/*.bam')
+// matching it or a directory containing it.
+pub fn path_matches(filter_path: &str, path: &str) -> bool {
+    let filter_path = filter_path.trim_end_matches('/');
+    if filter_path.is_empty() || filter_path == "." {
+        return true;
+    }
+    if !is_pattern(filter_path) {
+        return Path::new(path).starts_with(filter_path);
+    }
+    let Ok(pattern) = Pattern::new(filter_path) else {
+        return false;
+    };
+    Path::new(path)
+        .ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| pattern.matches_path_with(path, MATCH_OPTIONS))
+}
+
+// Restricts an operation to a subset of the manifest's files.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    // manifest paths (files or directories) or glob patterns to include;
+    // empty is all
+    pub paths: Vec<String>,
+    // only include files modified at or after this time
+    pub since: Option<DateTime<Utc>>,
+    // skip files that sort before this manifest path (e.g. to resume a
+    // failed push, which processes files in path order)
+    pub resume_from: Option<String>,
+    // only include files whose manifest size is in this range
+    pub size: SizeRange,
+}
+
+impl FileFilter {
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+            && self.since.is_none()
+            && self.resume_from.is_none()
+            && self.size.is_empty()
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        if self
+            .resume_from
+            .as_ref()
+            .is_some_and(|resume_from| path < resume_from.as_str())
+        {
+            return false;
+        }
+        self.paths.is_empty()
+            || self
+                .paths
+                .iter()
+                .any(|filter_path| path_matches(filter_path, path))
+    }
+
+    // Like matches(), but only by the manifest entry (its path and size),
+    // so files that no longer exist locally can match, e.g. to pull them.
+    pub fn matches_entry(&self, data_file: &DataFile) -> bool {
+        self.matches_path(&data_file.path) && self.size.contains(data_file.size)
+    }
+
+    // Check that each path matches one of files, described as what (e.g.
+    // a typo in 'sdf pull alignmnets/' would otherwise transfer nothing),
+    // and that the file to resume from is one of them (as a typo would
+    // otherwise skip files silently).
+    pub fn check_paths<'a>(
+        &self,
+        files: impl Iterator<Item = &'a DataFile> + Clone,
+        what: &str,
+    ) -> Result<()> {
+        for filter_path in &self.paths {
+            if is_pattern(filter_path) {
+                Pattern::new(filter_path)
+                    .map_err(|err| anyhow!("Invalid pattern '{}': {}", filter_path, err))?;
+            }
+            if !files
+                .clone()
+                .any(|data_file| path_matches(filter_path, &data_file.path))
+            {
+                return Err(anyhow!("No {} match '{}'.", what, filter_path));
+            }
+        }
+        if let Some(resume_from) = &self.resume_from {
+            if !files
+                .clone()
+                .any(|data_file| &data_file.path == resume_from)
+            {
+                return Err(anyhow!(
+                    "Cannot resume from '{}': it is not one of the {}.",
+                    resume_from,
+                    what
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Note: files that no longer exist never match a --since filter.
+    pub fn matches(&self, data_file: &DataFile, path_context: &Path) -> bool {
+        if !self.matches_path(&data_file.path) || !self.size.contains(data_file.size) {
+            return false;
+        }
+        match self.since {
+            None => true,
+            Some(cutoff) => data_file
+                .get_mod_time(path_context)
+                .is_ok_and(|mod_time| mod_time >= cutoff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        let cutoff = parse_since("7d").unwrap();
+        let expected = Utc::now() - Duration::days(7);
+        assert!((cutoff - expected).num_seconds().abs() < 5);
+
+        let cutoff = parse_since("2024-01-31").unwrap();
+        assert_eq!(cutoff.to_rfc3339(), "2024-01-31T00:00:00+00:00");
+
+        assert!(parse_since("7y").is_err());
+        assert!(parse_since("").is_err());
+        assert!(parse_since("7é").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10MB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("1.5 gb").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_size("2KiB").unwrap(), 2048);
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("MB").is_err());
+
+        let range = SizeRange {
+            min: Some(10),
+            max: Some(20),
+        };
+        assert!(range.contains(10) && range.contains(20));
+        assert!(!range.contains(9) && !range.contains(21));
+        assert!(SizeRange::default().contains(0));
+    }
+
+    #[test]
+    fn test_filter_paths_and_patterns() {
+        assert!(path_matches("data/raw/", "data/raw/a.bam"));
+        assert!(!path_matches("data/raw", "data/rawer/a.bam"));
+        assert!(path_matches("data/**/*.bam", "data/raw/run1/a.bam"));
+        assert!(!path_matches("data/*.bam", "data/raw/a.bam"));
+        // a pattern matching a directory selects the files in it
+        assert!(path_matches("data/run*", "data/run2/reads.fq"));
+        assert!(path_matches(".", "data/a.bam"));
+
+        let file = |path: &str| DataFile {
+            path: path.to_string(),
+            tracked: true,
+            md5: String::new(),
+            size: 0,
+            url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+            algo: Default::default(),
+        };
+        let files = [file("alignments/a.bam"), file("reads/r.fq")];
+        let filter = FileFilter {
+            paths: vec!["alignments".to_string(), "*/*.fq".to_string()],
+            ..Default::default()
+        };
+        filter.check_paths(files.iter(), "tracked files").unwrap();
+        let filter = FileFilter {
+            paths: vec!["alignmnets".to_string()],
+            ..Default::default()
+        };
+        let err = filter.check_paths(files.iter(), "tracked files");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "No tracked files match 'alignmnets'."
+        );
+
+        let filter = FileFilter {
+            resume_from: Some("reads/r.fq".to_string()),
+            ..Default::default()
+        };
+        filter.check_paths(files.iter(), "tracked files").unwrap();
+        let filter = FileFilter {
+            resume_from: Some("reads/r.fastq".to_string()),
+            ..Default::default()
+        };
+        let err = filter.check_paths(files.iter(), "tracked files");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Cannot resume from 'reads/r.fastq': it is not one of the tracked files."
+        );
+    }
+}
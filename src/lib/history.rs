@@ -0,0 +1,351 @@
+// The project's change log. Each save of the manifest appends what it
+// changed (files added, changed or removed, remotes linked or unlinked,
+// mirrors and metadata set) to .sdf/history.jsonl, one JSON event per
+// line, with the time it was saved. 'sdf log' shows the events, and
+// 'sdf replay' applies them in order to rebuild the manifest, e.g. to audit
+// what it held at some time, or to recover it from a checkout that only
+// has the history.
+//
+// The last-known remote state of files (see DataFile.remote_md5) is left
+// out, as it is rewritten on every 'sdf status --remotes'; replayed
+// manifests get it again from the next status or push.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::lib::data::{DataCollection, DataCollectionMetadata, DataFile};
+use crate::lib::remote::Remote;
+use crate::lib::timestamp::format_timestamp;
+
+pub const HISTORY_FILE: &str = ".sdf/history.jsonl";
+
+pub fn history_path(path_context: &Path) -> PathBuf {
+    path_context.join(HISTORY_FILE)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    Added { file: DataFile },
+    Changed { file: DataFile },
+    Removed { path: String },
+    Linked { dir: String, remote: Remote },
+    Unlinked { dir: String },
+    Mirrors { dir: String, mirrors: Vec<Remote> },
+    Metadata { metadata: DataCollectionMetadata },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(with = "crate::lib::timestamp")]
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+// Whether two values serialize differently, i.e. would be saved
+// differently in the manifest (remotes also hold runtime state, such as
+// their usage counts, that is not saved).
+fn differs<T: serde::Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+}
+
+// A file's manifest entry without its last-known remote state.
+fn recorded(data_file: &DataFile) -> DataFile {
+    DataFile {
+        remote_md5: None,
+        remote_size: None,
+        remote: None,
+        ..data_file.clone()
+    }
+}
+
+impl Change {
+    // The file or directory the change is to ('' for the metadata).
+    pub fn target(&self) -> &str {
+        match self {
+            Change::Added { file } | Change::Changed { file } => &file.path,
+            Change::Removed { path } => path,
+            Change::Linked { dir, .. } | Change::Unlinked { dir } | Change::Mirrors { dir, .. } => {
+                dir
+            }
+            Change::Metadata { .. } => "",
+        }
+    }
+
+    // A short description, for 'sdf log'.
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Added { file } => format!("added (md5 {})", file.md5),
+            Change::Changed { file } => format!(
+                "changed (md5 {}, {})",
+                file.md5,
+                if file.tracked { "tracked" } else { "untracked" }
+            ),
+            Change::Removed { .. } => "removed".to_string(),
+            Change::Linked { remote, .. } => format!("linked to {}", remote.name()),
+            Change::Unlinked { .. } => "unlinked".to_string(),
+            Change::Mirrors { mirrors, .. } => {
+                let names: Vec<&str> = mirrors.iter().map(|mirror| mirror.name()).collect();
+                format!("mirrors set to [{}]", names.join(", "))
+            }
+            Change::Metadata { metadata } => format!(
+                "metadata set (title: {})",
+                metadata.title.as_deref().unwrap_or("none")
+            ),
+        }
+    }
+
+    fn apply(self, data: &mut DataCollection) {
+        match self {
+            Change::Added { file } | Change::Changed { file } => {
+                data.files.insert(file.path.clone(), file);
+            }
+            Change::Removed { path } => {
+                data.files.remove(&path);
+            }
+            Change::Linked { dir, remote } => {
+                data.remotes.insert(dir, remote);
+            }
+            Change::Unlinked { dir } => {
+                data.remotes.remove(&dir);
+            }
+            Change::Mirrors { dir, mirrors } => {
+                if mirrors.is_empty() {
+                    data.mirrors.remove(&dir);
+                } else {
+                    data.mirrors.insert(dir, mirrors);
+                }
+            }
+            Change::Metadata { metadata } => data.metadata = metadata,
+        }
+    }
+}
+
+// The changes that turn before into after, in path order.
+pub fn changes(before: &DataCollection, after: &DataCollection) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let paths: BTreeSet<&String> = before.files.keys().chain(after.files.keys()).collect();
+    for path in paths {
+        let old = before.files.get(path).map(recorded);
+        let new = after.files.get(path).map(recorded);
+        match (old, new) {
+            (None, Some(file)) => changes.push(Change::Added { file }),
+            (Some(old), Some(file)) if differs(&old, &file) => {
+                changes.push(Change::Changed { file })
+            }
+            (Some(_), None) => changes.push(Change::Removed { path: path.clone() }),
+            _ => (),
+        }
+    }
+    let dirs: BTreeSet<&String> = before.remotes.keys().chain(after.remotes.keys()).collect();
+    for dir in dirs {
+        match (before.remotes.get(dir), after.remotes.get(dir)) {
+            (old, Some(remote)) if old.is_none_or(|old| differs(old, remote)) => {
+                changes.push(Change::Linked {
+                    dir: dir.clone(),
+                    remote: remote.clone(),
+                })
+            }
+            (Some(_), None) => changes.push(Change::Unlinked { dir: dir.clone() }),
+            _ => (),
+        }
+    }
+    let dirs: BTreeSet<&String> = before.mirrors.keys().chain(after.mirrors.keys()).collect();
+    for dir in dirs {
+        let old = before.mirrors.get(dir).cloned().unwrap_or_default();
+        let new = after.mirrors.get(dir).cloned().unwrap_or_default();
+        if differs(&old, &new) {
+            changes.push(Change::Mirrors {
+                dir: dir.clone(),
+                mirrors: new,
+            });
+        }
+    }
+    if differs(&before.metadata, &after.metadata) {
+        changes.push(Change::Metadata {
+            metadata: after.metadata.clone(),
+        });
+    }
+    changes
+}
+
+// Append changes to the history, as saved at time.
+pub fn record(path_context: &Path, changes: Vec<Change>, time: DateTime<Utc>) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let path = history_path(path_context);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut lines = String::new();
+    for change in changes {
+        lines.push_str(&serde_json::to_string(&Event { time, change })?);
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|err| anyhow!("Could not open the history {:?}: {}", path, err))?;
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+// Read the events of a history file, in the order they were saved.
+pub fn read_history(path: &Path) -> Result<Vec<Event>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Could not read the history {:?}: {}", path, err))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|err| anyhow!("Invalid event on line {} of {:?}: {}", i + 1, path, err))
+        })
+        .collect()
+}
+
+// The manifest the events leave, applying those saved up to until (all, if
+// None).
+pub fn replay(events: Vec<Event>, until: Option<DateTime<Utc>>) -> DataCollection {
+    let mut data = DataCollection::new();
+    for event in events {
+        if until.is_some_and(|until| event.time > until) {
+            break;
+        }
+        event.change.apply(&mut data);
+    }
+    data
+}
+
+// The rows of 'sdf log': time, file or directory, and change.
+pub fn log_rows(events: &[Event]) -> Vec<Vec<String>> {
+    events
+        .iter()
+        .map(|event| {
+            vec![
+                format_timestamp(&event.time),
+                event.change.target().to_string(),
+                event.change.describe(),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::api::figshare::FigShareAPI;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn data_file(path: &str, md5: &str, tracked: bool) -> DataFile {
+        DataFile {
+            path: path.to_string(),
+            tracked,
+            md5: md5.to_string(),
+            size: 4,
+            url: None,
+            modified: None,
+            remote_md5: None,
+            remote_size: None,
+            remote: None,
+            checksums: Default::default(),
+            algo: Default::default(),
+            block_hashes: None,
+            compression: None,
+            encryption: None,
+            stored: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_recorded_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let start = Utc::now() - Duration::hours(3);
+
+        // a small history, saved an hour apart: two files are added, one
+        // is tracked and its directory linked and mirrored, then the other
+        // is removed
+        let mut states = vec![DataCollection::new()];
+        let mut data = DataCollection::new();
+        data.metadata.title = Some("atlas".to_string());
+        for file in [
+            data_file("data/a.tsv", "aaaa", false),
+            data_file("data/b.tsv", "bbbb", false),
+        ] {
+            data.files.insert(file.path.clone(), file);
+        }
+        states.push(data);
+        let mut data = DataCollection {
+            files: states[1].files.clone(),
+            metadata: states[1].metadata.clone(),
+            ..Default::default()
+        };
+        data.files.get_mut("data/a.tsv").unwrap().tracked = true;
+        // the last-known remote state is not recorded
+        data.files.get_mut("data/a.tsv").unwrap().remote_md5 = Some("aaaa".to_string());
+        // (as saved in a manifest, without the runtime token and URL)
+        let figshare = FigShareAPI::new("atlas", Some("http://localhost".to_string())).unwrap();
+        let saved = serde_json::to_string(&Remote::FigShareAPI(figshare)).unwrap();
+        let remote: Remote = serde_json::from_str(&saved).unwrap();
+        data.remotes.insert("data".to_string(), remote.clone());
+        data.mirrors.insert("data".to_string(), vec![remote]);
+        states.push(data);
+        let mut data = DataCollection {
+            files: states[2].files.clone(),
+            remotes: states[2].remotes.clone(),
+            mirrors: states[2].mirrors.clone(),
+            metadata: states[2].metadata.clone(),
+            ..Default::default()
+        };
+        data.files.remove("data/b.tsv");
+        states.push(data);
+        for (i, pair) in states.windows(2).enumerate() {
+            let time = start + Duration::hours(i as i64);
+            record(path_context, changes(&pair[0], &pair[1]), time).unwrap();
+        }
+        // saving without changes records nothing
+        record(path_context, changes(&states[3], &states[3]), Utc::now()).unwrap();
+
+        let events = read_history(&history_path(path_context)).unwrap();
+        let targets: Vec<(&str, String)> = events
+            .iter()
+            .map(|event| (event.change.target(), event.change.describe()))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                ("data/a.tsv", "added (md5 aaaa)".to_string()),
+                ("data/b.tsv", "added (md5 bbbb)".to_string()),
+                ("", "metadata set (title: atlas)".to_string()),
+                ("data/a.tsv", "changed (md5 aaaa, tracked)".to_string()),
+                ("data", "linked to FigShare".to_string()),
+                ("data", "mirrors set to [FigShare]".to_string()),
+                ("data/b.tsv", "removed".to_string()),
+            ]
+        );
+
+        let mut expected = DataCollection {
+            files: states[3].files.clone(),
+            remotes: states[3].remotes.clone(),
+            mirrors: states[3].mirrors.clone(),
+            metadata: states[3].metadata.clone(),
+            ..Default::default()
+        };
+        expected.files.get_mut("data/a.tsv").unwrap().remote_md5 = None;
+        assert_eq!(replay(events.clone(), None), expected);
+        // up to a time, only the events saved by then are applied
+        let until = start + Duration::minutes(30);
+        assert_eq!(replay(events, Some(until)), states[1]);
+    }
+}
@@ -0,0 +1,104 @@
+// Project hooks: executables in <project>/.sdf/hooks, run around
+// transfers, e.g. a pre-push hook that validates files before they are
+// published, or a post-pull hook that decompresses or indexes them.
+//
+// A hook is run from the project directory, and is given the manifest
+// paths of the files transferred (or, for pre-* hooks, about to be), one
+// per line on its standard input; its output is passed through. Hooks
+// are only run when there is something to transfer. A pre-* hook exiting
+// with a non-zero status aborts its operation before anything is
+// transferred, while a post-* hook's failure is only warned about, since
+// the files are already transferred. Hooks that are not executable are
+// skipped (with a warning), as in git.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::print_warn;
+
+pub const HOOKS_DIR: &str = ".sdf/hooks";
+pub const PRE_PUSH: &str = "pre-push";
+pub const POST_PULL: &str = "post-pull";
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+// The project's hook called name, if it has one that can be run.
+pub fn find_hook(path_context: &Path, name: &str) -> Option<PathBuf> {
+    let hook = path_context.join(HOOKS_DIR).join(name);
+    if !hook.is_file() {
+        return None;
+    }
+    if !is_executable(&hook) {
+        print_warn!(
+            "The {} hook '{}' is not executable, so is skipped (see 'chmod +x').",
+            name,
+            hook.display()
+        );
+        return None;
+    }
+    Some(hook)
+}
+
+// Run hook on paths (sorted, and each listed once); a non-zero exit
+// status is an error.
+pub fn run_hook<'a>(
+    hook: &Path,
+    path_context: &Path,
+    paths: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+    let name = hook.file_name().unwrap_or_default().to_string_lossy();
+    let paths: BTreeSet<&str> = paths.into_iter().collect();
+    let mut child = Command::new(hook)
+        .current_dir(path_context)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            anyhow!(
+                "Could not run the {} hook '{}': {}",
+                name,
+                hook.display(),
+                err
+            )
+        })?;
+    let mut input = String::new();
+    for path in paths {
+        input.push_str(path);
+        input.push('\n');
+    }
+    let mut stdin = child.stdin.take().unwrap();
+    // a hook need not read its input
+    match stdin.write_all(input.as_bytes()) {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("Could not write to the {} hook: {}", name, err));
+        }
+        _ => drop(stdin),
+    }
+    let status = child.wait().map_err(|err| {
+        anyhow!(
+            "Could not run the {} hook '{}': {}",
+            name,
+            hook.display(),
+            err
+        )
+    })?;
+    if !status.success() {
+        return Err(anyhow!("The {} hook failed ({})", name, status));
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+// Paths excluded from 'sdf add' (with patterns or --recursive) and from
+// 'sdf status', set in .sdfignore files.
+//
+// Like .gitignore, each line is a pattern, and blank lines and lines
+// starting with '#' are ignored:
+//
+//   # scratch files, except the one we keep
+//   *.tmp
+//   !keep.tmp
+//   __pycache__/
+//   /scratch/**
+//
+// A pattern ending in '/' only matches directories, and a leading '!'
+// re-includes paths an earlier pattern excluded. A pattern containing a
+// '/' (other than a trailing one) matches paths from the directory of its
+// .sdfignore; otherwise it matches names at any depth. Wildcards are as
+// in .sdfattributes (see attributes.rs).
+//
+// Precedence: the last matching pattern wins, and a .sdfignore in a
+// subdirectory applies below it, after those of the directories above.
+// As in git, files in an ignored directory cannot be re-included. Files
+// named explicitly to 'sdf add', and files in the manifest, are never
+// ignored.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lib::attributes::glob_match;
+
+pub const IGNORE_FILE: &str = ".sdfignore";
+
+#[derive(Debug, Clone, PartialEq)]
+struct IgnoreRule {
+    pattern: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    // matched against the path from the .sdfignore's directory, rather
+    // than the file name
+    anchored: bool,
+}
+
+fn parse_rules(contents: &str) -> Vec<IgnoreRule> {
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        rules.push(IgnoreRule {
+            pattern: pattern.trim_start_matches('/').chars().collect(),
+            negated,
+            dir_only,
+            anchored: pattern.contains('/'),
+        });
+    }
+    rules
+}
+
+// The directories containing path (a '/'-separated path from the project
+// root), from the root ("") down.
+fn parent_dirs(path: &str) -> Vec<&str> {
+    let mut dirs = vec![""];
+    dirs.extend(path.match_indices('/').map(|(i, _)| &path[..i]));
+    dirs
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    // the rules of each directory's .sdfignore (by its path from the
+    // root), each parsed once
+    files: HashMap<String, Vec<IgnoreRule>>,
+}
+
+impl IgnoreRules {
+    pub fn new(path_context: &Path) -> Self {
+        let root = fs::canonicalize(path_context)
+            .or_else(|_| std::path::absolute(path_context))
+            .unwrap_or(path_context.to_path_buf());
+        IgnoreRules {
+            root,
+            files: HashMap::new(),
+        }
+    }
+
+    fn load(&mut self, dir: &str) -> Result<()> {
+        if self.files.contains_key(dir) {
+            return Ok(());
+        }
+        let path = self.root.join(dir).join(IGNORE_FILE);
+        let rules = match fs::read_to_string(&path) {
+            Ok(contents) => parse_rules(&contents),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(anyhow!("Could not read '{}': {}", path.display(), err)),
+        };
+        self.files.insert(dir.to_string(), rules);
+        Ok(())
+    }
+
+    // Whether the rules exclude path itself (its directories aside).
+    fn excludes(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for dir in parent_dirs(path) {
+            let relative = if dir.is_empty() {
+                path
+            } else {
+                &path[dir.len() + 1..]
+            };
+            let name: Vec<char> = relative
+                .rsplit('/')
+                .next()
+                .unwrap_or(relative)
+                .chars()
+                .collect();
+            let relative: Vec<char> = relative.chars().collect();
+            for rule in &self.files[dir] {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                let text = if rule.anchored { &relative } else { &name };
+                if glob_match(&rule.pattern, text) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+
+    // Whether path (a '/'-separated path from the project root) is
+    // ignored, itself or by being in an ignored directory.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool> {
+        for dir in parent_dirs(path) {
+            self.load(dir)?;
+        }
+        let ignored_dir = parent_dirs(path)
+            .into_iter()
+            .skip(1)
+            .any(|dir| self.excludes(dir, true));
+        Ok(ignored_dir || self.excludes(path, is_dir))
+    }
+
+    // Like is_ignored(), for a path on disk; paths outside the project
+    // are never ignored.
+    pub fn is_ignored_path(&mut self, path: &Path, is_dir: bool) -> Result<bool> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let (Ok(parent), Some(name)) = (fs::canonicalize(parent), path.file_name()) else {
+            return Ok(false);
+        };
+        let Ok(relative) = parent
+            .join(name)
+            .strip_prefix(&self.root)
+            .map(Path::to_path_buf)
+        else {
+            return Ok(false);
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.is_ignored(&relative, is_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ignore_negation_and_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(
+            root.join(IGNORE_FILE),
+            "# scratch\n*.txt\n!keep.txt\ntmp/\n/results/*.log\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("data/sub")).unwrap();
+        fs::write(root.join("data/sub").join(IGNORE_FILE), "!*.txt\n").unwrap();
+        let mut rules = IgnoreRules::new(root);
+        let mut ignored = |path: &str, is_dir: bool| rules.is_ignored(path, is_dir).unwrap();
+
+        assert!(ignored("notes.txt", false));
+        assert!(ignored("data/notes.txt", false));
+        assert!(!ignored("data/keep.txt", false));
+        assert!(!ignored("data/reads.fa", false));
+
+        // directory-only patterns do not match files, but do exclude
+        // everything in a matching directory
+        assert!(ignored("data/tmp", true));
+        assert!(!ignored("data/tmp", false));
+        assert!(ignored("data/tmp/reads.fa", false));
+        assert!(ignored("data/tmp/keep.txt", false));
+
+        // anchored patterns only match from the .sdfignore's directory
+        assert!(ignored("results/run.log", false));
+        assert!(!ignored("data/results/run.log", false));
+
+        // a nested .sdfignore layers on top of the root one
+        assert!(!ignored("data/sub/notes.txt", false));
+        assert!(ignored("data/other/notes.txt", false));
+    }
+}
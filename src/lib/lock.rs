@@ -0,0 +1,253 @@
+// An advisory lock on a project, held by commands that modify the
+// manifest (see Project::with_manifest()), so that two runs at once (e.g.
+// a cron 'sdf pull' and a manual 'sdf add') cannot overwrite each other's
+// changes. Read-only commands do not take it, and 'sdf status --remotes'
+// only if it is free (see Project::with_manifest_if_unlocked()).
+//
+// The lock is <project>/.sdf/lock, created exclusively and holding its
+// owner's PID, and removed when its guard is dropped. A lock left by a
+// run that is no longer running (e.g. it crashed, or was killed) is stale,
+// and is replaced. Only one run at a time may remove a stale lock (the one
+// that creates <project>/.sdf/lock.break), and only if it still holds what
+// was read, so a run cannot remove a lock another has just taken.
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+use crate::print_warn;
+
+pub const LOCK_FILE: &str = ".sdf/lock";
+
+// a lock file without a PID may be being written (and a break file is
+// removed right after it is made); after this long, it never will be
+const MIN_AGE: Duration = Duration::from_secs(10);
+
+// how many times to try taking the lock, while other runs remove a stale
+// one, and how long to wait between tries
+const ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+pub fn lock_path(path_context: &Path) -> PathBuf {
+    path_context.join(LOCK_FILE)
+}
+
+// Whether a process is running (or, where this cannot be checked, true).
+// Signal 0 only checks that the process exists; EPERM means it does, but
+// belongs to another user.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    // SAFETY: kill() with signal 0 sends nothing
+    let sent = unsafe { libc::kill(pid, 0) } == 0;
+    sent || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+// Whether a file was last modified at least MIN_AGE ago.
+fn is_settled(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= MIN_AGE)
+}
+
+// Remove the stale lock at path, if it still has the contents read
+// (stale), and no other run is removing it. Returns whether it was
+// removed (if not, the lock is to be taken again).
+fn break_stale(path: &Path, stale: &str) -> Result<bool> {
+    let break_path = path.with_extension("break");
+    let break_error = |err: std::io::Error| anyhow!("Could not lock '{}': {}", path.display(), err);
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&break_path)
+    {
+        Ok(_) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            // (one left by a run that exited while removing a lock)
+            if is_settled(&break_path) {
+                let _ = fs::remove_file(&break_path);
+            }
+            return Ok(false);
+        }
+        Err(err) => return Err(break_error(err)),
+    }
+    let removed = match fs::read_to_string(path) {
+        Ok(contents) if contents == stale => match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(break_error(err)),
+            _ => Ok(true),
+        },
+        _ => Ok(false),
+    };
+    let _ = fs::remove_file(&break_path);
+    removed
+}
+
+// The error of ProjectLock::acquire() when another run holds the lock (so
+// commands that can do without it can tell this from other errors).
+#[derive(Debug)]
+pub struct LockHeld {
+    // the PID of the run holding it (None if it is still being written)
+    pub pid: Option<u32>,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for LockHeld {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(
+                f,
+                "Another sdf process (PID {}) is modifying this project; try again once \
+                 it is done. If it is not running, delete '{}'.",
+                pid,
+                self.path.display()
+            ),
+            None => write!(
+                f,
+                "Another sdf process is modifying this project; try again once it is done."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LockHeld {}
+
+#[derive(Debug)]
+pub struct ProjectLock {
+    path: PathBuf,
+}
+
+impl ProjectLock {
+    // Take the project's lock, or fail if another run holds it.
+    pub fn acquire(path_context: &Path) -> Result<Self> {
+        let path = lock_path(path_context);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| {
+                anyhow!("Could not create directory '{}': {}", dir.display(), err)
+            })?;
+        }
+        let lock_error =
+            |err: std::io::Error| anyhow!("Could not lock '{}': {}", path.display(), err);
+        for _ in 0..ATTEMPTS {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id()).map_err(lock_error)?;
+                    return Ok(ProjectLock { path });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(lock_error(err)),
+            }
+            let Ok(contents) = fs::read_to_string(&path) else {
+                // (released meanwhile)
+                continue;
+            };
+            let holder = contents.trim().parse::<u32>().ok();
+            match holder {
+                Some(pid) if is_running(pid) => {
+                    return Err(LockHeld {
+                        pid: Some(pid),
+                        path,
+                    }
+                    .into());
+                }
+                None if !is_settled(&path) => return Err(LockHeld { pid: None, path }.into()),
+                _ => {}
+            }
+            if !break_stale(&path, &contents)? {
+                // (another run is taking it)
+                sleep(RETRY_DELAY);
+            } else if let Some(pid) = holder {
+                print_warn!("Removed a stale lock left by sdf process {}.", pid);
+            }
+        }
+        Err(anyhow!(
+            "Could not lock '{}': another sdf process keeps taking it.",
+            path.display()
+        ))
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock = ProjectLock::acquire(temp_dir.path()).unwrap();
+        let pid = std::process::id().to_string();
+        assert_eq!(fs::read_to_string(lock_path(temp_dir.path())).unwrap(), pid);
+
+        let err = ProjectLock::acquire(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains(&format!("(PID {})", pid)));
+
+        drop(lock);
+        assert!(!lock_path(temp_dir.path()).exists());
+        ProjectLock::acquire(temp_dir.path()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_lock_is_replaced() {
+        let temp_dir = TempDir::new().unwrap();
+        // the PID of a process that has exited
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        fs::create_dir(temp_dir.path().join(".sdf")).unwrap();
+        fs::write(lock_path(temp_dir.path()), dead_pid.to_string()).unwrap();
+
+        let _lock = ProjectLock::acquire(temp_dir.path()).unwrap();
+        let holder = fs::read_to_string(lock_path(temp_dir.path())).unwrap();
+        assert_eq!(holder, std::process::id().to_string());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_processes_of_other_users_are_running() {
+        assert!(is_running(std::process::id()));
+        // init cannot be signalled by other users (EPERM), but is running
+        assert!(is_running(1));
+    }
+
+    #[test]
+    fn test_only_the_stale_lock_is_broken() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = lock_path(temp_dir.path());
+        fs::create_dir(temp_dir.path().join(".sdf")).unwrap();
+        fs::write(&path, "4000001").unwrap();
+
+        // another run took the lock since the stale one was read
+        assert!(!break_stale(&path, "4000000").unwrap());
+        assert!(path.exists());
+        // another run is removing the stale lock
+        fs::write(path.with_extension("break"), "").unwrap();
+        assert!(!break_stale(&path, "4000001").unwrap());
+        assert!(path.exists());
+        fs::remove_file(path.with_extension("break")).unwrap();
+
+        assert!(break_stale(&path, "4000001").unwrap());
+        assert!(!path.exists());
+        assert!(!path.with_extension("break").exists());
+    }
+}
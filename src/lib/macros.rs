@@ -1,13 +1,15 @@
+// Warnings and notes go to stderr, so they are kept out of output for
+// scripts (e.g. 'sdf status | cut -f1').
 #[macro_export]
 macro_rules! print_warn {
     ($($arg:tt)*) => {
-        println!("{}: {}", "Warning".to_string().red().bold(), format!($($arg)*));
+        eprintln!("{}: {}", "Warning".to_string().red().bold(), format!($($arg)*));
     };
 }
 
 #[macro_export]
 macro_rules! print_info {
     ($($arg:tt)*) => {
-        println!("{}: {}", "Info".to_string().green().bold(), format!($($arg)*));
+        eprintln!("{}: {}", "Info".to_string().green().bold(), format!($($arg)*));
     };
 }
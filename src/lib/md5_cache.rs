@@ -0,0 +1,388 @@
+// A cache of file MD5s keyed on each file's size and modification time,
+// so that unchanged files are not hashed again on every 'sdf status'.
+// Other checksums (e.g. of files whose changes are detected by SHA-256 or
+// BLAKE3, see 'sdf add --checksum') are cached alongside the MD5.
+//
+// Entries are stored by absolute path in <project>/.sdf/md5_cache.json. A
+// cached checksum is only used if both the size and the modification time (to
+// the nanosecond) match, so touching a file re-hashes it even if its
+// content did not change. Files modified in the last few seconds are
+// not cached: another same-size write within the filesystem's timestamp
+// resolution could leave the modification time unchanged. 'sdf --rehash'
+// ignores the cache entirely.
+
+use anyhow::{anyhow, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::lib::checksum::ChecksumAlgo;
+use crate::lib::staging::StagedFile;
+
+pub const MD5_CACHE: &str = ".sdf/md5_cache.json";
+
+// files modified more recently than this are not cached
+const MIN_AGE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Fingerprint {
+    pub size: u64,
+    // the modification time, since the Unix epoch
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+}
+
+impl Fingerprint {
+    // The fingerprint of a file, or None if it does not exist (or has no
+    // usable modification time).
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Fingerprint {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+
+    fn mtime(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::new(self.mtime_secs, self.mtime_nanos)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CachedMd5 {
+    #[serde(flatten)]
+    fingerprint: Fingerprint,
+    md5: String,
+    // checksums from other algorithms, if any were computed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    checksums: BTreeMap<ChecksumAlgo, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Md5Cache {
+    // absolute path -> MD5 (and other checksums)
+    entries: BTreeMap<String, CachedMd5>,
+}
+
+pub fn md5_cache_path(path_context: &Path) -> PathBuf {
+    path_context.join(MD5_CACHE)
+}
+
+fn cache_key(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+impl Md5Cache {
+    // Load the cache; like the remote cache, a missing or unreadable
+    // cache is just empty.
+    pub fn load(path_context: &Path) -> Self {
+        fs::read_to_string(md5_cache_path(path_context))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path_context: &Path) -> Result<()> {
+        let staged = StagedFile::new(path_context, "md5-cache")?;
+        fs::write(staged.path(), serde_json::to_string(self)?)
+            .map_err(|err| anyhow!("Could not write the MD5 cache: {}", err))?;
+        staged.persist(&md5_cache_path(path_context))
+    }
+
+    // The cached MD5 of path, if it was computed for this fingerprint.
+    pub fn get(&self, path: &Path, fingerprint: &Fingerprint) -> Option<&str> {
+        self.get_checksum(path, fingerprint, ChecksumAlgo::Md5)
+    }
+
+    // Like get(), for any checksum.
+    pub fn get_checksum(
+        &self,
+        path: &Path,
+        fingerprint: &Fingerprint,
+        algo: ChecksumAlgo,
+    ) -> Option<&str> {
+        let entry = self
+            .entries
+            .get(&cache_key(path))
+            .filter(|entry| entry.fingerprint == *fingerprint)?;
+        match algo {
+            ChecksumAlgo::Md5 => Some(entry.md5.as_str()),
+            _ => entry.checksums.get(&algo).map(String::as_str),
+        }
+    }
+
+    // Cache the MD5 computed for fingerprint (taken before hashing), unless
+    // the file was modified too recently to trust its modification time.
+    // Returns whether the entry was cached.
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        fingerprint: Fingerprint,
+        md5: &str,
+        now: SystemTime,
+    ) -> bool {
+        let checksums = BTreeMap::from([(ChecksumAlgo::Md5, md5.to_string())]);
+        self.insert_checksums(path, fingerprint, &checksums, now)
+    }
+
+    // Like insert(), for checksums computed in one pass (which include the
+    // MD5). Checksums cached earlier for the same fingerprint are kept.
+    pub fn insert_checksums(
+        &mut self,
+        path: &Path,
+        fingerprint: Fingerprint,
+        checksums: &BTreeMap<ChecksumAlgo, String>,
+        now: SystemTime,
+    ) -> bool {
+        let key = cache_key(path);
+        let settled = now
+            .duration_since(fingerprint.mtime())
+            .is_ok_and(|age| age >= MIN_AGE);
+        let Some(md5) = checksums.get(&ChecksumAlgo::Md5) else {
+            return false;
+        };
+        let earlier = self
+            .entries
+            .remove(&key)
+            .filter(|entry| entry.fingerprint == fingerprint);
+        if !settled {
+            return false;
+        }
+        let mut entry = earlier.unwrap_or_else(|| CachedMd5 {
+            fingerprint,
+            md5: md5.clone(),
+            checksums: BTreeMap::new(),
+        });
+        entry.md5 = md5.clone();
+        for (algo, checksum) in checksums.iter().filter(|(algo, _)| !algo.is_md5()) {
+            entry.checksums.insert(*algo, checksum.clone());
+        }
+        self.entries.insert(key, entry);
+        true
+    }
+
+    // Drop the entries of files that no longer exist.
+    fn prune(&mut self) {
+        self.entries.retain(|path, _| Path::new(path).exists());
+    }
+}
+
+struct ActiveCache {
+    path_context: PathBuf,
+    cache: Md5Cache,
+    changed: bool,
+}
+
+static ACTIVE: Mutex<Option<ActiveCache>> = Mutex::new(None);
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+// Never use the cache in this run (sdf --rehash).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+// Use the cache of the project at path_context for this run (which is a
+// no-op if it was disabled, or is already in use).
+pub fn enable(path_context: &Path) {
+    if DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut active = ACTIVE.lock().unwrap();
+    if active.is_none() {
+        *active = Some(ActiveCache {
+            path_context: path_context.to_path_buf(),
+            cache: Md5Cache::load(path_context),
+            changed: false,
+        });
+    }
+}
+
+pub fn cached_md5(path: &Path, fingerprint: &Fingerprint) -> Option<String> {
+    cached_checksum(path, fingerprint, ChecksumAlgo::Md5)
+}
+
+pub fn cached_checksum(
+    path: &Path,
+    fingerprint: &Fingerprint,
+    algo: ChecksumAlgo,
+) -> Option<String> {
+    let active = ACTIVE.lock().unwrap();
+    active
+        .as_ref()?
+        .cache
+        .get_checksum(path, fingerprint, algo)
+        .map(|checksum| checksum.to_string())
+}
+
+pub fn record(path: &Path, fingerprint: Fingerprint, md5: &str) {
+    let checksums = BTreeMap::from([(ChecksumAlgo::Md5, md5.to_string())]);
+    record_checksums(path, fingerprint, &checksums);
+}
+
+pub fn record_checksums(
+    path: &Path,
+    fingerprint: Fingerprint,
+    checksums: &BTreeMap<ChecksumAlgo, String>,
+) {
+    if let Some(active) = ACTIVE.lock().unwrap().as_mut() {
+        active
+            .cache
+            .insert_checksums(path, fingerprint, checksums, SystemTime::now());
+        active.changed = true;
+    }
+}
+
+// Write the cache back, if any checksums were computed in this run.
+pub fn save() -> Result<()> {
+    let mut active = ACTIVE.lock().unwrap();
+    let Some(active) = active.as_mut().filter(|active| active.changed) else {
+        return Ok(());
+    };
+    active.cache.prune();
+    active.cache.save(&active.path_context)?;
+    active.changed = false;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::utils::{compute_checksum, compute_md5, rehash_checksum};
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    fn set_mtime(path: &Path, ago: Duration) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - ago).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_md5_cache_rehashes_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        enable(temp_dir.path());
+        let path = temp_dir.path().join("reads.fa");
+        let md5 = |contents: &str| format!("{:x}", md5::compute(contents));
+
+        fs::write(&path, "ACGT\n").unwrap();
+        set_mtime(&path, Duration::from_secs(3600));
+        let fingerprint = Fingerprint::of(&path).unwrap();
+        assert_eq!(compute_md5(&path).await.unwrap(), Some(md5("ACGT\n")));
+        assert_eq!(cached_md5(&path, &fingerprint), Some(md5("ACGT\n")));
+
+        // touched but not modified: hashed again, and still the same MD5
+        set_mtime(&path, Duration::from_secs(1800));
+        assert_eq!(cached_md5(&path, &Fingerprint::of(&path).unwrap()), None);
+        assert_eq!(compute_md5(&path).await.unwrap(), Some(md5("ACGT\n")));
+
+        // modified without changing size: the new modification time
+        // misses the cache, and is too recent to be cached itself
+        fs::write(&path, "TTGA\n").unwrap();
+        assert_eq!(compute_md5(&path).await.unwrap(), Some(md5("TTGA\n")));
+        assert_eq!(cached_md5(&path, &Fingerprint::of(&path).unwrap()), None);
+        fs::write(&path, "GGCA\n").unwrap();
+        assert_eq!(compute_md5(&path).await.unwrap(), Some(md5("GGCA\n")));
+    }
+
+    #[test]
+    fn test_other_checksums_are_cached_with_the_md5() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("calls.vcf");
+        fs::write(&path, "#CHROM\n").unwrap();
+        set_mtime(&path, Duration::from_secs(60));
+        let fingerprint = Fingerprint::of(&path).unwrap();
+        let now = SystemTime::now();
+
+        let mut cache = Md5Cache::default();
+        assert!(cache.insert(&path, fingerprint, "abc", now));
+        let blake3 = BTreeMap::from([
+            (ChecksumAlgo::Md5, "abc".to_string()),
+            (ChecksumAlgo::Blake3, "def".to_string()),
+        ]);
+        assert!(cache.insert_checksums(&path, fingerprint, &blake3, now));
+        let sha256 = BTreeMap::from([
+            (ChecksumAlgo::Md5, "abc".to_string()),
+            (ChecksumAlgo::Sha256, "123".to_string()),
+        ]);
+        assert!(cache.insert_checksums(&path, fingerprint, &sha256, now));
+        cache.save(temp_dir.path()).unwrap();
+        let cache = Md5Cache::load(temp_dir.path());
+        let get = |algo| cache.get_checksum(&path, &fingerprint, algo);
+        assert_eq!(get(ChecksumAlgo::Md5), Some("abc"));
+        assert_eq!(get(ChecksumAlgo::Blake3), Some("def"));
+        assert_eq!(get(ChecksumAlgo::Sha256), Some("123"));
+
+        // a new fingerprint drops the other checksums
+        let mut cache = cache;
+        let touched = Fingerprint {
+            mtime_secs: fingerprint.mtime_secs - 1,
+            ..fingerprint
+        };
+        assert!(cache.insert(&path, touched, "abc", now));
+        assert_eq!(
+            cache.get_checksum(&path, &touched, ChecksumAlgo::Blake3),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compute_checksum_uses_the_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        enable(temp_dir.path());
+        let path = temp_dir.path().join("aligned.bam");
+        fs::write(&path, "BAM\x01").unwrap();
+        set_mtime(&path, Duration::from_secs(3600));
+        let fingerprint = Fingerprint::of(&path).unwrap();
+        let blake3 = blake3::hash(b"BAM\x01").to_hex().to_string();
+
+        let computed = compute_checksum(&path, ChecksumAlgo::Blake3).await.unwrap();
+        assert_eq!(computed, Some(blake3.clone()));
+        assert_eq!(
+            cached_checksum(&path, &fingerprint, ChecksumAlgo::Blake3),
+            Some(blake3.clone())
+        );
+        assert!(cached_md5(&path, &fingerprint).is_some());
+
+        // a cached checksum is trusted, unless the file is rehashed
+        let stale = BTreeMap::from([
+            (ChecksumAlgo::Md5, "0".repeat(32)),
+            (ChecksumAlgo::Blake3, "0".repeat(64)),
+        ]);
+        record_checksums(&path, fingerprint, &stale);
+        let cached = compute_checksum(&path, ChecksumAlgo::Blake3).await.unwrap();
+        assert_eq!(cached, Some("0".repeat(64)));
+        let rehashed = rehash_checksum(&path, ChecksumAlgo::Blake3).await.unwrap();
+        assert_eq!(rehashed, Some(blake3));
+    }
+
+    #[test]
+    fn test_md5_cache_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("counts.tsv");
+        fs::write(&path, "gene\tcount\n").unwrap();
+        set_mtime(&path, Duration::from_secs(60));
+        let fingerprint = Fingerprint::of(&path).unwrap();
+
+        let mut cache = Md5Cache::default();
+        let now = SystemTime::now();
+        assert!(cache.insert(&path, fingerprint, "abc", now));
+        cache.save(temp_dir.path()).unwrap();
+        let cache = Md5Cache::load(temp_dir.path());
+        assert_eq!(cache.get(&path, &fingerprint), Some("abc"));
+        let resized = Fingerprint {
+            size: fingerprint.size + 1,
+            ..fingerprint
+        };
+        assert_eq!(cache.get(&path, &resized), None);
+
+        // too recently modified to trust the modification time
+        let mut cache = Md5Cache::default();
+        assert!(!cache.insert(&path, fingerprint, "abc", fingerprint.mtime()));
+        assert_eq!(cache.get(&path, &fingerprint), None);
+    }
+}
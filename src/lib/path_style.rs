@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+// Separator used to flatten nested paths for flat remotes,
+// e.g. "raw/sample_1.tsv" is stored as "raw__sample_1.tsv".
+pub const FLAT_SEPARATOR: &str = "__";
+
+// How paths under a tracked directory map onto remote file names.
+// FigShare articles and Zenodo depositions are flat, so nested files
+// are encoded into a single name; hierarchical remotes keep the
+// directories. Directories linked to S3 buckets and WebDAV servers are
+// hierarchical unless --path-style is given (see Remote::default_path_style),
+// while those linked before keep the flat default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    #[default]
+    Flat,
+    Hierarchical,
+}
+
+impl PathStyle {
+    pub fn is_flat(&self) -> bool {
+        *self == PathStyle::Flat
+    }
+
+    // The remote name of a file, from its path relative to the tracked
+    // directory. Files directly in the tracked directory keep their name.
+    pub fn encode(&self, relative_path: &str) -> String {
+        match self {
+            PathStyle::Flat => relative_path.replace('/', FLAT_SEPARATOR),
+            PathStyle::Hierarchical => relative_path.to_string(),
+        }
+    }
+
+    // The remote name of a manifest path under tracked_dir. Flat names
+    // cannot have FLAT_SEPARATOR in them, as they would not decode back to
+    // the path.
+    pub fn remote_name(&self, tracked_dir: &str, path: &str) -> Result<String> {
+        let relative = Path::new(path)
+            .strip_prefix(tracked_dir)
+            .unwrap_or(Path::new(path))
+            .to_string_lossy();
+        if self.is_flat() && relative.contains(FLAT_SEPARATOR) {
+            return Err(anyhow!(
+                "File '{}' has '{}' in its name, which a flat remote uses to separate \
+                 directories; rename it, or link '{}' with --path-style hierarchical.",
+                path,
+                FLAT_SEPARATOR,
+                tracked_dir
+            ));
+        }
+        Ok(self.encode(&relative))
+    }
+
+    // The path (relative to the tracked directory) of a remote file.
+    //
+    // Note: remote files are matched to manifest entries by encoding the
+    // manifest paths, so this is only needed for files that are not in
+    // the manifest.
+    pub fn decode(&self, remote_name: &str) -> String {
+        match self {
+            PathStyle::Flat => remote_name.replace(FLAT_SEPARATOR, "/"),
+            PathStyle::Hierarchical => remote_name.to_string(),
+        }
+    }
+
+    // Map manifest paths under tracked_dir to their remote names (remote
+    // name -> manifest path), erroring if two map onto the same name.
+    pub fn remote_names<'a>(
+        &self,
+        tracked_dir: &str,
+        paths: impl Iterator<Item = &'a String>,
+    ) -> Result<HashMap<String, String>> {
+        let mut names: HashMap<String, String> = HashMap::new();
+        for path in paths.filter(|path| Path::new(path).starts_with(tracked_dir)) {
+            let name = self.remote_name(tracked_dir, path)?;
+            if let Some(other) = names.insert(name.clone(), path.clone()) {
+                return Err(anyhow!(
+                    "Files '{}' and '{}' both map to the remote file '{}'; rename one of them.",
+                    other,
+                    path,
+                    name
+                ));
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_round_trip() {
+        let style = PathStyle::Flat;
+        let encoded = style.encode("raw/2024/sample_1.tsv.gz");
+        assert_eq!(encoded, "raw__2024__sample_1.tsv.gz");
+        assert_eq!(style.decode(&encoded), "raw/2024/sample_1.tsv.gz");
+        assert_eq!(style.encode("data.tsv"), "data.tsv");
+
+        let hierarchical = PathStyle::Hierarchical;
+        assert_eq!(hierarchical.encode("raw/a.tsv"), "raw/a.tsv");
+        assert_eq!(hierarchical.decode("raw/a.tsv"), "raw/a.tsv");
+    }
+
+    #[test]
+    fn test_flat_separator_in_name() {
+        let paths = ["data/raw/a.tsv".to_string(), "data/raw__a.tsv".to_string()];
+        let err = PathStyle::Flat
+            .remote_name("data", "data/raw__a.tsv")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("--path-style hierarchical"),
+            "{}",
+            err
+        );
+        assert!(PathStyle::Flat.remote_names("data", paths.iter()).is_err());
+        assert_eq!(
+            PathStyle::Flat
+                .remote_name("data", "data/raw/a.tsv")
+                .unwrap(),
+            "raw__a.tsv"
+        );
+        let names = PathStyle::Hierarchical
+            .remote_names("data", paths.iter())
+            .unwrap();
+        assert_eq!(names["raw/a.tsv"], "data/raw/a.tsv");
+    }
+}
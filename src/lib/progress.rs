@@ -1,14 +1,196 @@
 use anyhow::Result;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{
+    HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
+};
+use std::fmt::Write;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use crate::lib::utils::format_bytes;
+
 // these are separated since some APIs don't overload
 // indicatif bars, but take the same primitives.
 pub const DEFAULT_PROGRESS_STYLE: &str = "{spinner:.green} [{bar:40.green/white}] {pos:>}/{len} ({percent}%) eta {eta_precise:.green} {msg}";
 pub const DEFAULT_PROGRESS_INC: &str = "=> ";
 
+// Transfer bars count bytes, labeled with format_bytes().
+const TRANSFER_PROGRESS_STYLE: &str = "{spinner:.green} [{bar:40.green/white}] {done}/{total} ({rate}/s) eta {eta_precise:.green} {msg}";
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Hide progress for this run (sdf --quiet).
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// Progress bars are drawn unless --quiet was given, or stderr (where bars
+// are drawn) is not a terminal, e.g. in CI logs. There, unless --quiet was
+// given, progress is logged as plain lines instead.
+pub fn progress_enabled() -> bool {
+    !is_quiet() && std::io::stderr().is_terminal()
+}
+
+fn progress_logged() -> bool {
+    !is_quiet() && !std::io::stderr().is_terminal()
+}
+
+fn transfer_progress_style() -> Result<ProgressStyle> {
+    let bytes_key = |bytes: fn(&ProgressState) -> u64| {
+        move |state: &ProgressState, w: &mut dyn Write| {
+            let _ = write!(w, "{}", format_bytes(bytes(state)));
+        }
+    };
+    let style = ProgressStyle::default_bar()
+        .progress_chars(DEFAULT_PROGRESS_INC)
+        .template(TRANSFER_PROGRESS_STYLE)?
+        .with_key("done", bytes_key(|state| state.pos()))
+        .with_key("total", bytes_key(|state| state.len().unwrap_or(0)))
+        .with_key("rate", bytes_key(|state| state.per_sec() as u64));
+    Ok(style)
+}
+
+// The number of files finished, of those queued.
+#[derive(Debug, Default)]
+struct FileCount {
+    done: AtomicU64,
+    total: u64,
+}
+
+// Progress of a push or pull: a bar per file being transferred, and an
+// overall bar of the bytes of all files queued, labeled with how many
+// files are done.
+pub struct TransferProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    files: Arc<FileCount>,
+}
+
+impl TransferProgress {
+    pub fn new(total_bytes: u64, total_files: u64) -> Result<Self> {
+        let multi = if progress_enabled() {
+            MultiProgress::new()
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        };
+        let overall = multi.add(ProgressBar::new(total_bytes));
+        overall.set_style(transfer_progress_style()?);
+        overall.set_message(format!("total (0/{} files)", total_files));
+        let files = Arc::new(FileCount {
+            done: AtomicU64::new(0),
+            total: total_files,
+        });
+        Ok(TransferProgress {
+            multi,
+            overall,
+            files,
+        })
+    }
+
+    pub fn file(&self, name: &str, size: u64) -> Result<FileProgress> {
+        let bar = self
+            .multi
+            .insert_before(&self.overall, ProgressBar::new(size));
+        bar.set_style(transfer_progress_style()?);
+        bar.set_message(name.to_string());
+        Ok(FileProgress {
+            bar,
+            overall: self.overall.clone(),
+            files: self.files.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn finish(&self) {
+        self.overall.finish_and_clear();
+    }
+}
+
+// The progress of one file's transfer, which also advances the overall bar.
+#[derive(Debug, Clone)]
+pub struct FileProgress {
+    bar: ProgressBar,
+    overall: ProgressBar,
+    files: Arc<FileCount>,
+    name: String,
+}
+
+impl FileProgress {
+    // Progress that is not shown (e.g. for a single upload in tests).
+    pub fn hidden() -> Self {
+        FileProgress {
+            bar: ProgressBar::hidden(),
+            overall: ProgressBar::hidden(),
+            files: Arc::default(),
+            name: String::new(),
+        }
+    }
+
+    pub fn inc(&self, bytes: u64) {
+        self.bar.inc(bytes);
+        self.overall.inc(bytes);
+    }
+
+    // Set the bytes transferred so far (e.g. the size of a partial
+    // download, or 0 when an upload is retried).
+    pub fn set_position(&self, bytes: u64) {
+        let position = self.bar.position();
+        if bytes >= position {
+            self.overall.inc(bytes - position);
+        } else {
+            let overall = self.overall.position();
+            self.overall
+                .set_position(overall.saturating_sub(position - bytes));
+        }
+        self.bar.set_position(bytes);
+    }
+
+    // Whether all of a file of known size has been transferred.
+    pub fn is_complete(&self) -> bool {
+        self.bar
+            .length()
+            .is_some_and(|len| len > 0 && self.bar.position() >= len)
+    }
+
+    // Finish the file's bar, counting it as done (only the first time), and
+    // log it if bars are not drawn.
+    pub fn finish(&self) {
+        if self.bar.is_finished() {
+            return;
+        }
+        self.bar.finish_and_clear();
+        let done = self.files.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.overall
+            .set_message(format!("total ({}/{} files)", done, self.files.total));
+        if progress_logged() && !self.name.is_empty() {
+            eprintln!("{}", self.log_line(done));
+        }
+    }
+
+    // The line logged when the file is done (the done-th of all files),
+    // e.g. "[3/10 files, 1.20 MiB of 4.00 MiB, eta 5 seconds] data/a.tsv".
+    fn log_line(&self, done: u64) -> String {
+        let mut line = format!(
+            "[{}/{} files, {} of {}",
+            done,
+            self.files.total,
+            format_bytes(self.overall.position()),
+            format_bytes(self.overall.length().unwrap_or(0))
+        );
+        if done < self.files.total {
+            let _ = write!(line, ", eta {}", HumanDuration(self.overall.eta()));
+        }
+        format!("{}] {}", line, self.name)
+    }
+}
+
 pub fn default_progress_style() -> Result<ProgressStyle, anyhow::Error> {
     let style = ProgressStyle::default_bar()
         .progress_chars(DEFAULT_PROGRESS_INC)
@@ -27,6 +209,9 @@ impl Progress {
     pub fn new(len: u64) -> Result<Progress> {
         let bar = ProgressBar::new(len);
         bar.set_style(default_progress_style()?);
+        if !progress_enabled() {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
 
         let (tx, rx): (Sender<()>, Receiver<()>) = mpsc::channel();
 
@@ -46,6 +231,42 @@ impl Progress {
     }
 }
 
+impl Progress {
+    // Count one more item done, e.g. of "files hashed". If bars are not
+    // drawn, this is logged for each tenth of the items (and the last).
+    pub fn inc(&self, label: &str) {
+        self.bar.inc(1);
+        if progress_logged() {
+            if let Some(line) = self.log_line(label) {
+                eprintln!("{}", line);
+            }
+        }
+        let (done, total) = (self.bar.position(), self.bar.length().unwrap_or(0));
+        self.bar
+            .set_message(format!("{}/{} {}.", done, total, label));
+    }
+
+    // The line logged for the items done so far, if it is a tenth of them
+    // (or all of them), e.g. "20/100 files hashed. (eta 5 seconds)".
+    fn log_line(&self, label: &str) -> Option<String> {
+        let (done, total) = (self.bar.position(), self.bar.length().unwrap_or(0));
+        let step = total.div_ceil(10).max(1);
+        if done % step != 0 && done != total {
+            return None;
+        }
+        let message = format!("{}/{} {}.", done, total, label);
+        if done < total {
+            Some(format!(
+                "{} (eta {})",
+                message,
+                HumanDuration(self.bar.eta())
+            ))
+        } else {
+            Some(message)
+        }
+    }
+}
+
 impl Drop for Progress {
     fn drop(&mut self) {
         self.stop_spinner.send(()).unwrap();
@@ -54,3 +275,72 @@ impl Drop for Progress {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_progress_aggregates_files() {
+        set_quiet(true);
+        let progress = TransferProgress::new(300, 2).unwrap();
+        let upload = progress.file("data/a.tsv", 100).unwrap();
+        let download = progress.file("data/b.tsv", 200).unwrap();
+        upload.inc(60);
+        upload.inc(40);
+        // polled partial sizes only add what is new
+        download.set_position(50);
+        download.set_position(120);
+        assert_eq!(upload.bar.position(), 100);
+        assert_eq!(download.bar.position(), 120);
+        assert_eq!(progress.overall.position(), 220);
+
+        // files are counted as done once
+        upload.finish();
+        upload.finish();
+        assert_eq!(progress.overall.message(), "total (1/2 files)");
+        download.finish();
+        assert_eq!(progress.overall.message(), "total (2/2 files)");
+    }
+
+    // Without a terminal, finished files and every tenth item are logged
+    // as lines, rather than drawn as bars.
+    #[test]
+    fn test_progress_log_lines() {
+        let progress = TransferProgress::new(3 * 1024, 3).unwrap();
+        let file = progress.file("data/a.tsv", 1024).unwrap();
+        file.inc(1024);
+        let line = file.log_line(1);
+        assert!(
+            line.starts_with("[1/3 files, 1.00 KiB of 3.00 KiB, eta "),
+            "{}",
+            line
+        );
+        assert!(line.ends_with("] data/a.tsv"), "{}", line);
+        let last = progress.file("data/c.tsv", 2048).unwrap();
+        last.inc(2048);
+        assert_eq!(
+            last.log_line(3),
+            "[3/3 files, 3.00 KiB of 3.00 KiB] data/c.tsv"
+        );
+
+        let progress = Progress::new(25).unwrap();
+        let mut logged = Vec::new();
+        for _ in 0..25 {
+            progress.bar.inc(1);
+            if let Some(line) = progress.log_line("files hashed") {
+                logged.push(line);
+            }
+        }
+        let counts: Vec<&str> = logged
+            .iter()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(
+            counts,
+            ["3/25", "6/25", "9/25", "12/25", "15/25", "18/25", "21/25", "24/25", "25/25"]
+        );
+        assert!(logged[0].starts_with("3/25 files hashed. (eta "));
+        assert_eq!(logged[8], "25/25 files hashed.");
+    }
+}
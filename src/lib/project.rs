@@ -1,4 +1,7 @@
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use colored::*;
 use csv::{ReaderBuilder, StringRecord};
 use dirs;
 #[allow(unused_imports)]
@@ -10,14 +13,51 @@ use std::fs::{canonicalize, metadata, rename, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
+use crate::lib::api::dataverse::DataverseAPI;
 use crate::lib::api::figshare::FigShareAPI;
-use crate::lib::api::zenodo::ZenodoAPI;
+use crate::lib::api::filesystem::FilesystemAPI;
+use crate::lib::api::gdrive::{self, GoogleDriveAPI};
+use crate::lib::api::osf::OsfAPI;
+use crate::lib::api::rclone::RcloneAPI;
+use crate::lib::api::s3::{S3Credentials, S3API};
+use crate::lib::api::sftp::SftpAPI;
+use crate::lib::api::webdav::WebdavAPI;
+use crate::lib::api::zenodo::{self, ZenodoAPI};
+use crate::lib::attributes::{AttributeFile, ATTRIBUTES_FILE};
+use crate::lib::catalog::{read_datapackage, read_file_list};
+use crate::lib::checksum::ChecksumAlgo;
 use crate::lib::data::LocalStatusCode;
-use crate::lib::data::{DataCollection, DataFile};
+use crate::lib::data::{
+    CatSource, DataCollection, DataFile, DepositMetadata, StatusEntry, StatusOptions, VerifyReport,
+};
+use crate::lib::dedup::{find_duplicates, link_duplicate};
+use crate::lib::diff::ContentDiff;
 use crate::lib::download::Downloads;
+use crate::lib::export::{export_rows, write_export, ExportFormat};
+use crate::lib::filter::{FileFilter, SizeRange};
+use crate::lib::history::{self, history_path, log_rows, read_history, HISTORY_FILE};
+use crate::lib::hooks::{find_hook, run_hook, POST_PULL, PRE_PUSH};
+use crate::lib::ignore::IgnoreRules;
+use crate::lib::lock::{LockHeld, ProjectLock};
+use crate::lib::md5_cache::{self, MD5_CACHE};
+use crate::lib::path_style::PathStyle;
+use crate::lib::project_config::{ProjectConfig, PROJECT_CONFIG};
 use crate::lib::remote::Remote;
-use crate::lib::remote::{authenticate_remote, AuthKeys};
-use crate::lib::utils::{load_file, pluralize, print_status};
+use crate::lib::remote::{auth_keys_path, authenticate_remote, AuthKeys, MAX_CONCURRENT_TRANSFERS};
+use crate::lib::remote_cache::REMOTE_CACHE;
+use crate::lib::report::{
+    print_tsv, tsv_line, ExitError, Outcome, OutputFormat, TransferPlan, TransferReport,
+};
+use crate::lib::staging;
+use crate::lib::staging::StagedFile;
+use crate::lib::storage::io_settings;
+use crate::lib::usage::{print_latency, print_usage};
+use crate::lib::utils::{
+    confirm, format_bytes, format_mod_time, group_by_extension, load_file, normalize_path,
+    pluralize, print_extension_summary, print_fixed_width, print_status, project_path, shorten,
+    status_record, status_records, status_summary, DirSort, GroupBy, StatusRecord, STATUS_COLUMNS,
+};
+use crate::lib::walk::{expand_paths, is_pattern, WalkOptions};
 #[allow(unused_imports)]
 use crate::{print_info, print_warn};
 
@@ -25,6 +65,43 @@ use super::utils::is_directory;
 
 const MANIFEST: &str = "data_manifest.yml";
 
+// Passed as --manifest, reads the manifest from stdin (read-only
+// commands only, e.g. 'generate | sdf status --manifest -').
+pub const STDIN_MANIFEST: &str = "-";
+
+pub fn is_stdin_manifest(manifest: Option<&Path>) -> bool {
+    manifest == Some(Path::new(STDIN_MANIFEST))
+}
+
+// The directory of the project with this manifest (by default, the one
+// found from the current directory), if any. A manifest read from stdin
+// has none.
+pub fn project_dir(manifest: Option<&Path>) -> Option<PathBuf> {
+    if is_stdin_manifest(manifest) {
+        return None;
+    }
+    let manifest = match manifest {
+        Some(path) => path.to_path_buf(),
+        None => find_manifest(None, MANIFEST)?,
+    };
+    match manifest.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => Some(dir.to_path_buf()),
+        _ => Some(PathBuf::from(".")),
+    }
+}
+
+// The delimiter of a CSV or TSV file, by its extension.
+fn table_delimiter(filename: &str) -> Result<u8> {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(std::ffi::OsStr::to_str);
+    match extension {
+        Some("csv") => Ok(b','),
+        Some("tsv") => Ok(b'\t'),
+        _ => Err(anyhow!("Unsupported file type: {:?}", extension)),
+    }
+}
+
 pub fn find_manifest(start_dir: Option<&PathBuf>, filename: &str) -> Option<PathBuf> {
     let mut current_dir = match start_dir {
         Some(dir) => dir.to_path_buf(),
@@ -62,6 +139,9 @@ pub struct User {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Config {
     user: User,
+    // the rclone binary, if not the one on PATH
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rclone: Option<String>,
 }
 
 // Metadata about *local* project
@@ -70,13 +150,16 @@ pub struct Config {
 // that Remote.remote_init() can access, so we can pass
 // a single object to Remote.remote_init(). E.g. includes
 // User and DataCollectionMetadata.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct LocalMetadata {
     pub author_name: Option<String>,
     pub email: Option<String>,
     pub affiliation: Option<String>,
     pub title: Option<String>,
     pub description: Option<String>,
+    // the manifest's metadata for the deposit being linked (see
+    // DataCollectionMetadata::deposit())
+    pub deposit: DepositMetadata,
 }
 
 impl LocalMetadata {
@@ -87,14 +170,98 @@ impl LocalMetadata {
             affiliation: project.config.user.affiliation.clone(),
             title: project.data.metadata.title.clone(),
             description: project.data.metadata.description.clone(),
+            deposit: DepositMetadata::default(),
         }
     }
 }
 
+// Options for 'sdf add'.
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    // checksums recorded in addition to the MD5
+    pub algos: Vec<ChecksumAlgo>,
+    // the checksum used to detect changes
+    pub checksum: ChecksumAlgo,
+    pub block_size: Option<u64>,
+    pub size: SizeRange,
+    pub walk: WalkOptions,
+}
+
+// Options for 'sdf pull'.
+#[derive(Debug, Clone, Default)]
+pub struct PullOptions {
+    pub overwrite: bool,
+    // download from the URLs, rather than the remotes (or from both, with all)
+    pub urls: bool,
+    pub all: bool,
+    pub checksum_from_remote: bool,
+    // only print what would be downloaded
+    pub dry_run: bool,
+    pub size: SizeRange,
+    // the number of files downloaded at once
+    pub max_concurrent: usize,
+    // only pull from the remotes of this name (rather than from each
+    // directory's remote, then its mirrors)
+    pub remote: Option<String>,
+    // only pull these files, files in these directories, or files
+    // matching these patterns (all, if empty)
+    pub paths: Vec<String>,
+}
+
+// Options for 'sdf push'.
+#[derive(Debug, Clone, Default)]
+pub struct PushOptions {
+    pub overwrite: bool,
+    // upload only the changed parts of files, where supported
+    pub delta: bool,
+    // only print what would be uploaded
+    pub dry_run: bool,
+    // the number of files uploaded at once
+    pub max_concurrent: usize,
+    // only push to the remotes of this name (rather than to each
+    // directory's remote and mirrors)
+    pub remote: Option<String>,
+}
+
+// Options for 'sdf link'.
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    // the remote's project name (for S3, the bucket and key prefix)
+    pub name: Option<String>,
+    pub link_only: bool,
+    // (None: the remote's default, see Remote::default_path_style())
+    pub path_style: Option<PathStyle>,
+    // for S3: the endpoint (for S3-compatible stores) and region; for
+    // Dataverse, the endpoint is the server
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    // for Zenodo: use the sandbox, rather than the archive
+    pub sandbox: bool,
+    // for SFTP: compute MD5s by running md5sum on the server
+    pub md5sum: bool,
+    // add a mirror to an already-linked directory
+    pub mirror: bool,
+    // the DOI (or URL, or ID) of a published Zenodo record or FigShare
+    // article to link, read-only
+    pub published: Option<String>,
+}
+
+// The global options of 'sdf env' as given on the command line, so it can
+// say where each setting is from.
+#[derive(Debug, Clone, Default)]
+pub struct EnvOptions {
+    pub jobs: Option<u64>,
+    pub limit_rate: Option<u64>,
+}
+
 pub struct Project {
     pub manifest: PathBuf,
     pub data: DataCollection,
     pub config: Config,
+    // the manifest was read from stdin, so changes cannot be saved
+    pub from_stdin: bool,
+    // held until the project is dropped, if it may change the manifest
+    lock: Option<ProjectLock>,
 }
 
 impl Project {
@@ -128,18 +295,97 @@ impl Project {
     }
 
     pub fn new() -> Result<Self> {
-        let manifest = Project::get_manifest().context("Failed to get the manifest")?;
+        Project::with_manifest(None)
+    }
+
+    // Load the project, either from an explicitly set manifest
+    // (e.g. sdf --manifest <PATH>), or by searching up from the
+    // current directory. This takes the project's lock (see lock.rs), as
+    // the manifest may be changed.
+    pub fn with_manifest(manifest: Option<&Path>) -> Result<Self> {
+        Project::open(manifest, true)
+    }
+
+    // Load the project like with_manifest(), but if another run holds the
+    // lock, load it without (so, as with read_only(), changes are not to
+    // be saved; see is_locked()).
+    pub fn with_manifest_if_unlocked(manifest: Option<&Path>) -> Result<Self> {
+        match Project::open(manifest, true) {
+            Err(err) if err.is::<LockHeld>() => {
+                print_info!(
+                    "Another sdf process is modifying this project, so changes (e.g. fetched \
+                     remote state) are not saved."
+                );
+                Project::open(manifest, false)
+            }
+            result => result,
+        }
+    }
+
+    fn open(manifest: Option<&Path>, lock: bool) -> Result<Self> {
+        if is_stdin_manifest(manifest) {
+            return Err(anyhow!(
+                "This command modifies the manifest, so it cannot be read from stdin (--manifest -)."
+            ));
+        }
+        let manifest = match manifest {
+            Some(path) => canonicalize(path)
+                .with_context(|| format!("Manifest '{}' not found.", path.to_string_lossy()))?,
+            None => Project::get_manifest().context("Failed to get the manifest")?,
+        };
         info!("manifest: {:?}", manifest);
+        let path_context = manifest.parent().unwrap_or(Path::new("."));
+        // locked before loading, so the manifest is not read mid-change
+        let lock = if lock {
+            Some(ProjectLock::acquire(path_context)?)
+        } else {
+            None
+        };
         let data = Project::load(&manifest).context("Failed to load data from the manifest")?;
+        md5_cache::enable(path_context);
         let config = Project::load_config().context("Failed to load the project configuration")?;
         let proj = Project {
             manifest,
             data,
             config,
+            from_stdin: false,
+            lock,
         };
         Ok(proj)
     }
 
+    // Load the project for a read-only command. This is like
+    // with_manifest(), but the manifest can also be piped in with
+    // --manifest -, in which case paths are relative to the current
+    // directory. The project is not locked.
+    pub fn read_only(manifest: Option<&Path>) -> Result<Self> {
+        if !is_stdin_manifest(manifest) {
+            return Project::open(manifest, false);
+        }
+        let config = Project::load_config().context("Failed to load the project configuration")?;
+        Project::from_reader(std::io::stdin(), &env::current_dir()?, config)
+    }
+
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        path_context: &Path,
+        config: Config,
+    ) -> Result<Self> {
+        let mut contents = String::new();
+        reader
+            .read_to_string(&mut contents)
+            .context("Failed to read the manifest from stdin")?;
+        let data =
+            Project::parse_manifest(&contents).context("Failed to load data from the manifest")?;
+        Ok(Project {
+            manifest: path_context.join(MANIFEST),
+            data,
+            config,
+            from_stdin: true,
+            lock: None,
+        })
+    }
+
     fn get_parent_dir(file: &Path) -> String {
         file.parent()
             .and_then(|path| path.file_name())
@@ -147,6 +393,154 @@ impl Project {
             .unwrap_or_else(|| panic!("invalid project location: is it in root?"))
     }
 
+    // Resolve the effective configuration (manifest, config, and
+    // credential locations, and the settings commands use), for 'sdf env'.
+    // No secrets are included.
+    pub fn env(manifest: Option<&Path>, options: &EnvOptions) -> Result<Vec<(String, String)>> {
+        let mut entries = Vec::new();
+
+        let (manifest_path, source) = match manifest {
+            Some(path) => (
+                Some(canonicalize(path).with_context(|| {
+                    format!("Manifest '{}' not found.", path.to_string_lossy())
+                })?),
+                "set by --manifest",
+            ),
+            None => (Project::get_manifest().ok(), "found from current directory"),
+        };
+        let manifest_str = match &manifest_path {
+            Some(path) => format!("{} ({})", path.to_string_lossy(), source),
+            None => "not found (has sdf init been run?)".to_string(),
+        };
+        entries.push(("manifest".to_string(), manifest_str));
+
+        let config = config_path()?;
+        let config_state = if config.exists() { "" } else { " (not found)" };
+        entries.push((
+            "config".to_string(),
+            format!("{}{}", config.to_string_lossy(), config_state),
+        ));
+
+        let auth_keys_path = auth_keys_path()?;
+        let auth_keys_state = if auth_keys_path.exists() {
+            ""
+        } else {
+            " (not found)"
+        };
+        entries.push((
+            "auth keys".to_string(),
+            format!("{}{}", auth_keys_path.to_string_lossy(), auth_keys_state),
+        ));
+
+        // the project's files, and the settings resolved from the command
+        // line, the project's config, and their defaults
+        let project_dir = manifest_path
+            .as_ref()
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+        let project_config = match &project_dir {
+            Some(dir) => ProjectConfig::load(dir)?,
+            None => ProjectConfig::default(),
+        };
+        let from_config = format!("from {}", PROJECT_CONFIG);
+        let setting = |value: String, source: &str| format!("{} ({})", value, source);
+        if let Some(dir) = &project_dir {
+            let path_entry = |path: PathBuf| {
+                let state = if path.exists() { "" } else { " (not found)" };
+                format!("{}{}", path.to_string_lossy(), state)
+            };
+            for (key, file) in [
+                ("project config", PROJECT_CONFIG),
+                ("md5 cache", MD5_CACHE),
+                ("remote cache", REMOTE_CACHE),
+                ("history", HISTORY_FILE),
+            ] {
+                entries.push((key.to_string(), path_entry(dir.join(file))));
+            }
+        }
+
+        let jobs = io_settings().jobs.to_string();
+        let jobs = match (options.jobs, project_config.jobs) {
+            (Some(_), _) => setting(jobs, "set by --jobs"),
+            (None, Some(_)) => setting(jobs, &from_config),
+            (None, None) => setting(jobs, &format!("tuned to {} storage", io_settings().storage)),
+        };
+        entries.push(("jobs".to_string(), jobs));
+
+        let algo = |algo: ChecksumAlgo| format!("{:?}", algo).to_lowercase();
+        let mut checksum_algo = match project_config.checksum_algo {
+            Some(checksum_algo) => setting(algo(checksum_algo), &from_config),
+            None => setting(algo(ChecksumAlgo::default()), "default"),
+        };
+        if project_dir.is_some_and(|dir| dir.join(ATTRIBUTES_FILE).exists()) {
+            checksum_algo.push_str(&format!(", unless {} sets another", ATTRIBUTES_FILE));
+        }
+        entries.push(("checksum algo".to_string(), checksum_algo));
+
+        // (unless push or pull is given --max-concurrent)
+        let max_concurrent = match (
+            options.jobs,
+            project_config.max_concurrent,
+            project_config.jobs,
+        ) {
+            (Some(jobs), _, _) => setting(jobs.to_string(), "set by --jobs"),
+            (None, Some(max), _) => setting(max.to_string(), &from_config),
+            (None, None, Some(jobs)) => setting(jobs.to_string(), &format!("jobs {}", from_config)),
+            (None, None, None) => setting(MAX_CONCURRENT_TRANSFERS.to_string(), "default"),
+        };
+        entries.push((
+            "max concurrent".to_string(),
+            format!("{}, at most 2 for Zenodo and FigShare", max_concurrent),
+        ));
+        let rate = |rate: u64| format!("{}/s", format_bytes(rate));
+        let limit_rate = match (options.limit_rate, project_config.limit_rate) {
+            (Some(limit), _) => setting(rate(limit), "set by --limit-rate"),
+            (None, Some(limit)) => setting(rate(limit), &from_config),
+            (None, None) => "none".to_string(),
+        };
+        entries.push(("limit rate".to_string(), limit_rate));
+
+        let default_remote = match project_config.default_remote {
+            Some(remote) => setting(remote, &from_config),
+            None => "none (each directory's remote, then its mirrors)".to_string(),
+        };
+        entries.push(("default remote".to_string(), default_remote));
+        entries.push((
+            "byte units".to_string(),
+            "binary (1 KiB = 1024 bytes)".to_string(),
+        ));
+
+        if let Some(path) = &manifest_path {
+            let data = Project::load(path)?;
+            let auth_keys = AuthKeys::new();
+            let mut dirs: Vec<&String> = data.remotes.keys().collect();
+            dirs.sort();
+            for dir in dirs {
+                for (i, remote) in data.remotes_of(dir).into_iter().enumerate() {
+                    let service = remote.auth_key();
+                    let aws_source = match remote {
+                        Remote::S3API(_) => S3Credentials::aws_source(),
+                        _ => None,
+                    };
+                    let credential = if let Some(source) = aws_source {
+                        source
+                    } else if let Some(source) = remote.tokenless_credential() {
+                        format!("none needed ({})", source)
+                    } else if auth_keys.contains(service) {
+                        format!("key '{}' in {}", service, auth_keys_path.to_string_lossy())
+                    } else {
+                        format!("missing (no '{}' key)", service)
+                    };
+                    let kind = if i == 0 { "remote" } else { "mirror" };
+                    entries.push((
+                        format!("{} '{}'", kind, dir),
+                        format!("{} (credential: {})", remote.name(), credential),
+                    ));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
     // This tries to figure out a good default name to use, e.g. for
     // remote titles or names.
     //
@@ -176,6 +570,8 @@ impl Project {
                 manifest,
                 data,
                 config,
+                from_stdin: false,
+                lock: None,
             };
             // save to create the manifest
             proj.save()?;
@@ -202,6 +598,7 @@ impl Project {
         name: &Option<String>,
         email: &Option<String>,
         affiliation: &Option<String>,
+        rclone: &Option<String>,
     ) -> Result<()> {
         let mut config = Project::load_config().unwrap_or_else(|_| Config {
             user: User {
@@ -209,6 +606,7 @@ impl Project {
                 email: None,
                 affiliation: None,
             },
+            rclone: None,
         });
         info!("read config: {:?}", config);
         if let Some(new_name) = name {
@@ -220,6 +618,9 @@ impl Project {
         if let Some(new_affiliation) = affiliation {
             config.user.affiliation = Some(new_affiliation.to_string());
         }
+        if let Some(new_rclone) = rclone {
+            config.rclone = Some(new_rclone.to_string());
+        }
         if config.user.name.is_empty() {
             return Err(anyhow!("Config 'name' not set, and cannot be empty."));
         }
@@ -228,24 +629,51 @@ impl Project {
     }
 
     pub fn save(&self) -> Result<()> {
+        if self.from_stdin {
+            return Err(anyhow!(
+                "Cannot save changes to a manifest read from stdin (--manifest -)."
+            ));
+        }
+        // the changes are recorded in the history (see history.rs), against
+        // the manifest as last saved
+        let before = if self.manifest.exists() {
+            Project::load(&self.manifest).unwrap_or_default()
+        } else {
+            DataCollection::new()
+        };
+        Project::write_manifest(&self.manifest, &self.data)?;
+        let changes = history::changes(&before, &self.data);
+        if let Err(err) = history::record(&self.path_context(), changes, Utc::now()) {
+            print_warn!("The manifest was saved, but not its history: {}", err);
+        }
+        Ok(())
+    }
+
+    fn write_manifest(manifest: &Path, data: &DataCollection) -> Result<()> {
         // Serialize the data
-        let serialized_data = serde_yaml::to_string(&self.data)
+        let serialized_data = serde_yaml::to_string(data)
             .map_err(|err| anyhow::anyhow!("Failed to serialize data manifest: {}", err))?;
 
-        // Create the file
-        let mut file = File::create(&self.manifest)
-            .map_err(|err| anyhow::anyhow!("Failed to open file '{:?}': {}", self.manifest, err))?;
+        // Write to a staged file, then move it into place, so that an
+        // interrupted write cannot leave a truncated manifest
+        let path_context = manifest.parent().unwrap_or(Path::new("."));
+        let staged = StagedFile::new(path_context, "manifest")?;
+        let mut file = File::create(staged.path())
+            .map_err(|err| anyhow::anyhow!("Failed to open file '{:?}': {}", staged.path(), err))?;
 
         // Write the serialized data to the file
         write!(file, "{}", serialized_data)
             .map_err(|err| anyhow::anyhow!("Failed to write data manifest: {}", err))?;
+        file.sync_all()?;
 
-        Ok(())
+        staged.persist(manifest)
     }
 
     fn load(manifest: &PathBuf) -> Result<DataCollection> {
-        let contents = load_file(manifest);
+        Project::parse_manifest(&load_file(manifest))
+    }
 
+    fn parse_manifest(contents: &str) -> Result<DataCollection> {
         if contents.trim().is_empty() {
             // empty manifest, just create a new one
             return Err(anyhow!(
@@ -253,10 +681,15 @@ impl Project {
             ));
         }
 
-        let data = serde_yaml::from_str(&contents)?;
+        let data = serde_yaml::from_str(contents)?;
         Ok(data)
     }
 
+    // Whether this run holds the project's lock, so it can save changes.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
     /// Get the absolute path context of the current project.
     pub fn path_context(&self) -> PathBuf {
         let path = self.manifest.parent().unwrap().to_path_buf();
@@ -285,7 +718,11 @@ impl Project {
         // Compute relative path directly using strip_prefix
         match absolute_path.strip_prefix(&path_context) {
             Ok(rel_path) => Ok(rel_path.to_path_buf()),
-            Err(_) => Err(anyhow::anyhow!("Failed to compute relative path")),
+            Err(_) => Err(anyhow!(
+                "Path '{}' is outside the project directory '{}'.",
+                path.to_string_lossy(),
+                path_context.to_string_lossy()
+            )),
         }
     }
 
@@ -293,29 +730,315 @@ impl Project {
         if !path.exists() {
             Err(anyhow!("Path '{}' does not exist.", path.to_string_lossy()))
         } else {
-            Ok(self.relative_path(path)?.to_string_lossy().to_string())
+            Ok(normalize_path(&self.relative_path(path)?.to_string_lossy()))
+        }
+    }
+
+    // The manifest key of path. The file may be missing locally, in which
+    // case path is taken to be relative to the project (unless absolute).
+    pub fn manifest_path(&self, path: &str) -> Result<String> {
+        if Path::new(path).exists() {
+            self.relative_path_string(Path::new(path))
+        } else {
+            project_path(&self.path_context(), Path::new(path))
         }
     }
 
-    pub async fn remove(&mut self, files: &Vec<String>) -> Result<()> {
-        let mut num_removed = 0;
+    // The manifest paths of the paths (or glob patterns, relative to the
+    // current directory) given to restrict a command.
+    fn filter_paths(&self, paths: &[String]) -> Result<Vec<String>> {
+        paths
+            .iter()
+            .map(|path| {
+                if !is_pattern(path) || Path::new(path).exists() {
+                    return self.manifest_path(path);
+                }
+                // patterns outside the project are taken to be relative to it
+                let current_dir = self.relative_path(&env::current_dir()?).unwrap_or_default();
+                Ok(normalize_path(&current_dir.join(path).to_string_lossy()))
+            })
+            .collect()
+    }
+
+    // Remove files from the manifest (which may already be deleted on
+    // disk), and with delete_local, delete them. Remote copies are left
+    // alone. The manifest is saved before any file is deleted, so an
+    // interrupted removal never leaves entries for deleted files.
+    pub async fn remove(&mut self, files: &Vec<String>, delete_local: bool) -> Result<()> {
+        let mut removed = Vec::new();
         for filename in files {
             info!("Removing file '{}'.", filename);
-            let filepath = self.relative_path_string(Path::new(filename))?;
-            let removed = self.data.remove(&filepath).await;
-            num_removed += removed as i32;
+            let filepath = self.manifest_path(filename)?;
+            if let Some(data_file) = self.data.files.get(&filepath).filter(|file| file.tracked) {
+                if let Some(remote) = self.data.get_this_files_remote(data_file)? {
+                    print_warn!(
+                        "'{}' is tracked on {}, and its remote copy was not removed \
+                         (delete it on {} directly if it is no longer needed).",
+                        filepath,
+                        remote,
+                        remote
+                    );
+                }
+            }
+            if self.data.remove(&filepath).await {
+                removed.push(filepath);
+            }
         }
-        println!("Removed {}.", pluralize(num_removed as u64, "file"));
-        self.save()
+        self.save()?;
+        println!("Removed {}.", pluralize(removed.len() as u64, "file"));
+
+        if delete_local {
+            let mut num_deleted = 0;
+            for filepath in &removed {
+                let path = self.path_context().join(filepath);
+                if !path.exists() {
+                    continue;
+                }
+                std::fs::remove_file(&path)
+                    .map_err(|err| anyhow!("Could not delete '{}': {}", filepath, err))?;
+                num_deleted += 1;
+            }
+            println!("Deleted {} locally.", pluralize(num_deleted as u64, "file"));
+        }
+        Ok(())
     }
 
-    pub async fn status(&mut self, include_remotes: bool, all: bool) -> Result<()> {
+    // Remove the manifest entries whose files are missing locally (e.g.
+    // deleted outside sdf), once confirmed (or if yes). Tracked files still
+    // on their remote are warned about, since their remote copy would no
+    // longer be in the manifest.
+    pub async fn prune(&mut self, yes: bool) -> Result<()> {
+        let missing = self.data.missing_files(&self.path_context());
+        if missing.is_empty() {
+            println!("No manifest entries are missing their files.");
+            return Ok(());
+        }
+        println!(
+            "{} missing locally:",
+            pluralize(missing.len() as u64, "file")
+        );
+        for path in &missing {
+            println!("  {}", path);
+        }
+        match self.data.on_remotes(&missing).await {
+            Ok(on_remotes) => {
+                for (path, remote) in on_remotes {
+                    print_warn!(
+                        "'{}' is still on {}; once pruned, its remote copy is orphaned \
+                         (delete it on {} directly if it is no longer needed).",
+                        path,
+                        remote,
+                        remote
+                    );
+                }
+            }
+            Err(err) => {
+                print_warn!("Could not check which files are on remotes: {}", err);
+            }
+        }
+        if !yes && !confirm("Remove these entries from the manifest?")? {
+            println!("Nothing was pruned.");
+            return Ok(());
+        }
+        for path in &missing {
+            self.data.remove(path).await;
+        }
+        self.save()?;
+        println!(
+            "Pruned {} from the manifest.",
+            pluralize(missing.len() as u64, "file")
+        );
+        Ok(())
+    }
+
+    pub async fn status(
+        &mut self,
+        options: &StatusOptions,
+        all: bool,
+        group_by: GroupBy,
+        format: OutputFormat,
+        hyperlinks: bool,
+        dir_sort: DirSort,
+    ) -> Result<()> {
+        if options.ahead_behind {
+            return self.print_ahead_behind(format).await;
+        }
+        if options.only_changed_remotes && !options.include_remotes && !options.cached_remotes {
+            return Err(anyhow!(
+                "--only-changed-remotes requires --remotes or --assume-remote-current."
+            ));
+        }
+        if let Some(filter) = options.filters.iter().find(|filter| filter.needs_remotes()) {
+            if !options.include_remotes && !options.cached_remotes {
+                return Err(anyhow!(
+                    "--filter {} requires --remotes or --assume-remote-current.",
+                    filter.to_possible_value().unwrap().get_name()
+                ));
+            }
+        }
+        let options = &StatusOptions {
+            paths: self.filter_paths(&options.paths)?,
+            ..options.clone()
+        };
         // if include_remotes (e.g. --remotes) is set, we need to merge
         // in the remotes, so we authenticate first and then get them.
         let path_context = &canonicalize(self.path_context())?;
-        let status_rows = self.data.status(path_context, include_remotes).await?;
+        if options.pending_upload_size {
+            let size = self
+                .data
+                .pending_upload_size(path_context, options.cached_remotes)
+                .await?;
+            println!("{}", format_bytes(size));
+            return Ok(());
+        }
+        if options.stream {
+            return self
+                .print_status_stream(path_context, options, all, format)
+                .await;
+        }
+        let status_rows = self.data.status(path_context, options).await?;
+        if format == OutputFormat::Table {
+            for warning in self.data.unknown_remote_warnings()? {
+                print_warn!("{}", warning);
+            }
+        }
         //let remotes: Option<_> = include_remotes.then(|| &self.data.remotes);
-        print_status(status_rows, Some(&self.data.remotes), all);
+        if options.cached_remotes && format == OutputFormat::Table {
+            println!(
+                "{}",
+                "Remote status based on cached remote state (not fetched).".yellow()
+            );
+        }
+        match group_by {
+            GroupBy::Directory if format == OutputFormat::Json => {
+                let records = status_records(&status_rows, all);
+                println!("{}", serde_json::to_string_pretty(&records)?);
+            }
+            GroupBy::Directory if format == OutputFormat::Tsv => {
+                let rows: Vec<Vec<String>> = status_records(&status_rows, all)
+                    .iter()
+                    .map(StatusRecord::tsv_row)
+                    .collect();
+                print_tsv(&STATUS_COLUMNS, &rows);
+            }
+            GroupBy::Directory => {
+                let links = hyperlinks.then_some(path_context.as_path());
+                print_status(
+                    status_rows,
+                    Some(&self.data.linked_remotes()),
+                    all,
+                    links,
+                    dir_sort,
+                    options.tree_size,
+                    options.max_column_width,
+                );
+                print_usage(&self.data.usage());
+            }
+            GroupBy::Extension => {
+                print_extension_summary(&group_by_extension(&status_rows, all), format)?
+            }
+        }
+        if options.remote_latency && format == OutputFormat::Table {
+            print_latency(&self.data.latency());
+        }
+        if options.include_remotes && self.is_locked() {
+            // save the fetched remote state, for later offline use
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // Print each file's status as soon as it is hashed (in the order they
+    // finish, so the first lines come before large files are done), then
+    // the summary. With --format json, each line is a status record (and
+    // with --format tsv, a row, after the header).
+    async fn print_status_stream(
+        &mut self,
+        path_context: &Path,
+        options: &StatusOptions,
+        all: bool,
+        format: OutputFormat,
+    ) -> Result<()> {
+        if format == OutputFormat::Tsv {
+            println!("{}", tsv_line(&STATUS_COLUMNS));
+        }
+        let mut print_entry = |directory: &str, entry: &StatusEntry| {
+            if !all && entry.local_status.is_none() {
+                return;
+            }
+            match format {
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&status_record(directory, entry))
+                        .expect("Internal Error: status_record() is serializable.")
+                ),
+                OutputFormat::Tsv => {
+                    println!("{}", tsv_line(&status_record(directory, entry).tsv_row()))
+                }
+                OutputFormat::Table => println!("{}", entry.line(directory)),
+            }
+        };
+        let status_rows = self
+            .data
+            .status_streamed(path_context, options, &mut print_entry)
+            .await?;
+        if format == OutputFormat::Table {
+            println!();
+            for line in status_summary(&status_rows).lines() {
+                println!("{}", line);
+            }
+            for warning in self.data.unknown_remote_warnings()? {
+                print_warn!("{}", warning);
+            }
+        }
+        if options.include_remotes && !self.from_stdin {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // Print the ahead/behind/conflict counts of each remote. Unlike
+    // 'status --remotes', this does not save the fetched remote state, so
+    // it does not move the last sync.
+    async fn print_ahead_behind(&mut self, format: OutputFormat) -> Result<()> {
+        let path_context = canonicalize(self.path_context())?;
+        let summaries = self.data.ahead_behind(&path_context).await?;
+        match format {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+                return Ok(());
+            }
+            OutputFormat::Tsv => {
+                let rows: Vec<Vec<String>> = summaries
+                    .iter()
+                    .map(|summary| {
+                        vec![
+                            summary.directory.clone(),
+                            summary.remote.clone(),
+                            summary.ahead.to_string(),
+                            summary.behind.to_string(),
+                            summary.conflict.to_string(),
+                        ]
+                    })
+                    .collect();
+                print_tsv(
+                    &["directory", "remote", "ahead", "behind", "conflict"],
+                    &rows,
+                );
+                return Ok(());
+            }
+            OutputFormat::Table => (),
+        }
+        for summary in summaries {
+            println!(
+                "{} > {}: {} ahead (to push), {} behind (to pull), {} in conflict.",
+                summary.directory,
+                summary.remote,
+                pluralize(summary.ahead as u64, "file"),
+                summary.behind,
+                summary.conflict
+            );
+        }
         Ok(())
     }
 
@@ -349,19 +1072,177 @@ impl Project {
     Ok(())
     } */
 
-    pub async fn add(&mut self, files: &Vec<String>) -> Result<()> {
+    // Add files (or glob patterns, or directories with options.walk) to
+    // the manifest. Files found by a pattern or in a directory are skipped
+    // if they are already in the manifest, but naming one is an error.
+    // Files that cannot be hashed are reported, and the others still added.
+    pub async fn add(&mut self, files: &[String], options: &AddOptions) -> Result<()> {
+        let path_context = self.path_context();
+        let attributes = AttributeFile::load(&path_context)?;
+        let manifest = canonicalize(&self.manifest).ok();
         let mut num_added = 0;
-        for filepath in files {
-            let filename = self.relative_path_string(Path::new(&filepath.clone()))?;
-            let data_file = DataFile::new(filename.clone(), None, &self.path_context()).await?;
+        let mut num_skipped = 0;
+        let mut num_registered = 0;
+        let mut num_failed = 0;
+        let mut ignore = IgnoreRules::new(&path_context);
+        for found in expand_paths(files, options.walk, &mut ignore)? {
+            if !found.explicit && canonicalize(&found.path).ok() == manifest {
+                continue;
+            }
+            let filename = self.relative_path_string(&found.path)?;
+            if !found.explicit && self.data.files.contains_key(&filename) {
+                num_registered += 1;
+                continue;
+            }
+            if !options.size.is_empty() {
+                let file_size = std::fs::metadata(path_context.join(&filename))?.len();
+                if !options.size.contains(file_size) {
+                    info!("Skipping file '{}' (outside the size range).", filename);
+                    num_skipped += 1;
+                    continue;
+                }
+            }
+            let data_file = match self.new_data_file(&filename, &attributes, options).await {
+                Ok(data_file) => data_file,
+                Err(err) => {
+                    print_warn!("Could not add '{}': {}", filename, err);
+                    num_failed += 1;
+                    continue;
+                }
+            };
             info!("Adding file '{}'.", filename);
             self.data.register(data_file)?;
             num_added += 1;
         }
         println!("Added {}.", pluralize(num_added as u64, "file"));
+        if num_registered > 0 {
+            println!(
+                "Skipped {} already in the manifest.",
+                pluralize(num_registered as u64, "file")
+            );
+        }
+        if num_skipped > 0 {
+            println!(
+                "Skipped {} outside the size range.",
+                pluralize(num_skipped as u64, "file")
+            );
+        }
+        self.save()?;
+        if num_failed > 0 {
+            return Err(anyhow!(
+                "Could not add {}.",
+                pluralize(num_failed as u64, "file")
+            ));
+        }
+        Ok(())
+    }
+
+    // Hash a file to be added, as set by options and .sdfattributes.
+    async fn new_data_file(
+        &self,
+        filename: &str,
+        attributes: &AttributeFile,
+        options: &AddOptions,
+    ) -> Result<DataFile> {
+        let path_context = self.path_context();
+        let (mut algos, block_size) =
+            attributes.apply(filename, &options.algos, options.block_size);
+        algos.push(options.checksum);
+        let mut data_file =
+            DataFile::new_with_checksums(filename.to_string(), None, &path_context, &algos).await?;
+        data_file.algo = options.checksum;
+        let path_attributes = attributes.for_path(filename);
+        data_file.compression = path_attributes.compression;
+        data_file.encryption = path_attributes.encryption;
+        if let Some(block_size) = block_size {
+            data_file
+                .add_block_hashes(&path_context, block_size)
+                .await?;
+        }
+        Ok(data_file)
+    }
+
+    // Add the files listed in a data catalog (a Frictionless
+    // datapackage.json) to the manifest, using the catalog's checksums.
+    pub async fn import_catalog(&mut self, filename: &str) -> Result<()> {
+        let catalog = read_datapackage(&std::fs::read_to_string(filename)?)?;
+        let catalog_dir = Path::new(filename)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let prefix = self.relative_path(catalog_dir)?;
+        let path_context = self.path_context();
+
+        for (resource, reason) in &catalog.skipped {
+            print_warn!("Skipping '{}': {}.", resource, reason);
+        }
+        let mut num_added = 0;
+        for entry in &catalog.entries {
+            let data_file = entry.to_data_file(&prefix, &path_context).await?;
+            if self.data.contains(&data_file.path).await? {
+                print_warn!("Skipping '{}': already in the manifest.", data_file.path);
+                continue;
+            }
+            self.data.register(data_file)?;
+            num_added += 1;
+        }
+        let metadata = &mut self.data.metadata;
+        if metadata.title.is_none() {
+            metadata.title = catalog.title;
+        }
+        if metadata.description.is_none() {
+            metadata.description = catalog.description;
+        }
+        println!("Imported {}.", pluralize(num_added as u64, "file"));
         self.save()
     }
 
+    // Add the files in a CSV or TSV file list, checking them against any
+    // checksums it has. Files that are missing or do not match are not
+    // added, and are listed in the error (the others still are).
+    pub async fn import_list(&mut self, filename: &str) -> Result<()> {
+        let contents = std::fs::read_to_string(filename)
+            .map_err(|err| anyhow!("Could not read '{}': {}", filename, err))?;
+        let catalog = read_file_list(&contents, table_delimiter(filename)?)?;
+        let list_dir = Path::new(filename)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let prefix = self.relative_path(list_dir)?;
+        let path_context = self.path_context();
+
+        let mut failures = catalog.skipped;
+        let mut num_added = 0;
+        for entry in &catalog.entries {
+            let path = normalize_path(&prefix.join(&entry.path).to_string_lossy());
+            if self.data.contains(&path).await? {
+                print_warn!("Skipping '{}': already in the manifest.", path);
+                continue;
+            }
+            match entry.verify(&prefix, &path_context).await {
+                Ok(data_file) => {
+                    self.data.register(data_file)?;
+                    num_added += 1;
+                }
+                Err(err) => failures.push((path, err.to_string())),
+            }
+        }
+        println!("Imported {}.", pluralize(num_added as u64, "file"));
+        self.save()?;
+        if !failures.is_empty() {
+            let report: Vec<String> = failures
+                .iter()
+                .map(|(path, reason)| format!("  {}: {}", path, reason))
+                .collect();
+            return Err(anyhow!(
+                "Could not import {}:\n{}",
+                pluralize(failures.len() as u64, "file"),
+                report.join("\n")
+            ));
+        }
+        Ok(())
+    }
+
     pub async fn update(&mut self, files: Option<&Vec<String>>) -> Result<()> {
         let path_context = self.path_context();
         let mut num_updated = 0;
@@ -400,57 +1281,241 @@ impl Project {
         &mut self,
         dir: &str,
         service: &str,
-        key: &str,
-        name: &Option<String>,
-        link_only: &bool,
+        key: Option<&str>,
+        options: &LinkOptions,
     ) -> Result<()> {
         // (0) get the relative directory path
         let dir = self.relative_path_string(Path::new(dir))?;
+        let service = service.to_lowercase();
+        if service != "s3" && options.region.is_some() {
+            return Err(anyhow!("--region is only used with S3."));
+        }
+        if service != "s3" && service != "dataverse" && options.endpoint.is_some() {
+            return Err(anyhow!("--endpoint is only used with S3 and Dataverse."));
+        }
+        if service != "zenodo" && options.sandbox {
+            return Err(anyhow!("--sandbox is only used with Zenodo."));
+        }
+        if service != "sftp" && options.md5sum {
+            return Err(anyhow!("--md5sum is only used with SFTP."));
+        }
+        if service != "zenodo" && service != "figshare" && options.published.is_some() {
+            return Err(anyhow!(
+                "--read-only is only used with Zenodo and FigShare."
+            ));
+        }
 
-        // (1) save the auth key to home dir
-        let mut auth_keys = AuthKeys::new();
-        auth_keys.add(service, key);
+        // (1) save the auth key to home dir (S3 credentials can instead
+        // be set in the environment)
+        match key {
+            Some(_) if service == "rclone" => {
+                return Err(anyhow!(
+                    "rclone remotes are configured with 'rclone config', so take no token."
+                ))
+            }
+            Some(_) if service == "sftp" => {
+                return Err(anyhow!(
+                    "SFTP remotes use your SSH keys (see ~/.ssh/config), so take no token."
+                ))
+            }
+            Some(_) if service == "filesystem" => {
+                return Err(anyhow!(
+                    "Filesystem remotes are written with your own permissions, so take no token."
+                ))
+            }
+            Some(_) if options.published.is_some() => {
+                return Err(anyhow!(
+                    "Published records are downloaded without a token, so --read-only takes none."
+                ))
+            }
+            Some(key) => {
+                if service == "s3" {
+                    S3Credentials::parse(key)?;
+                }
+                if service == "gdrive" && !key.contains(':') {
+                    return Err(anyhow!(
+                        "Linking to Google Drive requires the OAuth client, as \
+                         CLIENT_ID:CLIENT_SECRET."
+                    ));
+                }
+                let mut auth_keys = AuthKeys::new();
+                let key_name = match service.as_str() {
+                    "zenodo" => zenodo::token_key(options.sandbox),
+                    _ => &service,
+                };
+                auth_keys.add(key_name, key);
+            }
+            None if ["s3", "rclone", "sftp", "filesystem"].contains(&service.as_str()) => (),
+            None if options.published.is_some() => (),
+            None => return Err(anyhow!("Linking to {} requires an access token.", service)),
+        }
 
         // (2) create a new remote, with a name
         // Associate a project (either by creating it, or finding it on FigShare)
-        let name = if let Some(n) = name {
+        let deposit = self.data.metadata.deposit(&dir);
+        let name = if let Some(n) = &options.name {
             n.to_string()
+        } else if let Some(title) = &deposit.title {
+            title.to_string()
         } else {
             self.name()
         };
 
-        let service = service.to_lowercase();
+        // a published record is found by its DOI
+        let published = options.published.as_deref();
         let mut remote = match service.as_str() {
-            "figshare" => Ok(Remote::FigShareAPI(FigShareAPI::new(&name, None)?)),
-            "zenodo" => Ok(Remote::ZenodoAPI(ZenodoAPI::new(&name, None)?)),
+            "figshare" => match published {
+                Some(doi) => Ok(Remote::FigShareAPI(FigShareAPI::published(doi, None)?)),
+                None => Ok(Remote::FigShareAPI(FigShareAPI::new(&name, None)?)),
+            },
+            "zenodo" => match published {
+                Some(doi) => Ok(Remote::ZenodoAPI(ZenodoAPI::published(
+                    doi,
+                    None,
+                    options.sandbox,
+                )?)),
+                None => Ok(Remote::ZenodoAPI(ZenodoAPI::new(
+                    &name,
+                    None,
+                    options.sandbox,
+                )?)),
+            },
+            "s3" => {
+                let location = options.name.as_deref().ok_or(anyhow!(
+                    "Linking to S3 requires --name <bucket>[/<prefix>]."
+                ))?;
+                let (endpoint, region) = (options.endpoint.clone(), options.region.clone());
+                Ok(Remote::S3API(S3API::new(location, endpoint, region)?))
+            }
+            "rclone" => {
+                let location = options.name.as_deref().ok_or(anyhow!(
+                    "Linking to rclone requires --name <remote>:<path>."
+                ))?;
+                Ok(Remote::RcloneAPI(RcloneAPI::new(location)?))
+            }
+            "sftp" => {
+                let location = options.name.as_deref().ok_or(anyhow!(
+                    "Linking to SFTP requires --name [<user>@]<host>:<path>."
+                ))?;
+                Ok(Remote::SftpAPI(SftpAPI::new(location, options.md5sum)?))
+            }
+            "filesystem" => {
+                let path = options.name.as_deref().ok_or(anyhow!(
+                    "Linking to a filesystem requires --name <PATH>, the directory to store \
+                     files in."
+                ))?;
+                let fs_api = FilesystemAPI::new(path)?;
+                // the tracked directory cannot hold its own remote copies
+                let tracked = std::path::absolute(self.path_context().join(&dir))?;
+                if fs_api.path().starts_with(&tracked) {
+                    return Err(anyhow!(
+                        "'{}' is inside '{}', so it cannot be its remote.",
+                        path,
+                        dir
+                    ));
+                }
+                Ok(Remote::FilesystemAPI(fs_api))
+            }
+            "webdav" => {
+                let url = options.name.as_deref().ok_or(anyhow!(
+                    "Linking to WebDAV requires --name <URL>, the collection's URL."
+                ))?;
+                Ok(Remote::WebdavAPI(WebdavAPI::new(url)?))
+            }
+            // without a folder ID, a folder named after the project is made
+            "gdrive" => match options.name.as_deref() {
+                Some(folder) => Ok(Remote::GoogleDriveAPI(GoogleDriveAPI::new(folder, None)?)),
+                None => Ok(Remote::GoogleDriveAPI(GoogleDriveAPI::with_new_folder(
+                    &self.name(),
+                    None,
+                ))),
+            },
+            "osf" => {
+                let project = options
+                    .name
+                    .as_deref()
+                    .ok_or(anyhow!("Linking to OSF requires --name <GUID>."))?;
+                Ok(Remote::OsfAPI(OsfAPI::new(project, None)?))
+            }
+            "dataverse" => {
+                let (location, server) = options
+                    .name
+                    .as_deref()
+                    .zip(options.endpoint.as_deref())
+                    .ok_or(anyhow!(
+                    "Linking to Dataverse requires --name <collection>[/<title>] and \
+                         --endpoint <server URL>."
+                ))?;
+                Ok(Remote::DataverseAPI(DataverseAPI::new(
+                    location,
+                    &self.name(),
+                    server,
+                )?))
+            }
             _ => Err(anyhow!("Service '{}' is not supported!", service)),
         }?;
+        let path_style = options
+            .path_style
+            .unwrap_or_else(|| remote.default_path_style());
+        remote.set_path_style(path_style);
 
-        // (3) authenticate remote
+        // (3) authenticate remote (Google Drive is authorized in the
+        // browser, once, saving its refresh token)
         authenticate_remote(&mut remote)?;
+        if let Remote::GoogleDriveAPI(ref mut drive_api) = remote {
+            if !drive_api.is_authorized() {
+                let refresh_token = drive_api.authorize().await?;
+                AuthKeys::new().add(gdrive::REFRESH_TOKEN_KEY, &refresh_token);
+            }
+        }
 
         // (4) validate this a proper remote directory (this is
         // also done in register_remote() for caution,
         // but we also want do it here to prevent the situation
         // where self.data.register_remote() fails, but remote_init()
-        // is already done.
-        self.data.validate_remote_directory(&dir)?;
+        // is already done. Mirrors are of already-linked directories.
+        if options.mirror {
+            self.data.validate_mirror_directory(&dir)?;
+        } else {
+            self.data.validate_remote_directory(&dir)?;
+        }
 
         // (5) initialize the remote (e.g. for FigShare, this
         // checks that the article doesn't exist (error if it
         // does), creates it, and sets the FigShare.article_id
         // once it is assigned by the remote).
         // Note: we pass the Project to remote_init
-        let local_metadata = LocalMetadata::from_project(self);
-        remote.remote_init(local_metadata, *link_only).await?;
-
-        // (6) register the remote in the manifest
-        self.data.register_remote(&dir, remote)?;
+        let local_metadata = LocalMetadata {
+            deposit,
+            ..LocalMetadata::from_project(self)
+        };
+        remote
+            .remote_init(local_metadata, options.link_only)
+            .await?;
+
+        // (6) register the remote in the manifest; a published record's
+        // files are added to it, to be pulled
+        if options.mirror {
+            self.data.register_mirror(&dir, remote)?;
+        } else if remote.is_read_only() {
+            let web_url = remote.web_url().unwrap_or_default();
+            self.data.register_remote(&dir, remote)?;
+            let added = self.data.register_remote_files(&dir).await?;
+            print_info!(
+                "Linked '{}' to {} (read-only), adding its {} to the manifest; \
+                 use 'sdf pull' to download them.",
+                dir,
+                web_url,
+                pluralize(added as u64, "file")
+            );
+        } else {
+            self.data.register_remote(&dir, remote)?;
+        }
         self.save()
     }
 
     pub async fn ls(&mut self) -> Result<()> {
-        let all_remote_files = self.data.merge(true).await?;
+        let all_remote_files = self.data.merge(true, None).await?;
         for (directory, remote_files) in all_remote_files.iter() {
             println!("Remote: {}", directory);
             for file in remote_files.values() {
@@ -467,44 +1532,95 @@ impl Project {
     // both the source *and* destination; the latter does not exist until after the file
     // has been successfully moved. So the updating is all done on the DataFile
     // directly, since lower interfaces cannot access the relative path.
-    pub async fn mv(&mut self, source: &str, destination: &str) -> Result<()> {
+    //
+    // The entry keeps its MD5, tracked flag, and last-known remote state.
+    // An existing destination (on disk or in the manifest) is only
+    // replaced with force. The manifest is saved atomically (see save()),
+    // and if it cannot be saved, the file is moved back.
+    pub async fn mv(&mut self, source: &str, destination: &str, force: bool) -> Result<()> {
         let source_path = Path::new(source);
-        let source_path_str = self.relative_path_string(source_path)?;
-        if let Some(file) = self.data.files.remove(&source_path_str) {
-            let mut destination_path = PathBuf::from(destination);
-
-            if is_directory(&destination_path) {
-                // if destination is a directory, append the file name from
-                // the source path to mimic unix mv
-                if let Some(file_name) = source_path.file_name() {
-                    destination_path = destination_path.join(file_name);
-                }
-            }
-
-            // move the actual file
-            rename(source, &destination_path).context("Error encountered when moving file.")?;
-
-            // update the relative path
-            let relative_destination = self.relative_path_string(destination_path.as_path())?;
-
-            // modify the DataFile
-            let mut new_file = file.clone();
-            new_file.path = relative_destination;
+        let source_key = self.relative_path_string(source_path)?;
+        let Some(file) = self.data.files.get(&source_key).cloned() else {
+            return Err(anyhow!(
+                "Cannot move file '{}' with 'sdf mv' since it is not in the manifest.",
+                source
+            ));
+        };
 
-            // insert it back into the map with the new key
-            self.data.files.insert(destination.to_string(), new_file);
+        let mut destination_path = PathBuf::from(destination);
+        if is_directory(&destination_path) {
+            // if destination is a directory, append the file name from
+            // the source path to mimic unix mv
+            if let Some(file_name) = source_path.file_name() {
+                destination_path = destination_path.join(file_name);
+            }
+        } else if destination.ends_with('/') {
+            return Err(anyhow!("Directory '{}' does not exist.", destination));
+        }
 
-            self.save()
+        // the destination does not exist yet, so its manifest key is
+        // that of its directory, plus its name
+        let (parent, file_name) = match (destination_path.parent(), destination_path.file_name()) {
+            (Some(parent), Some(file_name)) => (parent, file_name),
+            _ => return Err(anyhow!("Invalid destination '{}'.", destination)),
+        };
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
         } else {
-            Err(anyhow!(
-                "Cannot move file '{}' with 'sdf mv' since it is not in the manifest.",
-                source
-            ))
+            parent
+        };
+        let destination_key = normalize_path(
+            &self
+                .relative_path(parent)?
+                .join(file_name)
+                .to_string_lossy(),
+        );
+        if destination_key == source_key {
+            return Err(anyhow!(
+                "'{}' and '{}' are the same file.",
+                source,
+                destination
+            ));
         }
+        let tracked_destination = self.data.files.contains_key(&destination_key);
+        if (tracked_destination || destination_path.exists()) && !force {
+            let place = if tracked_destination {
+                "the manifest"
+            } else {
+                "the file system"
+            };
+            return Err(anyhow!(
+                "'{}' already exists in {} (use --force to overwrite it).",
+                destination_key,
+                place
+            ));
+        }
+
+        // move the actual file
+        rename(source, &destination_path).context("Error encountered when moving file.")?;
+
+        // modify the DataFile, and insert it back into the map with the new key
+        let previous = self.data.files.remove(&destination_key);
+        self.data.files.remove(&source_key);
+        let mut new_file = file.clone();
+        new_file.path = destination_key.clone();
+        self.data.files.insert(destination_key.clone(), new_file);
+
+        if let Err(err) = self.save() {
+            // move the file back, so the (unchanged) manifest still matches
+            let _ = rename(&destination_path, source);
+            self.data.files.remove(&destination_key);
+            self.data.files.insert(source_key, file);
+            if let Some(previous) = previous {
+                self.data.files.insert(destination_key, previous);
+            }
+            return Err(err);
+        }
+        Ok(())
     }
 
     pub async fn get(&mut self, url: &str, filename: Option<&str>, overwrite: bool) -> Result<()> {
-        let mut downloads = Downloads::new();
+        let mut downloads = Downloads::new(&self.path_context());
         let download = downloads.add(url.to_string(), filename, overwrite)?;
         if let Some(dl) = download {
             let filepath = dl.filename.clone();
@@ -550,16 +1666,7 @@ impl Project {
         header: bool,
         overwrite: bool,
     ) -> Result<()> {
-        let extension = std::path::Path::new(filename)
-            .extension()
-            .and_then(std::ffi::OsStr::to_str);
-
-        let delimiter = match extension {
-            Some("csv") => b',',
-            Some("tsv") => b'\t',
-            _ => return Err(anyhow!("Unsupported file type: {:?}", extension)),
-        };
-
+        let delimiter = table_delimiter(filename)?;
         let file = File::open(filename)?;
         let mut reader = ReaderBuilder::new()
             .delimiter(delimiter)
@@ -569,7 +1676,7 @@ impl Project {
         // convert 0-indexed to 1; first column is default
         let column = column.unwrap_or(0) as usize - 1;
 
-        let mut downloads = Downloads::new();
+        let mut downloads = Downloads::new(&self.path_context());
         let mut filepaths = Vec::new();
         let mut urls = Vec::new();
         let mut skipped = Vec::new();
@@ -628,25 +1735,993 @@ impl Project {
         self.save()
     }
 
-    pub fn track(&mut self, filepath: &String) -> Result<()> {
+    pub async fn track(
+        &mut self,
+        filepath: &String,
+        algos: &[ChecksumAlgo],
+        block_size: Option<u64>,
+    ) -> Result<()> {
         let filepath = self.relative_path_string(Path::new(filepath))?;
-        self.data.track_file(&filepath, &self.path_context())?;
+        let path_context = self.path_context();
+        let attributes = AttributeFile::load(&path_context)?;
+        let (algos, block_size) = attributes.apply(&filepath, algos, block_size);
+        let path_attributes = attributes.for_path(&filepath);
+        self.data.track_file(&filepath, &path_context)?;
+        if let Some(data_file) = self.data.files.get_mut(&filepath) {
+            data_file.add_checksums(&path_context, &algos).await?;
+            if path_attributes.compression.is_some() {
+                data_file.compression = path_attributes.compression;
+            }
+            if path_attributes.encryption.is_some() {
+                data_file.encryption = path_attributes.encryption;
+            }
+            if let Some(block_size) = block_size {
+                data_file
+                    .add_block_hashes(&path_context, block_size)
+                    .await?;
+            }
+        }
+        self.save()
+    }
+
+    // Show the manifest entry of a file: its size, checksums, and block
+    // hashes (if recorded).
+    pub fn stat(&self, path: &str, format: OutputFormat) -> Result<()> {
+        let path = self.manifest_path(path)?;
+        let data_file = self
+            .data
+            .files
+            .get(&path)
+            .ok_or(anyhow!("File '{}' is not in the manifest.", path))?;
+        if format == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(data_file)?);
+            return Ok(());
+        }
+        let mut rows = vec![
+            vec!["path".to_string(), data_file.path.clone()],
+            vec!["size".to_string(), format!("{} bytes", data_file.size)],
+            vec!["tracked".to_string(), data_file.tracked.to_string()],
+            vec!["md5".to_string(), data_file.md5.clone()],
+        ];
+        if let Some(modified) = data_file.modified {
+            rows.push(vec!["modified".to_string(), format_mod_time(modified)]);
+        }
+        for (algo, checksum) in &data_file.checksums {
+            rows.push(vec![format!("{:?}", algo).to_lowercase(), checksum.clone()]);
+        }
+        if let Some(compression) = data_file.compression {
+            rows.push(vec![
+                "compression".to_string(),
+                format!("{:?}", compression).to_lowercase(),
+            ]);
+        }
+        if let Some(encryption) = data_file.encryption {
+            rows.push(vec!["encryption".to_string(), encryption.to_string()]);
+        }
+        if let Some(copy) = &data_file.stored {
+            rows.push(vec![
+                "stored size".to_string(),
+                format!("{} bytes", copy.size),
+            ]);
+            rows.push(vec!["stored md5".to_string(), copy.md5.clone()]);
+            if let Some(nonce) = &copy.nonce {
+                rows.push(vec!["nonce".to_string(), nonce.clone()]);
+            }
+        }
+        if let Some(block_hashes) = &data_file.block_hashes {
+            rows.push(vec![
+                "block size".to_string(),
+                format!("{} bytes", block_hashes.block_size),
+            ]);
+            for (i, md5) in block_hashes.md5s.iter().enumerate() {
+                rows.push(vec![format!("block {}", i), md5.clone()]);
+            }
+        }
+        if format == OutputFormat::Tsv {
+            print_tsv(&["field", "value"], &rows);
+        } else {
+            print_fixed_width(&rows, None, None);
+        }
+        Ok(())
+    }
+
+    // Show everything known about one file: its manifest entry, current
+    // MD5 and status, and remotes (whose status is only fetched with
+    // include_remotes). Files not in the manifest are not an error.
+    pub async fn info(&mut self, path: &str, include_remotes: bool) -> Result<()> {
+        let path = self.manifest_path(path)?;
+        let path_context = canonicalize(self.path_context())?;
+        if !self.data.files.contains_key(&path) {
+            if path_context.join(&path).exists() {
+                println!(
+                    "File '{}' is not in the manifest, but exists on disk. Add it with:\n  \
+                     $ sdf add {}",
+                    path, path
+                );
+            } else {
+                println!(
+                    "File '{}' is not in the manifest, and does not exist on disk.",
+                    path
+                );
+            }
+            return Ok(());
+        }
+        let info = self
+            .data
+            .file_info(&path_context, &path, include_remotes)
+            .await?;
+        print_fixed_width(&info.rows(), None, None);
+        Ok(())
+    }
+
+    // Write the manifest as a table, to output or stdout.
+    pub async fn export(&self, format: ExportFormat, output: Option<&str>) -> Result<()> {
+        let rows = export_rows(&self.data, &self.path_context()).await?;
+        match output {
+            Some(output) => {
+                let file = File::create(output)
+                    .map_err(|err| anyhow!("Could not create '{}': {}", output, err))?;
+                write_export(&rows, format, file)
+            }
+            None => write_export(&rows, format, std::io::stdout().lock()),
+        }
+    }
+
+    // Report the sets of manifest files with the same content, and with
+    // link, replace the duplicates with hard links to one copy.
+    pub async fn dedup(&self, link: bool) -> Result<()> {
+        let path_context = self.path_context();
+        let sets = find_duplicates(&self.data, &path_context);
+        if sets.is_empty() {
+            println!("No duplicate files.");
+            return Ok(());
+        }
+        let reclaimable: u64 = sets.iter().map(|set| set.reclaimable()).sum();
+        let noun = if sets.len() == 1 { "set" } else { "sets" };
+        println!(
+            "{} {} of duplicate files ({} reclaimable):",
+            sets.len(),
+            noun,
+            format_bytes(reclaimable)
+        );
+        for set in &sets {
+            let copies = set.duplicates.len() + 1;
+            println!(
+                "  {} ({} × {})",
+                shorten(&set.md5, Some(8)),
+                copies,
+                format_bytes(set.size)
+            );
+            println!("    {}", set.keep);
+            for dup in &set.duplicates {
+                let linked = if dup.linked { " (already linked)" } else { "" };
+                println!("    {}{}", dup.path, linked);
+            }
+        }
+        if !link {
+            return Ok(());
+        }
+
+        let mut linked: u64 = 0;
+        let mut reclaimed = 0;
+        let mut failures = Vec::new();
+        for set in &sets {
+            for dup in set.duplicates.iter().filter(|dup| !dup.linked) {
+                match link_duplicate(set, &dup.path, &path_context).await {
+                    Ok(()) => {
+                        linked += 1;
+                        reclaimed += set.size;
+                    }
+                    Err(err) => failures.push(format!("  {}: {}", dup.path, err)),
+                }
+            }
+        }
+        println!(
+            "Linked {} ({} reclaimed).",
+            pluralize(linked, "duplicate"),
+            format_bytes(reclaimed)
+        );
+        if !failures.is_empty() {
+            return Err(anyhow!(
+                "Could not link {}:\n{}",
+                pluralize(failures.len() as u64, "duplicate"),
+                failures.join("\n")
+            ));
+        }
+        Ok(())
+    }
+
+    // Show the changes recorded in the project's history, oldest first.
+    pub fn log(&self, format: OutputFormat) -> Result<()> {
+        let path = history_path(&self.path_context());
+        let events = if path.exists() {
+            read_history(&path)?
+        } else {
+            Vec::new()
+        };
+        match format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&events)?),
+            OutputFormat::Tsv => print_tsv(&["time", "target", "change"], &log_rows(&events)),
+            OutputFormat::Table if events.is_empty() => println!("No changes recorded."),
+            OutputFormat::Table => print_fixed_width(&log_rows(&events), None, None),
+        }
+        Ok(())
+    }
+
+    // Rebuild the manifest of the current directory, which must not have
+    // one yet, from a history (by default, the directory's own), applying
+    // the changes saved up to until. Returns the number of files.
+    pub fn replay(history: Option<&Path>, until: Option<DateTime<Utc>>) -> Result<usize> {
+        let manifest = PathBuf::from(MANIFEST);
+        if manifest.exists() {
+            return Err(anyhow!(
+                "A manifest already exists here; replay into a directory without one."
+            ));
+        }
+        let path = history
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| history_path(Path::new("")));
+        if !path.exists() {
+            return Err(anyhow!("No history found at {:?}.", path));
+        }
+        let data = history::replay(read_history(&path)?, until);
+        // the replayed changes are already in the history, so are not
+        // recorded again
+        Project::write_manifest(&manifest, &data)?;
+        Ok(data.files.len())
+    }
+
+    pub async fn pull(&mut self, options: &PullOptions, format: OutputFormat) -> Result<()> {
+        if options.checksum_from_remote {
+            let report = self.data.backfill_checksums().await?;
+            self.save()?;
+            return self.finish_transfer(report, format, "Recorded checksums of");
+        }
+        let path_context = self.path_context();
+        let (overwrite, url, all) = (options.overwrite, options.urls, options.all);
+        let remote = options.remote.as_deref();
+        if url && remote.is_some() {
+            return Err(anyhow!("--remote cannot be used with --urls."));
+        }
+        let filter = FileFilter {
+            paths: self.filter_paths(&options.paths)?,
+            size: options.size,
+            ..Default::default()
+        };
+        // files pulled from URLs need not be tracked
+        let pullable = self.data.files.values().filter(|data_file| {
+            (data_file.tracked && (!url || all)) || (data_file.url.is_some() && (url || all))
+        });
+        let what = match (url, all) {
+            (_, true) => "tracked files or files with URLs",
+            (true, false) => "files with URLs",
+            (false, false) => "tracked files",
+        };
+        filter.check_paths(pullable, what)?;
+        if options.dry_run {
+            let mut plan = TransferPlan::default();
+            if url || all {
+                plan.extend(
+                    self.data
+                        .pull_urls_plan(&path_context, overwrite, &filter)?,
+                );
+            }
+            if !url || all {
+                let remotes_plan = self
+                    .data
+                    .pull_plan(&path_context, overwrite, &filter, remote)
+                    .await?;
+                plan.extend(remotes_plan);
+            }
+            return plan.print(format);
+        }
+        let jobs = options.max_concurrent;
+        let report = if all {
+            let mut report = self
+                .data
+                .pull_urls(&path_context, overwrite, &filter, jobs)
+                .await?;
+            report.extend(
+                self.data
+                    .pull(&path_context, overwrite, &filter, jobs, remote)
+                    .await?,
+            );
+            report
+        } else if url {
+            self.data
+                .pull_urls(&path_context, overwrite, &filter, jobs)
+                .await?
+        } else {
+            self.data
+                .pull(&path_context, overwrite, &filter, jobs, remote)
+                .await?
+        };
+        self.save()?;
+        let pulled: Vec<&str> = report
+            .files
+            .iter()
+            .filter(|file| file.status == Outcome::Succeeded)
+            .map(|file| file.path.as_str())
+            .collect();
+        if !pulled.is_empty() {
+            if let Some(hook) = find_hook(&path_context, POST_PULL) {
+                if let Err(err) = run_hook(&hook, &path_context, pulled) {
+                    print_warn!("{}; the pulled files are kept.", err);
+                }
+            }
+        }
+        self.finish_transfer(report, format, "Downloaded")
+    }
+
+    // Remove temporary files left over by interrupted or crashed runs.
+    pub fn clean(&self) -> Result<()> {
+        let removed = staging::clean(&self.path_context())?;
+        println!(
+            "Removed {}.",
+            pluralize(removed.len() as u64, "temporary file")
+        );
+        Ok(())
+    }
+
+    // Check every tracked file against its manifest MD5, optionally
+    // replacing corrupted files with their remote versions. With quiet,
+    // only failures are printed. Failures (that are not repaired) end the
+    // run with verify's exit codes (see VerifyReport::exit_code()).
+    pub async fn verify(
+        &mut self,
+        repair_from_remote: bool,
+        remotes: bool,
+        format: OutputFormat,
+        quiet: bool,
+    ) -> Result<()> {
+        let path_context = self.path_context();
+        let mut verified = self.data.verify(&path_context).await?;
+        if remotes {
+            self.data
+                .verify_remotes(&path_context, &mut verified)
+                .await?;
+        }
+        let corrupted = &verified.corrupted;
+        if !repair_from_remote || corrupted.is_empty() {
+            if quiet {
+                verified.report.print_failures(format)?;
+            } else {
+                verified.report.print(format, "Verified")?;
+            }
+            return verify_result(&verified, !repair_from_remote);
+        }
+        if format == OutputFormat::Table && !quiet {
+            println!(
+                "{} did not match the manifest.",
+                pluralize(corrupted.len() as u64, "file")
+            );
+        }
+        let repaired = self
+            .data
+            .repair_from_remote(&path_context, corrupted)
+            .await?;
+        self.save()?;
+        self.finish_transfer(repaired, format, "Repaired")?;
+        // corrupted files were repaired, but missing or unreadable ones were not
+        let unrepaired = VerifyReport {
+            missing: verified.missing,
+            unreadable: verified.unreadable,
+            remote_missing: verified.remote_missing,
+            remote_mismatched: verified.remote_mismatched,
+            ..Default::default()
+        };
+        verify_result(&unrepaired, false)
+    }
+
+    // Clear the last-known remote state of entries whose remote is no
+    // longer in the manifest.
+    pub fn remote_prune(&mut self) -> Result<()> {
+        let num_pruned = self.data.prune_dangling_remotes()?;
+        println!(
+            "Cleared unknown remote references from {}.",
+            pluralize(num_pruned as u64, "file")
+        );
         self.save()
     }
 
-    pub async fn pull(&mut self, overwrite: bool, url: bool, all: bool) -> Result<()> {
+    pub async fn cat(&mut self, path: &str) -> Result<()> {
         let path_context = self.path_context();
-        if all {
-            self.data.pull_urls(&path_context, overwrite).await?;
-            return self.data.pull(&path_context, overwrite).await;
+        let path = self.manifest_path(path)?;
+        let mut stdout = std::io::stdout().lock();
+        // stdout carries the file, so messages go to stderr
+        if let CatSource::Remote(remote) = self.data.cat(&path_context, &path, &mut stdout).await? {
+            eprintln!("Streamed '{}' from {}; MD5 verified.", path, remote);
+        }
+        Ok(())
+    }
+
+    // Compare a local file with its remote copy: a unified diff for text
+    // files, or the first differing offset for binary files.
+    pub async fn diff_remote(
+        &mut self,
+        path: &str,
+        max_size: u64,
+        remote: Option<&str>,
+    ) -> Result<()> {
+        let path_context = self.path_context();
+        let path = self.manifest_path(path)?;
+        match self
+            .data
+            .diff_remote(&path_context, &path, max_size, remote)
+            .await?
+        {
+            ContentDiff::Identical => println!("'{}' is identical to its remote copy.", path),
+            ContentDiff::Text(diff) => print!("{}", diff),
+            ContentDiff::Binary {
+                offset,
+                local_size,
+                other_size,
+            } => {
+                println!(
+                    "Binary files differ (local: {} bytes, remote: {} bytes).",
+                    local_size, other_size
+                );
+                match offset {
+                    Some(offset) => println!("First difference at byte offset {}.", offset),
+                    None => println!("The shorter file is a prefix of the longer one."),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Push the files matching filter, whose paths (and resume point) are
+    // as given on the command line, i.e. relative to the working directory.
+    pub async fn push(
+        &mut self,
+        options: &PushOptions,
+        format: OutputFormat,
+        mut filter: FileFilter,
+    ) -> Result<()> {
+        let (overwrite, remote) = (options.overwrite, options.remote.as_deref());
+        if options.delta {
+            let mirrors = self
+                .data
+                .mirrors
+                .iter_mut()
+                .flat_map(|(dir, mirrors)| mirrors.iter_mut().map(move |mirror| (dir, mirror)));
+            let remotes = self
+                .data
+                .remotes
+                .iter_mut()
+                .chain(mirrors)
+                .filter(|(_, r)| remote.is_none_or(|name| r.matches(name)));
+            for (dir, remote) in remotes {
+                if remote.supports_ranged_writes() {
+                    remote.set_delta(true);
+                } else {
+                    print_warn!(
+                        "{} (for '{}') does not support ranged writes; files will be fully uploaded.",
+                        remote.name(),
+                        dir
+                    );
+                }
+            }
+        }
+        filter.paths = self.filter_paths(&filter.paths)?;
+        filter.resume_from = filter
+            .resume_from
+            .map(|path| self.manifest_path(&path))
+            .transpose()?;
+        let tracked = self
+            .data
+            .files
+            .values()
+            .filter(|data_file| data_file.tracked);
+        filter.check_paths(tracked, "tracked files")?;
+        if options.dry_run {
+            let plan = self
+                .data
+                .push_plan(&self.path_context(), overwrite, &filter, remote)
+                .await?;
+            return plan.print(format);
+        }
+        if let Some(hook) = find_hook(&self.path_context(), PRE_PUSH) {
+            let plan = self
+                .data
+                .push_plan(&self.path_context(), overwrite, &filter, remote)
+                .await?;
+            if !plan.transfers.is_empty() {
+                let paths = plan.transfers.iter().map(|transfer| transfer.path.as_str());
+                run_hook(&hook, &self.path_context(), paths)
+                    .map_err(|err| anyhow!("{}; nothing was pushed.", err))?;
+            }
+        }
+        let report = self
+            .data
+            .push(
+                &self.path_context(),
+                overwrite,
+                &filter,
+                options.max_concurrent,
+                remote,
+            )
+            .await?;
+        self.save()?;
+        self.finish_transfer(report, format, "Uploaded")
+    }
+
+    // Publish the Zenodo deposition or FigShare article of a directory (the
+    // only one linked to either, by default), keeping the DOI it gets in the
+    // manifest. It can't be undone, so without confirm, nothing is published.
+    pub async fn publish(&mut self, dir: Option<&str>, confirm: bool) -> Result<()> {
+        let dir = match dir {
+            Some(dir) => self.relative_path_string(Path::new(dir))?,
+            None => {
+                let mut dirs: Vec<&String> = self
+                    .data
+                    .remotes
+                    .iter()
+                    .filter(|(_, remote)| remote.check_publishable().is_ok())
+                    .map(|(dir, _)| dir)
+                    .collect();
+                dirs.sort();
+                match dirs.as_slice() {
+                    [dir] => dir.to_string(),
+                    [] => {
+                        return Err(anyhow!(
+                            "No directory is linked to a Zenodo deposition or FigShare article."
+                        ))
+                    }
+                    _ => {
+                        let dirs: Vec<&str> = dirs.iter().map(|dir| dir.as_str()).collect();
+                        return Err(anyhow!(
+                            "Several directories are linked to deposits ({}); give the one to publish.",
+                            dirs.join(", ")
+                        ));
+                    }
+                }
+            }
+        };
+        let remote = self
+            .data
+            .remotes
+            .get_mut(&dir)
+            .ok_or(anyhow!("Directory '{}' is not linked to a remote.", dir))?;
+        let service = remote.name().to_string();
+        remote.check_publishable()?;
+        if !confirm {
+            let location = remote
+                .web_url()
+                .map_or(String::new(), |url| format!(" ({})", url));
+            print_info!(
+                "Publishing '{}' on {}{} can't be undone: its files are then \
+                 fixed, and it gets a DOI. Use --confirm to publish it.",
+                dir,
+                service,
+                location
+            );
+            return Ok(());
+        }
+        authenticate_remote(remote)?;
+        let doi = remote.publish().await?;
+        self.save()?;
+        println!(
+            "Published '{}' on {}: https://doi.org/{}",
+            dir, service, doi
+        );
+        Ok(())
+    }
+
+    // Print the end-of-run report; any failed file makes the run an error.
+    fn finish_transfer(
+        &self,
+        mut report: TransferReport,
+        format: OutputFormat,
+        verb: &str,
+    ) -> Result<()> {
+        report.usage = self.data.usage();
+        report.print(format, verb)?;
+        if report.has_failures() {
+            return Err(anyhow!(
+                "{} could not be transferred.",
+                pluralize(report.count(Outcome::Failed) as u64, "file")
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The error ending a failed 'sdf verify', carrying its exit code.
+fn verify_result(verified: &VerifyReport, suggest_repair: bool) -> Result<()> {
+    let code = verified.exit_code();
+    if code == 0 {
+        return Ok(());
+    }
+    let mut problems = Vec::new();
+    if !verified.corrupted.is_empty() {
+        problems.push(format!(
+            "{} did not match the manifest",
+            pluralize(verified.corrupted.len() as u64, "file")
+        ));
+    }
+    if verified.missing > 0 {
+        problems.push(format!(
+            "{} missing",
+            pluralize(verified.missing as u64, "file")
+        ));
+    }
+    if verified.unreadable > 0 {
+        problems.push(format!(
+            "{} could not be read",
+            pluralize(verified.unreadable as u64, "file")
+        ));
+    }
+    if verified.remote_missing > 0 {
+        problems.push(format!(
+            "{} missing on remotes",
+            pluralize(verified.remote_missing as u64, "file")
+        ));
+    }
+    if verified.remote_mismatched > 0 {
+        problems.push(format!(
+            "{} did not match the manifest",
+            pluralize(verified.remote_mismatched as u64, "remote file")
+        ));
+    }
+    let hint = if suggest_repair && !verified.corrupted.is_empty() {
+        " (use --repair-from-remote to re-download)"
+    } else {
+        ""
+    };
+    Err(anyhow!(ExitError {
+        code,
+        message: format!("{}{}.", problems.join(", "), hint),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_config() -> Config {
+        Config {
+            user: User {
+                name: "Joan B. Scientist".to_string(),
+                email: None,
+                affiliation: None,
+            },
+            rclone: None,
+        }
+    }
+
+    // An unlocked project with an empty manifest in dir (not yet saved).
+    fn test_project(dir: &Path) -> Project {
+        Project {
+            manifest: dir.join(MANIFEST),
+            data: DataCollection::new(),
+            config: test_config(),
+            from_stdin: false,
+            lock: None,
+        }
+    }
+
+    #[test]
+    fn test_env_manifest_override() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join("other_manifest.yml");
+        let data = DataCollection::new();
+        std::fs::write(&manifest, serde_yaml::to_string(&data).unwrap()).unwrap();
+
+        let entries = Project::env(Some(&manifest), &EnvOptions::default()).unwrap();
+        let (_, manifest_entry) = entries.iter().find(|(key, _)| key == "manifest").unwrap();
+        let expected = canonicalize(&manifest).unwrap();
+        assert_eq!(
+            manifest_entry,
+            &format!("{} (set by --manifest)", expected.to_string_lossy())
+        );
+    }
+
+    #[test]
+    fn test_env_reports_resolved_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join(MANIFEST);
+        let data = DataCollection::new();
+        std::fs::write(&manifest, serde_yaml::to_string(&data).unwrap()).unwrap();
+
+        let entries: std::collections::HashMap<String, String> =
+            Project::env(Some(&manifest), &EnvOptions::default())
+                .unwrap()
+                .into_iter()
+                .collect();
+        assert_eq!(entries["checksum algo"], "md5 (default)");
+        assert_eq!(
+            entries["default remote"],
+            "none (each directory's remote, then its mirrors)"
+        );
+        assert_eq!(entries["byte units"], "binary (1 KiB = 1024 bytes)");
+        assert_eq!(
+            entries["max concurrent"],
+            "4 (default), at most 2 for Zenodo and FigShare"
+        );
+        assert_eq!(entries["limit rate"], "none");
+        assert!(entries["jobs"].contains("tuned to"), "{}", entries["jobs"]);
+        assert!(entries["md5 cache"].ends_with("md5_cache.json (not found)"));
+        let history = canonicalize(temp_dir.path()).unwrap().join(HISTORY_FILE);
+        assert_eq!(
+            entries["history"],
+            format!("{} (not found)", history.to_string_lossy())
+        );
+
+        std::fs::create_dir(temp_dir.path().join(".sdf")).unwrap();
+        std::fs::write(
+            temp_dir.path().join(PROJECT_CONFIG),
+            "default_remote: zenodo\nlimit_rate: 1M\nchecksum_algo: sha256\nmax_concurrent: 6\n",
+        )
+        .unwrap();
+        let entries: std::collections::HashMap<String, String> =
+            Project::env(Some(&manifest), &EnvOptions::default())
+                .unwrap()
+                .into_iter()
+                .collect();
+        let dir = canonicalize(temp_dir.path()).unwrap();
+        assert_eq!(
+            entries["project config"],
+            dir.join(PROJECT_CONFIG).to_string_lossy()
+        );
+        assert_eq!(entries["limit rate"], "1.00 MiB/s (from .sdf/config.yml)");
+        assert_eq!(entries["default remote"], "zenodo (from .sdf/config.yml)");
+        assert_eq!(entries["checksum algo"], "sha256 (from .sdf/config.yml)");
+        assert_eq!(
+            entries["max concurrent"],
+            "6 (from .sdf/config.yml), at most 2 for Zenodo and FigShare"
+        );
+
+        let options = EnvOptions {
+            jobs: Some(3),
+            limit_rate: Some(2048),
+        };
+        let entries: std::collections::HashMap<String, String> =
+            Project::env(Some(&manifest), &options)
+                .unwrap()
+                .into_iter()
+                .collect();
+        assert_eq!(entries["limit rate"], "2.00 KiB/s (set by --limit-rate)");
+        assert_eq!(
+            entries["max concurrent"],
+            "3 (set by --jobs), at most 2 for Zenodo and FigShare"
+        );
+    }
+
+    #[test]
+    fn test_env_reports_tokenless_credentials() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = temp_dir.path().join(MANIFEST);
+        let mut data = DataCollection::new();
+        let shared = temp_dir.path().join("shared");
+        let remotes = [
+            (
+                "data",
+                Remote::FilesystemAPI(FilesystemAPI::new(&shared.to_string_lossy()).unwrap()),
+            ),
+            (
+                "raw",
+                Remote::SftpAPI(SftpAPI::new("me@lab:/srv/atlas", false).unwrap()),
+            ),
+            (
+                "results",
+                Remote::RcloneAPI(RcloneAPI::new("fake:atlas").unwrap()),
+            ),
+            (
+                "published",
+                Remote::ZenodoAPI(
+                    ZenodoAPI::published("10.5281/zenodo.1234", None, false).unwrap(),
+                ),
+            ),
+        ];
+        for (dir, remote) in remotes {
+            data.register_remote(&dir.to_string(), remote).unwrap();
         }
-        if url {
-            return self.data.pull_urls(&path_context, overwrite).await;
+        std::fs::write(&manifest, serde_yaml::to_string(&data).unwrap()).unwrap();
+
+        let entries: std::collections::HashMap<String, String> =
+            Project::env(Some(&manifest), &EnvOptions::default())
+                .unwrap()
+                .into_iter()
+                .collect();
+        assert_eq!(
+            entries["remote 'data'"],
+            "Filesystem (credential: none needed (file permissions))"
+        );
+        assert_eq!(
+            entries["remote 'raw'"],
+            "SFTP (credential: none needed (SSH keys))"
+        );
+        assert_eq!(
+            entries["remote 'results'"],
+            "Rclone (credential: none needed (rclone config))"
+        );
+        assert_eq!(
+            entries["remote 'published'"],
+            "Zenodo (credential: none needed (public record))"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stdin_manifest_is_read_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        std::fs::write(path_context.join("data/a.tsv"), "Mock data.").unwrap();
+        let mut data = DataCollection::new();
+        let data_file = DataFile::new("data/a.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        data.register(data_file).unwrap();
+        let manifest = serde_yaml::to_string(&data).unwrap();
+
+        let mut proj =
+            Project::from_reader(manifest.as_bytes(), path_context, test_config()).unwrap();
+        assert_eq!(proj.path_context(), path_context);
+        let statuses = proj
+            .data
+            .status(path_context, &StatusOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            statuses["data"][0].local_status,
+            Some(LocalStatusCode::Current)
+        );
+
+        assert!(proj.save().is_err());
+        assert!(!path_context.join(MANIFEST).exists());
+        assert!(Project::with_manifest(Some(Path::new(STDIN_MANIFEST))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transfer_hooks() {
+        use crate::lib::hooks::HOOKS_DIR;
+        use httpmock::prelude::*;
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = MockServer::start();
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        let contents = "Mock data.";
+        let md5 = format!("{:x}", md5::compute(contents));
+        std::fs::create_dir(path_context.join("data")).unwrap();
+        let mut data = DataCollection::new();
+        for path in ["data/b.tsv", "data/a.tsv"] {
+            std::fs::write(path_context.join(path), contents).unwrap();
+            let mut data_file = DataFile::new(path.to_string(), None, path_context)
+                .await
+                .unwrap();
+            data_file.tracked = true;
+            data.register(data_file).unwrap();
         }
-        self.data.pull(&path_context, overwrite).await
+        let mut api = S3API::new("bucket/project", Some(server.base_url()), None).unwrap();
+        api.set_credentials(S3Credentials::new("test-id", "test-secret"));
+        data.register_remote(&"data".to_string(), Remote::S3API(api))
+            .unwrap();
+        let listing = |objects: &str| {
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult>\
+                 <Name>bucket</Name><Prefix>project/</Prefix>\
+                 <IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                objects
+            )
+        };
+        let mut empty_listing = server.mock(|when, then| {
+            when.method(GET)
+                .path("/bucket")
+                .query_param("list-type", "2");
+            then.status(200).body(listing(""));
+        });
+        let upload_mock = server.mock(|when, then| {
+            when.method(PUT).path_contains("/bucket/project/");
+            then.status(200).header("etag", format!("\"{}\"", md5));
+        });
+        let mut proj = test_project(path_context);
+        proj.data = data;
+
+        // each hook records the files it was given, then fails
+        std::fs::create_dir_all(path_context.join(HOOKS_DIR)).unwrap();
+        for name in [PRE_PUSH, POST_PULL] {
+            let hook = path_context.join(HOOKS_DIR).join(name);
+            let script = format!("#!/bin/sh\ncat > {}.txt\nexit 1\n", name);
+            std::fs::write(&hook, script).unwrap();
+            std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let options = PushOptions {
+            max_concurrent: 2,
+            ..Default::default()
+        };
+        let err = proj
+            .push(&options, OutputFormat::Table, FileFilter::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("nothing was pushed"), "{}", err);
+        upload_mock.assert_hits(0);
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("pre-push.txt")).unwrap(),
+            "data/a.tsv\ndata/b.tsv\n"
+        );
+
+        // a failing post-pull hook does not undo the pull
+        empty_listing.delete();
+        let object = |name: &str| {
+            format!(
+                "<Contents><Key>project/{}</Key><ETag>&quot;{}&quot;</ETag>\
+                 <Size>{}</Size></Contents>",
+                name,
+                md5,
+                contents.len()
+            )
+        };
+        let objects = object("a.tsv") + &object("b.tsv");
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/bucket")
+                .query_param("list-type", "2");
+            then.status(200).body(listing(&objects));
+        });
+        let download_mock = server.mock(|when, then| {
+            when.method(GET).path("/bucket/project/a.tsv");
+            then.status(200).body(contents);
+        });
+        std::fs::remove_file(path_context.join("data/a.tsv")).unwrap();
+        let options = PullOptions {
+            max_concurrent: 2,
+            ..Default::default()
+        };
+        proj.pull(&options, OutputFormat::Table).await.unwrap();
+        download_mock.assert();
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("data/a.tsv")).unwrap(),
+            contents
+        );
+        assert_eq!(
+            std::fs::read_to_string(path_context.join("post-pull.txt")).unwrap(),
+            "data/a.tsv\n"
+        );
     }
 
-    pub async fn push(&mut self, overwrite: bool) -> Result<()> {
-        self.data.push(&self.path_context(), overwrite).await
+    #[tokio::test]
+    async fn test_publish_guards_and_finds_the_deposit() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start();
+        let any_request = server.mock(|when, then| {
+            when.any_request();
+            then.status(500);
+        });
+        let temp_dir = TempDir::new().unwrap();
+        let mut data = DataCollection::new();
+        let mut api = S3API::new("bucket/project", Some(server.base_url()), None).unwrap();
+        api.set_credentials(S3Credentials::new("test-id", "test-secret"));
+        data.register_remote(&"raw".to_string(), Remote::S3API(api))
+            .unwrap();
+        let mut proj = test_project(temp_dir.path());
+        proj.data = data;
+        let err = proj.publish(None, true).await.unwrap_err();
+        assert!(
+            err.to_string().contains("No directory is linked"),
+            "{}",
+            err
+        );
+
+        // the only deposit is the default, but is not published without
+        // confirmation
+        let zenodo = ZenodoAPI::new("test", Some(server.url("/")), false).unwrap();
+        proj.data
+            .register_remote(&"results".to_string(), Remote::ZenodoAPI(zenodo))
+            .unwrap();
+        proj.publish(None, false).await.unwrap();
+        any_request.assert_hits(0);
+        assert!(!proj.manifest.exists());
+
+        let figshare = FigShareAPI::new("test", Some(server.url(""))).unwrap();
+        proj.data
+            .register_remote(&"tables".to_string(), Remote::FigShareAPI(figshare))
+            .unwrap();
+        let err = proj.publish(None, false).await.unwrap_err();
+        assert!(err.to_string().contains("(results, tables)"), "{}", err);
     }
 }
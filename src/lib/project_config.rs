@@ -0,0 +1,214 @@
+// Project-wide defaults for command-line options, in <project>/.sdf/config.yml,
+// e.g.
+//
+//   default_remote: zenodo
+//   jobs: 8
+//   limit_rate: 20M
+//   checksum_algo: sha256
+//   max_concurrent: 8
+//   max_attempts: 10
+//   retry_delay: 2
+//
+// default_remote is the default of 'sdf push/pull --remote', jobs of
+// --jobs, limit_rate of --limit-rate, checksum_algo of 'sdf add
+// --checksum', max_concurrent of 'sdf push/pull --max-concurrent'
+// (which also defaults to jobs), and max_attempts and retry_delay (in
+// seconds) of --max-attempts and --retry-delay. Options given on the command line take
+// precedence, and options in neither have their usual defaults. A missing
+// file sets no defaults, while an unknown key, or a value that is not
+// valid for its key, is an error.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde_yaml::Value;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::lib::checksum::ChecksumAlgo;
+use crate::lib::remote::parse_delay;
+use crate::lib::throttle::parse_rate;
+
+pub const PROJECT_CONFIG: &str = ".sdf/config.yml";
+
+const KEYS: &str =
+    "default_remote, jobs, limit_rate, checksum_algo, max_concurrent, max_attempts, retry_delay";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectConfig {
+    pub default_remote: Option<String>,
+    pub jobs: Option<u64>,
+    pub limit_rate: Option<u64>,
+    pub checksum_algo: Option<ChecksumAlgo>,
+    pub max_concurrent: Option<usize>,
+    pub max_attempts: Option<u32>,
+    pub retry_delay: Option<Duration>,
+}
+
+// A YAML scalar as text (e.g. a rate may be given as 2M or 2000000).
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+impl ProjectConfig {
+    // The config of the project in path_context (empty if it has none).
+    pub fn load(path_context: &Path) -> Result<Self> {
+        let path = path_context.join(PROJECT_CONFIG);
+        match fs::read_to_string(&path) {
+            Ok(contents) => ProjectConfig::parse(&contents)
+                .map_err(|err| anyhow!("Invalid {}: {}", PROJECT_CONFIG, err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(ProjectConfig::default()),
+            Err(err) => Err(anyhow!("Could not read {}: {}", PROJECT_CONFIG, err)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut config = ProjectConfig::default();
+        let mapping = match serde_yaml::from_str(contents)? {
+            Value::Null => return Ok(config),
+            Value::Mapping(mapping) => mapping,
+            _ => return Err(anyhow!("expected 'key: value' lines ({})", KEYS)),
+        };
+        for (key, value) in mapping {
+            let key = scalar(&key).unwrap_or_default();
+            // an empty value sets no default
+            if value.is_null() {
+                continue;
+            }
+            let invalid = |expected: &str| {
+                let found = scalar(&value).unwrap_or(format!("{:?}", value));
+                anyhow!("'{}' must be {}, not '{}'", key, expected, found)
+            };
+            match key.as_str() {
+                "default_remote" => {
+                    let remote = scalar(&value).filter(|remote| !remote.is_empty());
+                    config.default_remote = Some(remote.ok_or_else(|| invalid("a remote name"))?);
+                }
+                "jobs" => {
+                    let jobs = value.as_u64().filter(|jobs| *jobs > 0);
+                    config.jobs = Some(jobs.ok_or_else(|| invalid("a number above zero"))?);
+                }
+                "limit_rate" => {
+                    let rate = scalar(&value).and_then(|rate| parse_rate(&rate).ok());
+                    config.limit_rate =
+                        Some(rate.ok_or_else(|| invalid("a rate in bytes per second, e.g. 2M"))?);
+                }
+                "checksum_algo" => {
+                    let algo =
+                        scalar(&value).and_then(|algo| ChecksumAlgo::from_str(&algo, true).ok());
+                    config.checksum_algo =
+                        Some(algo.ok_or_else(|| invalid("md5, sha256 or blake3"))?);
+                }
+                "max_concurrent" => {
+                    let max = value.as_u64().filter(|max| *max > 0);
+                    let max = max.ok_or_else(|| invalid("a number above zero"))?;
+                    config.max_concurrent = Some(max as usize);
+                }
+                "max_attempts" => {
+                    let max = value.as_u64().and_then(|max| u32::try_from(max).ok());
+                    let max = max.filter(|max| *max > 0);
+                    config.max_attempts = Some(max.ok_or_else(|| invalid("a number above zero"))?);
+                }
+                "retry_delay" => {
+                    let delay = scalar(&value).and_then(|delay| parse_delay(&delay).ok());
+                    config.retry_delay =
+                        Some(delay.ok_or_else(|| invalid("a delay in seconds, e.g. 0.5"))?);
+                }
+                _ => return Err(anyhow!("unknown key '{}' (supported: {})", key, KEYS)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_and_errors() {
+        let config = ProjectConfig::parse(
+            "default_remote: zenodo\njobs: 8\nlimit_rate: 2M\nchecksum_algo: SHA256\n\
+             max_concurrent: 6\nmax_attempts: 10\nretry_delay: 1.5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ProjectConfig {
+                default_remote: Some("zenodo".to_string()),
+                jobs: Some(8),
+                limit_rate: Some(2 * 1024 * 1024),
+                checksum_algo: Some(ChecksumAlgo::Sha256),
+                max_concurrent: Some(6),
+                max_attempts: Some(10),
+                retry_delay: Some(Duration::from_millis(1500)),
+            }
+        );
+        assert_eq!(
+            ProjectConfig::parse("limit_rate: 5000\njobs:\n").unwrap(),
+            ProjectConfig {
+                limit_rate: Some(5000),
+                ..Default::default()
+            }
+        );
+        assert_eq!(ProjectConfig::parse("").unwrap(), ProjectConfig::default());
+
+        for (contents, message) in [
+            (
+                "jobs: four",
+                "'jobs' must be a number above zero, not 'four'",
+            ),
+            ("jobs: 0", "'jobs' must be a number above zero, not '0'"),
+            (
+                "max_concurrent: 0",
+                "'max_concurrent' must be a number above zero",
+            ),
+            ("limit_rate: fast", "'limit_rate' must be a rate"),
+            (
+                "max_attempts: 0",
+                "'max_attempts' must be a number above zero",
+            ),
+            (
+                "retry_delay: soon",
+                "'retry_delay' must be a delay in seconds",
+            ),
+            (
+                "checksum_algo: crc32",
+                "'checksum_algo' must be md5, sha256 or blake3",
+            ),
+            (
+                "default_remote: ''",
+                "'default_remote' must be a remote name",
+            ),
+            ("job: 4", "unknown key 'job'"),
+            ("- jobs", "expected 'key: value' lines"),
+            ("jobs: [4", "did not find expected"),
+        ] {
+            let err = ProjectConfig::parse(contents).unwrap_err().to_string();
+            assert!(err.contains(message), "{}: {}", contents, err);
+        }
+
+        // a missing file sets no defaults, but a malformed one is an error
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(
+            ProjectConfig::load(temp_dir.path()).unwrap(),
+            ProjectConfig::default()
+        );
+        fs::create_dir(temp_dir.path().join(".sdf")).unwrap();
+        fs::write(temp_dir.path().join(PROJECT_CONFIG), "jobs: -2\n").unwrap();
+        let err = ProjectConfig::load(temp_dir.path())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Invalid .sdf/config.yml: 'jobs'"),
+            "{}",
+            err
+        );
+    }
+}
@@ -1,25 +1,57 @@
 use anyhow::{anyhow, Result};
+use futures_util::StreamExt;
 #[allow(unused_imports)]
 use log::{debug, info, trace};
-use reqwest::Url;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
 use serde_derive::{Deserialize, Serialize};
 use serde_yaml;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::future::Future;
+use std::io::{Read, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncSeekExt;
+use tokio_util::bytes::Bytes;
+use tokio_util::io::ReaderStream;
 use trauma::download::Download;
 
+use crate::lib::api::dataverse::DataverseAPI;
 use crate::lib::api::dryad::DataDryadAPI;
 use crate::lib::api::figshare::FigShareAPI;
-use crate::lib::api::zenodo::ZenodoAPI;
-use crate::lib::data::{DataFile, MergedFile};
+use crate::lib::api::filesystem::FilesystemAPI;
+use crate::lib::api::gdrive::{self, GoogleDriveAPI};
+use crate::lib::api::osf::OsfAPI;
+use crate::lib::api::rclone::{self, RcloneAPI};
+use crate::lib::api::s3::{S3Credentials, S3API};
+use crate::lib::api::sftp::SftpAPI;
+use crate::lib::api::webdav::WebdavAPI;
+use crate::lib::api::zenodo::{self, ZenodoAPI};
+use crate::lib::checksum::EMPTY_MD5;
+use crate::lib::data::{DataFile, DepositMetadata, MergedFile};
+use crate::lib::download::{stream_verified, RemoteDownload};
+use crate::lib::path_style::PathStyle;
+use crate::lib::progress::FileProgress;
 use crate::lib::project::LocalMetadata;
+use crate::lib::throttle::{self, paced};
+use crate::lib::usage::{Operation, UsageCounter};
+use crate::print_warn;
+use colored::Colorize;
 
 const AUTHKEYS: &str = ".scidataflow_authkeys.yml";
 
+// The default number of files pushed or pulled at once (--max-concurrent;
+// see also Remote::max_concurrent_transfers()).
+pub const MAX_CONCURRENT_TRANSFERS: usize = 4;
+
+// The size of the chunks binary request data is sent in, with --limit-rate.
+const PACED_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RemoteFile {
     pub name: String,
@@ -46,9 +78,11 @@ pub struct RemoteFile {
 //
 // Clean state: everything on the manifest tracked by the remote is
 // local, with nothing else.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RemoteStatusCode {
     Current,      // local and remote files are identical
+    SizeMismatch, // the MD5s agree, but the remote reports a different file size
     MessyLocal,   // local file is different than remote and manifest, which agree
     Different,    // the local file is current, but different than the remote
     NotExists,    // no remote file
@@ -59,19 +93,55 @@ pub enum RemoteStatusCode {
     Invalid,
 }
 
+impl RemoteStatusCode {
+    // How the status is shown by 'sdf status' (some rows add details).
+    pub fn label(&self) -> &'static str {
+        match self {
+            RemoteStatusCode::Current => "identical remote",
+            RemoteStatusCode::SizeMismatch => "remote size differs",
+            RemoteStatusCode::MessyLocal => "messy local",
+            RemoteStatusCode::Different => "different remote version",
+            RemoteStatusCode::NotExists => "not on remote",
+            RemoteStatusCode::Exists => "exists, no remote MD5",
+            RemoteStatusCode::NoLocal => "unknown (messy remote)",
+            RemoteStatusCode::DeletedLocal => "exists on remote",
+            RemoteStatusCode::Invalid => "invalid",
+        }
+    }
+}
+
 impl RemoteFile {
     pub fn set_md5(&mut self, md5: String) {
         self.md5 = Some(md5);
     }
+    // The remote's MD5, if it has one (which, for an empty file, is
+    // EMPTY_MD5 even if the remote does not report it).
     pub fn get_md5(&self) -> Option<String> {
-        let md5 = self.md5.clone();
-        md5.filter(|digest| !digest.is_empty())
+        let md5 = self.md5.clone().filter(|digest| !digest.is_empty());
+        match (md5, self.size) {
+            (None, Some(0)) => Some(EMPTY_MD5.to_string()),
+            (md5, _) => md5,
+        }
     }
     pub fn set_size(&mut self, size: u64) {
         self.size = Some(size);
     }
 }
 
+// A file uploaded to a remote as name, with the result of Remote::upload(),
+// for Remote::confirm_uploads() to confirm.
+#[derive(Debug)]
+pub struct Upload {
+    pub data_file: DataFile,
+    pub name: String,
+    pub result: Result<bool>,
+}
+
+pub fn auth_keys_path() -> Result<PathBuf> {
+    let home_dir = env::var("HOME").map_err(|_| anyhow!("Could not infer home directory"))?;
+    Ok(Path::new(&home_dir).join(AUTHKEYS))
+}
+
 #[derive(Serialize, Deserialize, Default, PartialEq, Debug)]
 pub struct AuthKeys {
     keys: HashMap<String, String>,
@@ -113,6 +183,10 @@ impl AuthKeys {
         self.keys.insert(service, key.to_owned());
     }
 
+    pub fn contains(&self, service: &str) -> bool {
+        self.keys.contains_key(service)
+    }
+
     pub fn get(&self, service: String) -> Result<String> {
         match self.keys.get(&service) {
             None => Err(anyhow!("no key found for service '{}'", service)),
@@ -135,6 +209,14 @@ pub enum Remote {
     FigShareAPI(FigShareAPI),
     DataDryadAPI(DataDryadAPI),
     ZenodoAPI(ZenodoAPI),
+    S3API(S3API),
+    RcloneAPI(RcloneAPI),
+    DataverseAPI(DataverseAPI),
+    OsfAPI(OsfAPI),
+    SftpAPI(SftpAPI),
+    WebdavAPI(WebdavAPI),
+    GoogleDriveAPI(GoogleDriveAPI),
+    FilesystemAPI(FilesystemAPI),
 }
 
 macro_rules! service_not_implemented {
@@ -150,7 +232,187 @@ impl Remote {
         match self {
             Remote::FigShareAPI(_) => "FigShare",
             Remote::DataDryadAPI(_) => "Dryad",
+            Remote::ZenodoAPI(znd_api) if znd_api.sandbox => "Zenodo Sandbox",
             Remote::ZenodoAPI(_) => "Zenodo",
+            Remote::S3API(_) => "S3",
+            Remote::RcloneAPI(_) => "Rclone",
+            Remote::DataverseAPI(_) => "Dataverse",
+            Remote::OsfAPI(_) => "OSF",
+            Remote::SftpAPI(_) => "SFTP",
+            Remote::WebdavAPI(_) => "WebDAV",
+            Remote::GoogleDriveAPI(_) => "Google Drive",
+            Remote::FilesystemAPI(_) => "Filesystem",
+        }
+    }
+    // The most files transferred at once to or from this remote, whatever
+    // --max-concurrent is, for services whose APIs are rate limited (each
+    // Zenodo or FigShare upload is several requests).
+    pub fn max_concurrent_transfers(&self) -> Option<usize> {
+        match self {
+            Remote::ZenodoAPI(_) | Remote::FigShareAPI(_) => Some(2),
+            _ => None,
+        }
+    }
+    // Whether the remote goes by name (e.g. in 'sdf push --remote'),
+    // either its name or its key, ignoring case.
+    pub fn matches(&self, name: &str) -> bool {
+        self.name().eq_ignore_ascii_case(name) || self.auth_key().eq_ignore_ascii_case(name)
+    }
+    // The key of the remote's token in ~/.scidataflow_authkeys.yml.
+    pub fn auth_key(&self) -> &str {
+        match self {
+            Remote::FigShareAPI(_) => "figshare",
+            Remote::DataDryadAPI(_) => "dryad",
+            Remote::ZenodoAPI(znd_api) => zenodo::token_key(znd_api.sandbox),
+            Remote::S3API(_) => "s3",
+            Remote::RcloneAPI(_) => "rclone",
+            Remote::DataverseAPI(_) => "dataverse",
+            Remote::OsfAPI(_) => "osf",
+            Remote::SftpAPI(_) => "sftp",
+            Remote::WebdavAPI(_) => "webdav",
+            Remote::GoogleDriveAPI(_) => "gdrive",
+            Remote::FilesystemAPI(_) => "filesystem",
+        }
+    }
+    // Whether the remote already has an access token (e.g. set by
+    // authenticate_remote(), or a test token).
+    pub fn has_token(&self) -> bool {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.has_token(),
+            Remote::ZenodoAPI(znd_api) => znd_api.has_token(),
+            Remote::S3API(s3_api) => s3_api.has_token(),
+            Remote::RcloneAPI(rclone_api) => rclone_api.has_token(),
+            Remote::DataverseAPI(dv_api) => dv_api.has_token(),
+            Remote::OsfAPI(osf_api) => osf_api.has_token(),
+            Remote::SftpAPI(sftp_api) => sftp_api.has_token(),
+            Remote::WebdavAPI(dav_api) => dav_api.has_token(),
+            Remote::GoogleDriveAPI(drive_api) => drive_api.has_token(),
+            Remote::FilesystemAPI(fs_api) => fs_api.has_token(),
+            Remote::DataDryadAPI(_) => false,
+        }
+    }
+    pub fn path_style(&self) -> PathStyle {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.path_style,
+            Remote::ZenodoAPI(znd_api) => znd_api.path_style,
+            Remote::S3API(s3_api) => s3_api.path_style,
+            Remote::RcloneAPI(rclone_api) => rclone_api.path_style,
+            Remote::DataverseAPI(dv_api) => dv_api.path_style,
+            Remote::OsfAPI(osf_api) => osf_api.path_style,
+            Remote::SftpAPI(sftp_api) => sftp_api.path_style,
+            Remote::WebdavAPI(dav_api) => dav_api.path_style,
+            Remote::GoogleDriveAPI(drive_api) => drive_api.path_style,
+            Remote::FilesystemAPI(fs_api) => fs_api.path_style,
+            Remote::DataDryadAPI(_) => PathStyle::Flat,
+        }
+    }
+    // The path style of a newly linked directory, unless --path-style is
+    // given: S3 buckets and WebDAV servers have directories, so files keep
+    // theirs. (Other remotes stay flat, as they were before path styles.)
+    pub fn default_path_style(&self) -> PathStyle {
+        match self {
+            Remote::S3API(_) | Remote::WebdavAPI(_) => PathStyle::Hierarchical,
+            _ => PathStyle::Flat,
+        }
+    }
+    pub fn set_path_style(&mut self, path_style: PathStyle) {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.path_style = path_style,
+            Remote::ZenodoAPI(znd_api) => znd_api.path_style = path_style,
+            Remote::S3API(s3_api) => s3_api.path_style = path_style,
+            Remote::RcloneAPI(rclone_api) => rclone_api.path_style = path_style,
+            Remote::DataverseAPI(dv_api) => dv_api.path_style = path_style,
+            Remote::OsfAPI(osf_api) => osf_api.path_style = path_style,
+            Remote::SftpAPI(sftp_api) => sftp_api.path_style = path_style,
+            Remote::WebdavAPI(dav_api) => dav_api.path_style = path_style,
+            Remote::GoogleDriveAPI(drive_api) => drive_api.path_style = path_style,
+            Remote::FilesystemAPI(fs_api) => fs_api.path_style = path_style,
+            Remote::DataDryadAPI(_) => (),
+        }
+    }
+    // Request and byte counts of this remote, for the current command.
+    pub fn usage(&self) -> Option<&UsageCounter> {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => Some(&fgsh_api.usage),
+            Remote::ZenodoAPI(znd_api) => Some(&znd_api.usage),
+            Remote::S3API(s3_api) => Some(&s3_api.usage),
+            Remote::RcloneAPI(rclone_api) => Some(&rclone_api.usage),
+            Remote::DataverseAPI(dv_api) => Some(&dv_api.usage),
+            Remote::OsfAPI(osf_api) => Some(&osf_api.usage),
+            Remote::SftpAPI(sftp_api) => Some(&sftp_api.usage),
+            Remote::WebdavAPI(dav_api) => Some(&dav_api.usage),
+            Remote::GoogleDriveAPI(drive_api) => Some(&drive_api.usage),
+            Remote::FilesystemAPI(fs_api) => Some(&fs_api.usage),
+            Remote::DataDryadAPI(_) => None,
+        }
+    }
+    // The web page of the remote's article or deposition, if known.
+    pub fn web_url(&self) -> Option<String> {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.web_url(),
+            Remote::ZenodoAPI(znd_api) => znd_api.web_url(),
+            Remote::S3API(_) => None,
+            Remote::RcloneAPI(_) => None,
+            Remote::DataverseAPI(dv_api) => dv_api.web_url(),
+            Remote::OsfAPI(osf_api) => osf_api.web_url(),
+            Remote::SftpAPI(_) => None,
+            Remote::WebdavAPI(dav_api) => dav_api.web_url(),
+            Remote::GoogleDriveAPI(drive_api) => drive_api.web_url(),
+            Remote::FilesystemAPI(_) => None,
+            Remote::DataDryadAPI(_) => None,
+        }
+    }
+    // Whether the remote is a published record, linked only for downloading
+    // (see 'sdf link --read-only').
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.is_read_only(),
+            Remote::DataDryadAPI(_) => false,
+            Remote::ZenodoAPI(znd_api) => znd_api.is_read_only(),
+            Remote::S3API(_) => false,
+            Remote::RcloneAPI(_) => false,
+            Remote::DataverseAPI(_) => false,
+            Remote::OsfAPI(_) => false,
+            Remote::SftpAPI(_) => false,
+            Remote::WebdavAPI(_) => false,
+            Remote::GoogleDriveAPI(_) => false,
+            Remote::FilesystemAPI(_) => false,
+        }
+    }
+    // What authenticates the remote, if it needs no token (for 'sdf env').
+    pub fn tokenless_credential(&self) -> Option<&'static str> {
+        if self.is_read_only() {
+            return Some("public record");
+        }
+        match self {
+            Remote::RcloneAPI(_) => Some("rclone config"),
+            Remote::SftpAPI(_) => Some("SSH keys"),
+            Remote::FilesystemAPI(_) => Some("file permissions"),
+            _ => None,
+        }
+    }
+    // Whether the remote can write part of a stored file in place (required
+    // for delta uploads; see delta.rs).
+    pub fn supports_ranged_writes(&self) -> bool {
+        match self {
+            Remote::FigShareAPI(_) => false,
+            Remote::DataDryadAPI(_) => false,
+            Remote::ZenodoAPI(_) => false,
+            Remote::S3API(_) => false,
+            Remote::RcloneAPI(_) => false,
+            Remote::DataverseAPI(_) => false,
+            Remote::OsfAPI(_) => false,
+            Remote::SftpAPI(_) => false,
+            Remote::WebdavAPI(_) => false,
+            Remote::GoogleDriveAPI(_) => false,
+            Remote::FilesystemAPI(_) => true,
+        }
+    }
+    // Upload only the changed parts of files that already have a remote
+    // copy, on remotes that support ranged writes (see delta.rs).
+    pub fn set_delta(&mut self, delta: bool) {
+        if let Remote::FilesystemAPI(fs_api) = self {
+            fs_api.delta = delta;
         }
     }
     // initialize the remote (i.e. tell it we have a new empty data set)
@@ -162,13 +424,84 @@ impl Remote {
         match self {
             Remote::FigShareAPI(fgsh_api) => fgsh_api.remote_init(local_metadata, link_only).await,
             Remote::ZenodoAPI(znd_api) => znd_api.remote_init(local_metadata, link_only).await,
+            Remote::S3API(s3_api) => s3_api.remote_init(local_metadata, link_only).await,
+            Remote::RcloneAPI(rclone_api) => {
+                rclone_api.remote_init(local_metadata, link_only).await
+            }
+            Remote::DataverseAPI(dv_api) => dv_api.remote_init(local_metadata, link_only).await,
+            Remote::OsfAPI(osf_api) => osf_api.remote_init(local_metadata, link_only).await,
+            Remote::SftpAPI(sftp_api) => sftp_api.remote_init(local_metadata, link_only).await,
+            Remote::WebdavAPI(dav_api) => dav_api.remote_init(local_metadata, link_only).await,
+            Remote::GoogleDriveAPI(drive_api) => {
+                drive_api.remote_init(local_metadata, link_only).await
+            }
+            Remote::FilesystemAPI(fs_api) => fs_api.remote_init(local_metadata, link_only).await,
             Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
         }
     }
+    // Ready the remote for uploads, before a push (e.g. a published Zenodo
+    // deposition gets a new version to push to). This may change the
+    // remote, which is then kept in the manifest.
+    pub async fn prepare_push(&mut self) -> Result<()> {
+        match self {
+            Remote::FigShareAPI(_) => Ok(()),
+            Remote::ZenodoAPI(znd_api) => znd_api.prepare_push().await,
+            Remote::S3API(_) => Ok(()),
+            Remote::RcloneAPI(_) => Ok(()),
+            Remote::DataverseAPI(_) => Ok(()),
+            Remote::OsfAPI(_) => Ok(()),
+            Remote::SftpAPI(_) => Ok(()),
+            Remote::WebdavAPI(_) => Ok(()),
+            Remote::GoogleDriveAPI(_) => Ok(()),
+            Remote::FilesystemAPI(_) => Ok(()),
+            Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
+        }
+    }
+    // Whether the remote is a deposit that can be published (see
+    // publish()), or else, why not.
+    pub fn check_publishable(&self) -> Result<()> {
+        if self.is_read_only() {
+            return Err(anyhow!("{} is a published record already.", self.name()));
+        }
+        match self {
+            Remote::FigShareAPI(_) | Remote::ZenodoAPI(_) => Ok(()),
+            Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
+            _ => Err(anyhow!(
+                "{} remotes are not published; only Zenodo and FigShare deposits are.",
+                self.name()
+            )),
+        }
+    }
+    // Update the record-level metadata of a deposit to the manifest's,
+    // returning whether it changed. Other remotes have none.
+    pub async fn update_metadata(&mut self, metadata: &DepositMetadata) -> Result<bool> {
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.update_metadata(metadata).await,
+            Remote::ZenodoAPI(znd_api) => znd_api.update_metadata(metadata).await,
+            _ => Ok(false),
+        }
+    }
+    // Publish the remote's deposit, returning the DOI it gets.
+    pub async fn publish(&mut self) -> Result<String> {
+        self.check_publishable()?;
+        match self {
+            Remote::FigShareAPI(fgsh_api) => fgsh_api.publish().await,
+            Remote::ZenodoAPI(znd_api) => znd_api.publish().await,
+            _ => unreachable!("checked by check_publishable()"),
+        }
+    }
     pub async fn get_files(&self) -> Result<Vec<RemoteFile>> {
         match self {
             Remote::FigShareAPI(fgsh_api) => fgsh_api.get_remote_files().await,
             Remote::ZenodoAPI(znd_api) => znd_api.get_remote_files().await,
+            Remote::S3API(s3_api) => s3_api.get_remote_files().await,
+            Remote::RcloneAPI(rclone_api) => rclone_api.get_remote_files().await,
+            Remote::DataverseAPI(dv_api) => dv_api.get_remote_files().await,
+            Remote::OsfAPI(osf_api) => osf_api.get_remote_files().await,
+            Remote::SftpAPI(sftp_api) => sftp_api.get_remote_files().await,
+            Remote::WebdavAPI(dav_api) => dav_api.get_remote_files().await,
+            Remote::GoogleDriveAPI(drive_api) => drive_api.get_remote_files().await,
+            Remote::FilesystemAPI(fs_api) => fs_api.get_remote_files().await,
             Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
         }
     }
@@ -181,29 +514,184 @@ impl Remote {
         }
         Ok(file_map)
     }
+    // Upload a file, as the remote file name (see PathStyle).
     pub async fn upload(
         &self,
         data_file: &DataFile,
+        name: &str,
         path_context: &Path,
         overwrite: bool,
+        progress: &FileProgress,
     ) -> Result<bool> {
+        if self.is_read_only() {
+            return Err(anyhow!(
+                "{} is a published record, so it is read-only.",
+                self.web_url().unwrap_or(self.name().to_string())
+            ));
+        }
         match self {
             Remote::FigShareAPI(fgsh_api) => {
-                fgsh_api.upload(data_file, path_context, overwrite).await
+                fgsh_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::ZenodoAPI(znd_api) => {
+                znd_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::S3API(s3_api) => {
+                s3_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::RcloneAPI(rclone_api) => {
+                rclone_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::DataverseAPI(dv_api) => {
+                dv_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::OsfAPI(osf_api) => {
+                osf_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::SftpAPI(sftp_api) => {
+                sftp_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::WebdavAPI(dav_api) => {
+                dav_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::GoogleDriveAPI(drive_api) => {
+                drive_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
+            }
+            Remote::FilesystemAPI(fs_api) => {
+                fs_api
+                    .upload(data_file, name, path_context, overwrite, progress)
+                    .await
             }
-            Remote::ZenodoAPI(znd_api) => znd_api.upload(data_file, path_context, overwrite).await,
             Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
         }
     }
-    // Get Download info: the URL (with token) and destination
-    // TODO: could be struct, if some APIs require more authentication
+    // Confirm that the remote has the uploaded files (see upload()) with
+    // their local MD5s, by looking up each file on S3 (one HEAD request
+    // each), or else by reading back the remote's file listing, once for
+    // all of them. A failed upload (e.g. one whose response was lost to a
+    // timeout) is a success if the remote has the right file after all, so
+    // it is not uploaded again.
+    //
+    // Returns the result of each upload, as upload() does.
+    pub async fn confirm_uploads(&self, uploads: Vec<Upload>) -> Vec<Result<bool>> {
+        // (files that were not uploaded have nothing to confirm)
+        if uploads
+            .iter()
+            .all(|upload| matches!(upload.result, Ok(false)))
+        {
+            return uploads.into_iter().map(|upload| upload.result).collect();
+        }
+        if let Remote::S3API(s3_api) = self {
+            let mut results = Vec::new();
+            for upload in uploads {
+                let result = match upload.result {
+                    Ok(false) => Ok(false),
+                    _ => {
+                        let remote_file = s3_api.get_remote_file(&upload.name).await;
+                        self.confirm_upload(upload, remote_file)
+                    }
+                };
+                results.push(result);
+            }
+            return results;
+        }
+        let listing = self.get_files_hashmap().await;
+        uploads
+            .into_iter()
+            .map(|upload| {
+                let remote_file = match &listing {
+                    Ok(files) => Ok(files.get(&upload.name).cloned()),
+                    Err(err) => Err(anyhow!("{:#}", err)),
+                };
+                self.confirm_upload(upload, remote_file)
+            })
+            .collect()
+    }
+    // Confirm an upload, given the remote's file of its name (if any).
+    fn confirm_upload(
+        &self,
+        upload: Upload,
+        remote_file: Result<Option<RemoteFile>>,
+    ) -> Result<bool> {
+        let Upload {
+            data_file,
+            name,
+            result: uploaded,
+        } = upload;
+        if let Ok(false) = uploaded {
+            return Ok(false);
+        }
+        // copies to a filesystem are re-read before they are moved into
+        // place, and listing it would hash every file again
+        if let Remote::FilesystemAPI(_) = self {
+            return uploaded;
+        }
+        let remote_file = match remote_file {
+            Ok(remote_file) => remote_file,
+            Err(err) if uploaded.is_ok() => {
+                print_warn!(
+                    "Could not confirm the upload of '{}' to {}: {}",
+                    name,
+                    self.name(),
+                    err
+                );
+                return Ok(true);
+            }
+            Err(_) => return uploaded,
+        };
+        let remote_md5 = remote_file.as_ref().and_then(|file| file.get_md5());
+        let confirmed = remote_md5.as_ref() == Some(&data_file.md5);
+        match uploaded {
+            Ok(_) if confirmed => Ok(true),
+            // some remotes only report MD5s once a file is processed
+            Ok(_) if remote_file.is_some() && remote_md5.is_none() => Ok(true),
+            Ok(_) => Err(anyhow!(
+                "After upload, {} reports {} for '{}' (expected MD5 {}).",
+                self.name(),
+                remote_md5.map_or("no such file".to_string(), |md5| format!("MD5 {}", md5)),
+                name,
+                data_file.md5
+            )),
+            Err(err) if confirmed => {
+                print_warn!(
+                    "The upload of '{}' reported an error ({}), but {} has the file with the \
+                     local MD5; treating it as uploaded.",
+                    name,
+                    err,
+                    self.name()
+                );
+                Ok(true)
+            }
+            Err(err) => Err(err),
+        }
+    }
+    // Get Download info: the URL (with token), or for rclone, SFTP and
+    // filesystems, the command, and destination
     // Note: requires each API actually *check* overwrite.
     pub fn get_download_info(
         &self,
         merged_file: &MergedFile,
         path_context: &Path,
         overwrite: bool,
-    ) -> Result<Download> {
+    ) -> Result<RemoteDownload> {
         // if local DataFile is none, not in manifest;
         // do not download
         let data_file = match &merged_file.local {
@@ -224,24 +712,112 @@ impl Remote {
             .remote
             .as_ref()
             .ok_or(anyhow!("Remote is None"))?;
+        let save_path = &data_file.full_path(path_context)?;
+        let filename = save_path.to_string_lossy().to_string();
+        if let Remote::RcloneAPI(rclone_api) = self {
+            let download = rclone_api.download(&remote.name, &filename);
+            return Ok(RemoteDownload::Command(download));
+        }
         let url = remote
             .url
             .as_ref()
             .ok_or(anyhow!("Cannot download; download URL not set."))?;
+        if let Remote::SftpAPI(sftp_api) = self {
+            return Ok(RemoteDownload::Command(sftp_api.download(url, &filename)));
+        }
+        if let Remote::FilesystemAPI(fs_api) = self {
+            return Ok(RemoteDownload::Command(fs_api.download(url, &filename)));
+        }
+        // OSF, WebDAV servers and Google Drive only take credentials in a
+        // header
+        let headers = match self {
+            Remote::OsfAPI(osf_api) => Some(osf_api.headers()?),
+            Remote::WebdavAPI(dav_api) => Some(dav_api.headers()?),
+            Remote::GoogleDriveAPI(drive_api) => Some(drive_api.headers()?),
+            _ => None,
+        };
+        if let Some(headers) = headers {
+            let download = Download {
+                url: Url::parse(url)?,
+                filename,
+            };
+            return Ok(RemoteDownload::Authorized(download, headers));
+        }
 
         let authenticated_url = match self {
             Remote::FigShareAPI(fgsh_api) => fgsh_api.authenticate_url(url),
             Remote::ZenodoAPI(znd_api) => znd_api.authenticate_url(url),
+            Remote::S3API(s3_api) => s3_api.authenticate_url(url),
+            Remote::DataverseAPI(dv_api) => dv_api.authenticate_url(url),
+            Remote::RcloneAPI(_)
+            | Remote::OsfAPI(_)
+            | Remote::SftpAPI(_)
+            | Remote::WebdavAPI(_)
+            | Remote::GoogleDriveAPI(_)
+            | Remote::FilesystemAPI(_) => unreachable!(),
             Remote::DataDryadAPI(_) => service_not_implemented!("DataDryad"),
         }?;
-        let save_path = &data_file.full_path(path_context)?;
         let url = Url::parse(&authenticated_url)?;
-        let filename = save_path.to_string_lossy().to_string();
-        Ok(Download { url, filename })
+        Ok(RemoteDownload::Url(Download { url, filename }))
+    }
+
+    // Stream a merged file's remote copy to writer, verifying it against
+    // expected_md5, and count the download in this remote's usage.
+    pub async fn stream_to<W: Write>(
+        &self,
+        merged_file: &MergedFile,
+        path_context: &Path,
+        expected_md5: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let result = match (
+            self,
+            self.get_download_info(merged_file, path_context, true)?,
+        ) {
+            (Remote::RcloneAPI(rclone_api), RemoteDownload::Command(_)) => {
+                let name = merged_file.remote.as_ref().map(|remote| &remote.name);
+                let name = name.ok_or(anyhow!("Remote is None"))?;
+                rclone_api.stream_to(name, expected_md5, writer).await
+            }
+            (Remote::SftpAPI(sftp_api), RemoteDownload::Command(_)) => {
+                let url = merged_file
+                    .remote
+                    .as_ref()
+                    .and_then(|remote| remote.url.as_ref());
+                let url = url.ok_or(anyhow!("Cannot download; download URL not set."))?;
+                sftp_api.stream_to(url, expected_md5, writer).await
+            }
+            (Remote::FilesystemAPI(fs_api), RemoteDownload::Command(_)) => {
+                let url = merged_file
+                    .remote
+                    .as_ref()
+                    .and_then(|remote| remote.url.as_ref());
+                let url = url.ok_or(anyhow!("Cannot download; download URL not set."))?;
+                fs_api.stream_to(url, expected_md5, writer).await
+            }
+            (_, RemoteDownload::Url(download)) => {
+                stream_verified(&download.url, &HeaderMap::new(), expected_md5, writer).await
+            }
+            (_, RemoteDownload::Authorized(download, headers)) => {
+                stream_verified(&download.url, &headers, expected_md5, writer).await
+            }
+            (_, RemoteDownload::Command(_)) => Err(anyhow!(
+                "Internal Error: {} cannot stream files. Please report.",
+                self.name()
+            )),
+        };
+        if let Some(counter) = self.usage() {
+            let size = result.as_ref().map(|size| *size).unwrap_or(0);
+            counter.record(Operation::Get, 0, size);
+        }
+        result
     }
 }
 
 pub fn authenticate_remote(remote: &mut Remote) -> Result<()> {
+    if remote.has_token() {
+        return Ok(());
+    }
     // Get the keys off disk
     let auth_keys = AuthKeys::new();
     let error_message = |service_name: &str, token_name: &str| {
@@ -261,13 +837,74 @@ pub fn authenticate_remote(remote: &mut Remote) -> Result<()> {
             fgsh_api.set_token(token);
         }
         Remote::ZenodoAPI(ref mut znd_api) => {
+            let key = zenodo::token_key(znd_api.sandbox);
             let token = auth_keys
                 .keys
-                .get("zenodo")
+                .get(key)
                 .cloned()
-                .ok_or_else(|| anyhow::anyhow!(error_message("Zenodo", "zenodo")))?;
+                .ok_or_else(|| anyhow::anyhow!(error_message("Zenodo", key)))?;
             znd_api.set_token(token);
         }
+        Remote::DataverseAPI(ref mut dv_api) => {
+            let token = auth_keys
+                .keys
+                .get("dataverse")
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!(error_message("Dataverse", "dataverse")))?;
+            dv_api.set_token(token);
+        }
+        Remote::OsfAPI(ref mut osf_api) => {
+            let token = auth_keys
+                .keys
+                .get("osf")
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!(error_message("OSF", "osf")))?;
+            osf_api.set_token(token);
+        }
+        Remote::WebdavAPI(ref mut dav_api) => {
+            let credentials = auth_keys
+                .keys
+                .get("webdav")
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!(error_message("WebDAV", "webdav")))?;
+            dav_api.set_token(credentials);
+        }
+        // Google Drive's refresh token is saved once sdf is authorized
+        // (see gdrive.rs)
+        Remote::GoogleDriveAPI(ref mut drive_api) => {
+            let client = auth_keys
+                .keys
+                .get("gdrive")
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!(error_message("Google Drive", "gdrive")))?;
+            let refresh_token = auth_keys.keys.get(gdrive::REFRESH_TOKEN_KEY).cloned();
+            drive_api.set_credentials(client, refresh_token);
+        }
+        // AWS's credentials (in the environment, then its shared
+        // credentials file) take precedence, as in AWS's tools
+        Remote::S3API(ref mut s3_api) => {
+            let aws_credentials =
+                S3Credentials::from_env().or_else(S3Credentials::from_aws_credentials);
+            let credentials = match aws_credentials {
+                Some(credentials) => credentials,
+                None => {
+                    let key = auth_keys.keys.get("s3").ok_or_else(|| {
+                        anyhow!(
+                            "Expected S3 credentials not found.\n\n\
+                             Set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, add them to\n\
+                             ~/.aws/credentials (e.g. with 'aws configure'), or add a line to\n\
+                             ~/.scidataflow_authkeys.yml like:\n\
+                             s3: <ACCESS_KEY_ID>:<SECRET_ACCESS_KEY>"
+                        )
+                    })?;
+                    S3Credentials::parse(key)?
+                }
+            };
+            s3_api.set_credentials(credentials);
+        }
+        // rclone remotes are configured with 'rclone config', so sdf only
+        // needs the binary
+        Remote::RcloneAPI(ref mut rclone_api) => rclone_api.set_binary(rclone::configured_binary()),
         // handle other Remote variants as necessary
         _ => Err(anyhow!(
             "Could not find correct API in authenticate_remote()"
@@ -285,10 +922,336 @@ pub enum RequestData<T: serde::Serialize> {
     Json(T),
     Binary(Vec<u8>),
     File(tokio::fs::File),
-    Stream(tokio::fs::File),
+    // a file streamed as it is sent, advancing the progress
+    Stream(tokio::fs::File, FileProgress),
+    // a multipart/form-data upload of a file, streamed like Stream()
+    Form(FileForm),
     Empty,
 }
 
+// A multipart/form-data body of text fields and a file (as the part
+// 'file'), e.g. for Dataverse's uploads.
+#[derive(Debug)]
+pub struct FileForm {
+    fields: Vec<(String, String)>,
+    filename: String,
+    file: tokio::fs::File,
+    progress: FileProgress,
+    boundary: String,
+}
+
+impl FileForm {
+    pub fn new(
+        fields: Vec<(String, String)>,
+        filename: &str,
+        file: tokio::fs::File,
+        progress: FileProgress,
+    ) -> Self {
+        let token: u64 = rand::thread_rng().gen();
+        FileForm {
+            fields,
+            filename: filename.to_string(),
+            file,
+            progress,
+            boundary: format!("scidataflow-{:016x}", token),
+        }
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    // The body around the file's contents.
+    fn framing(&self) -> (String, String) {
+        let quoted = |value: &str| {
+            value
+                .replace('"', "%22")
+                .replace('\r', "%0D")
+                .replace('\n', "%0A")
+        };
+        let mut head = String::new();
+        for (name, value) in &self.fields {
+            head.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                self.boundary,
+                quoted(name),
+                value
+            ));
+        }
+        head.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            self.boundary,
+            quoted(&self.filename)
+        ));
+        (head, format!("\r\n--{}--\r\n", self.boundary))
+    }
+
+    pub async fn len(&self) -> Result<u64> {
+        let (head, tail) = self.framing();
+        Ok(head.len() as u64 + self.file.metadata().await?.len() + tail.len() as u64)
+    }
+}
+
+// The chunks of file, paced by --limit-rate, advancing progress.
+fn progress_stream(
+    file: tokio::fs::File,
+    progress: FileProgress,
+) -> impl futures_util::Stream<Item = std::io::Result<Bytes>> {
+    paced(ReaderStream::new(file), throttle::limiter()).inspect(move |chunk| {
+        if let Ok(bytes) = chunk {
+            progress.inc(bytes.len() as u64);
+        }
+    })
+}
+
+// The request body of a RequestData::Stream(), paced by --limit-rate.
+pub fn stream_body(file: tokio::fs::File, progress: FileProgress) -> reqwest::Body {
+    reqwest::Body::wrap_stream(progress_stream(file, progress))
+}
+
+// Set a streamed body of len bytes, which would otherwise be sent
+// without a Content-Length.
+fn with_sized_body(
+    request: RequestBuilder,
+    body: reqwest::Body,
+    len: u64,
+) -> Result<RequestBuilder> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    request
+        .headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(len));
+    *request.body_mut() = Some(body);
+    Ok(RequestBuilder::from_parts(client, request))
+}
+
+// A handle to file, read from its start.
+async fn rewound(file: &tokio::fs::File) -> Result<tokio::fs::File> {
+    let mut file = file.try_clone().await?;
+    file.seek(SeekFrom::Start(0)).await?;
+    Ok(file)
+}
+
+impl<T: serde::Serialize> RequestData<T> {
+    // Set this as the body of request. Files are sent from their start
+    // (and streamed ones restart their progress), so the same data can be
+    // attached again to retry a request. With --limit-rate, binary data and
+    // files are streamed, so that they can be paced.
+    pub async fn attach(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        let limiter = throttle::limiter();
+        let request = match self {
+            RequestData::Json(json_data) => request.json(json_data),
+            RequestData::Binary(bin_data) if limiter.is_some() => {
+                let chunks: Vec<std::io::Result<Vec<u8>>> = bin_data
+                    .chunks(PACED_CHUNK_SIZE)
+                    .map(|chunk| Ok(chunk.to_vec()))
+                    .collect();
+                let stream = paced(futures_util::stream::iter(chunks), limiter);
+                let body = reqwest::Body::wrap_stream(stream);
+                with_sized_body(request, body, bin_data.len() as u64)?
+            }
+            RequestData::Binary(bin_data) => request.body(bin_data.clone()),
+            RequestData::File(file) if limiter.is_some() => {
+                let len = file.metadata().await?.len();
+                let stream = paced(ReaderStream::new(rewound(file).await?), limiter);
+                with_sized_body(request, reqwest::Body::wrap_stream(stream), len)?
+            }
+            RequestData::File(file) => request.body(rewound(file).await?),
+            // with its length, since without one an empty file would be
+            // sent as a chunked body, which some servers reject
+            RequestData::Stream(file, progress) => {
+                progress.set_position(0);
+                let len = file.metadata().await?.len();
+                let body = stream_body(rewound(file).await?, progress.clone());
+                with_sized_body(request, body, len)?
+            }
+            RequestData::Form(form) => {
+                form.progress.set_position(0);
+                let (head, tail) = form.framing();
+                let len = form.len().await?;
+                let file = progress_stream(rewound(&form.file).await?, form.progress.clone());
+                let stream = futures_util::stream::once(async { Ok(Bytes::from(head)) })
+                    .chain(file)
+                    .chain(futures_util::stream::once(async { Ok(Bytes::from(tail)) }));
+                let request = request.header(CONTENT_TYPE, form.content_type());
+                with_sized_body(request, reqwest::Body::wrap_stream(stream), len)?
+            }
+            RequestData::Empty => request.json(&serde_json::Value::Object(serde_json::Map::new())),
+        };
+        Ok(request)
+    }
+}
+
+// Retrying requests to remotes on transient failures: server errors
+// (5xx), 429 Too Many Requests, and connection errors or timeouts are
+// retried with exponential backoff and jitter (or after the response's
+// Retry-After, if set). Other client errors are not retried. Requests
+// that are not idempotent (POST and PATCH, e.g. creating a file) are only
+// retried if the server cannot have acted on them: connection errors, 429
+// and 503 Service Unavailable. 'sdf --max-attempts' and '--retry-delay'
+// tune the policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // including the first attempt
+    pub max_attempts: u32,
+    // the delay before the first retry, which doubles for each retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+// a longer Retry-After than this is taken as this
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+impl RetryPolicy {
+    // The delay before retrying after attempt (counting from 1): the
+    // doubled delay, or up to half less, at random, so that many clients
+    // do not retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+// Set the retry policy for this run.
+pub fn configure_retries(max_attempts: Option<u32>, base_delay: Option<Duration>) -> RetryPolicy {
+    let defaults = RetryPolicy::default();
+    let policy = RetryPolicy {
+        max_attempts: max_attempts.unwrap_or(defaults.max_attempts),
+        base_delay: base_delay.unwrap_or(defaults.base_delay),
+        ..defaults
+    };
+    RETRY_POLICY.get_or_init(|| policy);
+    policy
+}
+
+pub fn retry_policy() -> RetryPolicy {
+    *RETRY_POLICY.get_or_init(|| {
+        let policy = RetryPolicy::default();
+        if cfg!(test) {
+            // tests of failing requests should not wait
+            RetryPolicy {
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+                ..policy
+            }
+        } else {
+            policy
+        }
+    })
+}
+
+// A delay in seconds, e.g. '0.5' (for --retry-delay).
+pub fn parse_delay(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or(format!(
+            "invalid delay '{}' (expected seconds, e.g. 0.5)",
+            value
+        ))
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    !matches!(*method, Method::POST | Method::PATCH)
+}
+
+fn is_retryable(method: &Method, status: StatusCode) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+        _ => status.is_server_error() && is_idempotent(method),
+    }
+}
+
+// Connection errors and timeouts (including connections reset while the
+// request was sent).
+fn is_transient(method: &Method, err: &anyhow::Error) -> bool {
+    err.downcast_ref::<reqwest::Error>().is_some_and(|err| {
+        err.is_connect() || ((err.is_timeout() || err.is_request()) && is_idempotent(method))
+    })
+}
+
+// The response's Retry-After, in seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    let delay = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO)
+        }
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+// Send a method request (built anew by send for each attempt), retrying
+// transient failures as set by policy. The last attempt's response is
+// returned as is, so callers still handle error statuses.
+pub async fn send_with_retry<F, Fut>(
+    policy: &RetryPolicy,
+    method: &Method,
+    mut send: F,
+) -> Result<Response>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Response>>,
+{
+    let mut attempt = 1;
+    loop {
+        let result = send().await;
+        if attempt >= policy.max_attempts {
+            return result;
+        }
+        let (problem, delay) = match &result {
+            Ok(response) if is_retryable(method, response.status()) => (
+                format!("HTTP {}", response.status()),
+                retry_after(response).unwrap_or_else(|| policy.backoff(attempt)),
+            ),
+            Err(err) if is_transient(method, err) => {
+                let problem = match err.downcast_ref::<reqwest::Error>() {
+                    Some(err) if err.is_timeout() => "timed out",
+                    _ => "connection error",
+                };
+                (problem.to_string(), policy.backoff(attempt))
+            }
+            _ => return result,
+        };
+        print_warn!(
+            "Request failed ({}); retrying in {:.1}s (attempt {} of {}).",
+            problem,
+            delay.as_secs_f64(),
+            attempt + 1,
+            policy.max_attempts
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
 /* impl DataDryadAPI {
 fn upload(&self) {
 }
@@ -301,3 +1264,124 @@ Ok("ID".to_string())
 }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn test_default_path_styles() {
+        let s3 = Remote::S3API(S3API::new("bucket/project", None, None).unwrap());
+        let zenodo = Remote::ZenodoAPI(
+            ZenodoAPI::new("project", Some("http://localhost".to_string()), false).unwrap(),
+        );
+        let dav = WebdavAPI::new("https://cloud.example.org/dav/files/me/atlas").unwrap();
+        assert_eq!(s3.default_path_style(), PathStyle::Hierarchical);
+        assert_eq!(
+            Remote::WebdavAPI(dav).default_path_style(),
+            PathStyle::Hierarchical
+        );
+        assert_eq!(zenodo.default_path_style(), PathStyle::Flat);
+        let filesystem = Remote::FilesystemAPI(FilesystemAPI::new("/shared/atlas").unwrap());
+        assert_eq!(filesystem.default_path_style(), PathStyle::Flat);
+        // directories linked before (without a path style) stay flat
+        assert_eq!(s3.path_style(), PathStyle::Flat);
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_failures() {
+        let server = MockServer::start();
+        let mut unavailable = Some(server.mock(|when, then| {
+            when.method(GET).path("/files");
+            then.status(503);
+        }));
+        let ok = server.mock(|when, then| {
+            when.method(GET).path("/files");
+            then.status(200).body("[]");
+        });
+        let client = reqwest::Client::new();
+
+        // two 503s, then the mock is gone and the request succeeds
+        let mut attempts = 0;
+        let response = send_with_retry(&fast_policy(), &Method::GET, || {
+            attempts += 1;
+            if attempts == 3 {
+                let mut unavailable = unavailable.take().unwrap();
+                unavailable.assert_hits(2);
+                unavailable.delete();
+            }
+            let request = client.get(server.url("/files"));
+            async move { Ok(request.send().await?) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(attempts, 3);
+        ok.assert_hits(1);
+
+        // client errors are not retried
+        let not_found = server.mock(|when, then| {
+            when.method(GET).path("/missing");
+            then.status(404);
+        });
+        let response = send_with_retry(&fast_policy(), &Method::GET, || {
+            let request = client.get(server.url("/missing"));
+            async move { Ok(request.send().await?) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        not_found.assert_hits(1);
+
+        // a server that is down for good is tried max_attempts times
+        let down = server.mock(|when, then| {
+            when.method(GET).path("/down");
+            then.status(429).header("Retry-After", "0");
+        });
+        let response = send_with_retry(&fast_policy(), &Method::GET, || {
+            let request = client.get(server.url("/down"));
+            async move { Ok(request.send().await?) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        down.assert_hits(5);
+
+        // creating things is not retried after server errors, which may
+        // have happened after the server acted
+        let create = server.mock(|when, then| {
+            when.method(POST).path("/files");
+            then.status(500);
+        });
+        let response = send_with_retry(&fast_policy(), &Method::POST, || {
+            let request = client.post(server.url("/files"));
+            async move { Ok(request.send().await?) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        create.assert_hits(1);
+    }
+
+    #[test]
+    fn test_retry_backoff_is_capped() {
+        let policy = fast_policy();
+        for attempt in 1..40 {
+            let delay = policy.backoff(attempt);
+            assert!(delay <= policy.max_delay);
+        }
+        // jitter takes off at most half the delay
+        assert!(policy.backoff(1) >= Duration::from_micros(500));
+        assert_eq!(parse_delay("0.25"), Ok(Duration::from_millis(250)));
+        assert!(parse_delay("-1").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+// A cache of remote file listings, so that repeated 'sdf status --remotes'
+// runs within a TTL (--remote-cache-ttl) don't query the remotes again.
+//
+// Listings are stored per tracked directory in <project>/.sdf/remote_cache.json,
+// along with when they were fetched. A listing is only reused if it is for
+// the same remote that currently tracks the directory.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lib::remote::RemoteFile;
+use crate::lib::staging::StagedFile;
+
+pub const REMOTE_CACHE: &str = ".sdf/remote_cache.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CachedListing {
+    // the remote's name (e.g. "Zenodo")
+    pub remote: String,
+    #[serde(with = "crate::lib::timestamp")]
+    pub fetched: DateTime<Utc>,
+    pub files: HashMap<String, RemoteFile>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RemoteCache {
+    // tracked directory -> listing
+    listings: BTreeMap<String, CachedListing>,
+}
+
+pub fn remote_cache_path(path_context: &Path) -> PathBuf {
+    path_context.join(REMOTE_CACHE)
+}
+
+impl RemoteCache {
+    // Load the cache; a missing or unreadable cache is empty (it is only
+    // an optimization, so it is never an error to ignore it).
+    pub fn load(path_context: &Path) -> Self {
+        fs::read_to_string(remote_cache_path(path_context))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path_context: &Path) -> Result<()> {
+        let staged = StagedFile::new(path_context, "remote-cache")?;
+        fs::write(staged.path(), serde_json::to_string(self)?)
+            .map_err(|err| anyhow!("Could not write the remote cache: {}", err))?;
+        staged.persist(&remote_cache_path(path_context))
+    }
+
+    // The listing of the directory's remote, if it was fetched within ttl.
+    pub fn get(
+        &self,
+        directory: &str,
+        remote: &str,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<&CachedListing> {
+        self.listings
+            .get(directory)
+            .filter(|listing| listing.remote == remote && now - listing.fetched <= ttl)
+    }
+
+    pub fn insert(
+        &mut self,
+        directory: &str,
+        remote: &str,
+        files: HashMap<String, RemoteFile>,
+        now: DateTime<Utc>,
+    ) {
+        self.listings.insert(
+            directory.to_string(),
+            CachedListing {
+                remote: remote.to_string(),
+                fetched: now,
+                files,
+            },
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use csv::WriterBuilder;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::lib::usage::{print_usage, RemoteUsage};
+use crate::lib::utils::{format_bytes, pluralize, print_fixed_width};
+
+// The output format of end-of-run reports (and status and other
+// listings): a table to read, or JSON or TSV records for scripts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Tsv,
+}
+
+// A value as a TSV field: as it is in JSON, but with strings unquoted, and
+// nulls empty.
+pub fn tsv_field<T: serde::Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(text)) => text,
+        Ok(Value::Null) | Err(_) => String::new(),
+        Ok(value) => value.to_string(),
+    }
+}
+
+// A line of tab-separated fields (quoted, if they contain tabs, newlines
+// or quotes, as in 'sdf export --format tsv').
+pub fn tsv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .from_writer(Vec::new());
+    let fields = fields.iter().map(|field| field.as_ref());
+    let line = writer
+        .write_record(fields)
+        .ok()
+        .and_then(|_| writer.into_inner().ok())
+        .and_then(|line| String::from_utf8(line).ok())
+        .expect("Internal Error: TSV fields are written to memory.");
+    line.trim_end_matches(['\r', '\n']).to_string()
+}
+
+// Print a header line, and then the rows, as TSV.
+pub fn print_tsv<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) {
+    println!("{}", tsv_line(header));
+    for row in rows {
+        println!("{}", tsv_line(row));
+    }
+}
+
+const REPORT_COLUMNS: [&str; 4] = ["path", "status", "reason", "remote"];
+
+// Note: the variant order is the report's sort order, so that
+// failures are listed first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Failed,
+    Skipped,
+    Succeeded,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileOutcome {
+    pub path: String,
+    pub status: Outcome,
+    // why the file was skipped, or the error it failed with
+    pub reason: Option<String>,
+    // the remote, for directories with mirrors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+// How a path is shown in reports: with its remote, if it is named.
+fn display_path(path: &str, remote: Option<&String>) -> String {
+    match remote {
+        Some(remote) => format!("{} > {}", path, remote),
+        None => path.to_string(),
+    }
+}
+
+// Per-file outcomes of a push or pull, accumulated during the run and
+// reported at the end (rather than letting errors scroll by).
+#[derive(Debug, Default)]
+pub struct TransferReport {
+    pub files: Vec<FileOutcome>,
+    // requests and bytes per remote, set once the transfer is done
+    pub usage: Vec<RemoteUsage>,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    files: Vec<&'a FileOutcome>,
+    usage: &'a [RemoteUsage],
+}
+
+impl TransferReport {
+    pub fn new() -> Self {
+        TransferReport::default()
+    }
+
+    fn record(&mut self, path: &str, status: Outcome, reason: Option<String>) {
+        self.files.push(FileOutcome {
+            path: path.to_string(),
+            status,
+            reason,
+            remote: None,
+        });
+    }
+
+    pub fn succeeded(&mut self, path: &str) {
+        self.record(path, Outcome::Succeeded, None);
+    }
+
+    pub fn skipped(&mut self, path: &str, reason: &str) {
+        self.record(path, Outcome::Skipped, Some(reason.to_string()));
+    }
+
+    pub fn failed(&mut self, path: &str, error: &str) {
+        self.record(path, Outcome::Failed, Some(error.to_string()));
+    }
+
+    // Record the outcome of a file on one of its remotes.
+    pub fn record_on(&mut self, path: &str, remote: &str, status: Outcome, reason: Option<&str>) {
+        self.files.push(FileOutcome {
+            path: path.to_string(),
+            status,
+            reason: reason.map(str::to_string),
+            remote: Some(remote.to_string()),
+        });
+    }
+
+    pub fn extend(&mut self, other: TransferReport) {
+        self.files.extend(other.files);
+    }
+
+    pub fn count(&self, status: Outcome) -> usize {
+        self.files.iter().filter(|f| f.status == status).count()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.count(Outcome::Failed) > 0
+    }
+
+    // Make absolute paths (e.g. download destinations) relative to the
+    // project directory, so they match the manifest.
+    pub fn relative_to(mut self, path_context: &Path) -> Self {
+        for file in self.files.iter_mut() {
+            if let Ok(relative) = Path::new(&file.path).strip_prefix(path_context) {
+                file.path = relative.to_string_lossy().to_string();
+            }
+        }
+        self
+    }
+
+    // Sorted by status (failed, skipped, succeeded), then path (and
+    // remote).
+    pub fn sorted(&self) -> Vec<&FileOutcome> {
+        let mut files: Vec<&FileOutcome> = self.files.iter().collect();
+        files.sort_by(|a, b| (&a.status, &a.path, &a.remote).cmp(&(&b.status, &b.path, &b.remote)));
+        files
+    }
+
+    // The (sorted) rows of the report as TSV.
+    fn tsv_rows(&self) -> Vec<Vec<String>> {
+        self.sorted()
+            .iter()
+            .map(|f| {
+                vec![
+                    f.path.clone(),
+                    tsv_field(&f.status),
+                    f.reason.clone().unwrap_or_default(),
+                    f.remote.clone().unwrap_or_default(),
+                ]
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let report = JsonReport {
+            files: self.sorted(),
+            usage: &self.usage,
+        };
+        Ok(serde_json::to_string_pretty(&report)?)
+    }
+
+    pub fn print(&self, format: OutputFormat, verb: &str) -> Result<()> {
+        match format {
+            OutputFormat::Json => {
+                println!("{}", self.to_json()?);
+                return Ok(());
+            }
+            OutputFormat::Tsv => {
+                print_tsv(&REPORT_COLUMNS, &self.tsv_rows());
+                return Ok(());
+            }
+            OutputFormat::Table => (),
+        }
+        let succeeded = self.count(Outcome::Succeeded);
+        let skipped = self.count(Outcome::Skipped);
+        let failed = self.count(Outcome::Failed);
+        println!(
+            "{} {}, skipped {}, {} failed.",
+            verb,
+            pluralize(succeeded as u64, "file"),
+            pluralize(skipped as u64, "file"),
+            failed
+        );
+        if self.files.is_empty() {
+            print_usage(&self.usage);
+            return Ok(());
+        }
+        let rows: Vec<Vec<String>> = self
+            .sorted()
+            .iter()
+            .map(|f| {
+                let status = match f.status {
+                    Outcome::Failed => "failed".red().to_string(),
+                    Outcome::Skipped => "skipped".yellow().to_string(),
+                    Outcome::Succeeded => "succeeded".green().to_string(),
+                };
+                let path = display_path(&f.path, f.remote.as_ref());
+                vec![path, status, f.reason.clone().unwrap_or_default()]
+            })
+            .collect();
+        println!();
+        print_fixed_width(&rows, None, Some(1));
+        print_usage(&self.usage);
+        Ok(())
+    }
+
+    // Print only the failed files (e.g. for 'sdf verify --quiet'); in the
+    // table format, this prints nothing if there were no failures.
+    pub fn print_failures(&self, format: OutputFormat) -> Result<()> {
+        let failures = TransferReport {
+            files: self
+                .files
+                .iter()
+                .filter(|f| f.status == Outcome::Failed)
+                .cloned()
+                .collect(),
+            usage: Vec::new(),
+        };
+        match format {
+            OutputFormat::Json => {
+                println!("{}", failures.to_json()?);
+                return Ok(());
+            }
+            OutputFormat::Tsv => {
+                print_tsv(&REPORT_COLUMNS, &failures.tsv_rows());
+                return Ok(());
+            }
+            OutputFormat::Table => (),
+        }
+        let rows: Vec<Vec<String>> = failures
+            .sorted()
+            .iter()
+            .map(|f| {
+                let path = display_path(&f.path, f.remote.as_ref());
+                vec![path, f.reason.clone().unwrap_or_default()]
+            })
+            .collect();
+        print_fixed_width(&rows, None, None);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Upload => write!(f, "upload"),
+            Direction::Download => write!(f, "download"),
+        }
+    }
+}
+
+// A file that a push or pull would transfer, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedTransfer {
+    pub path: String,
+    pub direction: Direction,
+    pub size: Option<u64>,
+    pub reason: String,
+    // the remote, for directories with mirrors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+// What a push or pull would do (for --dry-run): the files it would
+// transfer, and in the report, the files it would skip.
+#[derive(Debug, Default)]
+pub struct TransferPlan {
+    pub transfers: Vec<PlannedTransfer>,
+    pub report: TransferReport,
+}
+
+#[derive(Serialize)]
+struct JsonPlan<'a> {
+    transfers: &'a [PlannedTransfer],
+    skipped: Vec<&'a FileOutcome>,
+}
+
+impl TransferPlan {
+    pub fn extend(&mut self, other: TransferPlan) {
+        self.transfers.extend(other.transfers);
+        self.report.extend(other.report);
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.transfers.iter().filter_map(|t| t.size).sum()
+    }
+
+    pub fn print(&self, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Json => {
+                let plan = JsonPlan {
+                    transfers: &self.transfers,
+                    skipped: self.report.sorted(),
+                };
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+                return Ok(());
+            }
+            // one row per file: its transfer, or "skip"
+            OutputFormat::Tsv => {
+                let mut rows: Vec<Vec<String>> = self
+                    .transfers
+                    .iter()
+                    .map(|t| {
+                        vec![
+                            t.direction.to_string(),
+                            t.path.clone(),
+                            tsv_field(&t.size),
+                            t.reason.clone(),
+                            t.remote.clone().unwrap_or_default(),
+                        ]
+                    })
+                    .collect();
+                rows.extend(self.report.sorted().iter().map(|f| {
+                    vec![
+                        "skip".to_string(),
+                        f.path.clone(),
+                        String::new(),
+                        f.reason.clone().unwrap_or_default(),
+                        f.remote.clone().unwrap_or_default(),
+                    ]
+                }));
+                print_tsv(&["action", "path", "size", "reason", "remote"], &rows);
+                return Ok(());
+            }
+            OutputFormat::Table => (),
+        }
+        println!(
+            "Would transfer {} ({}), skip {}.",
+            pluralize(self.transfers.len() as u64, "file"),
+            format_bytes(self.total_size()),
+            pluralize(self.report.files.len() as u64, "file")
+        );
+        let mut rows: Vec<Vec<String>> = self
+            .transfers
+            .iter()
+            .map(|t| {
+                let size = t
+                    .size
+                    .map(format_bytes)
+                    .unwrap_or("unknown size".to_string());
+                vec![
+                    t.direction.to_string(),
+                    display_path(&t.path, t.remote.as_ref()),
+                    size,
+                    t.reason.clone(),
+                ]
+            })
+            .collect();
+        rows.extend(self.report.sorted().iter().map(|f| {
+            let reason = f.reason.clone().unwrap_or_default();
+            vec![
+                "skip".yellow().to_string(),
+                display_path(&f.path, f.remote.as_ref()),
+                String::new(),
+                reason,
+            ]
+        }));
+        if !rows.is_empty() {
+            println!();
+            print_fixed_width(&rows, None, None);
+        }
+        Ok(())
+    }
+}
+
+// An error that ends the run with a specific exit code (rather than 1),
+// for commands that scripts check, like 'sdf verify'.
+#[derive(Debug)]
+pub struct ExitError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl fmt::Display for ExitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ExitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_sorts_by_status() {
+        let mut report = TransferReport::new();
+        report.succeeded("data/a.tsv");
+        report.skipped("data/b.tsv", "remote file is identical");
+        report.failed("data/c.tsv", "HTTP 500");
+        let statuses: Vec<Outcome> = report.sorted().iter().map(|f| f.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            vec![Outcome::Failed, Outcome::Skipped, Outcome::Succeeded]
+        );
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["files"][0]["status"], "failed");
+        assert_eq!(json["files"][0]["reason"], "HTTP 500");
+        assert_eq!(
+            report.tsv_rows()[0],
+            ["data/c.tsv", "failed", "HTTP 500", ""]
+        );
+    }
+
+    #[test]
+    fn test_tsv_fields_and_quoting() {
+        assert_eq!(tsv_field(&Outcome::Skipped), "skipped");
+        assert_eq!(tsv_field(&Some(12u64)), "12");
+        assert_eq!(tsv_field(&Some(true)), "true");
+        assert_eq!(tsv_field(&None::<String>), "");
+        assert_eq!(tsv_line(&["a.tsv", "", "x"]), "a.tsv\t\tx");
+        // fields that would break the columns are quoted
+        assert_eq!(
+            tsv_line(&["tab\there", "say \"hi\""]),
+            "\"tab\there\"\t\"say \"\"hi\"\"\""
+        );
+    }
+}
@@ -0,0 +1,138 @@
+// Temporary files for staging operations (e.g. writing the manifest, or
+// downloading a file before it replaces the local copy).
+//
+// All staged files are under <project>/.sdf/tmp, named
+// <label>-<pid>-<sequence>.tmp, and removed when their guard is dropped
+// unless they were moved into place. Files left behind by crashed runs
+// are removed by 'sdf clean'.
+//
+// Note: partial downloads of project files (<file>.partial) are not
+// staged; they are kept on purpose, so interrupted downloads can resume.
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const STAGING_DIR: &str = ".sdf/tmp";
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+pub fn staging_dir(path_context: &Path) -> PathBuf {
+    path_context.join(STAGING_DIR)
+}
+
+#[derive(Debug)]
+pub struct StagedFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl StagedFile {
+    // Reserve a new staged file name; the file itself is not created.
+    pub fn new(path_context: &Path, label: &str) -> Result<Self> {
+        let dir = staging_dir(path_context);
+        fs::create_dir_all(&dir).map_err(|err| {
+            anyhow!(
+                "Could not create staging directory '{}': {}",
+                dir.to_string_lossy(),
+                err
+            )
+        })?;
+        loop {
+            let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+            let name = format!("{}-{}-{}.tmp", label, std::process::id(), sequence);
+            let path = dir.join(name);
+            // skip names left over by a crashed run with the same PID
+            if !path.exists() {
+                return Ok(StagedFile {
+                    path,
+                    persisted: false,
+                });
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Move the staged file into place, replacing destination.
+    pub fn persist(mut self, destination: &Path) -> Result<()> {
+        fs::rename(&self.path, destination).map_err(|err| {
+            anyhow!(
+                "Could not move staged file to '{}': {}",
+                destination.to_string_lossy(),
+                err
+            )
+        })?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            // the file may never have been written
+            let _ = fs::remove_file(&self.path);
+            let _ = fs::remove_file(self.path.with_extension("tmp.partial"));
+        }
+    }
+}
+
+// Remove all staged files, e.g. left over from crashed runs. Returns the
+// removed files.
+//
+// Note: this should not be run while another sdf command is running in
+// the same project.
+pub fn clean(path_context: &Path) -> Result<Vec<PathBuf>> {
+    let dir = staging_dir(path_context);
+    let mut removed = Vec::new();
+    if !dir.exists() {
+        return Ok(removed);
+    }
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_then_fail(path_context: &Path) -> Result<()> {
+        let staged = StagedFile::new(path_context, "manifest")?;
+        fs::write(staged.path(), "staged contents")?;
+        assert!(staged.path().exists());
+        Err(anyhow!("aborted"))
+    }
+
+    #[test]
+    fn test_aborted_operation_leaves_no_temp_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        assert!(write_then_fail(path_context).is_err());
+        let leftover = fs::read_dir(staging_dir(path_context)).unwrap().count();
+        assert_eq!(leftover, 0);
+
+        // persisted files are moved into place
+        let staged = StagedFile::new(path_context, "manifest").unwrap();
+        fs::write(staged.path(), "contents").unwrap();
+        let destination = path_context.join("data_manifest.yml");
+        staged.persist(&destination).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "contents");
+
+        // orphans (e.g. from a crashed run) are swept by clean()
+        let orphan = staging_dir(path_context).join("download-1-0.tmp");
+        fs::write(&orphan, "orphan").unwrap();
+        assert_eq!(clean(path_context).unwrap(), vec![orphan]);
+    }
+}
@@ -0,0 +1,230 @@
+// I/O settings (hashing concurrency and read buffer size), with defaults
+// tuned to the storage the project is on.
+//
+// On Linux, the storage kind is detected from the filesystem type of the
+// mount containing the project (network filesystems like NFS or Lustre),
+// or else from the mount's block device: NVMe devices, other SSDs, and
+// rotational disks. Elsewhere (or if detection fails), files are hashed
+// on every CPU with modest buffers. Explicit --jobs and --buffer-size flags override the
+// defaults; 'sdf --profile' prints the chosen values.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::lib::utils::format_bytes;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+
+// Filesystem types of network and parallel filesystems.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "lustre",
+    "gpfs",
+    "beegfs",
+    "ceph",
+    "glusterfs",
+    "fuse.sshfs",
+    "9p",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageKind {
+    Nvme,
+    Ssd,
+    Hdd,
+    Network,
+    Unknown,
+}
+
+impl fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            StorageKind::Nvme => "NVMe",
+            StorageKind::Ssd => "SSD",
+            StorageKind::Hdd => "rotational disk",
+            StorageKind::Network => "network filesystem",
+            StorageKind::Unknown => "unknown storage",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoSettings {
+    // the number of files hashed concurrently
+    pub jobs: usize,
+    pub buffer_size: usize,
+    pub storage: StorageKind,
+}
+
+impl StorageKind {
+    // Many parallel large reads saturate NVMe; rotational disks and
+    // network filesystems thrash with concurrent readers, so they get
+    // few jobs (with large buffers, to keep reads sequential).
+    pub fn defaults(self) -> IoSettings {
+        let (jobs, buffer_size) = match self {
+            StorageKind::Nvme => (16, MB),
+            StorageKind::Ssd => (8, 256 * KB),
+            StorageKind::Hdd => (2, MB),
+            StorageKind::Network => (2, 4 * MB),
+            StorageKind::Unknown => (available_cpus(), 64 * KB),
+        };
+        IoSettings {
+            jobs,
+            buffer_size,
+            storage: self,
+        }
+    }
+}
+
+fn available_cpus() -> usize {
+    std::thread::available_parallelism()
+        .map(|cpus| cpus.get())
+        .unwrap_or(4)
+}
+
+// Classify storage from the mount's filesystem type, and (for local
+// filesystems) its block device's name and rotational flag.
+pub fn classify(fs_type: &str, device: Option<&str>, rotational: Option<bool>) -> StorageKind {
+    if NETWORK_FILESYSTEMS.contains(&fs_type) {
+        return StorageKind::Network;
+    }
+    match (device, rotational) {
+        (_, Some(true)) => StorageKind::Hdd,
+        (Some(device), _) if device.starts_with("nvme") => StorageKind::Nvme,
+        (_, Some(false)) => StorageKind::Ssd,
+        _ => StorageKind::Unknown,
+    }
+}
+
+// The filesystem type and device number ("MAJOR:MINOR") of the mount
+// containing path, from the contents of /proc/self/mountinfo.
+fn find_mount(mountinfo: &str, path: &Path) -> Option<(String, String)> {
+    let mut best: Option<(usize, String, String)> = None;
+    for line in mountinfo.lines() {
+        let Some((mount, filesystem)) = line.split_once(" - ") else {
+            continue;
+        };
+        let mount: Vec<&str> = mount.split(' ').collect();
+        let (Some(device), Some(mount_point)) = (mount.get(2), mount.get(4)) else {
+            continue;
+        };
+        let fs_type = filesystem.split(' ').next().unwrap_or_default();
+        let depth = Path::new(mount_point).components().count();
+        if path.starts_with(mount_point) && best.as_ref().is_none_or(|(d, _, _)| depth >= *d) {
+            best = Some((depth, fs_type.to_string(), device.to_string()));
+        }
+    }
+    best.map(|(_, fs_type, device)| (fs_type, device))
+}
+
+#[cfg(target_os = "linux")]
+pub fn detect_storage(path: &Path) -> StorageKind {
+    use std::fs;
+    let Ok(path) = path.canonicalize() else {
+        return StorageKind::Unknown;
+    };
+    let Some((fs_type, device)) = fs::read_to_string("/proc/self/mountinfo")
+        .ok()
+        .and_then(|mountinfo| find_mount(&mountinfo, &path))
+    else {
+        return StorageKind::Unknown;
+    };
+    // partitions have no queue/, but their parent (the disk) does
+    let block = fs::canonicalize(format!("/sys/dev/block/{}", device)).ok();
+    let disk = block.and_then(|block| {
+        [block.clone(), block.parent()?.to_path_buf()]
+            .into_iter()
+            .find(|dir| dir.join("queue/rotational").exists())
+    });
+    let name = disk
+        .as_ref()
+        .and_then(|disk| disk.file_name())
+        .map(|name| name.to_string_lossy().to_string());
+    let rotational = disk
+        .and_then(|disk| fs::read_to_string(disk.join("queue/rotational")).ok())
+        .map(|flag| flag.trim() == "1");
+    classify(&fs_type, name.as_deref(), rotational)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_storage(_path: &Path) -> StorageKind {
+    StorageKind::Unknown
+}
+
+static IO_SETTINGS: OnceLock<IoSettings> = OnceLock::new();
+
+// Set the I/O settings for this run: the defaults for the storage at
+// path, unless overridden.
+pub fn configure(path: &Path, jobs: Option<usize>, buffer_size: Option<usize>) -> IoSettings {
+    let defaults = detect_storage(path).defaults();
+    let settings = IoSettings {
+        jobs: jobs.unwrap_or(defaults.jobs),
+        buffer_size: buffer_size.unwrap_or(defaults.buffer_size),
+        ..defaults
+    };
+    IO_SETTINGS.get_or_init(|| settings);
+    settings
+}
+
+// The I/O settings of this run (if not configured, e.g. in tests, the
+// defaults for unknown storage).
+pub fn io_settings() -> IoSettings {
+    *IO_SETTINGS.get_or_init(|| StorageKind::Unknown.defaults())
+}
+
+impl fmt::Display for IoSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hashing jobs, {} read buffers (storage: {})",
+            self.jobs,
+            format_bytes(self.buffer_size as u64),
+            self.storage
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_defaults() {
+        let nvme = classify("ext4", Some("nvme0n1"), Some(false));
+        assert_eq!(nvme, StorageKind::Nvme);
+        assert_eq!(
+            (nvme.defaults().jobs, nvme.defaults().buffer_size),
+            (16, MB)
+        );
+
+        let hdd = classify("xfs", Some("sda"), Some(true));
+        assert_eq!(hdd, StorageKind::Hdd);
+        assert_eq!(hdd.defaults().jobs, 2);
+
+        assert_eq!(classify("ext4", Some("sdb"), Some(false)), StorageKind::Ssd);
+        assert_eq!(classify("nfs4", None, None), StorageKind::Network);
+        assert_eq!(classify("overlay", None, None), StorageKind::Unknown);
+
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime - ext4 /dev/nvme0n1p2 rw\n\
+40 22 0:45 / /home/lab rw,relatime - nfs4 server:/export rw\n";
+        let mount = |path: &str| find_mount(mountinfo, Path::new(path));
+        assert_eq!(
+            mount("/home/lab/project"),
+            Some(("nfs4".to_string(), "0:45".to_string()))
+        );
+        assert_eq!(
+            mount("/tmp/project"),
+            Some(("ext4".to_string(), "259:2".to_string()))
+        );
+        // '/home/labs' is not under '/home/lab'
+        assert_eq!(mount("/home/labs").unwrap().0, "ext4");
+    }
+}
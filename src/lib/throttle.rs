@@ -0,0 +1,160 @@
+// Limiting transfer bandwidth ('sdf --limit-rate'), e.g. so that a push
+// from a shared network does not saturate its uplink.
+//
+// One limiter is shared by all of a run's transfers, uploads and
+// downloads alike, so the limit applies to their total, however many run
+// at once. Each chunk of a transfer's byte stream reserves the next slot
+// of time at the limited rate, and is only passed on once its slot has
+// ended, so that by any time t at most rate × t bytes have been moved.
+// Time a limiter spends idle is not saved up for later bursts.
+
+use anyhow::{anyhow, Result};
+use futures_util::{Stream, StreamExt};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::lib::filter::parse_size;
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    // bytes per second
+    rate: u64,
+    // when the last reserved slot ends
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        RateLimiter {
+            rate: rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    // Wait until bytes can be moved without exceeding the rate.
+    pub async fn acquire(&self, bytes: u64) {
+        let ends = {
+            let mut next = self.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+            *next
+        };
+        tokio::time::sleep_until(ends).await;
+    }
+}
+
+static LIMITER: OnceLock<Option<Arc<RateLimiter>>> = OnceLock::new();
+
+// Limit this run's transfers to rate bytes per second (or, if None, do
+// not limit them).
+pub fn limit_rate(rate: Option<u64>) {
+    LIMITER.get_or_init(|| rate.map(|rate| Arc::new(RateLimiter::new(rate))));
+}
+
+// The limiter of this run's transfers, if they are limited.
+pub fn limiter() -> Option<Arc<RateLimiter>> {
+    LIMITER.get_or_init(|| None).clone()
+}
+
+// A rate in bytes per second, e.g. '2M' (for --limit-rate); units are
+// those of sizes (see filter::parse_size()), and a trailing '/s' is
+// allowed.
+pub fn parse_rate(value: &str) -> Result<u64> {
+    let size = value.trim();
+    let size = size.strip_suffix("/s").unwrap_or(size);
+    match parse_size(size)? {
+        0 => Err(anyhow!("Invalid rate '{}': it must be above zero.", value)),
+        rate => Ok(rate),
+    }
+}
+
+// Pace the chunks of stream with limiter (if any).
+pub fn paced<S, T, E>(
+    stream: S,
+    limiter: Option<Arc<RateLimiter>>,
+) -> impl Stream<Item = std::result::Result<T, E>>
+where
+    S: Stream<Item = std::result::Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    stream.then(move |chunk| {
+        let limiter = limiter.clone();
+        async move {
+            if let (Some(limiter), Ok(bytes)) = (&limiter, &chunk) {
+                limiter.acquire(bytes.as_ref().len() as u64).await;
+            }
+            chunk
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use tokio_util::io::ReaderStream;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("2M").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("512k/s").unwrap(), 512 * 1024);
+        assert_eq!(parse_rate("1.5G").unwrap(), 3 * 512 * 1024 * 1024);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limit_applies_to_concurrent_transfers() {
+        const SIZE: usize = 64 * 1024;
+        const RATE: u64 = 256 * 1024;
+        let server = MockServer::start();
+        let payload = vec![7u8; SIZE];
+        let upload_mock = server.mock(|when, then| {
+            when.method("PUT").path("/upload");
+            then.status(200);
+        });
+        let download_mock = server.mock(|when, then| {
+            when.method(GET).path("/download");
+            then.status(200).body(&payload);
+        });
+        let limiter = Some(Arc::new(RateLimiter::new(RATE)));
+        let client = reqwest::Client::new();
+
+        // two uploads and a download at once: 3 × 64 KiB at 256 KiB/s
+        let started = Instant::now();
+        let upload = || {
+            let body = ReaderStream::new(std::io::Cursor::new(payload.clone()));
+            let body = reqwest::Body::wrap_stream(paced(body, limiter.clone()));
+            client.put(server.url("/upload")).body(body).send()
+        };
+        let download = async {
+            let response = client.get(server.url("/download")).send().await.unwrap();
+            let mut stream = Box::pin(paced(response.bytes_stream(), limiter.clone()));
+            let mut received = 0;
+            while let Some(chunk) = stream.next().await {
+                received += chunk.unwrap().len();
+            }
+            received
+        };
+        let (first, second, received) = tokio::join!(upload(), upload(), download);
+        let elapsed = started.elapsed().as_secs_f64();
+        assert!(first.unwrap().status().is_success());
+        assert!(second.unwrap().status().is_success());
+        assert_eq!(received, SIZE);
+        upload_mock.assert_hits(2);
+        download_mock.assert();
+
+        let expected = (3 * SIZE) as f64 / RATE as f64;
+        assert!(
+            elapsed >= expected * 0.95 && elapsed < expected * 2.0,
+            "took {:.3}s, expected about {:.3}s",
+            elapsed,
+            expected
+        );
+    }
+}
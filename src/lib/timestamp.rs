@@ -0,0 +1,151 @@
+// Timestamps persisted in the manifest.
+//
+// These are always written as UTC in RFC 3339 form (e.g.
+// "2024-01-02T03:04:05Z"), and only converted to local time for display
+// (see utils::format_mod_time()), so a manifest reads the same in every
+// timezone. Fields use them with:
+//
+//   #[serde(with = "crate::lib::timestamp")]
+//
+// or, for optional fields (e.g. DataFile::modified), with
+// "crate::lib::timestamp::option". Timestamps written without an offset
+// (in local time) are migrated to UTC as they are read.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serializer};
+
+// Formats of timestamps written without an offset, which are taken to be
+// in local time.
+const LEGACY_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+pub fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// Parse a persisted timestamp into UTC. Timestamps with an offset are
+// converted; ones without (e.g. written in local time by hand) are
+// migrated by interpreting them in the local timezone.
+pub fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>> {
+    parse_timestamp_in(timestamp, &Local)
+}
+
+// Like parse_timestamp(), taking timestamps without an offset to be in
+// timezone.
+fn parse_timestamp_in<Tz: TimeZone>(timestamp: &str, timezone: &Tz) -> Result<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(timestamp) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    for format in LEGACY_FORMATS {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(timestamp, format) {
+            return timezone
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|local| local.with_timezone(&Utc))
+                .ok_or(anyhow!(
+                    "Timestamp '{}' does not exist in the local timezone.",
+                    timestamp
+                ));
+        }
+    }
+    Err(anyhow!(
+        "Invalid timestamp '{}': expected RFC 3339 (e.g. 2024-01-02T03:04:05Z).",
+        timestamp
+    ))
+}
+
+pub fn serialize<S>(timestamp: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format_timestamp(timestamp))
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp = String::deserialize(deserializer)?;
+    parse_timestamp(&timestamp).map_err(serde::de::Error::custom)
+}
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S>(timestamp: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match timestamp {
+            Some(timestamp) => super::serialize(timestamp, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|timestamp| parse_timestamp(&timestamp).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::{DataCollection, DataFile};
+    use chrono::FixedOffset;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_manifest_timestamps_read_back_identically() {
+        let temp_dir = TempDir::new().unwrap();
+        let path_context = temp_dir.path();
+        std::fs::write(path_context.join("data.tsv"), "data").unwrap();
+        let mut dc = DataCollection::new();
+        let mut data_file = DataFile::new("data.tsv".to_string(), None, path_context)
+            .await
+            .unwrap();
+        let modified = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        data_file.modified = Some(modified);
+        dc.register(data_file).unwrap();
+
+        let written = serde_yaml::to_string(&dc).unwrap();
+        assert!(
+            written.contains("modified: 2024-01-02T03:04:05Z"),
+            "{}",
+            written
+        );
+        let read: DataCollection = serde_yaml::from_str(&written).unwrap();
+        assert_eq!(read.files["data.tsv"].modified, Some(modified));
+        assert_eq!(serde_yaml::to_string(&read).unwrap(), written);
+
+        // offsets are normalized to UTC
+        let offset = written.replace("2024-01-02T03:04:05Z", "2024-01-02T12:04:05+09:00");
+        let read: DataCollection = serde_yaml::from_str(&offset).unwrap();
+        assert_eq!(serde_yaml::to_string(&read).unwrap(), written);
+    }
+
+    #[test]
+    fn test_legacy_timestamps_are_migrated_from_their_timezone() {
+        let expected = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let new_york = FixedOffset::west_opt(5 * 3600).unwrap();
+        assert_eq!(
+            parse_timestamp_in("2024-01-02 12:04:05", &tokyo).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_timestamp_in("2024-01-01T22:04:05", &new_york).unwrap(),
+            expected
+        );
+        // timestamps with an offset do not depend on the timezone
+        for timestamp in ["2024-01-02T03:04:05Z", "2024-01-02T12:04:05+09:00"] {
+            assert_eq!(parse_timestamp_in(timestamp, &tokyo).unwrap(), expected);
+            assert_eq!(parse_timestamp_in(timestamp, &new_york).unwrap(), expected);
+        }
+        assert!(parse_timestamp_in("yesterday", &tokyo).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use reqwest::Method;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::lib::remote::RequestData;
+use crate::lib::utils::{pluralize, print_fixed_width};
+
+// The kinds of remote operations that are accounted separately: reads of
+// the API (e.g. listing a deposition's files), file downloads, and writes
+// (uploads, creating and deleting files).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    List,
+    Get,
+    Put,
+}
+
+impl Operation {
+    pub fn of_api_request(method: &Method) -> Self {
+        if method == Method::GET || method == Method::HEAD {
+            Operation::List
+        } else {
+            Operation::Put
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    usage: BTreeMap<Operation, Usage>,
+    // round-trip times of API requests
+    latencies: Vec<Duration>,
+}
+
+// Per-operation request and byte counts of one remote, for the current
+// command. Clones share the same counts.
+#[derive(Debug, Clone, Default)]
+pub struct UsageCounter(Arc<Mutex<Counts>>);
+
+// Counts are not part of a remote's identity.
+impl PartialEq for UsageCounter {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl UsageCounter {
+    pub fn record(&self, operation: Operation, bytes_sent: u64, bytes_received: u64) {
+        let mut counts = self
+            .0
+            .lock()
+            .expect("Internal Error: usage counter poisoned.");
+        let usage = counts.usage.entry(operation).or_default();
+        usage.requests += 1;
+        usage.bytes_sent += bytes_sent;
+        usage.bytes_received += bytes_received;
+    }
+
+    pub fn record_latency(&self, latency: Duration) {
+        self.0
+            .lock()
+            .expect("Internal Error: usage counter poisoned.")
+            .latencies
+            .push(latency);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<Operation, Usage> {
+        self.0
+            .lock()
+            .expect("Internal Error: usage counter poisoned.")
+            .usage
+            .clone()
+    }
+
+    // Latency statistics of the API requests so far (None if there were
+    // no requests).
+    pub fn latency(&self) -> Option<Latency> {
+        let mut latencies = self
+            .0
+            .lock()
+            .expect("Internal Error: usage counter poisoned.")
+            .latencies
+            .clone();
+        Latency::from_samples(&mut latencies)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Latency {
+    pub calls: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+}
+
+impl Latency {
+    pub fn from_samples(samples: &mut [Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let n = samples.len();
+        let median = if n % 2 == 1 {
+            samples[n / 2]
+        } else {
+            (samples[n / 2 - 1] + samples[n / 2]) / 2
+        };
+        Some(Latency {
+            calls: n,
+            min: samples[0],
+            median,
+            max: samples[n - 1],
+        })
+    }
+}
+
+// The size of a request body, to be accounted as bytes sent.
+pub async fn request_body_size<T: serde::Serialize>(data: &Option<RequestData<T>>) -> Result<u64> {
+    let size = match data {
+        Some(RequestData::Json(json_data)) => serde_json::to_vec(json_data)?.len() as u64,
+        Some(RequestData::Binary(bin_data)) => bin_data.len() as u64,
+        Some(RequestData::File(file)) | Some(RequestData::Stream(file, _)) => {
+            file.metadata().await?.len()
+        }
+        Some(RequestData::Form(form)) => form.len().await?,
+        Some(RequestData::Empty) => 2, // "{}"
+        None => 0,
+    };
+    Ok(size)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RemoteUsage {
+    pub directory: String,
+    pub remote: String,
+    pub operation: Operation,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteLatency {
+    pub directory: String,
+    pub remote: String,
+    pub latency: Latency,
+}
+
+pub fn print_latency(latency: &[RemoteLatency]) {
+    if latency.is_empty() {
+        return;
+    }
+    let ms = |duration: Duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+    let rows: Vec<Vec<String>> = latency
+        .iter()
+        .map(|row| {
+            vec![
+                format!("{} > {}", row.directory, row.remote),
+                pluralize(row.latency.calls as u64, "call"),
+                format!("min {}", ms(row.latency.min)),
+                format!("median {}", ms(row.latency.median)),
+                format!("max {}", ms(row.latency.max)),
+            ]
+        })
+        .collect();
+    println!("\nRemote latency:");
+    print_fixed_width(&rows, None, Some(1));
+}
+
+pub fn print_usage(usage: &[RemoteUsage]) {
+    if usage.is_empty() {
+        return;
+    }
+    let rows: Vec<Vec<String>> = usage
+        .iter()
+        .map(|row| {
+            vec![
+                format!("{} > {}", row.directory, row.remote),
+                format!("{:?}", row.operation).to_lowercase(),
+                pluralize(row.usage.requests, "request"),
+                format!("{} bytes sent", row.usage.bytes_sent),
+                format!("{} bytes received", row.usage.bytes_received),
+            ]
+        })
+        .collect();
+    println!("\nRemote usage:");
+    print_fixed_width(&rows, None, Some(1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_stats() {
+        let counter = UsageCounter::default();
+        assert_eq!(counter.latency(), None);
+        for ms in [30, 10, 20, 40] {
+            counter.record_latency(Duration::from_millis(ms));
+        }
+        let latency = counter.latency().unwrap();
+        assert_eq!(latency.calls, 4);
+        assert_eq!(latency.min, Duration::from_millis(10));
+        assert_eq!(latency.median, Duration::from_millis(25));
+        assert_eq!(latency.max, Duration::from_millis(40));
+    }
+}
@@ -1,22 +1,37 @@
 use anyhow::{anyhow, Result};
-use chrono::{Local, Utc};
+use chrono::format::{Item, Pad, StrftimeItems};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use clap::ValueEnum;
 use colored::*;
 #[allow(unused_imports)]
 use log::{debug, info, trace};
-use md5::Context;
+use reqwest::Url;
+use serde_derive::Serialize;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
 use timeago::Formatter;
+use unicode_normalization::UnicodeNormalization;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::lib::data::StatusEntry;
-use crate::lib::remote::Remote;
+use crate::lib::checksum::{compute_checksums, ChecksumAlgo};
+use crate::lib::data::{LocalStatusCode, MirrorStatus, StatusEntry};
+use crate::lib::md5_cache::{self, Fingerprint};
+use crate::lib::remote::{Remote, RemoteStatusCode};
+use crate::lib::report::{print_tsv, tsv_field, OutputFormat};
+use crate::lib::timestamp::format_timestamp;
 
 pub const ISSUE_URL: &str = "https://github.com/vsbuffalo/scidataflow/issues";
 
+// The narrowest status columns are truncated to, to fit the terminal.
+const MIN_COLUMN_WIDTH: usize = 8;
+const ELLIPSIS: char = '…';
+
 pub fn load_file(path: &PathBuf) -> String {
     let mut file = File::open(path).expect("unable to open file");
     let mut contents = String::new();
@@ -25,6 +40,99 @@ pub fn load_file(path: &PathBuf) -> String {
     contents
 }
 
+// Paths are stored in the manifest in Unicode NFC form, since the same
+// filename may be NFC or NFD depending on the platform (macOS uses NFD).
+pub fn normalize_path(path: &str) -> String {
+    path.nfc().collect()
+}
+
+// Resolve '.' and '..' segments of path, lexically (without following
+// symlinks); leading '..' segments of relative paths are kept, and those of
+// absolute paths dropped (as '/..' is '/').
+pub fn clean_path(path: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match cleaned.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    cleaned.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => cleaned.push(".."),
+            },
+            other => cleaned.push(other),
+        }
+    }
+    cleaned
+}
+
+// The manifest path of path: relative to the project directory
+// path_context (if absolute, it must be under it), with '.' and '..'
+// segments resolved. Paths outside the project are an error, since
+// manifest paths must resolve the same way on any machine.
+pub fn project_path(path_context: &Path, path: &Path) -> Result<String> {
+    let cleaned = clean_path(path);
+    let relative = if cleaned.is_absolute() {
+        let canonical_context = fs::canonicalize(path_context).ok();
+        [Some(clean_path(path_context)), canonical_context]
+            .into_iter()
+            .flatten()
+            .find_map(|context| cleaned.strip_prefix(context).ok().map(Path::to_path_buf))
+    } else {
+        Some(cleaned).filter(|relative| !relative.starts_with(".."))
+    };
+    let relative = relative.ok_or(anyhow!(
+        "Path '{}' is outside the project directory '{}'.",
+        path.to_string_lossy(),
+        path_context.to_string_lossy()
+    ))?;
+    Ok(normalize_path(&relative.to_string_lossy()))
+}
+
+// Whether path is a manifest path as project_path() makes them.
+pub fn is_project_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+// Find the on-disk path of a manifest path, matching each component up to
+// Unicode normalization, e.g. an NFC manifest entry with an NFD file.
+// If there is no match, this is just path_context.join(path).
+pub fn resolve_normalized(path_context: &Path, path: &str) -> PathBuf {
+    let direct = path_context.join(path);
+    if direct.exists() {
+        return direct;
+    }
+    let mut resolved = path_context.to_path_buf();
+    for component in Path::new(path).components() {
+        let candidate = resolved.join(component);
+        if candidate.exists() {
+            resolved = candidate;
+            continue;
+        }
+        let target = normalize_path(&component.as_os_str().to_string_lossy());
+        let dir = if resolved.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            resolved.as_path()
+        };
+        let found = fs::read_dir(dir).ok().and_then(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name())
+                .find(|name| normalize_path(&name.to_string_lossy()) == target)
+        });
+        match found {
+            Some(name) => resolved.push(name),
+            None => return direct,
+        }
+    }
+    resolved
+}
+
 pub fn ensure_directory(dir: &Path) -> Result<()> {
     let path = Path::new(dir);
     if path.is_dir() {
@@ -51,95 +159,279 @@ pub fn ensure_exists(path: &Path) -> Result<()> {
     }
 }
 
-/// Compute the MD5 of a file returning None if the file is empty.
+/// Compute the MD5 of a file returning None if the file does not exist.
+/// Unchanged files (by size and modification time) use the MD5 cache.
 pub async fn compute_md5(file_path: &Path) -> Result<Option<String>> {
-    const BUFFER_SIZE: usize = 1024;
+    let cached = Fingerprint::of(file_path)
+        .and_then(|fingerprint| md5_cache::cached_md5(file_path, &fingerprint));
+    match cached {
+        Some(md5) => Ok(Some(md5)),
+        None => rehash_md5(file_path).await,
+    }
+}
+
+/// Compute one checksum of a file, returning None if the file does not
+/// exist. Unchanged files use the MD5 cache (see compute_md5()), which
+/// keeps other checksums too.
+pub async fn compute_checksum(file_path: &Path, algo: ChecksumAlgo) -> Result<Option<String>> {
+    if algo.is_md5() {
+        return compute_md5(file_path).await;
+    }
+    let cached = Fingerprint::of(file_path)
+        .and_then(|fingerprint| md5_cache::cached_checksum(file_path, &fingerprint, algo));
+    match cached {
+        Some(checksum) => Ok(Some(checksum)),
+        None => rehash_checksum(file_path, algo).await,
+    }
+}
 
-    let mut file = match File::open(file_path) {
-        Ok(file) => file,
-        Err(_) => return Ok(None),
+/// Like compute_checksum(), but always reads the file (updating the MD5
+/// cache, with the MD5 computed in the same pass).
+pub async fn rehash_checksum(file_path: &Path, algo: ChecksumAlgo) -> Result<Option<String>> {
+    if algo.is_md5() {
+        return rehash_md5(file_path).await;
+    }
+    let fingerprint = Fingerprint::of(file_path);
+    let Some(checksums) = compute_checksums(file_path, &[algo]).await? else {
+        return Ok(None);
     };
+    if let Some(fingerprint) = fingerprint {
+        md5_cache::record_checksums(file_path, fingerprint, &checksums);
+    }
+    Ok(checksums.get(&algo).cloned())
+}
 
-    let mut buffer = [0; BUFFER_SIZE];
-    let mut md5 = Context::new();
+/// Like compute_md5(), but always reads the file (updating the MD5 cache).
+pub async fn rehash_md5(file_path: &Path) -> Result<Option<String>> {
+    // fingerprint before hashing, so a write during hashing is not cached
+    let fingerprint = Fingerprint::of(file_path);
+    let checksums = compute_checksums(file_path, &[]).await?;
+    let md5 = checksums.and_then(|mut checksums| checksums.remove(&ChecksumAlgo::Md5));
+    if let (Some(fingerprint), Some(md5)) = (fingerprint, &md5) {
+        md5_cache::record(file_path, fingerprint, md5);
+    }
+    Ok(md5)
+}
+// Print rows of columns, padding each column to its widest entry.
+pub fn print_fixed_width(rows: &[Vec<String>], nspaces: Option<usize>, indent: Option<usize>) {
+    let indent = indent.unwrap_or(0);
+    let nspaces = nspaces.unwrap_or(6);
 
-    loop {
-        let bytes_read = match file.read(&mut buffer) {
-            Ok(0) => break, // EOF
-            Ok(bytes_read) => bytes_read,
-            Err(e) => return Err(anyhow!("I/O reading file: {:?}", e)),
-        };
+    let max_cols = rows.iter().map(|cols| cols.len()).max().unwrap_or(0);
+    let mut max_lengths = vec![0; max_cols];
+    for cols in rows {
+        for (i, col) in cols.iter().enumerate() {
+            max_lengths[i] = max_lengths[i].max(col.len());
+        }
+    }
+
+    let spacer = " ".repeat(nspaces);
+    for cols in rows {
+        let fixed_row: Vec<String> = cols
+            .iter()
+            .enumerate()
+            .map(|(i, col)| format!("{:width$}", col, width = max_lengths[i]))
+            .collect();
+        println!(
+            "{}{}",
+            " ".repeat(indent),
+            fixed_row.join(&spacer).trim_end()
+        );
+    }
+}
 
-        md5.consume(&buffer[..bytes_read]);
+// The display width of text (e.g. 2 for each CJK character, and 0 for
+// combining characters), rather than its length in bytes.
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+// text, padded with spaces to a display width of width.
+fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+// Truncate text to a display width of at most max_width, replacing what
+// is cut with an ellipsis: at the end, or with from_left, at the start
+// (e.g. to keep a path's file name visible).
+pub fn truncate_width(text: &str, max_width: usize, from_left: bool) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+    let budget = max_width.saturating_sub(1);
+    let chars: Box<dyn Iterator<Item = char>> = if from_left {
+        Box::new(text.chars().rev())
+    } else {
+        Box::new(text.chars())
+    };
+    let mut kept = Vec::new();
+    let mut width = 0;
+    for c in chars {
+        let char_width = c.width().unwrap_or(0);
+        if width + char_width > budget {
+            break;
+        }
+        width += char_width;
+        kept.push(c);
+    }
+    if from_left {
+        // combining characters are dropped with the character they follow
+        while kept.last().is_some_and(|c| c.width() == Some(0)) {
+            kept.pop();
+        }
+        let kept: String = kept.into_iter().rev().collect();
+        format!("{}{}", ELLIPSIS, kept)
+    } else {
+        let kept: String = kept.into_iter().collect();
+        format!("{}{}", kept, ELLIPSIS)
     }
+}
 
-    let result = md5.compute();
-    Ok(Some(format!("{:x}", result)))
+// The widest each of these columns is shown, to fit within available:
+// columns narrower than an even share keep their width, and the wider
+// ones split what remains (down to MIN_COLUMN_WIDTH).
+pub fn column_caps(widths: &[usize], available: usize) -> Vec<usize> {
+    let mut caps = widths.to_vec();
+    let mut remaining = available;
+    let mut wide: Vec<usize> = (0..widths.len()).collect();
+    while !wide.is_empty() {
+        let share = remaining / wide.len();
+        let (narrow, still_wide): (Vec<usize>, Vec<usize>) =
+            wide.iter().partition(|&&i| widths[i] <= share);
+        if narrow.is_empty() {
+            for i in still_wide {
+                caps[i] = share.max(MIN_COLUMN_WIDTH);
+            }
+            break;
+        }
+        for i in narrow {
+            remaining -= widths[i];
+        }
+        wide = still_wide;
+    }
+    caps
 }
-/*
-   pub fn print_fixed_width(rows: HashMap<String, Vec<StatusEntry>>, nspaces: Option<usize>, indent: Option<usize>, color: bool) {
-   let indent = indent.unwrap_or(0);
-   let nspaces = nspaces.unwrap_or(6);
 
-   let max_cols = rows.values()
-   .flat_map(|v| v.iter())
-   .filter_map(|entry| {
-   match &entry.cols {
-   None => None,
-   Some(cols) => Some(cols.len())
-   }
-   })
-   .max()
-   .unwrap_or(0);
+// The width of the terminal stdout is, if it is one.
+pub fn terminal_width() -> Option<usize> {
+    console::Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+}
 
-   let mut max_lengths = vec![0; max_cols];
+// OSC 8 terminal hyperlink: displays text, linking to url.
+pub fn hyperlink(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
 
-// compute max lengths across all rows
-for entry in rows.values().flat_map(|v| v.iter()) {
-if let Some(cols) = &entry.cols {
-for (i, col) in cols.iter().enumerate() {
-max_lengths[i] = max_lengths[i].max(col.width());
+// Whether output is colored. This is the one place table printing checks:
+// colors are off with --no-color (which sets colored's override), if
+// NO_COLOR is set, or if stdout is not a terminal (unless CLICOLOR_FORCE
+// is set).
+pub fn color_enabled() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
 }
+
+// Hyperlinks are only emitted if requested, stdout is a terminal, and
+// colors have not been disabled.
+pub fn hyperlinks_enabled(requested: bool) -> bool {
+    requested && color_enabled() && std::io::stdout().is_terminal()
 }
+
+// What status hyperlinks point to: files are linked relative to the
+// project directory, and directory headings (keyed by their displayed
+// name) to their remote's web page.
+#[derive(Debug, Default)]
+pub struct StatusLinks {
+    pub path_context: PathBuf,
+    pub directories: HashMap<String, String>,
+    pub remote_urls: HashMap<String, String>,
 }
-// print status table
-let mut keys: Vec<&String> = rows.keys().collect();
-keys.sort();
-for (key, value) in &rows {
-let pretty_key = if color { key.bold().to_string() } else { key.clone() };
-println!("[{}]", pretty_key);
 
-// Print the rows with the correct widths
-for row in value {
-let mut fixed_row = Vec::new();
-let tracked = &row.tracked;
-let local_status = &row.local_status;
-let remote_status = &row.remote_status;
-if let Some(cols) = &row.cols {
-for (i, col) in cols.iter().enumerate() {
-// push a fixed-width column to vector
-let fixed_col = format!("{:width$}", col, width = max_lengths[i]);
-fixed_row.push(fixed_col);
+impl StatusLinks {
+    fn file_url(&self, key: &str, name: &str) -> Option<String> {
+        let directory = self.directories.get(key).map_or(key, |dir| dir.as_str());
+        let path = self.path_context.join(directory).join(name);
+        Url::from_file_path(path).ok().map(|url| url.to_string())
+    }
 }
+
+// The order directory groups are printed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum DirSort {
+    #[default]
+    Name,
+    // largest total size first
+    Size,
+    // worst status first (e.g. directories with modified files)
+    Status,
 }
-let spacer = " ".repeat(nspaces);
-let status_line = fixed_row.join(&spacer);
-println!("{}{}", " ".repeat(indent), status_line);
+
+// Order the directory keys of rows by dir_sort; ties are broken by name.
+pub fn sort_directories(
+    rows: &BTreeMap<String, Vec<StatusEntry>>,
+    dir_sort: DirSort,
+) -> Vec<&String> {
+    let mut dir_keys: Vec<&String> = rows.keys().collect();
+    match dir_sort {
+        DirSort::Name => dir_keys.sort(),
+        DirSort::Size => {
+            let size = |key: &String| -> u64 { rows[key].iter().filter_map(|e| e.size()).sum() };
+            dir_keys.sort_by(|a, b| size(b).cmp(&size(a)).then(a.cmp(b)));
+        }
+        DirSort::Status => {
+            let severity = |key: &String| rows[key].iter().map(|e| e.severity()).max();
+            dir_keys.sort_by(|a, b| severity(b).cmp(&severity(a)).then(a.cmp(b)));
+        }
+    }
+    dir_keys
 }
-println!();
+
+// How the status table is displayed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatusDisplay {
+    pub color: bool,
+    // include files not in the manifest
+    pub all: bool,
+    pub dir_sort: DirSort,
+    // color directory headers by their share of the total size
+    pub tree_size: bool,
+    // the widest a column is shown, truncating longer values (by default,
+    // columns are fit to terminal_width, if known)
+    pub max_column_width: Option<usize>,
+    pub terminal_width: Option<usize>,
 }
+
+// The header color for a directory holding this share of the total
+// size, from cool (small) to warm (large).
+pub fn size_heat_color(share: f64) -> Color {
+    match share {
+        s if s >= 0.5 => Color::Red,
+        s if s >= 0.25 => Color::Yellow,
+        s if s >= 0.1 => Color::Cyan,
+        _ => Color::Blue,
+    }
 }
-*/
+
 // More specialized version of print_fixed_width() for statuses.
 // Handles coloring, manual annotation, etc
-pub fn print_fixed_width_status(
-    rows: BTreeMap<String, Vec<StatusEntry>>,
+pub fn format_status_lines(
+    rows: &BTreeMap<String, Vec<StatusEntry>>,
     nspaces: Option<usize>,
     indent: Option<usize>,
-    color: bool,
-    all: bool,
-) {
+    display: &StatusDisplay,
+    links: Option<&StatusLinks>,
+) -> Vec<String> {
     //debug!("rows: {:?}", rows);
+    let StatusDisplay {
+        color,
+        all,
+        dir_sort,
+        tree_size,
+        max_column_width,
+        terminal_width,
+    } = *display;
     let indent = indent.unwrap_or(0);
     let nspaces = nspaces.unwrap_or(6);
 
@@ -155,26 +447,47 @@ pub fn print_fixed_width_status(
 
     let mut max_lengths = vec![0; max_cols];
 
-    // compute max lengths across all rows
+    // compute max display widths across all rows
     for status in rows.values().flat_map(|v| v.iter()) {
         let cols = status.columns(abbrev);
         for (i, col) in cols.iter().enumerate() {
-            max_lengths[i] = max_lengths[i].max(col.len()); // Assuming col is a string
+            max_lengths[i] = max_lengths[i].max(display_width(col));
         }
     }
+    // then cap them (the leading space, and the spacers, take room too)
+    let caps = match (max_column_width, terminal_width) {
+        (Some(max_width), _) => vec![max_width; max_cols],
+        (None, Some(terminal_width)) => {
+            let spacing = indent + 1 + nspaces * max_cols.saturating_sub(1);
+            column_caps(&max_lengths, terminal_width.saturating_sub(spacing))
+        }
+        (None, None) => max_lengths.clone(),
+    };
+    for (width, cap) in max_lengths.iter_mut().zip(caps) {
+        *width = (*width).min(cap);
+    }
+
+    let dir_size = |key: &String| -> u64 { rows[key].iter().filter_map(|e| e.size()).sum() };
+    let total_size: u64 = rows.keys().map(dir_size).sum();
 
     // print status table
-    let mut dir_keys: Vec<&String> = rows.keys().collect();
-    dir_keys.sort();
-    for key in dir_keys {
+    let mut lines = Vec::new();
+    for key in sort_directories(rows, dir_sort) {
         let statuses = &rows[key];
         let pretty_key = if key.is_empty() { "." } else { key };
-        let prettier_key = if color {
+        let pretty_key = match links.and_then(|links| links.remote_urls.get(key)) {
+            Some(url) => hyperlink(pretty_key, url),
+            None => pretty_key.to_string(),
+        };
+        let prettier_key = if color && tree_size && total_size > 0 {
+            let share = dir_size(key) as f64 / total_size as f64;
+            pretty_key.bold().color(size_heat_color(share)).to_string()
+        } else if color {
             pretty_key.bold().to_string()
         } else {
-            pretty_key.to_string()
+            pretty_key
         };
-        println!("[{}]", prettier_key);
+        lines.push(format!("[{}]", prettier_key));
 
         // Print the rows with the correct widths
         for status in statuses {
@@ -185,9 +498,17 @@ pub fn print_fixed_width_status(
             let cols = status.columns(abbrev);
             let mut fixed_row = Vec::new();
             for (i, col) in cols.iter().enumerate() {
-                // push a fixed-width column to vector
+                // push a fixed-width column to vector (file paths are
+                // truncated from the left, keeping the file name)
                 let spacer = if i == 0 { " " } else { "" };
-                let fixed_col = format!("{}{:width$}", spacer, col, width = max_lengths[i]);
+                let shown = truncate_width(col, max_lengths[i], i == 0);
+                let fixed_col = format!("{}{}", spacer, pad(&shown, max_lengths[i]));
+                // link only the file name, not the padding
+                let file_url = links.filter(|_| i == 0).and_then(|l| l.file_url(key, col));
+                let fixed_col = match file_url {
+                    Some(url) => fixed_col.replacen(shown.as_str(), &hyperlink(&shown, &url), 1),
+                    None => fixed_col,
+                };
                 fixed_row.push(fixed_col);
             }
             let spacer = " ".repeat(nspaces);
@@ -197,29 +518,25 @@ pub fn print_fixed_width_status(
             } else {
                 line.to_string()
             };
-            println!("{}{}", " ".repeat(indent), status_line);
+            lines.push(format!("{}{}", " ".repeat(indent), status_line));
         }
-        println!();
+        lines.push(String::new());
     }
+    lines
 }
 
-/* fn organize_by_dir(rows: Vec<StatusEntry>) -> BTreeMap<String, Vec<StatusEntry>> {
-let mut dir_map: BTreeMap<String, Vec<StatusEntry>> = BTreeMap::new();
-
-for entry in rows {
-if let Some(cols) = &entry.cols {
-if let Some(first_elem) = cols.first() {
-let path = Path::new(first_elem);
-if let Some(parent_path) = path.parent() {
-let parent_dir = parent_path.to_string_lossy().into_owned();
-dir_map.entry(parent_dir).or_default().push(entry);
-}
-}
-}
-}
-dir_map
+// Print the status lines (see format_status_lines()).
+pub fn print_fixed_width_status(
+    rows: BTreeMap<String, Vec<StatusEntry>>,
+    nspaces: Option<usize>,
+    indent: Option<usize>,
+    display: &StatusDisplay,
+    links: Option<&StatusLinks>,
+) {
+    for line in format_status_lines(&rows, nspaces, indent, display, links) {
+        println!("{}", line);
+    }
 }
-*/
 
 pub fn pluralize<T: Into<u64>>(count: T, noun: &str) -> String {
     let count = count.into();
@@ -230,6 +547,16 @@ pub fn pluralize<T: Into<u64>>(count: T, noun: &str) -> String {
     }
 }
 
+// Ask a yes/no question on the terminal; anything but a yes (including no
+// answer, e.g. if stdin is closed) is a no.
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 struct FileCounts {
     local: u64,
     remote: u64,
@@ -293,8 +620,12 @@ fn get_counts(rows: &BTreeMap<String, Vec<StatusEntry>>) -> Result<FileCounts> {
 
 pub fn print_status(
     rows: BTreeMap<String, Vec<StatusEntry>>,
-    remote: Option<&HashMap<String, Remote>>,
+    remotes: Option<&HashMap<String, Vec<&Remote>>>,
     all: bool,
+    hyperlinks: Option<&Path>,
+    dir_sort: DirSort,
+    tree_size: bool,
+    max_column_width: Option<usize>,
 ) {
     println!("{}", "Project data status:".bold());
     let counts = get_counts(&rows).expect("Internal Error: get_counts() panicked.");
@@ -307,14 +638,25 @@ pub fn print_status(
         pluralize(counts.total, "file")
     );
 
-    // this brings the remote name (if there is a corresponding remote) into
-    // the key, so the linked remote can be displayed in the status
-    let rows_by_dir: BTreeMap<String, Vec<StatusEntry>> = match remote {
+    // this brings the remote names (if there are corresponding remotes)
+    // into the key, so the linked remotes can be displayed in the status
+    let mut links = hyperlinks.map(|path_context| StatusLinks {
+        path_context: path_context.to_path_buf(),
+        ..Default::default()
+    });
+    let rows_by_dir: BTreeMap<String, Vec<StatusEntry>> = match remotes {
         Some(remote_map) => {
             let mut new_map = BTreeMap::new();
             for (directory, statuses) in rows {
-                if let Some(remote) = remote_map.get(&directory) {
-                    let new_key = format!("{} > {}", directory, remote.name());
+                if let Some(remotes) = remote_map.get(&directory).filter(|r| !r.is_empty()) {
+                    let names: Vec<&str> = remotes.iter().map(|remote| remote.name()).collect();
+                    let new_key = format!("{} > {}", directory, names.join(", "));
+                    if let Some(links) = links.as_mut() {
+                        links.directories.insert(new_key.clone(), directory.clone());
+                        if let Some(url) = remotes[0].web_url() {
+                            links.remote_urls.insert(new_key.clone(), url);
+                        }
+                    }
                     new_map.insert(new_key, statuses);
                 } else {
                     new_map.insert(directory, statuses);
@@ -325,41 +667,376 @@ pub fn print_status(
         None => rows,
     };
 
-    print_fixed_width_status(rows_by_dir, None, None, true, all);
+    let summary = status_summary(&rows_by_dir);
+    let display = StatusDisplay {
+        color: color_enabled(),
+        all,
+        dir_sort,
+        tree_size,
+        max_column_width,
+        terminal_width: terminal_width(),
+    };
+    print_fixed_width_status(rows_by_dir, None, None, &display, links.as_ref());
+    println!();
+    for line in summary.lines() {
+        println!("{}", line);
+    }
+}
+
+// Totals over the status rows, for the footer of 'sdf status'.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatusSummary {
+    // files in the manifest, and how many of them are tracked by a remote
+    pub files: u64,
+    pub tracked: u64,
+    // files only on a remote
+    pub remote_only: u64,
+    // the total size of the files in the manifest
+    pub size: u64,
+    // the number of files in each state, by label
+    pub local: BTreeMap<&'static str, u64>,
+    pub remote: BTreeMap<&'static str, u64>,
+    // and on each mirror, by its name
+    pub mirrors: BTreeMap<String, BTreeMap<&'static str, u64>>,
 }
 
+pub fn status_summary(rows: &BTreeMap<String, Vec<StatusEntry>>) -> StatusSummary {
+    let mut summary = StatusSummary::default();
+    for entry in rows.values().flatten() {
+        if let Some(status) = &entry.remote_status {
+            *summary.remote.entry(status.label()).or_default() += 1;
+        }
+        for mirror in &entry.mirrors {
+            if let Some(status) = &mirror.remote_status {
+                let states = summary.mirrors.entry(mirror.remote.clone()).or_default();
+                *states.entry(status.label()).or_default() += 1;
+            }
+        }
+        let Some(status) = &entry.local_status else {
+            summary.remote_only += 1;
+            continue;
+        };
+        *summary.local.entry(status.label()).or_default() += 1;
+        summary.files += 1;
+        summary.tracked += (entry.tracked == Some(true)) as u64;
+        summary.size += entry.manifest_size.unwrap_or(0);
+    }
+    summary
+}
+
+impl StatusSummary {
+    pub fn lines(&self) -> Vec<String> {
+        let counts = |states: &BTreeMap<&str, u64>| {
+            states
+                .iter()
+                .map(|(label, count)| format!("{} {}", count, label))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut total = format!(
+            "{} ({} tracked, {} untracked), {} total",
+            pluralize(self.files, "file"),
+            self.tracked,
+            self.files - self.tracked,
+            format_bytes(self.size)
+        );
+        if self.remote_only > 0 {
+            total.push_str(&format!(
+                "; {} only on remotes",
+                pluralize(self.remote_only, "file")
+            ));
+        }
+        let mut lines = vec![format!("{}.", total)];
+        if !self.local.is_empty() {
+            lines.push(format!("local: {}", counts(&self.local)));
+        }
+        if !self.remote.is_empty() {
+            lines.push(format!("remote: {}", counts(&self.remote)));
+        }
+        for (remote, states) in &self.mirrors {
+            lines.push(format!("mirror {}: {}", remote, counts(states)));
+        }
+        lines
+    }
+}
+
+// A status entry, as output by 'sdf status --format json' (or tsv).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusRecord {
+    pub path: String,
+    pub tracked: Option<bool>,
+    pub local_status: Option<LocalStatusCode>,
+    pub remote_status: Option<RemoteStatusCode>,
+    pub size: Option<u64>,
+    // the local file's MD5 (or if there is none, the manifest's or remote's)
+    pub md5: Option<String>,
+    // the local file's modification time (RFC 3339, in UTC)
+    pub mtime: Option<String>,
+    pub remote: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<MirrorStatus>,
+}
+
+// The columns of 'sdf status --format tsv' (mirrors are only in the JSON).
+pub const STATUS_COLUMNS: [&str; 8] = [
+    "path",
+    "tracked",
+    "local_status",
+    "remote_status",
+    "md5",
+    "size",
+    "mtime",
+    "remote",
+];
+
+impl StatusRecord {
+    pub fn tsv_row(&self) -> Vec<String> {
+        vec![
+            self.path.clone(),
+            tsv_field(&self.tracked),
+            tsv_field(&self.local_status),
+            tsv_field(&self.remote_status),
+            tsv_field(&self.md5),
+            tsv_field(&self.size),
+            tsv_field(&self.mtime),
+            tsv_field(&self.remote),
+        ]
+    }
+}
+
+// The status rows as records, skipping files not in the manifest (as
+// the status table does) unless all.
+pub fn status_records(rows: &BTreeMap<String, Vec<StatusEntry>>, all: bool) -> Vec<StatusRecord> {
+    rows.iter()
+        .flat_map(|(directory, entries)| entries.iter().map(move |entry| (directory, entry)))
+        .filter(|(_, entry)| all || entry.local_status.is_some())
+        .map(|(directory, entry)| status_record(directory, entry))
+        .collect()
+}
+
+pub fn status_record(directory: &str, entry: &StatusEntry) -> StatusRecord {
+    StatusRecord {
+        path: if directory.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", directory, entry.name)
+        },
+        tracked: entry.tracked,
+        local_status: entry.local_status.clone(),
+        remote_status: entry.remote_status.clone(),
+        size: entry.size(),
+        md5: entry
+            .local_md5
+            .clone()
+            .or_else(|| entry.manifest_md5.clone())
+            .or_else(|| entry.remote_md5.clone()),
+        mtime: entry.local_mod_time.as_ref().map(format_timestamp),
+        remote: entry.remote_service.clone(),
+        mirrors: entry.mirrors.clone(),
+    }
+}
+
+// How status rows are grouped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    #[default]
+    Directory,
+    Extension,
+}
+
+// Compression suffixes, which are kept with the preceding extension
+// (e.g. "vcf.gz" rather than "gz").
+const COMPRESSION_EXTENSIONS: &[&str] = &["gz", "bgz", "bz2", "xz", "zst", "zip"];
+
+pub fn file_extension(name: &str) -> String {
+    let basename = Path::new(name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // leading dots (hidden files) are not extensions
+    let parts: Vec<&str> = basename.trim_start_matches('.').split('.').collect();
+    match parts.as_slice() {
+        [] | [_] => "".to_string(),
+        [.., ext, compression]
+            if COMPRESSION_EXTENSIONS.contains(compression) && parts.len() > 2 =>
+        {
+            format!("{}.{}", ext, compression)
+        }
+        [.., ext] => ext.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtensionSummary {
+    pub extension: String,
+    pub files: u64,
+    pub size: u64,
+}
+
+// Summarize the number of files and their total size by file extension,
+// largest first. Sizes are from the manifest (or remote, for files only
+// on the remote, which are only included if all is set).
+pub fn group_by_extension(
+    rows: &BTreeMap<String, Vec<StatusEntry>>,
+    all: bool,
+) -> Vec<ExtensionSummary> {
+    let mut groups: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for status in rows.values().flat_map(|v| v.iter()) {
+        if status.local_status.is_none() && !all {
+            continue;
+        }
+        let size = status.manifest_size.or(status.remote_size).unwrap_or(0);
+        let group = groups.entry(file_extension(&status.name)).or_default();
+        group.0 += 1;
+        group.1 += size;
+    }
+    let mut summaries: Vec<ExtensionSummary> = groups
+        .into_iter()
+        .map(|(extension, (files, size))| ExtensionSummary {
+            extension,
+            files,
+            size,
+        })
+        .collect();
+    summaries.sort_by(|a, b| b.size.cmp(&a.size).then(a.extension.cmp(&b.extension)));
+    summaries
+}
+
+pub fn print_extension_summary(summaries: &[ExtensionSummary], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(summaries)?);
+            return Ok(());
+        }
+        OutputFormat::Tsv => {
+            let rows: Vec<Vec<String>> = summaries
+                .iter()
+                .map(|summary| {
+                    vec![
+                        summary.extension.clone(),
+                        summary.files.to_string(),
+                        summary.size.to_string(),
+                    ]
+                })
+                .collect();
+            print_tsv(&["extension", "files", "size"], &rows);
+            return Ok(());
+        }
+        OutputFormat::Table => (),
+    }
+    println!("{}", "Project data by file extension:".bold());
+    let rows: Vec<Vec<String>> = summaries
+        .iter()
+        .map(|summary| {
+            let extension = if summary.extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", summary.extension)
+            };
+            vec![
+                extension,
+                pluralize(summary.files, "file"),
+                format_bytes(summary.size),
+            ]
+        })
+        .collect();
+    print_fixed_width(&rows, None, Some(1));
+    Ok(())
+}
+
+// Format a size with binary units (1 KiB = 1024 bytes).
 pub fn format_bytes(size: u64) -> String {
-    const BYTES_IN_KB: f64 = 1024.0;
-    const BYTES_IN_MB: f64 = BYTES_IN_KB * 1024.0;
-    const BYTES_IN_GB: f64 = BYTES_IN_MB * 1024.0;
-    const BYTES_IN_TB: f64 = BYTES_IN_GB * 1024.0;
-    const BYTES_IN_PB: f64 = BYTES_IN_TB * 1024.0;
-    let size = size as f64;
-
-    if size < BYTES_IN_MB {
-        format!("{:.2} MB", size / BYTES_IN_KB)
-    } else if size < BYTES_IN_GB {
-        format!("{:.2} MB", size / BYTES_IN_MB)
-    } else if size < BYTES_IN_TB {
-        format!("{:.2} GB", size / BYTES_IN_GB)
-    } else if size < BYTES_IN_PB {
-        format!("{:.2} TB", size / BYTES_IN_TB)
-    } else {
-        format!("{:.2} PB", size / BYTES_IN_PB)
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    // the unit is chosen after rounding, so e.g. 1 MiB - 1 byte is
+    // "1.00 MiB", not "1024.00 KiB"
+    while (value * 100.0).round() / 100.0 >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
     }
+    format!("{:.2} {}", value, UNITS[unit])
 }
 
-pub fn format_mod_time(mod_time: chrono::DateTime<Utc>) -> String {
-    let now = Utc::now();
-    let duration_since_mod = now.signed_duration_since(mod_time);
+// How modification times are displayed: by default in local time, with
+// its UTC offset, so that output shared between timezones is unambiguous.
+// 'sdf --time-format' (or SDF_TIME_FORMAT) sets the strftime format, and
+// 'sdf --utc' (or SDF_UTC=1) shows times in UTC, e.g. for reproducible
+// output in CI.
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
 
-    // convert chrono::Duration to std::time::Duration
-    let std_duration = std::time::Duration::new(duration_since_mod.num_seconds() as u64, 0);
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeDisplay {
+    pub format: String,
+    pub utc: bool,
+}
 
-    let formatter = Formatter::new();
-    let local_time = mod_time.with_timezone(&Local);
-    let timestamp = local_time.format("%Y-%m-%d %l:%M%p").to_string();
-    format!("{} ({})", timestamp, formatter.convert(std_duration))
+impl Default for TimeDisplay {
+    fn default() -> Self {
+        TimeDisplay {
+            format: DEFAULT_TIME_FORMAT.to_string(),
+            utc: false,
+        }
+    }
+}
+
+static TIME_DISPLAY: OnceLock<TimeDisplay> = OnceLock::new();
+
+// A strftime format, checked so that formatting with it cannot fail.
+pub fn parse_time_format(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(anyhow!(
+            "Invalid time format '{}' (see 'man strftime').",
+            format
+        ));
+    }
+    Ok(format.to_string())
+}
+
+// Set how this run displays times; unset options fall back to the
+// environment, then the defaults.
+pub fn configure_time_display(format: Option<&str>, utc: bool) -> Result<TimeDisplay> {
+    let from_env = env::var("SDF_TIME_FORMAT").ok().filter(|f| !f.is_empty());
+    let format = match (format, from_env) {
+        (Some(format), _) => parse_time_format(format)?,
+        (None, Some(format)) => parse_time_format(&format)
+            .map_err(|_| anyhow!("Invalid SDF_TIME_FORMAT '{}' (see 'man strftime').", format))?,
+        (None, None) => DEFAULT_TIME_FORMAT.to_string(),
+    };
+    let utc = utc || env::var("SDF_UTC").is_ok_and(|utc| !matches!(utc.as_str(), "" | "0"));
+    let display = TimeDisplay { format, utc };
+    Ok(TIME_DISPLAY.get_or_init(|| display).clone())
+}
+
+// Format a time in tz, followed by how long before now it was (e.g.
+// "2024-01-02 03:04:05 +0000 (2 hours ago)").
+pub fn format_time_in<Tz>(time: DateTime<Utc>, now: DateTime<Utc>, tz: &Tz, format: &str) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    // times in the future (e.g. from clock skew) are "now"
+    let age = now.signed_duration_since(time).to_std().unwrap_or_default();
+    // without the space padding of e.g. single-digit hours (%l)
+    let items = StrftimeItems::new(format).map(|item| match item {
+        Item::Numeric(numeric, Pad::Space) => Item::Numeric(numeric, Pad::None),
+        item => item,
+    });
+    let timestamp = time.with_timezone(tz).format_with_items(items);
+    format!("{} ({})", timestamp, Formatter::new().convert(age))
+}
+
+pub fn format_mod_time(mod_time: DateTime<Utc>) -> String {
+    let display = TIME_DISPLAY.get_or_init(TimeDisplay::default);
+    let now = Utc::now();
+    if display.utc {
+        format_time_in(mod_time, now, &Utc, &display.format)
+    } else {
+        format_time_in(mod_time, now, &Local, &display.format)
+    }
 }
 
 pub fn shorten(hash: &str, abbrev: Option<i32>) -> String {
@@ -384,3 +1061,295 @@ pub fn md5_status(
         _ => "".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_path() {
+        let project = Path::new("/home/joan/project");
+        let manifest_path = |path: &str| project_path(project, Path::new(path));
+        assert_eq!(manifest_path("data/a.tsv").unwrap(), "data/a.tsv");
+        assert_eq!(
+            manifest_path("/home/joan/project/data/a.tsv").unwrap(),
+            "data/a.tsv"
+        );
+        assert_eq!(manifest_path("./data//raw/../a.tsv").unwrap(), "data/a.tsv");
+        assert_eq!(
+            manifest_path("/home/joan/../joan/project/./data/a.tsv").unwrap(),
+            "data/a.tsv"
+        );
+        for outside in ["../outside.tsv", "data/../../a.tsv", "/home/joan/a.tsv"] {
+            let err = manifest_path(outside).unwrap_err();
+            assert!(
+                err.to_string().contains("is outside the project"),
+                "{}",
+                err
+            );
+        }
+
+        assert!(is_project_path("data/a.tsv"));
+        for path in ["", "/data/a.tsv", "./data/a.tsv", "data/../a.tsv"] {
+            assert!(!is_project_path(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_format_bytes_units() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.00 KiB");
+        assert_eq!(format_bytes(2 * 1024), "2.00 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.00 MiB");
+        assert_eq!(format_bytes(1024 * 1024 - 1), "1.00 MiB");
+        assert_eq!(format_bytes(1024 * 1024 - 6), "1023.99 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.00 GiB");
+    }
+
+    #[test]
+    fn test_format_time_in_offsets() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 5, 7, 4, 9).unwrap();
+        let now = time + chrono::Duration::hours(2);
+        let india = chrono::FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        let pacific = chrono::FixedOffset::west_opt(8 * 3600).unwrap();
+        assert_eq!(
+            format_time_in(time, now, &Utc, DEFAULT_TIME_FORMAT),
+            "2024-03-05 07:04:09 +0000 (2 hours ago)"
+        );
+        assert_eq!(
+            format_time_in(time, now, &india, DEFAULT_TIME_FORMAT),
+            "2024-03-05 12:34:09 +0530 (2 hours ago)"
+        );
+        assert_eq!(
+            format_time_in(time, now, &pacific, DEFAULT_TIME_FORMAT),
+            "2024-03-04 23:04:09 -0800 (2 hours ago)"
+        );
+        // single-digit hours are not padded
+        assert_eq!(
+            format_time_in(time, now, &Utc, "%Y-%m-%d %l:%M%p"),
+            "2024-03-05 7:04AM (2 hours ago)"
+        );
+        // a time in the future is not "ago"
+        assert_eq!(format_time_in(now, time, &Utc, "%H:%M"), "09:04 (now)");
+        assert!(parse_time_format("%Y-%m-%d %H:%M").is_ok());
+        assert!(parse_time_format("%Y-%Q").is_err());
+    }
+
+    #[test]
+    fn test_status_lines_without_color() {
+        let entry = |name: &str, local_status| StatusEntry {
+            name: name.to_string(),
+            local_status: Some(local_status),
+            remote_status: Some(RemoteStatusCode::Different),
+            tracked: Some(true),
+            remote_service: Some("Zenodo".to_string()),
+            local_md5: Some("0123456789abcdef".to_string()),
+            remote_md5: Some("fedcba9876543210".to_string()),
+            manifest_md5: Some("0123456789abcdef".to_string()),
+            manifest_size: Some(2048),
+            remote_size: Some(1024),
+            local_mod_time: None,
+            mirrors: Vec::new(),
+        };
+        let mut rows = BTreeMap::new();
+        rows.insert(
+            "data".to_string(),
+            vec![
+                entry("modified.tsv", LocalStatusCode::Modified),
+                entry("current.tsv", LocalStatusCode::Current),
+            ],
+        );
+        let display = StatusDisplay {
+            color: false,
+            all: true,
+            tree_size: true,
+            ..Default::default()
+        };
+        let lines = format_status_lines(&rows, None, None, &display, None);
+        assert_eq!(lines[0], "[data]");
+        assert!(lines.iter().any(|line| line.contains("modified.tsv")));
+        assert!(lines.iter().all(|line| !line.contains('\x1b')));
+    }
+
+    #[test]
+    fn test_status_directory_order_is_stable() {
+        let entry = |name: &str, size| StatusEntry {
+            name: name.to_string(),
+            local_status: Some(LocalStatusCode::Current),
+            remote_status: None,
+            tracked: Some(false),
+            remote_service: None,
+            local_md5: None,
+            remote_md5: None,
+            manifest_md5: None,
+            manifest_size: Some(size),
+            remote_size: None,
+            local_mod_time: None,
+            mirrors: Vec::new(),
+        };
+        let mut rows = BTreeMap::new();
+        for (dir, size) in [("results", 10), ("", 5), ("data/raw", 10), ("data", 1)] {
+            rows.insert(dir.to_string(), vec![entry("a.tsv", size)]);
+        }
+        rows.get_mut("data/raw").unwrap()[0].local_status = Some(LocalStatusCode::Modified);
+        let headers = |dir_sort| -> Vec<String> {
+            let display = StatusDisplay {
+                dir_sort,
+                ..Default::default()
+            };
+            format_status_lines(&rows, None, None, &display, None)
+                .into_iter()
+                .filter(|line| line.starts_with('['))
+                .collect()
+        };
+        assert_eq!(
+            headers(DirSort::Name),
+            ["[.]", "[data]", "[data/raw]", "[results]"]
+        );
+        // ties (in size) are broken by name
+        assert_eq!(
+            headers(DirSort::Size),
+            ["[data/raw]", "[results]", "[.]", "[data]"]
+        );
+        // directories needing attention come first, then by name
+        assert_eq!(
+            headers(DirSort::Status),
+            ["[data/raw]", "[.]", "[data]", "[results]"]
+        );
+    }
+
+    #[test]
+    fn test_truncate_by_display_width() {
+        // CJK characters are two columns wide, so a cut can leave a column
+        // unused, but never overflows
+        let name = "実験結果_最終版.tsv";
+        assert_eq!(display_width(name), 19);
+        let truncated = truncate_width(name, 10, true);
+        assert_eq!(truncated, "…終版.tsv");
+        assert_eq!(display_width(&truncated), 9);
+        let truncated = truncate_width(name, 7, false);
+        assert_eq!(truncated, "実験結…");
+        assert_eq!(display_width(&truncated), 7);
+        assert_eq!(truncate_width(name, 19, true), name);
+
+        // combining characters stay with the character they combine with
+        let name = "cafe\u{301}s.tsv";
+        assert_eq!(display_width(name), 9);
+        assert_eq!(truncate_width(name, 6, true), "…s.tsv");
+        assert_eq!(truncate_width(name, 5, false), "cafe\u{301}…");
+
+        // wide columns split what the narrow ones leave
+        assert_eq!(column_caps(&[50, 16, 8, 10], 60), [26, 16, 8, 10]);
+        assert_eq!(column_caps(&[50, 16], 80), [50, 16]);
+        assert_eq!(column_caps(&[50, 40], 10), [8, 8]);
+    }
+
+    #[test]
+    fn test_status_lines_truncate_long_paths() {
+        let long_name = "raw/2024/実験/very_long_sample_name_replicate_3.fastq.gz";
+        let entry = |name: &str| StatusEntry {
+            name: name.to_string(),
+            local_status: Some(LocalStatusCode::Current),
+            remote_status: None,
+            tracked: Some(false),
+            remote_service: None,
+            local_md5: Some("0123456789abcdef".to_string()),
+            remote_md5: None,
+            manifest_md5: None,
+            manifest_size: Some(10),
+            remote_size: None,
+            local_mod_time: None,
+            mirrors: Vec::new(),
+        };
+        let mut rows = BTreeMap::new();
+        rows.insert(
+            "data".to_string(),
+            vec![entry(long_name), entry("短い.tsv")],
+        );
+
+        let display = StatusDisplay {
+            max_column_width: Some(24),
+            ..Default::default()
+        };
+        let lines = format_status_lines(&rows, None, None, &display, None);
+        let truncated = truncate_width(long_name, 24, true);
+        assert_eq!(truncated, "…me_replicate_3.fastq.gz");
+        assert_eq!(display_width(&truncated), 24);
+        assert!(lines[1].starts_with(&format!(" {}      ", truncated)));
+        // shorter names are padded to the same display width
+        assert!(lines[2].starts_with(&format!(" 短い.tsv{}      ", " ".repeat(16))));
+
+        // by default, columns are fit to the terminal
+        let display = StatusDisplay {
+            terminal_width: Some(60),
+            ..Default::default()
+        };
+        let lines = format_status_lines(&rows, None, None, &display, None);
+        assert!(lines[1].starts_with(" …"));
+        for line in &lines {
+            assert!(display_width(line.trim_end()) <= 60, "{}", line);
+        }
+        let display = StatusDisplay::default();
+        let lines = format_status_lines(&rows, None, None, &display, None);
+        assert!(lines[1].starts_with(&format!(" {}", long_name)));
+    }
+
+    #[test]
+    fn test_status_summary() {
+        let entry = |local_status: Option<LocalStatusCode>, remote_status, tracked| StatusEntry {
+            manifest_size: local_status.as_ref().map(|_| 1024),
+            name: "reads.fa".to_string(),
+            local_status,
+            remote_status,
+            tracked,
+            remote_service: None,
+            local_md5: None,
+            remote_md5: None,
+            manifest_md5: None,
+            remote_size: Some(4096),
+            local_mod_time: None,
+            mirrors: Vec::new(),
+        };
+        let mut rows = BTreeMap::new();
+        rows.insert(
+            "data".to_string(),
+            vec![
+                entry(
+                    Some(LocalStatusCode::Current),
+                    Some(RemoteStatusCode::Current),
+                    Some(true),
+                ),
+                entry(
+                    Some(LocalStatusCode::Modified),
+                    Some(RemoteStatusCode::MessyLocal),
+                    Some(true),
+                ),
+                entry(Some(LocalStatusCode::Current), None, Some(false)),
+            ],
+        );
+        rows.insert(
+            "results".to_string(),
+            vec![entry(None, Some(RemoteStatusCode::NoLocal), None)],
+        );
+        let summary = status_summary(&rows);
+        assert_eq!((summary.files, summary.tracked), (3, 2));
+        assert_eq!((summary.remote_only, summary.size), (1, 3072));
+        assert_eq!(summary.local["current"], 2);
+        assert_eq!(summary.local["changed"], 1);
+        assert_eq!(summary.remote["unknown (messy remote)"], 1);
+        let lines = summary.lines();
+        assert_eq!(
+            lines[0],
+            "3 files (2 tracked, 1 untracked), 3.00 KiB total; 1 file only on remotes."
+        );
+        assert_eq!(lines[1], "local: 1 changed, 2 current");
+
+        let empty = status_summary(&BTreeMap::new());
+        assert_eq!(
+            empty.lines(),
+            ["0 files (0 tracked, 0 untracked), 0 B total."]
+        );
+    }
+}
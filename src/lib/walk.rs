@@ -0,0 +1,269 @@
+// Expanding the paths given to 'sdf add' into files: glob patterns (e.g.
+// a quoted 'data/**/*.fastq.gz', which the shell did not expand) and, with
+// --recursive, directories.
+//
+// Patterns are matched against the files found by walking the directory
+// before the first wildcard, so they follow the same rules as --recursive:
+// hidden entries (e.g. .sdf/) are skipped, as in shells, and symbolic
+// links are not followed unless asked to. Even then, the same directory
+// is never walked twice, so links cannot cause cycles. Paths excluded by
+// .sdfignore files are skipped, too (see ignore.rs).
+
+use anyhow::{anyhow, Result};
+use glob::{MatchOptions, Pattern};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lib::ignore::IgnoreRules;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WalkOptions {
+    pub recursive: bool,
+    pub follow_symlinks: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundFile {
+    pub path: PathBuf,
+    // named as is, rather than matched by a pattern or found in a directory
+    pub explicit: bool,
+}
+
+pub fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+pub const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+// The files under dir, at most max_depth levels down (if set).
+struct Walk<'a> {
+    options: WalkOptions,
+    ignore: &'a mut IgnoreRules,
+    visited: HashSet<PathBuf>,
+    found: Vec<PathBuf>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(options: WalkOptions, ignore: &'a mut IgnoreRules) -> Self {
+        Walk {
+            options,
+            ignore,
+            visited: HashSet::new(),
+            found: Vec::new(),
+        }
+    }
+}
+
+fn walk_dir(walk: &mut Walk, dir: &Path, max_depth: Option<usize>) -> Result<()> {
+    let read_error = |err: std::io::Error| anyhow!("Could not read '{}': {}", dir.display(), err);
+    if max_depth == Some(0)
+        || !walk
+            .visited
+            .insert(fs::canonicalize(dir).map_err(read_error)?)
+    {
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(read_error)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(read_error)?;
+        let is_dir = if file_type.is_symlink() {
+            if !walk.options.follow_symlinks {
+                continue;
+            }
+            path.is_dir()
+        } else {
+            file_type.is_dir()
+        };
+        if walk.ignore.is_ignored_path(&path, is_dir)? {
+            continue;
+        }
+        if is_dir {
+            walk_dir(walk, &path, max_depth.map(|depth| depth - 1))?;
+        } else {
+            walk.found.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The files matching pattern (or, with --recursive, in a directory
+// matching it).
+fn match_pattern(arg: &str, mut walk: Walk) -> Result<Vec<PathBuf>> {
+    let options = walk.options;
+    let pattern = Pattern::new(arg).map_err(|err| anyhow!("Invalid pattern '{}': {}", arg, err))?;
+    let path = Path::new(arg);
+    let literal = path
+        .components()
+        .take_while(|component| !is_pattern(&component.as_os_str().to_string_lossy()))
+        .count();
+    let base: PathBuf = path.components().take(literal).collect();
+    let depth = path.components().count() - literal;
+    let max_depth = if options.recursive || arg.contains("**") {
+        None
+    } else {
+        Some(depth)
+    };
+
+    let dir = if literal == 0 { Path::new(".") } else { &base };
+    if dir.is_dir() {
+        walk_dir(&mut walk, dir, max_depth)?;
+    }
+    let matches = |path: &Path| pattern.matches_path_with(path, MATCH_OPTIONS);
+    Ok(walk
+        .found
+        .into_iter()
+        .map(|file| match file.strip_prefix(".") {
+            Ok(relative) if literal == 0 => relative.to_path_buf(),
+            _ => file,
+        })
+        .filter(|file| {
+            matches(file)
+                || (options.recursive
+                    && file
+                        .ancestors()
+                        .skip(1)
+                        .take_while(|dir| dir.starts_with(&base) && *dir != base)
+                        .any(matches))
+        })
+        .collect())
+}
+
+// The files named by args, in order. A pattern that matches no files is
+// an error, as is a directory without --recursive (though directories
+// matched by a pattern are just skipped).
+pub fn expand_paths(
+    args: &[String],
+    options: WalkOptions,
+    ignore: &mut IgnoreRules,
+) -> Result<Vec<FoundFile>> {
+    let mut found = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        let files = if is_pattern(arg) && !path.exists() {
+            let files = match_pattern(arg, Walk::new(options, ignore))?;
+            if files.is_empty() {
+                return Err(anyhow!("No files match '{}'.", arg));
+            }
+            files
+        } else if !path.is_dir() {
+            found.push(FoundFile {
+                path: path.to_path_buf(),
+                explicit: true,
+            });
+            continue;
+        } else if options.recursive {
+            let mut walk = Walk::new(options, ignore);
+            walk_dir(&mut walk, path, None)?;
+            walk.found
+        } else {
+            return Err(anyhow!(
+                "'{}' is a directory (use --recursive to add the files in it).",
+                arg
+            ));
+        };
+        found.extend(files.into_iter().map(|path| FoundFile {
+            path,
+            explicit: false,
+        }));
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_expand_nested_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for file in [
+            "data/a.fastq.gz",
+            "data/run1/b.fastq.gz",
+            "data/run1/lane2/c.fastq.gz",
+            "data/run1/notes.txt",
+            "data/.hidden/d.fastq.gz",
+        ] {
+            fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+            fs::write(root.join(file), "reads").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("data"), root.join("data/run1/loop")).unwrap();
+        let mut ignore = IgnoreRules::new(root);
+        let arg = |path: &str| root.join(path).to_string_lossy().to_string();
+        let names = |found: Vec<FoundFile>| -> Vec<String> {
+            found
+                .iter()
+                .map(|file| {
+                    let path = file.path.strip_prefix(root).unwrap();
+                    path.to_string_lossy().to_string()
+                })
+                .collect()
+        };
+
+        let found = expand_paths(
+            &[arg("data/**/*.fastq.gz")],
+            WalkOptions::default(),
+            &mut ignore,
+        )
+        .unwrap();
+        assert_eq!(
+            names(found),
+            [
+                "data/a.fastq.gz",
+                "data/run1/b.fastq.gz",
+                "data/run1/lane2/c.fastq.gz"
+            ]
+        );
+
+        let recursive = WalkOptions {
+            recursive: true,
+            follow_symlinks: false,
+        };
+        let found = expand_paths(&[arg("data/run1")], recursive, &mut ignore).unwrap();
+        assert!(found.iter().all(|file| !file.explicit));
+        assert_eq!(
+            names(found),
+            [
+                "data/run1/b.fastq.gz",
+                "data/run1/lane2/c.fastq.gz",
+                "data/run1/notes.txt"
+            ]
+        );
+
+        // following the link back to data/ walks it once
+        #[cfg(unix)]
+        {
+            let follow = WalkOptions {
+                follow_symlinks: true,
+                ..recursive
+            };
+            let found = expand_paths(&[arg("data/run1")], follow, &mut ignore).unwrap();
+            assert_eq!(names(found).len(), 4);
+        }
+
+        let err =
+            expand_paths(&[arg("data/run1")], WalkOptions::default(), &mut ignore).unwrap_err();
+        assert!(err.to_string().contains("use --recursive"));
+        let err = expand_paths(&[arg("data/**/*.bam")], recursive, &mut ignore).unwrap_err();
+        assert!(err.to_string().contains("No files match"));
+
+        fs::write(root.join(".sdfignore"), "lane2/\n*.txt\n").unwrap();
+        let mut ignore = IgnoreRules::new(root);
+        let found = expand_paths(&[arg("data/run1")], recursive, &mut ignore).unwrap();
+        assert_eq!(names(found), ["data/run1/b.fastq.gz"]);
+    }
+}
@@ -1,14 +1,39 @@
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 #[allow(unused_imports)]
 use log::{debug, info, trace};
 use scidataflow::lib::assets::GitHubRepo;
+use scidataflow::lib::checksum::ChecksumAlgo;
+use scidataflow::lib::data::{FileSort, StatusFilter, StatusOptions};
 use scidataflow::lib::download::Downloads;
+use scidataflow::lib::export::ExportFormat;
+use scidataflow::lib::filter::{parse_since, parse_size, FileFilter, SizeRange};
+use scidataflow::lib::md5_cache;
+use scidataflow::lib::path_style::PathStyle;
+use scidataflow::lib::progress;
+use scidataflow::lib::remote::{self, parse_delay, MAX_CONCURRENT_TRANSFERS};
 use tokio::runtime::Builder;
 
-use scidataflow::lib::project::Project;
+use scidataflow::lib::project::{
+    is_stdin_manifest, project_dir, AddOptions, EnvOptions, LinkOptions, Project, PullOptions,
+    PushOptions,
+};
+use scidataflow::lib::project_config::ProjectConfig;
+use scidataflow::lib::report::{ExitError, OutputFormat};
+use scidataflow::lib::storage;
+use scidataflow::lib::throttle::{self, parse_rate};
+use scidataflow::lib::timestamp::parse_timestamp;
+use scidataflow::lib::utils::{
+    self, format_bytes, hyperlinks_enabled, parse_time_format, DirSort, GroupBy,
+};
+use scidataflow::lib::walk::WalkOptions;
 use scidataflow::logging_setup::setup;
 
 pub mod logging_setup;
@@ -46,6 +71,12 @@ Some examples:
   Push data to a remote (you may want --overwrite):
   $ sdf push
 
+  Push only files in data/ modified in the last week:
+  $ sdf push data/ --since 7d
+
+  Publish the Zenodo deposition of data/, minting its DOI (irreversible):
+  $ sdf publish data/ --confirm
+
   Download a file from a URL and register it in the Data Manifest:
   $ sdf get https://ftp.ensembl.org/some/path/to/large/data.fa.gz
 
@@ -66,17 +97,117 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
 
+    /// Use this data manifest, rather than searching for one ('-' reads
+    /// it from stdin, for read-only commands like status and verify).
+    #[arg(long, global = true)]
+    manifest: Option<PathBuf>,
+
+    /// Disable colored output (also disabled by setting NO_COLOR, or if
+    /// output is not a terminal).
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// The number of files to hash at once (by default, tuned to the
+    /// project's storage, or the number of CPUs if it is not detected), and
+    /// to push or pull at once, unless --max-concurrent is given.
+    #[arg(short, long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+
+    /// The read buffer size for hashing, e.g. '1MB' (by default, tuned to
+    /// the project's storage).
+    #[arg(long, global = true, value_parser = parse_size)]
+    buffer_size: Option<u64>,
+
+    /// How many times to try each request to a remote that fails with a
+    /// server error, 429 Too Many Requests, or a connection error
+    /// (default: 5).
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    max_attempts: Option<u32>,
+
+    /// The delay before the first retry of a failed request, in seconds,
+    /// doubling for each retry (default: 0.5).
+    #[arg(long, global = true, value_parser = parse_delay, value_name = "SECONDS")]
+    retry_delay: Option<Duration>,
+
+    /// Limit the total bandwidth of pushes and pulls, in bytes per second,
+    /// e.g. '2M' (units are K, M and G, as for sizes).
+    #[arg(long, global = true, value_parser = parse_rate, value_name = "RATE")]
+    limit_rate: Option<u64>,
+
+    /// Print the I/O settings (and detected storage) used for this run.
+    #[arg(long, global = true)]
+    profile: bool,
+
+    /// Do not show progress (with verify, only print failures).
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Hash every file, rather than reusing cached checksums of files
+    /// whose size and modification time have not changed.
+    #[arg(long, global = true, visible_alias = "no-cache")]
+    rehash: bool,
+
+    /// The strftime format of displayed times (default: '%Y-%m-%d
+    /// %H:%M:%S %z'; also set by SDF_TIME_FORMAT).
+    #[arg(long, global = true, value_parser = parse_time_format, value_name = "FORMAT")]
+    time_format: Option<String>,
+
+    /// Display times in UTC, rather than local time (also set by
+    /// SDF_UTC=1).
+    #[arg(long, global = true)]
+    utc: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Add a data file to the manifest.
+    /// Add data files to the manifest.
     Add {
-        /// the file to begin tracking.
+        /// The files to begin tracking, or glob patterns like
+        /// 'data/**/*.fastq.gz' (quoted, so the shell does not expand them).
         #[arg(required = true)]
         filenames: Vec<String>,
+
+        /// Add the files in directories, and their subdirectories (hidden
+        /// files and directories are skipped).
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Follow symbolic links to files and directories with --recursive.
+        #[arg(long, requires = "recursive")]
+        follow_symlinks: bool,
+
+        /// Checksums to record in addition to the MD5, e.g. --algos sha256
+        /// (all are computed in one pass over each file).
+        #[arg(long, value_enum, value_delimiter = ',')]
+        algos: Vec<ChecksumAlgo>,
+
+        /// The checksum used to detect changes to these files, recorded in
+        /// the manifest (the MD5 is recorded too, since remotes use it;
+        /// default: the project's checksum_algo, or md5).
+        #[arg(long, value_enum)]
+        checksum: Option<ChecksumAlgo>,
+
+        /// Also record the MD5s of each SIZE-byte block of the files.
+        #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u64).range(1..))]
+        block_hashes: Option<u64>,
+
+        #[command(flatten)]
+        size: SizeArgs,
+    },
+    /// Add the files listed in a data catalog (a Frictionless
+    /// datapackage.json), using its checksums rather than rehashing.
+    ImportCatalog {
+        /// The catalog file.
+        filename: String,
+    },
+    /// Add the files listed in a CSV or TSV file, with a 'path' column and
+    /// optionally 'md5', 'sha256' or 'blake3' columns that the files must match.
+    Import {
+        /// The file list.
+        filename: String,
     },
     /// Set local system-wide metadata (e.g. your name, email, etc.), which
     /// can be propagated to some APIs.
@@ -90,6 +221,10 @@ enum Commands {
         // Your affiliation.
         #[arg(long)]
         affiliation: Option<String>,
+        /// The rclone binary used for rclone remotes (default: rclone on
+        /// PATH).
+        #[arg(long)]
+        rclone: Option<String>,
     },
     /// Initialize a new project.
     Init {
@@ -123,6 +258,11 @@ enum Commands {
     },
     /// Show status of data.
     Status {
+        /// Only show these files, files in these directories, or files
+        /// matching these patterns (e.g. 'data/raw/' or 'data/**/*.bam');
+        /// other files are not hashed.
+        paths: Vec<String>,
+
         /// Show remotes status (requires network).
         #[arg(long)]
         remotes: bool,
@@ -130,9 +270,190 @@ enum Commands {
         /// Show statuses of all files, including those on remote(s) but not in the manifest.
         #[arg(long)]
         all: bool,
+
+        /// Show remote status from the last-known remote state stored in the
+        /// manifest, without network access (an offline approximation).
+        #[arg(long, conflicts_with = "remotes")]
+        assume_remote_current: bool,
+
+        /// Only show files whose remote copy is not current (requires
+        /// --remotes or --assume-remote-current).
+        #[arg(long)]
+        only_changed_remotes: bool,
+
+        /// The checksum used to detect local changes, for files that have it
+        /// recorded (by default, each file's own checksum, see 'sdf add
+        /// --checksum'; files without it use the MD5).
+        #[arg(long, value_enum)]
+        algo: Option<ChecksumAlgo>,
+
+        /// Group files by directory, or summarize the count and total size
+        /// of files by extension.
+        #[arg(long, value_enum, default_value_t = GroupBy::Directory)]
+        group_by: GroupBy,
+
+        /// The output format (json and tsv list each file's status: its
+        /// path, tracked, local and remote status, md5, size and mtime;
+        /// or with --group-by extension, the summary).
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+
+        /// Shorthand for --format json (hidden: kept for scripts that use it).
+        #[arg(long, hide = true, conflicts_with = "format")]
+        json: bool,
+
+        /// Link file paths and remotes in the status table (for terminals
+        /// that support hyperlinks; ignored if output is not a terminal).
+        #[arg(long)]
+        hyperlinks: bool,
+
+        /// Only print the total size of files a push (with --overwrite)
+        /// would upload; use --assume-remote-current to skip fetching.
+        #[arg(long)]
+        pending_upload_size: bool,
+
+        /// Time each remote request, and print the min/median/max latency
+        /// and number of calls per remote.
+        #[arg(long)]
+        remote_latency: bool,
+
+        /// The order of directories: by name, by total size (largest
+        /// first), or by status (directories needing attention first).
+        #[arg(long, value_enum, default_value_t = DirSort::Name)]
+        dir_sort: DirSort,
+
+        /// The order of files within each directory: by name, by size
+        /// (smallest first), or by local modification time (oldest first).
+        #[arg(long, value_enum, default_value_t = FileSort::Name)]
+        sort: FileSort,
+
+        /// Reverse the order of files set by --sort (e.g. with --sort
+        /// size, largest first).
+        #[arg(long)]
+        reverse: bool,
+
+        /// Color each directory's header by its share of the total size,
+        /// from blue (small) to red (large).
+        #[arg(long)]
+        tree_size: bool,
+
+        /// Truncate column values wider than this, with an ellipsis (by
+        /// default, columns are fit to the terminal's width). File paths
+        /// are truncated from the left, keeping the file name.
+        #[arg(long, value_name = "WIDTH", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        max_column_width: Option<usize>,
+
+        /// With --remotes, reuse remote file listings fetched within this
+        /// many seconds (cached in .sdf/), rather than querying remotes.
+        #[arg(long, value_name = "SECONDS")]
+        remote_cache_ttl: Option<u64>,
+
+        /// Only print how many files of each remote are ahead (to push),
+        /// behind (to pull), or in conflict, relative to the last sync.
+        #[arg(long, conflicts_with_all = ["assume_remote_current", "pending_upload_size"])]
+        ahead_behind: bool,
+
+        /// Fetch remote file listings even if they are cached.
+        #[arg(long, requires = "remote_cache_ttl")]
+        refresh: bool,
+
+        /// Only show files with any of these statuses, e.g. --filter
+        /// modified,remote-missing (remote-missing and md5-mismatch require
+        /// --remotes or --assume-remote-current).
+        #[arg(long, value_enum, value_delimiter = ',')]
+        filter: Vec<StatusFilter>,
+
+        /// Only show manifest entries whose files are missing locally
+        /// (shorthand for --filter missing; see 'sdf prune').
+        #[arg(long)]
+        show_missing: bool,
+
+        /// Only show files modified locally (shorthand for --filter
+        /// modified).
+        #[arg(long)]
+        modified_only: bool,
+
+        /// Only show files in the manifest that are not tracked
+        /// (shorthand for --filter untracked).
+        #[arg(long)]
+        untracked: bool,
+
+        /// Only show tracked files not on their remote (shorthand for
+        /// --filter remote-missing; requires --remotes or
+        /// --assume-remote-current).
+        #[arg(long)]
+        not_on_remote: bool,
+
+        #[command(flatten)]
+        size: SizeArgs,
+
+        /// Print each file's status as soon as it is hashed (in the order
+        /// files finish, see --jobs), rather than a table at the end.
+        /// Statuses on mirrors are only counted in the summary.
+        #[arg(long, conflicts_with_all = ["group_by", "pending_upload_size", "ahead_behind", "sort", "dir_sort"])]
+        stream: bool,
     },
     /// Show file size statistics.
     Stats {},
+    /// Show the manifest entry of a file (size, checksums, block hashes).
+    Stat {
+        /// The file to show.
+        path: String,
+
+        /// The output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Show everything known about a file: its manifest entry, current
+    /// MD5, modification time, remotes, and status.
+    Info {
+        /// The file to show.
+        path: String,
+
+        /// Also fetch the file's remote status (only its directory's
+        /// remote is queried).
+        #[arg(long)]
+        remotes: bool,
+    },
+    /// Find files in the manifest with the same content (by their MD5s).
+    Dedup {
+        /// Replace the duplicates with hard links to one copy, once each is
+        /// re-read to confirm it matches.
+        #[arg(long)]
+        link: bool,
+    },
+    /// Export the manifest as a table, one row per file (no remotes are contacted).
+    Export {
+        /// The table format.
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Write to this file, rather than stdout.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Show the resolved configuration: the manifest and config paths,
+    /// credential sources, and the settings commands use.
+    Env {},
+    /// Show the changes recorded in the project's history
+    /// (.sdf/history.jsonl), oldest first.
+    Log {
+        /// The output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Rebuild the manifest from a recorded history, in a directory
+    /// without one (e.g. a fresh checkout).
+    Replay {
+        /// Only apply the changes saved up to this time (RFC 3339, e.g.
+        /// 2024-01-31T12:00:00Z).
+        #[arg(long, value_parser = parse_timestamp)]
+        until: Option<DateTime<Utc>>,
+
+        /// The history to replay (default: .sdf/history.jsonl).
+        #[arg(long, value_name = "PATH")]
+        history: Option<PathBuf>,
+    },
     /// Update MD5s
     Update {
         /// Which file to update (if not set, all tracked files are update).
@@ -144,9 +465,24 @@ enum Commands {
     },
     /// Remove a file from the manifest
     Rm {
-        /// Which file(s) to remove from the manifest (these are not deleted).
+        /// Which file(s) to remove from the manifest.
         #[arg(required = true)]
         filenames: Vec<String>,
+
+        /// Leave the files on disk (the default).
+        #[arg(long, conflicts_with = "delete_local")]
+        keep_local: bool,
+
+        /// Also delete the files on disk.
+        #[arg(long)]
+        delete_local: bool,
+    },
+    /// Remove the manifest entries of files that are missing locally (e.g.
+    /// deleted outside sdf).
+    Prune {
+        /// Remove them without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
     },
     /// Retrieve a SciDataFlow Asset
     Asset {
@@ -163,20 +499,77 @@ enum Commands {
     Link {
         /// Directory to link to remote storage.
         dir: String,
-        /// The data repository service to use (either 'figshare' or 'zenodo').
+        /// The data repository service to use ('figshare', 'zenodo',
+        /// 'dataverse', 'osf', 'webdav', 'gdrive', 's3', 'rclone', 'sftp' or
+        /// 'filesystem').
         service: String,
-        /// The authentication token.
-        key: String,
+        /// The authentication token (for S3, ACCESS_KEY_ID:SECRET_ACCESS_KEY;
+        /// not needed if AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are set).
+        /// For WebDAV, USER:PASSWORD for basic authentication, or a token.
+        /// For Google Drive, the OAuth client, as CLIENT_ID:CLIENT_SECRET.
+        key: Option<String>,
         /// Project name for remote (default: the metadata title in the data
-        /// manifest, or if that's not set, the directory name).
+        /// manifest, or if that's not set, the directory name). For S3, the
+        /// bucket and an optional key prefix, as bucket/prefix. For rclone,
+        /// the rclone remote and path, as remote:path. For SFTP, the server
+        /// and directory, as user@host:/path. For a filesystem, the
+        /// directory to store files in (e.g. on an NFS mount). For WebDAV, the collection's
+        /// URL. For Google Drive, the ID (or URL) of a folder sdf made before
+        /// (default: make a folder named after the project). For Dataverse, the
+        /// collection's alias and an optional dataset title (default: the
+        /// project name), as collection/title. For OSF, the GUID of the
+        /// project or component (e.g. abc12, for https://osf.io/abc12/).
         #[arg(long)]
         name: Option<String>,
 
+        /// For S3: the endpoint URL of an S3-compatible store (e.g. a MinIO
+        /// server; default: AWS's endpoint for the region). For Dataverse:
+        /// the server's URL (required), e.g. https://dataverse.harvard.edu.
+        #[arg(long)]
+        endpoint: Option<String>,
+
+        /// For S3: the region (default: AWS_REGION, or us-east-1).
+        #[arg(long)]
+        region: Option<String>,
+
+        /// For Zenodo: create (or find) the deposition on the sandbox,
+        /// sandbox.zenodo.org, rather than the archive. The sandbox needs its
+        /// own token, saved separately.
+        #[arg(long)]
+        sandbox: bool,
+
+        /// For SFTP: run md5sum on the server to get each file's MD5, so
+        /// files are compared by MD5, not only size (this reads every file
+        /// on the server).
+        #[arg(long)]
+        md5sum: bool,
+
+        /// Mirror an already-linked directory to this remote, too: push
+        /// and pull go to each of the directory's remotes (see --remote),
+        /// and 'sdf status --remotes' shows the file's status on each.
+        #[arg(long)]
+        mirror: bool,
+
+        /// Link a published Zenodo record or FigShare article (e.g.
+        /// someone else's), by its DOI (e.g. 10.5281/zenodo.1234), URL or
+        /// ID, only for downloading: its files are added to the manifest
+        /// for 'sdf pull', which needs no token, and 'sdf push' to it is
+        /// an error.
+        #[arg(long, value_name = "DOI", conflicts_with_all = ["key", "name", "link_only"])]
+        read_only: Option<String>,
+
         /// Don't initialize remote, only add to manifest. This will retrieve
         /// the remote information (i.e. the FigShare Article ID or Zenodo
         /// Depository ID) to add to the manifest. Requires network.
         #[arg(long)]
         link_only: bool,
+
+        /// How files in subdirectories are named on the remote: 'flat'
+        /// encodes the path into the name (e.g. raw__a.tsv), while
+        /// 'hierarchical' keeps the directories. The default is
+        /// 'hierarchical' for S3 and WebDAV, and 'flat' otherwise.
+        #[arg(long, value_enum)]
+        path_style: Option<PathStyle>,
     },
     /// No longer keep track of this file on the remote.
     Untrack {
@@ -187,14 +580,74 @@ enum Commands {
     Track {
         /// The file to track with remote.
         filename: String,
+
+        /// Checksums to record in addition to the MD5, e.g. --algos sha256.
+        #[arg(long, value_enum, value_delimiter = ',')]
+        algos: Vec<ChecksumAlgo>,
+
+        /// Also record the MD5s of each SIZE-byte block of the file.
+        #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u64).range(1..))]
+        block_hashes: Option<u64>,
     },
     /// Move or rename a file on the file system and in the manifest.
-    Mv { source: String, destination: String },
+    Mv {
+        source: String,
+
+        /// The new path, or an existing directory to move the file into.
+        destination: String,
+
+        /// Overwrite the destination if it exists (on disk or in the manifest).
+        #[arg(short, long)]
+        force: bool,
+    },
     /// Push all tracked files to remote.
     Push {
+        /// Only push these files, files in these directories, or files
+        /// matching these patterns (e.g. 'alignments/' or 'data/**/*.bam').
+        paths: Vec<String>,
+
         /// Overwrite remote files if they exit.
         #[arg(long)]
         overwrite: bool,
+
+        /// Only push files modified since this time: a duration (e.g. '7d',
+        /// '12h') or a date (e.g. '2024-01-31').
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Skip files before this one: files are pushed in path order, so
+        /// a failed push can be resumed from the file it failed on.
+        #[arg(long, value_name = "PATH")]
+        resume_from: Option<String>,
+
+        /// Upload only the changed parts of files that already have a remote
+        /// copy, for filesystem remotes (others get full uploads).
+        #[arg(long)]
+        delta: bool,
+
+        /// Only list the files that would be uploaded (and why), and those
+        /// that would be skipped; nothing is written to the remotes.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// The number of files to upload at once (default: --jobs, or the
+        /// project's max_concurrent or jobs, or 4; at most 2 for Zenodo and
+        /// FigShare, whose APIs are rate limited).
+        #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        max_concurrent: Option<usize>,
+
+        /// Only push to the remotes with this name (e.g. 'zenodo' or 's3'),
+        /// rather than to each directory's remote and its mirrors (default:
+        /// the project's default_remote, if set).
+        #[arg(long, value_name = "NAME")]
+        remote: Option<String>,
+
+        #[command(flatten)]
+        size: SizeArgs,
+
+        /// The format of the end-of-run report.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
     },
     /// Pull in all tracked files from the remote. If --urls is set,
     /// this will (re)-download all files (tracked or not) in that manifest
@@ -205,6 +658,11 @@ enum Commands {
     /// after the download is successful. While safer, this does temporarily
     /// increase disk usage.
     Pull {
+        /// Only pull these files, files in these directories, or files
+        /// matching these patterns (e.g. 'alignments/' or 'data/**/*.bam').
+        #[arg(conflicts_with = "checksum_from_remote")]
+        paths: Vec<String>,
+
         /// Overwrite local files if they exit.
         #[arg(long)]
         overwrite: bool,
@@ -216,8 +674,95 @@ enum Commands {
         /// Pull in files from remotes and URLs.
         #[arg(long)]
         all: bool,
-        // multiple optional directories
-        //directories: Vec<PathBuf>,
+
+        /// Don't download anything; record the remote-reported MD5s of
+        /// manifest entries that lack a checksum.
+        #[arg(long, conflicts_with_all = ["urls", "all", "overwrite"])]
+        checksum_from_remote: bool,
+
+        /// Only list the files that would be downloaded (and why), and
+        /// those that would be skipped; nothing is downloaded.
+        #[arg(long, conflicts_with = "checksum_from_remote")]
+        dry_run: bool,
+
+        /// The number of files to download at once (default: --jobs, or the
+        /// project's max_concurrent or jobs, or 4; at most 2 for Zenodo and
+        /// FigShare, whose APIs are rate limited).
+        #[arg(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+        max_concurrent: Option<usize>,
+
+        /// Only pull from the remotes with this name (e.g. 'zenodo' or
+        /// 's3'), rather than from each directory's remote, then its mirrors
+        /// (default: the project's default_remote, if set).
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["urls", "checksum_from_remote"])]
+        remote: Option<String>,
+
+        #[command(flatten)]
+        size: SizeArgs,
+
+        /// The format of the end-of-run report.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Publish a directory's Zenodo deposition or FigShare article, which
+    /// gives it a DOI (kept in the manifest). This can't be undone, so it
+    /// needs --confirm.
+    Publish {
+        /// The directory whose deposit to publish (default: the only one
+        /// linked to Zenodo or FigShare).
+        dir: Option<String>,
+
+        /// Publish it; without this, only what would be published is shown.
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Remove temporary files left over by interrupted or crashed runs.
+    Clean {},
+    /// Check local files against the checksums in the manifest (always
+    /// rehashing them), and with --remotes, their remote copies. Exits
+    /// with 1 if any files are corrupted or missing, and 2 if any could
+    /// not be read.
+    Verify {
+        /// Also check that each tracked file is on its remote (and
+        /// mirrors), with the manifest's MD5 and size (requires network).
+        #[arg(long)]
+        remotes: bool,
+
+        /// Re-download files that do not match the manifest from their
+        /// remote, replacing the local copy once the download is verified.
+        #[arg(long)]
+        repair_from_remote: bool,
+
+        /// The format of the report.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Write a tracked file to stdout, streaming it from its remote (and
+    /// verifying its MD5) if the local copy is missing or modified.
+    Cat {
+        /// The file to write.
+        path: String,
+    },
+    /// Compare a local file with its copy on the remote: text files get a
+    /// unified diff, binary files the first offset where they differ.
+    Diff {
+        /// The file to compare.
+        path: String,
+
+        /// Compare with the copy on the remote with this name, e.g. one of
+        /// the directory's mirrors (default: the directory's remote). The
+        /// copy is downloaded to a temporary file.
+        #[arg(long, value_name = "NAME")]
+        remote: Option<String>,
+
+        /// Don't compare files larger than this (locally or remotely).
+        #[arg(long, value_parser = parse_size, default_value = "10MB")]
+        max_size: u64,
+    },
+    /// Manage the remotes referenced by the manifest.
+    Remote {
+        #[command(subcommand)]
+        command: RemoteCommands,
     },
     /// Change the project metadata.
     Metadata {
@@ -228,6 +773,47 @@ enum Commands {
         #[arg(long)]
         description: Option<String>,
     },
+    /// Print a shell completion script, e.g. 'sdf completions bash >
+    /// ~/.local/share/bash-completion/completions/sdf'.
+    Completions {
+        /// The shell to complete in.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemoteCommands {
+    /// Clear references to remotes that are no longer in the manifest
+    /// (e.g. after removing a remote by hand).
+    Prune {},
+}
+
+// Size bounds shared by the commands that operate on sets of files.
+#[derive(Args)]
+struct SizeArgs {
+    /// Only include files at least this large (e.g. '100MB').
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Only include files at most this large (e.g. '1GB').
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+}
+
+impl SizeArgs {
+    fn range(&self) -> SizeRange {
+        SizeRange {
+            min: self.min_size,
+            max: self.max_size,
+        }
+    }
+}
+
+// Completions are generated from the same definitions the CLI parses
+// with, so they cannot go stale.
+fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "sdf", out);
 }
 
 pub fn print_errors(response: Result<()>) {
@@ -253,30 +839,135 @@ fn main() {
             Ok(_) => {}
             Err(e) => {
                 eprintln!("Error: {:?}", e);
-                std::process::exit(1);
+                let code = e.downcast_ref::<ExitError>().map_or(1, |err| err.code);
+                std::process::exit(code);
             }
         }
     });
 }
 
+// Fill in the options not given on the command line from the project's
+// config (see project_config.rs). The number of files transferred at once
+// is --max-concurrent, or else --jobs, the config's max_concurrent, or its
+// jobs, in that order.
+fn apply_project_config(cli: &mut Cli, config: &ProjectConfig) {
+    let transfers = (cli.jobs.map(|jobs| jobs as usize))
+        .or(config.max_concurrent)
+        .or(config.jobs.map(|jobs| jobs as usize));
+    cli.jobs = cli.jobs.or(config.jobs);
+    cli.limit_rate = cli.limit_rate.or(config.limit_rate);
+    cli.max_attempts = cli.max_attempts.or(config.max_attempts);
+    cli.retry_delay = cli.retry_delay.or(config.retry_delay);
+    match &mut cli.command {
+        Some(Commands::Add { checksum, .. }) => *checksum = checksum.or(config.checksum_algo),
+        Some(Commands::Push {
+            remote,
+            max_concurrent,
+            ..
+        }) => {
+            *remote = remote.take().or(config.default_remote.clone());
+            *max_concurrent = max_concurrent.or(transfers);
+        }
+        // (other than --urls and --checksum-from-remote pulls, which do
+        // not pull from remotes)
+        Some(Commands::Pull {
+            remote,
+            urls,
+            checksum_from_remote,
+            max_concurrent,
+            ..
+        }) => {
+            *max_concurrent = max_concurrent.or(transfers);
+            if !*urls && !*checksum_from_remote {
+                *remote = remote.take().or(config.default_remote.clone());
+            }
+        }
+        _ => (),
+    }
+}
+
 async fn run() -> Result<()> {
-    let cli = Cli::parse();
-    match &cli.command {
-        Some(Commands::Add { filenames }) => {
-            let mut proj = Project::new()?;
-            proj.add(filenames).await
+    let mut cli = Cli::parse();
+    // (as given, so 'sdf env' can tell flags from the project's defaults)
+    let env_options = EnvOptions {
+        jobs: cli.jobs,
+        limit_rate: cli.limit_rate,
+    };
+    let project_config = match project_dir(cli.manifest.as_deref()) {
+        Some(dir) => ProjectConfig::load(&dir)?,
+        None => ProjectConfig::default(),
+    };
+    apply_project_config(&mut cli, &project_config);
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+    let manifest = cli.manifest.as_deref();
+    let project_dir = manifest
+        .filter(|manifest| *manifest != Path::new("-"))
+        .and_then(|manifest| manifest.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let io_settings = storage::configure(
+        project_dir,
+        cli.jobs.map(|jobs| jobs as usize),
+        cli.buffer_size.map(|size| size.max(1) as usize),
+    );
+    if cli.profile {
+        eprintln!("I/O settings: {}.", io_settings);
+    }
+    remote::configure_retries(cli.max_attempts, cli.retry_delay);
+    throttle::limit_rate(cli.limit_rate);
+    if let (true, Some(limiter)) = (cli.profile, throttle::limiter()) {
+        eprintln!("Transfers limited to {}/s.", format_bytes(limiter.rate()));
+    }
+    if cli.rehash {
+        md5_cache::disable();
+    }
+    progress::set_quiet(cli.quiet);
+    utils::configure_time_display(cli.time_format.as_deref(), cli.utc)?;
+    let result = match &cli.command {
+        Some(Commands::Add {
+            filenames,
+            recursive,
+            follow_symlinks,
+            algos,
+            checksum,
+            block_hashes,
+            size,
+        }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            let options = AddOptions {
+                algos: algos.clone(),
+                checksum: checksum.unwrap_or_default(),
+                block_size: *block_hashes,
+                size: size.range(),
+                walk: WalkOptions {
+                    recursive: *recursive,
+                    follow_symlinks: *follow_symlinks,
+                },
+            };
+            proj.add(filenames, &options).await
+        }
+        Some(Commands::ImportCatalog { filename }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.import_catalog(filename).await
+        }
+        Some(Commands::Import { filename }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.import_list(filename).await
         }
         Some(Commands::Config {
             name,
             email,
             affiliation,
-        }) => Project::set_config(name, email, affiliation),
+            rclone,
+        }) => Project::set_config(name, email, affiliation, rclone),
         Some(Commands::Get {
             url,
             name,
             overwrite,
         }) => {
-            let mut proj = Project::new()?;
+            let mut proj = Project::with_manifest(manifest)?;
             proj.get(url, name.as_deref(), *overwrite).await
         }
         Some(Commands::Bulk {
@@ -285,25 +976,139 @@ async fn run() -> Result<()> {
             header,
             overwrite,
         }) => {
-            let mut proj = Project::new()?;
+            let mut proj = Project::with_manifest(manifest)?;
             proj.bulk(filename, *column, *header, *overwrite).await
         }
         Some(Commands::Init { name }) => Project::init(name.clone()),
-        Some(Commands::Status { remotes, all }) => {
-            let mut proj = Project::new()?;
-            proj.status(*remotes, *all).await
+        Some(Commands::Status {
+            paths,
+            remotes,
+            all,
+            assume_remote_current,
+            only_changed_remotes,
+            algo,
+            group_by,
+            format,
+            json,
+            hyperlinks,
+            dir_sort,
+            sort,
+            reverse,
+            remote_latency,
+            pending_upload_size,
+            tree_size,
+            max_column_width,
+            remote_cache_ttl,
+            refresh,
+            ahead_behind,
+            filter,
+            show_missing,
+            modified_only,
+            untracked,
+            not_on_remote,
+            size,
+            stream,
+        }) => {
+            // --remotes saves the fetched remote state to the manifest
+            // (unless another run is modifying it)
+            let mut proj = if *remotes && !is_stdin_manifest(manifest) {
+                Project::with_manifest_if_unlocked(manifest)?
+            } else {
+                Project::read_only(manifest)?
+            };
+            let mut filters = filter.clone();
+            let shorthands = [
+                (*show_missing, StatusFilter::Missing),
+                (*modified_only, StatusFilter::Modified),
+                (*untracked, StatusFilter::Untracked),
+                (*not_on_remote, StatusFilter::RemoteMissing),
+            ];
+            for (set, status_filter) in shorthands {
+                if set && !filters.contains(&status_filter) {
+                    filters.push(status_filter);
+                }
+            }
+            let options = StatusOptions {
+                include_remotes: *remotes,
+                cached_remotes: *assume_remote_current,
+                algo: *algo,
+                only_changed_remotes: *only_changed_remotes,
+                remote_latency: *remote_latency,
+                pending_upload_size: *pending_upload_size,
+                tree_size: *tree_size,
+                max_column_width: *max_column_width,
+                size: size.range(),
+                remote_cache_ttl: *remote_cache_ttl,
+                refresh: *refresh,
+                ahead_behind: *ahead_behind,
+                jobs: cli.jobs.map(|jobs| jobs as usize),
+                filters,
+                paths: paths.clone(),
+                sort: *sort,
+                reverse: *reverse,
+                stream: *stream,
+            };
+            let hyperlinks = hyperlinks_enabled(*hyperlinks);
+            let format = if *json { OutputFormat::Json } else { *format };
+            proj.status(&options, *all, *group_by, format, hyperlinks, *dir_sort)
+                .await
         }
         Some(Commands::Stats {}) => {
             //let proj = Project::new()?;
             //proj.stats()
             Ok(())
         }
-        Some(Commands::Rm { filenames }) => {
-            let mut proj = Project::new()?;
-            proj.remove(filenames).await
+        Some(Commands::Stat { path, format }) => {
+            let proj = Project::read_only(manifest)?;
+            proj.stat(path, *format)
+        }
+        Some(Commands::Info { path, remotes }) => {
+            let mut proj = Project::read_only(manifest)?;
+            proj.info(path, *remotes).await
+        }
+        Some(Commands::Dedup { link }) => {
+            // linking replaces project files, so locks out other runs
+            let proj = if *link {
+                Project::with_manifest(manifest)?
+            } else {
+                Project::read_only(manifest)?
+            };
+            proj.dedup(*link).await
+        }
+        Some(Commands::Export { format, output }) => {
+            let proj = Project::read_only(manifest)?;
+            proj.export(*format, output.as_deref()).await
+        }
+        Some(Commands::Log { format }) => {
+            let proj = Project::read_only(manifest)?;
+            proj.log(*format)
+        }
+        Some(Commands::Replay { until, history }) => {
+            let files = Project::replay(history.as_deref(), *until)?;
+            let files = utils::pluralize(files as u64, "file");
+            println!("Rebuilt the manifest, with {}.", files);
+            Ok(())
+        }
+        Some(Commands::Env {}) => {
+            for (key, value) in Project::env(manifest, &env_options)? {
+                println!("{}: {}", key, value);
+            }
+            Ok(())
+        }
+        Some(Commands::Rm {
+            filenames,
+            keep_local: _,
+            delete_local,
+        }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.remove(filenames, *delete_local).await
+        }
+        Some(Commands::Prune { yes }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.prune(*yes).await
         }
         Some(Commands::Update { filenames, all }) => {
-            let mut proj = Project::new()?;
+            let mut proj = Project::with_manifest(manifest)?;
             if !*all && filenames.is_empty() {
                 return Err(anyhow!("Specify --all or one or more file to update."));
             }
@@ -316,41 +1121,165 @@ async fn run() -> Result<()> {
             key,
             name,
             link_only,
+            path_style,
+            endpoint,
+            region,
+            sandbox,
+            md5sum,
+            mirror,
+            read_only,
         }) => {
-            let mut proj = Project::new()?;
-            proj.link(dir, service, key, name, link_only).await
+            let mut proj = Project::with_manifest(manifest)?;
+            let options = LinkOptions {
+                name: name.clone(),
+                link_only: *link_only,
+                path_style: *path_style,
+                endpoint: endpoint.clone(),
+                region: region.clone(),
+                sandbox: *sandbox,
+                md5sum: *md5sum,
+                mirror: *mirror,
+                published: read_only.clone(),
+            };
+            proj.link(dir, service, key.as_deref(), &options).await
         }
-        Some(Commands::Track { filename }) => {
-            let mut proj = Project::new()?;
-            proj.track(filename)
+        Some(Commands::Track {
+            filename,
+            algos,
+            block_hashes,
+        }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.track(filename, algos, *block_hashes).await
         }
         Some(Commands::Untrack { filename }) => {
-            let mut proj = Project::new()?;
+            let mut proj = Project::with_manifest(manifest)?;
             proj.untrack(filename)
         }
         Some(Commands::Mv {
             source,
             destination,
+            force,
         }) => {
-            let mut proj = Project::new()?;
-            proj.mv(source, destination).await
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.mv(source, destination, *force).await
         }
-        Some(Commands::Push { overwrite }) => {
-            let mut proj = Project::new()?;
-            proj.push(*overwrite).await
+        Some(Commands::Push {
+            paths,
+            overwrite,
+            since,
+            resume_from,
+            delta,
+            dry_run,
+            max_concurrent,
+            remote,
+            size,
+            format,
+        }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            let filter = FileFilter {
+                paths: paths.clone(),
+                since: *since,
+                resume_from: resume_from.clone(),
+                size: size.range(),
+            };
+            let options = PushOptions {
+                overwrite: *overwrite,
+                delta: *delta,
+                dry_run: *dry_run,
+                max_concurrent: max_concurrent.unwrap_or(MAX_CONCURRENT_TRANSFERS),
+                remote: remote.clone(),
+            };
+            proj.push(&options, *format, filter).await
         }
         Some(Commands::Pull {
+            paths,
             overwrite,
             urls,
             all,
+            checksum_from_remote,
+            dry_run,
+            max_concurrent,
+            remote,
+            size,
+            format,
+        }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            let options = PullOptions {
+                overwrite: *overwrite,
+                urls: *urls,
+                all: *all,
+                checksum_from_remote: *checksum_from_remote,
+                dry_run: *dry_run,
+                size: size.range(),
+                max_concurrent: max_concurrent.unwrap_or(MAX_CONCURRENT_TRANSFERS),
+                remote: remote.clone(),
+                paths: paths.clone(),
+            };
+            proj.pull(&options, *format).await
+        }
+        Some(Commands::Publish { dir, confirm }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            proj.publish(dir.as_deref(), *confirm).await
+        }
+        Some(Commands::Clean {}) => {
+            let proj = Project::with_manifest(manifest)?;
+            proj.clean()
+        }
+        Some(Commands::Verify {
+            remotes,
+            repair_from_remote,
+            format,
         }) => {
-            let mut proj = Project::new()?;
-            proj.pull(*overwrite, *urls, *all).await
+            let verify = async {
+                // repairs update the manifest, so they cannot use --manifest -
+                let mut proj = if *repair_from_remote {
+                    Project::with_manifest(manifest)?
+                } else {
+                    Project::read_only(manifest)?
+                };
+                proj.verify(*repair_from_remote, *remotes, *format, cli.quiet)
+                    .await
+            };
+            // any other error (e.g. reading the manifest) is an I/O error
+            verify
+                .await
+                .map_err(|err| match err.downcast::<ExitError>() {
+                    Ok(err) => anyhow!(err),
+                    Err(err) => anyhow!(ExitError {
+                        code: 2,
+                        message: format!("{:?}", err),
+                    }),
+                })
+        }
+        Some(Commands::Remote { command }) => {
+            let mut proj = Project::with_manifest(manifest)?;
+            match command {
+                RemoteCommands::Prune {} => proj.remote_prune(),
+            }
+        }
+        Some(Commands::Diff {
+            path,
+            remote,
+            max_size,
+        }) => {
+            let mut proj = Project::read_only(manifest)?;
+            proj.diff_remote(path, *max_size, remote.as_deref()).await
+        }
+        Some(Commands::Cat { path }) => {
+            let mut proj = Project::read_only(manifest)?;
+            proj.cat(path).await
         }
         Some(Commands::Metadata { title, description }) => {
-            let mut proj = Project::new()?;
+            let mut proj = Project::with_manifest(manifest)?;
             proj.set_metadata(title, description)
         }
+        Some(Commands::Completions { shell }) => {
+            // written at once, so a closed pipe is an error, not a panic
+            let mut script = Vec::new();
+            write_completions(*shell, &mut script);
+            std::io::stdout().write_all(&script)?;
+            Ok(())
+        }
         Some(Commands::Asset { github, url, asset }) => {
             if Path::new("data_manifest.yml").exists() {
                 return Err(anyhow!("data_manifest.yml already exists in the current directory; delete it manually first to use sdf asset."));
@@ -371,7 +1300,8 @@ async fn run() -> Result<()> {
                 (None, Some(url), None) => url.to_string(),
                 _ => return Err(anyhow!(msg)),
             };
-            let mut downloads = Downloads::new();
+            // the manifest is downloaded into the current directory
+            let mut downloads = Downloads::new(Path::new("."));
             downloads.add(url.clone(), None, false)?;
             downloads.retrieve(None, None, false).await?;
             Ok(())
@@ -380,5 +1310,138 @@ async fn run() -> Result<()> {
             println!("{}\n", INFO);
             std::process::exit(1);
         }
+    };
+    // the cache is only an optimization (and the project may be read-only)
+    if let Err(err) = md5_cache::save() {
+        debug!("not saving the MD5 cache: {}", err);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut script = Vec::new();
+            write_completions(shell, &mut script);
+            assert!(!script.is_empty(), "no completions for {}", shell);
+        }
+        let mut script = Vec::new();
+        write_completions(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        for command in Cli::command().get_subcommands() {
+            assert!(script.contains(command.get_name()));
+        }
+        assert!(script.contains("--limit-rate"));
+    }
+
+    #[test]
+    fn test_project_config_precedence() {
+        let config = ProjectConfig::parse(
+            "default_remote: zenodo\njobs: 8\nlimit_rate: 2M\nchecksum_algo: sha256\n\
+             max_concurrent: 6\nmax_attempts: 10\nretry_delay: 2\n",
+        )
+        .unwrap();
+        let parse = |args: &[&str], config: &ProjectConfig| {
+            let mut cli = Cli::parse_from([&["sdf"], args].concat());
+            apply_project_config(&mut cli, config);
+            cli
+        };
+
+        // the file's values beat the built-in defaults
+        let cli = parse(&["push"], &config);
+        assert_eq!((cli.jobs, cli.limit_rate), (Some(8), Some(2 * 1024 * 1024)));
+        assert_eq!(
+            (cli.max_attempts, cli.retry_delay),
+            (Some(10), Some(Duration::from_secs(2)))
+        );
+        let Some(Commands::Push {
+            remote,
+            max_concurrent,
+            ..
+        }) = cli.command
+        else {
+            panic!("expected push");
+        };
+        assert_eq!(remote.as_deref(), Some("zenodo"));
+        assert_eq!(max_concurrent, Some(6));
+        let Some(Commands::Add { checksum, .. }) = parse(&["add", "a.tsv"], &config).command else {
+            panic!("expected add");
+        };
+        assert_eq!(checksum, Some(ChecksumAlgo::Sha256));
+
+        // flags beat the file's values
+        let cli = parse(
+            &[
+                "pull",
+                "--remote",
+                "s3",
+                "--jobs",
+                "2",
+                "--limit-rate",
+                "5K",
+                "--max-concurrent",
+                "1",
+                "--max-attempts",
+                "3",
+            ],
+            &config,
+        );
+        assert_eq!((cli.jobs, cli.limit_rate), (Some(2), Some(5 * 1024)));
+        assert_eq!(cli.max_attempts, Some(3));
+        let Some(Commands::Pull {
+            remote,
+            max_concurrent,
+            ..
+        }) = cli.command
+        else {
+            panic!("expected pull");
+        };
+        assert_eq!(remote.as_deref(), Some("s3"));
+        assert_eq!(max_concurrent, Some(1));
+        // (and --jobs sets the files transferred at once too)
+        let Some(Commands::Push { max_concurrent, .. }) =
+            parse(&["push", "--jobs", "2"], &config).command
+        else {
+            panic!("expected push");
+        };
+        assert_eq!(max_concurrent, Some(2));
+        let Some(Commands::Add { checksum, .. }) =
+            parse(&["add", "a.tsv", "--checksum", "md5"], &config).command
+        else {
+            panic!("expected add");
+        };
+        assert_eq!(checksum, Some(ChecksumAlgo::Md5));
+
+        // pulls from URLs have no remote
+        let Some(Commands::Pull { remote, .. }) = parse(&["pull", "--urls"], &config).command
+        else {
+            panic!("expected pull");
+        };
+        assert_eq!(remote, None);
+
+        // the config's jobs, without its max_concurrent
+        let jobs_only = ProjectConfig::parse("jobs: 8\n").unwrap();
+        let Some(Commands::Pull { max_concurrent, .. }) = parse(&["pull"], &jobs_only).command
+        else {
+            panic!("expected pull");
+        };
+        assert_eq!(max_concurrent, Some(8));
+
+        // without the file, the built-in defaults are used
+        let cli = parse(&["push"], &ProjectConfig::default());
+        assert_eq!((cli.jobs, cli.limit_rate), (None, None));
+        let Some(Commands::Push {
+            remote,
+            max_concurrent,
+            ..
+        }) = cli.command
+        else {
+            panic!("expected push");
+        };
+        assert_eq!((remote, max_concurrent), (None, None));
     }
 }
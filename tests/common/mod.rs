@@ -1,4 +1,4 @@
-///! Testing Utility Functions
+//! Testing Utility Functions
 
 #[allow(unused_imports)]
 use anyhow::{anyhow, Result};
@@ -20,8 +20,8 @@ use std::path::{Path, PathBuf};
 use std::sync::Once;
 use tempfile::TempDir;
 
-use scidataflow::lib::data::StatusEntry;
-use scidataflow::lib::project::Project;
+use scidataflow::lib::data::{StatusEntry, StatusOptions};
+use scidataflow::lib::project::{AddOptions, Project};
 
 pub fn make_mock_fixtures() -> Vec<DataFileFixture> {
     let files = vec![
@@ -102,7 +102,7 @@ fn generate_directory_structure(
         if cached_file_path.exists() {
             std::fs::copy(&cached_file_path, &file_path)?;
         } else {
-            let is_gzip = file_path.extension().map_or(false, |ext| ext == "gz");
+            let is_gzip = file_path.extension().is_some_and(|ext| ext == "gz");
             let size_in_bytes = data_file_fixture.size * 1_000_000;
             generate_random_tsv(&file_path, size_in_bytes, is_gzip, rng)?;
             std::fs::copy(&file_path, &cached_file_path)?; // Now this should work
@@ -111,6 +111,7 @@ fn generate_directory_structure(
     Ok(())
 }
 
+#[allow(dead_code)]
 pub struct TestEnvironment {
     pub name: String,
     pub temp_dir: TempDir,
@@ -160,7 +161,7 @@ impl TestEnvironment {
     pub fn build_project_directories(&mut self, data_fixtures: Vec<DataFileFixture>) -> Result<()> {
         generate_directory_structure(
             &data_fixtures,
-            &self.temp_dir.path(),
+            self.temp_dir.path(),
             &self.cache_dir,
             &mut self.rng,
         )?;
@@ -175,7 +176,7 @@ impl TestEnvironment {
 
 #[allow(dead_code)] // will implement later
 pub fn read_keep_temp() -> bool {
-    return env::var("KEEP_TEMP_DIR").is_ok();
+    env::var("KEEP_TEMP_DIR").is_ok()
 }
 
 impl Drop for TestEnvironment {
@@ -210,6 +211,7 @@ pub async fn setup(do_add: bool) -> TestFixture {
         &Some("Joan B. Scientist".to_string()),
         &Some("joan@ucberkely.edu".to_string()),
         &Some("UC Berkeley".to_string()),
+        &None,
     );
     let _ = Project::init(Some(project_name));
     let mut project = Project::new().expect("setting up TestFixture failed");
@@ -220,13 +222,13 @@ pub async fn setup(do_add: bool) -> TestFixture {
         // get the files to add
         let files = &test_env.files.as_ref().unwrap();
         let add_files: Vec<String> = files
-            .into_iter()
+            .iter()
             .filter(|f| f.add)
             .map(|f| f.path.clone())
             .collect();
 
         // add those files
-        let _ = project.add(&add_files).await;
+        let _ = project.add(&add_files, &AddOptions::default()).await;
     }
 
     TestFixture {
@@ -254,7 +256,7 @@ pub async fn get_statuses(
     let statuses = fixture
         .project
         .data
-        .status(&path_context, false)
+        .status(path_context, &StatusOptions::default())
         .await
         .expect("Error in getting statuses.");
     iter_status_entries(&statuses)
@@ -269,7 +271,7 @@ pub async fn get_statuses_map(
     let statuses = fixture
         .project
         .data
-        .status(&path_context, false)
+        .status(path_context, &StatusOptions::default())
         .await
         .expect("Error in getting statuses.");
     iter_status_entries(&statuses)
@@ -12,9 +12,16 @@ mod tests {
     use super::generate_random_tsv;
     use super::get_statuses;
     use super::setup;
-    use scidataflow::lib::data::LocalStatusCode;
+    use scidataflow::lib::checksum::ChecksumAlgo;
+    use scidataflow::lib::data::{FileSort, LocalStatusCode, StatusFilter, StatusOptions};
+    use scidataflow::lib::filter::FileFilter;
+    use scidataflow::lib::project::{AddOptions, LinkOptions, Project, PushOptions};
+    use scidataflow::lib::report::OutputFormat;
+    use scidataflow::lib::utils::{DirSort, GroupBy};
+    use scidataflow::lib::walk::WalkOptions;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
 
     #[tokio::test]
     async fn test_fixture() {
@@ -40,6 +47,63 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_add_uses_sdfattributes_algo() {
+        let mut fixture = setup(false).await;
+        let path_context = fixture.project.path_context();
+        fs::write(
+            path_context.join(".sdfattributes"),
+            "data/supplement/*.gz algo=sha256\n",
+        )
+        .unwrap();
+        let add_files = vec![
+            "data/data.tsv".to_string(),
+            "data/supplement/big_1.tsv.gz".to_string(),
+        ];
+        fixture
+            .project
+            .add(&add_files, &AddOptions::default())
+            .await
+            .unwrap();
+
+        let files = &fixture.project.data.files;
+        let supplement = &files["data/supplement/big_1.tsv.gz"];
+        assert!(supplement.checksums.contains_key(&ChecksumAlgo::Sha256));
+        assert!(files["data/data.tsv"].checksums.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_add_recursive_skips_tracked() {
+        let mut fixture = setup(true).await;
+        let num_added = fixture.project.data.files.len();
+        assert!(num_added > 0);
+        fs::create_dir_all("data/extra/run1").unwrap();
+        fs::write("data/extra/run1/reads.tsv", "id\tcount\n").unwrap();
+
+        // the files added by setup() are skipped, not an error
+        let options = AddOptions {
+            walk: WalkOptions {
+                recursive: true,
+                follow_symlinks: false,
+            },
+            ..Default::default()
+        };
+        fixture
+            .project
+            .add(&["data".to_string()], &options)
+            .await
+            .unwrap();
+        let files = &fixture.project.data.files;
+        assert_eq!(files.len(), num_added + 1);
+        assert!(files.contains_key("data/extra/run1/reads.tsv"));
+
+        let result = fixture
+            .project
+            .add(&["data/**/*.bam".to_string()], &options)
+            .await;
+        assert!(result.unwrap_err().to_string().contains("No files match"));
+    }
+
     #[tokio::test]
     async fn test_add_status_current() {
         let mut fixture = setup(false).await;
@@ -52,13 +116,16 @@ mod tests {
         // get the files to add
         let files = &fixture.env.files.as_ref().unwrap();
         let add_files: Vec<String> = files
-            .into_iter()
+            .iter()
             .filter(|f| f.add)
             .map(|f| f.path.clone())
             .collect();
 
         // add those files
-        let _ = fixture.project.add(&add_files);
+        let _ = fixture
+            .project
+            .add(&add_files, &AddOptions::default())
+            .await;
 
         // get statuses again
         let statuses = get_statuses(&mut fixture, &path_context).await;
@@ -143,13 +210,15 @@ mod tests {
 
         if let Some(files) = &fixture.env.files {
             for file in files {
-                let mut file_list = Vec::new();
-                file_list.push(file.path.clone());
-                let result = fixture.project.add(&file_list).await;
+                let file_list = vec![file.path.clone()];
+                let result = fixture
+                    .project
+                    .add(&file_list, &AddOptions::default())
+                    .await;
 
                 // check that we get
                 match result {
-                    Ok(_) => assert!(false, "Expected an error, but got Ok"),
+                    Ok(_) => panic!("Expected an error, but got Ok"),
                     Err(err) => {
                         assert!(
                             err.to_string().contains("already registered"),
@@ -162,6 +231,106 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_status_filter_modified() {
+        let mut fixture = setup(true).await;
+        let path_context = fixture.project.path_context();
+        let modified = PathBuf::from("data/supplement/big_2.tsv.gz");
+        let _ = generate_random_tsv(&modified, 5, true, &mut fixture.env.rng);
+
+        let options = StatusOptions {
+            filters: vec![StatusFilter::Modified],
+            ..Default::default()
+        };
+        let statuses = fixture
+            .project
+            .data
+            .status(&path_context, &options)
+            .await
+            .unwrap();
+        // directories without modified files are left out entirely
+        let dirs: Vec<_> = statuses.keys().collect();
+        assert_eq!(dirs, ["data/supplement"]);
+        let names: Vec<_> = statuses["data/supplement"]
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["big_2.tsv.gz"]);
+
+        // filters are OR-combined
+        let options = StatusOptions {
+            filters: vec![StatusFilter::Modified, StatusFilter::Current],
+            ..Default::default()
+        };
+        let statuses = get_statuses(&mut fixture, &path_context).await;
+        let filtered = fixture
+            .project
+            .data
+            .status(&path_context, &options)
+            .await
+            .unwrap();
+        assert_eq!(filtered.values().flatten().count(), statuses.len());
+    }
+
+    #[tokio::test]
+    async fn test_second_mutating_project_is_locked_out() {
+        let fixture = setup(true).await;
+        let manifest = Some(fixture.project.manifest.clone());
+        let manifest = manifest.as_deref();
+
+        // the fixture's project holds the lock
+        let Err(err) = Project::with_manifest(manifest) else {
+            panic!("a second project could be loaded while the first holds the lock");
+        };
+        let pid = format!("(PID {})", std::process::id());
+        assert!(err.to_string().contains(&pid), "{}", err);
+        // read-only commands do not need it
+        assert!(Project::read_only(manifest).is_ok());
+
+        let path_context = fixture.project.path_context();
+        drop(fixture.project);
+        assert!(!path_context.join(".sdf/lock").exists());
+        let mut project = Project::with_manifest(manifest).unwrap();
+        project
+            .set_metadata(&Some("Unlocked".to_string()), &None)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_status_remotes_while_locked_is_not_saved() {
+        let fixture = setup(true).await;
+        let manifest = fixture.project.manifest.clone();
+        // (an old time, which a save would replace)
+        let saved = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&manifest)
+            .unwrap()
+            .set_modified(saved)
+            .unwrap();
+
+        // the fixture's project holds the lock
+        let mut project = Project::with_manifest_if_unlocked(Some(&manifest)).unwrap();
+        assert!(!project.is_locked());
+        let options = StatusOptions {
+            include_remotes: true,
+            ..Default::default()
+        };
+        project
+            .status(
+                &options,
+                false,
+                GroupBy::default(),
+                OutputFormat::Table,
+                false,
+                DirSort::default(),
+            )
+            .await
+            .unwrap();
+        let modified = fs::metadata(&manifest).unwrap().modified().unwrap();
+        assert_eq!(modified, saved);
+    }
+
     #[tokio::test]
     async fn test_mv() {
         let mut fixture = setup(false).await;
@@ -174,13 +343,16 @@ mod tests {
         // get the files to add
         let files = &fixture.env.files.as_ref().unwrap();
         let add_files: Vec<String> = files
-            .into_iter()
+            .iter()
             .filter(|f| f.add)
             .map(|f| f.path.clone())
             .collect();
 
         // add those files
-        let _ = fixture.project.add(&add_files).await;
+        let _ = fixture
+            .project
+            .add(&add_files, &AddOptions::default())
+            .await;
 
         let new_name = "data/data_alt.tsv";
         let target_path = PathBuf::from(new_name);
@@ -190,7 +362,11 @@ mod tests {
         assert!(!exists); // not there before move
 
         // try moving a file (renaming)
-        fixture.project.mv("data/data.tsv", new_name).await.unwrap();
+        fixture
+            .project
+            .mv("data/data.tsv", new_name, false)
+            .await
+            .unwrap();
 
         let exists = statuses.iter().any(|(path, _status)| path == &target_path);
         assert!(!exists); // now it should be there
@@ -199,7 +375,7 @@ mod tests {
         fs::create_dir_all("new_data/").unwrap();
         fixture
             .project
-            .mv("data/supplement/big_1.tsv.gz", "new_data/")
+            .mv("data/supplement/big_1.tsv.gz", "new_data/", false)
             .await
             .unwrap();
 
@@ -208,4 +384,427 @@ mod tests {
         let exists = statuses.iter().any(|(path, _status)| path == &target_path);
         assert!(!exists); // now it should be there
     }
+
+    #[tokio::test]
+    async fn test_mv_into_directory_and_refuse_overwrite() {
+        let mut fixture = setup(true).await;
+        let original = fixture.project.data.files["data/data.tsv"].clone();
+
+        fs::create_dir_all("data/moved").unwrap();
+        fixture
+            .project
+            .mv("data/data.tsv", "data/moved/", false)
+            .await
+            .unwrap();
+        assert!(Path::new("data/moved/data.tsv").exists());
+        let files = &fixture.project.data.files;
+        assert!(!files.contains_key("data/data.tsv"));
+        let moved = &files["data/moved/data.tsv"];
+        assert_eq!(moved.path, "data/moved/data.tsv");
+        assert_eq!(
+            (&moved.md5, moved.tracked),
+            (&original.md5, original.tracked)
+        );
+
+        // the saved manifest has the new path, too
+        let saved = fs::read_to_string(&fixture.project.manifest).unwrap();
+        assert!(saved.contains("data/moved/data.tsv"));
+
+        // another tracked file is not overwritten without --force
+        let other = "data/supplement/big_1.tsv.gz";
+        let result = fixture
+            .project
+            .mv(other, "data/moved/data.tsv", false)
+            .await;
+        assert!(result.unwrap_err().to_string().contains("--force"));
+        assert!(Path::new(other).exists());
+        assert_eq!(
+            fixture.project.data.files["data/moved/data.tsv"].md5,
+            original.md5
+        );
+
+        fixture
+            .project
+            .mv(other, "data/moved/data.tsv", true)
+            .await
+            .unwrap();
+        assert!(!Path::new(other).exists());
+        let files = &fixture.project.data.files;
+        assert!(!files.contains_key(other));
+        assert_ne!(files["data/moved/data.tsv"].md5, original.md5);
+    }
+
+    #[tokio::test]
+    async fn test_rm_keep_and_delete_local() {
+        let mut fixture = setup(true).await;
+        let kept = "data/data.tsv";
+        let deleted = "data/supplement/big_1.tsv.gz";
+
+        fixture
+            .project
+            .remove(&vec![kept.to_string()], false)
+            .await
+            .unwrap();
+        assert!(!fixture.project.data.files.contains_key(kept));
+        assert!(Path::new(kept).exists());
+
+        fixture
+            .project
+            .remove(&vec![deleted.to_string()], true)
+            .await
+            .unwrap();
+        assert!(!fixture.project.data.files.contains_key(deleted));
+        assert!(!Path::new(deleted).exists());
+
+        let saved = fs::read_to_string(&fixture.project.manifest).unwrap();
+        assert!(!saved.contains(kept) && !saved.contains(deleted));
+    }
+
+    #[tokio::test]
+    async fn test_prune_drops_only_missing_files() {
+        let mut fixture = setup(true).await;
+        let path_context = fixture.project.path_context();
+        let missing = "data/data.tsv";
+        let present = "data/supplement/big_1.tsv.gz";
+        fs::remove_file(missing).unwrap();
+
+        let options = StatusOptions {
+            filters: vec![StatusFilter::Missing],
+            ..Default::default()
+        };
+        let statuses = fixture
+            .project
+            .data
+            .status(&path_context, &options)
+            .await
+            .unwrap();
+        let listed: Vec<_> = statuses.values().flatten().collect();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "data.tsv");
+        assert_eq!(listed[0].local_status, Some(LocalStatusCode::Deleted));
+
+        let before = fixture.project.data.files.len();
+        fixture.project.prune(true).await.unwrap();
+        assert!(!fixture.project.data.files.contains_key(missing));
+        assert!(fixture.project.data.files.contains_key(present));
+        assert_eq!(fixture.project.data.files.len(), before - 1);
+        let saved = fs::read_to_string(&fixture.project.manifest).unwrap();
+        assert!(!saved.contains(missing) && saved.contains(present));
+    }
+
+    #[tokio::test]
+    async fn test_import_list_rejects_mismatched_checksum() {
+        let mut fixture = setup(false).await;
+        let md5 = format!("{:x}", md5::compute(fs::read("data/data.tsv").unwrap()));
+        let wrong_md5 = "0".repeat(32);
+        fs::write(
+            "files.csv",
+            format!(
+                "path,md5,notes\ndata/data.tsv,{},ok\ndata/raw/medium.tsv.gz,{},stale\n",
+                md5, wrong_md5
+            ),
+        )
+        .unwrap();
+
+        let err = fixture.project.import_list("files.csv").await.unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("Could not import 1 file:"),
+            "{}",
+            message
+        );
+        assert!(message.contains("data/raw/medium.tsv.gz: MD5 mismatch"));
+        assert!(message.contains(&wrong_md5));
+
+        // the matching file is still imported
+        let files = &fixture.project.data.files;
+        assert_eq!(files["data/data.tsv"].md5, md5);
+        assert!(!files.contains_key("data/raw/medium.tsv.gz"));
+    }
+
+    #[tokio::test]
+    async fn test_import_list_reports_missing_files() {
+        let mut fixture = setup(false).await;
+        fs::write(
+            "data/files.tsv",
+            "path\ndata.tsv\nmissing.tsv\n../outside.tsv\n",
+        )
+        .unwrap();
+
+        // paths are relative to the list's directory
+        let err = fixture
+            .project
+            .import_list("data/files.tsv")
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("Could not import 2 files:"),
+            "{}",
+            message
+        );
+        assert!(message.contains("data/missing.tsv: file does not exist"));
+        assert!(message.contains("../outside.tsv: path is outside"));
+
+        assert_eq!(fixture.project.data.files.len(), 1);
+        let saved = fs::read_to_string(&fixture.project.manifest).unwrap();
+        assert!(saved.contains("data/data.tsv") && !saved.contains("missing.tsv"));
+    }
+
+    #[tokio::test]
+    async fn test_add_normalizes_paths() {
+        let mut fixture = setup(false).await;
+        let path_context = fixture.project.path_context();
+
+        // absolute paths, and '.' and '..' segments, are made manifest paths
+        let absolute = path_context.join("data/data.tsv");
+        let redundant = "./data/supplement/../supplement/./big_1.tsv.gz".to_string();
+        fixture
+            .project
+            .add(
+                &[absolute.to_string_lossy().to_string(), redundant],
+                &AddOptions::default(),
+            )
+            .await
+            .unwrap();
+        let mut paths: Vec<&String> = fixture.project.data.files.keys().collect();
+        paths.sort();
+        assert_eq!(paths, ["data/data.tsv", "data/supplement/big_1.tsv.gz"]);
+        assert_eq!(
+            fixture.project.data.files["data/data.tsv"].path,
+            "data/data.tsv"
+        );
+
+        // paths escaping the project are an error
+        let outside = tempfile::NamedTempFile::new_in(path_context.parent().unwrap()).unwrap();
+        let escape = format!(
+            "../{}",
+            outside.path().file_name().unwrap().to_string_lossy()
+        );
+        let err = fixture
+            .project
+            .add(&[escape], &AddOptions::default())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("is outside the project directory"),
+            "{}",
+            err
+        );
+        assert_eq!(fixture.project.data.files.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_status_sort_size_reverse() {
+        let mut fixture = setup(true).await;
+        let path_context = fixture.project.path_context();
+        for file in [
+            "data/supplement/big_1.tsv.gz",
+            "data/supplement/big_2.tsv.gz",
+        ] {
+            fixture.project.data.files.get_mut(file).unwrap().tracked = true;
+        }
+        let options = StatusOptions {
+            sort: FileSort::Size,
+            reverse: true,
+            ..Default::default()
+        };
+        let statuses = fixture
+            .project
+            .data
+            .status(&path_context, &options)
+            .await
+            .unwrap();
+
+        // the largest tracked file is listed first
+        let supplement = &statuses["data/supplement"];
+        assert_eq!(supplement[0].name, "big_1.tsv.gz");
+        assert_eq!(supplement[0].tracked, Some(true));
+        for entries in statuses.values() {
+            let sizes: Vec<_> = entries.iter().map(|entry| entry.size()).collect();
+            assert!(
+                sizes.windows(2).all(|pair| pair[0] >= pair[1]),
+                "{:?}",
+                sizes
+            );
+        }
+
+        let ascending = StatusOptions {
+            sort: FileSort::Size,
+            ..Default::default()
+        };
+        let statuses = fixture
+            .project
+            .data
+            .status(&path_context, &ascending)
+            .await
+            .unwrap();
+        assert_eq!(statuses["data/supplement"][0].name, "big_2.tsv.gz");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_push_delta_updates_filesystem_copy_in_place() {
+        use std::io::Write;
+        use std::os::unix::fs::MetadataExt;
+
+        let mut fixture = setup(true).await;
+        let shared = fixture.env.temp_dir.path().join("shared");
+        let options = LinkOptions {
+            name: Some(shared.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        fixture
+            .project
+            .link("data/raw", "filesystem", None, &options)
+            .await
+            .unwrap();
+        let path = "data/raw/medium.tsv.gz".to_string();
+        fixture.project.track(&path, &[], None).await.unwrap();
+        let mut push = PushOptions {
+            max_concurrent: 1,
+            ..Default::default()
+        };
+        fixture
+            .project
+            .push(&push, OutputFormat::Json, FileFilter::default())
+            .await
+            .unwrap();
+        let copy = shared.join("medium.tsv.gz");
+        let inode = fs::metadata(&copy).unwrap().ino();
+
+        // append to the file, and push only its changed chunks
+        let local = fixture.env.get_file_path(&path);
+        let mut file = fs::OpenOptions::new().append(true).open(&local).unwrap();
+        file.write_all(b"appended\n").unwrap();
+        drop(file);
+        fixture.project.update(None).await.unwrap();
+        push.overwrite = true;
+        push.delta = true;
+        fixture
+            .project
+            .push(&push, OutputFormat::Json, FileFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), fs::read(&local).unwrap());
+        // the copy was written in place, not replaced
+        assert_eq!(fs::metadata(&copy).unwrap().ino(), inode);
+    }
+
+    #[tokio::test]
+    async fn test_json_push_writes_only_json_to_stdout() {
+        let mut fixture = setup(true).await;
+        let shared = fixture.env.temp_dir.path().join("shared");
+        let options = LinkOptions {
+            name: Some(shared.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        fixture
+            .project
+            .link("data/raw", "filesystem", None, &options)
+            .await
+            .unwrap();
+        let path = "data/raw/medium.tsv.gz".to_string();
+        fixture.project.track(&path, &[], None).await.unwrap();
+        // release the project's lock for sdf
+        let project_dir = fixture.project.path_context();
+        drop(fixture.project);
+
+        // stderr is not a terminal, so notes of each upload are printed
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_sdf"))
+            .args(["push", "--format", "json"])
+            .current_dir(&project_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&stdout)
+            .unwrap_or_else(|err| panic!("{}: stdout was not JSON:\n{}", err, stdout));
+        assert_eq!(report["files"][0]["path"], path.as_str());
+        assert_eq!(report["files"][0]["status"], "succeeded");
+        assert!(!String::from_utf8_lossy(&output.stderr).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_status_reads_manifest_from_stdin() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let fixture = setup(true).await;
+        let project_dir = fixture.project.path_context();
+        let manifest = fs::read_to_string(&fixture.project.manifest).unwrap();
+        let path = "data/raw/medium.tsv.gz";
+        let md5 = fixture.project.data.files[path].md5.clone();
+
+        // the manifest given on stdin, not the project's, is used
+        let altered = manifest.replace(&md5, "0123456789abcdef0123456789abcdef");
+        let mut child = Command::new(env!("CARGO_BIN_EXE_sdf"))
+            .args(["--manifest", "-", "status"])
+            .current_dir(&project_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(altered.as_bytes()).unwrap();
+        drop(stdin);
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let line = stdout
+            .lines()
+            .find(|line| line.contains("medium.tsv.gz"))
+            .unwrap_or_else(|| panic!("no status for {}:\n{}", path, stdout));
+        assert!(line.contains("changed"), "{}", line);
+        // and the project's manifest is untouched
+        assert_eq!(
+            fs::read_to_string(&fixture.project.manifest).unwrap(),
+            manifest
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_reads_manifest_from_stdin() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let fixture = setup(true).await;
+        let project_dir = fixture.project.path_context();
+        let manifest = fs::read_to_string(&fixture.project.manifest).unwrap();
+        let path = "data/raw/medium.tsv.gz";
+        let md5 = fixture.project.data.files[path].md5.clone();
+
+        // the manifest given on stdin, not the project's, is verified
+        let verify = |manifest: String| {
+            let mut child = Command::new(env!("CARGO_BIN_EXE_sdf"))
+                .args(["--manifest", "-", "verify", "--format", "json"])
+                .current_dir(&project_dir)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let mut stdin = child.stdin.take().unwrap();
+            stdin.write_all(manifest.as_bytes()).unwrap();
+            drop(stdin);
+            child.wait_with_output().unwrap()
+        };
+        let output = verify(manifest.clone());
+        assert!(output.status.success(), "{:?}", output);
+
+        let output = verify(manifest.replace(&md5, "0123456789abcdef0123456789abcdef"));
+        assert!(!output.status.success(), "{:?}", output);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&stdout)
+            .unwrap_or_else(|err| panic!("{}: stdout was not JSON:\n{}", err, stdout));
+        let failed: Vec<&serde_json::Value> = report["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|file| file["status"] == "failed")
+            .map(|file| &file["path"])
+            .collect();
+        assert_eq!(failed, [path]);
+    }
 }